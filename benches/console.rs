@@ -0,0 +1,81 @@
+//! Compares the buffered terminal console against flushing every character,
+//! which is how output used to be written. Run with `cargo bench --bench console`.
+
+use std::{
+    fs::File,
+    io::Write,
+    time::{Duration, Instant},
+};
+
+use lc3_vm::{
+    console::{Console, TerminalConsole},
+    errors::{IoError, VMError},
+    vm::VM,
+};
+
+const ITERATIONS: u16 = 2000;
+const MESSAGE: &str = "The quick brown fox jumps over the lazy dog\n";
+
+/// Writes to the sink and flushes after every character
+struct FlushEachChar(File);
+
+impl Console for FlushEachChar {
+    fn read_key(&mut self) -> Result<u8, VMError> {
+        Ok(0)
+    }
+
+    fn poll_key(&mut self) -> Result<Option<u8>, VMError> {
+        Ok(None)
+    }
+
+    fn write_char(&mut self, character: char) -> Result<(), VMError> {
+        let mut encoded = [0; 4];
+        self.0
+            .write_all(character.encode_utf8(&mut encoded).as_bytes())
+            .and_then(|()| self.0.flush())
+            .map_err(|e| VMError::StandardIO(IoError::caused_by("Could not write output", e)))
+    }
+
+    fn flush(&mut self) -> Result<(), VMError> {
+        Ok(())
+    }
+}
+
+/// Prints `MESSAGE` with PUTS `ITERATIONS` times
+fn image() -> Vec<u8> {
+    let mut words = vec![
+        0x3000, // .ORIG x3000
+        0x2205, // LD R1, COUNT
+        0xE005, // LOOP LEA R0, MESSAGE
+        0xF022, // PUTS
+        0x127F, // ADD R1, R1, #-1
+        0x03FC, // BRp LOOP
+        0xF025, // HALT
+        ITERATIONS,
+    ];
+    words.extend(MESSAGE.bytes().map(u16::from));
+    words.push(0);
+    words.iter().flat_map(|word| word.to_be_bytes()).collect()
+}
+
+fn measure(console: Box<dyn Console>) -> Result<Duration, VMError> {
+    let mut vm = VM::new();
+    vm.set_console(console);
+    vm.read_image_bytes(&image())?;
+    let start = Instant::now();
+    vm.run()?;
+    Ok(start.elapsed())
+}
+
+fn null_device() -> Result<File, VMError> {
+    File::create("/dev/null")
+        .map_err(|e| VMError::OpenFile(IoError::caused_by("Could not open /dev/null", e)))
+}
+
+fn main() -> Result<(), VMError> {
+    let unbuffered = measure(Box::new(FlushEachChar(null_device()?)))?;
+    let buffered = measure(Box::new(TerminalConsole::with_writer(null_device()?)))?;
+    println!("flush per character: {unbuffered:?}");
+    println!("buffered console:    {buffered:?}");
+    Ok(())
+}
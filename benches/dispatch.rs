@@ -0,0 +1,48 @@
+//! Measures raw dispatch speed on a tight ADD/BR loop.
+//! Run with `cargo bench --bench dispatch`, adding `--features threaded` to
+//! measure the threaded-code backend instead of the basic-block cache, or
+//! `--features jit` to compile hot blocks to native code.
+
+use std::time::Instant;
+
+use lc3_vm::{errors::VMError, vm::VM};
+
+const OUTER: u16 = 1000;
+const INNER: u16 = 10000;
+
+/// Two nested countdown loops executing about `2 * OUTER * INNER` instructions
+fn image() -> Vec<u8> {
+    let words = [
+        0x3000, // .ORIG x3000
+        0x2406, // LD R2, OUTER
+        0x2206, // OUTER_LOOP LD R1, INNER
+        0x127F, // INNER_LOOP ADD R1, R1, #-1
+        0x03FE, // BRp INNER_LOOP
+        0x14BF, // ADD R2, R2, #-1
+        0x03FB, // BRp OUTER_LOOP
+        0xF025, // HALT
+        OUTER, INNER,
+    ];
+    words.iter().flat_map(|word| word.to_be_bytes()).collect()
+}
+
+fn main() -> Result<(), VMError> {
+    let mut vm = VM::new();
+    vm.read_image_bytes(&image())?;
+    let start = Instant::now();
+    vm.run_headless()?;
+    let elapsed = start.elapsed();
+    let instructions = 2.0 * f64::from(OUTER) * f64::from(INNER);
+    let backend = if cfg!(feature = "threaded") {
+        "threaded code"
+    } else if cfg!(feature = "jit") {
+        "JIT"
+    } else {
+        "basic-block cache"
+    };
+    println!(
+        "tight ADD/BR loop ({backend}): {elapsed:?} ({:.1} MIPS)",
+        instructions / elapsed.as_secs_f64() / 1e6
+    );
+    Ok(())
+}
@@ -0,0 +1,163 @@
+//! Criterion suite covering dispatch, memory and I/O heavy workloads, all run
+//! headless. Run with `cargo bench --bench vm`, optionally adding
+//! `--features threaded` or `--features jit` to compare backends. The `fork`
+//! group measures what branching a machine with `VM::fork` costs against
+//! copying its memory.
+#![allow(clippy::expect_used)]
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use lc3_vm::vm::{Checkpoints, VM};
+
+/// Two nested countdown loops of ADD/BR, about two million instructions
+const ALU_LOOP: &[u16] = &[
+    0x3000, // .ORIG x3000
+    0x2406, // LD R2, OUTER
+    0x2206, // OUTER_LOOP LD R1, INNER
+    0x127F, // INNER_LOOP ADD R1, R1, #-1
+    0x03FE, // BRp INNER_LOOP
+    0x14BF, // ADD R2, R2, #-1
+    0x03FB, // BRp OUTER_LOOP
+    0xF025, // HALT
+    100,    // OUTER
+    10000,  // INNER
+];
+
+/// Copies 4096 words from x4000 to x8000 twenty times with LDR/STR
+const MEMORY_COPY: &[u16] = &[
+    0x3000, // .ORIG x3000
+    0x260C, // LD R3, PASSES
+    0x220C, // PASS LD R1, SRC
+    0x240C, // LD R2, DST
+    0x280C, // LD R4, LEN
+    0x6040, // COPY LDR R0, R1, #0
+    0x7080, // STR R0, R2, #0
+    0x1261, // ADD R1, R1, #1
+    0x14A1, // ADD R2, R2, #1
+    0x193F, // ADD R4, R4, #-1
+    0x03FA, // BRp COPY
+    0x16FF, // ADD R3, R3, #-1
+    0x03F5, // BRp PASS
+    0xF025, // HALT
+    20,     // PASSES
+    0x4000, // SRC
+    0x8000, // DST
+    4096,   // LEN
+];
+
+/// Writes a single character with OUT twenty thousand times
+const TRAP_HEAVY: &[u16] = &[
+    0x3000, // .ORIG x3000
+    0x2205, // LD R1, COUNT
+    0x2005, // LD R0, CHAR
+    0xF021, // LOOP OUT
+    0x127F, // ADD R1, R1, #-1
+    0x03FD, // BRp LOOP
+    0xF025, // HALT
+    20000,  // COUNT
+    0x0061, // CHAR 'a'
+];
+
+/// Stores R0 in the sixteen words from x4000, as a branch of a search would
+/// change a little of the memory
+const SHORT_BRANCH: &[u16] = &[
+    0x3000, // .ORIG x3000
+    0x2206, // LD R1, DST
+    0x2406, // LD R2, LEN
+    0x7040, // STORE STR R0, R1, #0
+    0x1261, // ADD R1, R1, #1
+    0x14BF, // ADD R2, R2, #-1
+    0x03FC, // BRp STORE
+    0xF025, // HALT
+    0x4000, // DST
+    16,     // LEN
+];
+
+/// Prints a line with PUTS two thousand times
+fn string_output() -> Vec<u16> {
+    let mut words = vec![
+        0x3000, // .ORIG x3000
+        0x2205, // LD R1, COUNT
+        0xE005, // LOOP LEA R0, MESSAGE
+        0xF022, // PUTS
+        0x127F, // ADD R1, R1, #-1
+        0x03FC, // BRp LOOP
+        0xF025, // HALT
+        2000,   // COUNT
+    ];
+    words.extend(
+        "The quick brown fox jumps over the lazy dog\n"
+            .bytes()
+            .map(u16::from),
+    );
+    words.push(0);
+    words
+}
+
+fn bench_program(criterion: &mut Criterion, name: &str, words: &[u16]) {
+    let image = image(words);
+    criterion.bench_function(name, |bencher| {
+        bencher.iter_batched(
+            || {
+                let mut vm = VM::new();
+                vm.read_image_bytes(&image).expect("benchmark image loads");
+                vm
+            },
+            |mut vm| vm.run_headless(),
+            BatchSize::LargeInput,
+        );
+    });
+}
+
+fn image(words: &[u16]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_be_bytes()).collect()
+}
+
+/// A fork against a copy of the whole memory, and running a short branch
+/// from a fork against a machine loaded from scratch
+fn forking(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("fork");
+    let mut vm = VM::new();
+    vm.read_image_bytes(&image(MEMORY_COPY))
+        .expect("benchmark image loads");
+    vm.run_headless().expect("benchmark program runs");
+    vm.fork().expect("benchmark machine forks");
+    group.bench_function("fork", |bencher| {
+        bencher.iter(|| vm.fork().expect("benchmark machine forks"));
+    });
+    let mut checkpoints = Checkpoints::new();
+    group.bench_function("checkpoint", |bencher| {
+        bencher.iter(|| checkpoints.save("branch", &vm));
+    });
+
+    let short = image(SHORT_BRANCH);
+    let mut start = VM::new();
+    start
+        .read_image_bytes(&short)
+        .expect("benchmark image loads");
+    group.bench_function("branch from a fork", |bencher| {
+        bencher.iter(|| {
+            let mut branch = start.fork().expect("benchmark machine forks");
+            branch.run().expect("benchmark program runs");
+        });
+    });
+    group.bench_function("branch from scratch", |bencher| {
+        bencher.iter(|| {
+            let mut branch = VM::new();
+            branch
+                .read_image_bytes(&short)
+                .expect("benchmark image loads");
+            branch.run_headless().expect("benchmark program runs");
+        });
+    });
+    group.finish();
+}
+
+fn workloads(criterion: &mut Criterion) {
+    bench_program(criterion, "alu loop", ALU_LOOP);
+    bench_program(criterion, "memory copy", MEMORY_COPY);
+    bench_program(criterion, "string output", &string_output());
+    bench_program(criterion, "trap heavy", TRAP_HEAVY);
+}
+
+criterion_group!(benches, workloads, forking);
+criterion_main!(benches);
@@ -0,0 +1,31 @@
+//! Runs arbitrary instruction words from x3000 in a VM without input,
+//! with every optional trap enabled and a cap on the instructions
+//! executed. The run must end with HALT, an error or the cap, never a
+//! panic or a hang.
+#![no_main]
+
+use lc3_vm::{
+    console::NullConsole,
+    vfs::MemoryFileSystem,
+    vm::{TrapMode, VM},
+};
+use libfuzzer_sys::fuzz_target;
+
+/// Instructions a single input may execute
+const INSTRUCTION_LIMIT: u64 = 10_000;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(mut vm) = VM::builder()
+        .console(Box::new(NullConsole))
+        .trap_mode(TrapMode::Extended)
+        .file_system(Box::new(MemoryFileSystem::new()))
+        .instruction_limit(INSTRUCTION_LIMIT)
+        .build()
+    else {
+        return;
+    };
+    let image: Vec<u8> = [0x30, 0x00].iter().chain(data).copied().collect();
+    if vm.read_image_bytes(&image).is_ok() {
+        let _ = vm.run();
+    }
+});
@@ -0,0 +1,16 @@
+//! Loads arbitrary bytes as an image, which must either load or fail with
+//! an error
+#![no_main]
+
+use lc3_vm::{console::NullConsole, vm::VM};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(mut vm) = VM::builder().console(Box::new(NullConsole)).build() else {
+        return;
+    };
+    if vm.read_image_bytes(data).is_ok() {
+        // a loaded image must be runnable without panicking
+        let _ = vm.run_with_fuel(1_000);
+    }
+});
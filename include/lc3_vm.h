@@ -0,0 +1,148 @@
+#ifndef LC3_VM_H
+#define LC3_VM_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit by hand */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * Result of the fallible calls
+ */
+enum Lc3Status
+#if defined(__cplusplus) || __STDC_VERSION__ >= 202311L
+  : int32_t
+#endif // defined(__cplusplus) || __STDC_VERSION__ >= 202311L
+ {
+  LC3_STATUS_OK = 0,
+  /**
+   * The program executed HALT
+   */
+  LC3_STATUS_HALTED = 1,
+  /**
+   * The call failed, `lc3_vm_last_error` describes why
+   */
+  LC3_STATUS_ERROR = -1,
+};
+#ifndef __cplusplus
+#if __STDC_VERSION__ >= 202311L
+typedef enum Lc3Status Lc3Status;
+#else
+typedef int32_t Lc3Status;
+#endif // __STDC_VERSION__ >= 202311L
+#endif // __cplusplus
+
+/**
+ * VM handle owned by the C side
+ */
+typedef struct Lc3Vm Lc3Vm;
+
+/**
+ * Returns the next key, or a negative value if there is none. With `block`
+ * set the VM needs a key to go on (GETC or IN), so returning none stops
+ * the program with an error.
+ */
+typedef int32_t (*Lc3ReadKey)(void *user_data, bool block);
+
+/**
+ * Receives a character written by the program
+ */
+typedef void (*Lc3WriteChar)(void *user_data, uint8_t character);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates a VM without input or output until `lc3_vm_set_io` is called.
+ * Release it with `lc3_vm_free`.
+ */
+struct Lc3Vm *lc3_vm_new(void);
+
+/**
+ * # Safety
+ *
+ * `vm` must come from `lc3_vm_new` and not be used afterwards. NULL is
+ * ignored.
+ */
+void lc3_vm_free(struct Lc3Vm *vm);
+
+/**
+ * Loads a big endian object file whose first word is the origin
+ *
+ * # Safety
+ *
+ * `vm` must come from `lc3_vm_new` and `bytes` must point to `len`
+ * readable bytes.
+ */
+Lc3Status lc3_vm_load(struct Lc3Vm *vm, const uint8_t *bytes, size_t len);
+
+/**
+ * Routes keyboard input and program output through callbacks, either of
+ * which may be NULL. `user_data` is passed back to them unchanged.
+ *
+ * # Safety
+ *
+ * `vm` must come from `lc3_vm_new`, and the callbacks must stay callable
+ * with `user_data` for as long as the VM runs.
+ */
+void lc3_vm_set_io(struct Lc3Vm *vm, void *user_data, Lc3ReadKey read_key, Lc3WriteChar write_char);
+
+/**
+ * Executes up to `count` instructions. Returns `LC3_STATUS_HALTED` once
+ * the program halted.
+ *
+ * # Safety
+ *
+ * `vm` must come from `lc3_vm_new`.
+ */
+Lc3Status lc3_vm_step(struct Lc3Vm *vm, uint32_t count);
+
+/**
+ * Value of register R`number`, or 0 if there is no such register
+ *
+ * # Safety
+ *
+ * `vm` must come from `lc3_vm_new`.
+ */
+uint16_t lc3_vm_read_reg(const struct Lc3Vm *vm, uint16_t number);
+
+/**
+ * # Safety
+ *
+ * `vm` must come from `lc3_vm_new`.
+ */
+uint16_t lc3_vm_pc(const struct Lc3Vm *vm);
+
+/**
+ * Reads memory without triggering device side effects
+ *
+ * # Safety
+ *
+ * `vm` must come from `lc3_vm_new`.
+ */
+uint16_t lc3_vm_read_mem(const struct Lc3Vm *vm, uint16_t address);
+
+/**
+ * # Safety
+ *
+ * `vm` must come from `lc3_vm_new`.
+ */
+Lc3Status lc3_vm_write_mem(struct Lc3Vm *vm, uint16_t address, uint16_t value);
+
+/**
+ * Description of the last error, valid until the next failing call, or
+ * NULL if nothing failed yet
+ *
+ * # Safety
+ *
+ * `vm` must come from `lc3_vm_new`.
+ */
+const char *lc3_vm_last_error(const struct Lc3Vm *vm);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* LC3_VM_H */
@@ -0,0 +1,206 @@
+//! ANSI/VT100 escape sequences printed by programs. With `ansi` set in the
+//! `ConsoleConfig` the VM recognizes them in the output of OUT, PUTS and
+//! PUTSP and hands each complete sequence to `Console::command`, so no
+//! flush splits one and consoles that are not VT100 terminals can carry
+//! them out themselves.
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt;
+
+/// Longest sequence kept, longer ones are passed on as they are
+const MAX_SEQUENCE: usize = 32;
+
+/// What part of the screen or line an erase command clears
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Erase {
+    /// From the cursor to the end
+    ToEnd,
+    /// From the start to the cursor
+    ToStart,
+    All,
+}
+
+/// A recognized escape sequence
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// `ESC [ n A` to `ESC [ n D`: moves the cursor `n` cells
+    CursorUp(u16),
+    CursorDown(u16),
+    CursorForward(u16),
+    CursorBack(u16),
+    /// `ESC [ row ; column H`, both counted from 1
+    CursorPosition {
+        row: u16,
+        column: u16,
+    },
+    /// `ESC [ n J`
+    EraseDisplay(Erase),
+    /// `ESC [ n K`
+    EraseLine(Erase),
+    /// `ESC [ ... m`: colors and text attributes, `[0]` to reset them
+    Style(Vec<u16>),
+    /// `ESC [ ? 25 h` and `ESC [ ? 25 l`
+    ShowCursor(bool),
+    /// Any other sequence, verbatim after its ESC
+    Other(String),
+}
+
+impl fmt::Display for Command {
+    /// The canonical escape sequence of the command
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::CursorUp(n) => write!(f, "\x1b[{n}A"),
+            Command::CursorDown(n) => write!(f, "\x1b[{n}B"),
+            Command::CursorForward(n) => write!(f, "\x1b[{n}C"),
+            Command::CursorBack(n) => write!(f, "\x1b[{n}D"),
+            Command::CursorPosition { row, column } => write!(f, "\x1b[{row};{column}H"),
+            Command::EraseDisplay(erase) => write!(f, "\x1b[{}J", erase.parameter()),
+            Command::EraseLine(erase) => write!(f, "\x1b[{}K", erase.parameter()),
+            Command::Style(parameters) => {
+                write!(f, "\x1b[")?;
+                for (index, parameter) in parameters.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ";")?;
+                    }
+                    write!(f, "{parameter}")?;
+                }
+                write!(f, "m")
+            }
+            Command::ShowCursor(true) => write!(f, "\x1b[?25h"),
+            Command::ShowCursor(false) => write!(f, "\x1b[?25l"),
+            Command::Other(sequence) => write!(f, "\x1b{sequence}"),
+        }
+    }
+}
+
+impl Erase {
+    fn parameter(self) -> u8 {
+        match self {
+            Erase::ToEnd => 0,
+            Erase::ToStart => 1,
+            Erase::All => 2,
+        }
+    }
+}
+
+/// A character of the output, or the command of a sequence it completed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Output {
+    Text(char),
+    Command(Command),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum State {
+    #[default]
+    Text,
+    /// After ESC
+    Escape,
+    /// After ESC [
+    Csi,
+}
+
+/// Splits printed characters into text and escape sequences
+#[derive(Debug, Clone, Default)]
+pub struct Parser {
+    state: State,
+    /// The sequence being read, without its ESC
+    sequence: String,
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes the next printed character, returning it if it is text or the
+    /// command once it completes a sequence
+    pub fn feed(&mut self, character: char) -> Option<Output> {
+        match self.state {
+            State::Text if character == '\x1b' => {
+                self.state = State::Escape;
+                self.sequence.clear();
+                None
+            }
+            State::Text => Some(Output::Text(character)),
+            State::Escape if character == '[' => {
+                self.state = State::Csi;
+                self.sequence.push(character);
+                None
+            }
+            State::Escape => {
+                self.state = State::Text;
+                Some(Output::Command(Command::Other(character.to_string())))
+            }
+            State::Csi => {
+                self.sequence.push(character);
+                if ('\x40'..='\x7e').contains(&character) {
+                    self.state = State::Text;
+                    Some(Output::Command(parse(&self.sequence)))
+                } else if self.sequence.len() >= MAX_SEQUENCE {
+                    self.state = State::Text;
+                    Some(Output::Command(Command::Other(self.sequence.clone())))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// The command of a complete `[...` sequence
+fn parse(sequence: &str) -> Command {
+    let other = || Command::Other(String::from(sequence));
+    let Some(rest) = sequence.strip_prefix('[') else {
+        return other();
+    };
+    let Some((at, last)) = rest.char_indices().last() else {
+        return other();
+    };
+    let body = rest.get(..at).unwrap_or_default();
+    match (body, last) {
+        ("?25", 'h') => return Command::ShowCursor(true),
+        ("?25", 'l') => return Command::ShowCursor(false),
+        _ => {}
+    }
+    let Some(parameters) = body
+        .split(';')
+        .map(|parameter| match parameter {
+            "" => Some(None),
+            digits => digits.parse().ok().map(Some),
+        })
+        .collect::<Option<Vec<Option<u16>>>>()
+    else {
+        return other();
+    };
+    let nth = |index: usize| parameters.get(index).copied().flatten();
+    let count = nth(0).unwrap_or(1).max(1);
+    let erase = match nth(0).unwrap_or(0) {
+        0 => Some(Erase::ToEnd),
+        1 => Some(Erase::ToStart),
+        2 => Some(Erase::All),
+        _ => None,
+    };
+    match (last, erase) {
+        ('A', _) => Command::CursorUp(count),
+        ('B', _) => Command::CursorDown(count),
+        ('C', _) => Command::CursorForward(count),
+        ('D', _) => Command::CursorBack(count),
+        ('H' | 'f', _) if parameters.len() <= 2 => Command::CursorPosition {
+            row: nth(0).unwrap_or(1).max(1),
+            column: nth(1).unwrap_or(1).max(1),
+        },
+        ('J', Some(erase)) if parameters.len() == 1 => Command::EraseDisplay(erase),
+        ('K', Some(erase)) if parameters.len() == 1 => Command::EraseLine(erase),
+        ('m', _) => Command::Style(
+            parameters
+                .iter()
+                .map(|parameter| parameter.unwrap_or(0))
+                .collect(),
+        ),
+        _ => other(),
+    }
+}
@@ -0,0 +1,1441 @@
+//! Two-pass assembler for LC-3 source in the dialect of lc3as: one `.ORIG`
+//! block closed by `.END`, `.FILL`, `.BLKW` and `.STRINGZ`, labels on their
+//! own line or before a statement, and numbers written `#10`, `10`, `x3000`
+//! or `0x3000`. Opcodes, registers and labels are case insensitive.
+//!
+//! Before assembly, `.DEFINE NAME VALUE` replaces the word `NAME` with
+//! `VALUE` in the lines that follow, and macros defined between
+//! `.MACRO NAME PARAM, ...` and `.ENDM` are expanded where `NAME` is used
+//! as an operation, their parameters replaced by the operands:
+//!
+//! ```text
+//! .DEFINE SP R6
+//! .MACRO PUSH REG
+//!         ADD SP, SP, #-1
+//!         STR REG, SP, #0
+//! .ENDM
+//!         PUSH R0
+//! ```
+//!
+//! Errors in an expansion are reported on the line using the macro. Labels
+//! defined inside a macro are defined again by every use, so a macro used
+//! twice cannot define any.
+//!
+//! Every error found is reported as a `Diagnostic` pointing at the offending
+//! token, with a suggested fix for the common ones.
+//!
+//! `.GLOBAL NAME, ...` exports labels to other programs, and
+//! `.EXTERNAL NAME, ...` declares labels they define, which `linker::link`
+//! resolves when combining the programs.
+
+use alloc::{borrow::ToOwned, collections::BTreeMap, format, string::String, vec::Vec};
+use core::cell::RefCell;
+
+use crate::{
+    errors::VMError,
+    instructions::{fits, Instruction, JsrTarget, Operand},
+    register::Register,
+    symbols::SymbolTable,
+};
+
+/// An assembled program
+#[derive(Debug, Clone)]
+pub struct Assembly {
+    pub origin: u16,
+    pub words: Vec<u16>,
+    pub symbols: SymbolTable,
+    /// The source lines that produced words, in address order
+    pub lines: Vec<SourceLine>,
+    /// Labels declared `.GLOBAL`
+    pub globals: Vec<String>,
+    /// Uses of `.EXTERNAL` labels, left for the linker to fill in
+    pub relocations: Vec<Relocation>,
+}
+
+/// A use of a label defined by another program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    /// Address of the word to fill in
+    pub address: u16,
+    pub symbol: String,
+    pub field: Field,
+}
+
+/// The part of a word that holds a label
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    /// The whole word, as in `.FILL`
+    Word,
+    /// A PC offset in the low bits, as in `LD` or `JSR`
+    PcOffset(u32),
+}
+
+/// Words produced by a source line, all of them for a line using a macro
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceLine {
+    pub line: usize,
+    pub address: u16,
+    pub length: u16,
+}
+
+impl Assembly {
+    /// The program as an object file: the origin followed by the words, big
+    /// endian
+    pub fn image(&self) -> Vec<u8> {
+        core::iter::once(self.origin)
+            .chain(self.words.iter().copied())
+            .flat_map(u16::to_be_bytes)
+            .collect()
+    }
+
+    /// The image as text, one word in hex per line from the origin, as
+    /// `lc3as -hex` writes it
+    pub fn hex(&self) -> String {
+        core::iter::once(self.origin)
+            .chain(self.words.iter().copied())
+            .map(|word| format!("{word:04X}\n"))
+            .collect()
+    }
+
+    /// First address past the program
+    pub fn end(&self) -> u16 {
+        let length = u16::try_from(self.words.len()).unwrap_or(u16::MAX);
+        self.origin.wrapping_add(length)
+    }
+
+    /// Fails if the program uses external labels, which only linking
+    /// resolves
+    pub fn ensure_linked(&self) -> Result<(), VMError> {
+        match self.relocations.first() {
+            None => Ok(()),
+            Some(relocation) => Err(VMError::Link(format!(
+                "{} is external and needs linking",
+                relocation.symbol
+            ))),
+        }
+    }
+
+    /// A listing in the layout of lc3as: every line of `source`, after the
+    /// address, hexadecimal and binary encoding of each word it produced
+    ///
+    /// ```text
+    ///   (3000) E002  1110000000000010 (   2) start   LEA R0, msg
+    ///   (3001) F022  1111000000100010 (   3)         PUTS
+    ///                                 (   4) ; data
+    /// ```
+    pub fn listing(&self, source: &str) -> String {
+        let mut listing = String::new();
+        let mut lines = self.lines.iter().peekable();
+        for (text, number) in source.lines().zip(1..) {
+            let mut words = Vec::new();
+            while let Some(produced) = lines.next_if(|produced| produced.line == number) {
+                words.extend((0..produced.length).map(|offset| {
+                    let address = produced.address.wrapping_add(offset);
+                    let word = address
+                        .checked_sub(self.origin)
+                        .and_then(|index| self.words.get(usize::from(index)))
+                        .copied()
+                        .unwrap_or_default();
+                    (address, word)
+                }));
+            }
+            let mut words = words.into_iter();
+            let prefix = match words.next() {
+                Some((address, word)) => format!("  ({address:04X}) {word:04X}  {word:016b}"),
+                None => " ".repeat(31),
+            };
+            listing.push_str(&format!("{prefix} ({number:4}) {text}\n"));
+            for (address, word) in words {
+                listing.push_str(&format!("  ({address:04X}) {word:04X}  {word:016b}\n"));
+            }
+        }
+        listing
+    }
+}
+
+/// An error in the source, pointing at the token that caused it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub line: usize,
+    /// Column of the first character of the token, counted from 1
+    pub column: usize,
+    /// Length of the token in characters
+    pub width: usize,
+    pub message: String,
+    /// A suggested fix
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    /// The message followed by the source line with the token underlined:
+    ///
+    /// ```text
+    /// line 2, column 21: 16 does not fit in 5 signed bits
+    /// 2 |         ADD R0, R0, #16
+    ///   |                     ^^^
+    ///   = help: ADD takes immediates from #-16 to #15; ...
+    /// ```
+    pub fn render(&self, source: &str) -> String {
+        let text = source
+            .lines()
+            .nth(self.line.saturating_sub(1))
+            .unwrap_or_default();
+        let number = format!("{}", self.line);
+        let gutter: String = number.chars().map(|_| ' ').collect();
+        let mut rendered = format!(
+            "line {}, column {}: {}\n{number} | {text}\n{gutter} | {}{}",
+            self.line,
+            self.column,
+            self.message,
+            indentation(text, self.column),
+            "^".repeat(self.width.max(1))
+        );
+        if let Some(help) = &self.help {
+            rendered.push_str(&format!("\n{gutter} = help: {help}"));
+        }
+        rendered
+    }
+
+    fn with_help(mut self, help: String) -> Self {
+        self.help = Some(help);
+        self
+    }
+}
+
+/// Blank space as wide as `text` up to `column`, keeping its tabs so a
+/// marker printed after it lines up with the character at `column`
+pub fn indentation(text: &str, column: usize) -> String {
+    text.chars()
+        .take(column.saturating_sub(1))
+        .map(|character| if character == '\t' { '\t' } else { ' ' })
+        .collect()
+}
+
+/// Renders diagnostics one after another
+pub fn report(source: &str, diagnostics: &[Diagnostic]) -> String {
+    let rendered: Vec<String> = diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.render(source))
+        .collect();
+    rendered.join("\n")
+}
+
+/// Assembles `source`, which must start with `.ORIG`
+pub fn assemble(source: &str) -> Result<Assembly, VMError> {
+    assemble_with_diagnostics(source, None)
+        .map_err(|diagnostics| VMError::Assembly(report(source, &diagnostics)))
+}
+
+/// Assembles `source` at `origin`, unless it starts with its own `.ORIG`
+pub fn assemble_at(source: &str, origin: u16) -> Result<Assembly, VMError> {
+    assemble_with_diagnostics(source, Some(origin))
+        .map_err(|diagnostics| VMError::Assembly(report(source, &diagnostics)))
+}
+
+/// Assembles `source` like `assemble_at`, or like `assemble` without an
+/// `origin`, returning every error found in line order
+pub fn assemble_with_diagnostics(
+    source: &str,
+    origin: Option<u16>,
+) -> Result<Assembly, Vec<Diagnostic>> {
+    Assembler::new(source, origin).assemble()
+}
+
+/// Where a token was written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Span {
+    line: usize,
+    column: usize,
+    width: usize,
+}
+
+/// Span of errors in a source without any token
+const START: Span = Span {
+    line: 1,
+    column: 1,
+    width: 1,
+};
+
+/// A word, or the contents of a string literal
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    quoted: bool,
+    span: Span,
+}
+
+impl Token {
+    /// The token, unless it is a string
+    fn word(&self) -> Option<&str> {
+        (!self.quoted).then_some(self.text.as_str())
+    }
+
+    /// The token written at another place, for substitutions
+    fn at(&self, span: Span) -> Token {
+        Token {
+            span,
+            ..self.clone()
+        }
+    }
+}
+
+/// A source line split into its label, operation and operands
+#[derive(Debug)]
+struct Statement {
+    label: Option<Token>,
+    operation: Option<String>,
+    /// The operation, or the label of a line without one
+    span: Span,
+    operands: Vec<Token>,
+}
+
+struct Assembler {
+    statements: Vec<Statement>,
+    origin: Option<u16>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Assembler {
+    fn new(source: &str, origin: Option<u16>) -> Self {
+        let Preprocessor {
+            lines,
+            mut diagnostics,
+            ..
+        } = preprocess(source);
+        let mut statements = Vec::new();
+        for tokens in lines {
+            match statement(tokens) {
+                Ok(Some(statement)) => statements.push(statement),
+                Ok(None) => {}
+                Err(diagnostic) => {
+                    // keep the addresses after the line right for the next errors
+                    statements.push(Statement {
+                        label: None,
+                        operation: Some(String::from(".FILL")),
+                        span: START,
+                        operands: alloc::vec![Token {
+                            text: String::from("0"),
+                            quoted: false,
+                            span: START,
+                        }],
+                    });
+                    diagnostics.push(diagnostic);
+                }
+            }
+        }
+        Assembler {
+            statements,
+            origin,
+            diagnostics,
+        }
+    }
+
+    fn assemble(mut self) -> Result<Assembly, Vec<Diagnostic>> {
+        let mut diagnostics = core::mem::take(&mut self.diagnostics);
+        let (origin, body) = match self.split_origin() {
+            Ok(split) => split,
+            Err(diagnostic) => {
+                diagnostics.push(diagnostic);
+                return Err(sorted(diagnostics));
+            }
+        };
+        let externals = linkage(body, ".EXTERNAL");
+        let symbols = define_labels(origin, body, &externals, &mut diagnostics);
+        let globals = linkage(body, ".GLOBAL");
+        for (name, span) in globals.values() {
+            if symbols.address_of(name).is_none() {
+                diagnostics.push(diagnostic(
+                    *span,
+                    &format!("{name} is declared .GLOBAL but not defined"),
+                ));
+            }
+        }
+        let mut words = Vec::new();
+        let mut lines: Vec<SourceLine> = Vec::new();
+        let mut relocations = Vec::new();
+        let mut address = origin;
+        for statement in body {
+            if statement.operation.as_deref() == Some(".END") {
+                break;
+            }
+            let context = Context {
+                symbols: &symbols,
+                externals: &externals,
+            };
+            let encoded = match encode(statement, address, &context) {
+                Ok((encoded, relocation)) => {
+                    relocations.extend(relocation);
+                    encoded
+                }
+                Err(diagnostic) => {
+                    diagnostics.push(diagnostic);
+                    let size = size(statement).unwrap_or(1);
+                    alloc::vec![0; usize::try_from(size).unwrap_or(1)]
+                }
+            };
+            let length = u16::try_from(encoded.len()).unwrap_or(u16::MAX);
+            match lines.last_mut() {
+                _ if length == 0 => {}
+                Some(last) if last.line == statement.span.line => {
+                    last.length = last.length.saturating_add(length);
+                }
+                _ => lines.push(SourceLine {
+                    line: statement.span.line,
+                    address,
+                    length,
+                }),
+            }
+            address = address.wrapping_add(length);
+            words.extend(encoded);
+        }
+        if !diagnostics.is_empty() {
+            return Err(sorted(diagnostics));
+        }
+        Ok(Assembly {
+            origin,
+            words,
+            symbols,
+            lines,
+            globals: globals.into_values().map(|(name, _)| name).collect(),
+            relocations,
+        })
+    }
+
+    /// The origin and the statements after `.ORIG`, which must be closed by
+    /// `.END`
+    fn split_origin(&self) -> Result<(u16, &[Statement]), Diagnostic> {
+        match self.statements.first() {
+            Some(statement) if statement.operation.as_deref() == Some(".ORIG") => {
+                if let Some(label) = &statement.label {
+                    return Err(diagnostic(label.span, ".ORIG cannot have a label"));
+                }
+                let origin = unsigned(statement, 0)?;
+                let body = self.statements.get(1..).unwrap_or_default();
+                if !body
+                    .iter()
+                    .any(|statement| statement.operation.as_deref() == Some(".END"))
+                {
+                    return Err(diagnostic(statement.span, "the program has no .END")
+                        .with_help(String::from("add .END after the last line of the program")));
+                }
+                Ok((origin, body))
+            }
+            first => match self.origin {
+                Some(origin) => Ok((origin, &self.statements)),
+                None => Err(diagnostic(
+                    first.map_or(START, |statement| statement.span),
+                    "the program must start with .ORIG",
+                )
+                .with_help(String::from(
+                    "add a first line like .ORIG x3000 with the address to load at",
+                ))),
+            },
+        }
+    }
+}
+
+/// The labels named by the `.EXTERNAL` or `.GLOBAL` statements, by their
+/// uppercase name
+fn linkage(statements: &[Statement], directive: &str) -> BTreeMap<String, (String, Span)> {
+    statements
+        .iter()
+        .filter(|statement| statement.operation.as_deref() == Some(directive))
+        .flat_map(|statement| &statement.operands)
+        .filter_map(|token| {
+            let name = token.word()?;
+            Some((name.to_ascii_uppercase(), (name.to_owned(), token.span)))
+        })
+        .collect()
+}
+
+fn sorted(mut diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+    diagnostics.sort_by_key(|diagnostic| (diagnostic.line, diagnostic.column));
+    diagnostics
+}
+
+/// Splits a line into its label, operation and operands, or `None` for a
+/// line without either
+fn statement(mut tokens: Vec<Token>) -> Result<Option<Statement>, Diagnostic> {
+    let label = match tokens.first() {
+        Some(token) if token.word().is_some_and(|word| !is_operation(word)) => {
+            Some(tokens.remove(0))
+        }
+        _ => None,
+    };
+    let operation = match tokens.first() {
+        None => None,
+        Some(token) => match token.word() {
+            // `ADDD R0, R0, #1` reads as the label ADDD before an operation R0
+            Some(word) if register(word).is_some() || number(word).is_some() => {
+                return Err(unknown_operation(label.as_ref().unwrap_or(token)));
+            }
+            Some(_) => Some(tokens.remove(0)),
+            None => return Err(diagnostic(token.span, "expected an operation")),
+        },
+    };
+    let Some(span) = operation
+        .as_ref()
+        .or(label.as_ref())
+        .map(|token| token.span)
+    else {
+        return Ok(None);
+    };
+    Ok(Some(Statement {
+        label,
+        operation: operation.map(|token| token.text.to_ascii_uppercase()),
+        span,
+        operands: tokens,
+    }))
+}
+
+fn unknown_operation(token: &Token) -> Diagnostic {
+    let found = diagnostic(token.span, &format!("unknown operation {}", token.text));
+    match closest(&token.text, operations()) {
+        Some(operation) => found.with_help(format!("did you mean {operation}?")),
+        None => found,
+    }
+}
+
+/// Macros nested deeper than this are taken for a macro using itself
+const MACRO_DEPTH: usize = 16;
+
+/// A `.MACRO` definition
+#[derive(Debug, Clone)]
+struct Macro {
+    parameters: Vec<String>,
+    body: Vec<Vec<Token>>,
+}
+
+/// Constants and macros defined so far, keyed by their uppercase name, the
+/// expanded lines and the errors found
+#[derive(Default)]
+struct Preprocessor {
+    constants: BTreeMap<String, Vec<Token>>,
+    macros: BTreeMap<String, Macro>,
+    lines: Vec<Vec<Token>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// Tokenizes `source`, removing the `.DEFINE` and `.MACRO` definitions and
+/// expanding their uses
+fn preprocess(source: &str) -> Preprocessor {
+    let mut preprocessor = Preprocessor::default();
+    let mut lines = source.lines().zip(1..);
+    while let Some((text, line)) = lines.next() {
+        let tokens = match tokenize(line, text) {
+            Ok(tokens) => tokens,
+            Err(diagnostic) => {
+                preprocessor.diagnostics.push(diagnostic);
+                continue;
+            }
+        };
+        let result = match definition(&tokens) {
+            Some((".DEFINE", _)) => preprocessor.define(&tokens),
+            Some((".MACRO", start)) => {
+                let mut body = Vec::new();
+                let mut closed = false;
+                for (text, line) in lines.by_ref() {
+                    let tokens = match tokenize(line, text) {
+                        Ok(tokens) => tokens,
+                        Err(diagnostic) => {
+                            preprocessor.diagnostics.push(diagnostic);
+                            continue;
+                        }
+                    };
+                    match definition(&tokens) {
+                        Some((".ENDM", _)) => {
+                            closed = true;
+                            break;
+                        }
+                        Some((directive, token)) => preprocessor.diagnostics.push(diagnostic(
+                            token.span,
+                            &format!("{directive} cannot be used inside a macro"),
+                        )),
+                        None if tokens.is_empty() => {}
+                        None => body.push(tokens),
+                    }
+                }
+                if closed {
+                    preprocessor.define_macro(&tokens, body)
+                } else {
+                    Err(diagnostic(start.span, ".MACRO has no matching .ENDM")
+                        .with_help(String::from("end the macro with a line holding .ENDM")))
+                }
+            }
+            Some((_, token)) => Err(diagnostic(token.span, ".ENDM without .MACRO")),
+            None => preprocessor.expand(tokens, 0),
+        };
+        if let Err(diagnostic) = result {
+            preprocessor.diagnostics.push(diagnostic);
+        }
+    }
+    preprocessor
+}
+
+/// The directive of a line starting with `.DEFINE`, `.MACRO` or `.ENDM`,
+/// and its token
+fn definition(tokens: &[Token]) -> Option<(&'static str, &Token)> {
+    let token = tokens.first()?;
+    let word = token.word()?.to_ascii_uppercase();
+    [".DEFINE", ".MACRO", ".ENDM"]
+        .into_iter()
+        .find(|directive| *directive == word)
+        .map(|directive| (directive, token))
+}
+
+impl Preprocessor {
+    /// `.DEFINE NAME VALUE`, where the value can use earlier constants
+    fn define(&mut self, tokens: &[Token]) -> Result<(), Diagnostic> {
+        let (name, value) = match tokens {
+            [_, name, value @ ..] if name.word().is_some() && !value.is_empty() => (name, value),
+            _ => {
+                let span = tokens.first().map_or(START, |token| token.span);
+                return Err(diagnostic(span, "expected .DEFINE NAME VALUE"));
+            }
+        };
+        let name = self.new_name(name)?;
+        let value = self.substitute(value.to_vec());
+        self.constants.insert(name, value);
+        Ok(())
+    }
+
+    /// `.MACRO NAME PARAM, ...` followed by its body
+    fn define_macro(&mut self, tokens: &[Token], body: Vec<Vec<Token>>) -> Result<(), Diagnostic> {
+        let (name, declared) = match tokens {
+            [_, name, declared @ ..] if name.word().is_some() => (name, declared),
+            _ => {
+                let span = tokens.first().map_or(START, |token| token.span);
+                return Err(diagnostic(span, "expected .MACRO NAME PARAM, ..."));
+            }
+        };
+        let name = self.new_name(name)?;
+        let mut parameters: Vec<String> = Vec::new();
+        for token in declared {
+            let Some(parameter) = token.word() else {
+                return Err(diagnostic(token.span, "unexpected string"));
+            };
+            let parameter = parameter.to_ascii_uppercase();
+            if parameters.contains(&parameter) {
+                return Err(diagnostic(
+                    token.span,
+                    &format!("parameter {parameter} is declared twice"),
+                ));
+            }
+            parameters.push(parameter);
+        }
+        self.macros.insert(name, Macro { parameters, body });
+        Ok(())
+    }
+
+    /// The uppercase name of a new constant or macro, which cannot shadow
+    /// an operation, a register or another definition
+    fn new_name(&self, token: &Token) -> Result<String, Diagnostic> {
+        let upper = token.text.to_ascii_uppercase();
+        if is_operation(&upper) || register(&upper).is_some() || number(&upper).is_some() {
+            return Err(diagnostic(
+                token.span,
+                &format!("{} cannot be redefined", token.text),
+            ));
+        }
+        if self.constants.contains_key(&upper) || self.macros.contains_key(&upper) {
+            return Err(diagnostic(
+                token.span,
+                &format!("{} is defined twice", token.text),
+            ));
+        }
+        Ok(upper)
+    }
+
+    /// Replaces the constants in a line, pointing their values at the name
+    fn substitute(&self, tokens: Vec<Token>) -> Vec<Token> {
+        tokens
+            .into_iter()
+            .flat_map(|token| {
+                let value = token
+                    .word()
+                    .and_then(|word| self.constants.get(&word.to_ascii_uppercase()));
+                match value {
+                    Some(value) => value.iter().map(|part| part.at(token.span)).collect(),
+                    None => alloc::vec![token],
+                }
+            })
+            .collect()
+    }
+
+    /// The name and definition of the macro a token names, if any
+    fn used_macro(&self, token: &Token) -> Option<(String, Macro)> {
+        let name = token.word()?.to_ascii_uppercase();
+        let definition = self.macros.get(&name)?.clone();
+        Some((name, definition))
+    }
+
+    /// Adds a line, expanding it if it uses a macro. The expansion points
+    /// at the macro name, or at the operand a parameter is replaced with.
+    fn expand(&mut self, tokens: Vec<Token>, depth: usize) -> Result<(), Diagnostic> {
+        let tokens = self.substitute(tokens);
+        let start = match tokens.as_slice() {
+            [first, ..] if self.used_macro(first).is_some() => 0,
+            [label, second, ..] if label.word().is_some() && self.used_macro(second).is_some() => 1,
+            _ => {
+                self.lines.push(tokens);
+                return Ok(());
+            }
+        };
+        let (label, invocation) = tokens.split_at(start);
+        let Some((token, ((name, definition), arguments))) = invocation
+            .split_first()
+            .and_then(|(token, arguments)| Some((token, (self.used_macro(token)?, arguments))))
+        else {
+            return Ok(());
+        };
+        if depth >= MACRO_DEPTH {
+            return Err(diagnostic(
+                token.span,
+                &format!("macro {name} is nested too deeply, does it use itself?"),
+            ));
+        }
+        let expected = definition.parameters.len();
+        if arguments.len() != expected {
+            return Err(diagnostic(
+                token.span,
+                &format!(
+                    "{name} takes {expected} operand{}, found {}",
+                    if expected == 1 { "" } else { "s" },
+                    arguments.len()
+                ),
+            ));
+        }
+        if !label.is_empty() {
+            self.lines.push(label.to_vec());
+        }
+        for body_line in definition.body {
+            let replaced = body_line
+                .iter()
+                .map(|part| {
+                    let parameter = part.word().and_then(|word| {
+                        definition
+                            .parameters
+                            .iter()
+                            .position(|parameter| parameter.eq_ignore_ascii_case(word))
+                    });
+                    match parameter.and_then(|index| arguments.get(index)) {
+                        Some(argument) => argument.clone(),
+                        None => part.at(token.span),
+                    }
+                })
+                .collect();
+            self.expand(replaced, depth.saturating_add(1))?;
+        }
+        Ok(())
+    }
+}
+
+/// First pass: the address of every label
+fn define_labels(
+    origin: u16,
+    statements: &[Statement],
+    externals: &BTreeMap<String, (String, Span)>,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> SymbolTable {
+    let mut symbols = SymbolTable::default();
+    let mut lines = BTreeMap::new();
+    let mut address = u32::from(origin);
+    for statement in statements {
+        if statement.operation.as_deref() == Some(".END") {
+            break;
+        }
+        let Ok(current) = u16::try_from(address) else {
+            diagnostics.push(diagnostic(
+                statement.span,
+                "the program does not fit in memory",
+            ));
+            return symbols;
+        };
+        if let Some(label) = &statement.label {
+            let name = label.text.to_ascii_uppercase();
+            match lines.get(&name) {
+                _ if externals.contains_key(&name) => diagnostics.push(diagnostic(
+                    label.span,
+                    &format!("label {} is declared .EXTERNAL", label.text),
+                )),
+                Some(line) => diagnostics.push(
+                    diagnostic(
+                        label.span,
+                        &format!("label {} is defined twice", label.text),
+                    )
+                    .with_help(format!("it was first defined on line {line}")),
+                ),
+                None => {
+                    lines.insert(name, label.span.line);
+                    symbols.insert(&label.text, current);
+                }
+            }
+        }
+        // a size that cannot be read is reported by the second pass
+        address = address.saturating_add(size(statement).unwrap_or(1));
+    }
+    if address > 0x10000 {
+        let last = statements
+            .iter()
+            .take_while(|statement| statement.operation.as_deref() != Some(".END"))
+            .last();
+        if let Some(statement) = last {
+            diagnostics.push(diagnostic(
+                statement.span,
+                "the program does not fit in memory",
+            ));
+        }
+    }
+    symbols
+}
+
+/// Number of words a statement occupies
+fn size(statement: &Statement) -> Result<u32, Diagnostic> {
+    Ok(match statement.operation.as_deref() {
+        None => 0,
+        Some(".BLKW") => u32::from(unsigned(statement, 0)?),
+        Some(".EXTERNAL" | ".GLOBAL") => 0,
+        Some(".STRINGZ") => {
+            let length = string(statement, 0)?.chars().count();
+            u32::try_from(length).unwrap_or(u32::MAX).saturating_add(1)
+        }
+        Some(_) => 1,
+    })
+}
+
+/// Second pass: the words of a statement placed at `address`
+fn encode(
+    statement: &Statement,
+    address: u16,
+    context: &Context,
+) -> Result<(Vec<u16>, Option<Relocation>), Diagnostic> {
+    let Some(operation) = statement.operation.as_deref() else {
+        return Ok((Vec::new(), None));
+    };
+    let operands = Operands {
+        statement,
+        operation,
+        next: address.wrapping_add(1),
+        context,
+        relocation: RefCell::new(None),
+    };
+    let words = encode_operation(&operands)?;
+    Ok((words, operands.relocation.into_inner()))
+}
+
+/// The labels known while encoding
+struct Context<'a> {
+    symbols: &'a SymbolTable,
+    externals: &'a BTreeMap<String, (String, Span)>,
+}
+
+fn encode_operation(operands: &Operands) -> Result<Vec<u16>, Diagnostic> {
+    let statement = operands.statement;
+    let operation = operands.operation;
+    let instruction = match operation {
+        ".EXTERNAL" | ".GLOBAL" => {
+            if let Some(token) = statement.operands.iter().find(|token| token.quoted) {
+                return Err(diagnostic(token.span, "expected a label"));
+            }
+            if statement.operands.is_empty() {
+                return Err(diagnostic(
+                    statement.span,
+                    &format!("{operation} takes labels"),
+                ));
+            }
+            return Ok(Vec::new());
+        }
+        ".ORIG" => {
+            return Err(
+                diagnostic(statement.span, "only one .ORIG is supported").with_help(String::from(
+                    "assemble each block on its own and load the images together",
+                )),
+            )
+        }
+        ".FILL" => {
+            operands.count(1)?;
+            return Ok(alloc::vec![operands.word_value(0)?]);
+        }
+        ".BLKW" => {
+            operands.count(1)?;
+            return Ok(alloc::vec![0; usize::from(unsigned(statement, 0)?)]);
+        }
+        ".STRINGZ" => {
+            operands.count(1)?;
+            let text = string(statement, 0)?;
+            let span = operands.token(0)?.span;
+            return text
+                .chars()
+                .map(|character| {
+                    u8::try_from(character).map(u16::from).map_err(|_| {
+                        diagnostic(span, &format!("{character:?} is not an 8-bit character"))
+                    })
+                })
+                .chain(core::iter::once(Ok(0)))
+                .collect();
+        }
+        "ADD" | "AND" => {
+            operands.count(3)?;
+            let dr = operands.register(0)?;
+            let sr1 = operands.register(1)?;
+            let operand = match operands.token(2)?.word().and_then(register) {
+                Some(register) => Operand::Register(register),
+                None => Operand::Immediate(operands.immediate(2, 5)?),
+            };
+            if operation == "ADD" {
+                Instruction::Add { dr, sr1, operand }
+            } else {
+                Instruction::And { dr, sr1, operand }
+            }
+        }
+        "NOT" => {
+            operands.count(2)?;
+            Instruction::Not {
+                dr: operands.register(0)?,
+                sr: operands.register(1)?,
+            }
+        }
+        "LD" | "LDI" | "LEA" | "ST" | "STI" => {
+            operands.count(2)?;
+            let register = operands.register(0)?;
+            let pc_offset = operands.pc_offset(1, 9)?;
+            match operation {
+                "LD" => Instruction::Ld {
+                    dr: register,
+                    pc_offset,
+                },
+                "LDI" => Instruction::Ldi {
+                    dr: register,
+                    pc_offset,
+                },
+                "LEA" => Instruction::Lea {
+                    dr: register,
+                    pc_offset,
+                },
+                "ST" => Instruction::St {
+                    sr: register,
+                    pc_offset,
+                },
+                _ => Instruction::Sti {
+                    sr: register,
+                    pc_offset,
+                },
+            }
+        }
+        "LDR" | "STR" => {
+            operands.count(3)?;
+            let register = operands.register(0)?;
+            let base = operands.register(1)?;
+            let offset = operands.immediate(2, 6)?;
+            if operation == "LDR" {
+                Instruction::Ldr {
+                    dr: register,
+                    base,
+                    offset,
+                }
+            } else {
+                Instruction::Str {
+                    sr: register,
+                    base,
+                    offset,
+                }
+            }
+        }
+        "JMP" => {
+            operands.count(1)?;
+            Instruction::Jmp {
+                base: operands.register(0)?,
+            }
+        }
+        "RET" => {
+            operands.count(0)?;
+            Instruction::Jmp { base: Register::R7 }
+        }
+        "JSR" => {
+            operands.count(1)?;
+            Instruction::Jsr {
+                target: JsrTarget::Offset(operands.pc_offset(0, 11)?),
+            }
+        }
+        "JSRR" => {
+            operands.count(1)?;
+            Instruction::Jsr {
+                target: JsrTarget::Register(operands.register(0)?),
+            }
+        }
+        "RTI" => {
+            operands.count(0)?;
+            Instruction::Rti
+        }
+        "TRAP" => {
+            operands.count(1)?;
+            let trap_vector = unsigned(statement, 0)?;
+            if trap_vector > 0xFF {
+                return Err(diagnostic(
+                    operands.token(0)?.span,
+                    &format!("trap vector x{trap_vector:X} does not fit in 8 bits"),
+                ));
+            }
+            Instruction::Trap { trap_vector }
+        }
+        _ => match (trap_alias(operation), branch_flags(operation)) {
+            (Some(trap_vector), _) => {
+                operands.count(0)?;
+                Instruction::Trap { trap_vector }
+            }
+            (None, Some((n, z, p))) => {
+                operands.count(1)?;
+                Instruction::Br {
+                    n,
+                    z,
+                    p,
+                    pc_offset: operands.pc_offset(0, 9)?,
+                }
+            }
+            (None, None) => {
+                return Err(unknown_operation(&Token {
+                    text: String::from(operation),
+                    quoted: false,
+                    span: statement.span,
+                }))
+            }
+        },
+    };
+    Ok(alloc::vec![instruction.encode()])
+}
+
+/// Operands of a statement, resolved against the labels
+struct Operands<'a> {
+    statement: &'a Statement,
+    operation: &'a str,
+    /// Address after the statement, which PC offsets are relative to
+    next: u16,
+    context: &'a Context<'a>,
+    /// The external label used, if any
+    relocation: RefCell<Option<Relocation>>,
+}
+
+impl Operands<'_> {
+    fn count(&self, expected: usize) -> Result<(), Diagnostic> {
+        let found = self.statement.operands.len();
+        if found == expected {
+            return Ok(());
+        }
+        let span = self
+            .statement
+            .operands
+            .get(expected)
+            .map_or(self.statement.span, |extra| extra.span);
+        Err(diagnostic(
+            span,
+            &format!(
+                "{} takes {expected} operand{}, found {found}",
+                self.operation,
+                if expected == 1 { "" } else { "s" }
+            ),
+        ))
+    }
+
+    fn token(&self, index: usize) -> Result<&Token, Diagnostic> {
+        self.statement
+            .operands
+            .get(index)
+            .ok_or_else(|| diagnostic(self.statement.span, "missing operand"))
+    }
+
+    /// Operand `index`, which cannot be a string
+    fn word(&self, index: usize) -> Result<(&str, Span), Diagnostic> {
+        let token = self.token(index)?;
+        match token.word() {
+            Some(word) => Ok((word, token.span)),
+            None => Err(diagnostic(token.span, "unexpected string")),
+        }
+    }
+
+    fn register(&self, index: usize) -> Result<Register, Diagnostic> {
+        let (word, span) = self.word(index)?;
+        register(word).ok_or_else(|| {
+            let found = diagnostic(span, &format!("expected a register, found {word}"));
+            if word.starts_with(['R', 'r']) {
+                found.with_help(String::from("registers are R0 to R7"))
+            } else {
+                found
+            }
+        })
+    }
+
+    /// A number that must fit a signed field of `bits` bits
+    fn immediate(&self, index: usize, bits: u32) -> Result<i16, Diagnostic> {
+        let (word, span) = self.word(index)?;
+        let value = number(word)
+            .ok_or_else(|| diagnostic(span, &format!("expected a number, found {word}")))?;
+        self.fit(value, bits, span).map_err(|found| {
+            let (low, high) = range(bits);
+            let operation = self.operation;
+            found.with_help(if bits == 5 {
+                format!(
+                    "{operation} takes immediates from #{low} to #{high}; \
+                     load other values with LD from a .FILL"
+                )
+            } else {
+                format!(
+                    "{operation} takes offsets from #{low} to #{high}; \
+                     add the rest to the base register first"
+                )
+            })
+        })
+    }
+
+    /// A label, as an offset from the next instruction, or a literal offset
+    fn pc_offset(&self, index: usize, bits: u32) -> Result<i16, Diagnostic> {
+        let (word, span) = self.word(index)?;
+        let value = match number(word) {
+            Some(offset) => offset,
+            None if self.external(word, Field::PcOffset(bits)) => 0,
+            None => {
+                let target = self.label(word, span)?;
+                i32::from(target).wrapping_sub(i32::from(self.next))
+            }
+        };
+        self.fit(value, bits, span).map_err(|found| {
+            let (low, high) = range(bits);
+            found.with_help(format!(
+                "{} reaches #{low} to #{high} words from the next instruction; move the \
+                 target closer, or load its address with LD from a .FILL and use a register",
+                self.operation
+            ))
+        })
+    }
+
+    /// A number or the address of a label, as a whole word
+    fn word_value(&self, index: usize) -> Result<u16, Diagnostic> {
+        let (word, span) = self.word(index)?;
+        match number(word) {
+            Some(value) => u16::try_from(value)
+                .or_else(|_| {
+                    i16::try_from(value).map(|value| u16::from_ne_bytes(value.to_ne_bytes()))
+                })
+                .map_err(|_| diagnostic(span, &format!("{word} does not fit in 16 bits"))),
+            None if self.external(word, Field::Word) => Ok(0),
+            None => self.label(word, span),
+        }
+    }
+
+    /// Whether `name` is external, recording its use for the linker
+    fn external(&self, name: &str, field: Field) -> bool {
+        let Some((symbol, _)) = self.context.externals.get(&name.to_ascii_uppercase()) else {
+            return false;
+        };
+        self.relocation.replace(Some(Relocation {
+            address: self.next.wrapping_sub(1),
+            symbol: symbol.clone(),
+            field,
+        }));
+        true
+    }
+
+    fn label(&self, name: &str, span: Span) -> Result<u16, Diagnostic> {
+        let symbols = self.context.symbols;
+        symbols.address_of(name).ok_or_else(|| {
+            let found = diagnostic(span, &format!("unknown label {name}"));
+            match closest(name, symbols.iter().map(|(_, label)| label)) {
+                Some(label) => found.with_help(format!("did you mean {label}?")),
+                None => found,
+            }
+        })
+    }
+
+    fn fit(&self, value: i32, bits: u32, span: Span) -> Result<i16, Diagnostic> {
+        match i16::try_from(value) {
+            Ok(value) if fits(value, bits) => Ok(value),
+            _ => Err(diagnostic(
+                span,
+                &format!("{value} does not fit in {bits} signed bits"),
+            )),
+        }
+    }
+}
+
+/// Smallest and largest values of a signed field of `bits` bits
+fn range(bits: u32) -> (i32, i32) {
+    let half = 1_i32.checked_shl(bits.saturating_sub(1)).unwrap_or(0);
+    (half.saturating_neg(), half.saturating_sub(1))
+}
+
+/// Operand `index` of a directive or TRAP, a number from 0 to xFFFF
+fn unsigned(statement: &Statement, index: usize) -> Result<u16, Diagnostic> {
+    let token = statement.operands.get(index);
+    token
+        .and_then(Token::word)
+        .and_then(number)
+        .and_then(|value| u16::try_from(value).ok())
+        .ok_or_else(|| {
+            diagnostic(
+                token.map_or(statement.span, |token| token.span),
+                "expected a number from 0 to xFFFF",
+            )
+        })
+}
+
+fn string(statement: &Statement, index: usize) -> Result<&str, Diagnostic> {
+    match statement.operands.get(index) {
+        Some(token) if token.quoted => Ok(&token.text),
+        token => Err(diagnostic(
+            token.map_or(statement.span, |token| token.span),
+            "expected a string in double quotes",
+        )),
+    }
+}
+
+/// Splits a line into words and strings, dropping commas and the comment
+fn tokenize(line: usize, text: &str) -> Result<Vec<Token>, Diagnostic> {
+    let mut tokens = Vec::new();
+    let mut characters = text.chars().zip(1..).peekable();
+    while let Some(&(character, column)) = characters.peek() {
+        match character {
+            ';' => break,
+            ',' => {
+                characters.next();
+            }
+            '"' => {
+                characters.next();
+                let (text, width) = quoted(line, column, &mut characters)?;
+                tokens.push(Token {
+                    text,
+                    quoted: true,
+                    span: Span {
+                        line,
+                        column,
+                        width,
+                    },
+                });
+            }
+            _ if character.is_whitespace() => {
+                characters.next();
+            }
+            _ => {
+                let mut word = String::new();
+                let mut width: usize = 0;
+                while let Some(&(character, _)) = characters.peek() {
+                    if character.is_whitespace() || matches!(character, ',' | ';' | '"') {
+                        break;
+                    }
+                    word.push(character);
+                    width = width.saturating_add(1);
+                    characters.next();
+                }
+                tokens.push(Token {
+                    text: word,
+                    quoted: false,
+                    span: Span {
+                        line,
+                        column,
+                        width,
+                    },
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// The rest of a string literal opened by the quote at `start`, and the
+/// width of the literal with its quotes
+fn quoted(
+    line: usize,
+    start: usize,
+    characters: &mut impl Iterator<Item = (char, usize)>,
+) -> Result<(String, usize), Diagnostic> {
+    let mut text = String::new();
+    let mut end = start;
+    while let Some((character, column)) = characters.next() {
+        end = column;
+        match character {
+            '"' => return Ok((text, column.saturating_sub(start).saturating_add(1))),
+            '\\' => {
+                let escaped = characters.next();
+                if let Some((_, last)) = escaped {
+                    end = last;
+                }
+                text.push(match escaped.map(|(escaped, _)| escaped) {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('r') => '\r',
+                    Some('0') => '\0',
+                    Some('e') => '\x1B',
+                    Some(other @ ('\\' | '"' | '\'')) => other,
+                    Some(other) => {
+                        let span = Span {
+                            line,
+                            column,
+                            width: 2,
+                        };
+                        return Err(diagnostic(span, &format!("unknown escape \\{other}"))
+                            .with_help(String::from(
+                                "the escapes are \\n, \\t, \\r, \\0, \\e, \\\\, \\\" and \\'",
+                            )));
+                    }
+                    None => break,
+                });
+            }
+            _ => text.push(character),
+        }
+    }
+    let span = Span {
+        line,
+        column: start,
+        width: end.saturating_sub(start).saturating_add(1),
+    };
+    Err(diagnostic(span, "unterminated string")
+        .with_help(String::from("close the string with a double quote")))
+}
+
+/// Parses `#10`, `10`, `#-3`, `x3000`, `0x3000` and `x-1`
+fn number(token: &str) -> Option<i32> {
+    let (radix, digits) = if let Some(decimal) = token.strip_prefix('#') {
+        (10, decimal)
+    } else if let Some(hex) = token
+        .strip_prefix("0x")
+        .or_else(|| token.strip_prefix("0X"))
+        .or_else(|| token.strip_prefix(['x', 'X']))
+    {
+        (16, hex)
+    } else {
+        (10, token)
+    };
+    let (negative, magnitude) = match digits.strip_prefix('-') {
+        Some(magnitude) => (true, magnitude),
+        None => (false, digits),
+    };
+    if magnitude.is_empty() || !magnitude.chars().all(|digit| digit.is_digit(radix)) {
+        return None;
+    }
+    let value = i32::from_str_radix(magnitude, radix).ok()?;
+    Some(if negative {
+        value.wrapping_neg()
+    } else {
+        value
+    })
+}
+
+fn register(token: &str) -> Option<Register> {
+    match token.as_bytes() {
+        [b'R' | b'r', digit @ b'0'..=b'7'] => Some(Register::from_bits(u16::from(*digit))),
+        _ => None,
+    }
+}
+
+const OPCODES: [&str; 16] = [
+    "ADD", "AND", "NOT", "LD", "LDI", "LDR", "LEA", "ST", "STI", "STR", "JMP", "RET", "JSR",
+    "JSRR", "RTI", "TRAP",
+];
+
+const TRAP_ALIASES: [(&str, u16); 6] = [
+    ("GETC", 0x20),
+    ("OUT", 0x21),
+    ("PUTS", 0x22),
+    ("IN", 0x23),
+    ("PUTSP", 0x24),
+    ("HALT", 0x25),
+];
+
+const BRANCHES: [&str; 8] = ["BR", "BRn", "BRz", "BRp", "BRnz", "BRnp", "BRzp", "BRnzp"];
+
+const DIRECTIVES: [&str; 10] = [
+    ".ORIG",
+    ".FILL",
+    ".BLKW",
+    ".STRINGZ",
+    ".END",
+    ".DEFINE",
+    ".MACRO",
+    ".ENDM",
+    ".EXTERNAL",
+    ".GLOBAL",
+];
+
+/// Every operation and directive, for suggestions
+fn operations() -> impl Iterator<Item = &'static str> {
+    OPCODES
+        .into_iter()
+        .chain(TRAP_ALIASES.into_iter().map(|(alias, _)| alias))
+        .chain(BRANCHES)
+        .chain(DIRECTIVES)
+}
+
+fn trap_alias(operation: &str) -> Option<u16> {
+    TRAP_ALIASES
+        .into_iter()
+        .find(|(alias, _)| *alias == operation)
+        .map(|(_, trap_vector)| trap_vector)
+}
+
+/// The n, z and p flags of `BR`, `BRn`, ..., `BRnzp`; plain `BR` branches
+/// always
+fn branch_flags(operation: &str) -> Option<(bool, bool, bool)> {
+    let flags = operation.strip_prefix("BR")?;
+    if flags.is_empty() {
+        return Some((true, true, true));
+    }
+    let mut rest = flags;
+    let mut take = |flag: char| match rest.strip_prefix(flag) {
+        Some(remaining) => {
+            rest = remaining;
+            true
+        }
+        None => false,
+    };
+    let (n, z, p) = (take('N'), take('Z'), take('P'));
+    rest.is_empty().then_some((n, z, p))
+}
+
+fn is_operation(word: &str) -> bool {
+    let upper = word.to_ascii_uppercase();
+    upper.starts_with('.')
+        || trap_alias(&upper).is_some()
+        || branch_flags(&upper).is_some()
+        || OPCODES.contains(&upper.as_str())
+}
+
+/// The candidate `word` is most likely a misspelling of, if any is close
+fn closest<'a>(word: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let word = word.to_ascii_uppercase();
+    let tolerance = (word.chars().count() / 3).max(1);
+    candidates
+        .map(|candidate| (distance(&word, &candidate.to_ascii_uppercase()), candidate))
+        .filter(|(distance, _)| *distance <= tolerance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Edit distance: the fewest characters to insert, delete or replace to
+/// turn `a` into `b`
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (row, from) in a.chars().enumerate() {
+        let mut current = Vec::with_capacity(previous.len());
+        current.push(row.saturating_add(1));
+        for ((to, diagonal), above) in b.iter().zip(&previous).zip(previous.iter().skip(1)) {
+            let left = current.last().copied().unwrap_or_default();
+            let replace = diagonal.saturating_add(usize::from(from != *to));
+            current.push(
+                replace
+                    .min(above.saturating_add(1))
+                    .min(left.saturating_add(1)),
+            );
+        }
+        previous = current;
+    }
+    previous.last().copied().unwrap_or_default()
+}
+
+fn diagnostic(span: Span, message: &str) -> Diagnostic {
+    Diagnostic {
+        line: span.line,
+        column: span.column,
+        width: span.width,
+        message: String::from(message),
+        help: None,
+    }
+}
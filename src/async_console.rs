@@ -0,0 +1,190 @@
+//! Console over tokio readers and writers, so the VM can be hosted inside
+//! async services. `VM::run_async` awaits keys instead of blocking the
+//! executor and yields whenever the program does I/O.
+//!
+//! ```ignore
+//! let (input, output) = socket.into_split();
+//! let mut console = AsyncConsole::new(input, output);
+//! vm.run_async(&mut console).await?;
+//! ```
+//!
+//! The VM is not `Send`, so run the future with `block_on` or on a
+//! `LocalSet`.
+
+use std::{cell::RefCell, collections::VecDeque, mem, rc::Rc};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    console::Console,
+    errors::{IoError, VMError},
+    instructions::Opcode,
+    vm::{StopReason, VM},
+};
+
+/// Instructions executed without any I/O before yielding anyway
+const YIELD_INTERVAL: u32 = 10_000;
+/// Bytes taken from the input per read
+const READ_CHUNK: usize = 256;
+
+#[derive(Default)]
+struct Buffers {
+    input: VecDeque<u8>,
+    output: String,
+    /// Set when the program polled the keyboard
+    polled: bool,
+}
+
+/// The side of the console owned by the VM, which only touches the buffers
+struct Endpoint(Rc<RefCell<Buffers>>);
+
+impl Console for Endpoint {
+    fn read_key(&mut self) -> Result<u8, VMError> {
+        self.0
+            .borrow_mut()
+            .input
+            .pop_front()
+            .ok_or_else(|| VMError::StandardIO(IoError::new("No input is available")))
+    }
+
+    fn poll_key(&mut self) -> Result<Option<u8>, VMError> {
+        let mut buffers = self.0.borrow_mut();
+        buffers.polled = true;
+        Ok(buffers.input.pop_front())
+    }
+
+    fn write_char(&mut self, character: char) -> Result<(), VMError> {
+        self.0.borrow_mut().output.push(character);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), VMError> {
+        Ok(())
+    }
+}
+
+/// Keyboard input read from `R` and output written to `W`
+pub struct AsyncConsole<R, W> {
+    input: R,
+    output: W,
+    buffers: Rc<RefCell<Buffers>>,
+}
+
+impl<R, W> AsyncConsole<R, W>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    pub fn new(input: R, output: W) -> Self {
+        AsyncConsole {
+            input,
+            output,
+            buffers: Rc::default(),
+        }
+    }
+
+    pub fn into_inner(self) -> (R, W) {
+        (self.input, self.output)
+    }
+
+    fn has_input(&self) -> bool {
+        !self.buffers.borrow().input.is_empty()
+    }
+
+    fn take_polled(&self) -> bool {
+        mem::take(&mut self.buffers.borrow_mut().polled)
+    }
+
+    /// Waits until at least one key arrived
+    async fn wait_for_key(&mut self) -> Result<(), VMError> {
+        self.write_output().await?;
+        let mut bytes = [0; READ_CHUNK];
+        let read = self.input.read(&mut bytes).await.map_err(io_error)?;
+        if read == 0 {
+            return Err(VMError::StandardIO(IoError::new(
+                "The input closed while the program waits for a key",
+            )));
+        }
+        self.push_input(&bytes, read);
+        Ok(())
+    }
+
+    /// Writes pending output, takes any input that already arrived and
+    /// lets other tasks run
+    async fn exchange(&mut self) -> Result<(), VMError> {
+        self.write_output().await?;
+        let mut bytes = [0; READ_CHUNK];
+        tokio::select! {
+            biased;
+            read = self.input.read(&mut bytes) => {
+                let read = read.map_err(io_error)?;
+                self.push_input(&bytes, read);
+            }
+            () = tokio::task::yield_now() => return Ok(()),
+        }
+        tokio::task::yield_now().await;
+        Ok(())
+    }
+
+    fn push_input(&self, bytes: &[u8], read: usize) {
+        let bytes = bytes.get(..read).unwrap_or_default();
+        self.buffers.borrow_mut().input.extend(bytes);
+    }
+
+    async fn write_output(&mut self) -> Result<(), VMError> {
+        let output = mem::take(&mut self.buffers.borrow_mut().output);
+        if output.is_empty() {
+            return Ok(());
+        }
+        self.output
+            .write_all(output.as_bytes())
+            .await
+            .map_err(io_error)?;
+        self.output.flush().await.map_err(io_error)
+    }
+}
+
+fn io_error(error: std::io::Error) -> VMError {
+    VMError::StandardIO(IoError::caused_by("Console I/O failed", error))
+}
+
+impl VM {
+    /// Runs the program with `console` as its terminal until it halts.
+    /// GETC and IN await a key, and the future yields after traps, keyboard
+    /// polls and every few thousand instructions.
+    pub async fn run_async<R, W>(&mut self, console: &mut AsyncConsole<R, W>) -> Result<(), VMError>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        self.set_console(Box::new(Endpoint(Rc::clone(&console.buffers))));
+        let result = self.run_async_loop(console).await;
+        let written = console.write_output().await;
+        result.and(written)
+    }
+
+    async fn run_async_loop<R, W>(
+        &mut self,
+        console: &mut AsyncConsole<R, W>,
+    ) -> Result<(), VMError>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut budget = YIELD_INTERVAL;
+        loop {
+            if self.waits_for_key() && !console.has_input() {
+                console.wait_for_key().await?;
+            }
+            let trap = Opcode::from_instruction(self.peek(self.pc())) == Opcode::Trap;
+            if self.step()? == StopReason::Halted {
+                return Ok(());
+            }
+            budget = budget.saturating_sub(1);
+            if console.take_polled() || trap || budget == 0 {
+                console.exchange().await?;
+                budget = YIELD_INTERVAL;
+            }
+        }
+    }
+}
@@ -0,0 +1,405 @@
+//! Runs a list of programs described by a TOML manifest, each with its own
+//! input, expected output and instruction limit, as grading pipelines do.
+//!
+//! ```toml
+//! # instruction limit of the programs that do not set one
+//! limit = 1000000
+//!
+//! [[program]]
+//! name = "hello"
+//! image = "hello.obj"
+//! expected = "hello.out"
+//!
+//! [[program]]
+//! image = ["lib.obj", "echo.obj"]
+//! input_text = "abc\n"
+//! expected_text = "abc\n"
+//! limit = 5000
+//! timeout = 2.5
+//! env = { SEED = "42" }
+//! ```
+//!
+//! Paths are relative to the manifest. `input` and `expected` name files,
+//! `input_text` and `expected_text` give their contents inline. Programs
+//! without an expected output pass when they halt. `timeout` stops programs
+//! after that many seconds of host time, and can be set for all of them
+//! like `limit`. `env` lists the variables the program reads with GETENV.
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use toml::{Table, Value};
+
+use crate::{
+    console::SharedConsole,
+    errors::{IoError, VMError},
+    vm::VM,
+};
+
+/// Instruction limit of programs when neither they nor the manifest set one
+pub const DEFAULT_LIMIT: u64 = 10_000_000;
+
+/// A program of the manifest, ready to run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    pub name: String,
+    pub images: Vec<PathBuf>,
+    /// Keys fed to the program
+    pub input: Vec<u8>,
+    /// Everything the program must print, if checked
+    pub expected: Option<String>,
+    pub limit: u64,
+    /// Host time the program may run for
+    pub timeout: Option<Duration>,
+    /// Variables the program reads with GETENV
+    pub env: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub jobs: Vec<Job>,
+}
+
+impl Manifest {
+    /// Reads the manifest at `path`, with the input and expected output files
+    /// it names
+    pub fn load(path: &Path) -> Result<Manifest, VMError> {
+        let text = fs::read_to_string(path).map_err(|e| {
+            VMError::ReadFile(IoError::caused_by(
+                format!("Could not read {}", path.display()),
+                e,
+            ))
+        })?;
+        Manifest::parse(&text, path.parent().unwrap_or(Path::new(".")))
+    }
+
+    /// Parses a manifest whose paths are relative to `base`
+    pub fn parse(text: &str, base: &Path) -> Result<Manifest, VMError> {
+        Ok(Manifest {
+            jobs: parse_jobs(text, base, "program", &[])?,
+        })
+    }
+
+    pub fn run(&self) -> Report {
+        Report {
+            outcomes: self.jobs.iter().map(Job::run).collect(),
+        }
+    }
+}
+
+/// Parses the `[[section]]` tables of a manifest into jobs loading `images`
+/// before the images they name
+pub(crate) fn parse_jobs(
+    text: &str,
+    base: &Path,
+    section: &str,
+    images: &[PathBuf],
+) -> Result<Vec<Job>, VMError> {
+    let table: Table = text
+        .parse()
+        .map_err(|error| invalid(format!("{error}").trim_end()))?;
+    let mut defaults = Defaults {
+        limit: DEFAULT_LIMIT,
+        timeout: None,
+    };
+    let mut entries = Vec::new();
+    for (key, value) in &table {
+        match (key.as_str(), value) {
+            ("limit", value) => defaults.limit = limit(value, "limit")?,
+            ("timeout", value) => defaults.timeout = Some(timeout(value, "timeout")?),
+            (key, Value::Array(tables)) if key == section => entries = tables.clone(),
+            _ => return Err(invalid(&format!("Unknown setting `{key}`"))),
+        }
+    }
+    if entries.is_empty() {
+        return Err(invalid(&format!("The manifest lists no [[{section}]]")));
+    }
+    entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            let Value::Table(entry) = entry else {
+                return Err(invalid(&format!("Every [[{section}]] must be a table")));
+            };
+            let context = format!("{section} {}", index.saturating_add(1));
+            job(entry, &context, base, images, &defaults)
+        })
+        .collect()
+}
+
+/// Settings of the jobs that do not set them
+struct Defaults {
+    limit: u64,
+    timeout: Option<Duration>,
+}
+
+fn job(
+    entry: &Table,
+    job_context: &str,
+    base: &Path,
+    images: &[PathBuf],
+    defaults: &Defaults,
+) -> Result<Job, VMError> {
+    let mut name = None;
+    let shared = images.len();
+    let mut images = images.to_vec();
+    let mut input = Vec::new();
+    let mut expected = None;
+    let mut job_limit = defaults.limit;
+    let mut job_timeout = defaults.timeout;
+    let mut env = BTreeMap::new();
+    for (key, value) in entry {
+        let context = format!("{job_context}: `{key}`");
+        match (key.as_str(), value) {
+            ("name", Value::String(text)) => name = Some(text.clone()),
+            ("image", Value::String(path)) => images.push(base.join(path)),
+            ("image", Value::Array(paths)) => {
+                for path in paths {
+                    let Value::String(path) = path else {
+                        return Err(invalid(&format!("{context} must list paths")));
+                    };
+                    images.push(base.join(path));
+                }
+            }
+            ("input", Value::String(path)) => input = read(&base.join(path))?.into_bytes(),
+            ("input_text", Value::String(text)) => input = text.clone().into_bytes(),
+            ("expected", Value::String(path)) => expected = Some(read(&base.join(path))?),
+            ("expected_text", Value::String(text)) => expected = Some(text.clone()),
+            ("limit", value) => job_limit = limit(value, &context)?,
+            ("timeout", value) => job_timeout = Some(timeout(value, &context)?),
+            ("env", Value::Table(variables)) => {
+                for (name, value) in variables {
+                    let Value::String(value) = value else {
+                        return Err(invalid(&format!("{context} must map names to strings")));
+                    };
+                    env.insert(name.clone(), value.clone());
+                }
+            }
+            (
+                "name" | "image" | "input" | "input_text" | "expected" | "expected_text" | "env",
+                _,
+            ) => {
+                return Err(invalid(&format!("{context} has the wrong type")));
+            }
+            _ => return Err(invalid(&format!("{context} is not a setting"))),
+        }
+    }
+    if images.is_empty() {
+        return Err(invalid(&format!("{job_context} has no `image`")));
+    }
+    // named after its first own image, or its position when it only loads
+    // the shared ones
+    let name = name.unwrap_or_else(|| match images.get(shared) {
+        Some(first) => first.file_stem().map_or_else(
+            || first.display().to_string(),
+            |stem| stem.to_string_lossy().into_owned(),
+        ),
+        None => String::from(job_context),
+    });
+    Ok(Job {
+        name,
+        images,
+        input,
+        expected,
+        limit: job_limit,
+        timeout: job_timeout,
+        env,
+    })
+}
+
+fn limit(value: &Value, context: &str) -> Result<u64, VMError> {
+    value
+        .as_integer()
+        .and_then(|limit| u64::try_from(limit).ok())
+        .filter(|&limit| limit > 0)
+        .ok_or_else(|| {
+            invalid(&format!(
+                "{context} must be a positive number of instructions"
+            ))
+        })
+}
+
+fn timeout(value: &Value, context: &str) -> Result<Duration, VMError> {
+    let seconds = match value {
+        Value::Integer(seconds) => i32::try_from(*seconds).ok().map(f64::from),
+        Value::Float(seconds) => Some(*seconds),
+        _ => None,
+    };
+    seconds
+        .filter(|&seconds| seconds > 0.0)
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| invalid(&format!("{context} must be a positive number of seconds")))
+}
+
+fn read(path: &Path) -> Result<String, VMError> {
+    fs::read_to_string(path).map_err(|e| {
+        VMError::ReadFile(IoError::caused_by(
+            format!("Could not read {}", path.display()),
+            e,
+        ))
+    })
+}
+
+fn invalid(message: &str) -> VMError {
+    VMError::InvalidArgument(format!("Invalid manifest: {message}"))
+}
+
+/// How a program of the batch ended
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    /// It halted, printing the expected output if there is one
+    Pass,
+    /// It halted but printed something else, described by the message
+    Fail(String),
+    /// It did not halt, or could not be loaded
+    Error(VMError),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub name: String,
+    pub verdict: Verdict,
+    pub instructions: u64,
+    /// Host time the program ran for
+    pub time: Duration,
+    /// Everything the program printed
+    pub output: String,
+}
+
+impl Job {
+    pub fn run(&self) -> Outcome {
+        let console = SharedConsole::new();
+        console.push_input(self.input.iter().copied());
+        let mut instructions = 0;
+        let mut time = Duration::ZERO;
+        let result = self.execute(&console, &mut instructions, &mut time);
+        let output = console.take_output();
+        let verdict = match result {
+            Err(error) => Verdict::Error(error),
+            Ok(()) => match &self.expected {
+                Some(expected) => compare(expected, &output),
+                None => Verdict::Pass,
+            },
+        };
+        Outcome {
+            name: self.name.clone(),
+            verdict,
+            instructions,
+            time,
+            output,
+        }
+    }
+
+    /// Runs the program on a fresh VM, which reaches no files, sockets or
+    /// host variables besides `env`
+    fn execute(
+        &self,
+        console: &SharedConsole,
+        instructions: &mut u64,
+        time: &mut Duration,
+    ) -> Result<(), VMError> {
+        let mut builder = VM::builder()
+            .console(Box::new(console.clone()))
+            .instruction_limit(self.limit);
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        for (name, value) in &self.env {
+            builder = builder.env_var(name, value);
+        }
+        let mut vm = builder.build()?;
+        for image in &self.images {
+            vm.read_image(&image.to_string_lossy())?;
+        }
+        let result = vm.run();
+        let metrics = vm.metrics();
+        *instructions = metrics.instructions;
+        *time = metrics.host_time;
+        result
+    }
+}
+
+/// Describes the first line where `actual` differs from `expected`
+fn compare(expected: &str, actual: &str) -> Verdict {
+    if expected == actual {
+        return Verdict::Pass;
+    }
+    let mut expected_lines = expected.split_inclusive('\n');
+    let mut actual_lines = actual.split_inclusive('\n');
+    let mut line = 1_usize;
+    loop {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(wanted), Some(got)) if wanted == got => line = line.saturating_add(1),
+            (wanted, got) => {
+                return Verdict::Fail(format!(
+                    "line {line}: expected {}, got {}",
+                    describe(wanted),
+                    describe(got)
+                ))
+            }
+        }
+    }
+}
+
+fn describe(line: Option<&str>) -> String {
+    match line {
+        Some(line) => format!("{line:?}"),
+        None => String::from("the end of the output"),
+    }
+}
+
+/// Outcomes of every program of a batch, displayed as a table
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub outcomes: Vec<Outcome>,
+}
+
+impl Report {
+    pub fn passed(&self) -> usize {
+        self.outcomes
+            .iter()
+            .filter(|outcome| outcome.verdict == Verdict::Pass)
+            .count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.outcomes.len().saturating_sub(self.passed())
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .outcomes
+            .iter()
+            .map(|outcome| outcome.name.chars().count())
+            .chain([7])
+            .max()
+            .unwrap_or_default();
+        writeln!(f, "{:width$}  RESULT  INSTRUCTIONS  DETAIL", "PROGRAM")?;
+        for outcome in &self.outcomes {
+            let (result, detail) = match &outcome.verdict {
+                Verdict::Pass => ("pass", String::new()),
+                Verdict::Fail(reason) => ("fail", reason.clone()),
+                Verdict::Error(error) => ("error", error.to_string()),
+            };
+            let row = format!(
+                "{:width$}  {result:6}  {:>12}  {detail}",
+                outcome.name, outcome.instructions
+            );
+            writeln!(f, "{}", row.trim_end())?;
+        }
+        write!(
+            f,
+            "{} passed, {} failed of {} programs",
+            self.passed(),
+            self.failed(),
+            self.outcomes.len()
+        )
+    }
+}
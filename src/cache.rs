@@ -0,0 +1,281 @@
+//! Simulated caches between the CPU and memory, for studying locality with
+//! real programs. The caches never change what the program computes: they
+//! follow the addresses of instruction fetches and data accesses and count
+//! hits and misses, in total and per instruction causing them.
+//!
+//! Caches are set associative with LRU replacement and allocate lines on
+//! writes as on reads. Device registers are never cached.
+
+use alloc::{collections::BTreeMap, format, string::String, vec, vec::Vec};
+use core::{fmt::Write, str::FromStr};
+
+use crate::{errors::VMError, memory::MMIO_START, observer::Observer, stats::percent, vm::VM};
+
+/// Sites listed in reports, those with the most misses first
+const REPORTED_SITES: usize = 10;
+
+/// Geometry of a cache, in words
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheConfig {
+    pub size: usize,
+    /// Lines in each set
+    pub associativity: usize,
+    pub line_size: usize,
+}
+
+impl CacheConfig {
+    /// Fails unless all three are powers of two and a set fits in the cache
+    pub fn new(size: usize, associativity: usize, line_size: usize) -> Result<Self, VMError> {
+        let powers = [size, associativity, line_size]
+            .iter()
+            .all(|value| value.is_power_of_two());
+        let set_size = associativity.saturating_mul(line_size);
+        if !powers || set_size > size {
+            return Err(VMError::InvalidArgument(format!(
+                "A cache of {size} words with {associativity} ways of {line_size}-word lines \
+                 is impossible: all three must be powers of two and a set must fit"
+            )));
+        }
+        Ok(CacheConfig {
+            size,
+            associativity,
+            line_size,
+        })
+    }
+
+    pub fn sets(&self) -> usize {
+        self.size
+            .checked_div(self.associativity.saturating_mul(self.line_size))
+            .unwrap_or(1)
+    }
+}
+
+/// Parses `SIZE:WAYS:LINE`, e.g. `256:2:4`
+impl FromStr for CacheConfig {
+    type Err = VMError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let numbers: Vec<usize> = text
+            .split(':')
+            .map(|part| part.parse::<usize>().ok())
+            .collect::<Option<_>>()
+            .unwrap_or_default();
+        match numbers.as_slice() {
+            &[size, associativity, line_size] => CacheConfig::new(size, associativity, line_size),
+            _ => Err(VMError::InvalidArgument(format!(
+                "Invalid cache `{text}`, expected SIZE:WAYS:LINE in words"
+            ))),
+        }
+    }
+}
+
+/// Hits and misses of a cache or of one site
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AccessCounts {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl AccessCounts {
+    pub fn accesses(&self) -> u64 {
+        self.hits.saturating_add(self.misses)
+    }
+
+    fn count(&mut self, hit: bool) {
+        let counter = if hit {
+            &mut self.hits
+        } else {
+            &mut self.misses
+        };
+        *counter = counter.saturating_add(1);
+    }
+}
+
+/// A cache and its statistics
+#[derive(Debug, Clone)]
+pub struct Cache {
+    config: CacheConfig,
+    /// Tags of the lines of every set with when they were last used
+    sets: Vec<Vec<(usize, u64)>>,
+    clock: u64,
+    totals: AccessCounts,
+    /// Counts by address of the instruction causing the accesses
+    sites: BTreeMap<u16, AccessCounts>,
+}
+
+impl Cache {
+    pub fn new(config: CacheConfig) -> Self {
+        Cache {
+            config,
+            sets: vec![Vec::new(); config.sets()],
+            clock: 0,
+            totals: AccessCounts::default(),
+            sites: BTreeMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> CacheConfig {
+        self.config
+    }
+
+    /// Looks `address` up for the instruction at `site`, loading its line on
+    /// a miss, and returns whether it hit
+    pub fn access(&mut self, site: u16, address: u16) -> bool {
+        self.clock = self.clock.wrapping_add(1);
+        let line = usize::from(address)
+            .checked_div(self.config.line_size)
+            .unwrap_or_default();
+        let index = line.checked_rem(self.sets.len()).unwrap_or_default();
+        let tag = line.checked_div(self.sets.len()).unwrap_or_default();
+        let Some(set) = self.sets.get_mut(index) else {
+            return false;
+        };
+        let hit = match set.iter_mut().find(|(line_tag, _)| *line_tag == tag) {
+            Some(entry) => {
+                entry.1 = self.clock;
+                true
+            }
+            None => {
+                if set.len() >= self.config.associativity {
+                    // evict the least recently used line
+                    if let Some(oldest) = set
+                        .iter()
+                        .enumerate()
+                        .min_by_key(|(_, (_, used))| *used)
+                        .map(|(position, _)| position)
+                    {
+                        set.swap_remove(oldest);
+                    }
+                }
+                set.push((tag, self.clock));
+                false
+            }
+        };
+        self.totals.count(hit);
+        self.sites.entry(site).or_default().count(hit);
+        hit
+    }
+
+    pub fn totals(&self) -> AccessCounts {
+        self.totals
+    }
+
+    /// Counts of the instruction at `site`
+    pub fn site(&self, site: u16) -> AccessCounts {
+        self.sites.get(&site).copied().unwrap_or_default()
+    }
+
+    /// Every instruction that accessed the cache, by address
+    pub fn sites(&self) -> impl Iterator<Item = (u16, AccessCounts)> + '_ {
+        self.sites.iter().map(|(&site, &counts)| (site, counts))
+    }
+
+    /// Totals and the sites with the most misses, headed by `name`
+    pub fn report(&self, name: &str) -> String {
+        let CacheConfig {
+            size,
+            associativity,
+            line_size,
+        } = self.config;
+        let mut report =
+            format!("--- {name} ({size} words, {associativity}-way, {line_size}-word lines) ---\n");
+        let totals = self.totals;
+        let _ = writeln!(report, "Accesses:  {}", totals.accesses());
+        let _ = writeln!(
+            report,
+            "Hits:      {} ({})",
+            totals.hits,
+            percent(totals.hits, totals.accesses())
+        );
+        let _ = writeln!(
+            report,
+            "Misses:    {} ({})",
+            totals.misses,
+            percent(totals.misses, totals.accesses())
+        );
+        let mut sites: Vec<(u16, AccessCounts)> = self
+            .sites()
+            .filter(|(_, counts)| counts.misses > 0)
+            .collect();
+        sites.sort_by_key(|&(site, counts)| (core::cmp::Reverse(counts.misses), site));
+        if !sites.is_empty() {
+            report.push_str("Sites with the most misses:\n");
+            for (site, counts) in sites.into_iter().take(REPORTED_SITES) {
+                let _ = writeln!(
+                    report,
+                    "  x{site:04X} {:>10} hits {:>10} misses {:>6}",
+                    counts.hits,
+                    counts.misses,
+                    percent(counts.misses, counts.accesses())
+                );
+            }
+        }
+        report
+    }
+}
+
+/// Observer feeding instruction fetches and data accesses to the caches
+#[derive(Debug, Clone, Default)]
+pub struct CacheSimulator {
+    instructions: Option<Cache>,
+    data: Option<Cache>,
+    /// Instruction being executed, the site of its data accesses
+    pc: u16,
+}
+
+impl CacheSimulator {
+    /// Simulates an instruction cache, a data cache or both
+    pub fn new(instructions: Option<CacheConfig>, data: Option<CacheConfig>) -> Self {
+        CacheSimulator {
+            instructions: instructions.map(Cache::new),
+            data: data.map(Cache::new),
+            pc: 0,
+        }
+    }
+
+    pub fn instruction_cache(&self) -> Option<&Cache> {
+        self.instructions.as_ref()
+    }
+
+    pub fn data_cache(&self) -> Option<&Cache> {
+        self.data.as_ref()
+    }
+
+    /// The reports of the simulated caches
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        if let Some(cache) = &self.instructions {
+            report.push_str(&cache.report("instruction cache"));
+        }
+        if let Some(cache) = &self.data {
+            report.push_str(&cache.report("data cache"));
+        }
+        report
+    }
+
+    fn access_data(&mut self, address: u16) {
+        if address >= MMIO_START {
+            return;
+        }
+        if let Some(cache) = &mut self.data {
+            cache.access(self.pc, address);
+        }
+    }
+}
+
+impl Observer for CacheSimulator {
+    fn before_instruction(&mut self, _vm: &VM, pc: u16, _raw: u16) {
+        self.pc = pc;
+        if let Some(cache) = &mut self.instructions {
+            cache.access(pc, pc);
+        }
+    }
+
+    fn on_mem_read(&mut self, address: u16, _value: u16) {
+        self.access_data(address);
+    }
+
+    fn on_mem_write(&mut self, address: u16, _value: u16) {
+        self.access_data(address);
+    }
+}
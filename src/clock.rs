@@ -0,0 +1,136 @@
+#[cfg(feature = "std")]
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How many times per second the clock checks whether it is ahead of time
+#[cfg(feature = "std")]
+const CHECKS_PER_SECOND: u32 = 100;
+
+/// Execution speed of the VM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Speed {
+    /// Run as fast as the host allows
+    #[default]
+    Unlimited,
+    /// Run at most this many instructions per second
+    InstructionsPerSecond(u32),
+    /// Run at most this many cycles per second, as estimated by the cycle
+    /// model, see `VM::set_cycle_model`. Without a model every instruction
+    /// counts as one cycle, as for `InstructionsPerSecond`.
+    CyclesPerSecond(u32),
+}
+
+/// Where the time read by the TIME trap comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TimeSource {
+    /// The wall-clock time spent running, see `Metrics::host_time`
+    #[default]
+    Host,
+    /// Derived from the instructions executed at this many per second, so
+    /// that runs read the same times and repeat exactly. The JIT is not
+    /// used, as for throttling.
+    Virtual(u32),
+    /// Like `Virtual`, from the cycles estimated by the cycle model at this
+    /// many per second
+    VirtualCycles(u32),
+}
+
+/// Paces execution to a configured instruction or cycle rate by sleeping
+/// whenever the VM gets ahead of the wall clock. Without `std` there is no wall
+/// clock and the VM always runs unthrottled.
+#[derive(Debug, Clone)]
+pub struct Clock {
+    speed: Speed,
+    #[cfg(feature = "std")]
+    window_start: Option<Instant>,
+    /// Instructions or cycles executed in the current window
+    #[cfg(feature = "std")]
+    executed: u32,
+    /// Value of `executed` when the clock last checked the time
+    #[cfg(feature = "std")]
+    checked: u32,
+    /// Cycle count of the VM at the previous tick
+    #[cfg(feature = "std")]
+    cycles: u64,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new(Speed::default())
+    }
+}
+
+impl Clock {
+    pub fn new(speed: Speed) -> Self {
+        Clock {
+            speed,
+            #[cfg(feature = "std")]
+            window_start: None,
+            #[cfg(feature = "std")]
+            executed: 0,
+            #[cfg(feature = "std")]
+            checked: 0,
+            #[cfg(feature = "std")]
+            cycles: 0,
+        }
+    }
+
+    pub fn speed(&self) -> Speed {
+        self.speed
+    }
+
+    /// Accounts for one executed instruction, given the cycles the VM
+    /// estimated so far or `None` without a cycle model, sleeping if needed
+    #[inline]
+    pub fn tick(&mut self, cycles: Option<u64>) {
+        #[cfg(feature = "std")]
+        match (self.speed, cycles) {
+            (Speed::Unlimited, _) => {}
+            (Speed::InstructionsPerSecond(rate) | Speed::CyclesPerSecond(rate), None)
+            | (Speed::InstructionsPerSecond(rate), Some(_)) => self.throttle(rate, 1),
+            (Speed::CyclesPerSecond(rate), Some(cycles)) => {
+                let spent = cycles.saturating_sub(self.cycles);
+                self.cycles = cycles;
+                self.throttle(rate, u32::try_from(spent).unwrap_or(u32::MAX));
+            }
+        }
+        #[cfg(not(feature = "std"))]
+        let _ = cycles;
+    }
+
+    /// Counts `units` more instructions or cycles against `rate` per second
+    #[cfg(feature = "std")]
+    fn throttle(&mut self, rate: u32, units: u32) {
+        let rate = rate.max(1);
+        let start = *self.window_start.get_or_insert_with(Instant::now);
+        self.executed = self.executed.saturating_add(units);
+        let batch = (rate / CHECKS_PER_SECOND).max(1);
+        if self.executed.saturating_sub(self.checked) < batch {
+            return;
+        }
+        self.checked = self.executed;
+        let target = Duration::from_secs(1)
+            .checked_mul(self.executed)
+            .and_then(|total| total.checked_div(rate))
+            .unwrap_or_default();
+        let elapsed = start.elapsed();
+        match target.checked_sub(elapsed) {
+            Some(ahead) => thread::sleep(ahead),
+            None => {
+                // after blocking on input do not burst to catch up
+                self.executed = 0;
+                self.checked = 0;
+                self.window_start = Some(Instant::now());
+                return;
+            }
+        }
+        if self.executed >= rate {
+            // start a new window every second so the counter never overflows
+            self.executed = self.executed.saturating_sub(rate);
+            self.checked = self.executed;
+            self.window_start = start.checked_add(Duration::from_secs(1));
+        }
+    }
+}
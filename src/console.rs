@@ -0,0 +1,456 @@
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    format,
+    rc::Rc,
+    string::String,
+    vec::Vec,
+};
+use core::{cell::RefCell, ops::Bound, time::Duration};
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+use std::io::{stdout, BufWriter, Stdout, Write};
+
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+use crate::terminal;
+use crate::{
+    ansi::Command,
+    errors::{IoError, VMError},
+};
+
+/// Character I/O used by the traps and the keyboard registers
+pub trait Console {
+    /// Blocks until a key is pressed and returns it
+    fn read_key(&mut self) -> Result<u8, VMError>;
+
+    /// Returns the pending key if there is one, without blocking
+    fn poll_key(&mut self) -> Result<Option<u8>, VMError>;
+
+    /// Carries out an escape sequence printed by the program when `ansi`
+    /// is set in the `ConsoleConfig`. By default it is written as text, for
+    /// consoles that are VT100 terminals.
+    fn command(&mut self, command: &Command) -> Result<(), VMError> {
+        format!("{command}")
+            .chars()
+            .try_for_each(|character| self.write_char(character))
+    }
+
+    /// Waits at most `timeout` for a key, returning `None` if none was
+    /// pressed. Consoles that cannot stop waiting block like `read_key`.
+    fn read_key_timeout(&mut self, timeout: Duration) -> Result<Option<u8>, VMError> {
+        let _ = timeout;
+        self.read_key().map(Some)
+    }
+
+    /// Writes a character, which may stay buffered until `flush`
+    fn write_char(&mut self, character: char) -> Result<(), VMError>;
+
+    /// Makes every written character visible
+    fn flush(&mut self) -> Result<(), VMError>;
+}
+
+/// Console without a terminal: output is discarded and there is never any
+/// input, so programs waiting for a key fail instead of blocking
+pub struct NullConsole;
+
+impl Console for NullConsole {
+    fn read_key(&mut self) -> Result<u8, VMError> {
+        Err(VMError::StandardIO(IoError::new(
+            "No input is available without a terminal",
+        )))
+    }
+
+    fn poll_key(&mut self) -> Result<Option<u8>, VMError> {
+        Ok(None)
+    }
+
+    fn write_char(&mut self, _character: char) -> Result<(), VMError> {
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), VMError> {
+        Ok(())
+    }
+}
+
+/// How the VM translates between the program and its console
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsoleConfig {
+    /// Translates the keys read from the console
+    pub key_map: KeyMap,
+    /// Whether GETC prints the key it read. The specification does not,
+    /// but some courses expect it.
+    pub getc_echo: bool,
+    /// Whether IN prints the key it read after its prompt, followed by a
+    /// newline, as the specification does
+    pub in_echo: bool,
+    /// Line ending the program reads and writes
+    pub newline: Newline,
+    /// How the bytes the program prints become characters
+    pub encoding: Encoding,
+    /// Whether the escape sequences the program prints are passed to
+    /// `Console::command` whole, see `ansi`
+    pub ansi: bool,
+    /// Whether KBDR holds the scancodes of keys pressed and released
+    /// instead of characters, see `scancode`. GETC and IN still read
+    /// characters.
+    pub scancodes: bool,
+}
+
+/// How OUT, PUTS, PUTSP and the echo of keys turn bytes into characters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// Bytes up to x7F only. Printing a larger one fails with
+    /// `VMError::InvalidCharacter`.
+    #[default]
+    Ascii,
+    /// Every byte is the Unicode character of the same value
+    Latin1,
+    /// ASCII below x80, and the characters of the table from x80, like
+    /// `CP437`
+    CodePage(&'static [char; 128]),
+    /// Bytes are UTF-8 sequences, printed once complete. Invalid sequences
+    /// are printed as U+FFFD.
+    Utf8,
+}
+
+/// Characters x80 to xFF of the code page of the IBM PC, with its box
+/// drawing characters
+pub static CP437: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å', //
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ', //
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»', //
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐', //
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧', //
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀', //
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩', //
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{A0}',
+];
+
+/// Line ending of a program. Others than `Lf` are translated: the Enter
+/// key reaches the program as that line ending, and its line endings are
+/// printed as host newlines.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Newline {
+    /// `\n`, like the host, so nothing is translated
+    #[default]
+    Lf,
+    /// `\r`, as programs written for some other simulators expect
+    Cr,
+    /// `\r\n`. Carriage returns printed by the program are dropped.
+    CrLf,
+}
+
+impl Default for ConsoleConfig {
+    fn default() -> Self {
+        ConsoleConfig {
+            key_map: KeyMap::new(),
+            getc_echo: false,
+            in_echo: true,
+            newline: Newline::Lf,
+            encoding: Encoding::Ascii,
+            ansi: false,
+            scancodes: false,
+        }
+    }
+}
+
+/// What a key, or the escape sequence the terminal sends for it, becomes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyBinding {
+    /// Delivered to the program as these bytes instead, none to ignore it
+    Keys(Vec<u8>),
+    /// Not delivered: stops the program so a debugger can take over, see
+    /// `StopReason::Break`
+    Break,
+}
+
+/// Translates the keys typed on the host before the program sees them, e.g.
+/// the escape sequences of arrow keys into single codes. Sequences are
+/// matched as their bytes arrive, so a binding for a prefix of another one
+/// is only used when the longer one does not follow.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyMap {
+    bindings: BTreeMap<Vec<u8>, KeyBinding>,
+}
+
+impl KeyMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes `sequence` act as `binding`, replacing any previous binding
+    pub fn bind(&mut self, sequence: impl Into<Vec<u8>>, binding: KeyBinding) {
+        self.bindings.insert(sequence.into(), binding);
+    }
+
+    pub fn get(&self, sequence: &[u8]) -> Option<&KeyBinding> {
+        self.bindings.get(sequence)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bindings.is_empty()
+    }
+
+    /// Whether a binding for a longer sequence starts with `prefix`
+    pub fn extends(&self, prefix: &[u8]) -> bool {
+        self.bindings
+            .range::<[u8], _>((Bound::Excluded(prefix), Bound::Unbounded))
+            .next()
+            .is_some_and(|(sequence, _)| sequence.starts_with(prefix))
+    }
+
+    /// Adds a binding written `KEY=VALUE`. `KEY` is a single character or
+    /// a key named in `key_sequence`, and `VALUE` is `break`, a code like
+    /// `x80` or the text to deliver instead, nothing to ignore the key.
+    pub fn parse_binding(&mut self, spec: &str) -> Result<(), VMError> {
+        let invalid = || VMError::InvalidArgument(format!("Invalid key binding {spec}"));
+        // the key may be `=` itself, so the separator follows its first character
+        let first = spec.chars().next().ok_or_else(invalid)?.len_utf8();
+        let separator = spec
+            .get(first..)
+            .and_then(|rest| rest.find('='))
+            .ok_or_else(invalid)?;
+        let (key, value) = spec
+            .split_at_checked(first.saturating_add(separator))
+            .ok_or_else(invalid)?;
+        let value = value.get(1..).unwrap_or_default();
+        let sequence = match key_sequence(key) {
+            Some(sequence) => Vec::from(sequence),
+            None if key.chars().count() == 1 => Vec::from(key.as_bytes()),
+            None => return Err(VMError::InvalidArgument(format!("Unknown key {key}"))),
+        };
+        let binding = if value.eq_ignore_ascii_case("break") {
+            KeyBinding::Break
+        } else if let Some(code) = value
+            .strip_prefix('x')
+            .filter(|hex| !hex.is_empty())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            KeyBinding::Keys(Vec::from([code]))
+        } else {
+            KeyBinding::Keys(Vec::from(value.as_bytes()))
+        };
+        self.bind(sequence, binding);
+        Ok(())
+    }
+}
+
+/// Escape sequence an xterm compatible terminal sends for the key `name`:
+/// `up`, `down`, `right`, `left`, `home`, `end`, `esc` or `f1` to `f12`,
+/// in any case
+pub fn key_sequence(name: &str) -> Option<&'static [u8]> {
+    let sequence: &[u8] = match name.to_ascii_lowercase().as_str() {
+        "up" => b"\x1b[A",
+        "down" => b"\x1b[B",
+        "right" => b"\x1b[C",
+        "left" => b"\x1b[D",
+        "home" => b"\x1b[H",
+        "end" => b"\x1b[F",
+        "esc" => b"\x1b",
+        "f1" => b"\x1bOP",
+        "f2" => b"\x1bOQ",
+        "f3" => b"\x1bOR",
+        "f4" => b"\x1bOS",
+        "f5" => b"\x1b[15~",
+        "f6" => b"\x1b[17~",
+        "f7" => b"\x1b[18~",
+        "f8" => b"\x1b[19~",
+        "f9" => b"\x1b[20~",
+        "f10" => b"\x1b[21~",
+        "f11" => b"\x1b[23~",
+        "f12" => b"\x1b[24~",
+        _ => return None,
+    };
+    Some(sequence)
+}
+
+#[derive(Default)]
+struct ConsoleBuffers {
+    output: String,
+    input: VecDeque<u8>,
+}
+
+/// Console over in-memory buffers shared between its clones, for front ends
+/// that display the output themselves and feed keys as they arrive. Reading a
+/// key fails when none was fed, since nothing else could provide it.
+#[derive(Clone, Default)]
+pub struct SharedConsole(Rc<RefCell<ConsoleBuffers>>);
+
+impl SharedConsole {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues keys for the program to read
+    pub fn push_input(&self, keys: impl IntoIterator<Item = u8>) {
+        self.0.borrow_mut().input.extend(keys);
+    }
+
+    pub fn has_input(&self) -> bool {
+        !self.0.borrow().input.is_empty()
+    }
+
+    /// Returns and clears everything written since the last call
+    pub fn take_output(&self) -> String {
+        core::mem::take(&mut self.0.borrow_mut().output)
+    }
+}
+
+impl Console for SharedConsole {
+    fn read_key(&mut self) -> Result<u8, VMError> {
+        self.0
+            .borrow_mut()
+            .input
+            .pop_front()
+            .ok_or_else(|| VMError::StandardIO(IoError::new("No input is available")))
+    }
+
+    fn poll_key(&mut self) -> Result<Option<u8>, VMError> {
+        Ok(self.0.borrow_mut().input.pop_front())
+    }
+
+    fn write_char(&mut self, character: char) -> Result<(), VMError> {
+        self.0.borrow_mut().output.push(character);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), VMError> {
+        Ok(())
+    }
+}
+
+/// Console over the process terminal. Output is buffered and flushed on
+/// newlines and whenever the program waits for input, instead of issuing a
+/// write per character.
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub struct TerminalConsole<W: Write = Stdout> {
+    output: BufWriter<W>,
+}
+
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+impl Default for TerminalConsole {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+impl TerminalConsole {
+    pub fn new() -> Self {
+        Self::with_writer(stdout())
+    }
+}
+
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+impl<W: Write> TerminalConsole<W> {
+    /// Creates a console that reads the terminal and writes to `writer`
+    pub fn with_writer(writer: W) -> Self {
+        TerminalConsole {
+            output: BufWriter::new(writer),
+        }
+    }
+}
+
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+impl<W: Write> Console for TerminalConsole<W> {
+    fn read_key(&mut self) -> Result<u8, VMError> {
+        self.flush()?;
+        terminal::read_key()
+    }
+
+    fn poll_key(&mut self) -> Result<Option<u8>, VMError> {
+        self.flush()?;
+        Ok(terminal::poll_key())
+    }
+
+    fn read_key_timeout(&mut self, timeout: Duration) -> Result<Option<u8>, VMError> {
+        self.flush()?;
+        terminal::read_key_timeout(timeout)
+    }
+
+    /// Windows consoles interpret the sequences once told to
+    #[cfg(windows)]
+    fn command(&mut self, command: &Command) -> Result<(), VMError> {
+        terminal::enable_virtual_terminal();
+        write!(self.output, "{command}")
+            .map_err(|e| VMError::StandardIO(IoError::caused_by("Could not write output", e)))
+    }
+
+    fn write_char(&mut self, character: char) -> Result<(), VMError> {
+        let mut encoded = [0; 4];
+        self.output
+            .write_all(character.encode_utf8(&mut encoded).as_bytes())
+            .map_err(|e| VMError::StandardIO(IoError::caused_by("Could not write output", e)))?;
+        if character == '\n' {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), VMError> {
+        self.output
+            .flush()
+            .map_err(|e| VMError::StandardIO(IoError::caused_by("Could not flush output", e)))
+    }
+}
+
+/// Console that only writes to `W`, for channels a program prints to but
+/// never reads, such as a debug log. Output is flushed on newlines.
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub struct OutputConsole<W: Write> {
+    output: BufWriter<W>,
+}
+
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+impl<W: Write> OutputConsole<W> {
+    pub fn new(writer: W) -> Self {
+        OutputConsole {
+            output: BufWriter::new(writer),
+        }
+    }
+}
+
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+impl OutputConsole<Box<dyn Write>> {
+    /// Writes to `stdout`, `stderr` or the file created at the path
+    /// `output`
+    pub fn open(output: &str) -> Result<Self, VMError> {
+        let writer: Box<dyn Write> = match output {
+            "stdout" => Box::new(stdout()),
+            "stderr" => Box::new(std::io::stderr()),
+            path => Box::new(std::fs::File::create(path).map_err(|e| {
+                VMError::OpenFile(IoError::caused_by(format!("Could not create {path}"), e))
+            })?),
+        };
+        Ok(Self::new(writer))
+    }
+}
+
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+impl<W: Write> Console for OutputConsole<W> {
+    fn read_key(&mut self) -> Result<u8, VMError> {
+        Err(VMError::StandardIO(IoError::new(
+            "No input is available on an output console",
+        )))
+    }
+
+    fn poll_key(&mut self) -> Result<Option<u8>, VMError> {
+        Ok(None)
+    }
+
+    fn write_char(&mut self, character: char) -> Result<(), VMError> {
+        let mut encoded = [0; 4];
+        self.output
+            .write_all(character.encode_utf8(&mut encoded).as_bytes())
+            .map_err(|e| VMError::StandardIO(IoError::caused_by("Could not write output", e)))?;
+        if character == '\n' {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), VMError> {
+        self.output
+            .flush()
+            .map_err(|e| VMError::StandardIO(IoError::caused_by("Could not flush output", e)))
+    }
+}
@@ -0,0 +1,120 @@
+//! Cost models estimating how many cycles a program takes, for exercises
+//! such as "sort the array in fewer than 20000 cycles". A model gives every
+//! opcode a cost, fetch included, and adds a cost per data read and write
+//! made by the instruction or its trap:
+//!
+//! ```text
+//! lc3,read=2,write=2   the states of the LC-3 with slower memory
+//! ldi=12,sti=12        the uniform model with costly indirection
+//! ```
+//!
+//! `VM::set_cycle_model` makes the VM count the estimate in
+//! `Metrics::cycles`. `Speed::CyclesPerSecond` and
+//! `TimeSource::VirtualCycles` pace execution and the TIME trap by it.
+
+use alloc::format;
+use core::str::FromStr;
+
+use crate::errors::VMError;
+
+/// Names of the opcodes in model specifications, by opcode
+const OPCODE_NAMES: [&str; 16] = [
+    "br", "add", "ld", "st", "jsr", "and", "ldr", "str", "rti", "not", "ldi", "sti", "jmp", "res",
+    "lea", "trap",
+];
+
+/// Cycles of every opcode and memory access
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CycleModel {
+    /// Cost of each opcode, indexed by its number
+    pub opcodes: [u32; 16],
+    /// Added for every data read, instruction fetches excluded
+    pub read: u32,
+    /// Added for every write
+    pub write: u32,
+}
+
+impl CycleModel {
+    /// A cycle per instruction, so the estimate is the instruction count
+    pub const UNIFORM: CycleModel = CycleModel {
+        opcodes: [1; 16],
+        read: 0,
+        write: 0,
+    };
+
+    /// The states the control unit of Patt and Patel's appendix C goes
+    /// through for each opcode, with memory always ready. Branches count
+    /// as not taken and TRAP and RTI without their privilege checks.
+    pub const LC3: CycleModel = CycleModel {
+        //        BR ADD LD ST JSR AND LDR STR RTI NOT LDI STI JMP RES LEA TRAP
+        opcodes: [5, 5, 7, 7, 6, 5, 7, 7, 12, 5, 9, 9, 5, 5, 5, 7],
+        read: 0,
+        write: 0,
+    };
+
+    /// Cost of the instruction `raw` before its memory accesses
+    #[inline]
+    pub fn instruction(&self, raw: u16) -> u32 {
+        self.opcodes
+            .get(usize::from(raw >> 12))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+impl Default for CycleModel {
+    fn default() -> Self {
+        CycleModel::UNIFORM
+    }
+}
+
+/// Parses comma or line separated settings applied in order to the uniform
+/// model: `uniform` or `lc3` for a whole model, `OPCODE=N` with the opcode
+/// in lowercase, `read=N` and `write=N`. `#` starts a comment, so models
+/// can be kept in files.
+impl FromStr for CycleModel {
+    type Err = VMError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut model = CycleModel::UNIFORM;
+        let settings = text
+            .lines()
+            .map(|line| line.split_once('#').map_or(line, |(setting, _)| setting))
+            .flat_map(|line| line.split(','))
+            .map(str::trim)
+            .filter(|setting| !setting.is_empty());
+        for setting in settings {
+            let Some((name, cost)) = setting.split_once('=') else {
+                model = match setting {
+                    "uniform" => CycleModel::UNIFORM,
+                    "lc3" => CycleModel::LC3,
+                    _ => {
+                        return Err(VMError::InvalidArgument(format!(
+                            "Unknown cycle model `{setting}`, expected uniform or lc3"
+                        )))
+                    }
+                };
+                continue;
+            };
+            let (name, cost) = (name.trim(), cost.trim());
+            let cost = cost.parse::<u32>().map_err(|_| {
+                VMError::InvalidArgument(format!("Invalid cost `{cost}` for {name}"))
+            })?;
+            let slot = match name {
+                "read" => &mut model.read,
+                "write" => &mut model.write,
+                _ => OPCODE_NAMES
+                    .iter()
+                    .position(|&opcode| opcode == name)
+                    .and_then(|index| model.opcodes.get_mut(index))
+                    .ok_or_else(|| {
+                        VMError::InvalidArgument(format!(
+                            "Unknown cost `{name}`, expected an opcode, read or write"
+                        ))
+                    })?,
+            };
+            *slot = cost;
+        }
+        Ok(model)
+    }
+}
@@ -0,0 +1,852 @@
+//! Debug Adapter Protocol server over stdin/stdout, so editors like VS Code
+//! can launch and debug LC-3 programs.
+//!
+//! Program output is sent as `output` events. Keyboard input is typed in the
+//! debug console prefixed with `>`; when the program waits for a key that has
+//! not been typed yet, execution pauses. When a `.sym` symbol table is
+//! available, frames are named after labels and breakpoints can be set by
+//! label name. When the matching `.asm` source assembles, breakpoints can
+//! be set on any of its lines, frames point at the line of the PC, steps
+//! run whole lines such as macro uses, and addresses inside the program are
+//! also shown relative to their label. Watch expressions like `mem[R6+1]`
+//! can be evaluated, and data breakpoints stop when their value changes.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::Path,
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    assembler::assemble_with_diagnostics,
+    console::SharedConsole,
+    errors::{IoError, VMError},
+    memory::MMIO_START,
+    register::Register,
+    source_map::SourceMap,
+    symbols::SymbolTable,
+    vm::{DeviceAccess, StopReason, VM},
+    watch::{Change, Expression, Watchpoints},
+};
+
+const THREAD_ID: u64 = 1;
+const REGISTERS_REFERENCE: u64 = 1;
+/// Instructions executed between checks for new requests while running
+const RUN_BATCH: u32 = 10_000;
+/// Exception breakpoint filters stopping before every TRAP and before
+/// interrupts and exceptions
+const TRAP_FILTER: &str = "traps";
+const INTERRUPT_FILTER: &str = "interrupts";
+
+/// Serves a debugging session on stdin/stdout until the client disconnects
+pub fn serve() -> Result<(), VMError> {
+    serve_streams(io::stdin(), io::stdout())
+}
+
+/// Like `serve`, reading the requests from `input` and writing the responses
+/// and events to `output`, e.g. a socket or pipes to an editor
+pub fn serve_streams(
+    input: impl Read + Send + 'static,
+    output: impl Write + 'static,
+) -> Result<(), VMError> {
+    let requests = spawn_reader(input);
+    DapServer::new(Box::new(output)).run(&requests)
+}
+
+/// Reads requests on a separate thread, so a running program can be paused
+fn spawn_reader(input: impl Read + Send + 'static) -> Receiver<Value> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut input = BufReader::new(input);
+        while let Some(message) = read_message(&mut input) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Reads a `Content-Length` framed JSON message
+fn read_message(input: &mut impl BufRead) -> Option<Value> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok();
+        }
+    }
+    let mut body = vec![0; length?];
+    input.read_exact(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Stopped,
+    /// Running until a breakpoint, or until the PC reaches `until` when
+    /// stepping out of a subroutine
+    Running {
+        until: Option<u16>,
+    },
+    Terminated,
+}
+
+/// The `.asm` file shown in frames, with the lines its words came from, or
+/// only those of its labels if it does not assemble
+struct SourceFile {
+    path: String,
+    map: SourceMap,
+}
+
+struct DapServer {
+    output: Box<dyn Write>,
+    vm: VM,
+    console: SharedConsole,
+    symbols: SymbolTable,
+    source: Option<SourceFile>,
+    state: State,
+    stop_on_entry: bool,
+    line_breakpoints: BTreeSet<u16>,
+    function_breakpoints: BTreeSet<u16>,
+    watchpoints: Watchpoints,
+    /// Data breakpoint hit by the last instruction executed
+    changed: Option<Change>,
+    sequence: u64,
+}
+
+impl DapServer {
+    fn new(output: Box<dyn Write>) -> Self {
+        DapServer {
+            output,
+            vm: VM::new(),
+            console: SharedConsole::new(),
+            symbols: SymbolTable::default(),
+            source: None,
+            state: State::Stopped,
+            stop_on_entry: false,
+            line_breakpoints: BTreeSet::new(),
+            function_breakpoints: BTreeSet::new(),
+            watchpoints: Watchpoints::new(),
+            changed: None,
+            sequence: 0,
+        }
+    }
+
+    fn run(&mut self, requests: &Receiver<Value>) -> Result<(), VMError> {
+        loop {
+            let request = if matches!(self.state, State::Running { .. }) {
+                match requests.try_recv() {
+                    Ok(request) => Some(request),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            } else {
+                match requests.recv() {
+                    Ok(request) => Some(request),
+                    Err(_) => return Ok(()),
+                }
+            };
+            match request {
+                Some(request) => {
+                    if !self.handle(&request)? {
+                        return Ok(());
+                    }
+                }
+                None => self.run_batch()?,
+            }
+        }
+    }
+
+    /// Answers a request, returning false once the client disconnects
+    fn handle(&mut self, request: &Value) -> Result<bool, VMError> {
+        let command = request["command"].as_str().unwrap_or_default();
+        let arguments = &request["arguments"];
+        if command == "disconnect" {
+            self.respond(request, Ok(Value::Null))?;
+            return Ok(false);
+        }
+        let result = self.dispatch(command, arguments);
+        let succeeded = result.is_ok();
+        self.respond(request, result)?;
+        match command {
+            "launch" => self.send_event("initialized", Value::Null)?,
+            "configurationDone" if self.stop_on_entry => self.stop("entry", None)?,
+            "configurationDone" => self.state = State::Running { until: None },
+            "next" | "stepIn" => self.step()?,
+            "stepBack" if succeeded => self.stop("step", None)?,
+            "pause" if matches!(self.state, State::Running { .. }) => self.stop("pause", None)?,
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn dispatch(&mut self, command: &str, arguments: &Value) -> Result<Value, VMError> {
+        match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsFunctionBreakpoints": true,
+                "supportsReadMemoryRequest": true,
+                "supportsDataBreakpoints": true,
+                "supportsStepBack": true,
+                "exceptionBreakpointFilters": [
+                    { "filter": TRAP_FILTER, "label": "TRAP instructions" },
+                    { "filter": INTERRUPT_FILTER, "label": "Interrupts and exceptions" },
+                ],
+            })),
+            "launch" => self.launch(arguments),
+            "setBreakpoints" => Ok(self.set_breakpoints(arguments)),
+            "setFunctionBreakpoints" => Ok(self.set_function_breakpoints(arguments)),
+            "dataBreakpointInfo" => Ok(self.data_breakpoint_info(arguments)),
+            "setDataBreakpoints" => Ok(self.set_data_breakpoints(arguments)),
+            "setExceptionBreakpoints" => Ok(self.set_exception_breakpoints(arguments)),
+            "configurationDone" | "next" | "stepIn" | "pause" => Ok(Value::Null),
+            "stepBack" => self.vm.undo().map(|()| Value::Null),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "LC-3" }] })),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(json!({
+                "scopes": [{
+                    "name": "Registers",
+                    "variablesReference": REGISTERS_REFERENCE,
+                    "expensive": false,
+                }]
+            })),
+            "variables" => Ok(self.variables()),
+            "continue" => {
+                self.state = State::Running { until: None };
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "stepOut" => {
+                self.state = State::Running {
+                    until: Some(self.vm.register(Register::R7)),
+                };
+                Ok(Value::Null)
+            }
+            "readMemory" => self.read_memory(arguments),
+            "evaluate" => self.evaluate(arguments),
+            _ => Err(VMError::Debugger(format!("Unsupported request {command}"))),
+        }
+    }
+
+    fn launch(&mut self, arguments: &Value) -> Result<Value, VMError> {
+        let program = arguments["program"].as_str().ok_or_else(|| {
+            VMError::Debugger(String::from("launch requires the image path in `program`"))
+        })?;
+        self.vm = VM::new();
+        self.console = SharedConsole::new();
+        self.vm.set_console(Box::new(self.console.clone()));
+        self.vm.set_undo(true);
+        self.vm.read_image(program)?;
+        self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+
+        let symbols = companion(arguments, "symbols", program, "sym");
+        self.symbols = match &symbols {
+            Some(path) => SymbolTable::from_file(path)?,
+            None => SymbolTable::default(),
+        };
+        self.source = match companion(arguments, "source", program, "asm") {
+            Some(path) => {
+                let text = fs::read_to_string(&path).map_err(|e| {
+                    VMError::ReadFile(IoError::caused_by(format!("Could not read {path}"), e))
+                })?;
+                let map = match assemble_with_diagnostics(&text, None) {
+                    Ok(assembly) => {
+                        if symbols.is_none() {
+                            self.symbols = assembly.symbols.clone();
+                        }
+                        SourceMap::new(&assembly)
+                    }
+                    Err(_) => label_lines(&text, &self.symbols),
+                };
+                Some(SourceFile { path, map })
+            }
+            None => None,
+        };
+        self.state = State::Stopped;
+        Ok(Value::Null)
+    }
+
+    /// Maps the requested lines to the first instruction at or after them,
+    /// replacing the previous line breakpoints
+    fn set_breakpoints(&mut self, arguments: &Value) -> Value {
+        for address in std::mem::take(&mut self.line_breakpoints) {
+            if !self.function_breakpoints.contains(&address) {
+                self.vm.remove_breakpoint(address);
+            }
+        }
+        let lines = arguments["breakpoints"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        let breakpoints: Vec<Value> = lines
+            .iter()
+            .map(|breakpoint| {
+                let line = breakpoint["line"].as_u64().unwrap_or_default();
+                let found = self.source.as_ref().and_then(|source| {
+                    source
+                        .map
+                        .address_of_line(usize::try_from(line).unwrap_or(usize::MAX))
+                });
+                match found {
+                    Some((line, address)) => {
+                        self.line_breakpoints.insert(address);
+                        self.vm.add_breakpoint(address);
+                        json!({ "verified": true, "line": line })
+                    }
+                    None => json!({
+                        "verified": false,
+                        "line": line,
+                        "message": "No instruction at or after this line",
+                    }),
+                }
+            })
+            .collect();
+        json!({ "breakpoints": breakpoints })
+    }
+
+    /// Sets breakpoints on labels or hexadecimal addresses like `x3000`
+    fn set_function_breakpoints(&mut self, arguments: &Value) -> Value {
+        for address in std::mem::take(&mut self.function_breakpoints) {
+            if !self.line_breakpoints.contains(&address) {
+                self.vm.remove_breakpoint(address);
+            }
+        }
+        let names = arguments["breakpoints"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        let breakpoints: Vec<Value> = names
+            .iter()
+            .map(|breakpoint| {
+                let name = breakpoint["name"].as_str().unwrap_or_default();
+                match self.resolve(name) {
+                    Some(address) => {
+                        self.function_breakpoints.insert(address);
+                        self.vm.add_breakpoint(address);
+                        json!({ "verified": true })
+                    }
+                    None => json!({
+                        "verified": false,
+                        "message": format!("Unknown label {name}"),
+                    }),
+                }
+            })
+            .collect();
+        json!({ "breakpoints": breakpoints })
+    }
+
+    /// Any watch expression can be a data breakpoint, including the
+    /// registers shown in the variables pane. Device registers such as
+    /// `mem[xFE00]` can also stop when read.
+    fn data_breakpoint_info(&self, arguments: &Value) -> Value {
+        let name = arguments["name"].as_str().unwrap_or_default();
+        if let Some(address) = self.device_register(name) {
+            return json!({
+                "dataId": name,
+                "description": format!("When x{address:04X} is accessed"),
+                "accessTypes": ["read", "write", "readWrite"],
+            });
+        }
+        match Expression::parse(name, &self.symbols) {
+            Ok(_) => json!({
+                "dataId": name,
+                "description": format!("When {name} changes"),
+                "accessTypes": ["write"],
+            }),
+            Err(error) => json!({ "dataId": null, "description": format!("{error:#}") }),
+        }
+    }
+
+    /// Stops before every TRAP and before interrupts and exceptions as the
+    /// selected filters ask
+    fn set_exception_breakpoints(&mut self, arguments: &Value) -> Value {
+        let filters: Vec<&str> = arguments["filters"]
+            .as_array()
+            .map(|filters| filters.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+        if filters.contains(&TRAP_FILTER) {
+            self.vm.add_trap_break(None);
+        } else {
+            self.vm.remove_trap_break(None);
+        }
+        self.vm
+            .set_break_on_interrupt(filters.contains(&INTERRUPT_FILTER));
+        json!({ "breakpoints": filters.iter().map(|_| json!({ "verified": true })).collect::<Vec<_>>() })
+    }
+
+    /// The device register `mem[...]` around a constant names, if any
+    fn device_register(&self, name: &str) -> Option<u16> {
+        match Expression::parse(name, &self.symbols) {
+            Ok(Expression::Memory(address)) => match *address {
+                Expression::Number(address) if address >= MMIO_START => Some(address),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Replaces the data breakpoints, each watching the expression in its
+    /// `dataId`, or the accesses of its `accessType` for a device register
+    fn set_data_breakpoints(&mut self, arguments: &Value) -> Value {
+        self.watchpoints.clear();
+        let devices: Vec<u16> = self
+            .vm
+            .device_breaks()
+            .map(|(address, _)| address)
+            .collect();
+        for address in devices {
+            self.vm.remove_device_break(address);
+        }
+        let requested = arguments["breakpoints"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        let breakpoints: Vec<Value> = requested
+            .iter()
+            .map(|breakpoint| {
+                let text = breakpoint["dataId"].as_str().unwrap_or_default();
+                if let Some(address) = self.device_register(text) {
+                    let access = match breakpoint["accessType"].as_str() {
+                        Some("read") => DeviceAccess::Read,
+                        Some("readWrite") => DeviceAccess::Any,
+                        _ => DeviceAccess::Write,
+                    };
+                    return match self.vm.add_device_break(address, access) {
+                        Ok(()) => json!({ "verified": true }),
+                        Err(error) => json!({ "verified": false, "message": format!("{error:#}") }),
+                    };
+                }
+                match self.watchpoints.add(text, &self.symbols, &self.vm) {
+                    Ok(()) => json!({ "verified": true }),
+                    Err(error) => json!({ "verified": false, "message": format!("{error:#}") }),
+                }
+            })
+            .collect();
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn stack_trace(&self) -> Value {
+        let pc = self.vm.pc();
+        let name = self
+            .symbols
+            .describe(pc)
+            .unwrap_or_else(|| format!("x{pc:04X}"));
+        let mut frame = json!({
+            "id": 0,
+            "name": name,
+            "line": 0,
+            "column": 0,
+            "instructionPointerReference": format!("0x{pc:04x}"),
+        });
+        if let Some(source) = &self.source {
+            if let Some(line) = source.map.line_at(pc) {
+                set(&mut frame, "line", json!(line));
+                set(&mut frame, "column", json!(1));
+                let name = Path::new(&source.path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy());
+                set(
+                    &mut frame,
+                    "source",
+                    json!({ "name": name, "path": source.path }),
+                );
+            }
+        }
+        json!({ "stackFrames": [frame], "totalFrames": 1 })
+    }
+
+    fn variables(&self) -> Value {
+        let mut variables: Vec<Value> = (0..8)
+            .filter_map(Register::new)
+            .map(|register| {
+                let value = self.vm.register(register);
+                json!({
+                    "name": register.to_string(),
+                    "value": self.describe_value(value),
+                    "variablesReference": 0,
+                    "memoryReference": format!("0x{value:04x}"),
+                })
+            })
+            .collect();
+        let pc = self.vm.pc();
+        variables.push(json!({
+            "name": "PC",
+            "value": match self.symbols.describe(pc) {
+                Some(label) => format!("x{pc:04X} ({label})"),
+                None => format!("x{pc:04X}"),
+            },
+            "variablesReference": 0,
+            "memoryReference": format!("0x{pc:04x}"),
+        }));
+        variables.push(json!({
+            "name": "COND",
+            "value": self.vm.psr().condition_letter().to_string(),
+            "variablesReference": 0,
+        }));
+        json!({ "variables": variables })
+    }
+
+    /// Reads memory as big endian words, like in object files. The memory
+    /// reference is a word address and the offset is in bytes.
+    fn read_memory(&self, arguments: &Value) -> Result<Value, VMError> {
+        let reference = arguments["memoryReference"].as_str().unwrap_or_default();
+        let start = parse_address(reference)
+            .ok_or_else(|| VMError::Debugger(format!("Invalid memory reference {reference}")))?;
+        let offset = arguments["offset"].as_i64().unwrap_or_default();
+        let count = arguments["count"].as_u64().unwrap_or_default();
+        let first = i64::from(start)
+            .saturating_mul(2)
+            .saturating_add(offset)
+            .max(0);
+        let mut bytes = Vec::new();
+        for byte in (first..).take(usize::try_from(count).unwrap_or_default()) {
+            let Some(word) = u16::try_from(byte >> 1).ok() else {
+                break;
+            };
+            let [high, low] = self.vm.peek(word).to_be_bytes();
+            bytes.push(if byte & 1 == 0 { high } else { low });
+        }
+        let unreadable = count.saturating_sub(u64::try_from(bytes.len()).unwrap_or_default());
+        Ok(json!({
+            "address": format!("0x{start:04x}"),
+            "data": base64(&bytes),
+            "unreadableBytes": unreadable,
+        }))
+    }
+
+    /// `>text` types `text` as keyboard input, anything else is evaluated
+    /// as a watch expression
+    fn evaluate(&mut self, arguments: &Value) -> Result<Value, VMError> {
+        let expression = arguments["expression"].as_str().unwrap_or_default();
+        if let Some(keys) = expression.strip_prefix('>') {
+            let keys = if keys.is_empty() { "\n" } else { keys };
+            self.console.push_input(keys.bytes());
+            return Ok(json!({
+                "result": format!("queued {} key(s)", keys.len()),
+                "variablesReference": 0,
+            }));
+        }
+        let value = Expression::parse(expression, &self.symbols)
+            .map_err(|error| match error {
+                VMError::Debugger(message) => {
+                    VMError::Debugger(format!("{message}, prefix input with `>`"))
+                }
+                other => other,
+            })?
+            .evaluate(&self.vm);
+        Ok(json!({
+            "result": self.describe_value(value),
+            "variablesReference": 0,
+            "memoryReference": format!("0x{value:04x}"),
+        }))
+    }
+
+    /// A value in hex and signed decimal, and relative to a label when it
+    /// is an address inside the program
+    fn describe_value(&self, value: u16) -> String {
+        let label = match (self.symbols.label_at(value), &self.source) {
+            (Some(label), _) => Some(String::from(label)),
+            (None, Some(source)) if source.map.contains(value) => self.symbols.describe(value),
+            _ => None,
+        };
+        match label {
+            Some(label) => format!("{}, {label}", describe_word(value)),
+            None => describe_word(value),
+        }
+    }
+
+    fn resolve(&self, name: &str) -> Option<u16> {
+        self.symbols
+            .address_of(name)
+            .or_else(|| parse_address(name))
+    }
+
+    fn step(&mut self) -> Result<(), VMError> {
+        if self.waiting_for_input() {
+            return self.pause_for_input();
+        }
+        let stop = self.step_line();
+        self.flush_output()?;
+        match stop {
+            Ok(StopReason::Halted) => self.terminate(),
+            Ok(StopReason::DeviceAccess { address, write }) => {
+                self.stop("data breakpoint", Some(device_access(address, write)))
+            }
+            Ok(_) if self.changed.is_some() => {
+                let change = self.changed.take().map(|change| change.to_string());
+                self.stop("data breakpoint", change)
+            }
+            Ok(_) => self.stop("step", None),
+            Err(error) => self.stop("exception", Some(format!("{error:#}"))),
+        }
+    }
+
+    /// Executes the instructions of the source line at the PC, or a single
+    /// one without a source, stopping early at a breakpoint or for input
+    fn step_line(&mut self) -> Result<StopReason, VMError> {
+        let line = self
+            .source
+            .as_ref()
+            .and_then(|source| source.map.line_at(self.vm.pc()));
+        loop {
+            let stop = self.execute()?;
+            let pc = self.vm.pc();
+            let same_line = line.is_some()
+                && self
+                    .source
+                    .as_ref()
+                    .and_then(|source| source.map.line_at(pc))
+                    == line;
+            if stop != StopReason::Step
+                || self.changed.is_some()
+                || !same_line
+                || self.vm.pending_stop().is_some()
+                || self.waiting_for_input()
+            {
+                return Ok(stop);
+            }
+        }
+    }
+
+    /// Executes one instruction, noting a data breakpoint it hits
+    fn execute(&mut self) -> Result<StopReason, VMError> {
+        let stop = self.vm.step()?;
+        if !self.watchpoints.is_empty() {
+            self.changed = self.watchpoints.check(&self.vm);
+        }
+        Ok(stop)
+    }
+
+    fn run_batch(&mut self) -> Result<(), VMError> {
+        let State::Running { until } = self.state else {
+            return Ok(());
+        };
+        for _ in 0..RUN_BATCH {
+            if self.waiting_for_input() {
+                self.flush_output()?;
+                return self.pause_for_input();
+            }
+            match self.execute() {
+                Ok(StopReason::Halted) => {
+                    self.flush_output()?;
+                    return self.terminate();
+                }
+                Ok(StopReason::Break) => {
+                    self.flush_output()?;
+                    return self.stop("pause", None);
+                }
+                Ok(StopReason::DeviceAccess { address, write }) => {
+                    self.flush_output()?;
+                    return self.stop("data breakpoint", Some(device_access(address, write)));
+                }
+                Ok(_) => {}
+                Err(error) => {
+                    self.flush_output()?;
+                    return self.stop("exception", Some(format!("{error:#}")));
+                }
+            }
+            if let Some(change) = self.changed.take() {
+                self.flush_output()?;
+                return self.stop("data breakpoint", Some(change.to_string()));
+            }
+            let pc = self.vm.pc();
+            if until == Some(pc) {
+                self.flush_output()?;
+                return self.stop("step", None);
+            }
+            let stop = self.vm.pending_stop();
+            if stop.is_some() {
+                self.flush_output()?;
+            }
+            match stop {
+                Some(StopReason::Trap(vector)) => {
+                    return self.stop("exception", Some(format!("TRAP x{vector:02X}")));
+                }
+                Some(StopReason::Interrupt(vector)) => {
+                    return self.stop("exception", Some(format!("Exception x{vector:02X}")));
+                }
+                Some(_) => return self.stop("breakpoint", None),
+                None => {}
+            }
+        }
+        self.flush_output()
+    }
+
+    /// Whether the next instruction blocks on a key that was not typed yet
+    fn waiting_for_input(&self) -> bool {
+        self.vm.waits_for_key() && !self.console.has_input()
+    }
+
+    fn pause_for_input(&mut self) -> Result<(), VMError> {
+        self.send_output(
+            "console",
+            "The program is waiting for input, type `>` followed by the keys in the debug console\n",
+        )?;
+        self.stop("pause", None)
+    }
+
+    fn stop(&mut self, reason: &str, text: Option<String>) -> Result<(), VMError> {
+        self.state = State::Stopped;
+        self.send_event(
+            "stopped",
+            json!({
+                "reason": reason,
+                "text": text,
+                "threadId": THREAD_ID,
+                "allThreadsStopped": true,
+            }),
+        )
+    }
+
+    fn terminate(&mut self) -> Result<(), VMError> {
+        self.state = State::Terminated;
+        self.send_event("exited", json!({ "exitCode": 0 }))?;
+        self.send_event("terminated", Value::Null)
+    }
+
+    fn flush_output(&mut self) -> Result<(), VMError> {
+        let output = self.console.take_output();
+        if output.is_empty() {
+            return Ok(());
+        }
+        self.send_output("stdout", &output)
+    }
+
+    fn send_output(&mut self, category: &str, output: &str) -> Result<(), VMError> {
+        self.send_event("output", json!({ "category": category, "output": output }))
+    }
+
+    fn respond(&mut self, request: &Value, result: Result<Value, VMError>) -> Result<(), VMError> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(Value::Null) => {}
+            Ok(body) => set(&mut response, "body", body),
+            Err(error) => set(&mut response, "message", json!(format!("{error:#}"))),
+        }
+        self.send(response)
+    }
+
+    fn send_event(&mut self, event: &str, body: Value) -> Result<(), VMError> {
+        let mut message = json!({ "type": "event", "event": event });
+        if !body.is_null() {
+            set(&mut message, "body", body);
+        }
+        self.send(message)
+    }
+
+    fn send(&mut self, mut message: Value) -> Result<(), VMError> {
+        self.sequence = self.sequence.saturating_add(1);
+        set(&mut message, "seq", json!(self.sequence));
+        let body = message.to_string();
+        write!(self.output, "Content-Length: {}\r\n\r\n{body}", body.len())
+            .and_then(|()| self.output.flush())
+            .map_err(|e| {
+                VMError::StandardIO(IoError::caused_by("Could not write to the client", e))
+            })
+    }
+}
+
+/// Path of a file given in the launch arguments, or the program path with
+/// `extension` if that file exists
+fn companion(arguments: &Value, key: &str, program: &str, extension: &str) -> Option<String> {
+    if let Some(path) = arguments[key].as_str() {
+        return Some(path.to_owned());
+    }
+    let path = Path::new(program).with_extension(extension);
+    path.exists().then(|| path.to_string_lossy().into_owned())
+}
+
+/// Line (1-based) of each label definition in assembly `source`, for
+/// sources that do not assemble on their own
+fn label_lines(source: &str, symbols: &SymbolTable) -> SourceMap {
+    let mut lines = BTreeMap::new();
+    for (line, text) in (1..).zip(source.lines()) {
+        let code = text.split(';').next().unwrap_or_default();
+        let Some(first) = code.split_whitespace().next() else {
+            continue;
+        };
+        if let Some(address) = symbols.address_of(first.trim_end_matches(':')) {
+            lines.entry(address).or_insert(line);
+        }
+    }
+    // each label line stands for the words up to the next label
+    let mut map = SourceMap::default();
+    let mut labels = lines.iter().peekable();
+    while let Some((&address, &line)) = labels.next() {
+        let end = labels.peek().map_or(u16::MAX, |(&next, _)| next);
+        map.insert(line, address, end.wrapping_sub(address).max(1));
+    }
+    map
+}
+
+/// Parses `x3000`, `0x3000` or `3000` as a hexadecimal address
+fn parse_address(text: &str) -> Option<u16> {
+    let hex = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix(['x', 'X']))
+        .unwrap_or(text);
+    u16::from_str_radix(hex, 16).ok()
+}
+
+fn describe_word(value: u16) -> String {
+    let signed = i16::from_ne_bytes(value.to_ne_bytes());
+    format!("x{value:04X} ({signed})")
+}
+
+/// `Read xFE00` or `Wrote xFE00`, for stops at device breaks
+fn device_access(address: u16, write: bool) -> String {
+    let verb = if write { "Wrote" } else { "Read" };
+    format!("{verb} x{address:04X}")
+}
+
+/// Sets `key` on a JSON object
+fn set(object: &mut Value, key: &str, value: Value) {
+    if let Some(object) = object.as_object_mut() {
+        object.insert(key.to_owned(), value);
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let mut group = [0; 3];
+        for (slot, byte) in group.iter_mut().zip(chunk) {
+            *slot = *byte;
+        }
+        let [a, b, c] = group;
+        let indices = [
+            a >> 2,
+            (a & 0x3) << 4 | b >> 4,
+            (b & 0xF) << 2 | c >> 6,
+            c & 0x3F,
+        ];
+        let produced = chunk.len().saturating_add(1);
+        for (position, index) in indices.iter().enumerate() {
+            if position < produced {
+                let symbol = ALPHABET.get(usize::from(*index)).copied().unwrap_or(b'=');
+                encoded.push(char::from(symbol));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
@@ -0,0 +1,408 @@
+//! Datapath view of executed instructions, in the style of PennSim: the
+//! phases of the instruction cycle and which elements of the LC-3 datapath
+//! each one uses, with the register transfers it performs, e.g.
+//!
+//! ```text
+//! x3001  ADD R0, R1, #2
+//!   fetch            PC  IR  MAR MDR MEM ·   ·    ·   ·    MAR ← PC, PC ← PC + 1, MDR ← M[MAR], IR ← MDR
+//!   decode           ·   IR  ·   ·   ·   ·   ·    ·   ·    opcode 0001 selects ADD
+//!   fetch operands   ·   ·   ·   ·   ·   ·   ·    REG ·    R1
+//!   execute          ·   ·   ·   ·   ·   ALU ·    ·   ·    R1 + #2
+//!   store result     ·   ·   ·   ·   ·   ·   ·    REG CC   R0 ← R1 + #2, CC ← NZP(R0)
+//! ```
+//!
+//! The phases follow the architectural definition of each instruction, so
+//! traps show the jump through the trap vector table even though the VM
+//! serves them natively.
+
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::fmt::{self, Write};
+
+use crate::{
+    disassembler::disassemble,
+    instructions::{Instruction, JsrTarget, Operand},
+    observer::Observer,
+    register::Register,
+    vm::{Conformance, VM},
+};
+
+/// Phases of the instruction cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Fetch,
+    Decode,
+    EvaluateAddress,
+    FetchOperands,
+    Execute,
+    StoreResult,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Phase::Fetch => "fetch",
+            Phase::Decode => "decode",
+            Phase::EvaluateAddress => "evaluate address",
+            Phase::FetchOperands => "fetch operands",
+            Phase::Execute => "execute",
+            Phase::StoreResult => "store result",
+        })
+    }
+}
+
+/// Elements of the datapath, in the order they are drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Element {
+    Pc,
+    Ir,
+    Mar,
+    Mdr,
+    Memory,
+    Alu,
+    /// The adder computing PC- and base-relative addresses
+    AddressAdder,
+    RegisterFile,
+    /// The condition codes
+    Cc,
+}
+
+impl Element {
+    pub const ALL: [Element; 9] = [
+        Element::Pc,
+        Element::Ir,
+        Element::Mar,
+        Element::Mdr,
+        Element::Memory,
+        Element::Alu,
+        Element::AddressAdder,
+        Element::RegisterFile,
+        Element::Cc,
+    ];
+
+    /// Short label of the element in diagrams
+    pub fn label(self) -> &'static str {
+        match self {
+            Element::Pc => "PC",
+            Element::Ir => "IR",
+            Element::Mar => "MAR",
+            Element::Mdr => "MDR",
+            Element::Memory => "MEM",
+            Element::Alu => "ALU",
+            Element::AddressAdder => "ADDR",
+            Element::RegisterFile => "REG",
+            Element::Cc => "CC",
+        }
+    }
+}
+
+/// What the datapath does during one phase of an instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Activity {
+    pub phase: Phase,
+    pub elements: Vec<Element>,
+    /// The register transfers, such as `MAR ← PC, PC ← PC + 1`
+    pub transfer: String,
+}
+
+impl Activity {
+    fn new(phase: Phase, elements: &[Element], transfer: String) -> Self {
+        Activity {
+            phase,
+            elements: elements.to_vec(),
+            transfer,
+        }
+    }
+}
+
+/// The phases `instruction` goes through, in order, the phases it skips
+/// left out. `conformance` decides whether LEA sets the condition codes.
+pub fn phases(instruction: &Instruction, raw: u16, conformance: &Conformance) -> Vec<Activity> {
+    use Element::*;
+    use Phase::*;
+
+    let mut activities = vec![
+        Activity::new(
+            Fetch,
+            &[Pc, Ir, Mar, Mdr, Memory],
+            String::from("MAR ← PC, PC ← PC + 1, MDR ← M[MAR], IR ← MDR"),
+        ),
+        Activity::new(
+            Decode,
+            &[Ir],
+            format!("opcode {:04b} selects {}", raw >> 12, name(instruction)),
+        ),
+    ];
+    let flags = |dr: Register| format!(", CC ← NZP({dr})");
+    let rest = match *instruction {
+        Instruction::Add { dr, sr1, operand } | Instruction::And { dr, sr1, operand } => {
+            let symbol = if matches!(instruction, Instruction::Add { .. }) {
+                "+"
+            } else {
+                "AND"
+            };
+            let (operands, second) = match operand {
+                Operand::Register(sr2) => (format!("{sr1}, {sr2}"), sr2.to_string()),
+                Operand::Immediate(immediate) => (sr1.to_string(), format!("#{immediate}")),
+            };
+            let result = format!("{sr1} {symbol} {second}");
+            vec![
+                Activity::new(FetchOperands, &[RegisterFile], operands),
+                Activity::new(Execute, &[Alu], result.clone()),
+                Activity::new(
+                    StoreResult,
+                    &[RegisterFile, Cc],
+                    format!("{dr} ← {result}{}", flags(dr)),
+                ),
+            ]
+        }
+        Instruction::Not { dr, sr } => vec![
+            Activity::new(FetchOperands, &[RegisterFile], sr.to_string()),
+            Activity::new(Execute, &[Alu], format!("NOT {sr}")),
+            Activity::new(
+                StoreResult,
+                &[RegisterFile, Cc],
+                format!("{dr} ← NOT {sr}{}", flags(dr)),
+            ),
+        ],
+        Instruction::Ld { dr, pc_offset } => vec![
+            Activity::new(
+                EvaluateAddress,
+                &[Pc, Ir, AddressAdder, Mar],
+                format!("MAR ← PC + #{pc_offset}"),
+            ),
+            Activity::new(
+                FetchOperands,
+                &[Mar, Memory, Mdr],
+                String::from("MDR ← M[MAR]"),
+            ),
+            Activity::new(
+                StoreResult,
+                &[Mdr, RegisterFile, Cc],
+                format!("{dr} ← MDR{}", flags(dr)),
+            ),
+        ],
+        Instruction::Ldi { dr, pc_offset } => vec![
+            Activity::new(
+                EvaluateAddress,
+                &[Pc, Ir, AddressAdder, Mar, Memory, Mdr],
+                format!("MAR ← PC + #{pc_offset}, MDR ← M[MAR], MAR ← MDR"),
+            ),
+            Activity::new(
+                FetchOperands,
+                &[Mar, Memory, Mdr],
+                String::from("MDR ← M[MAR]"),
+            ),
+            Activity::new(
+                StoreResult,
+                &[Mdr, RegisterFile, Cc],
+                format!("{dr} ← MDR{}", flags(dr)),
+            ),
+        ],
+        Instruction::Ldr { dr, base, offset } => vec![
+            Activity::new(
+                EvaluateAddress,
+                &[RegisterFile, Ir, AddressAdder, Mar],
+                format!("MAR ← {base} + #{offset}"),
+            ),
+            Activity::new(
+                FetchOperands,
+                &[Mar, Memory, Mdr],
+                String::from("MDR ← M[MAR]"),
+            ),
+            Activity::new(
+                StoreResult,
+                &[Mdr, RegisterFile, Cc],
+                format!("{dr} ← MDR{}", flags(dr)),
+            ),
+        ],
+        Instruction::Lea { dr, pc_offset } => {
+            let (elements, flags): (&[Element], String) = if conformance.lea_sets_flags {
+                (&[RegisterFile, Cc], flags(dr))
+            } else {
+                (&[RegisterFile], String::new())
+            };
+            vec![
+                Activity::new(
+                    EvaluateAddress,
+                    &[Pc, Ir, AddressAdder],
+                    format!("PC + #{pc_offset}"),
+                ),
+                Activity::new(
+                    StoreResult,
+                    elements,
+                    format!("{dr} ← PC + #{pc_offset}{flags}"),
+                ),
+            ]
+        }
+        Instruction::St { sr, pc_offset } => {
+            store(&[Pc, Ir], format!("MAR ← PC + #{pc_offset}"), sr)
+        }
+        Instruction::Str { sr, base, offset } => {
+            store(&[RegisterFile, Ir], format!("MAR ← {base} + #{offset}"), sr)
+        }
+        Instruction::Sti { sr, pc_offset } => store(
+            &[Pc, Ir, Memory, Mdr],
+            format!("MAR ← PC + #{pc_offset}, MDR ← M[MAR], MAR ← MDR"),
+            sr,
+        ),
+        Instruction::Br { n, z, p, pc_offset } => {
+            let tested: String = [(n, 'n'), (z, 'z'), (p, 'p')]
+                .iter()
+                .filter(|(tested, _)| *tested)
+                .map(|(_, letter)| letter)
+                .collect();
+            let tested = if tested.is_empty() {
+                String::from("none")
+            } else {
+                tested
+            };
+            vec![Activity::new(
+                Execute,
+                &[Cc, Pc, Ir, AddressAdder],
+                format!("BEN ← CC in {tested}, if BEN: PC ← PC + #{pc_offset}"),
+            )]
+        }
+        Instruction::Jmp { base } => vec![Activity::new(
+            Execute,
+            &[RegisterFile, Pc],
+            format!("PC ← {base}"),
+        )],
+        Instruction::Jsr { target } => {
+            let (elements, destination): (&[Element], String) = match target {
+                JsrTarget::Offset(offset) => (
+                    &[Pc, Ir, AddressAdder, RegisterFile],
+                    format!("PC + #{offset}"),
+                ),
+                JsrTarget::Register(base) => (&[Pc, RegisterFile], base.to_string()),
+            };
+            vec![Activity::new(
+                Execute,
+                elements,
+                format!("R7 ← PC, PC ← {destination}"),
+            )]
+        }
+        Instruction::Trap { trap_vector } => vec![
+            Activity::new(
+                EvaluateAddress,
+                &[Ir, Mar],
+                format!("MAR ← x{trap_vector:04X}"),
+            ),
+            Activity::new(
+                FetchOperands,
+                &[Mar, Memory, Mdr],
+                String::from("MDR ← M[MAR]"),
+            ),
+            Activity::new(
+                Execute,
+                &[Pc, Mdr, RegisterFile],
+                String::from("R7 ← PC, PC ← MDR"),
+            ),
+        ],
+        Instruction::Rti => vec![Activity::new(
+            Execute,
+            &[RegisterFile, Mar, Memory, Mdr, Pc, Cc],
+            String::from("PC ← M[R6], PSR ← M[R6 + 1], R6 ← R6 + 2"),
+        )],
+        Instruction::Res => vec![Activity::new(
+            Execute,
+            &[],
+            String::from("illegal opcode exception"),
+        )],
+    };
+    activities.extend(rest);
+    activities
+}
+
+/// The phases of ST, STR and STI once `evaluate` put the address in MAR
+fn store(evaluate: &[Element], address: String, sr: Register) -> Vec<Activity> {
+    let mut elements = evaluate.to_vec();
+    elements.extend([Element::AddressAdder, Element::Mar]);
+    vec![
+        Activity::new(Phase::EvaluateAddress, &elements, address),
+        Activity::new(
+            Phase::FetchOperands,
+            &[Element::RegisterFile, Element::Mdr],
+            format!("MDR ← {sr}"),
+        ),
+        Activity::new(
+            Phase::StoreResult,
+            &[Element::Mar, Element::Mdr, Element::Memory],
+            String::from("M[MAR] ← MDR"),
+        ),
+    ]
+}
+
+fn name(instruction: &Instruction) -> &'static str {
+    match instruction {
+        Instruction::Br { .. } => "BR",
+        Instruction::Add { .. } => "ADD",
+        Instruction::Ld { .. } => "LD",
+        Instruction::St { .. } => "ST",
+        Instruction::Jsr {
+            target: JsrTarget::Offset(_),
+        } => "JSR",
+        Instruction::Jsr {
+            target: JsrTarget::Register(_),
+        } => "JSRR",
+        Instruction::And { .. } => "AND",
+        Instruction::Ldr { .. } => "LDR",
+        Instruction::Str { .. } => "STR",
+        Instruction::Rti => "RTI",
+        Instruction::Not { .. } => "NOT",
+        Instruction::Ldi { .. } => "LDI",
+        Instruction::Sti { .. } => "STI",
+        Instruction::Jmp { .. } => "JMP",
+        Instruction::Res => "RES",
+        Instruction::Lea { .. } => "LEA",
+        Instruction::Trap { .. } => "TRAP",
+    }
+}
+
+/// The diagram of the instruction `raw` at `pc`: its disassembly, then a
+/// line per phase with the labels of the active elements in fixed columns
+pub fn render(pc: u16, raw: u16, activities: &[Activity]) -> String {
+    let mut diagram = format!("x{pc:04X}  {}\n", disassemble(pc, raw));
+    for activity in activities {
+        let _ = write!(diagram, "  {:<17}", activity.phase.to_string());
+        for element in Element::ALL {
+            let label = if activity.elements.contains(&element) {
+                element.label()
+            } else {
+                "·"
+            };
+            let width = element.label().len().max(3);
+            let _ = write!(diagram, "{label:<width$} ");
+        }
+        let _ = writeln!(diagram, " {}", activity.transfer);
+    }
+    diagram
+}
+
+/// Observer handing the diagram of every executed instruction to a callback
+pub struct DatapathView {
+    sink: Box<dyn FnMut(&str)>,
+}
+
+impl DatapathView {
+    pub fn new<F>(sink: F) -> Self
+    where
+        F: FnMut(&str) + 'static,
+    {
+        DatapathView {
+            sink: Box::new(sink),
+        }
+    }
+}
+
+impl Observer for DatapathView {
+    fn after_instruction(&mut self, vm: &VM, pc: u16, raw: u16) {
+        let activities = phases(&Instruction::decode(raw), raw, &vm.conformance());
+        (self.sink)(&render(pc, raw, &activities));
+    }
+}
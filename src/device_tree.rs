@@ -0,0 +1,248 @@
+//! Machines described by a TOML file listing the devices to attach and
+//! their settings, so a setup with several devices is reproduced with one
+//! option instead of host code (`lc3-vm run --devices machine.toml`).
+//!
+//! ```toml
+//! # what programs get at device addresses no device answers
+//! unmapped = "bus-error"
+//!
+//! [[device]]
+//! type = "display"
+//! columns = 40
+//! rows = 12
+//!
+//! [[device]]
+//! type = "serial"
+//! address = "127.0.0.1:4000"
+//!
+//! [[device]]
+//! type = "timer"
+//! period = 1000
+//!
+//! [[device]]
+//! type = "rng"
+//! seed = 42
+//!
+//! [[device]]
+//! type = "dma"
+//!
+//! [[device]]
+//! type = "channel"
+//! name = "debug"
+//! base = 0xFE30
+//! output = "debug.log"
+//! ```
+//!
+//! Devices are attached in order, so two at the same addresses are refused
+//! like with the methods of `VM`. A channel prints to `stdout`, `stderr` or
+//! a file created at `output`, relative to the working directory. `unmapped` takes the values of
+//! `--unmapped` and defaults to `memory`.
+
+use std::{fs, path::Path};
+
+use toml::{Table, Value};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{console::OutputConsole, serial::SerialPort};
+use crate::{
+    display::{Display, COLUMNS, ROWS},
+    errors::{IoError, VMError},
+    vm::{UnmappedAccess, VM},
+};
+
+/// A device of the file with its settings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceSpec {
+    /// Serial port waiting for a peer on `address`, `HOST:PORT` or
+    /// `unix:PATH`
+    Serial {
+        address: String,
+    },
+    /// Character display of `columns` by `rows` cells, 80 by 24 by default
+    Display {
+        columns: usize,
+        rows: usize,
+    },
+    Dma,
+    /// Interval timer firing every `period` instructions
+    Timer {
+        period: u16,
+    },
+    /// Random number generator starting from `seed`, 0 by default
+    Rng {
+        seed: u64,
+    },
+    /// Console channel with its registers from `base`, printing to `output`
+    Channel {
+        name: String,
+        base: u16,
+        output: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceTree {
+    pub devices: Vec<DeviceSpec>,
+    pub unmapped: UnmappedAccess,
+}
+
+impl DeviceTree {
+    pub fn load(path: &Path) -> Result<DeviceTree, VMError> {
+        let text = fs::read_to_string(path).map_err(|e| {
+            VMError::ReadFile(IoError::caused_by(
+                format!("Could not read {}", path.display()),
+                e,
+            ))
+        })?;
+        DeviceTree::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<DeviceTree, VMError> {
+        let table: Table = text
+            .parse()
+            .map_err(|error| invalid(format!("{error}").trim_end()))?;
+        let mut tree = DeviceTree {
+            devices: Vec::new(),
+            unmapped: UnmappedAccess::default(),
+        };
+        for (key, value) in &table {
+            match (key.as_str(), value) {
+                ("unmapped", Value::String(policy)) => {
+                    tree.unmapped = policy.parse().map_err(|_| {
+                        invalid(&format!(
+                            "`unmapped` must be memory, zero, bus-error or fail, not {policy}"
+                        ))
+                    })?;
+                }
+                ("device", Value::Array(devices)) => {
+                    for (index, device) in devices.iter().enumerate() {
+                        let Value::Table(device) = device else {
+                            return Err(invalid("Every [[device]] must be a table"));
+                        };
+                        let context = format!("device {}", index.saturating_add(1));
+                        tree.devices.push(spec(device, &context)?);
+                    }
+                }
+                ("unmapped" | "device", _) => {
+                    return Err(invalid(&format!("`{key}` has the wrong type")));
+                }
+                _ => return Err(invalid(&format!("Unknown setting `{key}`"))),
+            }
+        }
+        Ok(tree)
+    }
+
+    /// Attaches the devices to `vm` in order and sets the unmapped access
+    /// policy, waiting for the peer of every serial port
+    pub fn apply(&self, vm: &mut VM) -> Result<(), VMError> {
+        for device in &self.devices {
+            match device {
+                #[cfg(not(target_arch = "wasm32"))]
+                DeviceSpec::Serial { address } => vm.set_serial(SerialPort::listen(address)?)?,
+                #[cfg(not(target_arch = "wasm32"))]
+                DeviceSpec::Channel { name, base, output } => {
+                    vm.add_channel(name, *base, Box::new(OutputConsole::open(output)?))?;
+                }
+                #[cfg(target_arch = "wasm32")]
+                DeviceSpec::Serial { .. } | DeviceSpec::Channel { .. } => {
+                    return Err(VMError::InvalidArgument(String::from(
+                        "Serial ports and channels are not available on this platform",
+                    )))
+                }
+                DeviceSpec::Display { columns, rows } => {
+                    vm.set_display(Display::with_size(*columns, *rows)?)?;
+                }
+                DeviceSpec::Dma => vm.enable_dma()?,
+                DeviceSpec::Timer { period } => vm.enable_timer(*period)?,
+                DeviceSpec::Rng { seed } => vm.enable_rng(*seed)?,
+            }
+        }
+        vm.set_unmapped_access(self.unmapped);
+        Ok(())
+    }
+}
+
+/// The device a `[[device]]` table describes
+fn spec(device: &Table, context: &str) -> Result<DeviceSpec, VMError> {
+    let kind = match device.get("type") {
+        Some(Value::String(kind)) => kind.as_str(),
+        _ => return Err(invalid(&format!("{context} has no `type`"))),
+    };
+    let settings: &[&str] = match kind {
+        "serial" => &["address"],
+        "display" => &["columns", "rows"],
+        "dma" => &[],
+        "timer" => &["period"],
+        "rng" => &["seed"],
+        "channel" => &["name", "base", "output"],
+        _ => return Err(invalid(&format!("{context}: unknown device type {kind}"))),
+    };
+    if let Some(key) = device
+        .keys()
+        .find(|key| *key != "type" && !settings.contains(&key.as_str()))
+    {
+        return Err(invalid(&format!(
+            "{context}: `{key}` is not a setting of {kind}"
+        )));
+    }
+    let number = |key: &str| -> Result<Option<u64>, VMError> {
+        device
+            .get(key)
+            .map(|value| {
+                value
+                    .as_integer()
+                    .and_then(|number| u64::try_from(number).ok())
+                    .ok_or_else(|| {
+                        invalid(&format!("{context}: `{key}` must be a positive number"))
+                    })
+            })
+            .transpose()
+    };
+    let size = |key: &str, default: usize| -> Result<usize, VMError> {
+        number(key)?.map_or(Ok(default), |size| {
+            usize::try_from(size).map_err(|_| invalid(&format!("{context}: `{key}` is too large")))
+        })
+    };
+    Ok(match kind {
+        "serial" => match device.get("address") {
+            Some(Value::String(address)) => DeviceSpec::Serial {
+                address: address.clone(),
+            },
+            _ => return Err(invalid(&format!("{context} has no `address`"))),
+        },
+        "display" => DeviceSpec::Display {
+            columns: size("columns", COLUMNS)?,
+            rows: size("rows", ROWS)?,
+        },
+        "dma" => DeviceSpec::Dma,
+        "channel" => {
+            let text = |key: &str| match device.get(key) {
+                Some(Value::String(text)) => Ok(text.clone()),
+                _ => Err(invalid(&format!("{context} has no `{key}`"))),
+            };
+            let base =
+                number("base")?.ok_or_else(|| invalid(&format!("{context} has no `base`")))?;
+            DeviceSpec::Channel {
+                name: text("name")?,
+                base: u16::try_from(base)
+                    .map_err(|_| invalid(&format!("{context}: `base` must be at most 0xFFFF")))?,
+                output: text("output")?,
+            }
+        }
+        "timer" => {
+            let period =
+                number("period")?.ok_or_else(|| invalid(&format!("{context} has no `period`")))?;
+            DeviceSpec::Timer {
+                period: u16::try_from(period)
+                    .map_err(|_| invalid(&format!("{context}: `period` must be at most 65535")))?,
+            }
+        }
+        _ => DeviceSpec::Rng {
+            seed: number("seed")?.unwrap_or_default(),
+        },
+    })
+}
+
+fn invalid(message: &str) -> VMError {
+    VMError::InvalidArgument(format!("Invalid device tree: {message}"))
+}
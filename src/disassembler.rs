@@ -0,0 +1,283 @@
+//! Renders instructions in LC-3 assembly syntax, one at a time or whole
+//! programs laid out like their source
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use crate::{
+    instructions::{Instruction, JsrTarget, Operand, TrapCode},
+    symbols::SymbolTable,
+};
+
+/// Disassembles the word `raw` stored at `address`. PC-relative operands are
+/// shown as the absolute address they refer to.
+pub fn disassemble(address: u16, raw: u16) -> String {
+    let next = address.wrapping_add(1);
+    render(raw, |offset| match next.checked_add_signed(offset) {
+        Some(target) => format!("x{target:04X}"),
+        None => format!("#{offset}"),
+    })
+}
+
+/// Disassembles a program loaded at `origin` as source that assembles back
+/// to the same words. Control flow is followed from the origin, then from
+/// the labels of `symbols` that no reached instruction uses as data, and
+/// the words it never reaches are shown as `.FILL`, `.BLKW` or `.STRINGZ`
+/// instead of instructions. Targets without a symbol get an `Lxxxx` label.
+pub fn disassemble_program(origin: u16, words: &[u16], symbols: &SymbolTable) -> String {
+    let address_of = |index: usize| {
+        let offset = u16::try_from(index).unwrap_or(u16::MAX);
+        origin.wrapping_add(offset)
+    };
+    let code = find_code(origin, words, symbols);
+    let mut labels: BTreeMap<u16, String> = symbols
+        .iter()
+        .map(|(address, label)| (address, String::from(label)))
+        .collect();
+    for (index, &raw) in words.iter().enumerate() {
+        if code.get(index) == Some(&true) {
+            if let Some(target) = target(address_of(index), Instruction::decode(raw)) {
+                if offset_in(origin, words, target).is_some() {
+                    labels
+                        .entry(target)
+                        .or_insert_with(|| format!("L{target:04X}"));
+                }
+            }
+        }
+    }
+    let mut lines = vec![format!("        .ORIG x{origin:04X}")];
+    let mut index = 0;
+    while let Some(&raw) = words.get(index) {
+        let address = address_of(index);
+        let label = labels.get(&address).map_or("", String::as_str);
+        let (text, length) = if code.get(index) == Some(&true) {
+            let next = address.wrapping_add(1);
+            let text = render(raw, |offset| {
+                match next.checked_add_signed(offset).and_then(|t| labels.get(&t)) {
+                    Some(label) => label.clone(),
+                    None => format!("#{offset}"),
+                }
+            });
+            (text, 1)
+        } else {
+            // data runs stop at the next label or instruction
+            let run: Vec<u16> = words
+                .iter()
+                .enumerate()
+                .skip(index)
+                .take_while(|&(at, _)| {
+                    at == index
+                        || (code.get(at) != Some(&true) && !labels.contains_key(&address_of(at)))
+                })
+                .map(|(_, &word)| word)
+                .collect();
+            data(&run)
+        };
+        lines.push(match label {
+            "" => format!("        {text}"),
+            label => format!("{label:<7} {text}"),
+        });
+        index = index.saturating_add(length.max(1));
+    }
+    lines.push(String::from("        .END"));
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+/// Marks the words of a program at `origin` that are reached as
+/// instructions, following control flow from the origin and then from the
+/// labels of `symbols` not used as data by the instructions found so far
+pub fn find_code(origin: u16, words: &[u16], symbols: &SymbolTable) -> Vec<bool> {
+    let mut code = vec![false; words.len()];
+    let mut data = BTreeSet::new();
+    trace(origin, words, origin, &mut code, &mut data);
+    for (address, _) in symbols.iter() {
+        if !data.contains(&address) {
+            trace(origin, words, address, &mut code, &mut data);
+        }
+    }
+    code
+}
+
+/// Marks the instructions reachable from `entry`, collecting the addresses
+/// they load, store or take as data
+fn trace(origin: u16, words: &[u16], entry: u16, code: &mut [bool], data: &mut BTreeSet<u16>) {
+    let mut pending = vec![entry];
+    while let Some(address) = pending.pop() {
+        let Some(index) = offset_in(origin, words, address) else {
+            continue;
+        };
+        let (Some(&raw), Some(false)) = (words.get(index), code.get(index).copied()) else {
+            continue;
+        };
+        if !is_instruction(raw) {
+            continue;
+        }
+        if let Some(marked) = code.get_mut(index) {
+            *marked = true;
+        }
+        let instruction = Instruction::decode(raw);
+        let next = address.wrapping_add(1);
+        match instruction {
+            Instruction::Ld { .. }
+            | Instruction::Ldi { .. }
+            | Instruction::St { .. }
+            | Instruction::Sti { .. }
+            | Instruction::Lea { .. } => {
+                data.extend(target(address, instruction));
+            }
+            Instruction::Br { .. } | Instruction::Jsr { .. } => {
+                pending.extend(target(address, instruction));
+            }
+            _ => {}
+        }
+        if falls_through(instruction) {
+            pending.push(next);
+        }
+    }
+}
+
+/// Whether `raw` can be an instruction: words that do not encode back to
+/// themselves, never-taken branches such as zero, and the reserved opcode
+/// are taken as data
+fn is_instruction(raw: u16) -> bool {
+    let instruction = Instruction::decode(raw);
+    match instruction {
+        Instruction::Res => false,
+        Instruction::Br { n, z, p, .. } if !(n || z || p) => false,
+        _ => instruction.encode() == raw,
+    }
+}
+
+/// Whether execution can continue with the next word
+fn falls_through(instruction: Instruction) -> bool {
+    match instruction {
+        Instruction::Br { n, z, p, .. } => !(n && z && p),
+        Instruction::Jmp { .. } | Instruction::Rti => false,
+        Instruction::Trap { trap_vector } => TrapCode::try_from(trap_vector) != Ok(TrapCode::Halt),
+        _ => true,
+    }
+}
+
+/// The address a PC-relative instruction at `address` refers to
+fn target(address: u16, instruction: Instruction) -> Option<u16> {
+    let offset = match instruction {
+        Instruction::Br { pc_offset, .. }
+        | Instruction::Ld { pc_offset, .. }
+        | Instruction::Ldi { pc_offset, .. }
+        | Instruction::St { pc_offset, .. }
+        | Instruction::Sti { pc_offset, .. }
+        | Instruction::Lea { pc_offset, .. } => pc_offset,
+        Instruction::Jsr {
+            target: JsrTarget::Offset(offset),
+        } => offset,
+        _ => return None,
+    };
+    address.wrapping_add(1).checked_add_signed(offset)
+}
+
+/// Index of `address` in a program at `origin`
+fn offset_in(origin: u16, words: &[u16], address: u16) -> Option<usize> {
+    let index = usize::from(address.checked_sub(origin)?);
+    (index < words.len()).then_some(index)
+}
+
+/// Renders the start of a run of data words, returning the directive and
+/// how many words it covers: a string with its terminating zero, zeros, or
+/// a single word
+fn data(run: &[u16]) -> (String, usize) {
+    let text: String = run
+        .iter()
+        .map_while(|&word| u8::try_from(word).ok().and_then(escape))
+        .collect();
+    let characters = run
+        .iter()
+        .take_while(|&&word| u8::try_from(word).ok().and_then(escape).is_some())
+        .count();
+    if characters > 0 && run.get(characters) == Some(&0) {
+        return (format!(".STRINGZ \"{text}\""), characters.saturating_add(1));
+    }
+    let zeros = run.iter().take_while(|&&word| word == 0).count();
+    if zeros > 1 {
+        return (format!(".BLKW #{zeros}"), zeros);
+    }
+    let word = run.first().copied().unwrap_or_default();
+    (format!(".FILL x{word:04X}"), 1)
+}
+
+/// How a character is written in a `.STRINGZ`, if it can be
+fn escape(byte: u8) -> Option<String> {
+    match byte {
+        b'\n' => Some(String::from("\\n")),
+        b'\t' => Some(String::from("\\t")),
+        b'\r' => Some(String::from("\\r")),
+        0x1B => Some(String::from("\\e")),
+        b'"' => Some(String::from("\\\"")),
+        b'\\' => Some(String::from("\\\\")),
+        b' '..=b'~' => Some(String::from(char::from(byte))),
+        _ => None,
+    }
+}
+
+/// Renders `raw`, writing PC-relative operands with `target`
+fn render(raw: u16, target: impl Fn(i16) -> String) -> String {
+    match Instruction::decode(raw) {
+        Instruction::Br { n, z, p, pc_offset } => {
+            if !(n || z || p) {
+                return String::from("NOP");
+            }
+            let flags: String = [(n, 'n'), (z, 'z'), (p, 'p')]
+                .iter()
+                .filter(|(set, _)| *set)
+                .map(|(_, flag)| *flag)
+                .collect();
+            format!("BR{flags} {}", target(pc_offset))
+        }
+        Instruction::Add { dr, sr1, operand } => {
+            format!("ADD {dr}, {sr1}, {}", operand_text(operand))
+        }
+        Instruction::And { dr, sr1, operand } => {
+            format!("AND {dr}, {sr1}, {}", operand_text(operand))
+        }
+        Instruction::Ld { dr, pc_offset } => format!("LD {dr}, {}", target(pc_offset)),
+        Instruction::Ldi { dr, pc_offset } => format!("LDI {dr}, {}", target(pc_offset)),
+        Instruction::Lea { dr, pc_offset } => format!("LEA {dr}, {}", target(pc_offset)),
+        Instruction::St { sr, pc_offset } => format!("ST {sr}, {}", target(pc_offset)),
+        Instruction::Sti { sr, pc_offset } => format!("STI {sr}, {}", target(pc_offset)),
+        Instruction::Ldr { dr, base, offset } => format!("LDR {dr}, {base}, #{offset}"),
+        Instruction::Str { sr, base, offset } => format!("STR {sr}, {base}, #{offset}"),
+        Instruction::Not { dr, sr } => format!("NOT {dr}, {sr}"),
+        Instruction::Jsr {
+            target: JsrTarget::Offset(offset),
+        } => format!("JSR {}", target(offset)),
+        Instruction::Jsr {
+            target: JsrTarget::Register(base),
+        } => format!("JSRR {base}"),
+        Instruction::Jmp { base } if base.number() == 7 => String::from("RET"),
+        Instruction::Jmp { base } => format!("JMP {base}"),
+        Instruction::Rti => String::from("RTI"),
+        Instruction::Res => format!(".FILL x{raw:04X}"),
+        Instruction::Trap { trap_vector } => match TrapCode::try_from(trap_vector) {
+            Ok(TrapCode::Getc) => String::from("GETC"),
+            Ok(TrapCode::Out) => String::from("OUT"),
+            Ok(TrapCode::Puts) => String::from("PUTS"),
+            Ok(TrapCode::In) => String::from("IN"),
+            Ok(TrapCode::Putsp) => String::from("PUTSP"),
+            Ok(TrapCode::Halt) => String::from("HALT"),
+            Err(_) => format!("TRAP x{trap_vector:02X}"),
+        },
+    }
+}
+
+fn operand_text(operand: Operand) -> String {
+    match operand {
+        Operand::Register(register) => register.to_string(),
+        Operand::Immediate(value) => format!("#{value}"),
+    }
+}
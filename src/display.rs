@@ -0,0 +1,135 @@
+//! Character display, an 80x24 grid of words in high memory shown on the
+//! terminal so programs can draw full screens without printing escape
+//! sequences themselves:
+//!
+//! - `DISPLAY_START` (xF000) to xF77F: the cells row by row, the low byte of
+//!   each word is its character. Other values than printable ASCII show as
+//!   blanks.
+//! - `DFR` (xFE10): writing any value presents the grid. Only the cells that
+//!   changed since the last frame are redrawn.
+//!
+//! Grids of other sizes, made with `Display::with_size`, take their cells
+//! from `DISPLAY_START` the same way, up to the device registers.
+
+use alloc::{format, string::String, vec, vec::Vec};
+
+use crate::{errors::VMError, memory::MMIO_START};
+
+/// First word of the grid
+pub const DISPLAY_START: u16 = 0xF000;
+/// Last word of the grid
+pub const DISPLAY_END: u16 = 0xF77F;
+pub const COLUMNS: usize = 80;
+pub const ROWS: usize = 24;
+/// Words of the grid
+pub const CELLS: usize = COLUMNS * ROWS;
+/// Display frame memory mapped register, writing it presents the grid
+pub const DFR: u16 = 0xFE10;
+
+/// Remembers the frame shown on the terminal to redraw only what changed
+#[derive(Debug, Clone)]
+pub struct Display {
+    columns: usize,
+    rows: usize,
+    /// Characters on the terminal, `None` before the first frame
+    shown: Option<Vec<u8>>,
+}
+
+impl Default for Display {
+    fn default() -> Self {
+        Display {
+            columns: COLUMNS,
+            rows: ROWS,
+            shown: None,
+        }
+    }
+}
+
+impl Display {
+    /// The 80x24 grid
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A grid of `columns` by `rows` cells, failing unless it fits between
+    /// `DISPLAY_START` and the device registers
+    pub fn with_size(columns: usize, rows: usize) -> Result<Self, VMError> {
+        let room = usize::from(MMIO_START.wrapping_sub(DISPLAY_START));
+        match columns.checked_mul(rows) {
+            Some(cells) if cells > 0 && cells <= room => Ok(Display {
+                columns,
+                rows,
+                shown: None,
+            }),
+            _ => Err(VMError::InvalidArgument(format!(
+                "A {columns}x{rows} display does not fit in the {room} words from x{DISPLAY_START:04X}"
+            ))),
+        }
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Words of the grid
+    pub fn cells(&self) -> usize {
+        self.columns.saturating_mul(self.rows)
+    }
+
+    /// Last word of the grid
+    pub fn end(&self) -> u16 {
+        let last = self.cells().saturating_sub(1);
+        DISPLAY_START.saturating_add(u16::try_from(last).unwrap_or(u16::MAX))
+    }
+
+    /// Escape sequences turning the frame shown last into `frame`, the words
+    /// of the grid, leaving the cursor below the grid for the text the
+    /// program prints. The first frame clears the terminal.
+    pub fn render(&mut self, frame: &[u16]) -> String {
+        let mut output = String::new();
+        let (columns, cells) = (self.columns, self.cells());
+        let shown = self.shown.get_or_insert_with(|| {
+            output.push_str("\x1b[2J");
+            vec![b' '; cells]
+        });
+        let mut cursor = None;
+        for (index, (old, &word)) in shown.iter_mut().zip(frame).enumerate() {
+            let new = glyph(word);
+            if *old == new {
+                continue;
+            }
+            *old = new;
+            if cursor != Some(index) {
+                let row = index.checked_div(columns).unwrap_or_default();
+                let column = index.checked_rem(columns).unwrap_or_default();
+                output.push_str(&format!(
+                    "\x1b[{};{}H",
+                    row.saturating_add(1),
+                    column.saturating_add(1)
+                ));
+            }
+            output.push(char::from(new));
+            // the terminal keeps the cursor on the last column
+            cursor = Some(index.saturating_add(1))
+                .filter(|next| next.checked_rem(columns).is_some_and(|column| column != 0));
+        }
+        if !output.is_empty() {
+            output.push_str(&format!("\x1b[{};1H", self.rows.saturating_add(1)));
+        }
+        output
+    }
+}
+
+/// Character of a cell
+fn glyph(word: u16) -> u8 {
+    let [byte, _] = word.to_le_bytes();
+    if byte.is_ascii_graphic() {
+        byte
+    } else {
+        b' '
+    }
+}
@@ -0,0 +1,171 @@
+use alloc::string::String;
+#[cfg(feature = "std")]
+use alloc::sync::Arc;
+use core::{error::Error, fmt};
+
+/// Everything that can go wrong loading, assembling, running or debugging
+/// a program.
+///
+/// `Display` shows the kind of error and its message; the alternate form
+/// (`{:#}`) also appends the underlying I/O error, if any, which is
+/// available through `Error::source` as well.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VMError {
+    OpenFile(IoError),
+    ReadFile(IoError),
+    InvalidImage(String),
+    MemoryIndex(String),
+    AddressOverflow(String),
+    InvalidOpcode(String),
+    InvalidTrapCode(String),
+    InvalidCharacter(String),
+    StandardIO(IoError),
+    TerminalMode(IoError),
+    StackViolation(String),
+    InvalidArgument(String),
+    InfiniteLoop(String),
+    InstructionLimit(String),
+    Debugger(String),
+    Assembly(String),
+    Link(String),
+    InvalidTrace(String),
+    TraceMismatch(String),
+    BatchFailed(String),
+    Timeout(String),
+    OutputLimit(String),
+    /// Two devices claim the same address
+    DeviceConflict(String),
+}
+
+impl fmt::Display for VMError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VMError::OpenFile(error) => {
+                f.write_str("Error opening file: ")?;
+                fmt::Display::fmt(error, f)
+            }
+            VMError::ReadFile(error) => {
+                f.write_str("Error reading file: ")?;
+                fmt::Display::fmt(error, f)
+            }
+            VMError::InvalidImage(msg) => write!(f, "Invalid image: {msg}"),
+            VMError::MemoryIndex(msg) => write!(f, "Memory error: {msg}"),
+            VMError::AddressOverflow(msg) => write!(f, "Address overflow: {msg}"),
+            VMError::InvalidOpcode(msg) => write!(f, "Invalid opcode: {msg}"),
+            VMError::InvalidTrapCode(msg) => write!(f, "Invalid trap code: {msg}"),
+            VMError::InvalidCharacter(msg) => write!(f, "Invalid character: {msg}"),
+            VMError::StandardIO(error) => {
+                f.write_str("I/O error: ")?;
+                fmt::Display::fmt(error, f)
+            }
+            VMError::TerminalMode(error) => {
+                f.write_str("Terminal error: ")?;
+                fmt::Display::fmt(error, f)
+            }
+            VMError::StackViolation(msg) => write!(f, "Stack violation: {msg}"),
+            VMError::InvalidArgument(msg) => write!(f, "{msg}"),
+            VMError::InfiniteLoop(msg) => write!(f, "Infinite loop: {msg}"),
+            VMError::InstructionLimit(msg) => write!(f, "Instruction limit reached: {msg}"),
+            VMError::Debugger(msg) => write!(f, "Debugger error: {msg}"),
+            VMError::Assembly(msg) => write!(f, "Assembly error: {msg}"),
+            VMError::Link(msg) => write!(f, "Link error: {msg}"),
+            VMError::InvalidTrace(msg) => write!(f, "Invalid trace: {msg}"),
+            VMError::TraceMismatch(msg) => write!(f, "Trace mismatch: {msg}"),
+            VMError::BatchFailed(msg) => write!(f, "Batch failed: {msg}"),
+            VMError::Timeout(msg) => write!(f, "{msg}"),
+            VMError::OutputLimit(msg) => write!(f, "Output limit reached: {msg}"),
+            VMError::DeviceConflict(msg) => write!(f, "Device conflict: {msg}"),
+        }
+    }
+}
+
+impl Error for VMError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            VMError::OpenFile(error)
+            | VMError::ReadFile(error)
+            | VMError::StandardIO(error)
+            | VMError::TerminalMode(error) => error.source(),
+            _ => None,
+        }
+    }
+}
+
+/// Message of a failed I/O operation, with the `io::Error` that caused it
+/// when there is one
+#[derive(Debug, Clone)]
+pub struct IoError {
+    message: String,
+    #[cfg(feature = "std")]
+    source: Option<Arc<std::io::Error>>,
+}
+
+impl IoError {
+    pub fn new(message: impl Into<String>) -> Self {
+        IoError {
+            message: message.into(),
+            #[cfg(feature = "std")]
+            source: None,
+        }
+    }
+
+    /// `message` describes what was attempted, e.g. `Could not open x.obj`
+    #[cfg(feature = "std")]
+    pub fn caused_by(message: impl Into<String>, source: std::io::Error) -> Self {
+        IoError {
+            message: message.into(),
+            source: Some(Arc::new(source)),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Kind of the underlying I/O error
+    #[cfg(feature = "std")]
+    pub fn kind(&self) -> Option<std::io::ErrorKind> {
+        self.source.as_ref().map(|source| source.kind())
+    }
+
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        #[cfg(feature = "std")]
+        if let Some(source) = &self.source {
+            return Some(source.as_ref());
+        }
+        None
+    }
+}
+
+/// Errors compare by message and, for I/O errors, their kind
+impl PartialEq for IoError {
+    fn eq(&self, other: &Self) -> bool {
+        #[cfg(feature = "std")]
+        if self.kind() != other.kind() {
+            return false;
+        }
+        self.message == other.message
+    }
+}
+
+impl fmt::Display for IoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        match self.source() {
+            Some(source) if f.alternate() => write!(f, ": {source}"),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl From<String> for IoError {
+    fn from(message: String) -> Self {
+        IoError::new(message)
+    }
+}
+
+impl From<&str> for IoError {
+    fn from(message: &str) -> Self {
+        IoError::new(message)
+    }
+}
@@ -0,0 +1,256 @@
+//! Plain-English explanations of executed instructions for students
+//! learning the ISA, e.g.
+//!
+//! ```text
+//! ADD: R0 ← R1 + #2 = x0001 + x0002 = x0003; condition codes set to P
+//! LDR: R2 ← mem[R6 + #1] = mem[x4001] = x0041; condition codes set to P
+//! BRz: the condition codes are P, so the branch to x3008 is not taken
+//! ```
+//!
+//! An `Explainer` observer hands one to a callback after every instruction,
+//! computed from the decoded `Instruction`, the registers before and after
+//! it and the memory it accessed.
+
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+
+use crate::{
+    instructions::{Instruction, JsrTarget, Operand, TrapCode},
+    observer::Observer,
+    register::Register,
+    vm::{Psr, VM},
+};
+
+/// Observer explaining every executed instruction to a callback
+pub struct Explainer {
+    sink: Box<dyn FnMut(&str)>,
+    /// Registers before the current instruction
+    registers: [u16; 8],
+    psr: Psr,
+    /// Data memory the instruction read and wrote, in order
+    reads: Vec<(u16, u16)>,
+    writes: Vec<(u16, u16)>,
+    /// Whether a trap is running, whose own accesses are not explained
+    in_trap: bool,
+}
+
+impl Explainer {
+    pub fn new<F>(sink: F) -> Self
+    where
+        F: FnMut(&str) + 'static,
+    {
+        Explainer {
+            sink: Box::new(sink),
+            registers: [0; 8],
+            psr: Psr::default(),
+            reads: Vec::new(),
+            writes: Vec::new(),
+            in_trap: false,
+        }
+    }
+}
+
+impl Observer for Explainer {
+    fn before_instruction(&mut self, vm: &VM, _pc: u16, _raw: u16) {
+        self.registers = vm.registers();
+        self.psr = vm.psr();
+        self.reads.clear();
+        self.writes.clear();
+        self.in_trap = false;
+    }
+
+    fn after_instruction(&mut self, vm: &VM, pc: u16, raw: u16) {
+        let step = Step {
+            pc,
+            before: self.registers,
+            psr: self.psr,
+            reads: &self.reads,
+            writes: &self.writes,
+        };
+        let explanation = step.explain(vm, &Instruction::decode(raw));
+        (self.sink)(&explanation);
+    }
+
+    fn on_mem_read(&mut self, address: u16, value: u16) {
+        if !self.in_trap {
+            self.reads.push((address, value));
+        }
+    }
+
+    fn on_mem_write(&mut self, address: u16, value: u16) {
+        if !self.in_trap {
+            self.writes.push((address, value));
+        }
+    }
+
+    fn on_trap(&mut self, _vector: u16) {
+        self.in_trap = true;
+    }
+}
+
+/// What an instruction saw, to explain it
+struct Step<'a> {
+    pc: u16,
+    before: [u16; 8],
+    psr: Psr,
+    reads: &'a [(u16, u16)],
+    writes: &'a [(u16, u16)],
+}
+
+impl Step<'_> {
+    fn explain(&self, vm: &VM, instruction: &Instruction) -> String {
+        let next = self.pc.wrapping_add(1);
+        let target = |offset: i16| next.wrapping_add_signed(offset);
+        let flags = || format!("; condition codes set to {}", vm.psr().condition_letter());
+        match *instruction {
+            Instruction::Add { dr, sr1, operand } | Instruction::And { dr, sr1, operand } => {
+                let (name, symbol) = match instruction {
+                    Instruction::Add { .. } => ("ADD", "+"),
+                    _ => ("AND", "AND"),
+                };
+                let (operand, value) = match operand {
+                    Operand::Register(sr2) => (format!("{sr2}"), self.before(sr2)),
+                    Operand::Immediate(immediate) => (
+                        format!("#{immediate}"),
+                        u16::from_ne_bytes(immediate.to_ne_bytes()),
+                    ),
+                };
+                format!(
+                    "{name}: {dr} ← {sr1} {symbol} {operand} = x{:04X} {symbol} x{value:04X} = x{:04X}{}",
+                    self.before(sr1),
+                    vm.register(dr),
+                    flags()
+                )
+            }
+            Instruction::Not { dr, sr } => format!(
+                "NOT: {dr} ← NOT {sr} = NOT x{:04X} = x{:04X}{}",
+                self.before(sr),
+                vm.register(dr),
+                flags()
+            ),
+            Instruction::Br { n, z, p, pc_offset } => {
+                let tested: String = [(n, 'n'), (z, 'z'), (p, 'p')]
+                    .iter()
+                    .filter(|(tested, _)| *tested)
+                    .map(|(_, letter)| letter)
+                    .collect();
+                let destination = target(pc_offset);
+                if n && z && p {
+                    return format!("BR{tested}: always branches, to x{destination:04X}");
+                }
+                if tested.is_empty() {
+                    return String::from("BR: tests no condition code, so it never branches");
+                }
+                let taken = (n && self.psr.negative())
+                    || (z && self.psr.zero())
+                    || (p && self.psr.positive());
+                format!(
+                    "BR{tested}: the condition codes are {}, so the branch to x{destination:04X} is {}",
+                    self.psr.condition_letter(),
+                    if taken { "taken" } else { "not taken" }
+                )
+            }
+            Instruction::Ld { dr, .. } => match self.reads.first() {
+                Some(&(address, value)) => {
+                    format!("LD: {dr} ← mem[x{address:04X}] = x{value:04X}{}", flags())
+                }
+                None => format!("LD: {dr} ← x{:04X}{}", vm.register(dr), flags()),
+            },
+            Instruction::Ldi { dr, .. } => match self.reads {
+                [(pointer, address), (_, value), ..] => format!(
+                    "LDI: {dr} ← mem[mem[x{pointer:04X}]] = mem[x{address:04X}] = x{value:04X}{}",
+                    flags()
+                ),
+                _ => format!("LDI: {dr} ← x{:04X}{}", vm.register(dr), flags()),
+            },
+            Instruction::Ldr { dr, base, offset } => {
+                let address = self.before(base).wrapping_add_signed(offset);
+                format!(
+                    "LDR: {dr} ← mem[{base} + #{offset}] = mem[x{address:04X}] = x{:04X}{}",
+                    vm.register(dr),
+                    flags()
+                )
+            }
+            Instruction::Lea { dr, pc_offset } => {
+                let flags = if vm.conformance().lea_sets_flags {
+                    flags()
+                } else {
+                    String::from("; condition codes unchanged")
+                };
+                format!("LEA: {dr} ← x{:04X}{flags}", target(pc_offset))
+            }
+            Instruction::St { sr, pc_offset } => format!(
+                "ST: mem[x{:04X}] ← {sr} = x{:04X}",
+                target(pc_offset),
+                self.before(sr)
+            ),
+            Instruction::Sti { sr, pc_offset } => {
+                let address = self.writes.first().map_or(0, |&(address, _)| address);
+                format!(
+                    "STI: mem[mem[x{:04X}]] = mem[x{address:04X}] ← {sr} = x{:04X}",
+                    target(pc_offset),
+                    self.before(sr)
+                )
+            }
+            Instruction::Str { sr, base, offset } => format!(
+                "STR: mem[{base} + #{offset}] = mem[x{:04X}] ← {sr} = x{:04X}",
+                self.before(base).wrapping_add_signed(offset),
+                self.before(sr)
+            ),
+            Instruction::Jmp { base } if base == Register::R7 => format!(
+                "RET: PC ← R7 = x{:04X}, returning from the subroutine",
+                vm.pc()
+            ),
+            Instruction::Jmp { base } => format!("JMP: PC ← {base} = x{:04X}", vm.pc()),
+            Instruction::Jsr { target: jsr } => {
+                let (name, destination) = match jsr {
+                    JsrTarget::Offset(offset) => ("JSR", format!("x{:04X}", target(offset))),
+                    JsrTarget::Register(base) => {
+                        ("JSRR", format!("{base} = x{:04X}", self.before(base)))
+                    }
+                };
+                format!("{name}: R7 ← x{next:04X}, the return address, then PC ← {destination}")
+            }
+            Instruction::Trap { trap_vector } => {
+                let action = match TrapCode::try_from(trap_vector) {
+                    Ok(TrapCode::Getc) => format!(
+                        " (GETC): reads a key into R0 = x{:04X} without echoing it",
+                        vm.register(Register::R0)
+                    ),
+                    Ok(TrapCode::Out) => {
+                        format!(
+                            " (OUT): prints the character in R0 = x{:04X}",
+                            self.before(Register::R0)
+                        )
+                    }
+                    Ok(TrapCode::Puts) => format!(
+                        " (PUTS): prints the string of one character per word at R0 = x{:04X}",
+                        self.before(Register::R0)
+                    ),
+                    Ok(TrapCode::In) => format!(
+                        " (IN): prompts for a key, echoes it and reads it into R0 = x{:04X}",
+                        vm.register(Register::R0)
+                    ),
+                    Ok(TrapCode::Putsp) => format!(
+                        " (PUTSP): prints the string of two characters per word at R0 = x{:04X}",
+                        self.before(Register::R0)
+                    ),
+                    Ok(TrapCode::Halt) => String::from(" (HALT): stops the machine"),
+                    Err(_) => String::from(": runs a service routine of the operating system"),
+                };
+                format!("TRAP x{trap_vector:02X}{action}")
+            }
+            Instruction::Rti => format!(
+                "RTI: returns from the interrupt or trap, PC ← x{:04X}",
+                vm.pc()
+            ),
+            Instruction::Res => String::from("RES: the reserved opcode, which does nothing"),
+        }
+    }
+
+    fn before(&self, register: Register) -> u16 {
+        self.before
+            .get(register.index())
+            .copied()
+            .unwrap_or_default()
+    }
+}
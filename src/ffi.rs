@@ -0,0 +1,248 @@
+//! C API for embedding the VM in C and C++ programs. The matching header is
+//! `include/lc3_vm.h`, regenerated with
+//! `cbindgen --config cbindgen.toml src/ffi.rs --output include/lc3_vm.h`.
+//!
+//! ```c
+//! static void put(void *user_data, uint8_t byte) { putchar(byte); }
+//!
+//! Lc3Vm *vm = lc3_vm_new();
+//! lc3_vm_set_io(vm, NULL, NULL, put);
+//! if (lc3_vm_load(vm, image, image_len) != LC3_STATUS_OK)
+//!     fprintf(stderr, "%s\n", lc3_vm_last_error(vm));
+//! while (lc3_vm_step(vm, 10000) == LC3_STATUS_OK) {}
+//! lc3_vm_free(vm);
+//! ```
+
+use alloc::{boxed::Box, ffi::CString, format, string::String};
+use core::{
+    ffi::{c_char, c_void},
+    ptr, slice,
+};
+
+use crate::{
+    console::{Console, NullConsole},
+    errors::{IoError, VMError},
+    register::Register,
+    vm::VM,
+};
+
+/// Result of the fallible calls
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lc3Status {
+    Ok = 0,
+    /// The program executed HALT
+    Halted = 1,
+    /// The call failed, `lc3_vm_last_error` describes why
+    Error = -1,
+}
+
+/// Returns the next key, or a negative value if there is none. With `block`
+/// set the VM needs a key to go on (GETC or IN), so returning none stops
+/// the program with an error.
+pub type Lc3ReadKey = Option<extern "C" fn(user_data: *mut c_void, block: bool) -> i32>;
+
+/// Receives a character written by the program
+pub type Lc3WriteChar = Option<extern "C" fn(user_data: *mut c_void, character: u8)>;
+
+/// VM handle owned by the C side
+pub struct Lc3Vm {
+    vm: VM,
+    last_error: Option<CString>,
+}
+
+impl Lc3Vm {
+    fn record(&mut self, result: Result<Lc3Status, VMError>) -> Lc3Status {
+        match result {
+            Ok(status) => status,
+            Err(error) => {
+                let message = format!("{error:#}").replace('\0', " ");
+                self.last_error = CString::new(message).ok();
+                Lc3Status::Error
+            }
+        }
+    }
+}
+
+/// Console forwarding input and output to the callbacks of the embedder
+struct CallbackConsole {
+    user_data: *mut c_void,
+    read_key: Lc3ReadKey,
+    write_char: Lc3WriteChar,
+}
+
+impl CallbackConsole {
+    fn key(&self, block: bool) -> Option<u8> {
+        let read_key = self.read_key?;
+        u8::try_from(read_key(self.user_data, block)).ok()
+    }
+}
+
+impl Console for CallbackConsole {
+    fn read_key(&mut self) -> Result<u8, VMError> {
+        self.key(true)
+            .ok_or_else(|| VMError::StandardIO(IoError::new("No input is available")))
+    }
+
+    fn poll_key(&mut self) -> Result<Option<u8>, VMError> {
+        Ok(self.key(false))
+    }
+
+    fn write_char(&mut self, character: char) -> Result<(), VMError> {
+        if let Some(write_char) = self.write_char {
+            write_char(self.user_data, u8::try_from(character).unwrap_or(b'?'));
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), VMError> {
+        Ok(())
+    }
+}
+
+/// Creates a VM without input or output until `lc3_vm_set_io` is called.
+/// Release it with `lc3_vm_free`.
+#[no_mangle]
+pub extern "C" fn lc3_vm_new() -> *mut Lc3Vm {
+    let mut vm = VM::new();
+    vm.set_console(Box::new(NullConsole));
+    Box::into_raw(Box::new(Lc3Vm {
+        vm,
+        last_error: None,
+    }))
+}
+
+/// # Safety
+///
+/// `vm` must come from `lc3_vm_new` and not be used afterwards. NULL is
+/// ignored.
+#[no_mangle]
+pub unsafe extern "C" fn lc3_vm_free(vm: *mut Lc3Vm) {
+    if !vm.is_null() {
+        drop(Box::from_raw(vm));
+    }
+}
+
+/// Loads a big endian object file whose first word is the origin
+///
+/// # Safety
+///
+/// `vm` must come from `lc3_vm_new` and `bytes` must point to `len`
+/// readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn lc3_vm_load(vm: *mut Lc3Vm, bytes: *const u8, len: usize) -> Lc3Status {
+    let Some(handle) = vm.as_mut() else {
+        return Lc3Status::Error;
+    };
+    let result = if bytes.is_null() {
+        Err(VMError::InvalidImage(String::from("The image is NULL")))
+    } else {
+        let image = slice::from_raw_parts(bytes, len);
+        handle.vm.read_image_bytes(image).map(|()| Lc3Status::Ok)
+    };
+    handle.record(result)
+}
+
+/// Routes keyboard input and program output through callbacks, either of
+/// which may be NULL. `user_data` is passed back to them unchanged.
+///
+/// # Safety
+///
+/// `vm` must come from `lc3_vm_new`, and the callbacks must stay callable
+/// with `user_data` for as long as the VM runs.
+#[no_mangle]
+pub unsafe extern "C" fn lc3_vm_set_io(
+    vm: *mut Lc3Vm,
+    user_data: *mut c_void,
+    read_key: Lc3ReadKey,
+    write_char: Lc3WriteChar,
+) {
+    if let Some(handle) = vm.as_mut() {
+        handle.vm.set_console(Box::new(CallbackConsole {
+            user_data,
+            read_key,
+            write_char,
+        }));
+    }
+}
+
+/// Executes up to `count` instructions. Returns `LC3_STATUS_HALTED` once
+/// the program halted.
+///
+/// # Safety
+///
+/// `vm` must come from `lc3_vm_new`.
+#[no_mangle]
+pub unsafe extern "C" fn lc3_vm_step(vm: *mut Lc3Vm, count: u32) -> Lc3Status {
+    let Some(handle) = vm.as_mut() else {
+        return Lc3Status::Error;
+    };
+    let result = handle.vm.run_with_fuel(u64::from(count)).map(|poll| {
+        if poll.is_ready() {
+            Lc3Status::Halted
+        } else {
+            Lc3Status::Ok
+        }
+    });
+    handle.record(result)
+}
+
+/// Value of register R`number`, or 0 if there is no such register
+///
+/// # Safety
+///
+/// `vm` must come from `lc3_vm_new`.
+#[no_mangle]
+pub unsafe extern "C" fn lc3_vm_read_reg(vm: *const Lc3Vm, number: u16) -> u16 {
+    let Some(handle) = vm.as_ref() else {
+        return 0;
+    };
+    Register::new(number)
+        .map(|register| handle.vm.register(register))
+        .unwrap_or_default()
+}
+
+/// # Safety
+///
+/// `vm` must come from `lc3_vm_new`.
+#[no_mangle]
+pub unsafe extern "C" fn lc3_vm_pc(vm: *const Lc3Vm) -> u16 {
+    vm.as_ref().map(|handle| handle.vm.pc()).unwrap_or_default()
+}
+
+/// Reads memory without triggering device side effects
+///
+/// # Safety
+///
+/// `vm` must come from `lc3_vm_new`.
+#[no_mangle]
+pub unsafe extern "C" fn lc3_vm_read_mem(vm: *const Lc3Vm, address: u16) -> u16 {
+    vm.as_ref()
+        .map(|handle| handle.vm.peek(address))
+        .unwrap_or_default()
+}
+
+/// # Safety
+///
+/// `vm` must come from `lc3_vm_new`.
+#[no_mangle]
+pub unsafe extern "C" fn lc3_vm_write_mem(vm: *mut Lc3Vm, address: u16, value: u16) -> Lc3Status {
+    let Some(handle) = vm.as_mut() else {
+        return Lc3Status::Error;
+    };
+    let result = handle.vm.poke(address, value).map(|()| Lc3Status::Ok);
+    handle.record(result)
+}
+
+/// Description of the last error, valid until the next failing call, or
+/// NULL if nothing failed yet
+///
+/// # Safety
+///
+/// `vm` must come from `lc3_vm_new`.
+#[no_mangle]
+pub unsafe extern "C" fn lc3_vm_last_error(vm: *const Lc3Vm) -> *const c_char {
+    vm.as_ref()
+        .and_then(|handle| handle.last_error.as_ref())
+        .map_or(ptr::null(), |message| message.as_ptr())
+}
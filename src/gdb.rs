@@ -0,0 +1,574 @@
+//! gdbserver compatible stub speaking the GDB remote serial protocol over
+//! TCP.
+//!
+//! gdb addresses memory in bytes, so LC-3 word `xNNNN` is exposed as the two
+//! little endian bytes at `2 * xNNNN`. The PC register and breakpoints use
+//! the same byte addresses. Watchpoints are only supported on the device
+//! registers, where they stop after the accessing instruction. Memory is
+//! written with `M` or the binary `X` packets. The register file is R0-R7
+//! (16 bits), PC (32 bits, to fit byte addresses) and the PSR, whose bits
+//! 2:0 are the condition codes.
+//!
+//! `serve` waits for gdb and runs the session quietly; front ends telling
+//! their users where to connect call `listen`, `accept` and
+//! `serve_connection` themselves.
+
+use std::{
+    io::{BufReader, ErrorKind, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+};
+
+use crate::{
+    errors::{IoError, VMError},
+    memory::MMIO_START,
+    register::Register,
+    vm::{DeviceAccess, Psr, StopReason, VM},
+};
+
+/// How many instructions run between checks for an interrupt from gdb
+const INTERRUPT_CHECK_INTERVAL: u32 = 4096;
+/// Byte gdb sends to interrupt a running target (Ctrl-C)
+const INTERRUPT: u8 = 0x03;
+/// Byte before an escaped byte of a packet, which follows XORed with 0x20
+const ESCAPE: u8 = b'}';
+/// R0-R7, PC and PSR
+const REGISTER_COUNT: usize = 10;
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.lc3.core">
+    <reg name="r0" bitsize="16" type="int"/>
+    <reg name="r1" bitsize="16" type="int"/>
+    <reg name="r2" bitsize="16" type="int"/>
+    <reg name="r3" bitsize="16" type="int"/>
+    <reg name="r4" bitsize="16" type="int"/>
+    <reg name="r5" bitsize="16" type="int"/>
+    <reg name="r6" bitsize="16" type="data_ptr"/>
+    <reg name="r7" bitsize="16" type="code_ptr"/>
+    <reg name="pc" bitsize="32" type="code_ptr"/>
+    <reg name="psr" bitsize="16" type="int"/>
+  </feature>
+</target>
+"#;
+
+/// Waits for gdb to connect on `address` (e.g. `127.0.0.1:1234`) and serves
+/// a single debugging session
+pub fn serve(vm: &mut VM, address: &str) -> Result<(), VMError> {
+    let (stream, _) = accept(&listen(address)?)?;
+    serve_connection(vm, stream)
+}
+
+/// Listens for gdb on `address`, for hosts telling their users where to
+/// connect before waiting with `accept`
+pub fn listen(address: &str) -> Result<TcpListener, VMError> {
+    TcpListener::bind(address).map_err(|e| {
+        VMError::StandardIO(IoError::caused_by(
+            format!("Could not listen on {address}"),
+            e,
+        ))
+    })
+}
+
+/// Waits for gdb to connect to `listener`, returning the connection and
+/// where it comes from
+pub fn accept(listener: &TcpListener) -> Result<(TcpStream, SocketAddr), VMError> {
+    listener
+        .accept()
+        .map_err(|e| VMError::StandardIO(IoError::caused_by("Could not accept gdb connection", e)))
+}
+
+/// Serves a debugging session to gdb connected through `stream`, until it
+/// detaches, kills the program or disconnects
+pub fn serve_connection(vm: &mut VM, stream: TcpStream) -> Result<(), VMError> {
+    GdbStub::new(vm, stream)?.run()
+}
+
+struct GdbStub<'a> {
+    vm: &'a mut VM,
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+/// What the session loop does after handling a packet
+enum Reply {
+    Packet(String),
+    Close,
+}
+
+impl<'a> GdbStub<'a> {
+    fn new(vm: &'a mut VM, stream: TcpStream) -> Result<Self, VMError> {
+        let writer = stream.try_clone().map_err(io_error)?;
+        vm.set_undo(true);
+        Ok(GdbStub {
+            vm,
+            reader: BufReader::new(stream),
+            writer,
+        })
+    }
+
+    fn run(&mut self) -> Result<(), VMError> {
+        while let Some(packet) = self.read_packet()? {
+            match self.handle(&packet)? {
+                Reply::Packet(reply) => self.write_packet(&reply)?,
+                Reply::Close => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the next `$data#checksum` packet, acknowledging it, and returns
+    /// its data with escaped bytes restored. Returns `None` once gdb
+    /// disconnects.
+    fn read_packet(&mut self) -> Result<Option<Vec<u8>>, VMError> {
+        loop {
+            let Some(byte) = self.read_byte()? else {
+                return Ok(None);
+            };
+            if byte != b'$' {
+                // acknowledgements and stray interrupts while stopped
+                continue;
+            }
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                    None => return Ok(None),
+                }
+            }
+            let mut checksum = [0; 2];
+            self.reader.read_exact(&mut checksum).map_err(io_error)?;
+            let expected = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|text| u8::from_str_radix(text, 16).ok());
+            if expected != Some(checksum_of(&data)) {
+                self.writer.write_all(b"-").map_err(io_error)?;
+                continue;
+            }
+            self.writer.write_all(b"+").map_err(io_error)?;
+            return Ok(Some(unescape(&data)));
+        }
+    }
+
+    fn read_byte(&mut self) -> Result<Option<u8>, VMError> {
+        let mut byte = [0];
+        match self.reader.read(&mut byte) {
+            Ok(0) => Ok(None),
+            Ok(_) => Ok(Some(u8::from_ne_bytes(byte))),
+            Err(e) if e.kind() == ErrorKind::ConnectionReset => Ok(None),
+            Err(e) => Err(io_error(e)),
+        }
+    }
+
+    fn write_packet(&mut self, data: &str) -> Result<(), VMError> {
+        let packet = format!("${data}#{:02x}", checksum_of(data.as_bytes()));
+        self.writer
+            .write_all(packet.as_bytes())
+            .and_then(|()| self.writer.flush())
+            .map_err(io_error)
+    }
+
+    fn handle(&mut self, packet: &[u8]) -> Result<Reply, VMError> {
+        if let Some(data) = packet.strip_prefix(b"X") {
+            return Ok(Reply::Packet(self.write_binary(data)?));
+        }
+        let packet = String::from_utf8_lossy(packet);
+        let (command, arguments) = packet.split_at_checked(1).unwrap_or((&packet, ""));
+        let reply = match (command, arguments) {
+            ("?", _) => format!("S{SIGTRAP:02x}"),
+            ("g", _) => self.read_registers(),
+            ("G", values) => self.write_registers(values),
+            ("p", number) => self.read_register(number),
+            ("P", assignment) => self.write_register(assignment),
+            ("m", range) => self.read_memory(range),
+            ("M", data) => self.write_memory(data)?,
+            ("Z", breakpoint) => self.set_breakpoint(breakpoint, true),
+            ("z", breakpoint) => self.set_breakpoint(breakpoint, false),
+            ("c", address) => {
+                self.jump(address);
+                self.resume()?
+            }
+            ("s", address) => {
+                self.jump(address);
+                let stop = self.vm.step();
+                self.stop_reply(stop)?
+            }
+            // reverse-stepi, back over the last instruction only
+            ("b", "s") => match self.vm.undo() {
+                Ok(()) => format!("S{SIGTRAP:02x}"),
+                Err(_) => format!("T{SIGTRAP:02x}replaylog:begin;"),
+            },
+            ("H", _) => String::from("OK"),
+            ("D", _) => {
+                self.write_packet("OK")?;
+                return Ok(Reply::Close);
+            }
+            ("k", _) => return Ok(Reply::Close),
+            ("q", query) => self.query(query),
+            _ => String::new(),
+        };
+        Ok(Reply::Packet(reply))
+    }
+
+    fn query(&self, query: &str) -> String {
+        if query.starts_with("Supported") {
+            String::from("PacketSize=4000;qXfer:features:read+;swbreak+;hwbreak+;ReverseStep+")
+        } else if query == "Attached" {
+            String::from("1")
+        } else if let Some(range) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            transfer_chunk(TARGET_XML, range)
+        } else {
+            String::new()
+        }
+    }
+
+    fn read_registers(&self) -> String {
+        (0..REGISTER_COUNT)
+            .filter_map(|number| self.register_hex(number))
+            .collect()
+    }
+
+    fn write_registers(&mut self, values: &str) -> String {
+        let mut rest = values;
+        for number in 0..REGISTER_COUNT {
+            let width = register_width(number);
+            let Some((value, remaining)) = rest.split_at_checked(width) else {
+                return String::from("E01");
+            };
+            let Some(value) = decode_le(value) else {
+                return String::from("E01");
+            };
+            self.set_register_value(number, value);
+            rest = remaining;
+        }
+        String::from("OK")
+    }
+
+    fn read_register(&self, number: &str) -> String {
+        usize::from_str_radix(number, 16)
+            .ok()
+            .and_then(|number| self.register_hex(number))
+            .unwrap_or_else(|| String::from("E01"))
+    }
+
+    fn write_register(&mut self, assignment: &str) -> String {
+        let parsed = assignment.split_once('=').and_then(|(number, value)| {
+            Some((usize::from_str_radix(number, 16).ok()?, decode_le(value)?))
+        });
+        match parsed {
+            Some((number, value)) if number < REGISTER_COUNT => {
+                self.set_register_value(number, value);
+                String::from("OK")
+            }
+            _ => String::from("E01"),
+        }
+    }
+
+    /// Register `number` as target (little endian) hex
+    fn register_hex(&self, number: usize) -> Option<String> {
+        let value = match number {
+            0..=7 => u32::from(
+                self.vm
+                    .register(Register::new(u16::try_from(number).ok()?)?),
+            ),
+            8 => byte_address(self.vm.pc()),
+            9 => u32::from(self.vm.psr().bits()),
+            _ => return None,
+        };
+        let bytes = value.to_le_bytes();
+        let width = register_width(number) / 2;
+        Some(
+            bytes
+                .iter()
+                .take(width)
+                .map(|byte| format!("{byte:02x}"))
+                .collect(),
+        )
+    }
+
+    fn set_register_value(&mut self, number: usize, value: u32) {
+        let low = u16::try_from(value & 0xFFFF).unwrap_or_default();
+        match number {
+            0..=7 => {
+                let register = u16::try_from(number).ok().and_then(Register::new);
+                if let Some(register) = register {
+                    self.vm.set_register(register, low);
+                }
+            }
+            8 => self.vm.set_pc(word_address(value)),
+            9 => self.vm.set_psr(Psr::from_bits(low)),
+            _ => {}
+        }
+    }
+
+    fn read_memory(&self, range: &str) -> String {
+        let Some((start, length)) = parse_range(range) else {
+            return String::from("E01");
+        };
+        let mut hex = String::new();
+        for offset in 0..length {
+            let Some(byte) = start
+                .checked_add(offset)
+                .and_then(|address| self.byte_at(address))
+            else {
+                break;
+            };
+            hex.push_str(&format!("{byte:02x}"));
+        }
+        if hex.is_empty() && length > 0 {
+            String::from("E01")
+        } else {
+            hex
+        }
+    }
+
+    fn write_memory(&mut self, data: &str) -> Result<String, VMError> {
+        let parsed = data
+            .split_once(':')
+            .and_then(|(range, bytes)| Some((parse_range(range)?, decode_bytes(bytes)?)));
+        let Some(((start, _), bytes)) = parsed else {
+            return Ok(String::from("E01"));
+        };
+        self.write_bytes(start, &bytes)
+    }
+
+    /// Handles `X`, which writes memory like `M` with the bytes themselves
+    /// instead of in hex
+    fn write_binary(&mut self, data: &[u8]) -> Result<String, VMError> {
+        let Some(colon) = data.iter().position(|&byte| byte == b':') else {
+            return Ok(String::from("E01"));
+        };
+        let (range, bytes) = data.split_at(colon);
+        let range = std::str::from_utf8(range).ok().and_then(parse_range);
+        let bytes = bytes.get(1..).unwrap_or_default();
+        match range {
+            Some((start, length)) if usize::try_from(length).ok() == Some(bytes.len()) => {
+                self.write_bytes(start, bytes)
+            }
+            _ => Ok(String::from("E01")),
+        }
+    }
+
+    fn write_bytes(&mut self, start: u32, bytes: &[u8]) -> Result<String, VMError> {
+        for (address, &byte) in (start..).zip(bytes) {
+            let Ok(word_index) = u16::try_from(address >> 1) else {
+                return Ok(String::from("E01"));
+            };
+            let [low, high] = self.vm.peek(word_index).to_le_bytes();
+            let word = if address & 1 == 0 {
+                u16::from_le_bytes([byte, high])
+            } else {
+                u16::from_le_bytes([low, byte])
+            };
+            self.vm.poke(word_index, word)?;
+        }
+        Ok(String::from("OK"))
+    }
+
+    fn byte_at(&self, address: u32) -> Option<u8> {
+        let word = self.vm.peek(u16::try_from(address >> 1).ok()?);
+        let [low, high] = word.to_le_bytes();
+        Some(if address & 1 == 0 { low } else { high })
+    }
+
+    /// Handles `Z`/`z` packets for software and hardware breakpoints
+    fn set_breakpoint(&mut self, breakpoint: &str, insert: bool) -> String {
+        let mut fields = breakpoint.split(',');
+        let (Some(kind), Some(address)) = (fields.next(), fields.next()) else {
+            return String::from("E01");
+        };
+        let access = match kind {
+            "0" | "1" => None,
+            "2" => Some(DeviceAccess::Write),
+            "3" => Some(DeviceAccess::Read),
+            "4" => Some(DeviceAccess::Any),
+            _ => return String::new(),
+        };
+        let Ok(address) = u32::from_str_radix(address, 16) else {
+            return String::from("E01");
+        };
+        let address = word_address(address);
+        if let Some(access) = access {
+            // gdb falls back to software watchpoints on memory
+            if address < MMIO_START {
+                return String::new();
+            }
+            if insert {
+                let _ = self.vm.add_device_break(address, access);
+            } else {
+                self.vm.remove_device_break(address);
+            }
+        } else if insert {
+            self.vm.add_breakpoint(address);
+        } else {
+            self.vm.remove_breakpoint(address);
+        }
+        String::from("OK")
+    }
+
+    /// Moves the PC if `c`/`s` carried an address to resume from
+    fn jump(&mut self, address: &str) {
+        if let Ok(address) = u32::from_str_radix(address, 16) {
+            self.vm.set_pc(word_address(address));
+        }
+    }
+
+    /// Runs until a breakpoint, a trap or exception the VM breaks on, HALT,
+    /// an error or an interrupt from gdb
+    fn resume(&mut self) -> Result<String, VMError> {
+        let mut until_check = INTERRUPT_CHECK_INTERVAL;
+        loop {
+            match self.vm.step() {
+                Ok(StopReason::Step) => {}
+                stop => return self.stop_reply(stop),
+            }
+            if let Some(stop) = self.vm.pending_stop() {
+                return self.stop_reply(Ok(stop));
+            }
+            until_check = until_check.saturating_sub(1);
+            if until_check == 0 {
+                until_check = INTERRUPT_CHECK_INTERVAL;
+                if self.interrupted()? {
+                    return Ok(format!("S{SIGINT:02x}"));
+                }
+            }
+        }
+    }
+
+    /// Checks without blocking whether gdb sent an interrupt
+    fn interrupted(&mut self) -> Result<bool, VMError> {
+        let stream = self.reader.get_mut();
+        stream.set_nonblocking(true).map_err(io_error)?;
+        let mut byte = [0];
+        let received = match stream.read(&mut byte) {
+            Ok(1) => byte == [INTERRUPT],
+            Ok(_) => false,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => false,
+            Err(e) => return Err(io_error(e)),
+        };
+        stream.set_nonblocking(false).map_err(io_error)?;
+        Ok(received)
+    }
+
+    /// Stop packet for the result of running the program. Errors are shown
+    /// in gdb's console and reported as an illegal instruction.
+    fn stop_reply(&mut self, stop: Result<StopReason, VMError>) -> Result<String, VMError> {
+        match stop {
+            Ok(StopReason::Halted) => Ok(String::from("W00")),
+            Ok(StopReason::Break) => Ok(format!("S{SIGINT:02x}")),
+            Ok(
+                StopReason::Step
+                | StopReason::Breakpoint(_)
+                | StopReason::Trap(_)
+                | StopReason::Interrupt(_),
+            ) => Ok(format!("S{SIGTRAP:02x}")),
+            Ok(StopReason::DeviceAccess { address, write }) => {
+                let access = self
+                    .vm
+                    .device_breaks()
+                    .find(|&(watched, _)| watched == address)
+                    .map(|(_, access)| access);
+                let kind = match access {
+                    Some(DeviceAccess::Any) => "awatch",
+                    _ if write => "watch",
+                    _ => "rwatch",
+                };
+                Ok(format!("T{SIGTRAP:02x}{kind}:{:x};", byte_address(address)))
+            }
+            Err(error) => {
+                let message = format!("{error:#}\n");
+                let hex: String = message.bytes().map(|byte| format!("{byte:02x}")).collect();
+                self.write_packet(&format!("O{hex}"))?;
+                Ok(format!("S{SIGILL:02x}"))
+            }
+        }
+    }
+}
+
+fn register_width(number: usize) -> usize {
+    if number == 8 {
+        8
+    } else {
+        4
+    }
+}
+
+fn byte_address(word: u16) -> u32 {
+    u32::from(word) << 1
+}
+
+fn word_address(byte: u32) -> u16 {
+    u16::try_from(byte >> 1).unwrap_or(u16::MAX)
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+/// Parses `ADDR,LENGTH` as used by the memory packets
+fn parse_range(range: &str) -> Option<(u32, u32)> {
+    let (address, length) = range.split_once(',')?;
+    Some((
+        u32::from_str_radix(address, 16).ok()?,
+        u32::from_str_radix(length, 16).ok()?,
+    ))
+}
+
+/// Restores the bytes escaped in packet data
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(data.len());
+    let mut escaped = false;
+    for &byte in data {
+        if escaped {
+            bytes.push(byte ^ 0x20);
+            escaped = false;
+        } else if byte == ESCAPE {
+            escaped = true;
+        } else {
+            bytes.push(byte);
+        }
+    }
+    bytes
+}
+
+fn decode_bytes(hex: &str) -> Option<Vec<u8>> {
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
+
+/// Decodes a little endian hex register value
+fn decode_le(hex: &str) -> Option<u32> {
+    let bytes = decode_bytes(hex)?;
+    let mut padded = [0; 4];
+    for (slot, byte) in padded.iter_mut().zip(bytes) {
+        *slot = byte;
+    }
+    Some(u32::from_le_bytes(padded))
+}
+
+/// Answers a `qXfer` read of `OFFSET,LENGTH` from `document`
+fn transfer_chunk(document: &str, range: &str) -> String {
+    let Some((offset, length)) = parse_range(range) else {
+        return String::from("E01");
+    };
+    let bytes = document.as_bytes();
+    let start = usize::try_from(offset)
+        .unwrap_or(usize::MAX)
+        .min(bytes.len());
+    let end = start
+        .saturating_add(usize::try_from(length).unwrap_or(usize::MAX))
+        .min(bytes.len());
+    let chunk = bytes.get(start..end).unwrap_or_default();
+    let marker = if end == bytes.len() { 'l' } else { 'm' };
+    format!("{marker}{}", String::from_utf8_lossy(chunk))
+}
+
+fn io_error(error: std::io::Error) -> VMError {
+    VMError::StandardIO(IoError::caused_by("gdb connection failed", error))
+}
@@ -0,0 +1,225 @@
+//! Grades a submission: runs its images against every case of a suite and
+//! writes the results as JUnit XML or JSON for CI systems and learning
+//! platforms. A suite is a batch manifest whose `[[case]]` tables name no
+//! image, since each loads the submission:
+//!
+//! ```toml
+//! limit = 1000000
+//! timeout = 5
+//!
+//! [[case]]
+//! name = "empty line"
+//! input_text = "\n"
+//! expected_text = "\nHALT\n"
+//!
+//! [[case]]
+//! input = "long.in"
+//! expected = "long.out"
+//! env = { SEED = "42" }
+//! ```
+//!
+//! Cases may still give an `image`, loaded after the submission, e.g. a
+//! driver calling its subroutines. Every case runs on a fresh VM without
+//! file traps, serial port or host variables, see `batch::Job`.
+
+use std::{
+    fmt,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde_json::json;
+
+use crate::{
+    batch::{self, Job, Outcome, Report, Verdict},
+    errors::{IoError, VMError},
+};
+
+/// Cases run against the same submission
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suite {
+    /// Name of the suite in the results, the stem of its file
+    pub name: String,
+    pub cases: Vec<Job>,
+}
+
+impl Suite {
+    /// Reads the suite at `path` for the submission made of `images`
+    pub fn load(path: &Path, images: &[PathBuf]) -> Result<Suite, VMError> {
+        let text = fs::read_to_string(path).map_err(|e| {
+            VMError::ReadFile(IoError::caused_by(
+                format!("Could not read {}", path.display()),
+                e,
+            ))
+        })?;
+        let mut suite = Suite::parse(&text, path.parent().unwrap_or(Path::new(".")), images)?;
+        if let Some(stem) = path.file_stem() {
+            suite.name = stem.to_string_lossy().into_owned();
+        }
+        Ok(suite)
+    }
+
+    /// Parses a suite whose paths are relative to `base`
+    pub fn parse(text: &str, base: &Path, images: &[PathBuf]) -> Result<Suite, VMError> {
+        if images.is_empty() {
+            return Err(VMError::InvalidArgument(String::from(
+                "The submission has no image",
+            )));
+        }
+        Ok(Suite {
+            name: String::from("suite"),
+            cases: batch::parse_jobs(text, base, "case", images)?,
+        })
+    }
+
+    pub fn run(&self) -> Report {
+        Report {
+            outcomes: self.cases.iter().map(Job::run).collect(),
+        }
+    }
+}
+
+/// Machine-readable result formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// The XML of JUnit reports, read by most CI systems
+    Junit,
+    /// A JSON object with a `cases` array
+    Json,
+}
+
+impl Format {
+    /// The results of `report` for the suite `name`
+    pub fn render(self, name: &str, report: &Report) -> String {
+        match self {
+            Format::Junit => junit(name, report),
+            Format::Json => json(name, report),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = VMError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "junit" => Ok(Format::Junit),
+            "json" => Ok(Format::Json),
+            _ => Err(VMError::InvalidArgument(format!(
+                "Unknown result format `{name}`, expected junit or json"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Format::Junit => "junit",
+            Format::Json => "json",
+        })
+    }
+}
+
+/// Result and message of a case, as the formats name them
+fn result(outcome: &Outcome) -> (&'static str, Option<String>) {
+    match &outcome.verdict {
+        Verdict::Pass => ("pass", None),
+        Verdict::Fail(reason) => ("fail", Some(reason.clone())),
+        Verdict::Error(error) => ("error", Some(error.to_string())),
+    }
+}
+
+fn json(name: &str, report: &Report) -> String {
+    let cases: Vec<_> = report
+        .outcomes
+        .iter()
+        .map(|outcome| {
+            let (result, message) = result(outcome);
+            json!({
+                "name": outcome.name,
+                "result": result,
+                "message": message,
+                "instructions": outcome.instructions,
+                "time": outcome.time.as_secs_f64(),
+                "output": outcome.output,
+            })
+        })
+        .collect();
+    let results = json!({
+        "suite": name,
+        "passed": report.passed(),
+        "failed": report.failed(),
+        "cases": cases,
+    });
+    format!("{results:#}\n")
+}
+
+fn junit(name: &str, report: &Report) -> String {
+    let errors = report
+        .outcomes
+        .iter()
+        .filter(|outcome| matches!(outcome.verdict, Verdict::Error(_)))
+        .count();
+    let failures = report.failed().saturating_sub(errors);
+    let time: f64 = report
+        .outcomes
+        .iter()
+        .map(|outcome| outcome.time.as_secs_f64())
+        .sum();
+    let name = escape(name);
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let totals = format!(
+        "tests=\"{}\" failures=\"{failures}\" errors=\"{errors}\" time=\"{time:.6}\"",
+        report.outcomes.len()
+    );
+    let _ = writeln!(xml, "<testsuites name=\"{name}\" {totals}>");
+    let _ = writeln!(xml, "  <testsuite name=\"{name}\" {totals}>");
+    for outcome in &report.outcomes {
+        let _ = writeln!(
+            xml,
+            "    <testcase name=\"{}\" classname=\"{name}\" time=\"{:.6}\">",
+            escape(&outcome.name),
+            outcome.time.as_secs_f64()
+        );
+        let problem = match &outcome.verdict {
+            Verdict::Pass => None,
+            Verdict::Fail(reason) => Some(("failure", reason.clone())),
+            Verdict::Error(error) => Some(("error", error.to_string())),
+        };
+        if let Some((tag, message)) = problem {
+            let _ = writeln!(xml, "      <{tag} message=\"{}\"/>", escape(&message));
+        }
+        let _ = writeln!(
+            xml,
+            "      <system-out>{}</system-out>",
+            escape(&outcome.output)
+        );
+        xml.push_str("    </testcase>\n");
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+/// Escapes text for XML content and attributes. Control characters XML 1.0
+/// cannot hold are written as `\u{..}`, like Rust escapes them.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\n' | '\t' | '\r' => escaped.push(character),
+            character if character.is_control() => {
+                let _ = write!(escaped, "{}", character.escape_unicode());
+            }
+            character => escaped.push(character),
+        }
+    }
+    escaped
+}
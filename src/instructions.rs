@@ -0,0 +1,358 @@
+use alloc::format;
+
+use crate::{errors::VMError, register::Register};
+
+/// The sixteen opcodes encoded in bits [15:12] of every instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Opcode {
+    Br,
+    Add,
+    Ld,
+    St,
+    Jsr,
+    And,
+    Ldr,
+    Str,
+    Rti,
+    Not,
+    Ldi,
+    Sti,
+    Jmp,
+    Res,
+    Lea,
+    Trap,
+}
+
+impl Opcode {
+    pub fn from_instruction(instruction: u16) -> Opcode {
+        match instruction >> 12 {
+            0x0 => Opcode::Br,
+            0x1 => Opcode::Add,
+            0x2 => Opcode::Ld,
+            0x3 => Opcode::St,
+            0x4 => Opcode::Jsr,
+            0x5 => Opcode::And,
+            0x6 => Opcode::Ldr,
+            0x7 => Opcode::Str,
+            0x8 => Opcode::Rti,
+            0x9 => Opcode::Not,
+            0xA => Opcode::Ldi,
+            0xB => Opcode::Sti,
+            0xC => Opcode::Jmp,
+            0xD => Opcode::Res,
+            0xE => Opcode::Lea,
+            _ => Opcode::Trap,
+        }
+    }
+}
+
+/// Trap vectors implemented by the VM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrapCode {
+    /// get character from keyboard, not echoed onto the terminal
+    Getc = 0x20,
+    /// output a character
+    Out = 0x21,
+    /// output a word string
+    Puts = 0x22,
+    /// get character from keyboard, echoed onto the terminal
+    In = 0x23,
+    /// output a byte string
+    Putsp = 0x24,
+    /// halt the program
+    Halt = 0x25,
+}
+
+impl TryFrom<u16> for TrapCode {
+    type Error = VMError;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            0x20 => Ok(TrapCode::Getc),
+            0x21 => Ok(TrapCode::Out),
+            0x22 => Ok(TrapCode::Puts),
+            0x23 => Ok(TrapCode::In),
+            0x24 => Ok(TrapCode::Putsp),
+            0x25 => Ok(TrapCode::Halt),
+            _ => Err(VMError::InvalidTrapCode(format!(
+                "Trap code {value:#06x} is not supported"
+            ))),
+        }
+    }
+}
+
+/// Second operand of ADD and AND, selected by bit 5
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Register(Register),
+    Immediate(i16),
+}
+
+/// Target of JSR/JSRR, selected by bit 11
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsrTarget {
+    Offset(i16),
+    Register(Register),
+}
+
+/// A decoded instruction with offsets already sign extended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Br {
+        n: bool,
+        z: bool,
+        p: bool,
+        pc_offset: i16,
+    },
+    Add {
+        dr: Register,
+        sr1: Register,
+        operand: Operand,
+    },
+    Ld {
+        dr: Register,
+        pc_offset: i16,
+    },
+    St {
+        sr: Register,
+        pc_offset: i16,
+    },
+    Jsr {
+        target: JsrTarget,
+    },
+    And {
+        dr: Register,
+        sr1: Register,
+        operand: Operand,
+    },
+    Ldr {
+        dr: Register,
+        base: Register,
+        offset: i16,
+    },
+    Str {
+        sr: Register,
+        base: Register,
+        offset: i16,
+    },
+    Rti,
+    Not {
+        dr: Register,
+        sr: Register,
+    },
+    Ldi {
+        dr: Register,
+        pc_offset: i16,
+    },
+    Sti {
+        sr: Register,
+        pc_offset: i16,
+    },
+    Jmp {
+        base: Register,
+    },
+    Res,
+    Lea {
+        dr: Register,
+        pc_offset: i16,
+    },
+    Trap {
+        trap_vector: u16,
+    },
+}
+
+impl Instruction {
+    pub fn decode(instruction: u16) -> Instruction {
+        let dr = Register::from_bits(instruction >> 9);
+        let sr1 = Register::from_bits(instruction >> 6);
+        match Opcode::from_instruction(instruction) {
+            Opcode::Br => Instruction::Br {
+                n: (instruction >> 11) & 1 == 1,
+                z: (instruction >> 10) & 1 == 1,
+                p: (instruction >> 9) & 1 == 1,
+                pc_offset: sign_extend(instruction, 9),
+            },
+            Opcode::Add => Instruction::Add {
+                dr,
+                sr1,
+                operand: decode_operand(instruction),
+            },
+            Opcode::Ld => Instruction::Ld {
+                dr,
+                pc_offset: sign_extend(instruction, 9),
+            },
+            Opcode::St => Instruction::St {
+                sr: dr,
+                pc_offset: sign_extend(instruction, 9),
+            },
+            Opcode::Jsr => {
+                let target = if (instruction >> 11) & 1 == 1 {
+                    JsrTarget::Offset(sign_extend(instruction, 11))
+                } else {
+                    JsrTarget::Register(sr1)
+                };
+                Instruction::Jsr { target }
+            }
+            Opcode::And => Instruction::And {
+                dr,
+                sr1,
+                operand: decode_operand(instruction),
+            },
+            Opcode::Ldr => Instruction::Ldr {
+                dr,
+                base: sr1,
+                offset: sign_extend(instruction, 6),
+            },
+            Opcode::Str => Instruction::Str {
+                sr: dr,
+                base: sr1,
+                offset: sign_extend(instruction, 6),
+            },
+            Opcode::Rti => Instruction::Rti,
+            Opcode::Not => Instruction::Not { dr, sr: sr1 },
+            Opcode::Ldi => Instruction::Ldi {
+                dr,
+                pc_offset: sign_extend(instruction, 9),
+            },
+            Opcode::Sti => Instruction::Sti {
+                sr: dr,
+                pc_offset: sign_extend(instruction, 9),
+            },
+            Opcode::Jmp => Instruction::Jmp { base: sr1 },
+            Opcode::Res => Instruction::Res,
+            Opcode::Lea => Instruction::Lea {
+                dr,
+                pc_offset: sign_extend(instruction, 9),
+            },
+            Opcode::Trap => Instruction::Trap {
+                trap_vector: instruction & 0xFF,
+            },
+        }
+    }
+
+    /// Encodes the instruction back into a word. Offsets and immediates are
+    /// truncated to the width of their field and unused bits are set as the
+    /// specification prescribes (ones for NOT, zeros elsewhere), so
+    /// `decode(encode(i)) == i` whenever the offsets fit.
+    pub fn encode(&self) -> u16 {
+        match *self {
+            Instruction::Br { n, z, p, pc_offset } => {
+                u16::from(n) << 11 | u16::from(z) << 10 | u16::from(p) << 9 | field(pc_offset, 9)
+            }
+            Instruction::Add { dr, sr1, operand } => {
+                0x1000 | dr.number() << 9 | sr1.number() << 6 | encode_operand(operand)
+            }
+            Instruction::Ld { dr, pc_offset } => 0x2000 | dr.number() << 9 | field(pc_offset, 9),
+            Instruction::St { sr, pc_offset } => 0x3000 | sr.number() << 9 | field(pc_offset, 9),
+            Instruction::Jsr {
+                target: JsrTarget::Offset(offset),
+            } => 0x4800 | field(offset, 11),
+            Instruction::Jsr {
+                target: JsrTarget::Register(base),
+            } => 0x4000 | base.number() << 6,
+            Instruction::And { dr, sr1, operand } => {
+                0x5000 | dr.number() << 9 | sr1.number() << 6 | encode_operand(operand)
+            }
+            Instruction::Ldr { dr, base, offset } => {
+                0x6000 | dr.number() << 9 | base.number() << 6 | field(offset, 6)
+            }
+            Instruction::Str { sr, base, offset } => {
+                0x7000 | sr.number() << 9 | base.number() << 6 | field(offset, 6)
+            }
+            Instruction::Rti => 0x8000,
+            Instruction::Not { dr, sr } => 0x903F | dr.number() << 9 | sr.number() << 6,
+            Instruction::Ldi { dr, pc_offset } => 0xA000 | dr.number() << 9 | field(pc_offset, 9),
+            Instruction::Sti { sr, pc_offset } => 0xB000 | sr.number() << 9 | field(pc_offset, 9),
+            Instruction::Jmp { base } => 0xC000 | base.number() << 6,
+            Instruction::Res => 0xD000,
+            Instruction::Lea { dr, pc_offset } => 0xE000 | dr.number() << 9 | field(pc_offset, 9),
+            Instruction::Trap { trap_vector } => 0xF000 | (trap_vector & 0xFF),
+        }
+    }
+}
+
+fn encode_operand(operand: Operand) -> u16 {
+    match operand {
+        Operand::Register(register) => register.number(),
+        Operand::Immediate(value) => 0x20 | field(value, 5),
+    }
+}
+
+/// The lowest `bit_count` bits of `value`
+fn field(value: i16, bit_count: u32) -> u16 {
+    u16::from_ne_bytes(value.to_ne_bytes()) & (1_u16 << bit_count).wrapping_sub(1)
+}
+
+fn decode_operand(instruction: u16) -> Operand {
+    if (instruction >> 5) & 1 == 1 {
+        Operand::Immediate(sign_extend(instruction, 5))
+    } else {
+        Operand::Register(Register::from_bits(instruction))
+    }
+}
+
+/// Takes the lowest `bit_count` bits of `value` and sign extends them to 16 bits
+#[inline]
+pub fn sign_extend(value: u16, bit_count: u32) -> i16 {
+    let shift = 16_u32.saturating_sub(bit_count);
+    i16::from_ne_bytes(value.wrapping_shl(shift).to_ne_bytes()).wrapping_shr(shift)
+}
+
+/// Whether `value` fits a signed field of `bit_count` bits
+pub(crate) fn fits(value: i16, bit_count: u32) -> bool {
+    let limit = 1_i32 << bit_count.saturating_sub(1);
+    (limit.wrapping_neg()..limit).contains(&i32::from(value))
+}
+
+/// How effective addresses and the PC behave past either end of the
+/// address space
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AddressArithmetic {
+    /// Fails, which catches runaway pointers and programs running off the
+    /// end of memory
+    #[default]
+    Checked,
+    /// Wraps around modulo 2^16 like the hardware and other simulators
+    Wrapping,
+}
+
+impl AddressArithmetic {
+    /// Computes `base + offset`
+    #[inline]
+    pub fn offset(self, base: u16, offset: i16) -> Result<u16, VMError> {
+        match self {
+            AddressArithmetic::Checked => match base.checked_add_signed(offset) {
+                Some(address) => Ok(address),
+                None => Err(address_overflow(base, offset)),
+            },
+            AddressArithmetic::Wrapping => Ok(base.wrapping_add_signed(offset)),
+        }
+    }
+
+    /// The address of the instruction after the one at `pc`
+    #[inline]
+    pub fn next(self, pc: u16) -> Result<u16, VMError> {
+        match self {
+            AddressArithmetic::Checked => pc.checked_add(1).ok_or_else(|| pc_overflow(pc)),
+            AddressArithmetic::Wrapping => Ok(pc.wrapping_add(1)),
+        }
+    }
+}
+
+/// Computes `base + offset`, failing if the result falls outside of memory
+#[inline]
+pub fn offset_address(base: u16, offset: i16) -> Result<u16, VMError> {
+    AddressArithmetic::Checked.offset(base, offset)
+}
+
+#[cold]
+fn address_overflow(base: u16, offset: i16) -> VMError {
+    VMError::AddressOverflow(format!(
+        "Address {base:#06x} with offset {offset} is out of memory bounds"
+    ))
+}
+
+#[cold]
+fn pc_overflow(pc: u16) -> VMError {
+    VMError::AddressOverflow(format!("The PC ran past the end of memory after {pc:#06x}"))
+}
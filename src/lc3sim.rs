@@ -0,0 +1,748 @@
+//! Front end speaking the command language of the classic lc3sim, so course
+//! scripts that pipe commands into it work unchanged (`lc3-vm lc3sim`, or
+//! the binary installed under the name `lc3sim`). Commands may be shortened
+//! to any prefix, the first match in alphabetical order winning, so `c` is
+//! `continue` and `f` is `file`. Commands lc3sim does not have, such as
+//! `checkpoint`, only match once no lc3sim command does. The IN and HALT
+//! traps print the messages of the lc3sim operating system.
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::{
+    assembler::{assemble_with_diagnostics, report},
+    console::SharedConsole,
+    disassembler::disassemble,
+    errors::{IoError, VMError},
+    instructions::Instruction,
+    memory::{read_image_file, symbol_path},
+    register::Register,
+    serial::SerialPort,
+    symbols::SymbolTable,
+    vm::{Checkpoints, DeviceAccess, Profile, StopReason, TrapMessages, VM},
+};
+
+pub const PROMPT: &str = "(lc3sim) ";
+
+const COMMANDS: [&str; 16] = [
+    "break",
+    "continue",
+    "dump",
+    "execute",
+    "file",
+    "finish",
+    "help",
+    "list",
+    "memory",
+    "next",
+    "printregs",
+    "quit",
+    "register",
+    "reset",
+    "step",
+    "translate",
+];
+
+/// Commands of our own, matched by prefix after the lc3sim ones
+const EXTENSIONS: [&str; 7] = [
+    "attach",
+    "checkpoint",
+    "diff",
+    "devices",
+    "detach",
+    "microstep",
+    "undo",
+];
+
+const HELP: &str = "\
+file <file>           -- file load (also sets PC to start of file)
+break clear <addr>|all -- clear one or all breakpoints
+break list            -- list all breakpoints
+break set <addr>      -- set a breakpoint
+break trap [<vector>] -- stop before every TRAP or those with <vector>
+break trap clear      -- stop before no TRAP
+break interrupt on|off -- stop before interrupts and exceptions
+break device <addr> [read|write] -- stop after accesses to a device register
+break device clear    -- stop after no device access
+continue              -- continue execution
+finish                -- execute to end of current subroutine
+next                  -- execute next instruction (full subroutine/trap)
+step                  -- execute one step (into subroutine/trap)
+list [<addr> [<end>]] -- list instructions at the PC or an address
+dump [<addr> [<end>]] -- dump memory at the PC or an address
+translate <addr>      -- show the value of a label and print the contents
+printregs             -- print registers and current instruction
+memory <addr> <val>   -- set the value held in a memory location
+register <reg> <val>  -- set a register to a value
+execute <file>        -- execute a script file
+reset                 -- reset LC-3 and reload last file
+checkpoint save <name> -- remember the registers and memory as <name>
+checkpoint restore <name> -- go back to checkpoint <name>
+checkpoint delete <name> -- forget checkpoint <name>
+checkpoint list       -- list all checkpoints
+diff <name>           -- show what changed since checkpoint <name>
+devices               -- list the devices and their addresses
+attach serial <address>|display|dma|timer <period>|rng <seed> -- attach a device
+detach <name>         -- detach a device
+microstep             -- execute one state of the control unit
+undo                  -- take back the last instruction executed
+quit                  -- quit the simulator
+help                  -- print this help
+Addresses are labels or numbers such as x3000 or #12.";
+
+const ATTACH_USAGE: &str = "Usage: attach serial <address>|display|dma|timer <period>|rng <seed>";
+
+/// Lines shown by `list` and `dump` without an end address
+const LINES: u16 = 10;
+/// Words on each line of `dump`
+const DUMP_WORDS: u16 = 8;
+/// Scripts that may be running inside one another through `execute`
+const SCRIPT_DEPTH: u8 = 8;
+
+pub struct Simulator {
+    vm: VM,
+    symbols: SymbolTable,
+    /// Object file loaded last, reloaded by `reset`
+    file: Option<String>,
+    /// Last instruction executed
+    ir: u16,
+    scripts: u8,
+    quit: bool,
+    /// Machine states saved by `checkpoint save`
+    checkpoints: Checkpoints,
+    /// Console keys are typed into, when not the terminal. Execution stops
+    /// before a trap that would wait for a key it does not have yet.
+    input: Option<SharedConsole>,
+}
+
+impl Simulator {
+    pub fn new(mut vm: VM) -> Self {
+        vm.set_trap_messages(TrapMessages::LC3SIM);
+        vm.set_conformance(Profile::Lc3sim);
+        vm.set_undo(true);
+        Simulator {
+            vm,
+            symbols: SymbolTable::default(),
+            file: None,
+            ir: 0,
+            scripts: 0,
+            quit: false,
+            checkpoints: Checkpoints::new(),
+            input: None,
+        }
+    }
+
+    /// Simulator whose program reads keys from and writes to `console`
+    pub fn with_console(mut vm: VM, console: SharedConsole) -> Self {
+        vm.set_console(Box::new(console.clone()));
+        Simulator {
+            input: Some(console),
+            ..Simulator::new(vm)
+        }
+    }
+
+    pub fn vm(&self) -> &VM {
+        &self.vm
+    }
+
+    /// Whether `quit` was given, possibly in a script
+    pub fn has_quit(&self) -> bool {
+        self.quit
+    }
+
+    /// Evaluates one command, returning the text to show, or `None` once
+    /// the user quits
+    pub fn eval(&mut self, line: &str) -> Option<String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((typed, arguments)) = words.split_first() else {
+            return Some(String::new());
+        };
+        let typed = typed.to_ascii_lowercase();
+        let Some(command) = COMMANDS
+            .iter()
+            .chain(&EXTENSIONS)
+            .find(|name| name.starts_with(&typed))
+        else {
+            return Some(String::from("Unknown command.  Type 'help' for a list."));
+        };
+        let output = match (*command, arguments) {
+            ("quit", []) => {
+                self.quit = true;
+                return None;
+            }
+            ("help", []) => String::from(HELP),
+            ("file", [path]) => self.load(path).unwrap_or_else(|error| format!("{error:#}")),
+            ("break", arguments) => self.break_command(arguments),
+            ("continue", []) => self.execute(|_, _| false),
+            ("step", []) => self.execute(|_, _| true),
+            ("next", []) => self.next(),
+            ("finish", []) => self.finish(),
+            ("list", arguments) => self.range(arguments, Self::list),
+            ("dump", arguments) => self.range(arguments, Self::dump),
+            ("translate", [address]) => match self.address(address) {
+                Some(address) => format!(
+                    "Address x{address:04X} has value x{:04X}.",
+                    self.vm.peek(address)
+                ),
+                None => format!("Could not translate {address}."),
+            },
+            ("printregs", []) => self.registers(),
+            ("memory", [address, value]) => match (self.address(address), self.address(value)) {
+                (Some(address), Some(value)) => match self.vm.poke(address, value) {
+                    Ok(()) => format!("Wrote x{value:04X} to address x{address:04X}."),
+                    Err(error) => format!("{error:#}"),
+                },
+                _ => String::from("Usage: memory <addr> <val>"),
+            },
+            ("register", [register, value]) => self.set_register(register, value),
+            ("execute", [path]) => self.script(path),
+            ("reset", []) => self.reset(),
+            ("checkpoint", arguments) => self.checkpoint(arguments),
+            ("diff", [name]) => self.diff(name),
+            ("devices", []) => self
+                .vm
+                .memory()
+                .mmio_map()
+                .to_string()
+                .trim_end()
+                .to_owned(),
+            ("attach", arguments) => self.attach(arguments),
+            ("detach", [name]) => match self.vm.detach_device(name) {
+                Ok(()) => format!("Detached {name}."),
+                Err(error) => format!("{error:#}."),
+            },
+            ("microstep", []) => self.microstep(),
+            ("undo", []) => match self.vm.undo() {
+                Ok(()) => self.registers(),
+                Err(_) => String::from("No instruction to undo."),
+            },
+            (command, _) => {
+                format!("Wrong number of arguments to {command}.  Type 'help' for a list.")
+            }
+        };
+        Some(output)
+    }
+
+    fn diff(&self, name: &str) -> String {
+        let Some(checkpoint) = self.checkpoints.get(name) else {
+            return format!("No checkpoint is named {name}.");
+        };
+        let diff = checkpoint.diff(&self.vm.snapshot(&[(0, self.vm.memory().size())]));
+        if diff.is_empty() {
+            format!("Nothing changed since checkpoint {name}.")
+        } else {
+            diff.to_string()
+        }
+    }
+
+    fn checkpoint(&mut self, arguments: &[&str]) -> String {
+        match arguments {
+            ["save", name] => {
+                self.checkpoints.save(name, &self.vm);
+                format!("Saved checkpoint {name} at x{:04X}.", self.vm.pc())
+            }
+            ["restore", name] => match self.checkpoints.restore(name, &mut self.vm) {
+                Ok(()) => format!("Restored checkpoint {name}.\n{}", self.registers()),
+                Err(VMError::Debugger(message)) => format!("{message}."),
+                Err(error) => format!("{error:#}"),
+            },
+            ["delete", name] if self.checkpoints.remove(name) => {
+                format!("Deleted checkpoint {name}.")
+            }
+            ["delete", name] => format!("No checkpoint is named {name}."),
+            ["list"] => {
+                let names: Vec<&str> = self.checkpoints.names().collect();
+                if names.is_empty() {
+                    String::from("No checkpoints are saved.")
+                } else {
+                    names.join("\n")
+                }
+            }
+            _ => String::from("Usage: checkpoint save|restore|delete <name>, checkpoint list"),
+        }
+    }
+
+    /// Attaches a device to the stopped machine, waiting for the peer of a
+    /// serial port
+    fn attach(&mut self, arguments: &[&str]) -> String {
+        let (name, result) = match arguments {
+            ["serial", address] => (
+                "serial",
+                SerialPort::listen(address).and_then(|port| self.vm.set_serial(port)),
+            ),
+            ["display"] => ("display", self.vm.enable_display()),
+            ["dma"] => ("dma", self.vm.enable_dma()),
+            ["timer", period] => match self.address(period) {
+                Some(period) => ("timer", self.vm.enable_timer(period)),
+                None => return format!("Could not translate {period}."),
+            },
+            ["rng", seed] => match seed.parse() {
+                Ok(seed) => ("rng", self.vm.enable_rng(seed)),
+                Err(_) => return format!("Could not parse the seed {seed}."),
+            },
+            _ => return String::from(ATTACH_USAGE),
+        };
+        match result {
+            Ok(()) => format!("Attached {name}."),
+            Err(error) => format!("{error:#}."),
+        }
+    }
+
+    /// Executes one state of the control unit, showing the registers of the
+    /// microarchitecture after it and the whole machine once the
+    /// instruction completes
+    fn microstep(&mut self) -> String {
+        if self.vm.microstate().at_instruction_boundary() {
+            self.ir = self.vm.peek(self.vm.pc());
+        }
+        match self.vm.micro_step() {
+            Ok(step) => {
+                let micro = self.vm.microstate();
+                let mut output = format!(
+                    "State {}: {}\nMAR=x{:04X} MDR=x{:04X} IR=x{:04X} BEN={} next state {}",
+                    step.state,
+                    step.transfer,
+                    micro.mar,
+                    micro.mdr,
+                    micro.ir,
+                    u8::from(micro.ben),
+                    step.next
+                );
+                if micro.at_instruction_boundary() {
+                    output.push('\n');
+                    output.push_str(&self.registers());
+                }
+                output
+            }
+            Err(VMError::Debugger(message)) => format!("{message}."),
+            Err(error) => format!("{error:#}"),
+        }
+    }
+
+    /// Loads an object file, or assembles a source first, along with the
+    /// symbol table next to it, and points the PC at its origin
+    pub fn load(&mut self, path: &str) -> Result<String, VMError> {
+        let path = if Path::new(path).extension().is_some() {
+            String::from(path)
+        } else {
+            format!("{path}.obj")
+        };
+        let (image, symbols) = if path.ends_with(".asm") {
+            let source = fs::read_to_string(&path).map_err(|e| {
+                VMError::ReadFile(IoError::caused_by(format!("Could not read {path}"), e))
+            })?;
+            let assembly = assemble_with_diagnostics(&source, None)
+                .map_err(|diagnostics| VMError::Assembly(report(&source, &diagnostics)))?;
+            assembly.ensure_linked()?;
+            (assembly.image(), assembly.symbols)
+        } else {
+            let image = read_image_file(&path)?;
+            let symbols = fs::read_to_string(symbol_path(&path))
+                .map(|text| SymbolTable::parse(&text))
+                .unwrap_or_default();
+            (image, symbols)
+        };
+        self.vm.read_image_bytes(&image)?;
+        let origin = origin(&image);
+        self.vm.set_pc(origin);
+        self.symbols = symbols;
+        self.file = Some(path.clone());
+        Ok(format!("Loaded \"{path}\" and set PC to x{origin:04X}"))
+    }
+
+    fn trap_break(&mut self, vector: Option<u16>) -> String {
+        let which = vector.map_or_else(
+            || String::from("every TRAP"),
+            |vector| format!("TRAP x{vector:02X}"),
+        );
+        if self.vm.add_trap_break(vector) {
+            format!("Will stop before {which}.")
+        } else {
+            format!("Already stopping before {which}.")
+        }
+    }
+
+    /// `printregs`: the registers, the flags and the next instruction
+    pub fn registers(&self) -> String {
+        let psr = self.vm.psr();
+        let name = match psr.condition_letter() {
+            'N' => "NEGATIVE",
+            'Z' => "ZERO",
+            _ => "POSITIVE",
+        };
+        let registers: String = (0..8)
+            .map(|number| {
+                let register = Register::from_bits(number);
+                format!("{register}=x{:04X} ", self.vm.register(register))
+            })
+            .collect();
+        format!(
+            "PC=x{:04X} IR=x{:04X} PSR={psr} {name}\n{registers}\n{}",
+            self.vm.pc(),
+            self.ir,
+            self.location(self.vm.pc())
+        )
+    }
+
+    fn break_command(&mut self, arguments: &[&str]) -> String {
+        match arguments {
+            ["list"] => {
+                let lines: Vec<String> = self
+                    .vm
+                    .breakpoints()
+                    .map(|address| self.location(address))
+                    .collect();
+                if lines.is_empty() {
+                    String::from("No breakpoints are set.")
+                } else {
+                    format!(
+                        "The following instructions have breakpoints:\n{}",
+                        lines.join("\n")
+                    )
+                }
+            }
+            ["clear", "all"] => {
+                let set: Vec<u16> = self.vm.breakpoints().collect();
+                for address in set {
+                    self.vm.remove_breakpoint(address);
+                }
+                String::from("Cleared all breakpoints.")
+            }
+            ["set", address] => match self.address(address) {
+                Some(address) if self.vm.add_breakpoint(address) => {
+                    format!("Set breakpoint at x{address:04X}.")
+                }
+                Some(address) => format!("A breakpoint is already set at x{address:04X}."),
+                None => format!("Could not translate {address}."),
+            },
+            ["clear", address] => match self.address(address) {
+                Some(address) if self.vm.remove_breakpoint(address) => {
+                    format!("Cleared breakpoint at x{address:04X}.")
+                }
+                Some(address) => format!("No breakpoint was set at x{address:04X}."),
+                None => format!("Could not translate {address}."),
+            },
+            ["trap"] => self.trap_break(None),
+            ["trap", "clear"] => {
+                let breaks: Vec<Option<u16>> = self.vm.trap_breaks().collect();
+                for vector in breaks {
+                    self.vm.remove_trap_break(vector);
+                }
+                String::from("Cleared all trap breaks.")
+            }
+            ["trap", vector] => match self.address(vector) {
+                Some(vector) if vector <= 0xFF => self.trap_break(Some(vector)),
+                _ => format!("{vector} is not a trap vector."),
+            },
+            ["interrupt", setting @ ("on" | "off")] => {
+                let enabled = *setting == "on";
+                self.vm.set_break_on_interrupt(enabled);
+                if enabled {
+                    String::from("Will stop before interrupts and exceptions.")
+                } else {
+                    String::from("Will not stop before interrupts and exceptions.")
+                }
+            }
+            ["device", "clear"] => {
+                let breaks: Vec<u16> = self
+                    .vm
+                    .device_breaks()
+                    .map(|(address, _)| address)
+                    .collect();
+                for address in breaks {
+                    self.vm.remove_device_break(address);
+                }
+                String::from("Cleared all device breaks.")
+            }
+            ["device", address, access @ ..] if access.len() <= 1 => {
+                let (access, which) = match access.first() {
+                    None => (DeviceAccess::Any, "accessing"),
+                    Some(&"read") => (DeviceAccess::Read, "reading"),
+                    Some(&"write") => (DeviceAccess::Write, "writing"),
+                    Some(access) => return format!("{access} is not read or write."),
+                };
+                let Some(address) = self.address(address) else {
+                    return format!("Could not translate {address}.");
+                };
+                match self.vm.add_device_break(address, access) {
+                    Ok(()) => format!("Will stop after {which} x{address:04X}."),
+                    Err(_) => format!("x{address:04X} is not a device register."),
+                }
+            }
+            _ => String::from(
+                "Usage: break set|clear <addr>, break clear all, break list, \
+                 break trap [<vector>|clear], break interrupt on|off, \
+                 break device <addr> [read|write], break device clear",
+            ),
+        }
+    }
+
+    /// Executes instructions until `done` holds for the new PC and the
+    /// instruction just executed, the VM has a pending stop such as a
+    /// breakpoint or the program halts, then prints the registers
+    fn execute(&mut self, mut done: impl FnMut(u16, Instruction) -> bool) -> String {
+        let stopped = loop {
+            let waiting = self.vm.waits_for_key()
+                && self
+                    .input
+                    .as_ref()
+                    .is_some_and(|console| !console.has_input());
+            if waiting {
+                break Err(String::from("The program is waiting for input."));
+            }
+            self.ir = self.vm.peek(self.vm.pc());
+            match self.vm.step() {
+                Ok(StopReason::Halted) => break Ok(()),
+                Ok(StopReason::Break) => break Err(String::from("Stopped by the break key.")),
+                Ok(StopReason::DeviceAccess { address, write }) => {
+                    let verb = if write { "writing" } else { "reading" };
+                    break Err(format!("Stopped after {verb} x{address:04X}."));
+                }
+                Ok(_) if done(self.vm.pc(), Instruction::decode(self.ir)) => break Ok(()),
+                Ok(_) => match self.vm.pending_stop() {
+                    Some(StopReason::Trap(vector)) => {
+                        break Err(format!("Stopped before TRAP x{vector:02X}."))
+                    }
+                    Some(StopReason::Interrupt(vector)) => {
+                        break Err(format!("Stopped before exception x{vector:02X}."))
+                    }
+                    Some(_) => break Ok(()),
+                    None => {}
+                },
+                Err(error) => break Err(format!("{error:#}")),
+            }
+        };
+        match stopped {
+            Ok(()) => self.registers(),
+            Err(message) => format!("{message}\n{}", self.registers()),
+        }
+    }
+
+    /// Steps over subroutine calls; traps already run as one instruction
+    fn next(&mut self) -> String {
+        let pc = self.vm.pc();
+        match Instruction::decode(self.vm.peek(pc)) {
+            Instruction::Jsr { .. } => {
+                let back = pc.wrapping_add(1);
+                self.execute(|next, _| next == back)
+            }
+            _ => self.execute(|_, _| true),
+        }
+    }
+
+    /// Runs until the current subroutine returns, following nested calls
+    fn finish(&mut self) -> String {
+        let mut depth: u32 = 0;
+        self.execute(|_, instruction| match instruction {
+            Instruction::Jsr { .. } => {
+                depth = depth.saturating_add(1);
+                false
+            }
+            Instruction::Jmp { base: Register::R7 } => match depth.checked_sub(1) {
+                Some(outer) => {
+                    depth = outer;
+                    false
+                }
+                None => true,
+            },
+            _ => false,
+        })
+    }
+
+    /// Runs `show` over the optional start and end addresses of `list` and
+    /// `dump`, starting at the PC
+    fn range(&self, arguments: &[&str], show: fn(&Self, u16, Option<u16>) -> String) -> String {
+        match arguments {
+            [] => show(self, self.vm.pc(), None),
+            [start] => match self.address(start) {
+                Some(start) => show(self, start, None),
+                None => format!("Could not translate {start}."),
+            },
+            [start, end] => match (self.address(start), self.address(end)) {
+                (Some(start), Some(end)) => show(self, start, Some(end)),
+                _ => format!("Could not translate {start} {end}."),
+            },
+            _ => String::from("Usage: list|dump [<addr> [<end>]]"),
+        }
+    }
+
+    fn list(&self, start: u16, end: Option<u16>) -> String {
+        let end = end.unwrap_or(start.saturating_add(LINES.saturating_sub(1)));
+        let lines: Vec<String> = (start..=end)
+            .map(|address| self.location(address))
+            .collect();
+        lines.join("\n")
+    }
+
+    fn dump(&self, start: u16, end: Option<u16>) -> String {
+        let end =
+            end.unwrap_or(start.saturating_add(LINES.saturating_mul(DUMP_WORDS).saturating_sub(1)));
+        let addresses: Vec<u16> = (start..=end).collect();
+        let lines: Vec<String> = addresses
+            .chunks(usize::from(DUMP_WORDS))
+            .map(|row| {
+                let words: Vec<String> = row
+                    .iter()
+                    .map(|&address| format!("x{:04X}", self.vm.peek(address)))
+                    .collect();
+                format!(
+                    "x{:04X}: {}",
+                    row.first().copied().unwrap_or(start),
+                    words.join(" ")
+                )
+            })
+            .collect();
+        lines.join("\n")
+    }
+
+    fn set_register(&mut self, register: &str, value: &str) -> String {
+        let Some(value) = self.address(value) else {
+            return format!("Could not translate {value}.");
+        };
+        let name = register.to_ascii_uppercase();
+        if name == "PC" {
+            self.vm.set_pc(value);
+            return format!("Set PC to x{value:04X}.");
+        }
+        match name
+            .strip_prefix('R')
+            .and_then(|digit| digit.parse::<u16>().ok())
+        {
+            Some(number) if number < 8 => {
+                self.vm.set_register(Register::from_bits(number), value);
+                format!("Set {name} to x{value:04X}.")
+            }
+            _ => String::from("Registers are R0 to R7 and PC."),
+        }
+    }
+
+    /// Runs the commands of a script file, as if typed, up to a `quit`
+    fn script(&mut self, path: &str) -> String {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) => return format!("Could not open script {path}: {error}"),
+        };
+        if self.scripts >= SCRIPT_DEPTH {
+            return format!("Scripts are nested too deeply to execute {path}.");
+        }
+        self.scripts = self.scripts.saturating_add(1);
+        let mut outputs = Vec::new();
+        for line in text.lines() {
+            match self.eval(line) {
+                Some(output) if !output.is_empty() => outputs.push(output),
+                _ => {}
+            }
+            if self.quit {
+                break;
+            }
+        }
+        self.scripts = self.scripts.saturating_sub(1);
+        outputs.join("\n")
+    }
+
+    fn reset(&mut self) -> String {
+        self.vm.reset(false);
+        self.ir = 0;
+        match self.file.clone() {
+            Some(file) => self
+                .load(&file)
+                .unwrap_or_else(|error| format!("{error:#}")),
+            None => String::from("The LC-3 was reset."),
+        }
+    }
+
+    /// An address or value typed by the user: a label, x1234, #12 or 12
+    fn address(&self, text: &str) -> Option<u16> {
+        if let Some(address) = self.symbols.address_of(text) {
+            return Some(address);
+        }
+        let (digits, radix) = match text.strip_prefix(['x', 'X']) {
+            Some(hex) => (hex, 16),
+            None => (text.strip_prefix('#').unwrap_or(text), 10),
+        };
+        let value = i32::from_str_radix(digits, radix).ok()?;
+        match u16::try_from(value) {
+            Ok(value) => Some(value),
+            Err(_) => i16::try_from(value)
+                .ok()
+                .map(|value| u16::from_ne_bytes(value.to_ne_bytes())),
+        }
+    }
+
+    /// One line of `list`: the label, the address, the word and its
+    /// disassembly
+    fn location(&self, address: u16) -> String {
+        let word = self.vm.peek(address);
+        format!(
+            "{:<16}  x{address:04X} x{word:04X} {}",
+            self.symbols.label_at(address).unwrap_or_default(),
+            disassemble(address, word)
+        )
+    }
+}
+
+fn origin(image: &[u8]) -> u16 {
+    match image {
+        [high, low, ..] => u16::from_be_bytes([*high, *low]),
+        _ => 0,
+    }
+}
+
+/// Runs lc3sim with `lc3sim [-s SCRIPT] [FILE]`: the commands of the
+/// script first, each shown as it runs, then those typed on stdin
+pub fn run(vm: VM, args: &[String]) -> Result<(), VMError> {
+    let mut simulator = Simulator::new(vm);
+    let mut stdout = io::stdout();
+    let mut args = args.iter();
+    let mut script = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-s" => {
+                let path = args.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("-s requires a script file"))
+                })?;
+                script = Some(fs::read_to_string(path).map_err(|e| {
+                    VMError::ReadFile(IoError::caused_by(
+                        format!("Could not read script {path}"),
+                        e,
+                    ))
+                })?);
+            }
+            file => show(&mut stdout, simulator.eval(&format!("file {file}")))?,
+        }
+    }
+    for line in script.as_deref().unwrap_or_default().lines() {
+        show(&mut stdout, simulator.eval(line))?;
+        if simulator.has_quit() {
+            return Ok(());
+        }
+    }
+    loop {
+        write!(stdout, "{PROMPT}").map_err(io_error)?;
+        stdout.flush().map_err(io_error)?;
+        // stdin is locked for each line only, so GETC and IN can read keys
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line).map_err(io_error)? == 0 {
+            writeln!(stdout).map_err(io_error)?;
+            return Ok(());
+        }
+        show(&mut stdout, simulator.eval(&line))?;
+        if simulator.has_quit() {
+            return Ok(());
+        }
+    }
+}
+
+fn show(stdout: &mut io::Stdout, output: Option<String>) -> Result<(), VMError> {
+    match output {
+        Some(output) if !output.is_empty() => writeln!(stdout, "{output}").map_err(io_error),
+        _ => Ok(()),
+    }
+}
+
+fn io_error(error: io::Error) -> VMError {
+    VMError::StandardIO(IoError::caused_by("Could not use the terminal", error))
+}
@@ -0,0 +1,75 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+/// A `tracing` event at `level` for `target`, compiled only with the
+/// `tracing` feature: `event!(TRACE, "lc3_vm::memory", address, "read")`
+macro_rules! event {
+    ($level:ident, $target:expr, $($fields:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::event!(target: $target, tracing::Level::$level, $($fields)*);
+    };
+}
+
+pub mod ansi;
+pub mod assembler;
+#[cfg(feature = "tokio")]
+pub mod async_console;
+#[cfg(feature = "std")]
+pub mod batch;
+pub mod cache;
+pub mod clock;
+pub mod console;
+pub mod cycles;
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub mod dap;
+pub mod datapath;
+#[cfg(feature = "std")]
+pub mod device_tree;
+pub mod disassembler;
+pub mod display;
+pub mod errors;
+pub mod explain;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub mod gdb;
+#[cfg(feature = "std")]
+pub mod grade;
+pub mod instructions;
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub mod lc3sim;
+pub mod linker;
+pub mod loop_detector;
+pub mod memory;
+pub mod mmio;
+pub mod observer;
+#[cfg(feature = "persistent")]
+mod persistent;
+pub mod register;
+#[cfg(all(unix, feature = "std", not(target_arch = "wasm32")))]
+pub mod remote;
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub mod repl;
+#[cfg(feature = "std")]
+pub mod sandbox;
+pub mod scancode;
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub mod serial;
+pub mod source_map;
+pub mod stack;
+pub mod stats;
+pub mod symbols;
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub mod terminal;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+pub mod testgen;
+pub mod trace;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod vfs;
+pub mod vm;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod watch;
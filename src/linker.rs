@@ -0,0 +1,141 @@
+//! Combines programs assembled separately into one image, filling in the
+//! labels each one declares `.EXTERNAL` with the addresses of the labels
+//! another one declares `.GLOBAL`. Programs keep their own `.ORIG`, so they
+//! must not overlap; the gaps between them are filled with zeros.
+
+use alloc::{borrow::ToOwned, collections::BTreeMap, format, string::String, vec::Vec};
+
+use crate::{
+    assembler::{Assembly, Field, Relocation},
+    errors::VMError,
+    instructions::fits,
+    symbols::SymbolTable,
+};
+
+/// Links `programs` into one. The result has the labels of every program,
+/// except those defined by more than one without being exported, and no
+/// source lines.
+pub fn link(programs: &[Assembly]) -> Result<Assembly, VMError> {
+    let exports = exports(programs)?;
+    let mut ordered: Vec<&Assembly> = programs.iter().collect();
+    ordered.sort_by_key(|program| program.origin);
+    let Some(first) = ordered.first() else {
+        return Err(VMError::Link(String::from("nothing to link")));
+    };
+    let origin = first.origin;
+    let mut words = Vec::new();
+    let mut previous: Option<&Assembly> = None;
+    for program in &ordered {
+        if let Some(previous) = previous {
+            let end = u32::from(previous.origin).saturating_add(length(previous));
+            if u32::from(program.origin) < end {
+                return Err(VMError::Link(format!(
+                    "the programs at x{:04X} and x{:04X} overlap",
+                    previous.origin, program.origin
+                )));
+            }
+        }
+        let start = usize::from(program.origin.wrapping_sub(origin));
+        words.resize(start, 0);
+        words.extend_from_slice(&program.words);
+        previous = Some(program);
+    }
+    for relocation in programs.iter().flat_map(|program| &program.relocations) {
+        let target = exports
+            .get(&relocation.symbol.to_ascii_uppercase())
+            .copied()
+            .ok_or_else(|| {
+                VMError::Link(format!(
+                    "{} is used at x{:04X} but no program declares it .GLOBAL",
+                    relocation.symbol, relocation.address
+                ))
+            })?;
+        let word = usize::from(relocation.address.wrapping_sub(origin));
+        let Some(word) = words.get_mut(word) else {
+            continue;
+        };
+        *word = patch(*word, relocation, target)?;
+    }
+    Ok(Assembly {
+        origin,
+        words,
+        symbols: symbols(programs),
+        lines: Vec::new(),
+        globals: programs
+            .iter()
+            .flat_map(|program| program.globals.iter().cloned())
+            .collect(),
+        relocations: Vec::new(),
+    })
+}
+
+/// Number of words of a program
+fn length(program: &Assembly) -> u32 {
+    u32::try_from(program.words.len()).unwrap_or(u32::MAX)
+}
+
+/// The address of every `.GLOBAL` label, by uppercase name
+fn exports(programs: &[Assembly]) -> Result<BTreeMap<String, u16>, VMError> {
+    let mut exports = BTreeMap::new();
+    for program in programs {
+        for name in &program.globals {
+            let Some(address) = program.symbols.address_of(name) else {
+                continue;
+            };
+            if let Some(other) = exports.insert(name.to_ascii_uppercase(), address) {
+                return Err(VMError::Link(format!(
+                    "{name} is declared .GLOBAL twice, at x{other:04X} and x{address:04X}"
+                )));
+            }
+        }
+    }
+    Ok(exports)
+}
+
+/// `word` with the address of `target` filled in
+fn patch(word: u16, relocation: &Relocation, target: u16) -> Result<u16, VMError> {
+    match relocation.field {
+        Field::Word => Ok(target),
+        Field::PcOffset(bits) => {
+            let offset = target.wrapping_sub(relocation.address.wrapping_add(1));
+            let offset = i16::from_ne_bytes(offset.to_ne_bytes());
+            if !fits(offset, bits) {
+                return Err(VMError::Link(format!(
+                    "{} at x{target:04X} is too far from x{:04X} for a {bits}-bit offset",
+                    relocation.symbol, relocation.address
+                )));
+            }
+            let mask = 1_u16
+                .checked_shl(bits)
+                .map_or(u16::MAX, |bit| bit.wrapping_sub(1));
+            let field = u16::from_ne_bytes(offset.to_ne_bytes()) & mask;
+            Ok(word & !mask | field)
+        }
+    }
+}
+
+/// The labels of all the programs, leaving out the local ones defined by
+/// more than one
+fn symbols(programs: &[Assembly]) -> SymbolTable {
+    let mut definitions: BTreeMap<String, Vec<(u16, String)>> = BTreeMap::new();
+    for program in programs {
+        for (address, label) in program.symbols.iter() {
+            definitions
+                .entry(label.to_ascii_uppercase())
+                .or_default()
+                .push((address, label.to_owned()));
+        }
+    }
+    let mut symbols = SymbolTable::default();
+    for (address, label) in
+        definitions
+            .into_values()
+            .filter_map(|definitions| match definitions.as_slice() {
+                [single] => Some(single.clone()),
+                _ => None,
+            })
+    {
+        symbols.insert(&label, address);
+    }
+    symbols
+}
@@ -0,0 +1,91 @@
+use alloc::{format, vec::Vec};
+
+use crate::{errors::VMError, vm::Psr};
+
+/// Default number of identical iterations before a loop is reported
+pub const DEFAULT_LOOP_THRESHOLD: u32 = 1000;
+
+/// How many distinct loop heads are remembered, bounding the cycle length
+/// that can be recognized
+const HISTORY_SIZE: usize = 8;
+
+/// Machine state observed when control jumps backwards
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LoopState {
+    pc: u16,
+    registers: [u16; 8],
+    psr: Psr,
+}
+
+/// Detects programs spinning without making progress, like `BRnzp #-1` or a
+/// short loop that neither changes registers nor touches memory or devices.
+///
+/// Every backward control transfer records the machine state. Seeing a state
+/// already in the recent history means the loop did no work since then; after
+/// `threshold` consecutive repetitions the program is reported as stuck.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoopDetector {
+    threshold: u32,
+    history: Vec<LoopState>,
+    repetitions: u32,
+}
+
+impl Default for LoopDetector {
+    fn default() -> Self {
+        Self::new(DEFAULT_LOOP_THRESHOLD)
+    }
+}
+
+impl LoopDetector {
+    /// Reports loops whose state repeated `threshold` times in a row. A
+    /// threshold of 0 reports them at the first repetition, like 1.
+    pub fn new(threshold: u32) -> Self {
+        LoopDetector {
+            threshold,
+            history: Vec::with_capacity(HISTORY_SIZE),
+            repetitions: 0,
+        }
+    }
+
+    /// Forgets the observed states, used whenever the program has a side
+    /// effect (memory write, device access, trap)
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.repetitions = 0;
+    }
+
+    /// Records the state after an instruction at `from` transferred control
+    /// to `to`. Fails once the same state has been seen `threshold` times in
+    /// a row.
+    pub fn observe(
+        &mut self,
+        from: u16,
+        to: u16,
+        registers: [u16; 8],
+        psr: Psr,
+    ) -> Result<(), VMError> {
+        if to > from {
+            return Ok(());
+        }
+        let state = LoopState {
+            pc: to,
+            registers,
+            psr,
+        };
+        if !self.history.contains(&state) {
+            if self.history.len() >= HISTORY_SIZE {
+                self.history.remove(0);
+            }
+            self.history.push(state);
+            self.repetitions = 0;
+            return Ok(());
+        }
+        self.repetitions = self.repetitions.saturating_add(1);
+        if self.repetitions >= self.threshold {
+            return Err(VMError::InfiniteLoop(format!(
+                "program appears stuck at x{to:04X}"
+            )));
+        }
+        Ok(())
+    }
+}
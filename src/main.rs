@@ -1,3 +1,1012 @@
+use std::{
+    cell::RefCell,
+    env, fs,
+    io::{self, BufWriter, IsTerminal, Write},
+    path::{Path, PathBuf},
+    process::exit,
+    rc::Rc,
+    time::Duration,
+};
+
+use lc3_vm::{
+    assembler::{assemble_with_diagnostics, report, Assembly},
+    batch::Manifest,
+    cache::{CacheConfig, CacheSimulator},
+    clock::{Speed, TimeSource},
+    console::{Encoding, Newline, OutputConsole, CP437},
+    dap,
+    datapath::DatapathView,
+    device_tree::{DeviceSpec, DeviceTree},
+    disassembler::disassemble_program,
+    errors::{IoError, VMError},
+    explain::Explainer,
+    gdb,
+    grade::{Format, Suite},
+    instructions::AddressArithmetic,
+    lc3sim,
+    linker::link,
+    loop_detector::LoopDetector,
+    memory::{append_checksum, read_image_file, symbol_path, Image},
+    observer::Observer,
+    register::Register,
+    repl,
+    sandbox::Sandbox,
+    serial::SerialPort,
+    stack::StackChecker,
+    stats::Statistics,
+    symbols::SymbolTable,
+    terminal,
+    trace::{self, Tracer},
+    vfs::DirectoryFileSystem,
+    vm::{DeviceAccess, Profile, UnmappedAccess, VM},
+};
+
+const USAGE: &str = "Usage: lc3-vm dap\n       lc3-vm lc3as [-hex] <source.asm>\n       lc3-vm lc3sim [-s SCRIPT] [image.obj]\n       lc3-vm disasm <image.obj>\n       lc3-vm remote <SOCKET> [image.obj]\n       lc3-vm verify <trace.bin> [OPTIONS] <image-file1> ...\n       lc3-vm trace-diff [--context N] <trace1> <trace2>\n       lc3-vm batch <manifest.toml>\n       lc3-vm grade [--format junit|json] [--output FILE] <suite.toml> <image-file1> ...\n       lc3-vm asm [--listing] [--checksum] [--link OUTPUT] <source1.asm> ...\n       lc3-vm sandbox [--limit N] [--output-limit N] [--timeout SECONDS] [--input FILE] [--env NAME=VALUE] <image-file1> ...\n       lc3-vm tui [OPTIONS] <image-file1> ...\n       lc3-vm repl [OPTIONS] [image-file1] ...\n       lc3-vm [run] [--stack LIMIT:BASE[:REGISTER]] [--loop-threshold N] [--speed IPS|unlimited] [--timeout SECONDS] [--virtual-time IPS] [--cycles MODEL|FILE] [--break-trap VECTOR|all] [--break-interrupt] [--break-device ADDRESS[:read|write]] [--gdb ADDRESS] [--serial ADDRESS] [--display] [--dma] [--channel NAME:ADDRESS:OUTPUT] [--devices FILE] [--unmapped memory|zero|bus-error|fail] [--persist FILE] [--files DIR] [--env NAME[=VALUE]] [--entry ADDRESS] [--extended-traps] [--strict-traps] [--profile strict-spec|lc3sim|permissive] [--addresses checked|wrap] [--key KEY=VALUE] [--echo getc,in|none] [--newline lf|cr|crlf] [--encoding ascii|latin1|cp437|utf8] [--ansi] [--scancodes] [--stats] [--mix FILE] [--icache SIZE:WAYS:LINE] [--dcache SIZE:WAYS:LINE] [--explain] [--datapath] [--trace FILE] [--color] [--trace-binary FILE] <image-file1> [image-file2] ... [-- ARGUMENTS]";
+
 fn main() {
-    println!("Hello, world!");
+    let program = env::args().next().unwrap_or_default();
+    let args: Vec<String> = env::args().skip(1).collect();
+    // installed under the names of the classic tools, act as them
+    let result = match Path::new(&program)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+    {
+        Some("lc3as") => lc3as(&args),
+        Some("lc3sim") => lc3sim::run(VM::new(), &args),
+        _ => run_command(&args),
+    };
+    if let Err(error) = result {
+        eprintln!("{error:#}");
+        if matches!(error, VMError::InvalidArgument(_)) {
+            eprintln!("{USAGE}");
+        }
+        exit(1);
+    }
+}
+
+fn run_command(args: &[String]) -> Result<(), VMError> {
+    if args.is_empty() {
+        eprintln!("{USAGE}");
+        exit(2);
+    }
+    match args.first().map(String::as_str) {
+        Some("dap") => dap::serve(),
+        Some("tui") => run_tui(args.get(1..).unwrap_or_default()),
+        Some("repl") => run_repl(args.get(1..).unwrap_or_default()),
+        Some("asm") => assemble_files(args.get(1..).unwrap_or_default()),
+        Some("disasm") => disassemble_file(args.get(1..).unwrap_or_default()),
+        Some("trace-diff") => diff_traces(args.get(1..).unwrap_or_default()),
+        Some("batch") => run_batch(args.get(1..).unwrap_or_default()),
+        Some("grade") => grade(args.get(1..).unwrap_or_default()),
+        Some("sandbox") => run_sandboxed(args.get(1..).unwrap_or_default()),
+        Some("verify") => verify_trace(args.get(1..).unwrap_or_default()),
+        Some("remote") => serve_remote(args.get(1..).unwrap_or_default()),
+        Some("lc3as") => lc3as(args.get(1..).unwrap_or_default()),
+        Some("lc3sim") => lc3sim::run(VM::new(), args.get(1..).unwrap_or_default()),
+        Some("run") => run(args.get(1..).unwrap_or_default()),
+        _ => run(args),
+    }
+}
+
+fn run(args: &[String]) -> Result<(), VMError> {
+    let mut stats = false;
+    let mut explain = false;
+    let mut datapath = false;
+    let mut mix = None;
+    let mut instruction_cache = None;
+    let mut data_cache = None;
+    let mut trace = None;
+    let mut color = false;
+    let mut binary_trace = None;
+    let mut rest = Vec::new();
+    let mut options = args.iter();
+    while let Some(arg) = options.next() {
+        match arg.as_str() {
+            "--stats" => stats = true,
+            "--explain" => explain = true,
+            "--datapath" => datapath = true,
+            "--color" => color = true,
+            // the arguments of the program follow
+            "--" => {
+                rest.push(arg.clone());
+                rest.extend(options.by_ref().cloned());
+            }
+            "--trace" => {
+                let path = options.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--trace requires a file"))
+                })?;
+                trace = Some(path);
+            }
+            "--mix" => {
+                let path = options.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--mix requires a file"))
+                })?;
+                mix = Some(Path::new(path));
+            }
+            "--icache" | "--dcache" => {
+                let config: CacheConfig = options
+                    .next()
+                    .ok_or_else(|| {
+                        VMError::InvalidArgument(format!("{arg} requires SIZE:WAYS:LINE"))
+                    })?
+                    .parse()?;
+                if arg == "--icache" {
+                    instruction_cache = Some(config);
+                } else {
+                    data_cache = Some(config);
+                }
+            }
+            "--trace-binary" => {
+                let path = options.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--trace-binary requires a file"))
+                })?;
+                binary_trace = Some(path);
+            }
+            _ => rest.push(arg.clone()),
+        }
+    }
+    let (mut vm, gdb_address) = configure(&rest)?;
+    let statistics = Rc::new(RefCell::new(Statistics::new()));
+    let mut observers: Vec<Box<dyn Observer>> = Vec::new();
+    if stats || mix.is_some() {
+        observers.push(Box::new(Rc::clone(&statistics)));
+    }
+    let caches = Rc::new(RefCell::new(CacheSimulator::new(
+        instruction_cache,
+        data_cache,
+    )));
+    let simulate_caches = instruction_cache.is_some() || data_cache.is_some();
+    if simulate_caches {
+        observers.push(Box::new(Rc::clone(&caches)));
+    }
+    if explain {
+        observers.push(Box::new(Explainer::new(|explanation| {
+            eprintln!("{explanation}");
+        })));
+    }
+    if datapath {
+        observers.push(Box::new(DatapathView::new(|diagram| {
+            eprint!("{diagram}");
+        })));
+    }
+    if let Some(path) = trace {
+        let (mut out, terminal) = trace_output(path)?;
+        let colored = color && terminal;
+        observers.push(Box::new(Tracer::new(move |step| {
+            let _ = if colored {
+                writeln!(out, "{}", step.colored())
+            } else {
+                writeln!(out, "{step}")
+            };
+        })));
+    }
+    if let Some(path) = binary_trace {
+        let (mut out, _) = trace_output(path)?;
+        let mut record = trace::HEADER.to_vec();
+        observers.push(Box::new(Tracer::new(move |step| {
+            step.encode(&mut record);
+            let _ = out.write_all(&record);
+            record.clear();
+        })));
+    }
+    if !observers.is_empty() {
+        vm.set_observer(Box::new(observers));
+    }
+    let saved_mode = terminal::enable_raw_mode().ok();
+    let result = match gdb_address {
+        Some(address) => serve_gdb(&mut vm, address),
+        None => vm.run(),
+    };
+    #[cfg(feature = "persistent")]
+    let result = result.and_then(|()| vm.sync_memory());
+    if let Some(mode) = saved_mode {
+        terminal::restore_mode(&mode)?;
+    }
+    if gdb_address.is_none() && result.is_ok() && !vm.is_halted() {
+        eprintln!("\nStopped by the break key at x{:04X}.", vm.pc());
+    }
+    if stats {
+        eprint!("{}", statistics.borrow().report(&vm.metrics()));
+    } else if vm.cycle_model().is_some() {
+        eprintln!("Cycles: {}", vm.metrics().cycles);
+    }
+    if simulate_caches {
+        eprint!("{}", caches.borrow().report());
+    }
+    if let Some(path) = mix {
+        let statistics = statistics.borrow();
+        let report = if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            statistics.mix_json()
+        } else {
+            statistics.mix_csv()
+        };
+        write(path, report.as_bytes())?;
+    }
+    result
+}
+
+/// Where `--trace` writes: the file at `path`, or standard output for `-`,
+/// and whether that is a terminal that `--color` may color
+fn trace_output(path: &str) -> Result<(Box<dyn Write>, bool), VMError> {
+    if path == "-" {
+        let stdout = io::stdout();
+        let terminal = stdout.is_terminal();
+        return Ok((Box::new(stdout), terminal));
+    }
+    let file = fs::File::create(path).map_err(|error| {
+        VMError::OpenFile(IoError::caused_by(
+            format!("Could not create {path}"),
+            error,
+        ))
+    })?;
+    Ok((Box::new(BufWriter::new(file)), false))
+}
+
+/// Re-executes a program against a trace recorded with `--trace-binary`,
+/// reporting the first step that changed different state
+fn verify_trace(args: &[String]) -> Result<(), VMError> {
+    let Some((path, rest)) = args.split_first() else {
+        return Err(VMError::InvalidArgument(String::from(
+            "verify requires a trace file",
+        )));
+    };
+    let recording = fs::read(path).map_err(|error| {
+        VMError::ReadFile(IoError::caused_by(format!("Could not read {path}"), error))
+    })?;
+    let (mut vm, _) = configure(rest)?;
+    let saved_mode = terminal::enable_raw_mode().ok();
+    let result = trace::verify(&mut vm, &recording);
+    if let Some(mode) = saved_mode {
+        terminal::restore_mode(&mode)?;
+    }
+    eprintln!("Verified {} steps.", result?);
+    Ok(())
+}
+
+/// Prints where two traces, text or binary, first differ
+fn diff_traces(args: &[String]) -> Result<(), VMError> {
+    let mut context = 3;
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--context" => {
+                let count = args.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--context requires a count"))
+                })?;
+                context = count
+                    .parse()
+                    .map_err(|_| VMError::InvalidArgument(format!("Invalid context {count}")))?;
+            }
+            path => paths.push(path),
+        }
+    }
+    let [left, right] = paths.as_slice() else {
+        return Err(VMError::InvalidArgument(String::from(
+            "trace-diff requires two trace files",
+        )));
+    };
+    let read = |path: &str| {
+        let bytes = fs::read(path).map_err(|error| {
+            VMError::ReadFile(IoError::caused_by(format!("Could not read {path}"), error))
+        })?;
+        trace::read_lines(&bytes)
+    };
+    let (left_lines, right_lines) = (read(left)?, read(right)?);
+    match trace::first_divergence(&left_lines, &right_lines, context) {
+        None => {
+            println!("The traces are identical ({} steps).", left_lines.len());
+            Ok(())
+        }
+        Some(divergence) => {
+            print!("--- {left}\n+++ {right}\n{divergence}");
+            Err(VMError::TraceMismatch(format!(
+                "The traces differ from step {}",
+                divergence.step
+            )))
+        }
+    }
+}
+
+fn run_repl(args: &[String]) -> Result<(), VMError> {
+    match configure(args)? {
+        (vm, None) => repl::run(vm),
+        (_, Some(_)) => Err(VMError::InvalidArgument(String::from(
+            "--gdb cannot be used with repl",
+        ))),
+    }
+}
+
+/// Assembles each source into an image and a symbol table next to it, like
+/// lc3as, and a listing with `--listing`. With `--link OUTPUT` the programs
+/// are linked into a single image at `OUTPUT` instead.
+fn assemble_files(args: &[String]) -> Result<(), VMError> {
+    let mut listing = false;
+    let mut checksum = false;
+    let mut output = None;
+    let mut sources = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--listing" => listing = true,
+            "--checksum" => checksum = true,
+            "--link" => {
+                output = Some(args.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--link requires an output file"))
+                })?);
+            }
+            source => sources.push(source),
+        }
+    }
+    if sources.is_empty() {
+        return Err(VMError::InvalidArgument(String::from(
+            "asm requires a source file",
+        )));
+    }
+    let mut programs = Vec::new();
+    for path in sources {
+        let source = fs::read_to_string(path).map_err(|e| {
+            VMError::ReadFile(IoError::caused_by(format!("Could not read {path}"), e))
+        })?;
+        let assembly = assemble_with_diagnostics(&source, None).map_err(|diagnostics| {
+            VMError::Assembly(format!("{path}: {}", report(&source, &diagnostics)))
+        })?;
+        let path = Path::new(path);
+        if listing {
+            write(
+                &path.with_extension("lst"),
+                assembly.listing(&source).as_bytes(),
+            )?;
+        }
+        if output.is_none() {
+            assembly.ensure_linked().map_err(|_| {
+                VMError::Link(format!(
+                    "{} uses .EXTERNAL labels, assemble it with --link",
+                    path.display()
+                ))
+            })?;
+            write_program(path, &assembly, checksum)?;
+        }
+        programs.push(assembly);
+    }
+    match output {
+        Some(output) => write_program(Path::new(output), &link(&programs)?, checksum),
+        None => Ok(()),
+    }
+}
+
+/// Runs every program of a manifest and prints a table of the outcomes,
+/// failing if any program did not pass
+fn run_batch(args: &[String]) -> Result<(), VMError> {
+    let [manifest] = args else {
+        return Err(VMError::InvalidArgument(String::from(
+            "batch requires one manifest file",
+        )));
+    };
+    let report = Manifest::load(Path::new(manifest))?.run();
+    println!("{report}");
+    match report.failed() {
+        0 => Ok(()),
+        failed => Err(VMError::BatchFailed(format!(
+            "{failed} of {} programs did not pass",
+            report.outcomes.len()
+        ))),
+    }
+}
+
+/// Runs a submission against a suite, printing the results in `--format`,
+/// or writing them to `--output` and printing the table of `batch`
+fn grade(args: &[String]) -> Result<(), VMError> {
+    let mut format = Format::Junit;
+    let mut output = None;
+    let mut files = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => {
+                format = args
+                    .next()
+                    .ok_or_else(|| {
+                        VMError::InvalidArgument(String::from("--format requires junit or json"))
+                    })?
+                    .parse()?;
+            }
+            "--output" => {
+                output = Some(args.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--output requires a file"))
+                })?);
+            }
+            _ => files.push(arg),
+        }
+    }
+    let Some((suite, images)) = files.split_first() else {
+        return Err(VMError::InvalidArgument(String::from(
+            "grade requires a suite and the images of the submission",
+        )));
+    };
+    let images: Vec<PathBuf> = images.iter().map(PathBuf::from).collect();
+    let suite = Suite::load(Path::new(suite), &images)?;
+    let report = suite.run();
+    let results = format.render(&suite.name, &report);
+    match output {
+        Some(path) => {
+            write(Path::new(path), results.as_bytes())?;
+            println!("{report}");
+        }
+        None => print!("{results}"),
+    }
+    match report.failed() {
+        0 => Ok(()),
+        failed => Err(VMError::BatchFailed(format!(
+            "{failed} of {} cases did not pass",
+            report.outcomes.len()
+        ))),
+    }
+}
+
+/// Runs images in a `Sandbox` and prints how the run ended as JSON. Faults
+/// of the program are results, only mistakes in the arguments fail.
+fn run_sandboxed(args: &[String]) -> Result<(), VMError> {
+    let mut sandbox = Sandbox::new();
+    let mut input = Vec::new();
+    let mut images = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |what: &str| {
+            args.next()
+                .ok_or_else(|| VMError::InvalidArgument(format!("{arg} requires {what}")))
+        };
+        match arg.as_str() {
+            "--limit" => {
+                let limit = value("a number of instructions")?;
+                sandbox.instruction_limit = limit
+                    .parse()
+                    .ok()
+                    .filter(|&limit| limit > 0)
+                    .ok_or_else(|| VMError::InvalidArgument(format!("Invalid limit {limit}")))?;
+            }
+            "--output-limit" => {
+                let limit = value("a number of characters")?;
+                sandbox.output_limit =
+                    limit
+                        .parse()
+                        .ok()
+                        .filter(|&limit| limit > 0)
+                        .ok_or_else(|| {
+                            VMError::InvalidArgument(format!("Invalid output limit {limit}"))
+                        })?;
+            }
+            "--timeout" => sandbox.timeout = parse_timeout(value("a number of seconds")?)?,
+            "--input" => {
+                let path = value("a file")?;
+                input = fs::read(path).map_err(|e| {
+                    VMError::ReadFile(IoError::caused_by(format!("Could not read {path}"), e))
+                })?;
+            }
+            "--env" => {
+                let variable = value("NAME=VALUE")?;
+                let Some((name, value)) = variable.split_once('=') else {
+                    return Err(VMError::InvalidArgument(format!(
+                        "Invalid variable {variable}, expected NAME=VALUE"
+                    )));
+                };
+                sandbox.env.insert(name.to_string(), value.to_string());
+            }
+            path => images.push(read_image_file(path)?),
+        }
+    }
+    if images.is_empty() {
+        return Err(VMError::InvalidArgument(String::from(
+            "sandbox requires at least one image file",
+        )));
+    }
+    let images: Vec<&[u8]> = images.iter().map(Vec::as_slice).collect();
+    print!("{}", sandbox.run(&images, &input).to_json());
+    Ok(())
+}
+
+/// Prints an image as assembly source, using the symbol table next to it
+/// if there is one
+fn disassemble_file(args: &[String]) -> Result<(), VMError> {
+    let [path] = args else {
+        return Err(VMError::InvalidArgument(String::from(
+            "disasm requires one image file",
+        )));
+    };
+    let bytes = read_image_file(path)?;
+    let image = Image::parse(&bytes).map_err(|error| match error {
+        VMError::InvalidImage(reason) => VMError::InvalidImage(format!("{path}: {reason}")),
+        error => error,
+    })?;
+    let symbols = fs::read_to_string(symbol_path(path))
+        .map(|text| SymbolTable::parse(&text))
+        .unwrap_or_default();
+    print!(
+        "{}",
+        disassemble_program(image.origin, &image.words, &symbols)
+    );
+    Ok(())
+}
+
+/// Assembles like lc3as: `FILE` or `FILE.asm` into `FILE.obj` and
+/// `FILE.sym`, printing its pass messages. `-hex` writes the image as text
+/// to `FILE.hex` instead of `FILE.obj`.
+fn lc3as(args: &[String]) -> Result<(), VMError> {
+    let (hex, path) = match args {
+        [flag, path] if flag == "-hex" => (true, path),
+        [path] => (false, path),
+        _ => {
+            return Err(VMError::InvalidArgument(String::from(
+                "Usage: lc3as [-hex] <ASM filename>",
+            )))
+        }
+    };
+    let path = if path.ends_with(".asm") {
+        path.clone()
+    } else {
+        format!("{path}.asm")
+    };
+    let source = fs::read_to_string(&path)
+        .map_err(|e| VMError::ReadFile(IoError::caused_by(format!("Could not read {path}"), e)))?;
+    println!("STARTING PASS 1");
+    let assembly = assemble_with_diagnostics(&source, None).map_err(|diagnostics| {
+        println!("{} errors found in first pass.", diagnostics.len());
+        VMError::Assembly(format!("{path}: {}", report(&source, &diagnostics)))
+    })?;
+    assembly.ensure_linked()?;
+    println!("0 errors found in first pass.\nSTARTING PASS 2\n0 errors found in second pass.");
+    let path = Path::new(&path);
+    if hex {
+        write(&path.with_extension("hex"), assembly.hex().as_bytes())?;
+        write(
+            &path.with_extension("sym"),
+            assembly.symbols.to_sym().as_bytes(),
+        )
+    } else {
+        write_program(path, &assembly, false)
+    }
+}
+
+/// Writes the image of a program at `path` with an `.obj` extension,
+/// followed by a checksum footer if `checksum` is set, and its symbol table
+/// with a `.sym` one
+fn write_program(path: &Path, program: &Assembly, checksum: bool) -> Result<(), VMError> {
+    let mut image = program.image();
+    if checksum {
+        append_checksum(&mut image);
+    }
+    write(&path.with_extension("obj"), &image)?;
+    write(
+        &path.with_extension("sym"),
+        program.symbols.to_sym().as_bytes(),
+    )
+}
+
+fn write(path: &Path, contents: &[u8]) -> Result<(), VMError> {
+    fs::write(path, contents).map_err(|e| {
+        VMError::StandardIO(IoError::caused_by(
+            format!("Could not write {}", path.display()),
+            e,
+        ))
+    })
+}
+
+/// Serves the debugger commands on a Unix socket, after loading the files
+#[cfg(unix)]
+fn serve_remote(args: &[String]) -> Result<(), VMError> {
+    let Some((socket, files)) = args.split_first() else {
+        return Err(VMError::InvalidArgument(String::from(
+            "remote requires a socket path",
+        )));
+    };
+    let mut session = lc3_vm::remote::Session::new(VM::new());
+    for file in files {
+        if let Some(output) = session.handle(&format!("file {file}")) {
+            println!("{output}");
+        }
+    }
+    lc3_vm::remote::serve_session(session, socket)
+}
+
+#[cfg(not(unix))]
+fn serve_remote(_args: &[String]) -> Result<(), VMError> {
+    Err(VMError::InvalidArgument(String::from(
+        "remote control needs Unix sockets",
+    )))
+}
+
+#[cfg(feature = "tui")]
+fn run_tui(args: &[String]) -> Result<(), VMError> {
+    match configure(args)? {
+        (vm, None) => lc3_vm::tui::run(vm),
+        (_, Some(_)) => Err(VMError::InvalidArgument(String::from(
+            "--gdb cannot be used with tui",
+        ))),
+    }
+}
+
+#[cfg(not(feature = "tui"))]
+fn run_tui(_args: &[String]) -> Result<(), VMError> {
+    Err(VMError::InvalidArgument(String::from(
+        "lc3-vm was built without the `tui` feature",
+    )))
+}
+
+#[cfg(feature = "persistent")]
+fn persist_memory(vm: &mut VM, path: &str) -> Result<(), VMError> {
+    vm.persist_memory(Path::new(path))
+}
+
+#[cfg(not(feature = "persistent"))]
+fn persist_memory(_vm: &mut VM, _path: &str) -> Result<(), VMError> {
+    Err(VMError::InvalidArgument(String::from(
+        "lc3-vm was built without the `persistent` feature",
+    )))
+}
+
+/// Runs the program under gdb, telling the user where to connect
+fn serve_gdb(vm: &mut VM, address: &str) -> Result<(), VMError> {
+    let listener = gdb::listen(address)?;
+    eprintln!("Waiting for gdb on {address}");
+    let (stream, peer) = gdb::accept(&listener)?;
+    eprintln!("gdb connected from {peer}");
+    gdb::serve_connection(vm, stream)
+}
+
+/// Creates a VM from the options and images on the command line, also
+/// returning the address to serve gdb on if requested
+fn configure(args: &[String]) -> Result<(VM, Option<&String>), VMError> {
+    let mut vm = VM::new();
+    let mut gdb_address = None;
+    let mut images = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--stack" => {
+                let spec = args.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--stack requires a region"))
+                })?;
+                vm.set_stack_checker(parse_stack(spec)?);
+            }
+            "--loop-threshold" => {
+                let threshold = args.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--loop-threshold requires a count"))
+                })?;
+                let threshold = threshold.parse().map_err(|_| {
+                    VMError::InvalidArgument(format!("Invalid loop threshold {threshold}"))
+                })?;
+                vm.set_loop_detector(LoopDetector::new(threshold));
+            }
+            "--speed" => {
+                let speed = args.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--speed requires a rate"))
+                })?;
+                vm.set_speed(parse_speed(speed)?);
+            }
+            "--virtual-time" => {
+                let rate = args.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--virtual-time requires a rate"))
+                })?;
+                match parse_speed(rate)? {
+                    Speed::InstructionsPerSecond(rate) => {
+                        vm.set_time_source(TimeSource::Virtual(rate));
+                    }
+                    Speed::Unlimited | Speed::CyclesPerSecond(_) => {
+                        return Err(VMError::InvalidArgument(String::from(
+                            "--virtual-time requires a number of instructions per second",
+                        )))
+                    }
+                }
+            }
+            "--cycles" => {
+                let model = args.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--cycles requires a model"))
+                })?;
+                let model = if Path::new(model).is_file() {
+                    fs::read_to_string(model).map_err(|e| {
+                        VMError::ReadFile(IoError::caused_by(format!("Could not read {model}"), e))
+                    })?
+                } else {
+                    model.clone()
+                };
+                vm.set_cycle_model(Some(model.parse()?));
+            }
+            "--timeout" => {
+                let seconds = args.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--timeout requires a number of seconds"))
+                })?;
+                vm.set_timeout(Some(parse_timeout(seconds)?))?;
+            }
+            "--gdb" => {
+                let address = args.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--gdb requires an address"))
+                })?;
+                gdb_address = Some(address);
+            }
+            "--serial" => {
+                let address = args.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--serial requires an address"))
+                })?;
+                eprintln!("Waiting for a serial connection on {address}");
+                vm.set_serial(SerialPort::listen(address)?)?;
+            }
+            "--break-trap" => {
+                let vector = args.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--break-trap requires a vector"))
+                })?;
+                let vector = match vector.as_str() {
+                    "all" => None,
+                    vector => Some(parse_number(vector)?),
+                };
+                if vector.is_some_and(|vector| vector > 0xFF) {
+                    return Err(VMError::InvalidArgument(String::from(
+                        "Trap vectors range from x00 to xFF",
+                    )));
+                }
+                vm.add_trap_break(vector);
+            }
+            "--break-interrupt" => vm.set_break_on_interrupt(true),
+            "--break-device" => {
+                let spec = args.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from(
+                        "--break-device requires a device register",
+                    ))
+                })?;
+                let (address, access) = match spec.split_once(':') {
+                    None => (spec.as_str(), DeviceAccess::Any),
+                    Some((address, "read")) => (address, DeviceAccess::Read),
+                    Some((address, "write")) => (address, DeviceAccess::Write),
+                    Some((_, access)) => {
+                        return Err(VMError::InvalidArgument(format!(
+                            "Unknown access `{access}`, expected read or write"
+                        )))
+                    }
+                };
+                vm.add_device_break(parse_number(address)?, access)?;
+            }
+            "--extended-traps" => vm.set_extended_traps(true),
+            "--strict-traps" => vm.set_strict_traps(true),
+            "--profile" => {
+                let profile = args.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--profile requires a name"))
+                })?;
+                vm.set_conformance(profile.parse::<Profile>()?);
+            }
+            "--addresses" => {
+                let mode = args.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--addresses requires a mode"))
+                })?;
+                let mut conformance = vm.conformance();
+                conformance.addresses = match mode.as_str() {
+                    "checked" => AddressArithmetic::Checked,
+                    "wrap" => AddressArithmetic::Wrapping,
+                    _ => {
+                        return Err(VMError::InvalidArgument(format!(
+                            "Unknown address mode {mode}, expected checked or wrap"
+                        )))
+                    }
+                };
+                vm.set_conformance(conformance);
+            }
+            "--key" => {
+                let spec = args.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--key requires a binding"))
+                })?;
+                let mut config = vm.console_config().clone();
+                config.key_map.parse_binding(spec)?;
+                vm.set_console_config(config);
+            }
+            "--newline" => {
+                let newline = args.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--newline requires a line ending"))
+                })?;
+                let mut config = vm.console_config().clone();
+                config.newline = match newline.as_str() {
+                    "lf" => Newline::Lf,
+                    "cr" => Newline::Cr,
+                    "crlf" => Newline::CrLf,
+                    _ => {
+                        return Err(VMError::InvalidArgument(format!(
+                            "Invalid line ending {newline}, expected lf, cr or crlf"
+                        )))
+                    }
+                };
+                vm.set_console_config(config);
+            }
+            "--scancodes" => {
+                let mut config = vm.console_config().clone();
+                config.scancodes = true;
+                vm.set_console_config(config);
+            }
+            "--ansi" => {
+                let mut config = vm.console_config().clone();
+                config.ansi = true;
+                vm.set_console_config(config);
+            }
+            "--encoding" => {
+                let encoding = args.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--encoding requires an encoding"))
+                })?;
+                let mut config = vm.console_config().clone();
+                config.encoding = match encoding.as_str() {
+                    "ascii" => Encoding::Ascii,
+                    "latin1" => Encoding::Latin1,
+                    "cp437" => Encoding::CodePage(&CP437),
+                    "utf8" => Encoding::Utf8,
+                    _ => {
+                        return Err(VMError::InvalidArgument(format!(
+                            "Invalid encoding {encoding}, expected ascii, latin1, cp437 or utf8"
+                        )))
+                    }
+                };
+                vm.set_console_config(config);
+            }
+            "--echo" => {
+                let traps = args.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--echo requires a list of traps"))
+                })?;
+                let mut config = vm.console_config().clone();
+                config.getc_echo = false;
+                config.in_echo = false;
+                for trap in traps.split(',') {
+                    match trap {
+                        "getc" => config.getc_echo = true,
+                        "in" => config.in_echo = true,
+                        "none" => {}
+                        _ => {
+                            return Err(VMError::InvalidArgument(format!(
+                                "Invalid echo {trap}, expected getc, in or none"
+                            )))
+                        }
+                    }
+                }
+                vm.set_console_config(config);
+            }
+            "--entry" => {
+                let address = args.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--entry requires an address"))
+                })?;
+                vm.set_entry(parse_number(address)?);
+            }
+            "--display" => vm.enable_display()?,
+            "--channel" => {
+                let spec = args.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--channel requires a channel"))
+                })?;
+                let mut parts = spec.splitn(3, ':');
+                let (Some(name), Some(base), Some(output)) =
+                    (parts.next(), parts.next(), parts.next())
+                else {
+                    return Err(VMError::InvalidArgument(format!(
+                        "Invalid channel {spec}, expected NAME:ADDRESS:OUTPUT"
+                    )));
+                };
+                vm.add_channel(
+                    name,
+                    parse_number(base)?,
+                    Box::new(OutputConsole::open(output)?),
+                )?;
+            }
+            "--devices" => {
+                let path = args.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--devices requires a file"))
+                })?;
+                let tree = DeviceTree::load(Path::new(path))?;
+                for device in &tree.devices {
+                    if let DeviceSpec::Serial { address } = device {
+                        eprintln!("Waiting for a serial connection on {address}");
+                    }
+                }
+                tree.apply(&mut vm)?;
+            }
+            "--unmapped" => {
+                let policy = args.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--unmapped requires a policy"))
+                })?;
+                vm.set_unmapped_access(policy.parse::<UnmappedAccess>()?);
+            }
+            "--dma" => vm.enable_dma()?,
+            "--env" => {
+                let variable = args.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--env requires a variable"))
+                })?;
+                match variable.split_once('=') {
+                    Some((name, value)) => vm.set_env_var(name, value),
+                    None if !vm.expose_env_var(variable) => {
+                        eprintln!("Warning: {variable} is not set, GETENV will not find it");
+                    }
+                    None => {}
+                }
+            }
+            "--persist" => {
+                let path = args.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--persist requires a file"))
+                })?;
+                if !images.is_empty() {
+                    // the file would replace the images already loaded
+                    return Err(VMError::InvalidArgument(String::from(
+                        "--persist must come before the images",
+                    )));
+                }
+                persist_memory(&mut vm, path)?;
+            }
+            "--files" => {
+                let directory = args.next().ok_or_else(|| {
+                    VMError::InvalidArgument(String::from("--files requires a directory"))
+                })?;
+                vm.enable_file_traps(Box::new(DirectoryFileSystem::new(directory)?));
+            }
+            "--" => {
+                let program = images.first().cloned().unwrap_or_default();
+                vm.set_args([program].into_iter().chain(args.by_ref().cloned()))?;
+            }
+            path => {
+                vm.read_image(path)?;
+                images.push(path.to_string());
+            }
+        }
+    }
+    if vm.cycle_model().is_some() {
+        // with a cycle model the rates count cycles
+        if let Speed::InstructionsPerSecond(rate) = vm.speed() {
+            vm.set_speed(Speed::CyclesPerSecond(rate));
+        }
+        if let TimeSource::Virtual(rate) = vm.time_source() {
+            vm.set_time_source(TimeSource::VirtualCycles(rate));
+        }
+    }
+    Ok((vm, gdb_address))
+}
+
+/// Parses `LIMIT:BASE[:REGISTER]`, e.g. `x2F00:x3000:R6`
+fn parse_stack(spec: &str) -> Result<StackChecker, VMError> {
+    let mut parts = spec.split(':');
+    let (Some(limit), Some(base)) = (parts.next(), parts.next()) else {
+        return Err(VMError::InvalidArgument(format!(
+            "Invalid stack region {spec}"
+        )));
+    };
+    let checker = StackChecker::new(parse_number(limit)?, parse_number(base)?)?;
+    match parts.next() {
+        Some(register) => {
+            let number = parse_number(register.trim_start_matches(['R', 'r']))?;
+            let register = Register::new(number).ok_or_else(|| {
+                VMError::InvalidArgument(format!("Register {register} does not exist"))
+            })?;
+            Ok(checker.with_register(register))
+        }
+        None => Ok(checker),
+    }
+}
+
+/// Parses a positive number of seconds, possibly fractional
+fn parse_timeout(text: &str) -> Result<Duration, VMError> {
+    text.parse()
+        .ok()
+        .filter(|&seconds: &f64| seconds > 0.0)
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| VMError::InvalidArgument(format!("Invalid timeout {text}")))
+}
+
+/// Parses `unlimited` or a positive number of instructions per second
+fn parse_speed(text: &str) -> Result<Speed, VMError> {
+    if text == "unlimited" {
+        return Ok(Speed::Unlimited);
+    }
+    match text.parse() {
+        Ok(rate) if rate > 0 => Ok(Speed::InstructionsPerSecond(rate)),
+        _ => Err(VMError::InvalidArgument(format!("Invalid speed {text}"))),
+    }
+}
+
+/// Parses a decimal number or an hexadecimal one prefixed by `x`/`0x`
+fn parse_number(text: &str) -> Result<u16, VMError> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix('x')) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| VMError::InvalidArgument(format!("Invalid number {text}")))
 }
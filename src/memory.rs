@@ -0,0 +1,573 @@
+use alloc::{boxed::Box, format, rc::Rc, string::String, vec::Vec};
+use core::{
+    hash::{Hash, Hasher},
+    ops::Range,
+};
+#[cfg(feature = "std")]
+use std::{fs::File, io::Read};
+
+#[cfg(feature = "std")]
+use crate::errors::IoError;
+#[cfg(feature = "persistent")]
+use crate::persistent::MappedWords;
+use crate::{
+    errors::VMError,
+    mmio::{DeviceRegion, MmioMap},
+};
+
+pub const MEMORY_SIZE: usize = 1 << 16;
+
+/// First address of the memory mapped device registers
+pub const MMIO_START: u16 = 0xFE00;
+/// Keyboard status memory mapped register
+pub const KBSR: u16 = 0xFE00;
+/// Keyboard data memory mapped register
+pub const KBDR: u16 = 0xFE02;
+
+/// Words per page of a memory shared with its forks, the unit copied when
+/// one side first writes it. Smaller pages copy less on a write and more
+/// page pointers on a fork; 1024 words measured best on `benches/vm.rs`.
+const PAGE_WORDS: usize = 1 << PAGE_SHIFT;
+const PAGE_SHIFT: u32 = 10;
+const PAGE_MASK: usize = PAGE_WORDS - 1;
+
+type Page = [u16; PAGE_WORDS];
+
+/// Where the words of a `Memory` live
+enum Storage {
+    Heap(Box<[u16; MEMORY_SIZE]>),
+    /// Pages shared with forks until written, see `Memory::fork`
+    Shared(Vec<Rc<Page>>),
+    /// A memory file, see `Memory::persist_to`
+    #[cfg(feature = "persistent")]
+    Mapped(MappedWords),
+}
+
+impl Storage {
+    #[inline]
+    fn get(&self, index: usize) -> Option<u16> {
+        match self {
+            Storage::Heap(words) => words.get(index).copied(),
+            Storage::Shared(pages) => pages
+                .get(index >> PAGE_SHIFT)?
+                .get(index & PAGE_MASK)
+                .copied(),
+            #[cfg(feature = "persistent")]
+            Storage::Mapped(words) => words.get(index).copied(),
+        }
+    }
+
+    /// The word at `index`, copying its page first if it is shared
+    #[inline]
+    fn get_mut(&mut self, index: usize) -> Option<&mut u16> {
+        match self {
+            Storage::Heap(words) => words.get_mut(index),
+            Storage::Shared(pages) => {
+                Rc::make_mut(pages.get_mut(index >> PAGE_SHIFT)?).get_mut(index & PAGE_MASK)
+            }
+            #[cfg(feature = "persistent")]
+            Storage::Mapped(words) => words.get_mut(index),
+        }
+    }
+
+    /// The words in `range`, if they are in memory and not split across
+    /// shared pages
+    fn words(&self, range: Range<usize>) -> Option<&[u16]> {
+        match self {
+            Storage::Heap(words) => words.get(range),
+            Storage::Shared(pages) => {
+                let page = pages.get(range.start >> PAGE_SHIFT)?;
+                let offset = range.start & PAGE_MASK;
+                page.get(offset..offset.checked_add(range.len())?)
+            }
+            #[cfg(feature = "persistent")]
+            Storage::Mapped(words) => words.get(range),
+        }
+    }
+
+    /// All the words in one slice, gathering shared pages into words of
+    /// their own first
+    fn flat_mut(&mut self) -> &mut [u16] {
+        if let Storage::Shared(pages) = self {
+            let mut words = Box::new([0; MEMORY_SIZE]);
+            for (chunk, page) in words.chunks_exact_mut(PAGE_WORDS).zip(pages.iter()) {
+                chunk.copy_from_slice(page.as_slice());
+            }
+            *self = Storage::Heap(words);
+        }
+        match self {
+            Storage::Heap(words) => words.as_mut_slice(),
+            Storage::Shared(_) => &mut [],
+            #[cfg(feature = "persistent")]
+            Storage::Mapped(words) => words,
+        }
+    }
+
+    fn clear(&mut self) {
+        match self {
+            Storage::Heap(words) => words.fill(0),
+            // pointing every page to the same zeros copies nothing
+            Storage::Shared(pages) => {
+                let zeros = Rc::new([0; PAGE_WORDS]);
+                pages.fill_with(|| Rc::clone(&zeros));
+            }
+            #[cfg(feature = "persistent")]
+            Storage::Mapped(words) => words.fill(0),
+        }
+    }
+
+    /// Pages holding the same words, shared with `self` until either side
+    /// writes them. Words on the heap move to pages first, which is the
+    /// only copy; a memory file stays in the file and gives a copy.
+    fn share(&mut self) -> Storage {
+        let pages = match self {
+            Storage::Shared(pages) => return Storage::Shared(pages.clone()),
+            Storage::Heap(words) => paginate(words.as_slice()),
+            #[cfg(feature = "persistent")]
+            Storage::Mapped(words) => return Storage::Shared(paginate(words)),
+        };
+        *self = Storage::Shared(pages.clone());
+        Storage::Shared(pages)
+    }
+}
+
+/// The words of `words` in pages, all the pages of zeros sharing one
+fn paginate(words: &[u16]) -> Vec<Rc<Page>> {
+    let zeros = Rc::new([0; PAGE_WORDS]);
+    words
+        .chunks_exact(PAGE_WORDS)
+        .map(|chunk| match Page::try_from(chunk) {
+            Ok(page) if page != *zeros => Rc::new(page),
+            _ => Rc::clone(&zeros),
+        })
+        .collect()
+}
+
+/// Hashes like the slice of all the words, whatever the storage
+impl Hash for Storage {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Storage::Heap(words) => words.as_slice().hash(state),
+            Storage::Shared(pages) => {
+                state.write_usize(MEMORY_SIZE);
+                for page in pages {
+                    Hash::hash_slice(page.as_slice(), state);
+                }
+            }
+            #[cfg(feature = "persistent")]
+            Storage::Mapped(words) => (**words).hash(state),
+        }
+    }
+}
+
+pub struct Memory {
+    memory: Storage,
+    /// First address past the RAM, accesses from there up to the device
+    /// registers fail
+    end: u16,
+    device_accessed: bool,
+    mmio: MmioMap,
+}
+
+impl Hash for Memory {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.memory.hash(state);
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Memory {
+            memory: Storage::Heap(Box::new([0; MEMORY_SIZE])),
+            end: MMIO_START,
+            device_accessed: false,
+            mmio: MmioMap::standard(),
+        }
+    }
+
+    /// Memory with only `words` words of RAM starting at x0000, at most the
+    /// x0000-xFDFF below the device registers
+    pub fn with_size(words: usize) -> Result<Self, VMError> {
+        let end = u16::try_from(words)
+            .ok()
+            .filter(|end| (1..=MMIO_START).contains(end))
+            .ok_or_else(|| {
+                VMError::InvalidArgument(format!(
+                    "Memory size must be between 1 and {MMIO_START:#06x} words"
+                ))
+            })?;
+        Ok(Memory { end, ..Self::new() })
+    }
+
+    /// Number of words of RAM
+    pub fn size(&self) -> usize {
+        usize::from(self.end)
+    }
+
+    #[inline]
+    pub fn read(&mut self, address: u16) -> Result<u16, VMError> {
+        if address >= self.end {
+            if address < MMIO_START {
+                return Err(out_of_bounds("read", address));
+            }
+            self.device_accessed = true;
+        }
+        match self.memory.get(usize::from(address)) {
+            Some(value) => Ok(value),
+            None => Err(out_of_bounds("read", address)),
+        }
+    }
+
+    /// Reads a word without recording device accesses
+    pub fn peek(&self, address: u16) -> u16 {
+        self.memory.get(usize::from(address)).unwrap_or_default()
+    }
+
+    #[inline]
+    pub fn write(&mut self, address: u16, value: u16) -> Result<(), VMError> {
+        if (self.end..MMIO_START).contains(&address) {
+            return Err(out_of_bounds("write", address));
+        }
+        match self.memory.get_mut(usize::from(address)) {
+            Some(cell) => {
+                *cell = value;
+                Ok(())
+            }
+            None => Err(out_of_bounds("write", address)),
+        }
+    }
+
+    /// The `length` words from `start`, if they are all in memory. A
+    /// memory shared with forks only gives them after `unshare`.
+    pub(crate) fn words(&self, start: u16, length: u16) -> Option<&[u16]> {
+        let begin = usize::from(start);
+        self.memory
+            .words(begin..begin.checked_add(usize::from(length))?)
+    }
+
+    /// The `length` words from `start`, if they are all in memory, no
+    /// longer sharing them with forks
+    pub(crate) fn words_mut(&mut self, start: u16, length: u16) -> Option<&mut [u16]> {
+        let begin = usize::from(start);
+        self.memory
+            .flat_mut()
+            .get_mut(begin..begin.checked_add(usize::from(length))?)
+    }
+
+    /// Raw pointer to the whole memory, used by compiled code, no longer
+    /// sharing it with forks
+    #[cfg(all(feature = "jit", not(feature = "threaded")))]
+    pub(crate) fn as_mut_ptr(&mut self) -> *mut u16 {
+        self.memory.flat_mut().as_mut_ptr()
+    }
+
+    /// Gathers words shared with forks into words of its own, so they can
+    /// be borrowed as one slice
+    pub(crate) fn unshare(&mut self) {
+        self.memory.flat_mut();
+    }
+
+    /// A copy of the memory and its device map that shares the words with
+    /// `self` until either writes them, a page at a time. The first fork
+    /// moves the words into pages; later ones copy nothing but the page
+    /// pointers. See `VM::fork`.
+    pub fn fork(&mut self) -> Memory {
+        Memory {
+            memory: self.memory.share(),
+            end: self.end,
+            device_accessed: false,
+            mmio: self.mmio.clone(),
+        }
+    }
+
+    /// Keeps the words in the memory file at `path` from now on, see
+    /// `crate::persistent`. An existing file replaces the contents of the
+    /// memory, and a new one starts with them; the device registers are
+    /// cleared either way.
+    #[cfg(feature = "persistent")]
+    pub fn persist_to(&mut self, path: &std::path::Path) -> Result<(), VMError> {
+        self.memory = Storage::Mapped(MappedWords::open(path, self.memory.flat_mut())?);
+        self.clear_devices();
+        Ok(())
+    }
+
+    /// Writes the words of a memory file to disk, which the operating system
+    /// otherwise does at its own pace. Does nothing for other memories.
+    #[cfg(feature = "persistent")]
+    pub fn sync(&self) -> Result<(), VMError> {
+        match &self.memory {
+            Storage::Heap(_) | Storage::Shared(_) => Ok(()),
+            Storage::Mapped(words) => words.flush().map_err(|error| {
+                VMError::StandardIO(IoError::caused_by("Could not sync the memory file", error))
+            }),
+        }
+    }
+
+    /// Where the attached devices are mapped
+    pub fn mmio_map(&self) -> &MmioMap {
+        &self.mmio
+    }
+
+    /// Maps a device at `region`, failing if another device answers any of
+    /// its addresses
+    pub fn map_device(&mut self, region: DeviceRegion) -> Result<(), VMError> {
+        self.mmio.insert(region)
+    }
+
+    /// Maps the device `name` at several ranges at once, see
+    /// `MmioMap::insert_device`
+    pub fn map_device_ranges(&mut self, name: &str, ranges: &[(u16, u16)]) -> Result<(), VMError> {
+        self.mmio.insert_device(name, ranges)
+    }
+
+    /// Unmaps every region of the device `name`, returning false if it was
+    /// not mapped
+    pub fn unmap_device(&mut self, name: &str) -> bool {
+        self.mmio.remove(name)
+    }
+
+    /// Returns whether a device register was read since the last call
+    pub fn take_device_access(&mut self) -> bool {
+        core::mem::take(&mut self.device_accessed)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn read_image(&mut self, path: &str) -> Result<(), VMError> {
+        self.read_image_bytes(&read_image_file(path)?)
+    }
+
+    /// Zeroes the RAM and the device registers
+    pub fn clear(&mut self) {
+        self.memory.clear();
+    }
+
+    /// Zeroes the device registers, keeping the RAM
+    pub fn clear_devices(&mut self) {
+        for index in usize::from(MMIO_START)..MEMORY_SIZE {
+            // zeros are left alone so a shared page is not copied
+            if self.memory.get(index) != Some(0) {
+                if let Some(word) = self.memory.get_mut(index) {
+                    *word = 0;
+                }
+            }
+        }
+    }
+
+    /// Loads a big endian image whose first word is the origin address,
+    /// after checking that it fits in memory and matches its checksum
+    /// footer if it has one. Nothing is written if it does not.
+    pub fn read_image_bytes(&mut self, bytes: &[u8]) -> Result<(), VMError> {
+        let image = Image::parse(bytes)?;
+        self.check_fits(&image)?;
+        let mut address = image.origin;
+        for &word in &image.words {
+            self.write(address, word)?;
+            address = address.wrapping_add(1);
+        }
+        Ok(())
+    }
+
+    fn check_fits(&self, image: &Image) -> Result<(), VMError> {
+        let origin = image.origin;
+        let count = image.words.len();
+        let last = usize::from(origin).saturating_add(count).saturating_sub(1);
+        let last = u16::try_from(last).map_err(|_| {
+            VMError::InvalidImage(format!(
+                "Image of {count} words loaded at {origin:#06x} runs past xFFFF"
+            ))
+        })?;
+        // a gap between the RAM and the device registers cannot be loaded
+        if origin < MMIO_START && last >= self.end && self.end < MMIO_START {
+            return Err(VMError::InvalidImage(format!(
+                "Image of {count} words loaded at {origin:#06x} runs past the {} words of RAM",
+                self.size()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Words marking a checksum footer, `LC3S` in ASCII, followed by the CRC-16
+/// of every byte of the image before them
+const CHECKSUM_MARKER: [u16; 2] = [0x4C43, 0x3353];
+
+/// The contents of an object file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub origin: u16,
+    /// Words loaded from the origin on, without the checksum footer
+    pub words: Vec<u16>,
+}
+
+impl Image {
+    /// Parses a big endian image whose first word is the origin address,
+    /// verifying and removing its checksum footer if it has one
+    pub fn parse(bytes: &[u8]) -> Result<Image, VMError> {
+        let pairs = bytes.chunks_exact(2);
+        if !pairs.remainder().is_empty() {
+            return Err(VMError::InvalidImage(format!(
+                "Image has an odd number of bytes ({}), its last word is truncated",
+                bytes.len()
+            )));
+        }
+        let mut words: Vec<u16> = pairs
+            .filter_map(|pair| <[u8; 2]>::try_from(pair).ok())
+            .map(u16::from_be_bytes)
+            .collect();
+        if let [.., first, second, expected] = words[..] {
+            if [first, second] == CHECKSUM_MARKER {
+                let body = bytes
+                    .get(..bytes.len().saturating_sub(6))
+                    .unwrap_or_default();
+                let actual = crc16(body);
+                if actual != expected {
+                    return Err(VMError::InvalidImage(format!(
+                        "Checksum {actual:#06x} does not match the {expected:#06x} of the footer"
+                    )));
+                }
+                words.truncate(words.len().saturating_sub(3));
+            }
+        }
+        if words.is_empty() {
+            return Err(VMError::InvalidImage(String::from("Image has no origin")));
+        }
+        let origin = words.remove(0);
+        if words.is_empty() {
+            return Err(VMError::InvalidImage(format!(
+                "Image loaded at {origin:#06x} has no words after its origin"
+            )));
+        }
+        Ok(Image { origin, words })
+    }
+}
+
+/// Appends a checksum footer to `image`, verified when it is loaded
+pub fn append_checksum(image: &mut Vec<u8>) {
+    let checksum = crc16(image);
+    for word in CHECKSUM_MARKER.into_iter().chain([checksum]) {
+        image.extend(word.to_be_bytes());
+    }
+}
+
+/// CRC-16/CCITT-FALSE of `bytes`
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0xFFFF, |crc, &byte| {
+        (0..8).fold(crc ^ (u16::from(byte) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// The address an image loads at, from its first word
+pub fn image_origin(bytes: &[u8]) -> Option<u16> {
+    match bytes {
+        [high, low, ..] => Some(u16::from_be_bytes([*high, *low])),
+        _ => None,
+    }
+}
+
+/// First bytes of a gzip stream
+#[cfg(feature = "std")]
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+
+/// Bytes of the largest image: its origin, a word for every address and a
+/// checksum footer
+#[cfg(any(feature = "gzip", feature = "http"))]
+const MAX_IMAGE_BYTES: u64 = 131_080;
+
+/// Reads the bytes of the image file at `path`, decompressing it if it was
+/// compressed with gzip. With the `http` feature `path` can also be an
+/// `http://` or `https://` URL.
+#[cfg(feature = "std")]
+pub fn read_image_file(path: &str) -> Result<Vec<u8>, VMError> {
+    let bytes = if path.starts_with("http://") || path.starts_with("https://") {
+        download(path)?
+    } else {
+        let mut file = File::open(path).map_err(|e| {
+            VMError::OpenFile(IoError::caused_by(format!("Could not open {path}"), e))
+        })?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes).map_err(|e| {
+            VMError::ReadFile(IoError::caused_by(format!("Could not read {path}"), e))
+        })?;
+        bytes
+    };
+    if bytes.starts_with(&GZIP_MAGIC) {
+        return decompress(path, &bytes);
+    }
+    Ok(bytes)
+}
+
+#[cfg(feature = "http")]
+fn download(url: &str) -> Result<Vec<u8>, VMError> {
+    let failure = |e: ureq::Error| {
+        VMError::OpenFile(IoError::caused_by(
+            format!("Could not download {url}"),
+            std::io::Error::other(e),
+        ))
+    };
+    let response = ureq::get(url).call().map_err(failure)?;
+    response
+        .into_body()
+        .with_config()
+        .limit(MAX_IMAGE_BYTES)
+        .read_to_vec()
+        .map_err(failure)
+}
+
+#[cfg(all(feature = "std", not(feature = "http")))]
+fn download(url: &str) -> Result<Vec<u8>, VMError> {
+    Err(VMError::OpenFile(IoError::new(format!(
+        "Could not open {url}, loading images from URLs needs the `http` feature"
+    ))))
+}
+
+#[cfg(feature = "gzip")]
+fn decompress(path: &str, compressed: &[u8]) -> Result<Vec<u8>, VMError> {
+    let mut bytes = Vec::new();
+    flate2::read::GzDecoder::new(compressed)
+        .take(MAX_IMAGE_BYTES.saturating_add(1))
+        .read_to_end(&mut bytes)
+        .map_err(|e| {
+            VMError::ReadFile(IoError::caused_by(
+                format!("Could not decompress {path}"),
+                e,
+            ))
+        })?;
+    if u64::try_from(bytes.len()).map_or(true, |length| length > MAX_IMAGE_BYTES) {
+        return Err(VMError::InvalidImage(format!(
+            "{path} decompresses to more than the {MAX_IMAGE_BYTES} bytes of the largest image"
+        )));
+    }
+    Ok(bytes)
+}
+
+#[cfg(all(feature = "std", not(feature = "gzip")))]
+fn decompress(path: &str, _compressed: &[u8]) -> Result<Vec<u8>, VMError> {
+    Err(VMError::InvalidImage(format!(
+        "{path} is compressed with gzip, which needs the `gzip` feature"
+    )))
+}
+
+/// The symbol table file of the image at `path`, next to it with a `.sym`
+/// extension instead of `.obj` or `.obj.gz`
+#[cfg(feature = "std")]
+pub fn symbol_path(path: &str) -> std::path::PathBuf {
+    let image = path.strip_suffix(".gz").unwrap_or(path);
+    std::path::Path::new(image).with_extension("sym")
+}
+
+#[cold]
+fn out_of_bounds(access: &str, address: u16) -> VMError {
+    VMError::MemoryIndex(format!("Failed to {access} address {address:#06x}"))
+}
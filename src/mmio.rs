@@ -0,0 +1,149 @@
+//! The layout of the devices in the address space. Every device attached to
+//! a VM registers the addresses it answers, and a device whose addresses
+//! overlap another's is refused, so two devices never fight over a
+//! register. Debuggers show the map and tests assert on it:
+//!
+//! ```text
+//! xFE00-xFE02  keyboard
+//! xFE12-xFE1A  dma
+//! xFFFC        psr
+//! ```
+
+use alloc::{format, string::String, vec::Vec};
+use core::fmt;
+
+use crate::{
+    errors::VMError,
+    memory::{KBDR, KBSR},
+    vm::PSR,
+};
+
+/// Addresses answered by a device, `start` to `end` included
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceRegion {
+    pub name: String,
+    pub start: u16,
+    pub end: u16,
+}
+
+impl DeviceRegion {
+    /// Fails unless `start` is at most `end`
+    pub fn new(name: impl Into<String>, start: u16, end: u16) -> Result<Self, VMError> {
+        let name = name.into();
+        if start > end {
+            return Err(VMError::InvalidArgument(format!(
+                "Device `{name}` ends at x{end:04X} before its start x{start:04X}"
+            )));
+        }
+        Ok(DeviceRegion { name, start, end })
+    }
+
+    pub fn contains(&self, address: u16) -> bool {
+        (self.start..=self.end).contains(&address)
+    }
+
+    pub fn overlaps(&self, other: &DeviceRegion) -> bool {
+        self.start <= other.end && other.start <= self.end
+    }
+}
+
+/// `xFE00-xFE02` or `xFFFC` for a single word
+impl fmt::Display for DeviceRegion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.start == self.end {
+            f.pad(&format!("x{:04X}", self.start))
+        } else {
+            f.pad(&format!("x{:04X}-x{:04X}", self.start, self.end))
+        }
+    }
+}
+
+/// Regions of the attached devices, by address. A device may have several.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MmioMap {
+    regions: Vec<DeviceRegion>,
+}
+
+impl MmioMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The devices of every machine: the keyboard and the PSR
+    pub fn standard() -> Self {
+        MmioMap {
+            regions: Vec::from([
+                DeviceRegion {
+                    name: String::from("keyboard"),
+                    start: KBSR,
+                    end: KBDR,
+                },
+                DeviceRegion {
+                    name: String::from("psr"),
+                    start: PSR,
+                    end: PSR,
+                },
+            ]),
+        }
+    }
+
+    /// Adds `region`, failing if another device answers any of its
+    /// addresses. Regions of the same device may not overlap either.
+    pub fn insert(&mut self, region: DeviceRegion) -> Result<(), VMError> {
+        if let Some(taken) = self.regions.iter().find(|taken| taken.overlaps(&region)) {
+            return Err(VMError::DeviceConflict(format!(
+                "`{}` at {region} overlaps `{}` at {taken}",
+                region.name, taken.name
+            )));
+        }
+        let index = self
+            .regions
+            .partition_point(|mapped| mapped.start < region.start);
+        self.regions.insert(index, region);
+        Ok(())
+    }
+
+    /// Maps the device `name` at the `(start, end)` ranges, replacing its
+    /// regions if it was mapped. Nothing changes if a range is refused.
+    pub fn insert_device(&mut self, name: &str, ranges: &[(u16, u16)]) -> Result<(), VMError> {
+        let mut map = self.clone();
+        map.remove(name);
+        for &(start, end) in ranges {
+            map.insert(DeviceRegion::new(name, start, end)?)?;
+        }
+        *self = map;
+        Ok(())
+    }
+
+    /// Removes every region of the device `name`, returning false if it had
+    /// none
+    pub fn remove(&mut self, name: &str) -> bool {
+        let before = self.regions.len();
+        self.regions.retain(|region| region.name != name);
+        self.regions.len() != before
+    }
+
+    /// The regions, lowest address first
+    pub fn regions(&self) -> &[DeviceRegion] {
+        &self.regions
+    }
+
+    /// The region answering `address`, if any
+    pub fn region_at(&self, address: u16) -> Option<&DeviceRegion> {
+        self.regions.iter().find(|region| region.contains(address))
+    }
+
+    pub fn contains_device(&self, name: &str) -> bool {
+        self.regions.iter().any(|region| region.name == name)
+    }
+}
+
+/// One region per line, as in the module documentation
+impl fmt::Display for MmioMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for region in &self.regions {
+            writeln!(f, "{region:<12} {}", region.name)?;
+        }
+        Ok(())
+    }
+}
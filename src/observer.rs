@@ -0,0 +1,100 @@
+//! Callbacks for tools layered on top of the VM, like tracers and coverage
+//! collectors. While an observer is attached the VM executes one
+//! instruction at a time instead of running decoded blocks or native code.
+
+use alloc::{boxed::Box, rc::Rc, vec::Vec};
+use core::cell::RefCell;
+
+use crate::vm::VM;
+
+/// Receives execution events. Every callback does nothing by default, so
+/// observers only implement what they need.
+pub trait Observer {
+    /// Called before the instruction `raw` at `pc` executes
+    fn before_instruction(&mut self, _vm: &VM, _pc: u16, _raw: u16) {}
+
+    /// Called after the instruction `raw` at `pc` executed successfully
+    fn after_instruction(&mut self, _vm: &VM, _pc: u16, _raw: u16) {}
+
+    /// Called when an instruction or trap reads data memory, with the value
+    /// read. Instruction fetches are not reported.
+    fn on_mem_read(&mut self, _address: u16, _value: u16) {}
+
+    /// Called for every memory write by instructions, traps and devices
+    /// copying memory. Device registers the VM updates as the program polls
+    /// them are not reported.
+    fn on_mem_write(&mut self, _address: u16, _value: u16) {}
+
+    /// Called when the program executes `TRAP vector`, before the trap runs
+    fn on_trap(&mut self, _vector: u16) {}
+
+    /// Called for every character the VM writes to the console
+    fn on_output(&mut self, _character: char) {}
+}
+
+/// Lets the embedder keep a handle to an attached observer to read what it
+/// collected
+impl<T: Observer> Observer for Rc<RefCell<T>> {
+    fn before_instruction(&mut self, vm: &VM, pc: u16, raw: u16) {
+        self.borrow_mut().before_instruction(vm, pc, raw);
+    }
+
+    fn after_instruction(&mut self, vm: &VM, pc: u16, raw: u16) {
+        self.borrow_mut().after_instruction(vm, pc, raw);
+    }
+
+    fn on_mem_read(&mut self, address: u16, value: u16) {
+        self.borrow_mut().on_mem_read(address, value);
+    }
+
+    fn on_mem_write(&mut self, address: u16, value: u16) {
+        self.borrow_mut().on_mem_write(address, value);
+    }
+
+    fn on_trap(&mut self, vector: u16) {
+        self.borrow_mut().on_trap(vector);
+    }
+
+    fn on_output(&mut self, character: char) {
+        self.borrow_mut().on_output(character);
+    }
+}
+
+/// Attaches several observers at once, each called in turn
+impl Observer for Vec<Box<dyn Observer>> {
+    fn before_instruction(&mut self, vm: &VM, pc: u16, raw: u16) {
+        for observer in self {
+            observer.before_instruction(vm, pc, raw);
+        }
+    }
+
+    fn after_instruction(&mut self, vm: &VM, pc: u16, raw: u16) {
+        for observer in self {
+            observer.after_instruction(vm, pc, raw);
+        }
+    }
+
+    fn on_mem_read(&mut self, address: u16, value: u16) {
+        for observer in self {
+            observer.on_mem_read(address, value);
+        }
+    }
+
+    fn on_mem_write(&mut self, address: u16, value: u16) {
+        for observer in self {
+            observer.on_mem_write(address, value);
+        }
+    }
+
+    fn on_trap(&mut self, vector: u16) {
+        for observer in self {
+            observer.on_trap(vector);
+        }
+    }
+
+    fn on_output(&mut self, character: char) {
+        for observer in self {
+            observer.on_output(character);
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! RAM kept in a file between runs, for a "non-volatile" machine whose data
+//! structures survive the process: `lc3-vm run --persist machine.mem`. The
+//! file is mapped into the address space, so the words are in it as soon as
+//! the program writes them, and starting takes no copy whatever its
+//! contents.
+//!
+//! The file holds the 65536 words of the address space in the byte order of
+//! the host, x0000 first, so it is only portable between hosts of the same
+//! endianness. A new file starts with the memory of the machine at the time
+//! it was attached. The device registers are not kept: they are cleared
+//! when the file is attached, as on power-up. A file is locked while mapped,
+//! so two machines never share one.
+
+use core::{
+    ops::{Deref, DerefMut},
+    slice,
+};
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    path::Path,
+};
+
+use memmap2::MmapMut;
+
+use crate::{
+    errors::{IoError, VMError},
+    memory::MEMORY_SIZE,
+};
+
+/// Bytes of a memory file
+const FILE_SIZE: usize = MEMORY_SIZE * 2;
+
+/// The words of a memory file mapped in the address space
+pub(crate) struct MappedWords {
+    map: MmapMut,
+    /// Holds the lock until the words are unmapped
+    _file: File,
+}
+
+impl MappedWords {
+    /// Maps the memory file at `path`, creating it with `initial` as its
+    /// contents if it does not exist
+    pub(crate) fn open(path: &Path, initial: &[u16]) -> Result<Self, VMError> {
+        let name = path.display();
+        let opening =
+            |error| VMError::OpenFile(IoError::caused_by(format!("Could not open {name}"), error));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(opening)?;
+        file.try_lock().map_err(|error| {
+            VMError::OpenFile(IoError::caused_by(
+                format!("Could not lock {name}, another machine may be using it"),
+                io::Error::from(error),
+            ))
+        })?;
+        let length = file.metadata().map_err(opening)?.len();
+        let created = length == 0;
+        if created {
+            file.set_len(u64::try_from(FILE_SIZE).unwrap_or(u64::MAX))
+                .map_err(opening)?;
+        } else if usize::try_from(length).ok() != Some(FILE_SIZE) {
+            return Err(VMError::InvalidArgument(format!(
+                "{name} is not a memory file: it has {length} bytes instead of {FILE_SIZE}"
+            )));
+        }
+        // SAFETY: the file is locked, so no other machine maps or writes it
+        // through this crate while the map is alive. Other processes writing
+        // it anyway only change the words the program reads.
+        let map = unsafe { MmapMut::map_mut(&file) }.map_err(opening)?;
+        let mut words = MappedWords { map, _file: file };
+        if created {
+            let length = initial.len().min(MEMORY_SIZE);
+            if let (Some(words), Some(initial)) = (words.get_mut(..length), initial.get(..length)) {
+                words.copy_from_slice(initial);
+            }
+        }
+        Ok(words)
+    }
+
+    /// Writes the words changed since the last sync to the file
+    pub(crate) fn flush(&self) -> io::Result<()> {
+        self.map.flush()
+    }
+}
+
+impl Deref for MappedWords {
+    type Target = [u16];
+
+    fn deref(&self) -> &[u16] {
+        // SAFETY: the map has FILE_SIZE bytes, and starts at a page boundary
+        // aligned for u16
+        unsafe { slice::from_raw_parts(self.map.as_ptr().cast::<u16>(), MEMORY_SIZE) }
+    }
+}
+
+impl DerefMut for MappedWords {
+    fn deref_mut(&mut self) -> &mut [u16] {
+        // SAFETY: as for `deref`, borrowed mutably through the map
+        unsafe { slice::from_raw_parts_mut(self.map.as_mut_ptr().cast::<u16>(), MEMORY_SIZE) }
+    }
+}
@@ -0,0 +1,45 @@
+use core::fmt;
+
+/// Index of one of the eight general purpose registers.
+///
+/// Built by masking the 3 bit instruction fields, so it is always in range
+/// and register accesses cannot fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Register(u16);
+
+impl Register {
+    pub const R0: Register = Register(0);
+    pub const R1: Register = Register(1);
+    pub const R2: Register = Register(2);
+    pub const R3: Register = Register(3);
+    pub const R4: Register = Register(4);
+    pub const R5: Register = Register(5);
+    pub const R6: Register = Register(6);
+    pub const R7: Register = Register(7);
+
+    /// Takes the register from the lowest 3 bits of `bits`
+    #[inline]
+    pub const fn from_bits(bits: u16) -> Register {
+        Register(bits & 0x7)
+    }
+
+    /// Returns the register numbered `number`, if it exists
+    pub fn new(number: u16) -> Option<Register> {
+        (number < 8).then_some(Register(number))
+    }
+
+    #[inline]
+    pub fn index(self) -> usize {
+        usize::from(self.0)
+    }
+
+    pub fn number(self) -> u16 {
+        self.0
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "R{}", self.0)
+    }
+}
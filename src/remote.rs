@@ -0,0 +1,158 @@
+//! Remote control of the debugger over a Unix socket, so editors, scripts
+//! or a separate UI process can drive a VM without embedding it
+//! (`lc3-vm remote SOCKET [FILE]`).
+//!
+//! Clients send one command per line: the commands of `lc3sim` such as
+//! `break set LOOP`, `continue` or `printregs`, plus `input TEXT`, which
+//! types `TEXT` into the program (`\n`, `\t` and `\\` are escapes). Every
+//! command is answered with what the program printed meanwhile followed by
+//! the output of the command, then a line holding a single `.`; lines of
+//! the answer starting with `.` get another `.` in front, like in SMTP.
+//! Clients may disconnect and reconnect to the same machine; `quit` stops
+//! the server.
+
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+};
+
+use crate::{
+    console::SharedConsole,
+    errors::{IoError, VMError},
+    lc3sim::Simulator,
+    vm::VM,
+};
+
+/// Commands of one machine, with the program output in the answers
+pub struct Session {
+    simulator: Simulator,
+    console: SharedConsole,
+}
+
+impl Session {
+    pub fn new(vm: VM) -> Self {
+        let console = SharedConsole::new();
+        Session {
+            simulator: Simulator::with_console(vm, console.clone()),
+            console,
+        }
+    }
+
+    pub fn simulator(&self) -> &Simulator {
+        &self.simulator
+    }
+
+    /// Answers one command, without the framing. `None` once the client
+    /// asked to quit.
+    pub fn handle(&mut self, line: &str) -> Option<String> {
+        let line = line.trim_end_matches(['\n', '\r']);
+        let output = match line.trim_start().strip_prefix("input") {
+            Some(text) if text.is_empty() || text.starts_with(' ') => {
+                let keys = unescape(text.strip_prefix(' ').unwrap_or(text));
+                let count = keys.len();
+                self.console.push_input(keys);
+                format!("Queued {count} key(s).")
+            }
+            _ => self.simulator.eval(line)?,
+        };
+        if self.simulator.has_quit() {
+            return None;
+        }
+        let printed = self.console.take_output();
+        Some(match (printed.is_empty(), output.is_empty()) {
+            (true, _) => output,
+            (false, true) => printed,
+            (false, false) if printed.ends_with('\n') => format!("{printed}{output}"),
+            (false, false) => format!("{printed}\n{output}"),
+        })
+    }
+}
+
+/// An answer as sent on the socket: dot-stuffed lines ending with `.`
+pub fn frame(answer: &str) -> String {
+    let mut framed = String::new();
+    for line in answer.lines() {
+        if line.starts_with('.') {
+            framed.push('.');
+        }
+        framed.push_str(line);
+        framed.push('\n');
+    }
+    framed.push_str(".\n");
+    framed
+}
+
+/// Serves `vm` on a Unix socket at `path`, one client at a time, until a
+/// client sends `quit`. A socket left at `path` by an earlier server is
+/// replaced.
+pub fn serve(vm: VM, path: &str) -> Result<(), VMError> {
+    serve_session(Session::new(vm), path)
+}
+
+/// Like `serve`, for a session already set up, e.g. with a program loaded
+pub fn serve_session(mut session: Session, path: &str) -> Result<(), VMError> {
+    if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        fs::remove_file(path).map_err(|e| socket_error(path, e))?;
+    }
+    let listener = UnixListener::bind(path).map_err(|e| socket_error(path, e))?;
+    let result = accept(&listener, &mut session, path);
+    fs::remove_file(path).map_err(|e| socket_error(path, e))?;
+    result
+}
+
+fn accept(listener: &UnixListener, session: &mut Session, path: &str) -> Result<(), VMError> {
+    for stream in listener.incoming() {
+        let stream = stream.map_err(|e| socket_error(path, e))?;
+        if !converse(stream, session).map_err(|e| socket_error(path, e))? {
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+/// Answers the commands of one client, returning false once it quit
+fn converse(stream: UnixStream, session: &mut Session) -> io::Result<bool> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        match session.handle(&line?) {
+            Some(answer) => {
+                writer.write_all(frame(&answer).as_bytes())?;
+                writer.flush()?;
+            }
+            None => {
+                writer.write_all(frame("").as_bytes())?;
+                return Ok(false);
+            }
+        }
+    }
+    Ok(true)
+}
+
+fn unescape(text: &str) -> Vec<u8> {
+    let mut keys = Vec::new();
+    let mut bytes = text.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte != b'\\' {
+            keys.push(byte);
+            continue;
+        }
+        match bytes.next() {
+            Some(b'n') => keys.push(b'\n'),
+            Some(b't') => keys.push(b'\t'),
+            Some(other) => keys.push(other),
+            None => keys.push(b'\\'),
+        }
+    }
+    keys
+}
+
+fn socket_error(path: &str, error: io::Error) -> VMError {
+    VMError::StandardIO(IoError::caused_by(
+        format!("Could not serve on {path}"),
+        error,
+    ))
+}
@@ -0,0 +1,209 @@
+//! Interactive playground (`lc3-vm repl`): every line typed is assembled at
+//! the PC and executed at once against the live machine, then the registers
+//! and flags are printed. Directives store their data at the PC instead.
+//! Each line is assembled on its own, so a label can only be used on the
+//! line that defines it. Lines starting with `:` are commands, see `HELP`.
+
+use std::io::{self, Write};
+
+use crate::{
+    assembler::{assemble_at, assemble_with_diagnostics, indentation, Diagnostic},
+    disassembler::disassemble,
+    errors::{IoError, VMError},
+    register::Register,
+    vm::VM,
+};
+
+const HELP: &str = "\
+Type LC-3 instructions to run them at the PC, or directives such as .FILL
+to store data there. Labels are only known on the line defining them.
+Commands:
+  :regs               show the registers and flags
+  :mem ADDR [COUNT]   show COUNT words of memory from ADDR (default 8)
+  :pc ADDR            move the PC
+  :devices            list the devices and their addresses
+  :reset              clear the machine
+  :help               show this help
+  :quit               leave";
+
+/// Words shown by `:mem` without a count
+const MEMORY_WORDS: u16 = 8;
+
+pub struct Repl {
+    vm: VM,
+}
+
+impl Repl {
+    pub fn new(vm: VM) -> Self {
+        Repl { vm }
+    }
+
+    pub fn vm(&self) -> &VM {
+        &self.vm
+    }
+
+    /// Prompt showing where the next line will be assembled
+    pub fn prompt(&self) -> String {
+        format!("x{:04X}> ", self.vm.pc())
+    }
+
+    /// Evaluates one line, returning the text to show, or `None` once the
+    /// user quits
+    pub fn eval(&mut self, line: &str) -> Option<String> {
+        let typed = line.trim_end_matches(['\n', '\r']);
+        let line = line.trim();
+        let Some(command) = line.strip_prefix(':') else {
+            if line.is_empty() {
+                return Some(String::new());
+            }
+            if let Err(diagnostics) = assemble_with_diagnostics(typed, Some(self.vm.pc())) {
+                return Some(self.point_at(typed, &diagnostics));
+            }
+            let result = if is_directive(line) {
+                self.store(line)
+            } else {
+                self.vm.exec_asm(line)
+            };
+            return Some(match result {
+                Ok(()) => self.registers(),
+                Err(error) => format!("error: {error:#}"),
+            });
+        };
+        let mut words = command.split_whitespace();
+        let output = match (words.next(), words.next(), words.next()) {
+            (Some("quit" | "q"), None, None) => return None,
+            (Some("help" | "h"), None, None) => String::from(HELP),
+            (Some("regs" | "r"), None, None) => self.registers(),
+            (Some("devices"), None, None) => self
+                .vm
+                .memory()
+                .mmio_map()
+                .to_string()
+                .trim_end()
+                .to_owned(),
+            (Some("mem" | "m"), Some(address), count) => match (
+                parse_number(address),
+                count.map_or(Some(MEMORY_WORDS), parse_number),
+            ) {
+                (Some(address), Some(count)) => self.memory(address, count),
+                _ => String::from("error: expected :mem ADDR [COUNT]"),
+            },
+            (Some("pc"), Some(address), None) => match parse_number(address) {
+                Some(address) => {
+                    self.vm.set_pc(address);
+                    self.registers()
+                }
+                None => String::from("error: expected :pc ADDR"),
+            },
+            (Some("reset"), None, None) => {
+                self.vm.reset(false);
+                self.registers()
+            }
+            _ => format!("error: unknown command :{command}, see :help"),
+        };
+        Some(output)
+    }
+
+    /// Marks the errors under the line as it was typed after the prompt
+    fn point_at(&self, typed: &str, diagnostics: &[Diagnostic]) -> String {
+        let prompt = " ".repeat(self.prompt().chars().count());
+        let lines: Vec<String> = diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let marker = format!(
+                    "{prompt}{}{} {}",
+                    indentation(typed, diagnostic.column),
+                    "^".repeat(diagnostic.width.max(1)),
+                    diagnostic.message
+                );
+                match &diagnostic.help {
+                    Some(help) => format!("{marker}\n{prompt}help: {help}"),
+                    None => marker,
+                }
+            })
+            .collect();
+        lines.join("\n")
+    }
+
+    /// Stores data at the PC and moves the PC past it, without executing it
+    fn store(&mut self, line: &str) -> Result<(), VMError> {
+        let assembly = assemble_at(line, self.vm.pc())?;
+        self.vm
+            .memory_mut()
+            .write_words(assembly.origin, &assembly.words)?;
+        self.vm.set_pc(assembly.end());
+        Ok(())
+    }
+
+    fn registers(&self) -> String {
+        let registers: Vec<String> = (0..8)
+            .map(|number| {
+                let register = Register::from_bits(number);
+                format!("{register}=x{:04X}", self.vm.register(register))
+            })
+            .collect();
+        let condition = self.vm.psr().condition_letter();
+        let halted = if self.vm.is_halted() { "  halted" } else { "" };
+        format!(
+            "{}\nPC=x{:04X} CC={condition}{halted}",
+            registers.join(" "),
+            self.vm.pc()
+        )
+    }
+
+    fn memory(&self, start: u16, count: u16) -> String {
+        let lines: Vec<String> = (0..count)
+            .map(|offset| {
+                let address = start.wrapping_add(offset);
+                let word = self.vm.peek(address);
+                format!(
+                    "x{address:04X}  x{word:04X}  {}",
+                    disassemble(address, word)
+                )
+            })
+            .collect();
+        lines.join("\n")
+    }
+}
+
+/// Runs the playground on stdin and stdout until `:quit` or end of input.
+/// Program output and keys go through the VM's console.
+pub fn run(vm: VM) -> Result<(), VMError> {
+    let mut repl = Repl::new(vm);
+    let mut stdout = io::stdout();
+    writeln!(stdout, "LC-3 playground, :help for commands").map_err(io_error)?;
+    loop {
+        write!(stdout, "{}", repl.prompt()).map_err(io_error)?;
+        stdout.flush().map_err(io_error)?;
+        // stdin is locked for each line only, so GETC and IN can read keys
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line).map_err(io_error)? == 0 {
+            writeln!(stdout).map_err(io_error)?;
+            return Ok(());
+        }
+        match repl.eval(&line) {
+            Some(output) if output.is_empty() => {}
+            Some(output) => writeln!(stdout, "{output}").map_err(io_error)?,
+            None => return Ok(()),
+        }
+    }
+}
+
+/// Whether the line is a directive, such as `.FILL`, optionally labelled
+fn is_directive(line: &str) -> bool {
+    let mut words = line.split_whitespace();
+    let first = words.next().unwrap_or_default();
+    let second = words.next().unwrap_or_default();
+    first.starts_with('.') || second.starts_with('.')
+}
+
+fn parse_number(text: &str) -> Option<u16> {
+    match text.strip_prefix(['x', 'X']) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.strip_prefix('#').unwrap_or(text).parse().ok(),
+    }
+}
+
+fn io_error(error: io::Error) -> VMError {
+    VMError::StandardIO(IoError::caused_by("Could not use the terminal", error))
+}
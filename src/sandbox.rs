@@ -0,0 +1,245 @@
+//! Hardened runs of untrusted images, e.g. student code graded on a server.
+//! A sandboxed run:
+//!
+//! - reaches no host files, sockets, terminal or environment variables: the
+//!   program reads the input it is given and its output is collected
+//! - always has an instruction and an output limit, and a host time limit
+//!   outside of the web
+//! - never waits: reading a key past the input is a fault
+//! - only has the LC-3 traps, and GETENV for the variables of `env`
+//! - never compiles to native code, since the instruction limit keeps the
+//!   JIT off
+//! - ends in an `Execution` describing how it stopped, whatever the program
+//!   or the VM did, panics included
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::{
+    console::SharedConsole,
+    errors::VMError,
+    vm::{Conformance, VM},
+};
+
+/// Instruction limit of sandboxes by default
+pub const DEFAULT_INSTRUCTION_LIMIT: u64 = 10_000_000;
+/// Output limit of sandboxes by default, in characters
+pub const DEFAULT_OUTPUT_LIMIT: usize = 1 << 16;
+
+/// Limits and settings of sandboxed runs
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sandbox {
+    pub instruction_limit: u64,
+    /// Characters the program may print, escape sequences included
+    pub output_limit: usize,
+    /// Host time the program may run for
+    #[cfg(not(target_arch = "wasm32"))]
+    pub timeout: Duration,
+    pub conformance: Conformance,
+    /// Variables the program reads with GETENV
+    pub env: BTreeMap<String, String>,
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Sandbox {
+            instruction_limit: DEFAULT_INSTRUCTION_LIMIT,
+            output_limit: DEFAULT_OUTPUT_LIMIT,
+            #[cfg(not(target_arch = "wasm32"))]
+            timeout: Duration::from_secs(10),
+            conformance: Conformance::default(),
+            env: BTreeMap::new(),
+        }
+    }
+}
+
+/// How a sandboxed run stopped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Termination {
+    Halted,
+    Fault(Fault),
+}
+
+/// Why a sandboxed run did not halt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fault {
+    pub kind: FaultKind,
+    /// The error, for people
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultKind {
+    /// An image could not be loaded
+    InvalidImage,
+    InstructionLimit,
+    OutputLimit,
+    Timeout,
+    /// The program read a key past the end of its input
+    InputExhausted,
+    /// A reserved or unsupported opcode, RTI or an unknown trap
+    IllegalInstruction,
+    /// An address past either end of memory
+    MemoryAccess,
+    /// The VM panicked, which is a bug of the VM
+    Panic,
+    Other,
+}
+
+impl FaultKind {
+    pub fn of(error: &VMError) -> FaultKind {
+        match error {
+            VMError::InvalidImage(_) | VMError::OpenFile(_) | VMError::ReadFile(_) => {
+                FaultKind::InvalidImage
+            }
+            VMError::InstructionLimit(_) => FaultKind::InstructionLimit,
+            VMError::OutputLimit(_) => FaultKind::OutputLimit,
+            VMError::Timeout(_) => FaultKind::Timeout,
+            // the console of the sandbox only fails reading past the input
+            VMError::StandardIO(_) => FaultKind::InputExhausted,
+            VMError::InvalidOpcode(_) | VMError::InvalidTrapCode(_) => {
+                FaultKind::IllegalInstruction
+            }
+            VMError::MemoryIndex(_) | VMError::AddressOverflow(_) => FaultKind::MemoryAccess,
+            _ => FaultKind::Other,
+        }
+    }
+}
+
+impl fmt::Display for FaultKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FaultKind::InvalidImage => "invalid_image",
+            FaultKind::InstructionLimit => "instruction_limit",
+            FaultKind::OutputLimit => "output_limit",
+            FaultKind::Timeout => "timeout",
+            FaultKind::InputExhausted => "input_exhausted",
+            FaultKind::IllegalInstruction => "illegal_instruction",
+            FaultKind::MemoryAccess => "memory_access",
+            FaultKind::Panic => "panic",
+            FaultKind::Other => "other",
+        })
+    }
+}
+
+/// The result of a sandboxed run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Execution {
+    pub termination: Termination,
+    pub instructions: u64,
+    /// Everything the program printed
+    pub output: String,
+    /// The registers when the program stopped
+    pub registers: [u16; 8],
+    /// The PC when the program stopped, past the faulting instruction
+    pub pc: u16,
+}
+
+impl Execution {
+    pub fn halted(&self) -> bool {
+        self.termination == Termination::Halted
+    }
+
+    /// The execution as a JSON object, with a `fault` object that is null
+    /// when the program halted
+    pub fn to_json(&self) -> String {
+        let fault = match &self.termination {
+            Termination::Halted => None,
+            Termination::Fault(fault) => Some(json!({
+                "kind": fault.kind.to_string(),
+                "message": fault.message,
+            })),
+        };
+        let execution = json!({
+            "halted": self.halted(),
+            "fault": fault,
+            "instructions": self.instructions,
+            "output": self.output,
+            "registers": self.registers,
+            "pc": self.pc,
+        });
+        format!("{execution:#}\n")
+    }
+}
+
+impl Sandbox {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads `images` in order and runs them with `input` as the keys
+    pub fn run(&self, images: &[&[u8]], input: &[u8]) -> Execution {
+        let console = SharedConsole::new();
+        console.push_input(input.iter().copied());
+        let mut execution = Execution {
+            termination: Termination::Halted,
+            instructions: 0,
+            output: String::new(),
+            registers: [0; 8],
+            pc: 0,
+        };
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            self.execute(&console, images, &mut execution)
+        }));
+        execution.output = console.take_output();
+        execution.termination = match result {
+            Ok(Ok(())) => Termination::Halted,
+            Ok(Err(error)) => Termination::Fault(Fault {
+                kind: FaultKind::of(&error),
+                message: error.to_string(),
+            }),
+            Err(payload) => Termination::Fault(Fault {
+                kind: FaultKind::Panic,
+                message: panic_message(payload.as_ref()),
+            }),
+        };
+        execution
+    }
+
+    fn execute(
+        &self,
+        console: &SharedConsole,
+        images: &[&[u8]],
+        execution: &mut Execution,
+    ) -> Result<(), VMError> {
+        let builder = VM::builder()
+            .console(Box::new(console.clone()))
+            .conformance(self.conformance)
+            .instruction_limit(self.instruction_limit)
+            .output_limit(self.output_limit);
+        #[cfg(not(target_arch = "wasm32"))]
+        let builder = builder.timeout(self.timeout);
+        let mut vm = self
+            .env
+            .iter()
+            .fold(builder, |builder, (name, value)| {
+                builder.env_var(name, value)
+            })
+            .build()?;
+        for image in images {
+            vm.read_image_bytes(image)?;
+        }
+        let result = vm.run();
+        execution.instructions = vm.metrics().instructions;
+        execution.registers = vm.registers();
+        execution.pc = vm.pc();
+        result
+    }
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    let message = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown cause");
+    format!("The VM panicked: {message}")
+}
@@ -0,0 +1,107 @@
+//! PC scancodes (set 1) delivered by the keyboard when `scancodes` is set
+//! in the `ConsoleConfig`. KBDR then holds an event for each key pressed or
+//! released instead of the character typed:
+//!
+//! - bits [6:0]: the scancode of the key
+//! - bit 7: set when the key is released (break), clear when it is pressed
+//!   (make)
+//! - bit 8: set for the extended keys sent after xE0, like the arrows
+//!
+//! Terminals only report characters, so a typed character becomes the
+//! presses and releases that type it on a US keyboard, with shift or control
+//! around it when needed: `A` is shift, A down, A up, shift up.
+
+use alloc::vec::Vec;
+
+use crate::console::key_sequence;
+
+/// Bit of the events of released keys
+pub const RELEASED: u16 = 0x80;
+/// Bit of the keys sent after xE0
+pub const EXTENDED: u16 = 0x100;
+
+const SHIFT: u16 = 0x2A;
+const CONTROL: u16 = 0x1D;
+const SPACE: u16 = 0x39;
+
+/// Keys the terminal sends as escape sequences, named as in `key_sequence`
+const NAMED: [(&str, u16); 18] = [
+    ("up", EXTENDED | 0x48),
+    ("down", EXTENDED | 0x50),
+    ("right", EXTENDED | 0x4D),
+    ("left", EXTENDED | 0x4B),
+    ("home", EXTENDED | 0x47),
+    ("end", EXTENDED | 0x4F),
+    ("f1", 0x3B),
+    ("f2", 0x3C),
+    ("f3", 0x3D),
+    ("f4", 0x3E),
+    ("f5", 0x3F),
+    ("f6", 0x40),
+    ("f7", 0x41),
+    ("f8", 0x42),
+    ("f9", 0x43),
+    ("f10", 0x44),
+    ("f11", 0x57),
+    ("f12", 0x58),
+];
+
+/// Rows of a US keyboard: the scancode of their first key and their
+/// characters without and with shift
+const ROWS: [(u16, &[u8], &[u8]); 4] = [
+    (0x02, b"1234567890-=", b"!@#$%^&*()_+"),
+    (0x10, b"qwertyuiop[]", b"QWERTYUIOP{}"),
+    (0x1E, b"asdfghjkl;'`", b"ASDFGHJKL:\"~"),
+    (0x2B, b"\\zxcvbnm,./", b"|ZXCVBNM<>?"),
+];
+
+/// Events of the keys typed to send `sequence`, a named key or characters.
+/// Characters no key types are dropped.
+pub fn events(sequence: &[u8]) -> Vec<u16> {
+    if let Some(&(_, code)) = NAMED
+        .iter()
+        .find(|(name, _)| key_sequence(name) == Some(sequence))
+    {
+        return Vec::from([code, code | RELEASED]);
+    }
+    let mut events = Vec::new();
+    for &byte in sequence {
+        let Some((code, modifier)) = key(byte) else {
+            continue;
+        };
+        events.extend(modifier);
+        events.extend([code, code | RELEASED]);
+        events.extend(modifier.map(|modifier| modifier | RELEASED));
+    }
+    events
+}
+
+/// Whether a named key is sent as a longer sequence starting with `prefix`
+pub fn extends(prefix: &[u8]) -> bool {
+    NAMED.iter().any(|(name, _)| {
+        key_sequence(name)
+            .is_some_and(|sequence| sequence.len() > prefix.len() && sequence.starts_with(prefix))
+    })
+}
+
+/// Scancode of the key typing `byte`, and of the modifier held for it
+fn key(byte: u8) -> Option<(u16, Option<u16>)> {
+    match byte {
+        b'\n' | b'\r' => return Some((0x1C, None)),
+        b'\t' => return Some((0x0F, None)),
+        0x08 | 0x7F => return Some((0x0E, None)),
+        0x1B => return Some((0x01, None)),
+        b' ' => return Some((SPACE, None)),
+        // control and a letter
+        0x01..=0x1A => return key(byte | 0x60).map(|(code, _)| (code, Some(CONTROL))),
+        _ => {}
+    }
+    ROWS.iter().find_map(|&(first, plain, shifted)| {
+        let (position, modifier) = match plain.iter().position(|&key| key == byte) {
+            Some(position) => (position, None),
+            None => (shifted.iter().position(|&key| key == byte)?, Some(SHIFT)),
+        };
+        let code = first.checked_add(u16::try_from(position).ok()?)?;
+        Some((code, modifier))
+    })
+}
@@ -0,0 +1,114 @@
+//! Serial port device connected to a TCP or Unix socket, so programs can
+//! talk to other processes. It is memory mapped next to the keyboard:
+//!
+//! - `SRSR` (xFE08): bit 15 is set when a received byte waits in `SRDR`
+//! - `SRDR` (xFE0A): the received byte, reading it clears `SRSR`
+//! - `STSR` (xFE0C): bit 15 is set when a byte can be sent, which is always
+//! - `STDR` (xFE0E): writing sends the low byte
+
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use crate::errors::{IoError, VMError};
+
+/// Serial receive status memory mapped register
+pub const SRSR: u16 = 0xFE08;
+/// Serial receive data memory mapped register
+pub const SRDR: u16 = 0xFE0A;
+/// Serial transmit status memory mapped register
+pub const STSR: u16 = 0xFE0C;
+/// Serial transmit data memory mapped register
+pub const STDR: u16 = 0xFE0E;
+
+pub struct SerialPort {
+    received: Receiver<u8>,
+    writer: Box<dyn Write>,
+}
+
+impl SerialPort {
+    /// Connects the port to a byte stream, reading it on a background thread
+    pub fn new(reader: impl Read + Send + 'static, writer: impl Write + 'static) -> Self {
+        SerialPort {
+            received: spawn_reader(reader),
+            writer: Box::new(writer),
+        }
+    }
+
+    /// Waits for a peer on `address`, either `HOST:PORT` for TCP or
+    /// `unix:PATH` for a Unix socket, and connects the port to it. It says
+    /// nothing while waiting, so front ends tell their users where to
+    /// connect first.
+    pub fn listen(address: &str) -> Result<Self, VMError> {
+        match address.strip_prefix("unix:") {
+            Some(path) => Self::listen_unix(path),
+            None => {
+                let listener = TcpListener::bind(address)
+                    .map_err(|e| serial_error(&format!("Could not listen on {address}"), e))?;
+                let (stream, _) = listener
+                    .accept()
+                    .map_err(|e| serial_error("Could not accept a connection", e))?;
+                let reader = stream
+                    .try_clone()
+                    .map_err(|e| serial_error("Could not share the connection", e))?;
+                Ok(Self::new(reader, stream))
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    fn listen_unix(path: &str) -> Result<Self, VMError> {
+        let listener = UnixListener::bind(path)
+            .map_err(|e| serial_error(&format!("Could not listen on {path}"), e))?;
+        let (stream, _) = listener
+            .accept()
+            .map_err(|e| serial_error("Could not accept a connection", e))?;
+        let reader = stream
+            .try_clone()
+            .map_err(|e| serial_error("Could not share the connection", e))?;
+        Ok(Self::new(reader, stream))
+    }
+
+    #[cfg(not(unix))]
+    fn listen_unix(_path: &str) -> Result<Self, VMError> {
+        Err(VMError::InvalidArgument(String::from(
+            "Unix sockets are not available on this platform",
+        )))
+    }
+
+    /// Next received byte, if one arrived
+    pub(crate) fn receive(&mut self) -> Option<u8> {
+        self.received.try_recv().ok()
+    }
+
+    pub(crate) fn send(&mut self, byte: u8) -> Result<(), VMError> {
+        self.writer
+            .write_all(&[byte])
+            .map_err(|e| serial_error("Could not send a byte", e))
+    }
+}
+
+/// Forwards every byte read from `reader` until it closes
+fn spawn_reader(mut reader: impl Read + Send + 'static) -> Receiver<u8> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buffer = [0; 256];
+        while let Ok(read @ 1..) = reader.read(&mut buffer) {
+            for byte in buffer.get(..read).unwrap_or_default() {
+                if sender.send(*byte).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    receiver
+}
+
+fn serial_error(context: &str, error: std::io::Error) -> VMError {
+    VMError::StandardIO(IoError::caused_by(format!("Serial port: {context}"), error))
+}
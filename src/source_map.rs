@@ -0,0 +1,72 @@
+//! Line tables relating the lines of an assembly source to the words they
+//! were assembled into, for debugging at the source level
+
+use alloc::{collections::BTreeMap, format, string::String};
+
+use crate::{assembler::Assembly, symbols::SymbolTable};
+
+/// The words produced by each line of a source, by address
+#[derive(Debug, Default, Clone)]
+pub struct SourceMap {
+    /// First address of each run of words, with its line and length
+    spans: BTreeMap<u16, (usize, u16)>,
+}
+
+impl SourceMap {
+    /// The line table of an assembled program
+    pub fn new(assembly: &Assembly) -> Self {
+        let mut map = SourceMap::default();
+        for line in &assembly.lines {
+            map.insert(line.line, line.address, line.length);
+        }
+        map
+    }
+
+    /// Records that `line` (1-based) produced `length` words at `address`.
+    /// Lines producing no words are ignored.
+    pub fn insert(&mut self, line: usize, address: u16, length: u16) {
+        if length > 0 {
+            self.spans.insert(address, (line, length));
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.spans.is_empty()
+    }
+
+    /// Line that produced the word at `address`
+    pub fn line_at(&self, address: u16) -> Option<usize> {
+        let (&start, &(line, length)) = self.spans.range(..=address).next_back()?;
+        (address.wrapping_sub(start) < length).then_some(line)
+    }
+
+    /// Where a breakpoint on `line` goes: the first line at or after it
+    /// that produced words, with the address of its first word
+    pub fn address_of_line(&self, line: usize) -> Option<(usize, u16)> {
+        self.spans
+            .iter()
+            .filter(|(_, &(produced, _))| produced >= line)
+            .min_by_key(|(&address, &(produced, _))| (produced, address))
+            .map(|(&address, &(produced, _))| (produced, address))
+    }
+
+    /// Whether `address` holds a word of the program
+    pub fn contains(&self, address: u16) -> bool {
+        self.line_at(address).is_some()
+    }
+
+    /// `address` in hex, followed by its position relative to the closest
+    /// label when it is inside the program or exactly on a label, e.g.
+    /// `x3004 (LOOP+2)`
+    pub fn describe(&self, symbols: &SymbolTable, address: u16) -> String {
+        let relative = match symbols.label_at(address) {
+            Some(label) => Some(String::from(label)),
+            None if self.contains(address) => symbols.describe(address),
+            None => None,
+        };
+        match relative {
+            Some(relative) => format!("x{address:04X} ({relative})"),
+            None => format!("x{address:04X}"),
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use alloc::{format, string::String};
+
+use crate::{
+    errors::VMError,
+    instructions::{AddressArithmetic, Instruction},
+    register::Register,
+};
+
+/// Register conventionally used as stack pointer
+pub const DEFAULT_STACK_REGISTER: Register = Register::R6;
+
+/// Declared stack region growing downwards from `base`.
+///
+/// The stack pointer holds the address of the last pushed word, so it is
+/// `base` when the stack is empty and `limit` when the stack is full. The
+/// checker stays idle until the stack pointer first enters the region, so
+/// programs can initialize it freely.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackChecker {
+    register: Register,
+    limit: u16,
+    base: u16,
+    armed: bool,
+}
+
+impl StackChecker {
+    /// Creates a checker for the region `[limit, base)` using R6 as pointer
+    pub fn new(limit: u16, base: u16) -> Result<Self, VMError> {
+        if limit >= base {
+            return Err(VMError::StackViolation(format!(
+                "Stack limit {limit:#06x} must be below stack base {base:#06x}"
+            )));
+        }
+        Ok(StackChecker {
+            register: DEFAULT_STACK_REGISTER,
+            limit,
+            base,
+            armed: false,
+        })
+    }
+
+    /// Uses `register` as stack pointer instead of R6
+    pub fn with_register(self, register: Register) -> Self {
+        StackChecker { register, ..self }
+    }
+
+    pub fn register(&self) -> Register {
+        self.register
+    }
+
+    /// Idles the checker again until the stack pointer enters the region,
+    /// used when the program restarts
+    pub fn reset(&mut self) {
+        self.armed = false;
+    }
+
+    /// Rejects stores relative to the stack pointer that land outside of the
+    /// stack, computing their address with `addresses` like the VM. Checked
+    /// before `instruction` executes.
+    pub fn check_instruction(
+        &self,
+        pc: u16,
+        raw: u16,
+        instruction: &Instruction,
+        pointer: u16,
+        addresses: AddressArithmetic,
+    ) -> Result<(), VMError> {
+        let Instruction::Str { base, offset, .. } = *instruction else {
+            return Ok(());
+        };
+        if base != self.register || !self.armed {
+            return Ok(());
+        }
+        let address = addresses.offset(pointer, offset)?;
+        if address < self.limit || address >= self.base {
+            return Err(self.violation(
+                pc,
+                raw,
+                format!("write to {address:#06x} is outside of the stack"),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Rejects a stack pointer that left the stack after `raw` executed
+    pub fn check_pointer(&mut self, pc: u16, raw: u16, pointer: u16) -> Result<(), VMError> {
+        if !self.armed {
+            self.armed = (self.limit..=self.base).contains(&pointer);
+            return Ok(());
+        }
+        if pointer < self.limit {
+            return Err(self.violation(
+                pc,
+                raw,
+                format!("stack overflow, {} = {pointer:#06x}", self.register),
+            ));
+        }
+        if pointer > self.base {
+            return Err(self.violation(
+                pc,
+                raw,
+                format!("stack underflow, {} = {pointer:#06x}", self.register),
+            ));
+        }
+        Ok(())
+    }
+
+    fn violation(&self, pc: u16, raw: u16, reason: String) -> VMError {
+        VMError::StackViolation(format!("{reason} at {pc:#06x} (instruction {raw:#06x})"))
+    }
+}
@@ -0,0 +1,454 @@
+//! Statistics of a whole run, printed by `--stats` after the program halts:
+//! instructions executed, wall time and speed from `VM::metrics`, plus how
+//! often each opcode and trap ran, how deep subroutine calls nested and how
+//! each conditional branch went.
+//!
+//! The dynamic instruction mix, with the memory reads and writes of each
+//! class of instructions and trap, can also be exported as CSV or JSON for
+//! plotting, see `Statistics::mix_csv` and `Statistics::mix_json`.
+
+use alloc::{collections::BTreeMap, format, string::String, vec::Vec};
+use core::fmt::Write;
+
+use crate::{
+    disassembler::disassemble,
+    instructions::{Instruction, Opcode, TrapCode},
+    observer::Observer,
+    register::Register,
+    vm::{Metrics, VM},
+};
+
+/// Branches listed by the report, the least predictable first
+const REPORTED_BRANCHES: usize = 10;
+
+/// Observer counting opcodes, traps, the subroutine call depth and branch
+/// outcomes
+#[derive(Debug, Clone, Default)]
+pub struct Statistics {
+    /// Executions of each opcode, indexed by its 4 bits
+    opcodes: [u64; 16],
+    traps: BTreeMap<u16, u64>,
+    depth: u32,
+    peak_depth: u32,
+    /// Outcomes of the conditional branches by address, with the last
+    /// instruction seen there
+    branches: BTreeMap<u16, (u16, BranchCounts)>,
+    /// Memory words read and written by each opcode, traps included
+    traffic: [Traffic; 16],
+    /// Memory words read and written by each trap
+    trap_traffic: BTreeMap<u16, Traffic>,
+    /// Opcode of the instruction executing
+    opcode: usize,
+    /// Vector of the trap executing
+    trap: Option<u16>,
+}
+
+/// Memory words read and written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Traffic {
+    pub reads: u64,
+    pub writes: u64,
+}
+
+impl Traffic {
+    fn add(&mut self, other: Traffic) {
+        self.reads = self.reads.saturating_add(other.reads);
+        self.writes = self.writes.saturating_add(other.writes);
+    }
+}
+
+/// Groups of opcodes in the instruction mix
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum InstructionClass {
+    /// ADD, AND and NOT
+    Operate,
+    /// LD, LDI, LDR and LEA
+    Load,
+    /// ST, STI and STR
+    Store,
+    /// BR, JMP, JSR, JSRR and RTI
+    Control,
+    Trap,
+    Reserved,
+}
+
+impl InstructionClass {
+    pub const ALL: [InstructionClass; 6] = [
+        InstructionClass::Operate,
+        InstructionClass::Load,
+        InstructionClass::Store,
+        InstructionClass::Control,
+        InstructionClass::Trap,
+        InstructionClass::Reserved,
+    ];
+
+    pub fn of(opcode: Opcode) -> InstructionClass {
+        match opcode {
+            Opcode::Add | Opcode::And | Opcode::Not => InstructionClass::Operate,
+            Opcode::Ld | Opcode::Ldi | Opcode::Ldr | Opcode::Lea => InstructionClass::Load,
+            Opcode::St | Opcode::Sti | Opcode::Str => InstructionClass::Store,
+            Opcode::Br | Opcode::Jmp | Opcode::Jsr | Opcode::Rti => InstructionClass::Control,
+            Opcode::Trap => InstructionClass::Trap,
+            Opcode::Res => InstructionClass::Reserved,
+        }
+    }
+
+    /// Name of the class in reports
+    pub fn name(self) -> &'static str {
+        match self {
+            InstructionClass::Operate => "operate",
+            InstructionClass::Load => "load",
+            InstructionClass::Store => "store",
+            InstructionClass::Control => "control",
+            InstructionClass::Trap => "trap",
+            InstructionClass::Reserved => "reserved",
+        }
+    }
+}
+
+/// Executions and memory traffic of a class of instructions or of a trap
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MixEntry {
+    pub count: u64,
+    pub traffic: Traffic,
+}
+
+/// How often a conditional branch was taken
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BranchCounts {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+impl BranchCounts {
+    pub fn executions(&self) -> u64 {
+        self.taken.saturating_add(self.not_taken)
+    }
+
+    /// Times the best fixed guess, always taken or never taken, is wrong:
+    /// zero for branches that always go the same way
+    pub fn mispredictions(&self) -> u64 {
+        self.taken.min(self.not_taken)
+    }
+}
+
+impl Statistics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opcodes that were executed, with how often
+    pub fn opcodes(&self) -> impl Iterator<Item = (Opcode, u64)> + '_ {
+        (0..16u16)
+            .zip(self.opcodes)
+            .filter(|&(_, count)| count > 0)
+            .map(|(bits, count)| (Opcode::from_instruction(bits << 12), count))
+    }
+
+    /// Trap vectors that were executed, with how often
+    pub fn traps(&self) -> impl Iterator<Item = (u16, u64)> + '_ {
+        self.traps.iter().map(|(&vector, &count)| (vector, count))
+    }
+
+    /// Most subroutine calls (JSR or JSRR) that were active at once
+    pub fn peak_call_depth(&self) -> u32 {
+        self.peak_depth
+    }
+
+    /// Executions and memory traffic of every class of instructions that
+    /// ran
+    pub fn mix(&self) -> Vec<(InstructionClass, MixEntry)> {
+        let mut classes: BTreeMap<InstructionClass, MixEntry> = BTreeMap::new();
+        for ((opcode, count), traffic) in self.opcodes().zip(self.opcode_traffic()) {
+            let entry = classes.entry(InstructionClass::of(opcode)).or_default();
+            entry.count = entry.count.saturating_add(count);
+            entry.traffic.add(traffic);
+        }
+        classes.into_iter().collect()
+    }
+
+    /// Executions and memory traffic of every trap that ran, by vector
+    pub fn trap_mix(&self) -> Vec<(u16, MixEntry)> {
+        self.traps()
+            .map(|(vector, count)| {
+                let traffic = self.trap_traffic.get(&vector).copied().unwrap_or_default();
+                (vector, MixEntry { count, traffic })
+            })
+            .collect()
+    }
+
+    /// Load instructions executed per store, `None` without stores
+    pub fn loads_per_store(&self) -> Option<(u64, u64)> {
+        let count = |class| {
+            self.mix()
+                .iter()
+                .find(|(other, _)| *other == class)
+                .map_or(0, |(_, entry)| entry.count)
+        };
+        let stores = count(InstructionClass::Store);
+        (stores > 0).then(|| (count(InstructionClass::Load), stores))
+    }
+
+    /// The instruction mix as CSV: a row per class of instructions, then a
+    /// row per trap vector. `share` is the fraction of all instructions.
+    pub fn mix_csv(&self) -> String {
+        let total = self.instructions();
+        let mut csv = String::from("group,name,count,share,reads,writes\n");
+        for (class, entry) in self.mix() {
+            let _ = writeln!(csv, "class,{},{}", class.name(), row(entry, total));
+        }
+        for (vector, entry) in self.trap_mix() {
+            let _ = writeln!(csv, "trap,{},{}", trap_name(vector), row(entry, total));
+        }
+        csv
+    }
+
+    /// The instruction mix as a JSON object with the total of instructions,
+    /// the loads per store and arrays of classes and traps
+    pub fn mix_json(&self) -> String {
+        let total = self.instructions();
+        let entry = |name: &str, entry: MixEntry| {
+            format!(
+                "{{\"name\": \"{name}\", \"count\": {}, \"share\": {}, \"reads\": {}, \"writes\": {}}}",
+                entry.count,
+                ratio(entry.count, total),
+                entry.traffic.reads,
+                entry.traffic.writes
+            )
+        };
+        let classes: Vec<String> = self
+            .mix()
+            .into_iter()
+            .map(|(class, mix)| entry(class.name(), mix))
+            .collect();
+        let traps: Vec<String> = self
+            .trap_mix()
+            .into_iter()
+            .map(|(vector, mix)| entry(&trap_name(vector), mix))
+            .collect();
+        let loads_per_store = self.loads_per_store().map_or_else(
+            || String::from("null"),
+            |(loads, stores)| ratio(loads, stores),
+        );
+        format!(
+            "{{\n  \"instructions\": {total},\n  \"loads_per_store\": {loads_per_store},\n  \"classes\": [\n    {}\n  ],\n  \"traps\": [\n    {}\n  ]\n}}\n",
+            classes.join(",\n    "),
+            traps.join(",\n    ")
+        )
+    }
+
+    fn instructions(&self) -> u64 {
+        self.opcodes.iter().sum()
+    }
+
+    /// Adds memory accesses to the instruction and trap executing
+    fn count_traffic(&mut self, traffic: Traffic) {
+        if let Some(total) = self.traffic.get_mut(self.opcode) {
+            total.add(traffic);
+        }
+        if let Some(vector) = self.trap {
+            self.trap_traffic.entry(vector).or_default().add(traffic);
+        }
+    }
+
+    /// Traffic of the opcodes that were executed, in the order of `opcodes`
+    fn opcode_traffic(&self) -> impl Iterator<Item = Traffic> + '_ {
+        self.opcodes
+            .iter()
+            .zip(self.traffic)
+            .filter(|&(&count, _)| count > 0)
+            .map(|(_, traffic)| traffic)
+    }
+
+    /// Conditional branches that were executed, by address. BRnzp and
+    /// branches testing no condition code are not counted.
+    pub fn branches(&self) -> impl Iterator<Item = (u16, BranchCounts)> + '_ {
+        self.branches
+            .iter()
+            .map(|(&address, &(_, counts))| (address, counts))
+    }
+
+    /// The summary printed by `--stats`, with the counters of `metrics`
+    pub fn report(&self, metrics: &Metrics) -> String {
+        let mut report = String::from("--- statistics ---\n");
+        let nanos = metrics.host_time.as_nanos();
+        let _ = writeln!(report, "Instructions:    {}", metrics.instructions);
+        if metrics.cycles > 0 {
+            // cycles per instruction, in hundredths
+            let cpi = u128::from(metrics.cycles)
+                .saturating_mul(100)
+                .checked_div(u128::from(metrics.instructions))
+                .unwrap_or_default();
+            let _ = writeln!(
+                report,
+                "Cycles:          {} ({} per instruction)",
+                metrics.cycles,
+                decimal(cpi, 100, 2)
+            );
+        }
+        let _ = writeln!(
+            report,
+            "Wall time:       {} ms",
+            decimal(metrics.host_time.as_micros(), 1000, 3)
+        );
+        if nanos > 0 {
+            // instructions per microsecond, in hundredths
+            let mips = u128::from(metrics.instructions)
+                .saturating_mul(100_000)
+                .checked_div(nanos)
+                .unwrap_or_default();
+            let _ = writeln!(report, "Speed:           {} MIPS", decimal(mips, 100, 2));
+        }
+        let _ = writeln!(report, "Peak call depth: {}", self.peak_depth);
+        let total: u64 = self.opcodes.iter().sum();
+        let mut opcodes: Vec<(Opcode, u64)> = self.opcodes().collect();
+        opcodes.sort_by_key(|&(_, count)| core::cmp::Reverse(count));
+        report.push_str("Opcodes:\n");
+        for (opcode, count) in opcodes {
+            let name = format!("{opcode:?}").to_uppercase();
+            let _ = writeln!(
+                report,
+                "  {name:<5} {count:>10} {:>6}",
+                percent(count, total)
+            );
+        }
+        if !self.traps.is_empty() {
+            report.push_str("Traps:\n");
+            for (vector, count) in self.traps() {
+                let name = TrapCode::try_from(vector)
+                    .map(|code| format!("{code:?}").to_uppercase())
+                    .unwrap_or_default();
+                let _ = writeln!(report, "  x{vector:02X} {name:<5} {count:>10}");
+            }
+        }
+        let mut branches: Vec<(u16, u16, BranchCounts)> = self
+            .branches
+            .iter()
+            .map(|(&address, &(raw, counts))| (address, raw, counts))
+            .filter(|(_, _, counts)| counts.mispredictions() > 0)
+            .collect();
+        // by share of mispredictions, then by how often they ran
+        branches.sort_by(|(_, _, left), (_, _, right)| {
+            let share = |counts: &BranchCounts, other: &BranchCounts| {
+                u128::from(counts.mispredictions()).saturating_mul(u128::from(other.executions()))
+            };
+            share(right, left)
+                .cmp(&share(left, right))
+                .then(right.executions().cmp(&left.executions()))
+        });
+        if !branches.is_empty() {
+            report.push_str("Least predictable branches:\n");
+            for (address, raw, counts) in branches.into_iter().take(REPORTED_BRANCHES) {
+                let _ = writeln!(
+                    report,
+                    "  x{address:04X} {:<16} {:>10} taken {:>10} not taken {:>6}",
+                    disassemble(address, raw),
+                    counts.taken,
+                    counts.not_taken,
+                    percent(counts.taken, counts.executions())
+                );
+            }
+        }
+        report
+    }
+}
+
+impl Observer for Statistics {
+    fn before_instruction(&mut self, _vm: &VM, _pc: u16, raw: u16) {
+        self.opcode = usize::from(raw >> 12);
+        self.trap = None;
+        if let Some(count) = self.opcodes.get_mut(self.opcode) {
+            *count = count.saturating_add(1);
+        }
+    }
+
+    fn on_mem_read(&mut self, _address: u16, _value: u16) {
+        self.count_traffic(Traffic {
+            reads: 1,
+            writes: 0,
+        });
+    }
+
+    fn on_mem_write(&mut self, _address: u16, _value: u16) {
+        self.count_traffic(Traffic {
+            reads: 0,
+            writes: 1,
+        });
+    }
+
+    fn after_instruction(&mut self, vm: &VM, pc: u16, raw: u16) {
+        match Instruction::decode(raw) {
+            Instruction::Br { n, z, p, .. } if (n || z || p) && !(n && z && p) => {
+                // branches leave the condition codes alone
+                let taken = vm.psr().branches(raw);
+                let (last, counts) = self.branches.entry(pc).or_default();
+                *last = raw;
+                let counter = if taken {
+                    &mut counts.taken
+                } else {
+                    &mut counts.not_taken
+                };
+                *counter = counter.saturating_add(1);
+            }
+            Instruction::Jsr { .. } => {
+                self.depth = self.depth.saturating_add(1);
+                self.peak_depth = self.peak_depth.max(self.depth);
+            }
+            Instruction::Jmp { base: Register::R7 } => {
+                self.depth = self.depth.saturating_sub(1);
+            }
+            _ => {}
+        }
+    }
+
+    fn on_trap(&mut self, vector: u16) {
+        self.trap = Some(vector);
+        let count = self.traps.entry(vector).or_default();
+        *count = count.saturating_add(1);
+    }
+}
+
+/// The columns of a CSV row after the name
+fn row(entry: MixEntry, total: u64) -> String {
+    format!(
+        "{},{},{},{}",
+        entry.count,
+        ratio(entry.count, total),
+        entry.traffic.reads,
+        entry.traffic.writes
+    )
+}
+
+/// Name of a trap in the mix, e.g. `OUT` or `x30`
+fn trap_name(vector: u16) -> String {
+    TrapCode::try_from(vector)
+        .map(|code| format!("{code:?}").to_uppercase())
+        .unwrap_or_else(|_| format!("x{vector:02X}"))
+}
+
+/// `part / total` with four decimals, e.g. `0.3636`
+fn ratio(part: u64, total: u64) -> String {
+    let scaled = u128::from(part)
+        .saturating_mul(10_000)
+        .checked_div(u128::from(total))
+        .unwrap_or_default();
+    decimal(scaled, 10_000, 4)
+}
+
+/// `part` as a percentage of `total` rounded to a tenth, e.g. `66.7%`
+pub(crate) fn percent(part: u64, total: u64) -> String {
+    let total = u128::from(total);
+    let tenths = u128::from(part)
+        .saturating_mul(1000)
+        .saturating_add(total / 2)
+        .checked_div(total)
+        .unwrap_or_default();
+    format!("{}%", decimal(tenths, 10, 1))
+}
+
+/// `value / scale` with `digits` decimals, `scale` being `10^digits`
+fn decimal(value: u128, scale: u128, digits: usize) -> String {
+    let units = value.checked_div(scale).unwrap_or_default();
+    let fraction = value.checked_rem(scale).unwrap_or_default();
+    format!("{units}.{fraction:0digits$}")
+}
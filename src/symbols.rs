@@ -0,0 +1,109 @@
+//! Symbol tables in the `.sym` format written by lc3as:
+//!
+//! ```text
+//! // Symbol table
+//! // Scope level 0:
+//! //  Symbol Name       Page Address
+//! //  ----------------  ------------
+//! //  LOOP              3002
+//! ```
+
+use alloc::{borrow::ToOwned, collections::BTreeMap, format, string::String, vec::Vec};
+#[cfg(feature = "std")]
+use std::fs;
+
+#[cfg(feature = "std")]
+use crate::errors::{IoError, VMError};
+
+#[derive(Debug, Default, Clone)]
+pub struct SymbolTable {
+    addresses: BTreeMap<String, u16>,
+    labels: BTreeMap<u16, String>,
+}
+
+impl SymbolTable {
+    /// Parses a symbol table, skipping header and malformed lines
+    pub fn parse(text: &str) -> Self {
+        let mut table = SymbolTable::default();
+        for line in text.lines() {
+            let line = line.trim_start_matches('/');
+            let mut fields = line.split_whitespace();
+            let (Some(name), Some(address), None) = (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            if let Ok(address) = u16::from_str_radix(address, 16) {
+                table.insert(name, address);
+            }
+        }
+        table
+    }
+
+    #[cfg(feature = "std")]
+    pub fn from_file(path: &str) -> Result<Self, VMError> {
+        let text = fs::read_to_string(path).map_err(|e| {
+            VMError::ReadFile(IoError::caused_by(format!("Could not read {path}"), e))
+        })?;
+        Ok(Self::parse(&text))
+    }
+
+    pub fn insert(&mut self, name: &str, address: u16) {
+        self.addresses.insert(name.to_ascii_uppercase(), address);
+        self.labels
+            .entry(address)
+            .or_insert_with(|| name.to_owned());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    /// Address of a label, which like in lc3as is case insensitive
+    pub fn address_of(&self, name: &str) -> Option<u16> {
+        self.addresses.get(&name.to_ascii_uppercase()).copied()
+    }
+
+    /// Label defined exactly at `address`
+    pub fn label_at(&self, address: u16) -> Option<&str> {
+        self.labels.get(&address).map(String::as_str)
+    }
+
+    /// Describes `address` relative to the closest label at or before it,
+    /// e.g. `LOOP` or `LOOP+3`
+    pub fn describe(&self, address: u16) -> Option<String> {
+        let (&start, label) = self.labels.range(..=address).next_back()?;
+        Some(match address.wrapping_sub(start) {
+            0 => label.clone(),
+            offset => format!("{label}+{offset}"),
+        })
+    }
+
+    /// Labels ordered by address
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &str)> {
+        self.labels
+            .iter()
+            .map(|(address, label)| (*address, label.as_str()))
+    }
+
+    /// The table in the `.sym` format, which `parse` reads back. Every
+    /// label is written, those sharing an address with another in uppercase.
+    pub fn to_sym(&self) -> String {
+        let mut symbols: Vec<(u16, &str)> = self
+            .addresses
+            .iter()
+            .map(|(name, &address)| match self.labels.get(&address) {
+                Some(label) if label.eq_ignore_ascii_case(name) => (address, label.as_str()),
+                _ => (address, name.as_str()),
+            })
+            .collect();
+        symbols.sort();
+        let mut text = String::from(
+            "// Symbol table\n// Scope level 0:\n//\tSymbol Name       Page Address\n//\t----------------  ------------\n",
+        );
+        for (address, name) in symbols {
+            text.push_str(&format!("//\t{name:<16}  {address:04X}\n"));
+        }
+        text.push('\n');
+        text
+    }
+}
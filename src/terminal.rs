@@ -0,0 +1,111 @@
+use std::{
+    io::Read,
+    process::{Command, Stdio},
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError},
+        Mutex, OnceLock,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::errors::{IoError, VMError};
+
+static KEYBOARD: OnceLock<Mutex<Receiver<u8>>> = OnceLock::new();
+
+/// Spawns (once) a thread that forwards every byte read from stdin, so the
+/// keyboard status register can be polled without blocking
+fn keyboard() -> &'static Mutex<Receiver<u8>> {
+    KEYBOARD.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut byte = [0_u8; 1];
+            let mut stdin = std::io::stdin();
+            while let Ok(1) = stdin.read(&mut byte) {
+                if sender.send(byte[0]).is_err() {
+                    break;
+                }
+            }
+        });
+        Mutex::new(receiver)
+    })
+}
+
+/// Blocks until a key is pressed and returns it
+pub fn read_key() -> Result<u8, VMError> {
+    let receiver = keyboard()
+        .lock()
+        .map_err(|_| VMError::StandardIO(IoError::new("Keyboard lock poisoned")))?;
+    receiver
+        .recv()
+        .map_err(|_| VMError::StandardIO(IoError::new("Standard input was closed")))
+}
+
+/// Waits at most `timeout` for a key, returning `None` if none was pressed
+pub fn read_key_timeout(timeout: Duration) -> Result<Option<u8>, VMError> {
+    let receiver = keyboard()
+        .lock()
+        .map_err(|_| VMError::StandardIO(IoError::new("Keyboard lock poisoned")))?;
+    match receiver.recv_timeout(timeout) {
+        Ok(key) => Ok(Some(key)),
+        Err(RecvTimeoutError::Timeout) => Ok(None),
+        Err(RecvTimeoutError::Disconnected) => Err(VMError::StandardIO(IoError::new(
+            "Standard input was closed",
+        ))),
+    }
+}
+
+/// Returns the pending key if there is one, without blocking
+pub fn poll_key() -> Option<u8> {
+    keyboard().lock().ok()?.try_recv().ok()
+}
+
+/// Makes the Windows console interpret the escape sequences written to
+/// stdout, once. Without a console, as when stdout is redirected, they are
+/// written as they are.
+#[cfg(windows)]
+pub fn enable_virtual_terminal() {
+    use windows_sys::Win32::System::Console::{
+        GetConsoleMode, GetStdHandle, SetConsoleMode, ENABLE_VIRTUAL_TERMINAL_PROCESSING,
+        STD_OUTPUT_HANDLE,
+    };
+    static ENABLED: OnceLock<()> = OnceLock::new();
+    ENABLED.get_or_init(|| {
+        // SAFETY: the handle comes from GetStdHandle and `mode` outlives the
+        // calls that use it
+        unsafe {
+            let handle = GetStdHandle(STD_OUTPUT_HANDLE);
+            let mut mode = 0;
+            if GetConsoleMode(handle, &mut mode) != 0 {
+                SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING);
+            }
+        }
+    });
+}
+
+/// Puts the terminal in non canonical mode without echo, returning the
+/// previous settings so they can be restored
+pub fn enable_raw_mode() -> Result<String, VMError> {
+    let saved = stty(&["-g"])?;
+    stty(&["-icanon", "-echo"])?;
+    Ok(saved.trim().to_string())
+}
+
+/// Restores the terminal settings returned by `enable_raw_mode`
+pub fn restore_mode(saved: &str) -> Result<(), VMError> {
+    stty(&[saved]).map(|_| ())
+}
+
+fn stty(args: &[&str]) -> Result<String, VMError> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()
+        .map_err(|e| VMError::TerminalMode(IoError::caused_by("Could not run stty", e)))?;
+    if !output.status.success() {
+        return Err(VMError::TerminalMode(IoError::new(
+            "stdin is not a terminal",
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
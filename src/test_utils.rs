@@ -0,0 +1,275 @@
+//! Builds small programs instruction by instruction for tests and examples,
+//! instead of hand-encoded words:
+//!
+//! ```
+//! use lc3_vm::{test_utils::{Program, R0, R1}, vm::VM};
+//!
+//! let mut vm = VM::new();
+//! Program::at(0x3000)
+//!     .add(R1, R1, 2)
+//!     .add(R0, R1, R1)
+//!     .trap_halt()
+//!     .load_into(&mut vm)
+//!     .unwrap();
+//! vm.step().unwrap();
+//! vm.step().unwrap();
+//! assert_eq!(vm.register(R0), 4);
+//! ```
+//!
+//! Offsets are relative to the next instruction, as in the encoding. The
+//! first offset or immediate that does not fit its field is reported when
+//! the program is encoded.
+
+use alloc::{format, vec::Vec};
+
+use crate::{
+    errors::VMError,
+    instructions::{fits, Instruction, JsrTarget, Operand},
+    register::Register,
+    vm::VM,
+};
+
+pub const R0: Register = Register::R0;
+pub const R1: Register = Register::R1;
+pub const R2: Register = Register::R2;
+pub const R3: Register = Register::R3;
+pub const R4: Register = Register::R4;
+pub const R5: Register = Register::R5;
+pub const R6: Register = Register::R6;
+pub const R7: Register = Register::R7;
+
+/// Second operand of ADD and AND: a register or an immediate
+pub trait IntoOperand {
+    fn into_operand(self) -> Operand;
+}
+
+impl IntoOperand for Register {
+    fn into_operand(self) -> Operand {
+        Operand::Register(self)
+    }
+}
+
+impl IntoOperand for i16 {
+    fn into_operand(self) -> Operand {
+        Operand::Immediate(self)
+    }
+}
+
+/// Words to be placed in memory from an origin
+#[derive(Debug, Clone)]
+pub struct Program {
+    origin: u16,
+    words: Vec<u16>,
+    error: Option<VMError>,
+}
+
+impl Program {
+    pub fn at(origin: u16) -> Self {
+        Program {
+            origin,
+            words: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// Appends an already built instruction
+    pub fn instruction(mut self, instruction: Instruction) -> Self {
+        let fits = match instruction {
+            Instruction::Br { pc_offset, .. }
+            | Instruction::Ld { pc_offset, .. }
+            | Instruction::St { pc_offset, .. }
+            | Instruction::Ldi { pc_offset, .. }
+            | Instruction::Sti { pc_offset, .. }
+            | Instruction::Lea { pc_offset, .. } => fits(pc_offset, 9),
+            Instruction::Add {
+                operand: Operand::Immediate(value),
+                ..
+            }
+            | Instruction::And {
+                operand: Operand::Immediate(value),
+                ..
+            } => fits(value, 5),
+            Instruction::Ldr { offset, .. } | Instruction::Str { offset, .. } => fits(offset, 6),
+            Instruction::Jsr {
+                target: JsrTarget::Offset(offset),
+            } => fits(offset, 11),
+            Instruction::Trap { trap_vector } => trap_vector <= 0xFF,
+            _ => true,
+        };
+        if !fits && self.error.is_none() {
+            self.error = Some(VMError::InvalidArgument(format!(
+                "Instruction {} of the program has an operand that does not fit: {instruction:?}",
+                self.words.len()
+            )));
+        }
+        self.fill(instruction.encode())
+    }
+
+    /// Appends a raw word, like `.FILL`
+    pub fn fill(mut self, word: u16) -> Self {
+        self.words.push(word);
+        self
+    }
+
+    /// Appends `count` zero words, like `.BLKW`
+    pub fn blkw(mut self, count: usize) -> Self {
+        self.words.extend(core::iter::repeat_n(0, count));
+        self
+    }
+
+    /// Appends `text` one character per word with a terminating zero, like
+    /// `.STRINGZ`
+    pub fn stringz(mut self, text: &str) -> Self {
+        self.words.extend(text.bytes().map(u16::from));
+        self.fill(0)
+    }
+
+    /// Branches when any of the given condition codes is set
+    pub fn br(self, n: bool, z: bool, p: bool, pc_offset: i16) -> Self {
+        self.instruction(Instruction::Br { n, z, p, pc_offset })
+    }
+
+    pub fn brn(self, pc_offset: i16) -> Self {
+        self.br(true, false, false, pc_offset)
+    }
+
+    pub fn brz(self, pc_offset: i16) -> Self {
+        self.br(false, true, false, pc_offset)
+    }
+
+    pub fn brp(self, pc_offset: i16) -> Self {
+        self.br(false, false, true, pc_offset)
+    }
+
+    /// Unconditional branch
+    pub fn brnzp(self, pc_offset: i16) -> Self {
+        self.br(true, true, true, pc_offset)
+    }
+
+    pub fn add(self, dr: Register, sr1: Register, operand: impl IntoOperand) -> Self {
+        self.instruction(Instruction::Add {
+            dr,
+            sr1,
+            operand: operand.into_operand(),
+        })
+    }
+
+    pub fn and(self, dr: Register, sr1: Register, operand: impl IntoOperand) -> Self {
+        self.instruction(Instruction::And {
+            dr,
+            sr1,
+            operand: operand.into_operand(),
+        })
+    }
+
+    pub fn not(self, dr: Register, sr: Register) -> Self {
+        self.instruction(Instruction::Not { dr, sr })
+    }
+
+    pub fn ld(self, dr: Register, pc_offset: i16) -> Self {
+        self.instruction(Instruction::Ld { dr, pc_offset })
+    }
+
+    pub fn ldi(self, dr: Register, pc_offset: i16) -> Self {
+        self.instruction(Instruction::Ldi { dr, pc_offset })
+    }
+
+    pub fn ldr(self, dr: Register, base: Register, offset: i16) -> Self {
+        self.instruction(Instruction::Ldr { dr, base, offset })
+    }
+
+    pub fn lea(self, dr: Register, pc_offset: i16) -> Self {
+        self.instruction(Instruction::Lea { dr, pc_offset })
+    }
+
+    pub fn st(self, sr: Register, pc_offset: i16) -> Self {
+        self.instruction(Instruction::St { sr, pc_offset })
+    }
+
+    pub fn sti(self, sr: Register, pc_offset: i16) -> Self {
+        self.instruction(Instruction::Sti { sr, pc_offset })
+    }
+
+    pub fn str(self, sr: Register, base: Register, offset: i16) -> Self {
+        self.instruction(Instruction::Str { sr, base, offset })
+    }
+
+    pub fn jmp(self, base: Register) -> Self {
+        self.instruction(Instruction::Jmp { base })
+    }
+
+    pub fn ret(self) -> Self {
+        self.jmp(R7)
+    }
+
+    pub fn jsr(self, pc_offset: i16) -> Self {
+        self.instruction(Instruction::Jsr {
+            target: JsrTarget::Offset(pc_offset),
+        })
+    }
+
+    pub fn jsrr(self, base: Register) -> Self {
+        self.instruction(Instruction::Jsr {
+            target: JsrTarget::Register(base),
+        })
+    }
+
+    pub fn trap(self, trap_vector: u16) -> Self {
+        self.instruction(Instruction::Trap { trap_vector })
+    }
+
+    pub fn trap_getc(self) -> Self {
+        self.trap(0x20)
+    }
+
+    pub fn trap_out(self) -> Self {
+        self.trap(0x21)
+    }
+
+    pub fn trap_puts(self) -> Self {
+        self.trap(0x22)
+    }
+
+    pub fn trap_in(self) -> Self {
+        self.trap(0x23)
+    }
+
+    pub fn trap_putsp(self) -> Self {
+        self.trap(0x24)
+    }
+
+    pub fn trap_halt(self) -> Self {
+        self.trap(0x25)
+    }
+
+    /// Address the next word will be placed at
+    pub fn here(&self) -> u16 {
+        let length = u16::try_from(self.words.len()).unwrap_or(u16::MAX);
+        self.origin.wrapping_add(length)
+    }
+
+    /// The encoded words, or the first operand that did not fit
+    pub fn words(&self) -> Result<&[u16], VMError> {
+        match &self.error {
+            Some(error) => Err(error.clone()),
+            None => Ok(&self.words),
+        }
+    }
+
+    /// The program as an object file: the origin followed by the words, big
+    /// endian
+    pub fn image(&self) -> Result<Vec<u8>, VMError> {
+        let words = self.words()?;
+        Ok(core::iter::once(self.origin)
+            .chain(words.iter().copied())
+            .flat_map(u16::to_be_bytes)
+            .collect())
+    }
+
+    /// Loads the program like an image and points the PC at its origin
+    pub fn load_into(&self, vm: &mut VM) -> Result<(), VMError> {
+        vm.read_image_bytes(&self.image()?)?;
+        vm.set_pc(self.origin);
+        Ok(())
+    }
+}
@@ -0,0 +1,216 @@
+//! Randomized but reproducible test vectors, so graders can give every
+//! student a variant of the same exercise: the same seed always generates
+//! the same keys and memory.
+//!
+//! ```
+//! use lc3_vm::{testgen::{seed_from, Generator}, vm::VM};
+//!
+//! let mut generator = Generator::new(seed_from("student-42"));
+//! // ten numbers to sort at x4000, and their count typed on the keyboard
+//! let mut numbers = generator.array(0x4000, 10, -100..=100);
+//! generator.input_line(&numbers.len().to_string());
+//! let vector = generator.finish();
+//!
+//! let mut vm = VM::new();
+//! vector.apply(&mut vm).unwrap();
+//! assert_eq!(vector.input, b"10\n");
+//! numbers.sort();
+//! // ...run the submission with `vector.input` as its keys and compare
+//! // the words at x4000 with `numbers`
+//! ```
+//!
+//! The generator is a SplitMix64, so vectors only depend on the seed and
+//! on the calls made, on every platform and version of the crate.
+
+use alloc::{format, string::String, vec::Vec};
+use core::ops::RangeInclusive;
+
+use crate::{errors::VMError, vm::VM};
+
+/// Seed of the variant named `name`, e.g. a student ID, with FNV-1a
+pub fn seed_from(name: &str) -> u64 {
+    name.bytes().fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01B3)
+    })
+}
+
+/// Seeded pseudo-random numbers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `range`, both ends included
+    pub fn range(&mut self, range: RangeInclusive<i16>) -> i16 {
+        let (low, high) = (i32::from(*range.start()), i32::from(*range.end()));
+        if high <= low {
+            return *range.start();
+        }
+        let span = u64::from(high.abs_diff(low)).saturating_add(1);
+        let offset = self
+            .next_u64()
+            .checked_rem(span)
+            .and_then(|offset| i32::try_from(offset).ok())
+            .unwrap_or_default();
+        i16::try_from(low.saturating_add(offset)).unwrap_or(*range.start())
+    }
+
+    /// An element of `choices`, or `None` if it is empty
+    pub fn choose<'a, T>(&mut self, choices: &'a [T]) -> Option<&'a T> {
+        let length = u64::try_from(choices.len()).ok()?;
+        let index = self.next_u64().checked_rem(length)?;
+        choices.get(usize::try_from(index).ok()?)
+    }
+
+    /// Puts `items` in a random order
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for last in (1..items.len()).rev() {
+            let span = u64::try_from(last).unwrap_or(u64::MAX).saturating_add(1);
+            let other = self
+                .next_u64()
+                .checked_rem(span)
+                .and_then(|other| usize::try_from(other).ok())
+                .unwrap_or_default();
+            items.swap(last, other);
+        }
+    }
+}
+
+/// Words written to memory before the program runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Precondition {
+    pub address: u16,
+    pub words: Vec<u16>,
+}
+
+/// Keys and memory of a test
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestVector {
+    pub seed: u64,
+    /// Keys typed for the program
+    pub input: Vec<u8>,
+    pub memory: Vec<Precondition>,
+}
+
+impl TestVector {
+    /// Writes the memory preconditions into `vm`, after its images are
+    /// loaded. The input is left to the console of the embedder.
+    pub fn apply(&self, vm: &mut VM) -> Result<(), VMError> {
+        for precondition in &self.memory {
+            let mut address = precondition.address;
+            for &word in &precondition.words {
+                vm.poke(address, word)?;
+                address = address.wrapping_add(1);
+            }
+        }
+        Ok(())
+    }
+
+    /// The input as text, for the `input_text` of batch manifests
+    pub fn input_text(&self) -> String {
+        self.input.iter().copied().map(char::from).collect()
+    }
+}
+
+/// Builds a `TestVector`, returning what it generates so graders can
+/// compute the expected results
+#[derive(Debug, Clone)]
+pub struct Generator {
+    rng: Rng,
+    vector: TestVector,
+}
+
+impl Generator {
+    pub fn new(seed: u64) -> Self {
+        Generator {
+            rng: Rng::new(seed),
+            vector: TestVector {
+                seed,
+                input: Vec::new(),
+                memory: Vec::new(),
+            },
+        }
+    }
+
+    /// The numbers behind the generator, for data of other shapes
+    pub fn rng(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+
+    /// Places `length` numbers from `range` at `address`
+    pub fn array(&mut self, address: u16, length: usize, range: RangeInclusive<i16>) -> Vec<i16> {
+        let numbers: Vec<i16> = (0..length).map(|_| self.rng.range(range.clone())).collect();
+        self.words(
+            address,
+            numbers
+                .iter()
+                .map(|number| u16::from_ne_bytes(number.to_ne_bytes()))
+                .collect(),
+        );
+        numbers
+    }
+
+    /// Places the numbers `1..=length` in a random order at `address`
+    pub fn permutation(&mut self, address: u16, length: u16) -> Vec<u16> {
+        let mut numbers: Vec<u16> = (1..=length).collect();
+        self.rng.shuffle(&mut numbers);
+        self.words(address, numbers.clone());
+        numbers
+    }
+
+    /// Places a string of `length` characters of `alphabet` at `address`, a
+    /// character per word followed by a zero word
+    pub fn string(&mut self, address: u16, length: usize, alphabet: &[u8]) -> String {
+        let text = self.text(length, alphabet);
+        self.words(address, text.bytes().map(u16::from).chain([0]).collect());
+        text
+    }
+
+    /// Places `words` at `address`
+    pub fn words(&mut self, address: u16, words: Vec<u16>) {
+        self.vector.memory.push(Precondition { address, words });
+    }
+
+    /// Types a number from `range` in decimal, followed by a newline
+    pub fn input_number(&mut self, range: RangeInclusive<i16>) -> i16 {
+        let number = self.rng.range(range);
+        self.input_line(&format!("{number}"));
+        number
+    }
+
+    /// Types `length` characters of `alphabet`, followed by a newline
+    pub fn input_word(&mut self, length: usize, alphabet: &[u8]) -> String {
+        let text = self.text(length, alphabet);
+        self.input_line(&text);
+        text
+    }
+
+    /// Types `line` followed by a newline
+    pub fn input_line(&mut self, line: &str) {
+        self.vector.input.extend(line.bytes().chain([b'\n']));
+    }
+
+    pub fn finish(self) -> TestVector {
+        self.vector
+    }
+
+    fn text(&mut self, length: usize, alphabet: &[u8]) -> String {
+        (0..length)
+            .filter_map(|_| self.rng.choose(alphabet).copied())
+            .map(char::from)
+            .collect()
+    }
+}
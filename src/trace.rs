@@ -0,0 +1,461 @@
+//! Instruction traces: one line per executed instruction with its
+//! disassembly and only the registers, condition codes and memory it
+//! changed, e.g. `x3004 ADD R0, R0, #1 ; R0: x0003→x0004, COND=P`.
+//! `Step::colored` renders the same line in aligned columns with ANSI
+//! colors for terminals.
+//!
+//! Steps can also be recorded in a compact binary form, `HEADER` followed
+//! by one record per step, all little-endian:
+//!
+//! | field | size |
+//! |---|---|
+//! | PC, instruction | 2 + 2 bytes |
+//! | mask of the changed registers, bit n for Rn | 1 byte |
+//! | new condition codes: 0 unchanged, 1 N, 2 Z, 3 P | 1 byte |
+//! | number of memory writes | 2 bytes |
+//! | each changed register, before and after | 2 + 2 bytes |
+//! | each write, address and value | 2 + 2 bytes |
+//!
+//! `verify` replays a program against such a recording, and
+//! `first_divergence` compares two traces of either format.
+
+use alloc::{
+    boxed::Box,
+    format,
+    rc::Rc,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{cell::RefCell, fmt};
+
+use crate::{
+    disassembler::disassemble,
+    errors::VMError,
+    observer::Observer,
+    register::Register,
+    vm::{Psr, RegisterChange, VM},
+};
+
+/// An executed instruction with what it changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub pc: u16,
+    pub raw: u16,
+    pub registers: Vec<RegisterChange>,
+    /// The new N, Z and P bits of the PSR, if they changed
+    pub condition: Option<u16>,
+    /// Words written by the instruction or its trap, with their new value,
+    /// in the order they were written
+    pub writes: Vec<(u16, u16)>,
+}
+
+impl Step {
+    /// The changes after the `;` of a trace line, empty if there are none
+    pub fn changes(&self) -> String {
+        let registers = self.registers.iter().map(|change| {
+            format!(
+                "{}: x{:04X}→x{:04X}",
+                change.register, change.before, change.after
+            )
+        });
+        let condition = self
+            .condition
+            .map(|condition| format!("COND={}", condition_letter(condition)));
+        let writes = self
+            .writes
+            .iter()
+            .map(|(address, value)| format!("mem[x{address:04X}]=x{value:04X}"));
+        let changes: Vec<String> = registers.chain(condition).chain(writes).collect();
+        changes.join(", ")
+    }
+
+    /// The trace line with ANSI colors: opcodes, registers, addresses and
+    /// immediates in distinct colors and the changed values highlighted.
+    /// The mnemonic and operands are padded so the changes line up.
+    pub fn colored(&self) -> String {
+        let text = disassemble(self.pc, self.raw);
+        let (mnemonic, operands) = text.split_once(' ').unwrap_or((&text, ""));
+        let mut line = format!(
+            "{} {}",
+            paint(&format!("x{:04X}", self.pc), DIM),
+            paint(&format!("{mnemonic:<MNEMONIC_WIDTH$}"), OPCODE)
+        );
+        let painted: Vec<String> = operands
+            .split(", ")
+            .filter(|operand| !operand.is_empty())
+            .map(paint_operand)
+            .collect();
+        line.push(' ');
+        line.push_str(&painted.join(", "));
+        let changes = self.colored_changes();
+        if !changes.is_empty() {
+            let padding = OPERANDS_WIDTH.saturating_sub(operands.chars().count());
+            line.extend(core::iter::repeat_n(' ', padding));
+            line.push_str(&paint(" ;", DIM));
+            line.push(' ');
+            line.push_str(&changes);
+        }
+        line
+    }
+
+    /// Appends the binary record of the step to `out`. Only the first
+    /// 65535 memory writes are recorded.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        let mask = self.registers.iter().fold(0u8, |mask, change| {
+            mask | 1u8
+                .checked_shl(u32::from(change.register.number()))
+                .unwrap_or(0)
+        });
+        let condition = match self.condition.map(condition_letter) {
+            None => 0,
+            Some('N') => 1,
+            Some('Z') => 2,
+            Some(_) => 3,
+        };
+        let writes = u16::try_from(self.writes.len()).unwrap_or(u16::MAX);
+        out.extend(self.pc.to_le_bytes());
+        out.extend(self.raw.to_le_bytes());
+        out.extend([mask, condition]);
+        out.extend(writes.to_le_bytes());
+        let mut registers: Vec<&RegisterChange> = self.registers.iter().collect();
+        registers.sort_by_key(|change| change.register.number());
+        for change in registers {
+            out.extend(change.before.to_le_bytes());
+            out.extend(change.after.to_le_bytes());
+        }
+        for (address, value) in self.writes.iter().take(usize::from(writes)) {
+            out.extend(address.to_le_bytes());
+            out.extend(value.to_le_bytes());
+        }
+    }
+
+    /// Reads the record at the start of `bytes`, advancing past it
+    fn decode(bytes: &mut &[u8]) -> Result<Step, VMError> {
+        let pc = read_word(bytes)?;
+        let raw = read_word(bytes)?;
+        let [mask, condition] = read_word(bytes)?.to_le_bytes();
+        let count = read_word(bytes)?;
+        let condition = match condition {
+            0 => None,
+            1 => Some(0b100),
+            2 => Some(0b010),
+            3 => Some(0b001),
+            other => {
+                return Err(VMError::InvalidTrace(format!(
+                    "Invalid condition codes {other} at x{pc:04X}"
+                )))
+            }
+        };
+        let mut registers = Vec::new();
+        for register in (0..).map_while(Register::new) {
+            if mask & 1u8.checked_shl(u32::from(register.number())).unwrap_or(0) != 0 {
+                registers.push(RegisterChange {
+                    register,
+                    before: read_word(bytes)?,
+                    after: read_word(bytes)?,
+                });
+            }
+        }
+        let mut writes = Vec::new();
+        for _ in 0..count {
+            writes.push((read_word(bytes)?, read_word(bytes)?));
+        }
+        Ok(Step {
+            pc,
+            raw,
+            registers,
+            condition,
+            writes,
+        })
+    }
+
+    fn colored_changes(&self) -> String {
+        let registers = self.registers.iter().map(|change| {
+            format!(
+                "{}: {}→{}",
+                paint(&change.register.to_string(), REGISTER),
+                paint(&format!("x{:04X}", change.before), DIM),
+                paint(&format!("x{:04X}", change.after), CHANGED)
+            )
+        });
+        let condition = self.condition.map(|condition| {
+            format!(
+                "COND={}",
+                paint(&condition_letter(condition).to_string(), CHANGED)
+            )
+        });
+        let writes = self.writes.iter().map(|(address, value)| {
+            format!(
+                "mem[{}]={}",
+                paint(&format!("x{address:04X}"), ADDRESS),
+                paint(&format!("x{value:04X}"), CHANGED)
+            )
+        });
+        let changes: Vec<String> = registers.chain(condition).chain(writes).collect();
+        changes.join(", ")
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "x{:04X} {}", self.pc, disassemble(self.pc, self.raw))?;
+        let changes = self.changes();
+        if changes.is_empty() {
+            Ok(())
+        } else {
+            write!(f, " ; {changes}")
+        }
+    }
+}
+
+/// Width of the widest mnemonic, `BRnzp`
+const MNEMONIC_WIDTH: usize = 5;
+/// Width the operands are padded to, enough for `R0, R1, #-16`
+const OPERANDS_WIDTH: usize = 12;
+
+const DIM: &str = "2";
+const OPCODE: &str = "1;34";
+const REGISTER: &str = "36";
+const ADDRESS: &str = "33";
+const IMMEDIATE: &str = "35";
+const CHANGED: &str = "1;32";
+
+/// `text` in the ANSI style `code`
+fn paint(text: &str, code: &str) -> String {
+    format!("\x1b[{code}m{text}\x1b[0m")
+}
+
+/// An operand colored by its kind: register, address or immediate
+fn paint_operand(operand: &str) -> String {
+    let code = if operand.starts_with('x') {
+        ADDRESS
+    } else if operand.starts_with('#') {
+        IMMEDIATE
+    } else {
+        REGISTER
+    };
+    paint(operand, code)
+}
+
+/// Start of every binary trace: a magic number and the format version
+pub const HEADER: [u8; 5] = *b"LC3T\x01";
+
+/// Steps of a binary trace, see `records`
+pub struct Records<'a> {
+    bytes: &'a [u8],
+}
+
+/// Reads the steps of the binary trace `bytes`, which must start with
+/// `HEADER`
+pub fn records(bytes: &[u8]) -> Result<Records<'_>, VMError> {
+    match bytes.strip_prefix(&HEADER) {
+        Some(bytes) => Ok(Records { bytes }),
+        None => Err(VMError::InvalidTrace(String::from(
+            "Not a binary trace, or written by another version",
+        ))),
+    }
+}
+
+impl Iterator for Records<'_> {
+    type Item = Result<Step, VMError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.bytes.is_empty() {
+            return None;
+        }
+        let step = Step::decode(&mut self.bytes);
+        if step.is_err() {
+            self.bytes = &[];
+        }
+        Some(step)
+    }
+}
+
+fn read_word(bytes: &mut &[u8]) -> Result<u16, VMError> {
+    let (word, rest) = bytes
+        .split_first_chunk::<2>()
+        .ok_or_else(|| VMError::InvalidTrace(String::from("The last record is truncated")))?;
+    *bytes = rest;
+    Ok(u16::from_le_bytes(*word))
+}
+
+/// Steps `vm` through the binary trace `recording`, checking that every
+/// instruction changes the same state as when it was recorded. Stops at
+/// the end of the recording and returns the number of steps verified.
+/// Replaces any attached observer.
+pub fn verify(vm: &mut VM, recording: &[u8]) -> Result<u64, VMError> {
+    let executed = Rc::new(RefCell::new(None));
+    let sink = Rc::clone(&executed);
+    vm.set_observer(Box::new(Tracer::new(move |step| {
+        *sink.borrow_mut() = Some(step.clone());
+    })));
+    let mut verified = 0u64;
+    for expected in records(recording)? {
+        let expected = expected?;
+        let number = verified.saturating_add(1);
+        if vm.is_halted() {
+            return Err(VMError::TraceMismatch(format!(
+                "The program halted before step {number}, `{expected}`"
+            )));
+        }
+        vm.step()?;
+        match executed.take() {
+            Some(actual) if actual == expected => verified = number,
+            Some(actual) => {
+                return Err(VMError::TraceMismatch(format!(
+                    "Step {number} was recorded as `{expected}` but executed as `{actual}`"
+                )))
+            }
+            None => {
+                return Err(VMError::TraceMismatch(format!(
+                    "Step {number}, `{expected}`, did not execute"
+                )))
+            }
+        }
+    }
+    Ok(verified)
+}
+
+/// The lines of a trace file: text as written by `--trace`, or the binary
+/// records of `--trace-binary` rendered like text
+pub fn read_lines(bytes: &[u8]) -> Result<Vec<String>, VMError> {
+    if bytes.starts_with(&HEADER) {
+        return records(bytes)?
+            .map(|step| step.map(|step| step.to_string()))
+            .collect();
+    }
+    let text = core::str::from_utf8(bytes)
+        .map_err(|_| VMError::InvalidTrace(String::from("Neither text nor a binary trace")))?;
+    Ok(text.lines().map(String::from).collect())
+}
+
+/// Where two traces stop agreeing, see `first_divergence`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Number of the first differing step, counting from 1
+    pub step: usize,
+    /// Steps both traces share right before the divergence
+    pub before: Vec<String>,
+    /// Steps of each trace from the divergence on. One of them is empty
+    /// when that trace ended early.
+    pub left: Vec<String>,
+    pub right: Vec<String>,
+}
+
+/// Compares two traces step by step, returning the first step that differs
+/// with up to `context` steps around it, or `None` if they are identical
+pub fn first_divergence(left: &[String], right: &[String], context: usize) -> Option<Divergence> {
+    let common = left
+        .iter()
+        .zip(right)
+        .take_while(|(left, right)| left == right)
+        .count();
+    if common == left.len() && common == right.len() {
+        return None;
+    }
+    let after = |lines: &[String]| -> Vec<String> {
+        lines
+            .iter()
+            .skip(common)
+            .take(context.saturating_add(1))
+            .cloned()
+            .collect()
+    };
+    Some(Divergence {
+        step: common.saturating_add(1),
+        before: left
+            .iter()
+            .take(common)
+            .skip(common.saturating_sub(context))
+            .cloned()
+            .collect(),
+        left: after(left),
+        right: after(right),
+    })
+}
+
+/// The divergence like a unified diff: shared steps indented, the steps
+/// of the left trace after `-` and those of the right one after `+`
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "First difference at step {}:", self.step)?;
+        for line in &self.before {
+            writeln!(f, "  {line}")?;
+        }
+        for line in &self.left {
+            writeln!(f, "- {line}")?;
+        }
+        if self.left.is_empty() {
+            writeln!(f, "- (end of trace)")?;
+        }
+        for line in &self.right {
+            writeln!(f, "+ {line}")?;
+        }
+        if self.right.is_empty() {
+            writeln!(f, "+ (end of trace)")?;
+        }
+        Ok(())
+    }
+}
+
+/// `N`, `Z` or `P` for the condition codes `condition` of a PSR
+pub fn condition_letter(condition: u16) -> char {
+    Psr::from_bits(condition).condition_letter()
+}
+
+/// Observer handing every executed instruction to a callback as a `Step`
+pub struct Tracer {
+    sink: Box<dyn FnMut(&Step)>,
+    /// Registers and PSR before the current instruction
+    registers: [u16; 8],
+    psr: Psr,
+    writes: Vec<(u16, u16)>,
+}
+
+impl Tracer {
+    pub fn new<F>(sink: F) -> Self
+    where
+        F: FnMut(&Step) + 'static,
+    {
+        Tracer {
+            sink: Box::new(sink),
+            registers: [0; 8],
+            psr: Psr::default(),
+            writes: Vec::new(),
+        }
+    }
+}
+
+impl Observer for Tracer {
+    fn before_instruction(&mut self, vm: &VM, _pc: u16, _raw: u16) {
+        self.registers = vm.registers();
+        self.psr = vm.psr();
+        self.writes.clear();
+    }
+
+    fn after_instruction(&mut self, vm: &VM, pc: u16, raw: u16) {
+        let registers = (0..)
+            .map_while(Register::new)
+            .zip(self.registers.iter().zip(vm.registers()))
+            .filter(|(_, (before, after))| **before != *after)
+            .map(|(register, (&before, after))| RegisterChange {
+                register,
+                before,
+                after,
+            })
+            .collect();
+        let step = Step {
+            pc,
+            raw,
+            registers,
+            condition: (vm.psr().condition_codes() != self.psr.condition_codes())
+                .then_some(vm.psr().condition_codes()),
+            writes: core::mem::take(&mut self.writes),
+        };
+        (self.sink)(&step);
+    }
+
+    fn on_mem_write(&mut self, address: u16, value: u16) {
+        self.writes.push((address, value));
+    }
+}
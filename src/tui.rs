@@ -0,0 +1,379 @@
+//! Terminal UI debugger showing the disassembly around the PC, registers,
+//! a memory pane and the program output side by side.
+//!
+//! While stopped: `s` steps, `c` continues, `b` toggles a breakpoint at the
+//! selected line, the arrows move the selection, PageUp/PageDown scroll the
+//! memory pane and `q` quits. While running, `Esc` pauses and any other key
+//! is typed into the program.
+//!
+//! After a step the registers, condition codes and memory words it changed
+//! are highlighted and the rest are dimmed.
+
+use std::time::Duration;
+
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout, Rect},
+    style::{Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Paragraph, Wrap},
+    DefaultTerminal, Frame,
+};
+
+use crate::{
+    console::SharedConsole,
+    disassembler::disassemble,
+    errors::{IoError, VMError},
+    register::Register,
+    vm::{StateDiff, StopReason, VM},
+};
+
+/// Instructions executed between redraws while running
+const RUN_BATCH: u32 = 20_000;
+/// Words shown per row of the memory pane
+const MEMORY_ROW: u16 = 8;
+/// Words scrolled by PageUp/PageDown in the memory pane
+const MEMORY_PAGE: u16 = 64;
+/// Output kept for the console pane
+const OUTPUT_LIMIT: usize = 16 * 1024;
+
+/// Runs the debugger on `vm` until the user quits
+pub fn run(mut vm: VM) -> Result<(), VMError> {
+    let console = SharedConsole::new();
+    vm.set_console(Box::new(console.clone()));
+    vm.set_undo(true);
+    let mut terminal = ratatui::try_init().map_err(terminal_error)?;
+    let result = Debugger::new(vm, console).run(&mut terminal);
+    ratatui::try_restore().map_err(terminal_error)?;
+    result
+}
+
+struct Debugger {
+    vm: VM,
+    console: SharedConsole,
+    output: String,
+    running: bool,
+    /// Selected line of the disassembly pane
+    cursor: u16,
+    memory_start: u16,
+    /// What the last step changed, `None` unless the last command was a
+    /// step
+    changes: Option<StateDiff>,
+    status: String,
+    quit: bool,
+}
+
+impl Debugger {
+    fn new(vm: VM, console: SharedConsole) -> Self {
+        let pc = vm.pc();
+        Debugger {
+            vm,
+            console,
+            output: String::new(),
+            running: false,
+            cursor: pc,
+            memory_start: pc,
+            changes: None,
+            status: String::from("Stopped"),
+            quit: false,
+        }
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<(), VMError> {
+        while !self.quit {
+            terminal
+                .draw(|frame| self.draw(frame))
+                .map_err(terminal_error)?;
+            let timeout = if self.running {
+                Duration::ZERO
+            } else {
+                Duration::from_millis(250)
+            };
+            if event::poll(timeout).map_err(terminal_error)? {
+                if let Event::Key(key) = event::read().map_err(terminal_error)? {
+                    if key.kind == KeyEventKind::Press {
+                        self.handle_key(key);
+                    }
+                }
+            }
+            if self.running {
+                self.run_batch();
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit = true;
+            return;
+        }
+        if self.running {
+            match key.code {
+                KeyCode::Esc => self.stop("Paused"),
+                KeyCode::Enter => self.console.push_input([b'\n']),
+                KeyCode::Char(character) => {
+                    if let Ok(byte) = u8::try_from(character) {
+                        self.console.push_input([byte]);
+                    }
+                }
+                _ => {}
+            }
+            return;
+        }
+        match key.code {
+            KeyCode::Char('q') => self.quit = true,
+            KeyCode::Char('s') | KeyCode::F(10) => self.step(),
+            KeyCode::Char('u') => self.undo(),
+            KeyCode::Char('c') | KeyCode::F(5) => {
+                self.running = true;
+                self.changes = None;
+                self.status = String::from("Running (Esc to pause, keys go to the program)");
+            }
+            KeyCode::Char('b') | KeyCode::F(9) => self.toggle_breakpoint(),
+            KeyCode::Up => self.cursor = self.cursor.wrapping_sub(1),
+            KeyCode::Down => self.cursor = self.cursor.wrapping_add(1),
+            KeyCode::PageUp => {
+                self.memory_start = self.memory_start.wrapping_sub(MEMORY_PAGE);
+            }
+            KeyCode::PageDown => {
+                self.memory_start = self.memory_start.wrapping_add(MEMORY_PAGE);
+            }
+            _ => {}
+        }
+    }
+
+    fn toggle_breakpoint(&mut self) {
+        if !self.vm.remove_breakpoint(self.cursor) {
+            self.vm.add_breakpoint(self.cursor);
+        }
+    }
+
+    fn step(&mut self) {
+        if self.vm.waits_for_key() && !self.console.has_input() {
+            self.status = String::from("The program waits for a key: continue (c) and type it");
+            return;
+        }
+        let stop = self.vm.step_diff().map(|(stop, changes)| {
+            self.changes = Some(changes);
+            stop
+        });
+        self.collect_output();
+        match stop {
+            Ok(StopReason::Halted) => self.stop("Halted"),
+            Ok(StopReason::Break) => self.stop("Stopped by the break key"),
+            Ok(_) => self.stop("Stopped"),
+            Err(error) => {
+                self.changes = None;
+                self.stop(&format!("Error: {error:#}"));
+            }
+        }
+    }
+
+    fn undo(&mut self) {
+        self.changes = None;
+        match self.vm.undo() {
+            Ok(()) => self.stop("Undid the last instruction"),
+            Err(_) => self.stop("Nothing to undo"),
+        }
+    }
+
+    fn run_batch(&mut self) {
+        for _ in 0..RUN_BATCH {
+            if self.vm.waits_for_key() && !self.console.has_input() {
+                self.status = String::from("Waiting for a key (Esc to pause)");
+                break;
+            }
+            match self.vm.step() {
+                Ok(StopReason::Halted) => {
+                    self.collect_output();
+                    return self.stop("Halted");
+                }
+                Ok(StopReason::Break) => {
+                    self.collect_output();
+                    return self.stop("Stopped by the break key");
+                }
+                Ok(StopReason::DeviceAccess { address, write }) => {
+                    self.collect_output();
+                    let verb = if write { "writing" } else { "reading" };
+                    return self.stop(&format!("Stopped after {verb} x{address:04X}"));
+                }
+                Ok(_) => {}
+                Err(error) => {
+                    self.collect_output();
+                    return self.stop(&format!("Error: {error:#}"));
+                }
+            }
+            let message = match self.vm.pending_stop() {
+                Some(StopReason::Trap(vector)) => format!("Stopped before TRAP x{vector:02X}"),
+                Some(StopReason::Interrupt(vector)) => {
+                    format!("Stopped before exception x{vector:02X}")
+                }
+                Some(_) => format!("Breakpoint at x{:04X}", self.vm.pc()),
+                None => continue,
+            };
+            self.collect_output();
+            return self.stop(&message);
+        }
+        self.collect_output();
+    }
+
+    fn stop(&mut self, status: &str) {
+        self.running = false;
+        self.cursor = self.vm.pc();
+        self.status = status.to_owned();
+    }
+
+    fn collect_output(&mut self) {
+        self.output.push_str(&self.console.take_output());
+        if self.output.len() > OUTPUT_LIMIT {
+            let excess = self.output.len().saturating_sub(OUTPUT_LIMIT);
+            let cut = (excess..self.output.len())
+                .find(|index| self.output.is_char_boundary(*index))
+                .unwrap_or(excess);
+            self.output.drain(..cut);
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let [top, bottom] =
+            Layout::vertical([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(main);
+        let [code, registers] =
+            Layout::horizontal([Constraint::Min(0), Constraint::Length(24)]).areas(top);
+        let [memory, output] =
+            Layout::horizontal([Constraint::Length(50), Constraint::Min(0)]).areas(bottom);
+
+        self.draw_code(frame, code);
+        self.draw_registers(frame, registers);
+        self.draw_memory(frame, memory);
+        let console = Paragraph::new(self.visible_output(output))
+            .wrap(Wrap { trim: false })
+            .block(Block::bordered().title(" Console "));
+        frame.render_widget(console, output);
+        let help = " s step  u undo  c continue  b breakpoint  ↑↓ select  PgUp/PgDn memory  q quit";
+        frame.render_widget(
+            Line::from(format!(" {} |{help}", self.status)).reversed(),
+            status,
+        );
+    }
+
+    fn draw_code(&self, frame: &mut Frame, area: Rect) {
+        let height = area.height.saturating_sub(2);
+        let first = self.cursor.saturating_sub(height / 2);
+        let pc = self.vm.pc();
+        let lines: Vec<Line> = (0..height)
+            .filter_map(|row| first.checked_add(row))
+            .map(|address| {
+                let raw = self.vm.peek(address);
+                let marker = match (address == pc, self.vm.is_breakpoint(address)) {
+                    (true, true) => "●▶",
+                    (true, false) => " ▶",
+                    (false, true) => "● ",
+                    (false, false) => "  ",
+                };
+                let text = format!(
+                    "{marker} x{address:04X}  {raw:04X}  {}",
+                    disassemble(address, raw)
+                );
+                let mut style = Style::default();
+                if address == pc {
+                    style = style.yellow().add_modifier(Modifier::BOLD);
+                }
+                if address == self.cursor && !self.running {
+                    style = style.reversed();
+                }
+                Line::styled(text, style)
+            })
+            .collect();
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(" Disassembly ")),
+            area,
+        );
+    }
+
+    fn draw_registers(&self, frame: &mut Frame, area: Rect) {
+        let mut lines: Vec<Line> = (0..8)
+            .filter_map(Register::new)
+            .map(|register| {
+                let value = self.vm.register(register);
+                let signed = i16::from_ne_bytes(value.to_ne_bytes());
+                let changed = self.changed(|changes| {
+                    changes
+                        .registers
+                        .iter()
+                        .any(|change| change.register == register)
+                });
+                Line::styled(format!("{register}  x{value:04X} {signed:>6}"), changed)
+            })
+            .collect();
+        let pc_changed = self.changed(|changes| changes.pc.is_some());
+        lines.push(Line::styled(
+            format!("PC  x{:04X}", self.vm.pc()),
+            pc_changed,
+        ));
+        let flags = self.vm.psr().condition_letter();
+        let flags_changed = self.changed(|changes| changes.psr.is_some());
+        lines.push(Line::styled(format!("CC  {flags}"), flags_changed));
+        if self.vm.is_halted() {
+            lines.push(Line::from("HALTED").red());
+        }
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(" Registers ")),
+            area,
+        );
+    }
+
+    fn draw_memory(&self, frame: &mut Frame, area: Rect) {
+        let rows = area.height.saturating_sub(2);
+        let lines: Vec<Line> = (0..rows)
+            .map(|row| {
+                let start = self.memory_start.wrapping_add(row.wrapping_mul(MEMORY_ROW));
+                let mut spans = vec![Span::raw(format!("x{start:04X}:"))];
+                for offset in 0..MEMORY_ROW {
+                    let address = start.wrapping_add(offset);
+                    let changed = self.changed(|changes| {
+                        changes
+                            .memory
+                            .iter()
+                            .any(|change| change.address == address)
+                    });
+                    spans.push(Span::raw(" "));
+                    spans.push(Span::styled(
+                        format!("{:04X}", self.vm.peek(address)),
+                        changed,
+                    ));
+                }
+                Line::from(spans)
+            })
+            .collect();
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(" Memory ")),
+            area,
+        );
+    }
+
+    /// Style of a value the last step changed if `changed` says so, or of
+    /// one it left alone. Values are plain unless the last command was a
+    /// step.
+    fn changed(&self, changed: impl Fn(&StateDiff) -> bool) -> Style {
+        match &self.changes {
+            None => Style::default(),
+            Some(changes) if changed(changes) => Style::default().green().bold(),
+            Some(_) => Style::default().dim(),
+        }
+    }
+
+    /// Last lines of output that fit in `area`
+    fn visible_output(&self, area: Rect) -> String {
+        let rows = usize::from(area.height.saturating_sub(2));
+        let lines: Vec<&str> = self.output.lines().collect();
+        let skip = lines.len().saturating_sub(rows);
+        lines.into_iter().skip(skip).collect::<Vec<_>>().join("\n")
+    }
+}
+
+fn terminal_error(error: std::io::Error) -> VMError {
+    VMError::TerminalMode(IoError::caused_by("Terminal UI failed", error))
+}
@@ -0,0 +1,138 @@
+//! File systems behind the optional file traps, which are disabled unless
+//! enabled with `VM::enable_file_traps`. Programs only see flat file names
+//! made of letters, digits, `.`, `_` and `-`, so they cannot leave the
+//! directory or in-memory store they were given.
+//!
+//! The traps take their arguments in R0-R2 and return a result in R0, or -1
+//! on failure, setting the condition codes from it:
+//!
+//! - `FOPEN` (x80): opens the file named by the string at R0 with mode R1,
+//!   0 to read, 1 to write from scratch or 2 to append. Returns a handle.
+//! - `FREAD` (x81): reads up to R2 bytes of handle R0 into the words at R1.
+//!   Returns the number of bytes read, 0 at the end of the file.
+//! - `FWRITE` (x82): writes the low bytes of the R2 words at R1 to handle R0.
+//!   Returns the number of bytes written.
+//! - `FCLOSE` (x83): closes handle R0, saving what was written. Returns 0.
+
+use alloc::{collections::BTreeMap, rc::Rc, string::String, vec::Vec};
+use core::cell::RefCell;
+#[cfg(feature = "std")]
+use std::{fs, io::ErrorKind, path::PathBuf};
+
+#[cfg(feature = "std")]
+use crate::errors::IoError;
+use crate::errors::VMError;
+
+/// Opens a file
+pub const FOPEN: u16 = 0x80;
+/// Reads from an open file
+pub const FREAD: u16 = 0x81;
+/// Writes to an open file
+pub const FWRITE: u16 = 0x82;
+/// Closes a file
+pub const FCLOSE: u16 = 0x83;
+
+/// Longest file name accepted from programs
+pub const MAX_NAME_LENGTH: usize = 64;
+
+/// Storage for the files of a program. Files are loaded whole when opened
+/// and stored whole when closed.
+pub trait FileSystem {
+    /// Contents of `name`, or `None` if there is no such file
+    fn load(&mut self, name: &str) -> Result<Option<Vec<u8>>, VMError>;
+
+    /// Replaces the contents of `name`, creating it if needed
+    fn store(&mut self, name: &str, contents: &[u8]) -> Result<(), VMError>;
+}
+
+/// Whether programs may use `name`: a flat name without path separators
+/// or a leading dot
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LENGTH
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || ".-_".contains(character))
+}
+
+/// Files kept in memory. Clones share the same files, so the embedder can
+/// keep one to inspect what the program wrote.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryFileSystem(Rc<RefCell<BTreeMap<String, Vec<u8>>>>);
+
+impl MemoryFileSystem {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&self, name: &str, contents: &[u8]) {
+        self.0
+            .borrow_mut()
+            .insert(String::from(name), contents.to_vec());
+    }
+
+    pub fn get(&self, name: &str) -> Option<Vec<u8>> {
+        self.0.borrow().get(name).cloned()
+    }
+}
+
+impl FileSystem for MemoryFileSystem {
+    fn load(&mut self, name: &str) -> Result<Option<Vec<u8>>, VMError> {
+        Ok(self.get(name))
+    }
+
+    fn store(&mut self, name: &str, contents: &[u8]) -> Result<(), VMError> {
+        self.insert(name, contents);
+        Ok(())
+    }
+}
+
+/// Files in a host directory, without access to its subdirectories
+#[cfg(feature = "std")]
+pub struct DirectoryFileSystem {
+    root: PathBuf,
+}
+
+#[cfg(feature = "std")]
+impl DirectoryFileSystem {
+    pub fn new(root: &str) -> Result<Self, VMError> {
+        if !fs::metadata(root).is_ok_and(|metadata| metadata.is_dir()) {
+            return Err(VMError::InvalidArgument(format!(
+                "{root} is not a directory"
+            )));
+        }
+        Ok(DirectoryFileSystem {
+            root: PathBuf::from(root),
+        })
+    }
+
+    fn path(&self, name: &str) -> Result<PathBuf, VMError> {
+        if !is_valid_name(name) {
+            return Err(VMError::StandardIO(IoError::new(format!(
+                "Invalid file name {name}"
+            ))));
+        }
+        Ok(self.root.join(name))
+    }
+}
+
+#[cfg(feature = "std")]
+impl FileSystem for DirectoryFileSystem {
+    fn load(&mut self, name: &str) -> Result<Option<Vec<u8>>, VMError> {
+        match fs::read(self.path(name)?) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(VMError::ReadFile(IoError::caused_by(
+                format!("Could not read {name}"),
+                e,
+            ))),
+        }
+    }
+
+    fn store(&mut self, name: &str, contents: &[u8]) -> Result<(), VMError> {
+        fs::write(self.path(name)?, contents).map_err(|e| {
+            VMError::StandardIO(IoError::caused_by(format!("Could not write {name}"), e))
+        })
+    }
+}
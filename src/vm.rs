@@ -0,0 +1,1231 @@
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+mod background;
+#[cfg(not(feature = "threaded"))]
+mod block_cache;
+mod builder;
+mod channels;
+mod conformance;
+mod debug;
+mod decoded;
+mod dma;
+mod encoding;
+mod environment;
+mod events;
+mod extended_traps;
+mod file_traps;
+mod fork;
+mod host_traps;
+mod hotplug;
+mod inline_asm;
+mod interrupts;
+#[cfg(all(feature = "jit", not(feature = "threaded")))]
+mod jit;
+mod keyboard;
+mod metrics;
+mod microcode;
+mod psr;
+mod rng;
+mod state;
+#[cfg(feature = "threaded")]
+mod threaded;
+mod timer;
+mod undo;
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+mod watchdog;
+mod windows;
+
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub use background::VmHandle;
+pub use builder::{TrapMessages, TrapMode, VMBuilder};
+pub use conformance::{Conformance, Profile, RtiMode};
+pub use debug::{DeviceAccess, MemoryMut, MemoryView, StopReason};
+pub use dma::{DMACR, DMADST, DMALEN, DMASR, DMASRC, DMA_DONE, DMA_ERROR};
+pub use environment::{ARGS_START, GETARG, GETENV};
+pub use events::{Events, ExecEvent};
+pub use extended_traps::{GETS, PUTD, TIME};
+pub use host_traps::{OpcodeHandler, TrapHandler, FIRST_FREE_TRAP};
+pub use hotplug::{UnmappedAccess, BUS_ERROR};
+pub use interrupts::{INTERRUPT_VECTOR_TABLE, SUPERVISOR_STACK};
+pub use metrics::{Metrics, MetricsCallback};
+pub use microcode::{MicroStep, Microstate, FETCH_STATE};
+pub use psr::{Psr, PSR};
+pub use rng::RNGDR;
+pub use state::{Checkpoints, MemoryChange, RegisterChange, StateDiff, VmState};
+pub use timer::{TMPR, TMSR};
+pub use windows::WindowMut;
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    format,
+    rc::Rc,
+    string::String,
+    vec::Vec,
+};
+use core::task::Poll;
+#[cfg(feature = "std")]
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+use crate::serial::{SerialPort, SRDR, SRSR, STDR, STSR};
+use crate::{
+    ansi,
+    clock::{Clock, Speed, TimeSource},
+    console::{Console, ConsoleConfig, NullConsole},
+    display::{Display, DFR, DISPLAY_START},
+    errors::VMError,
+    instructions::{sign_extend, Instruction, Opcode, TrapCode},
+    loop_detector::LoopDetector,
+    memory::{image_origin, Memory, KBDR, KBSR, MMIO_START},
+    mmio::DeviceRegion,
+    observer::Observer,
+    register::Register,
+    stack::StackChecker,
+};
+
+const PC_START: u16 = 0x3000;
+const REGISTER_COUNT: usize = 8;
+
+type Handler = fn(&mut VM, u16) -> Result<(), VMError>;
+
+/// Instruction handlers indexed by opcode
+const DISPATCH_TABLE: [Handler; 16] = [
+    VM::op_br,
+    VM::op_add,
+    VM::op_ld,
+    VM::op_st,
+    VM::op_jsr,
+    VM::op_and,
+    VM::op_ldr,
+    VM::op_str,
+    VM::op_rti,
+    VM::op_not,
+    VM::op_ldi,
+    VM::op_sti,
+    VM::op_jmp,
+    VM::op_reserved, // RES
+    VM::op_lea,
+    VM::op_trap,
+];
+
+pub struct VM {
+    memory: Memory,
+    /// Images loaded so far, for `reload`, shared with forks
+    images: Vec<Rc<[u8]>>,
+    registers: [u16; REGISTER_COUNT],
+    pc: u16,
+    /// PC the program starts at after a reset
+    entry: u16,
+    /// Whether `entry` was chosen explicitly instead of taken from the
+    /// origin of the first image
+    entry_fixed: bool,
+    psr: Psr,
+    running: bool,
+    halted: bool,
+    breakpoints: BTreeSet<u16>,
+    /// Trap vectors debugger runs stop at, `None` for all of them
+    trap_breaks: BTreeSet<Option<u16>>,
+    break_on_interrupt: bool,
+    /// Device registers debugger runs stop after accessing
+    device_breaks: BTreeMap<u16, DeviceAccess>,
+    /// First device access of the instruction being stepped that a device
+    /// break stops for, and whether it was a write
+    device_hit: Option<(u16, bool)>,
+    stack_checker: Option<StackChecker>,
+    loop_detector: Option<LoopDetector>,
+    clock: Clock,
+    /// Instructions left before `run_with_fuel` returns
+    fuel: Option<u64>,
+    /// Instructions `run` may execute before failing
+    instruction_limit: Option<u64>,
+    /// Characters `run` may print before failing
+    output_limit: Option<usize>,
+    /// Characters printed since `run` started
+    printed: usize,
+    #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+    watchdog: Option<watchdog::Watchdog>,
+    console: Box<dyn Console>,
+    console_config: ConsoleConfig,
+    /// Keys translated by the key map that the program has not read yet
+    pending_keys: VecDeque<u8>,
+    /// Key events not read by the program yet, in scancode mode
+    pending_scancodes: VecDeque<u16>,
+    /// Start of a UTF-8 sequence being printed
+    utf8: Vec<u8>,
+    /// Escape sequence being printed, with `ansi` in the console config
+    ansi: ansi::Parser,
+    /// Set when the break key was read, until the run or step stops
+    break_requested: bool,
+    trap_handlers: BTreeMap<u16, TrapHandler>,
+    reserved_opcode: Option<OpcodeHandler>,
+    observer: Option<Box<dyn Observer>>,
+    /// Words written by the instruction `step_diff` is executing
+    step_writes: Option<Vec<MemoryChange>>,
+    undo: undo::Undo,
+    interrupts: interrupts::Interrupts,
+    metrics: metrics::MetricsState,
+    extended_traps: bool,
+    time_source: TimeSource,
+    trap_messages: TrapMessages,
+    conformance: Conformance,
+    files: Option<file_traps::FileTraps>,
+    /// Variables the program reads with GETENV
+    env_vars: BTreeMap<String, String>,
+    /// Command line arguments in the argument area
+    args: Vec<String>,
+    #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+    serial: Option<SerialPort>,
+    display: Option<Display>,
+    /// `DMASR` of the block-copy device, `None` when it is not attached
+    dma: Option<u16>,
+    timer: Option<timer::Timer>,
+    rng: Option<rng::Random>,
+    /// Console channels besides the console of the traps
+    channels: Vec<channels::Channel>,
+    /// Shared memory windows of the host
+    windows: Vec<windows::Window>,
+    unmapped_access: UnmappedAccess,
+    /// Registers of the microarchitecture, see `micro_step`
+    micro: Microstate,
+    #[cfg(not(feature = "threaded"))]
+    blocks: block_cache::BlockCache,
+    #[cfg(feature = "threaded")]
+    threaded: threaded::ThreadedCode,
+    #[cfg(all(feature = "jit", not(feature = "threaded")))]
+    jit: Option<jit::Jit>,
+}
+
+impl Default for VM {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VM {
+    pub fn new() -> Self {
+        VM {
+            memory: Memory::new(),
+            images: Vec::new(),
+            registers: [0; REGISTER_COUNT],
+            pc: PC_START,
+            entry: PC_START,
+            entry_fixed: false,
+            psr: Psr::default(),
+            running: false,
+            halted: false,
+            breakpoints: BTreeSet::new(),
+            trap_breaks: BTreeSet::new(),
+            break_on_interrupt: false,
+            device_breaks: BTreeMap::new(),
+            device_hit: None,
+            stack_checker: None,
+            loop_detector: None,
+            clock: Clock::default(),
+            fuel: None,
+            instruction_limit: None,
+            output_limit: None,
+            printed: 0,
+            #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+            watchdog: None,
+            console: default_console(),
+            console_config: ConsoleConfig::default(),
+            pending_keys: VecDeque::new(),
+            pending_scancodes: VecDeque::new(),
+            utf8: Vec::new(),
+            ansi: ansi::Parser::new(),
+            break_requested: false,
+            trap_handlers: BTreeMap::new(),
+            reserved_opcode: None,
+            observer: None,
+            step_writes: None,
+            undo: undo::Undo::default(),
+            interrupts: interrupts::Interrupts::default(),
+            metrics: metrics::MetricsState::default(),
+            extended_traps: false,
+            time_source: TimeSource::Host,
+            trap_messages: TrapMessages::STANDARD,
+            conformance: Conformance::default(),
+            files: None,
+            env_vars: BTreeMap::new(),
+            args: Vec::new(),
+            #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+            serial: None,
+            display: None,
+            dma: None,
+            timer: None,
+            rng: None,
+            channels: Vec::new(),
+            windows: Vec::new(),
+            unmapped_access: UnmappedAccess::Memory,
+            micro: Microstate::default(),
+            #[cfg(not(feature = "threaded"))]
+            blocks: block_cache::BlockCache::new(),
+            #[cfg(feature = "threaded")]
+            threaded: threaded::ThreadedCode::new(),
+            #[cfg(all(feature = "jit", not(feature = "threaded")))]
+            jit: jit::Jit::new(),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn read_image(&mut self, path: &str) -> Result<(), VMError> {
+        self.read_image_bytes(&crate::memory::read_image_file(path)?)
+    }
+
+    pub fn read_image_bytes(&mut self, bytes: &[u8]) -> Result<(), VMError> {
+        self.clear_decoded();
+        self.images.push(Rc::from(bytes));
+        event!(
+            DEBUG,
+            "lc3_vm::memory",
+            bytes = bytes.len(),
+            "loading image"
+        );
+        self.memory.read_image_bytes(bytes)?;
+        if self.images.len() == 1 && !self.entry_fixed {
+            if let Some(origin) = image_origin(bytes) {
+                self.entry = origin;
+                self.pc = origin;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the machine to its state before the program started: zeroed
+    /// registers, Z set, the PC at the entry point and device registers
+    /// cleared. The RAM is zeroed and the images loaded so far forgotten
+    /// too unless `keep_memory` is set, which keeps whatever the last run
+    /// left in it. Keys read ahead and characters or escape sequences
+    /// printed in part are dropped. Breakpoints, handlers and devices stay
+    /// configured.
+    pub fn reset(&mut self, keep_memory: bool) {
+        if keep_memory {
+            self.memory.clear_devices();
+        } else {
+            self.memory.clear();
+            self.images.clear();
+            self.clear_decoded();
+            self.restore_args();
+        }
+        self.pending_keys.clear();
+        self.utf8.clear();
+        self.ansi = ansi::Parser::new();
+        self.registers = [0; REGISTER_COUNT];
+        self.pc = self.entry;
+        self.psr = Psr::default();
+        self.running = false;
+        self.halted = false;
+        self.micro = Microstate::default();
+        self.fuel = None;
+        self.undo.forget();
+        self.interrupts = interrupts::Interrupts::default();
+        if let Some(checker) = &mut self.stack_checker {
+            checker.reset();
+        }
+        if let Some(detector) = &mut self.loop_detector {
+            detector.reset();
+        }
+        if let Some(files) = &mut self.files {
+            files.discard_open();
+        }
+        if self.dma.is_some() {
+            self.dma = Some(DMA_DONE);
+        }
+        self.restart_timer();
+        self.reseed_rng();
+    }
+
+    /// Resets the machine and loads the images loaded so far again, from
+    /// copies kept in memory, to rerun the program from a clean state
+    pub fn reload(&mut self) -> Result<(), VMError> {
+        let images = core::mem::take(&mut self.images);
+        self.reset(false);
+        let result = images
+            .iter()
+            .try_for_each(|image| self.memory.read_image_bytes(image));
+        self.images = images;
+        result
+    }
+
+    /// Keeps the RAM in the memory file at `path` so it outlives the
+    /// process, loading what an earlier run left in it. Images loaded
+    /// afterwards write over the file, and `reset(false)` and `reload`
+    /// zero it like any RAM. See `crate::persistent` for the format.
+    #[cfg(feature = "persistent")]
+    pub fn persist_memory(&mut self, path: &std::path::Path) -> Result<(), VMError> {
+        self.memory.persist_to(path)?;
+        self.clear_decoded();
+        Ok(())
+    }
+
+    /// Writes the RAM kept by `persist_memory` to disk now
+    #[cfg(feature = "persistent")]
+    pub fn sync_memory(&self) -> Result<(), VMError> {
+        self.memory.sync()
+    }
+
+    /// Forgets every decoded instruction after memory was replaced
+    fn clear_decoded(&mut self) {
+        #[cfg(not(feature = "threaded"))]
+        self.blocks.clear();
+        #[cfg(feature = "threaded")]
+        self.threaded.clear();
+    }
+
+    /// Enables the stack discipline checker, validated after every instruction
+    pub fn set_stack_checker(&mut self, checker: StackChecker) {
+        self.stack_checker = Some(checker);
+    }
+
+    /// Enables stopping programs that spin without making progress
+    pub fn set_loop_detector(&mut self, detector: LoopDetector) {
+        self.loop_detector = Some(detector);
+    }
+
+    /// Limits how many instructions or cycles are executed per second
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.clock = Clock::new(speed);
+    }
+
+    pub fn speed(&self) -> Speed {
+        self.clock.speed()
+    }
+
+    /// Where the time read by the TIME trap comes from
+    pub fn set_time_source(&mut self, source: TimeSource) {
+        self.time_source = source;
+    }
+
+    pub fn time_source(&self) -> TimeSource {
+        self.time_source
+    }
+
+    /// Makes `run` fail once it executed `limit` instructions without the
+    /// program halting, or removes the limit with `None`
+    pub fn set_instruction_limit(&mut self, limit: Option<u64>) -> Result<(), VMError> {
+        if limit == Some(0) {
+            return Err(VMError::InvalidArgument(String::from(
+                "The instruction limit must be positive",
+            )));
+        }
+        self.instruction_limit = limit;
+        Ok(())
+    }
+
+    /// Makes `run` fail once the program printed `limit` characters, escape
+    /// sequences and display frames included, or removes the limit with
+    /// `None`
+    pub fn set_output_limit(&mut self, limit: Option<usize>) -> Result<(), VMError> {
+        if limit == Some(0) {
+            return Err(VMError::InvalidArgument(String::from(
+                "The output limit must be positive",
+            )));
+        }
+        self.output_limit = limit;
+        Ok(())
+    }
+
+    /// Enables or disables compiling hot blocks to native code. The JIT
+    /// builds on the basic-block cache, so with the `threaded` feature,
+    /// whose backend takes precedence, this does nothing. Enabling it is
+    /// ignored with less memory than the whole xFE00 words, as compiled code
+    /// accesses memory without bounds checks.
+    #[cfg(feature = "jit")]
+    pub fn set_jit(&mut self, enabled: bool) {
+        #[cfg(not(feature = "threaded"))]
+        {
+            let enabled = enabled && self.memory.size() >= usize::from(MMIO_START);
+            self.jit = if enabled { jit::Jit::new() } else { None };
+        }
+        #[cfg(feature = "threaded")]
+        let _ = enabled;
+        self.clear_decoded();
+    }
+
+    /// Hash of the registers, PC, PSR and memory, used to
+    /// compare the machine state across execution backends
+    #[cfg(feature = "std")]
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.registers.hash(&mut hasher);
+        self.pc.hash(&mut hasher);
+        self.psr.hash(&mut hasher);
+        self.memory.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Changes what IN and HALT print
+    pub fn set_trap_messages(&mut self, messages: TrapMessages) {
+        self.trap_messages = messages;
+    }
+
+    /// Makes the traps follow the LC-3 specification to the letter instead
+    /// of catching likely mistakes: OUT and PUTS print bits [7:0] of each
+    /// word, ignoring the others instead of failing, and PUTSP stops at a
+    /// zero high byte, which ends strings of odd length, instead of only at
+    /// a zero word.
+    pub fn set_strict_traps(&mut self, strict: bool) {
+        self.conformance.strict_traps = strict;
+    }
+
+    /// Replaces the terminal console used by the traps and keyboard
+    pub fn set_console(&mut self, console: Box<dyn Console>) {
+        self.console = console;
+    }
+
+    /// Attaches a serial port at `SRSR`-`STDR`, failing if another device
+    /// is mapped there
+    #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+    pub fn set_serial(&mut self, port: SerialPort) -> Result<(), VMError> {
+        self.memory.map_device_ranges("serial", &[(SRSR, STDR)])?;
+        self.serial = Some(port);
+        Ok(())
+    }
+
+    /// Shows the character grid at `DISPLAY_START` on the console each time
+    /// the program writes `DFR`, see `display`. Fails if another device is
+    /// mapped at the grid or `DFR`.
+    pub fn enable_display(&mut self) -> Result<(), VMError> {
+        self.set_display(Display::new())
+    }
+
+    /// Attaches `display`, a grid of any size, like `enable_display`
+    pub fn set_display(&mut self, display: Display) -> Result<(), VMError> {
+        self.memory
+            .map_device_ranges("display", &[(DISPLAY_START, display.end()), (DFR, DFR)])?;
+        self.display = Some(display);
+        Ok(())
+    }
+
+    /// Reserves `region` for a device the host implements, for instance with
+    /// an observer or trap handlers, so no other device can be attached
+    /// there. Fails if another device answers any of its addresses.
+    pub fn map_device(&mut self, region: DeviceRegion) -> Result<(), VMError> {
+        self.memory.map_device(region)
+    }
+
+    pub fn run(&mut self) -> Result<(), VMError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(target: "lc3_vm::vm", "run", pc = %format_args!("x{:04X}", self.pc)).entered();
+        self.running = true;
+        self.fuel = self.instruction_limit;
+        self.printed = 0;
+        self.undo.forget();
+        #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+        self.arm_watchdog();
+        self.start_timer();
+        let result = self.run_loop();
+        self.stop_timer();
+        #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+        let timeout = self.disarm_watchdog();
+        self.break_requested = false;
+        #[cfg(feature = "tracing")]
+        if let Err(error) = &result {
+            tracing::warn!(target: "lc3_vm::vm", %error, "run failed");
+        }
+        let exhausted = self.fuel == Some(0) && !self.halted;
+        self.fuel = None;
+        let flushed = self.console.flush().and_then(|()| self.flush_channels());
+        result.and(flushed)?;
+        #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+        if let Some(error) = timeout {
+            return Err(error);
+        }
+        match self.instruction_limit {
+            Some(limit) if exhausted => Err(VMError::InstructionLimit(format!(
+                "Stopped after {limit} instructions at {:#06x}",
+                self.pc
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Runs the program with its output discarded and no keyboard input,
+    /// for benchmarks and other runs without a terminal
+    pub fn run_headless(&mut self) -> Result<(), VMError> {
+        self.set_console(Box::new(NullConsole));
+        self.run()
+    }
+
+    /// Executes at most `fuel` instructions, returning `Poll::Ready` once
+    /// the program halted and `Poll::Pending` if the fuel ran out first, so
+    /// hosts can interleave the VM with their own event loop. The same fuel
+    /// always executes the same instructions, whatever the backend. Native
+    /// code from the JIT cannot stop at an exact count, so it is not used.
+    pub fn run_with_fuel(&mut self, fuel: u64) -> Result<Poll<()>, VMError> {
+        if self.halted {
+            return Ok(Poll::Ready(()));
+        }
+        if fuel == 0 {
+            return Ok(Poll::Pending);
+        }
+        #[cfg(feature = "tracing")]
+        let _span = tracing::trace_span!(target: "lc3_vm::vm", "run_with_fuel", fuel).entered();
+        self.fuel = Some(fuel);
+        self.running = true;
+        self.start_timer();
+        let result = self.run_loop();
+        self.stop_timer();
+        self.break_requested = false;
+        self.fuel = None;
+        let flushed = self.console.flush().and_then(|()| self.flush_channels());
+        result.and(flushed)?;
+        Ok(if self.halted {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        })
+    }
+
+    /// Paces the run to the speed for one more instruction
+    #[inline]
+    pub(crate) fn tick_clock(&mut self) {
+        let cycles = self.cycle_model().map(|_| self.metrics.counters.cycles);
+        self.clock.tick(cycles);
+    }
+
+    /// Accounts for an executed instruction, stopping the run when the fuel
+    /// is used up
+    #[inline]
+    fn tick(&mut self) {
+        self.tick_clock();
+        self.retire();
+        #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+        self.watch();
+        if let Some(fuel) = &mut self.fuel {
+            *fuel = fuel.saturating_sub(1);
+            if *fuel == 0 {
+                self.running = false;
+            }
+        }
+    }
+
+    #[cfg(feature = "threaded")]
+    fn run_loop(&mut self) -> Result<(), VMError> {
+        self.run_threaded()
+    }
+
+    #[cfg(not(feature = "threaded"))]
+    fn run_loop(&mut self) -> Result<(), VMError> {
+        if self.inspects_instructions() {
+            self.run_instructions()
+        } else {
+            self.run_blocks()
+        }
+    }
+
+    /// Whether the checkers, an observer, the cycle model or a tracing
+    /// subscriber need to see every instruction
+    fn inspects_instructions(&self) -> bool {
+        self.stack_checker.is_some()
+            || self.loop_detector.is_some()
+            || self.observer.is_some()
+            || self.cycle_model().is_some()
+            || traces_instructions()
+    }
+
+    /// Fetches and executes one instruction at a time, as needed by the
+    /// checkers and observers that inspect every instruction
+    #[cfg(not(feature = "threaded"))]
+    fn run_instructions(&mut self) -> Result<(), VMError> {
+        while self.running {
+            self.take_interrupt()?;
+            let pc = self.pc;
+            let instruction = self.memory.read(pc)?;
+            self.pc = self.next_pc(pc)?;
+            self.execute(pc, instruction)?;
+            self.tick();
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, pc: u16, raw: u16) -> Result<(), VMError> {
+        if self.stack_checker.is_some() {
+            self.check_stack_before(pc, raw)?;
+        }
+        if self.observer.is_some() {
+            self.notify(|observer, vm| observer.before_instruction(vm, pc, raw));
+        }
+        event!(
+            TRACE,
+            "lc3_vm::vm",
+            pc = %format_args!("x{pc:04X}"),
+            raw = %format_args!("x{raw:04X}"),
+            "execute"
+        );
+        let handler = DISPATCH_TABLE
+            .get(usize::from(raw >> 12))
+            .copied()
+            .unwrap_or(VM::op_reserved);
+        handler(self, raw)?;
+        self.count_cycles(raw);
+        if self.stack_checker.is_some() || self.loop_detector.is_some() {
+            self.check_after(pc, raw)?;
+        }
+        if self.observer.is_some() {
+            self.notify(|observer, vm| observer.after_instruction(vm, pc, raw));
+        }
+        Ok(())
+    }
+
+    /// Calls the observer with a shared view of the VM
+    fn notify(&mut self, callback: impl FnOnce(&mut dyn Observer, &VM)) {
+        if let Some(mut observer) = self.observer.take() {
+            callback(observer.as_mut(), self);
+            self.observer.get_or_insert(observer);
+        }
+    }
+
+    /// Attaches `observer`, replacing any previous one. Execution falls back
+    /// to one instruction at a time until it is removed.
+    pub fn set_observer(&mut self, observer: Box<dyn Observer>) {
+        self.observer = Some(observer);
+    }
+
+    /// Detaches and returns the observer
+    pub fn remove_observer(&mut self) -> Option<Box<dyn Observer>> {
+        self.observer.take()
+    }
+
+    fn check_stack_before(&self, pc: u16, raw: u16) -> Result<(), VMError> {
+        if let Some(checker) = &self.stack_checker {
+            let pointer = self.read_register(checker.register());
+            let instruction = Instruction::decode(raw);
+            let addresses = self.conformance.addresses;
+            checker.check_instruction(pc, raw, &instruction, pointer, addresses)?;
+        }
+        Ok(())
+    }
+
+    fn check_after(&mut self, pc: u16, raw: u16) -> Result<(), VMError> {
+        if let Some(mut checker) = self.stack_checker {
+            let pointer = self.read_register(checker.register());
+            let result = checker.check_pointer(pc, raw, pointer);
+            self.stack_checker = Some(checker);
+            result?;
+        }
+        let device_accessed = self.memory.take_device_access();
+        if let Some(detector) = &mut self.loop_detector {
+            let side_effect = matches!(
+                Opcode::from_instruction(raw),
+                Opcode::St | Opcode::Str | Opcode::Sti | Opcode::Trap
+            );
+            if side_effect || device_accessed {
+                detector.reset();
+            } else {
+                detector.observe(pc, self.pc, self.registers, self.psr)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn op_br(&mut self, raw: u16) -> Result<(), VMError> {
+        if self.psr.branches(raw) {
+            self.pc = self.effective_address(self.pc, sign_extend(raw, 9))?;
+        }
+        Ok(())
+    }
+
+    fn op_add(&mut self, raw: u16) -> Result<(), VMError> {
+        let value = self
+            .read_register(sr1(raw))
+            .wrapping_add(self.second_operand(raw));
+        self.write_register_with_flags(dr(raw), value);
+        Ok(())
+    }
+
+    fn op_ld(&mut self, raw: u16) -> Result<(), VMError> {
+        let value = self.read_memory(self.pc_relative(raw)?)?;
+        self.write_register_with_flags(dr(raw), value);
+        Ok(())
+    }
+
+    fn op_st(&mut self, raw: u16) -> Result<(), VMError> {
+        let address = self.pc_relative(raw)?;
+        self.write_memory(address, self.read_register(dr(raw)))
+    }
+
+    fn op_jsr(&mut self, raw: u16) -> Result<(), VMError> {
+        let return_address = self.pc;
+        self.pc = if (raw >> 11) & 1 == 1 {
+            self.effective_address(self.pc, sign_extend(raw, 11))?
+        } else {
+            self.read_register(sr1(raw))
+        };
+        self.write_register(Register::R7, return_address);
+        Ok(())
+    }
+
+    fn op_and(&mut self, raw: u16) -> Result<(), VMError> {
+        let value = self.read_register(sr1(raw)) & self.second_operand(raw);
+        self.write_register_with_flags(dr(raw), value);
+        Ok(())
+    }
+
+    fn op_ldr(&mut self, raw: u16) -> Result<(), VMError> {
+        let value = self.read_memory(self.base_relative(raw)?)?;
+        self.write_register_with_flags(dr(raw), value);
+        Ok(())
+    }
+
+    fn op_str(&mut self, raw: u16) -> Result<(), VMError> {
+        let address = self.base_relative(raw)?;
+        self.write_memory(address, self.read_register(dr(raw)))
+    }
+
+    fn op_not(&mut self, raw: u16) -> Result<(), VMError> {
+        let value = !self.read_register(sr1(raw));
+        self.write_register_with_flags(dr(raw), value);
+        Ok(())
+    }
+
+    fn op_ldi(&mut self, raw: u16) -> Result<(), VMError> {
+        let pointer = self.read_memory(self.pc_relative(raw)?)?;
+        let value = self.read_memory(pointer)?;
+        self.write_register_with_flags(dr(raw), value);
+        Ok(())
+    }
+
+    fn op_sti(&mut self, raw: u16) -> Result<(), VMError> {
+        let pointer = self.read_memory(self.pc_relative(raw)?)?;
+        self.write_memory(pointer, self.read_register(dr(raw)))
+    }
+
+    fn op_jmp(&mut self, raw: u16) -> Result<(), VMError> {
+        self.pc = self.read_register(sr1(raw));
+        Ok(())
+    }
+
+    fn op_lea(&mut self, raw: u16) -> Result<(), VMError> {
+        let address = self.pc_relative(raw)?;
+        if self.conformance.lea_sets_flags {
+            self.write_register_with_flags(dr(raw), address);
+        } else {
+            self.write_register(dr(raw), address);
+        }
+        Ok(())
+    }
+
+    fn op_trap(&mut self, raw: u16) -> Result<(), VMError> {
+        self.write_register(Register::R7, self.pc);
+        self.count_trap();
+        if let Some(observer) = &mut self.observer {
+            observer.on_trap(raw & 0xFF);
+        }
+        self.trap(raw & 0xFF)
+    }
+
+    fn op_reserved(&mut self, raw: u16) -> Result<(), VMError> {
+        if Opcode::from_instruction(raw) == Opcode::Res {
+            if let Some(result) = self.custom_opcode(raw) {
+                return result;
+            }
+            if self.conformance.ignore_reserved_opcode {
+                return Ok(());
+            }
+        }
+        let pc = self.pc.wrapping_sub(1);
+        Err(VMError::InvalidOpcode(format!(
+            "Instruction {raw:#06x} at {pc:#06x} uses an unsupported opcode"
+        )))
+    }
+
+    /// Reads memory, updating the device registers when polled
+    #[inline]
+    fn read_memory(&mut self, address: u16) -> Result<u16, VMError> {
+        let value = if address >= MMIO_START {
+            // writes polling the device are not the program's
+            let hit = self.device_hit;
+            let value = if self.device_answers(address)? {
+                self.read_device(address)?
+            } else {
+                0
+            };
+            self.device_hit = hit;
+            self.hit_device(address, false);
+            value
+        } else {
+            self.memory.read(address)?
+        };
+        self.count_read();
+        if let Some(observer) = &mut self.observer {
+            observer.on_mem_read(address, value);
+        }
+        event!(
+            TRACE,
+            "lc3_vm::memory",
+            address = %format_args!("x{address:04X}"),
+            value = %format_args!("x{value:04X}"),
+            "read"
+        );
+        Ok(value)
+    }
+
+    fn read_device(&mut self, address: u16) -> Result<u16, VMError> {
+        if address == PSR {
+            return Ok(self.psr.bits());
+        }
+        if address == KBSR {
+            self.poll_keyboard()?;
+        }
+        if address == DMASR {
+            self.poll_dma()?;
+        }
+        if self.timer.is_some() {
+            self.poll_timer(address)?;
+        }
+        if address == RNGDR {
+            self.poll_rng()?;
+        }
+        if !self.channels.is_empty() {
+            self.poll_channel(address)?;
+        }
+        #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+        if self.serial.is_some() {
+            self.poll_serial(address)?;
+        }
+        self.memory.read(address)
+    }
+
+    #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+    fn poll_serial(&mut self, address: u16) -> Result<(), VMError> {
+        match address {
+            SRSR if self.memory.peek(SRSR) & (1 << 15) == 0 => {
+                if let Some(byte) = self.serial.as_mut().and_then(SerialPort::receive) {
+                    event!(DEBUG, "lc3_vm::devices", byte, "serial byte received");
+                    self.memory.write(SRDR, u16::from(byte))?;
+                    self.memory.write(SRSR, 1 << 15)?;
+                }
+                Ok(())
+            }
+            SRDR => self.memory.write(SRSR, 0),
+            STSR => self.memory.write(STSR, 1 << 15),
+            _ => Ok(()),
+        }
+    }
+
+    fn poll_keyboard(&mut self) -> Result<(), VMError> {
+        let key = if self.console_config.scancodes {
+            self.next_scancode()?
+        } else {
+            self.next_key(false)?.map(u16::from)
+        };
+        match key {
+            Some(key) => {
+                event!(DEBUG, "lc3_vm::devices", key, "key available in KBDR");
+                self.memory.write(KBSR, 1 << 15)?;
+                self.memory.write(KBDR, key)
+            }
+            None => self.memory.write(KBSR, 0),
+        }
+    }
+
+    #[inline]
+    fn write_memory(&mut self, address: u16, value: u16) -> Result<(), VMError> {
+        if address >= MMIO_START {
+            self.hit_device(address, true);
+            if !self.device_answers(address)? {
+                return Ok(());
+            }
+        }
+        #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+        if address == STDR {
+            if let Some(serial) = &mut self.serial {
+                let [low, _] = value.to_le_bytes();
+                event!(DEBUG, "lc3_vm::devices", byte = low, "serial byte sent");
+                serial.send(low)?;
+            }
+        }
+        if address == PSR {
+            self.psr = Psr::from_bits(value);
+        }
+        if address == DFR && self.display.is_some() {
+            self.present()?;
+        }
+        if address == DMACR && self.dma.is_some() {
+            self.start_dma()?;
+        }
+        if address == TMPR {
+            self.set_timer_period(value);
+        }
+        if !self.channels.is_empty() {
+            self.send_to_channel(address, value)?;
+        }
+        #[cfg(not(feature = "threaded"))]
+        self.blocks.invalidate(address);
+        #[cfg(feature = "threaded")]
+        self.threaded.invalidate(address);
+        if !self.windows.is_empty() {
+            self.note_window_write(address);
+        }
+        self.count_write();
+        if let Some(observer) = &mut self.observer {
+            observer.on_mem_write(address, value);
+        }
+        if let Some(writes) = &mut self.step_writes {
+            writes.push(MemoryChange {
+                address,
+                before: self.memory.peek(address),
+                after: value,
+            });
+        }
+        if self.undo.recording() {
+            self.undo.record(MemoryChange {
+                address,
+                before: self.memory.peek(address),
+                after: value,
+            });
+        }
+        event!(
+            TRACE,
+            "lc3_vm::memory",
+            address = %format_args!("x{address:04X}"),
+            value = %format_args!("x{value:04X}"),
+            "write"
+        );
+        self.memory.write(address, value)
+    }
+
+    /// Draws the cells of the grid that changed since the last frame
+    fn present(&mut self) -> Result<(), VMError> {
+        let Some(display) = &mut self.display else {
+            return Ok(());
+        };
+        let frame: Vec<u16> = (DISPLAY_START..=display.end())
+            .map(|address| self.memory.peek(address))
+            .collect();
+        event!(DEBUG, "lc3_vm::devices", "display frame presented");
+        let rendered = display.render(&frame);
+        self.count_output(rendered.chars().count())?;
+        for character in rendered.chars() {
+            self.console.write_char(character)?;
+        }
+        self.console.flush()
+    }
+
+    /// Records that `count` characters are about to be printed, failing
+    /// past the output limit
+    fn count_output(&mut self, count: usize) -> Result<(), VMError> {
+        self.printed = self.printed.saturating_add(count);
+        match self.output_limit {
+            Some(limit) if self.printed > limit => Err(VMError::OutputLimit(format!(
+                "Stopped after printing {limit} characters at {:#06x}",
+                self.pc
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Second operand of ADD and AND, either imm5 or SR2
+    #[inline]
+    fn second_operand(&self, raw: u16) -> u16 {
+        if (raw >> 5) & 1 == 1 {
+            u16::from_ne_bytes(sign_extend(raw, 5).to_ne_bytes())
+        } else {
+            self.read_register(Register::from_bits(raw))
+        }
+    }
+
+    #[inline]
+    fn pc_relative(&self, raw: u16) -> Result<u16, VMError> {
+        self.effective_address(self.pc, sign_extend(raw, 9))
+    }
+
+    #[inline]
+    fn base_relative(&self, raw: u16) -> Result<u16, VMError> {
+        self.effective_address(self.read_register(sr1(raw)), sign_extend(raw, 6))
+    }
+
+    #[inline]
+    fn read_register(&self, register: Register) -> u16 {
+        self.registers
+            .get(register.index())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    #[inline]
+    fn write_register(&mut self, register: Register, value: u16) {
+        if let Some(slot) = self.registers.get_mut(register.index()) {
+            *slot = value;
+        }
+    }
+
+    #[inline]
+    fn write_register_with_flags(&mut self, register: Register, value: u16) {
+        self.write_register(register, value);
+        self.psr.set_condition(value);
+    }
+
+    fn trap(&mut self, trap_vector: u16) -> Result<(), VMError> {
+        event!(
+            DEBUG,
+            "lc3_vm::traps",
+            vector = %format_args!("x{trap_vector:02X}"),
+            "trap"
+        );
+        let code = match TrapCode::try_from(trap_vector) {
+            Ok(code) => code,
+            Err(error) => {
+                return self
+                    .host_trap(trap_vector)
+                    .or_else(|| self.extended_trap(trap_vector))
+                    .or_else(|| self.file_trap(trap_vector))
+                    .or_else(|| self.env_trap(trap_vector))
+                    .unwrap_or(Err(error))
+            }
+        };
+        match code {
+            TrapCode::Getc => self.getc(),
+            TrapCode::Out => self.out(),
+            TrapCode::Puts => self.puts(),
+            TrapCode::In => self.in_trap(),
+            TrapCode::Putsp => self.putsp(),
+            TrapCode::Halt => self.halt(),
+        }
+    }
+
+    fn getc(&mut self) -> Result<(), VMError> {
+        let Some(key) = self.trap_key()? else {
+            return Ok(());
+        };
+        if self.console_config.getc_echo {
+            self.print_byte(key)?;
+        }
+        self.write_register_with_flags(Register::R0, u16::from(key));
+        Ok(())
+    }
+
+    fn out(&mut self) -> Result<(), VMError> {
+        self.print_word(self.trap_character(self.read_register(Register::R0)))?;
+        self.console.flush()
+    }
+
+    fn puts(&mut self) -> Result<(), VMError> {
+        let start = self.read_register(Register::R0);
+        for offset in 0..=u16::MAX {
+            let word = self.read_memory(start.wrapping_add(offset))?;
+            if word == 0 {
+                return self.console.flush();
+            }
+            self.print_word(self.trap_character(word))?;
+        }
+        Err(unterminated_string(start))
+    }
+
+    fn in_trap(&mut self) -> Result<(), VMError> {
+        self.write_str(self.trap_messages.input)?;
+        let Some(key) = self.trap_key()? else {
+            return Ok(());
+        };
+        if self.console_config.in_echo {
+            self.print_byte(key)?;
+            self.output('\n')?;
+        }
+        self.console.flush()?;
+        self.write_register_with_flags(Register::R0, u16::from(key));
+        Ok(())
+    }
+
+    fn putsp(&mut self) -> Result<(), VMError> {
+        let start = self.read_register(Register::R0);
+        for offset in 0..=u16::MAX {
+            let word = self.read_memory(start.wrapping_add(offset))?;
+            if word == 0 {
+                return self.console.flush();
+            }
+            let [high, low] = word.to_be_bytes();
+            self.print_byte(low)?;
+            if high != 0 {
+                self.print_byte(high)?;
+            } else if self.conformance.strict_traps {
+                return self.console.flush();
+            }
+        }
+        Err(unterminated_string(start))
+    }
+
+    /// Character printed by OUT or PUTS for `word`, only its low byte when
+    /// the traps are strict
+    fn trap_character(&self, word: u16) -> u16 {
+        if self.conformance.strict_traps {
+            word & 0xFF
+        } else {
+            word
+        }
+    }
+
+    fn halt(&mut self) -> Result<(), VMError> {
+        self.write_str(self.trap_messages.halt)?;
+        event!(INFO, "lc3_vm::vm", pc = %format_args!("x{:04X}", self.pc), "halted");
+        self.running = false;
+        self.halted = true;
+        Ok(())
+    }
+
+    /// Writes `character` to the console, telling the observer about it
+    fn output(&mut self, character: char) -> Result<(), VMError> {
+        let Some(character) = self.host_character(character) else {
+            return Ok(());
+        };
+        self.count_output(1)?;
+        if let Some(observer) = &mut self.observer {
+            observer.on_output(character);
+        }
+        if !self.console_config.ansi {
+            return self.console.write_char(character);
+        }
+        match self.ansi.feed(character) {
+            Some(ansi::Output::Text(character)) => self.console.write_char(character),
+            Some(ansi::Output::Command(command)) => self.console.command(&command),
+            None => Ok(()),
+        }
+    }
+
+    fn write_str(&mut self, text: &str) -> Result<(), VMError> {
+        text.chars()
+            .try_for_each(|character| self.output(character))
+    }
+}
+
+/// Whether a subscriber wants an event for every executed instruction
+fn traces_instructions() -> bool {
+    #[cfg(feature = "tracing")]
+    if tracing::enabled!(target: "lc3_vm::vm", tracing::Level::TRACE) {
+        return true;
+    }
+    false
+}
+
+/// The terminal, or a console without input or output on the web and
+/// without `std`, where the embedder injects its own
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+fn default_console() -> Box<dyn Console> {
+    Box::new(crate::console::TerminalConsole::new())
+}
+
+#[cfg(not(all(feature = "std", not(target_arch = "wasm32"))))]
+fn default_console() -> Box<dyn Console> {
+    Box::new(NullConsole)
+}
+
+/// A string filling the whole memory, which would otherwise wrap around and
+/// print the memory again, endlessly while the keyboard keeps a key ready
+#[cold]
+fn unterminated_string(start: u16) -> VMError {
+    VMError::MemoryIndex(format!(
+        "String at {start:#06x} has no terminating zero word"
+    ))
+}
+
+/// Destination (or source for stores) register, bits [11:9]
+#[inline]
+fn dr(raw: u16) -> Register {
+    Register::from_bits(raw >> 9)
+}
+
+/// First source or base register, bits [8:6]
+#[inline]
+fn sr1(raw: u16) -> Register {
+    Register::from_bits(raw >> 6)
+}
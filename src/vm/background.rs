@@ -0,0 +1,130 @@
+//! Runs the interpreter on its own thread, controlled through a handle. The
+//! VM is built on that thread because decoded code and consoles are not
+//! `Send`.
+
+use std::{
+    sync::mpsc::{self, Receiver, Sender, TryRecvError},
+    thread::{self, JoinHandle},
+};
+
+use super::{VmState, VM};
+use crate::errors::VMError;
+
+/// Instructions executed between checks for commands
+const BATCH: u64 = 10_000;
+
+enum Command {
+    Pause,
+    Resume,
+    Stop,
+    Query,
+}
+
+/// Controls a VM started with `VM::spawn`. Commands take effect between
+/// batches of instructions, so a program blocked on GETC or IN only reacts
+/// once it gets its key.
+pub struct VmHandle {
+    commands: Sender<Command>,
+    states: Receiver<VmState>,
+    thread: JoinHandle<Result<VmState, VMError>>,
+}
+
+impl VmHandle {
+    /// Stops executing instructions until `resume`
+    pub fn pause(&self) -> Result<(), VMError> {
+        self.send(Command::Pause)
+    }
+
+    pub fn resume(&self) -> Result<(), VMError> {
+        self.send(Command::Resume)
+    }
+
+    /// Current registers and run state, after every earlier command took
+    /// effect
+    pub fn query(&self) -> Result<VmState, VMError> {
+        self.send(Command::Query)?;
+        self.states.recv().map_err(|_| thread_exited())
+    }
+
+    /// Ends the thread and returns the final state, or the error that
+    /// stopped the program
+    pub fn stop(self) -> Result<VmState, VMError> {
+        // the thread may already have exited with an error, which join reports
+        let _ = self.commands.send(Command::Stop);
+        self.thread
+            .join()
+            .map_err(|_| VMError::Debugger(String::from("The VM thread panicked")))?
+    }
+
+    fn send(&self, command: Command) -> Result<(), VMError> {
+        self.commands.send(command).map_err(|_| thread_exited())
+    }
+}
+
+fn thread_exited() -> VMError {
+    VMError::Debugger(String::from(
+        "The VM thread has exited, stop the handle to get its error",
+    ))
+}
+
+impl VM {
+    /// Builds a VM with `build` on a new thread and runs it there. The
+    /// thread stays alive after the program halts so its state can still be
+    /// queried, until the handle is stopped or dropped.
+    ///
+    /// The program runs in batches of `run_with_fuel`, so it never uses the
+    /// JIT. Hosts that want it call `run` on a thread of their own.
+    pub fn spawn<F>(build: F) -> VmHandle
+    where
+        F: FnOnce() -> Result<VM, VMError> + Send + 'static,
+    {
+        let (commands, command_receiver) = mpsc::channel();
+        let (state_sender, states) = mpsc::channel();
+        let thread = thread::spawn(move || build()?.serve(&command_receiver, &state_sender));
+        VmHandle {
+            commands,
+            states,
+            thread,
+        }
+    }
+
+    fn serve(
+        &mut self,
+        commands: &Receiver<Command>,
+        states: &Sender<VmState>,
+    ) -> Result<VmState, VMError> {
+        let mut paused = false;
+        loop {
+            let command = if paused || self.halted {
+                match commands.recv() {
+                    Ok(command) => Some(command),
+                    Err(_) => return Ok(self.background_state(paused)),
+                }
+            } else {
+                match commands.try_recv() {
+                    Ok(command) => Some(command),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => return Ok(self.background_state(paused)),
+                }
+            };
+            match command {
+                Some(Command::Pause) => paused = true,
+                Some(Command::Resume) => paused = false,
+                Some(Command::Stop) => return Ok(self.background_state(paused)),
+                Some(Command::Query) => {
+                    // a dropped handle is noticed when receiving the next command
+                    let _ = states.send(self.background_state(paused));
+                }
+                // halting is seen through `self.halted` on the next iteration
+                None => _ = self.run_with_fuel(BATCH)?,
+            }
+        }
+    }
+
+    fn background_state(&self, paused: bool) -> VmState {
+        VmState {
+            paused,
+            ..self.state()
+        }
+    }
+}
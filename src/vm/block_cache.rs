@@ -0,0 +1,283 @@
+//! Basic-block cache: straight-line runs of instructions ending in a control
+//! transfer are decoded once, keyed by their start address, and executed
+//! without fetching or decoding again. Writing to any address covered by a
+//! cached block drops that block.
+
+use alloc::{boxed::Box, rc::Rc, vec, vec::Vec};
+#[cfg(feature = "jit")]
+use core::cell::{Cell, OnceCell};
+
+#[cfg(feature = "jit")]
+use super::jit::{CompiledBlock, JitState, EXIT_CODE_WRITTEN, EXIT_DEOPT, HOT_THRESHOLD};
+use super::{
+    decoded::{decode, DecodedOp},
+    VM,
+};
+#[cfg(feature = "jit")]
+use crate::clock::{Speed, TimeSource};
+use crate::{errors::VMError, memory::MEMORY_SIZE};
+
+/// Longest block decoded at once, bounding the work wasted when a block is
+/// invalidated
+const MAX_BLOCK_LENGTH: u16 = 64;
+
+/// Start addresses per page of the block table, allocated once a block
+/// starts in them so a machine running little code, like a fork, sets up
+/// little of the table
+const TABLE_PAGE_SHIFT: u32 = 10;
+const TABLE_PAGE: usize = 1 << TABLE_PAGE_SHIFT;
+const TABLE_PAGE_MASK: usize = TABLE_PAGE - 1;
+
+/// Blocks starting in a page of the table, by offset
+type TablePage = Box<[Option<Rc<Block>>]>;
+
+struct Block {
+    start: u16,
+    ops: Vec<DecodedOp>,
+    #[cfg(feature = "jit")]
+    executions: Cell<u32>,
+    #[cfg(feature = "jit")]
+    compiled: OnceCell<Option<CompiledBlock>>,
+}
+
+impl Block {
+    fn contains(&self, address: u16) -> bool {
+        usize::from(address)
+            .checked_sub(usize::from(self.start))
+            .is_some_and(|offset| offset < self.ops.len())
+    }
+}
+
+pub(super) struct BlockCache {
+    /// Cached blocks indexed by start address, in pages of `TABLE_PAGE`
+    /// allocated on first use
+    blocks: Vec<Option<TablePage>>,
+    /// How many cached blocks cover each address
+    coverage: Vec<u8>,
+    /// Set when a write dropped a block, so the running block stops
+    invalidated: bool,
+}
+
+impl BlockCache {
+    pub(super) fn new() -> Self {
+        BlockCache {
+            blocks: Vec::new(),
+            coverage: Vec::new(),
+            invalidated: false,
+        }
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.blocks.clear();
+        self.coverage.clear();
+    }
+
+    /// Drops every block containing `address` after it was written
+    #[inline]
+    pub(super) fn invalidate(&mut self, address: u16) {
+        let covered = self.coverage.get(usize::from(address)).copied();
+        if covered.unwrap_or(0) == 0 {
+            return;
+        }
+        self.invalidated = true;
+        // blocks never span more than MAX_BLOCK_LENGTH words, so only a few
+        // start addresses can cover `address`
+        for start in address.saturating_sub(MAX_BLOCK_LENGTH)..=address {
+            let covers = self
+                .slot(start)
+                .and_then(Option::as_ref)
+                .is_some_and(|block| block.contains(address));
+            if covers {
+                self.remove(start);
+            }
+        }
+    }
+
+    fn remove(&mut self, start: u16) {
+        let Some(block) = self.slot_mut(start).and_then(Option::take) else {
+            return;
+        };
+        let begin = usize::from(block.start);
+        let end = begin.saturating_add(block.ops.len());
+        for count in self.coverage.iter_mut().take(end).skip(begin) {
+            *count = count.saturating_sub(1);
+        }
+    }
+
+    fn insert(&mut self, block: Block) -> Rc<Block> {
+        if self.blocks.is_empty() {
+            self.blocks = vec![None; MEMORY_SIZE >> TABLE_PAGE_SHIFT];
+            self.coverage = vec![0; MEMORY_SIZE];
+        }
+        let begin = usize::from(block.start);
+        let end = begin.saturating_add(block.ops.len());
+        for count in self.coverage.iter_mut().take(end).skip(begin) {
+            *count = count.saturating_add(1);
+        }
+        let start = block.start;
+        let block = Rc::new(block);
+        if let Some(page) = self.blocks.get_mut(begin >> TABLE_PAGE_SHIFT) {
+            page.get_or_insert_with(|| vec![None; TABLE_PAGE].into_boxed_slice());
+        }
+        if let Some(slot) = self.slot_mut(start) {
+            *slot = Some(Rc::clone(&block));
+        }
+        block
+    }
+
+    fn get(&self, start: u16) -> Option<Rc<Block>> {
+        self.slot(start)?.clone()
+    }
+
+    /// Where the block starting at `start` is kept, if its page of the
+    /// table is allocated
+    #[inline]
+    fn slot(&self, start: u16) -> Option<&Option<Rc<Block>>> {
+        let start = usize::from(start);
+        self.blocks
+            .get(start >> TABLE_PAGE_SHIFT)?
+            .as_ref()?
+            .get(start & TABLE_PAGE_MASK)
+    }
+
+    fn slot_mut(&mut self, start: u16) -> Option<&mut Option<Rc<Block>>> {
+        let start = usize::from(start);
+        self.blocks
+            .get_mut(start >> TABLE_PAGE_SHIFT)?
+            .as_mut()?
+            .get_mut(start & TABLE_PAGE_MASK)
+    }
+
+    #[cfg(feature = "jit")]
+    fn coverage_ptr(&self) -> *const u8 {
+        self.coverage.as_ptr()
+    }
+}
+
+impl VM {
+    pub(super) fn run_blocks(&mut self) -> Result<(), VMError> {
+        while self.running {
+            self.take_interrupt()?;
+            let block = match self.blocks.get(self.pc) {
+                Some(block) => block,
+                None => self.decode_block(self.pc)?,
+            };
+            #[cfg(feature = "jit")]
+            if let Some(compiled) = self.compiled_block(&block) {
+                self.run_compiled(compiled)?;
+                continue;
+            }
+            self.blocks.invalidated = false;
+            for op in &block.ops {
+                self.pc = self.next_pc(self.pc)?;
+                op.execute(self)?;
+                self.tick();
+                if self.blocks.invalidated || !self.running || self.interrupt_raised() {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn decode_block(&mut self, start: u16) -> Result<Rc<Block>, VMError> {
+        let mut ops = Vec::new();
+        let mut address = start;
+        loop {
+            let op = decode(self.memory.read(address)?);
+            ops.push(op);
+            if ends_block(op.raw) || ops.len() >= usize::from(MAX_BLOCK_LENGTH) {
+                break;
+            }
+            match address.checked_add(1) {
+                Some(next) => address = next,
+                None => break,
+            }
+        }
+        Ok(self.blocks.insert(Block {
+            start,
+            ops,
+            #[cfg(feature = "jit")]
+            executions: Cell::new(0),
+            #[cfg(feature = "jit")]
+            compiled: OnceCell::new(),
+        }))
+    }
+
+    /// Returns the native code of `block`, compiling it once it is hot
+    #[cfg(feature = "jit")]
+    fn compiled_block(&mut self, block: &Block) -> Option<CompiledBlock> {
+        // native code runs many passes of a loop at once, so it cannot stop
+        // at an exact count, deliver interrupts or fire the timer on time,
+        // and stores to RAM without telling windows
+        if self.fuel.is_some()
+            || self.watching()
+            || self.has_windows()
+            || self.timer.is_some()
+            || self.interrupt_raised()
+        {
+            return None;
+        }
+        if let Some(compiled) = block.compiled.get() {
+            return *compiled;
+        }
+        let jit = self.jit.as_mut()?;
+        let executions = block.executions.get().saturating_add(1);
+        block.executions.set(executions);
+        // throttling and virtual time count every instruction
+        if executions < HOT_THRESHOLD
+            || self.clock.speed() != Speed::Unlimited
+            || self.time_source != TimeSource::Host
+        {
+            return None;
+        }
+        let words: Vec<u16> = block.ops.iter().map(|op| op.raw).collect();
+        let lea_sets_flags = self.conformance.lea_sets_flags;
+        *block
+            .compiled
+            .get_or_init(|| jit.compile(block.start, &words, lea_sets_flags))
+    }
+
+    #[cfg(feature = "jit")]
+    fn run_compiled(&mut self, compiled: CompiledBlock) -> Result<(), VMError> {
+        let mut state = JitState {
+            registers: self.registers,
+            pc: self.pc,
+            cond: self.psr.condition_codes(),
+            written: 0,
+            executed: 0,
+            reads: 0,
+            writes: 0,
+            memory: self.memory.as_mut_ptr(),
+            coverage: self.blocks.coverage_ptr(),
+        };
+        // SAFETY: the state points to the whole memory and block coverage,
+        // which is all compiled code accesses
+        let exit = unsafe { compiled(&mut state) };
+        self.registers = state.registers;
+        self.pc = state.pc;
+        self.psr.set_condition_codes(state.cond);
+        // the clock only runs compiled code at unlimited speed, where it
+        // has nothing to count
+        self.retire_compiled(state.executed, state.reads, state.writes);
+        match exit {
+            EXIT_DEOPT => {
+                let pc = self.pc;
+                let raw = self.memory.read(pc)?;
+                self.pc = self.next_pc(pc)?;
+                self.execute(pc, raw)
+            }
+            EXIT_CODE_WRITTEN => {
+                self.blocks.invalidate(state.written);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Whether the instruction may transfer control (branches, jumps, subroutine
+/// calls, traps and invalid opcodes)
+fn ends_block(raw: u16) -> bool {
+    matches!(raw >> 12, 0x0 | 0x4 | 0x8 | 0xC | 0xD | 0xF)
+}
@@ -0,0 +1,351 @@
+//! Configures a `VM` in one place for embedders that need more than the
+//! defaults of `VM::new()`.
+
+use alloc::{boxed::Box, collections::BTreeMap, format, string::String};
+
+use super::{Conformance, UnmappedAccess, PC_START, VM};
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+use crate::serial::SerialPort;
+use crate::{
+    clock::{Speed, TimeSource},
+    console::{Console, ConsoleConfig},
+    cycles::CycleModel,
+    errors::VMError,
+    loop_detector::LoopDetector,
+    memory::{Memory, MMIO_START},
+    stack::StackChecker,
+    vfs::FileSystem,
+};
+
+/// Which traps programs may use besides the ones installed with
+/// `VM::register_trap`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TrapMode {
+    /// Only the traps of the LC-3 specification
+    #[default]
+    Standard,
+    /// The standard traps plus PUTD and GETS
+    Extended,
+}
+
+/// What the IN and HALT traps print around their work
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrapMessages {
+    /// Printed by IN before reading the key
+    pub input: &'static str,
+    /// Printed by HALT
+    pub halt: &'static str,
+}
+
+impl TrapMessages {
+    pub const STANDARD: TrapMessages = TrapMessages {
+        input: "Enter a character: ",
+        halt: "HALT\n",
+    };
+
+    /// The messages of the operating system shipped with lc3sim
+    pub const LC3SIM: TrapMessages = TrapMessages {
+        input: "\nInput a character> ",
+        halt: "\n\n--- halting the LC-3 ---\n\n",
+    };
+
+    /// No messages, for tests comparing the program's own output
+    pub const SILENT: TrapMessages = TrapMessages {
+        input: "",
+        halt: "",
+    };
+}
+
+impl Default for TrapMessages {
+    fn default() -> Self {
+        Self::STANDARD
+    }
+}
+
+/// Builds a configured `VM`, starting from the defaults of `VM::new()`
+pub struct VMBuilder {
+    entry: Option<u16>,
+    memory_size: usize,
+    console: Option<Box<dyn Console>>,
+    console_config: ConsoleConfig,
+    #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+    serial: Option<SerialPort>,
+    display: bool,
+    dma: bool,
+    unmapped_access: UnmappedAccess,
+    #[cfg(feature = "persistent")]
+    persistent_memory: Option<std::path::PathBuf>,
+    env_vars: BTreeMap<String, String>,
+    files: Option<Box<dyn FileSystem>>,
+    trap_mode: TrapMode,
+    trap_messages: TrapMessages,
+    conformance: Conformance,
+    stack_checker: Option<StackChecker>,
+    loop_detector: Option<LoopDetector>,
+    speed: Option<Speed>,
+    time_source: TimeSource,
+    cycle_model: Option<CycleModel>,
+    instruction_limit: Option<u64>,
+    output_limit: Option<usize>,
+    #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+    timeout: Option<core::time::Duration>,
+}
+
+impl Default for VMBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VMBuilder {
+    pub fn new() -> Self {
+        VMBuilder {
+            entry: None,
+            memory_size: usize::from(MMIO_START),
+            console: None,
+            console_config: ConsoleConfig::default(),
+            #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+            serial: None,
+            display: false,
+            dma: false,
+            unmapped_access: UnmappedAccess::Memory,
+            #[cfg(feature = "persistent")]
+            persistent_memory: None,
+            env_vars: BTreeMap::new(),
+            files: None,
+            trap_mode: TrapMode::Standard,
+            trap_messages: TrapMessages::STANDARD,
+            conformance: Conformance::default(),
+            stack_checker: None,
+            loop_detector: None,
+            speed: None,
+            time_source: TimeSource::Host,
+            cycle_model: None,
+            instruction_limit: None,
+            output_limit: None,
+            #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+            timeout: None,
+        }
+    }
+
+    /// Address of the first instruction instead of the origin of the first
+    /// image, or x3000 before one is loaded
+    pub fn entry(mut self, pc: u16) -> Self {
+        self.entry = Some(pc);
+        self
+    }
+
+    /// Words of RAM from x0000, up to the whole xFE00 below the device
+    /// registers. Accessing memory past it fails, and the JIT is disabled
+    /// for good.
+    pub fn memory_size(mut self, words: usize) -> Self {
+        self.memory_size = words;
+        self
+    }
+
+    /// Keeps the RAM in the memory file at `path` between runs, see
+    /// `VM::persist_memory`
+    #[cfg(feature = "persistent")]
+    pub fn persistent_memory(mut self, path: impl Into<std::path::PathBuf>) -> Self {
+        self.persistent_memory = Some(path.into());
+        self
+    }
+
+    /// Console for the traps and keyboard, the terminal by default
+    pub fn console(mut self, console: Box<dyn Console>) -> Self {
+        self.console = Some(console);
+        self
+    }
+
+    /// How keys and characters are translated between the program and the
+    /// console
+    pub fn console_config(mut self, config: ConsoleConfig) -> Self {
+        self.console_config = config;
+        self
+    }
+
+    /// Attaches a serial port at `SRSR`-`STDR`
+    #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+    pub fn serial(mut self, port: SerialPort) -> Self {
+        self.serial = Some(port);
+        self
+    }
+
+    /// Shows the character grid at `DISPLAY_START` on the console, see
+    /// `display`
+    pub fn display(mut self) -> Self {
+        self.display = true;
+        self
+    }
+
+    /// Attaches the block-copy device at `DMASRC`-`DMASR`, see `dma`
+    pub fn dma(mut self) -> Self {
+        self.dma = true;
+        self
+    }
+
+    /// What accessing a device address no device answers does, see
+    /// `UnmappedAccess`
+    pub fn unmapped_access(mut self, policy: UnmappedAccess) -> Self {
+        self.unmapped_access = policy;
+        self
+    }
+
+    /// Lets the program read `value` as the variable `name` with GETENV
+    pub fn env_var(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env_vars.insert(name.into(), value.into());
+        self
+    }
+
+    /// Enables the file traps on `fs`
+    pub fn file_system(mut self, fs: Box<dyn FileSystem>) -> Self {
+        self.files = Some(fs);
+        self
+    }
+
+    pub fn trap_mode(mut self, mode: TrapMode) -> Self {
+        self.trap_mode = mode;
+        self
+    }
+
+    pub fn trap_messages(mut self, messages: TrapMessages) -> Self {
+        self.trap_messages = messages;
+        self
+    }
+
+    /// Follows the trap specification to the letter, see
+    /// `VM::set_strict_traps`
+    pub fn strict_traps(mut self, strict: bool) -> Self {
+        self.conformance.strict_traps = strict;
+        self
+    }
+
+    /// Resolves the behaviors the specification leaves open, from a
+    /// `Profile` or individual `Conformance` settings
+    pub fn conformance(mut self, conformance: impl Into<Conformance>) -> Self {
+        self.conformance = conformance.into();
+        self
+    }
+
+    /// Fails on stack discipline violations
+    pub fn stack_checker(mut self, checker: StackChecker) -> Self {
+        self.stack_checker = Some(checker);
+        self
+    }
+
+    /// Fails on programs that spin without making progress
+    pub fn loop_detector(mut self, detector: LoopDetector) -> Self {
+        self.loop_detector = Some(detector);
+        self
+    }
+
+    /// Limits how many instructions or cycles are executed per second
+    pub fn speed(mut self, speed: Speed) -> Self {
+        self.speed = Some(speed);
+        self
+    }
+
+    /// Where the time read by the TIME trap comes from
+    pub fn time_source(mut self, source: TimeSource) -> Self {
+        self.time_source = source;
+        self
+    }
+
+    /// Estimates the cycles of the program with `model`
+    pub fn cycle_model(mut self, model: CycleModel) -> Self {
+        self.cycle_model = Some(model);
+        self
+    }
+
+    /// Makes `run` fail after `limit` instructions
+    pub fn instruction_limit(mut self, limit: u64) -> Self {
+        self.instruction_limit = Some(limit);
+        self
+    }
+
+    /// Makes `run` fail once the program printed `limit` characters
+    pub fn output_limit(mut self, limit: usize) -> Self {
+        self.output_limit = Some(limit);
+        self
+    }
+
+    /// Makes `run` fail after `timeout` of host time, even while the
+    /// program waits for a key
+    #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+    pub fn timeout(mut self, timeout: core::time::Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn build(self) -> Result<VM, VMError> {
+        let memory = Memory::with_size(self.memory_size)?;
+        let entry = self.entry.unwrap_or(PC_START);
+        if usize::from(entry) >= memory.size() {
+            return Err(VMError::InvalidArgument(format!(
+                "Entry point {entry:#06x} is outside the {} words of memory",
+                memory.size()
+            )));
+        }
+        let mut vm = VM::new();
+        #[cfg(feature = "jit")]
+        if memory.size() < usize::from(MMIO_START) {
+            // compiled code accesses memory without bounds checks
+            vm.set_jit(false);
+        }
+        vm.memory = memory;
+        #[cfg(feature = "persistent")]
+        if let Some(path) = &self.persistent_memory {
+            vm.persist_memory(path)?;
+        }
+        if let Some(entry) = self.entry {
+            vm.set_entry(entry);
+        }
+        if let Some(console) = self.console {
+            vm.set_console(console);
+        }
+        vm.set_console_config(self.console_config);
+        #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+        if let Some(port) = self.serial {
+            vm.set_serial(port)?;
+        }
+        if self.display {
+            vm.enable_display()?;
+        }
+        if self.dma {
+            vm.enable_dma()?;
+        }
+        vm.set_unmapped_access(self.unmapped_access);
+        for (name, value) in self.env_vars {
+            vm.set_env_var(name, value);
+        }
+        if let Some(fs) = self.files {
+            vm.enable_file_traps(fs);
+        }
+        vm.set_extended_traps(self.trap_mode == TrapMode::Extended);
+        vm.set_trap_messages(self.trap_messages);
+        vm.set_conformance(self.conformance);
+        if let Some(checker) = self.stack_checker {
+            vm.set_stack_checker(checker);
+        }
+        if let Some(detector) = self.loop_detector {
+            vm.set_loop_detector(detector);
+        }
+        if let Some(speed) = self.speed {
+            vm.set_speed(speed);
+        }
+        vm.set_time_source(self.time_source);
+        vm.set_cycle_model(self.cycle_model);
+        vm.set_instruction_limit(self.instruction_limit)?;
+        vm.set_output_limit(self.output_limit)?;
+        #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+        vm.set_timeout(self.timeout)?;
+        Ok(vm)
+    }
+}
+
+impl VM {
+    /// Starts configuring a VM, see `VMBuilder`
+    pub fn builder() -> VMBuilder {
+        VMBuilder::new()
+    }
+}
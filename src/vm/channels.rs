@@ -0,0 +1,122 @@
+//! Extra console channels, each a memory mapped UART connected to its own
+//! `Console` of the host, so programs can keep diagnostics apart from what
+//! the user sees. A channel takes four registers from its base address, in
+//! the layout of the serial port:
+//!
+//! - base (receive status): bit 15 is set when a key waits in the next one
+//! - base + 2 (receive data): the key, reading it clears the status
+//! - base + 4 (transmit status): bit 15 is set, characters are sent at once
+//! - base + 6 (transmit data): writing prints the low byte
+//!
+//! For example a debug log at xFE30, with the user still at the keyboard
+//! and the console of the traps:
+//!
+//! ```text
+//!         LD  R0, CHAR
+//!         STI R0, DEBUG    ; prints to the channel, not the console
+//! DEBUG   .FILL xFE36
+//! ```
+
+use alloc::{boxed::Box, format, string::String};
+
+use super::VM;
+use crate::{console::Console, errors::VMError, memory::MMIO_START};
+
+/// Distance from the base of a channel to its transmit data register
+const TRANSMIT_DATA: u16 = 6;
+
+/// A channel and the console it is connected to
+pub(super) struct Channel {
+    name: String,
+    base: u16,
+    console: Box<dyn Console>,
+}
+
+impl VM {
+    /// Attaches a channel called `name` with its registers from `base`,
+    /// see `channels`, failing unless they are device registers free for
+    /// it. A channel with the same name is replaced.
+    pub fn add_channel(
+        &mut self,
+        name: &str,
+        base: u16,
+        console: Box<dyn Console>,
+    ) -> Result<(), VMError> {
+        let end = base
+            .checked_add(TRANSMIT_DATA)
+            .filter(|_| base >= MMIO_START)
+            .ok_or_else(|| {
+                VMError::InvalidArgument(format!(
+                    "Channel `{name}` at x{base:04X} is not in the device registers"
+                ))
+            })?;
+        self.memory.map_device_ranges(name, &[(base, end)])?;
+        self.remove_channel(name);
+        self.channels.push(Channel {
+            name: String::from(name),
+            base,
+            console,
+        });
+        Ok(())
+    }
+
+    /// Drops the channel called `name`, if there is one
+    pub(super) fn remove_channel(&mut self, name: &str) {
+        self.channels.retain(|channel| channel.name != name);
+    }
+
+    /// Index of the channel with a register at `address` and its offset
+    fn channel_at(&self, address: u16) -> Option<(usize, u16)> {
+        self.channels
+            .iter()
+            .enumerate()
+            .find_map(|(index, channel)| {
+                let offset = address.checked_sub(channel.base)?;
+                (offset <= TRANSMIT_DATA).then_some((index, offset))
+            })
+    }
+
+    /// Updates the status registers of the channel answering `address`
+    pub(super) fn poll_channel(&mut self, address: u16) -> Result<(), VMError> {
+        let Some((index, offset)) = self.channel_at(address) else {
+            return Ok(());
+        };
+        let status = address.wrapping_sub(offset);
+        match offset {
+            0 if self.memory.peek(status) & (1 << 15) == 0 => {
+                let key = match self.channels.get_mut(index) {
+                    Some(channel) => channel.console.poll_key()?,
+                    None => None,
+                };
+                if let Some(key) = key {
+                    self.memory.write(status.wrapping_add(2), u16::from(key))?;
+                    self.memory.write(status, 1 << 15)?;
+                }
+                Ok(())
+            }
+            2 => self.memory.write(status, 0),
+            4 => self.memory.write(address, 1 << 15),
+            _ => Ok(()),
+        }
+    }
+
+    /// Prints the low byte of `value` if `address` is the transmit data
+    /// register of a channel
+    pub(super) fn send_to_channel(&mut self, address: u16, value: u16) -> Result<(), VMError> {
+        let Some((index, TRANSMIT_DATA)) = self.channel_at(address) else {
+            return Ok(());
+        };
+        let [low, _] = value.to_le_bytes();
+        match self.channels.get_mut(index) {
+            Some(channel) => channel.console.write_char(char::from(low)),
+            None => Ok(()),
+        }
+    }
+
+    /// Makes everything printed to the channels visible
+    pub(super) fn flush_channels(&mut self) -> Result<(), VMError> {
+        self.channels
+            .iter_mut()
+            .try_for_each(|channel| channel.console.flush())
+    }
+}
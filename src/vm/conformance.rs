@@ -0,0 +1,168 @@
+//! Behaviors on which the LC-3 specification, its editions and lc3sim
+//! disagree, bundled into named profiles.
+
+use core::{fmt, str::FromStr};
+
+use alloc::format;
+
+use super::{Psr, VM};
+use crate::{errors::VMError, instructions::AddressArithmetic, register::Register};
+
+/// What RTI does, since programs run without an operating system in
+/// supervisor mode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RtiMode {
+    /// Fails like the reserved opcode, as a privilege mode violation would
+    #[default]
+    Fail,
+    /// Pops the PC and then the PSR from the stack at R6, as in supervisor
+    /// mode
+    PopStack,
+}
+
+/// Individual behaviors a profile decides
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Conformance {
+    /// Whether effective addresses and the PC wrap around past xFFFF and
+    /// below x0000 instead of failing
+    pub addresses: AddressArithmetic,
+    pub rti: RtiMode,
+    /// See `VM::set_strict_traps`
+    pub strict_traps: bool,
+    /// Whether LEA sets the condition codes, as in the second edition of
+    /// the ISA
+    pub lea_sets_flags: bool,
+    /// Whether the reserved opcode executes as a no-op instead of failing
+    pub ignore_reserved_opcode: bool,
+}
+
+/// The defaults catch likely mistakes, failing where the hardware would
+/// silently do something surprising
+impl Default for Conformance {
+    fn default() -> Self {
+        Conformance {
+            addresses: AddressArithmetic::Checked,
+            rti: RtiMode::Fail,
+            strict_traps: false,
+            lea_sets_flags: true,
+            ignore_reserved_opcode: false,
+        }
+    }
+}
+
+/// Named bundles of `Conformance` settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    /// The third edition of the ISA to the letter: addresses wrap, LEA
+    /// leaves the condition codes alone and RTI in user mode is a
+    /// privilege mode violation
+    StrictSpec,
+    /// Matches lc3sim, which follows the second edition and runs programs
+    /// in supervisor mode
+    Lc3sim,
+    /// Keeps running whatever the program does
+    Permissive,
+}
+
+impl Profile {
+    pub fn conformance(self) -> Conformance {
+        match self {
+            Profile::StrictSpec => Conformance {
+                addresses: AddressArithmetic::Wrapping,
+                rti: RtiMode::Fail,
+                strict_traps: true,
+                lea_sets_flags: false,
+                ignore_reserved_opcode: false,
+            },
+            Profile::Lc3sim => Conformance {
+                addresses: AddressArithmetic::Wrapping,
+                rti: RtiMode::PopStack,
+                strict_traps: true,
+                lea_sets_flags: true,
+                ignore_reserved_opcode: false,
+            },
+            Profile::Permissive => Conformance {
+                addresses: AddressArithmetic::Wrapping,
+                rti: RtiMode::PopStack,
+                strict_traps: true,
+                lea_sets_flags: true,
+                ignore_reserved_opcode: true,
+            },
+        }
+    }
+}
+
+impl From<Profile> for Conformance {
+    fn from(profile: Profile) -> Self {
+        profile.conformance()
+    }
+}
+
+impl FromStr for Profile {
+    type Err = VMError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "strict-spec" => Ok(Profile::StrictSpec),
+            "lc3sim" | "lc3sim-compatible" => Ok(Profile::Lc3sim),
+            "permissive" => Ok(Profile::Permissive),
+            _ => Err(VMError::InvalidArgument(format!(
+                "Unknown profile {name}, expected strict-spec, lc3sim or permissive"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Profile::StrictSpec => "strict-spec",
+            Profile::Lc3sim => "lc3sim",
+            Profile::Permissive => "permissive",
+        })
+    }
+}
+
+impl VM {
+    pub fn conformance(&self) -> Conformance {
+        self.conformance
+    }
+
+    /// Changes how the VM resolves the behaviors the specification leaves
+    /// open, see `Profile`
+    pub fn set_conformance(&mut self, conformance: impl Into<Conformance>) {
+        self.conformance = conformance.into();
+        // decoded blocks and native code bake in the LEA flag updates
+        self.clear_decoded();
+    }
+
+    /// Computes `base + offset`, wrapping or failing past the ends of the
+    /// address space
+    #[inline]
+    pub(super) fn effective_address(&self, base: u16, offset: i16) -> Result<u16, VMError> {
+        self.conformance.addresses.offset(base, offset)
+    }
+
+    /// The PC after fetching the instruction at `pc`
+    #[inline]
+    pub(super) fn next_pc(&self, pc: u16) -> Result<u16, VMError> {
+        self.conformance.addresses.next(pc)
+    }
+
+    pub(super) fn op_rti(&mut self, _raw: u16) -> Result<(), VMError> {
+        if self.conformance.rti == RtiMode::Fail && !self.in_handler() {
+            let pc = self.pc.wrapping_sub(1);
+            return Err(VMError::InvalidOpcode(format!(
+                "RTI at {pc:#06x} is a privilege mode violation in user mode"
+            )));
+        }
+        let sp = self.read_register(Register::R6);
+        let pc = self.read_memory(sp)?;
+        let psr = self.read_memory(sp.wrapping_add(1))?;
+        self.write_register(Register::R6, sp.wrapping_add(2));
+        self.pc = pc;
+        self.psr = Psr::from_bits(psr);
+        self.leave_handler();
+        Ok(())
+    }
+}
@@ -0,0 +1,387 @@
+//! Single stepping, breakpoints and access to the machine state for
+//! debuggers and other tools. Stepping always goes through `execute`, so
+//! the checkers keep working while debugging.
+
+use alloc::{collections::BTreeMap, format, vec::Vec};
+
+use super::{MemoryChange, Psr, StateDiff, REGISTER_COUNT, VM};
+use crate::{
+    errors::VMError,
+    instructions::Opcode,
+    memory::{Memory, MMIO_START},
+    mmio::MmioMap,
+    register::Register,
+};
+
+/// Why a debugger-driven run stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// A single instruction was executed
+    Step,
+    /// The PC reached a breakpoint, which has not been executed yet
+    Breakpoint(u16),
+    /// The instruction at the PC is a TRAP with this vector that the
+    /// debugger breaks on, see `VM::add_trap_break`. It has not been
+    /// executed yet.
+    Trap(u16),
+    /// The interrupt with this vector is delivered before the instruction
+    /// at the PC, or the exception with this vector the last instruction
+    /// raised is entered, such as `BUS_ERROR`, see
+    /// `VM::set_break_on_interrupt`. Neither happened yet.
+    Interrupt(u16),
+    /// The instruction just executed read or wrote the device register at
+    /// `address`, see `VM::add_device_break`. The PC is past it.
+    DeviceAccess { address: u16, write: bool },
+    /// The program executed HALT
+    Halted,
+    /// The break key of the key map was pressed while the program read the
+    /// keyboard. A trap waiting for the key runs again on resuming.
+    Break,
+}
+
+/// Accesses to a device register that stop debugger runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceAccess {
+    Read,
+    Write,
+    /// Reads and writes
+    Any,
+}
+
+impl DeviceAccess {
+    pub fn matches(self, write: bool) -> bool {
+        match self {
+            DeviceAccess::Read => !write,
+            DeviceAccess::Write => write,
+            DeviceAccess::Any => true,
+        }
+    }
+}
+
+impl VM {
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    /// Where the program starts after a reset, the origin of the first
+    /// image loaded unless set
+    pub fn entry(&self) -> u16 {
+        self.entry
+    }
+
+    /// Starts the program at `pc`, now and after every reset, whatever the
+    /// origin of the images
+    pub fn set_entry(&mut self, pc: u16) {
+        self.entry = pc;
+        self.entry_fixed = true;
+        self.pc = pc;
+    }
+
+    pub fn register(&self, register: Register) -> u16 {
+        self.read_register(register)
+    }
+
+    pub fn set_register(&mut self, register: Register, value: u16) {
+        self.write_register(register, value);
+    }
+
+    /// R0-R7 in order
+    pub fn registers(&self) -> [u16; REGISTER_COUNT] {
+        self.registers
+    }
+
+    /// The processor status: privilege mode, priority and condition codes
+    pub fn psr(&self) -> Psr {
+        self.psr
+    }
+
+    pub fn set_psr(&mut self, psr: Psr) {
+        self.psr = psr;
+    }
+
+    /// Reads memory without polling the keyboard or other side effects
+    pub fn peek(&self, address: u16) -> u16 {
+        self.memory.peek(address)
+    }
+
+    /// Writes memory, dropping any code decoded from `address`
+    pub fn poke(&mut self, address: u16, value: u16) -> Result<(), VMError> {
+        self.host_write(address, value)
+    }
+
+    /// Read-only access to the memory
+    pub fn memory(&self) -> MemoryView<'_> {
+        MemoryView {
+            memory: &self.memory,
+        }
+    }
+
+    /// Access to the memory that keeps decoded code in sync with writes
+    pub fn memory_mut(&mut self) -> MemoryMut<'_> {
+        MemoryMut { vm: self }
+    }
+
+    /// Whether the program executed HALT
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Adds a breakpoint, returning false if it was already set
+    pub fn add_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.insert(address)
+    }
+
+    /// Removes a breakpoint, returning false if it was not set
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn is_breakpoint(&self, address: u16) -> bool {
+        self.breakpoints.contains(&address)
+    }
+
+    /// Stops debugger runs before TRAP instructions with `vector`, or any
+    /// TRAP for `None`, returning false if it already did
+    pub fn add_trap_break(&mut self, vector: Option<u16>) -> bool {
+        self.trap_breaks.insert(vector.map(|vector| vector & 0xFF))
+    }
+
+    /// Removes a trap break, returning false if it was not set
+    pub fn remove_trap_break(&mut self, vector: Option<u16>) -> bool {
+        self.trap_breaks.remove(&vector.map(|vector| vector & 0xFF))
+    }
+
+    /// The trap vectors debugger runs stop at, `None` standing for any trap
+    pub fn trap_breaks(&self) -> impl Iterator<Item = Option<u16>> + '_ {
+        self.trap_breaks.iter().copied()
+    }
+
+    /// Stops debugger runs before entering an exception raised by the last
+    /// instruction and before delivering an interrupt
+    pub fn set_break_on_interrupt(&mut self, enabled: bool) {
+        self.break_on_interrupt = enabled;
+    }
+
+    pub fn breaks_on_interrupt(&self) -> bool {
+        self.break_on_interrupt
+    }
+
+    /// Stops debugger runs after instructions reading or writing the device
+    /// register at `address`, such as KBSR in a polling loop, replacing the
+    /// accesses it stopped for. Fails below the device registers.
+    pub fn add_device_break(&mut self, address: u16, access: DeviceAccess) -> Result<(), VMError> {
+        if address < MMIO_START {
+            return Err(VMError::Debugger(format!(
+                "x{address:04X} is not a device register, they start at x{MMIO_START:04X}"
+            )));
+        }
+        self.device_breaks.insert(address, access);
+        Ok(())
+    }
+
+    /// Removes a device break, returning false if it was not set
+    pub fn remove_device_break(&mut self, address: u16) -> bool {
+        self.device_breaks.remove(&address).is_some()
+    }
+
+    pub fn device_breaks(&self) -> impl Iterator<Item = (u16, DeviceAccess)> + '_ {
+        self.device_breaks
+            .iter()
+            .map(|(&address, &access)| (address, access))
+    }
+
+    /// Records an access by the program to a device register if it stops
+    /// debugger runs. Only the first access of an instruction is kept.
+    pub(super) fn hit_device(&mut self, address: u16, write: bool) {
+        let breaks = self
+            .device_breaks
+            .get(&address)
+            .is_some_and(|access| access.matches(write));
+        if breaks && self.device_hit.is_none() {
+            self.device_hit = Some((address, write));
+        }
+    }
+
+    /// Why a debugger should stop before the instruction at the PC, if it
+    /// should: a breakpoint, a trap it breaks on or an interrupt or
+    /// exception about to be entered. `resume` stops for all three, front ends
+    /// stepping on their own check it after every step.
+    pub fn pending_stop(&self) -> Option<StopReason> {
+        let pc = self.pc;
+        if self.is_breakpoint(pc) {
+            return Some(StopReason::Breakpoint(pc));
+        }
+        let raw = self.peek(pc);
+        if Opcode::from_instruction(raw) == Opcode::Trap {
+            let vector = raw & 0xFF;
+            if self.trap_breaks.contains(&None) || self.trap_breaks.contains(&Some(vector)) {
+                return Some(StopReason::Trap(vector));
+            }
+        }
+        if self.break_on_interrupt {
+            return self
+                .pending_fault()
+                .or_else(|| self.deliverable_interrupt())
+                .map(|vector| StopReason::Interrupt(u16::from(vector)));
+        }
+        None
+    }
+
+    /// Whether the instruction at the PC is a trap blocking on a key
+    /// (GETC, IN or the extended GETS), so front ends can wait for input
+    /// before stepping
+    pub fn waits_for_key(&self) -> bool {
+        match self.peek(self.pc) {
+            0xF020 | 0xF023 => true,
+            0xF027 => self.extended_traps,
+            _ => false,
+        }
+    }
+
+    /// Executes the instruction at the PC, recording it for `undo` when
+    /// enabled
+    pub fn step(&mut self) -> Result<StopReason, VMError> {
+        let Some(before) = self.begin_undo_step() else {
+            return self.step_instruction();
+        };
+        let stop = self.step_instruction();
+        self.end_undo_step(before, stop.is_ok());
+        stop
+    }
+
+    fn step_instruction(&mut self) -> Result<StopReason, VMError> {
+        if self.halted {
+            return Ok(StopReason::Halted);
+        }
+        self.running = true;
+        self.device_hit = None;
+        self.take_interrupt()?;
+        let pc = self.pc;
+        let raw = self.memory.read(pc)?;
+        self.pc = self.next_pc(pc)?;
+        self.execute(pc, raw)?;
+        self.retire();
+        if Opcode::from_instruction(raw) == Opcode::Trap {
+            self.console.flush()?;
+        }
+        self.flush_channels()?;
+        if core::mem::take(&mut self.break_requested) {
+            return Ok(StopReason::Break);
+        }
+        Ok(match self.device_hit.take() {
+            _ if self.halted => StopReason::Halted,
+            Some((address, write)) => StopReason::DeviceAccess { address, write },
+            None => StopReason::Step,
+        })
+    }
+
+    /// Executes the instruction at the PC like `step` and reports what it
+    /// changed, its trap included, so front ends can highlight it. Words
+    /// written several times show their first and last values, and words
+    /// written with the value they held are left out.
+    pub fn step_diff(&mut self) -> Result<(StopReason, StateDiff), VMError> {
+        let before = self.state();
+        self.step_writes = Some(Vec::new());
+        let stop = self.step();
+        let writes = self.step_writes.take().unwrap_or_default();
+        let stop = stop?;
+        let mut diff = before.diff(&self.state());
+        let mut memory: BTreeMap<u16, MemoryChange> = BTreeMap::new();
+        for write in writes {
+            memory
+                .entry(write.address)
+                .and_modify(|change| change.after = write.after)
+                .or_insert(write);
+        }
+        diff.memory = memory
+            .into_values()
+            .filter(|change| change.before != change.after)
+            .collect();
+        Ok((stop, diff))
+    }
+
+    /// Runs until the PC reaches a breakpoint, a trap or exception the
+    /// debugger breaks on, see `pending_stop`, or the program halts. The
+    /// instruction at the PC is always executed, so resuming from a
+    /// breakpoint moves past it.
+    pub fn resume(&mut self) -> Result<StopReason, VMError> {
+        loop {
+            match self.step()? {
+                StopReason::Step => {}
+                stop => return Ok(stop),
+            }
+            if let Some(stop) = self.pending_stop() {
+                return Ok(stop);
+            }
+            self.tick_clock();
+        }
+    }
+}
+
+/// Reads memory without side effects, see `VM::memory`
+#[derive(Clone, Copy)]
+pub struct MemoryView<'a> {
+    memory: &'a Memory,
+}
+
+impl<'a> MemoryView<'a> {
+    /// Reads a word without polling the keyboard or other devices
+    pub fn read(&self, address: u16) -> u16 {
+        self.memory.peek(address)
+    }
+
+    /// Reads `count` words from `start`, wrapping around after xFFFF
+    pub fn read_words(&self, start: u16, count: usize) -> impl Iterator<Item = u16> + 'a {
+        let memory = self.memory;
+        (0..count).scan(start, move |address, _| {
+            let word = memory.peek(*address);
+            *address = address.wrapping_add(1);
+            Some(word)
+        })
+    }
+
+    /// Number of words of RAM below the device registers
+    pub fn size(&self) -> usize {
+        self.memory.size()
+    }
+
+    /// Where the attached devices are mapped
+    pub fn mmio_map(&self) -> &'a MmioMap {
+        self.memory.mmio_map()
+    }
+}
+
+/// Reads and writes memory, see `VM::memory_mut`
+pub struct MemoryMut<'a> {
+    vm: &'a mut VM,
+}
+
+impl MemoryMut<'_> {
+    /// Reads a word without polling the keyboard or other devices
+    pub fn read(&self, address: u16) -> u16 {
+        self.vm.memory.peek(address)
+    }
+
+    /// Writes a word, dropping any code decoded from `address`
+    pub fn write(&mut self, address: u16, value: u16) -> Result<(), VMError> {
+        self.vm.host_write(address, value)
+    }
+
+    /// Writes `words` from `start` on, wrapping around after xFFFF
+    pub fn write_words(&mut self, start: u16, words: &[u16]) -> Result<(), VMError> {
+        let mut address = start;
+        for word in words {
+            self.vm.host_write(address, *word)?;
+            address = address.wrapping_add(1);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+//! Pre-decoded instructions shared by the threaded-code backend and the
+//! basic-block cache: a handler pointer plus the operands already extracted
+//! from the instruction word.
+
+use super::VM;
+use crate::{errors::VMError, instructions::sign_extend, register::Register};
+
+type OpHandler = fn(&mut VM, DecodedOp) -> Result<(), VMError>;
+
+#[derive(Clone, Copy)]
+pub(super) struct DecodedOp {
+    handler: OpHandler,
+    pub(super) raw: u16,
+    dr: Register,
+    sr: Register,
+    /// Sign extended offset or immediate
+    operand: i16,
+}
+
+impl DecodedOp {
+    #[inline]
+    pub(super) fn execute(self, vm: &mut VM) -> Result<(), VMError> {
+        (self.handler)(vm, self)
+    }
+}
+
+pub(super) fn decode(raw: u16) -> DecodedOp {
+    let immediate = (raw >> 5) & 1 == 1;
+    let (handler, operand): (OpHandler, i16) = match raw >> 12 {
+        0x0 => (br, sign_extend(raw, 9)),
+        0x1 if immediate => (add_immediate, sign_extend(raw, 5)),
+        0x1 => (add_register, 0),
+        0x2 => (ld, sign_extend(raw, 9)),
+        0x3 => (st, sign_extend(raw, 9)),
+        0x4 if (raw >> 11) & 1 == 1 => (jsr, sign_extend(raw, 11)),
+        0x4 => (jsrr, 0),
+        0x5 if immediate => (and_immediate, sign_extend(raw, 5)),
+        0x5 => (and_register, 0),
+        0x6 => (ldr, sign_extend(raw, 6)),
+        0x7 => (str, sign_extend(raw, 6)),
+        0x9 => (not, 0),
+        0xA => (ldi, sign_extend(raw, 9)),
+        0xB => (sti, sign_extend(raw, 9)),
+        0xC => (jmp, 0),
+        0x8 => (rti, 0),
+        0xE => (lea, sign_extend(raw, 9)),
+        0xF => (trap, 0),
+        _ => (reserved, 0),
+    };
+    DecodedOp {
+        handler,
+        raw,
+        dr: Register::from_bits(raw >> 9),
+        sr: Register::from_bits(raw >> 6),
+        operand,
+    }
+}
+
+fn br(vm: &mut VM, op: DecodedOp) -> Result<(), VMError> {
+    if vm.psr.branches(op.raw) {
+        vm.pc = vm.effective_address(vm.pc, op.operand)?;
+    }
+    Ok(())
+}
+
+fn add_immediate(vm: &mut VM, op: DecodedOp) -> Result<(), VMError> {
+    let value = vm.read_register(op.sr).wrapping_add_signed(op.operand);
+    vm.write_register_with_flags(op.dr, value);
+    Ok(())
+}
+
+fn add_register(vm: &mut VM, op: DecodedOp) -> Result<(), VMError> {
+    let value = vm
+        .read_register(op.sr)
+        .wrapping_add(vm.read_register(Register::from_bits(op.raw)));
+    vm.write_register_with_flags(op.dr, value);
+    Ok(())
+}
+
+fn ld(vm: &mut VM, op: DecodedOp) -> Result<(), VMError> {
+    let value = vm.read_memory(vm.effective_address(vm.pc, op.operand)?)?;
+    vm.write_register_with_flags(op.dr, value);
+    Ok(())
+}
+
+fn st(vm: &mut VM, op: DecodedOp) -> Result<(), VMError> {
+    let address = vm.effective_address(vm.pc, op.operand)?;
+    vm.write_memory(address, vm.read_register(op.dr))
+}
+
+fn jsr(vm: &mut VM, op: DecodedOp) -> Result<(), VMError> {
+    let return_address = vm.pc;
+    vm.pc = vm.effective_address(vm.pc, op.operand)?;
+    vm.write_register(Register::R7, return_address);
+    Ok(())
+}
+
+fn jsrr(vm: &mut VM, op: DecodedOp) -> Result<(), VMError> {
+    let return_address = vm.pc;
+    vm.pc = vm.read_register(op.sr);
+    vm.write_register(Register::R7, return_address);
+    Ok(())
+}
+
+fn and_immediate(vm: &mut VM, op: DecodedOp) -> Result<(), VMError> {
+    let value = vm.read_register(op.sr) & u16::from_ne_bytes(op.operand.to_ne_bytes());
+    vm.write_register_with_flags(op.dr, value);
+    Ok(())
+}
+
+fn and_register(vm: &mut VM, op: DecodedOp) -> Result<(), VMError> {
+    let value = vm.read_register(op.sr) & vm.read_register(Register::from_bits(op.raw));
+    vm.write_register_with_flags(op.dr, value);
+    Ok(())
+}
+
+fn ldr(vm: &mut VM, op: DecodedOp) -> Result<(), VMError> {
+    let address = vm.effective_address(vm.read_register(op.sr), op.operand)?;
+    let value = vm.read_memory(address)?;
+    vm.write_register_with_flags(op.dr, value);
+    Ok(())
+}
+
+fn str(vm: &mut VM, op: DecodedOp) -> Result<(), VMError> {
+    let address = vm.effective_address(vm.read_register(op.sr), op.operand)?;
+    vm.write_memory(address, vm.read_register(op.dr))
+}
+
+fn not(vm: &mut VM, op: DecodedOp) -> Result<(), VMError> {
+    let value = !vm.read_register(op.sr);
+    vm.write_register_with_flags(op.dr, value);
+    Ok(())
+}
+
+fn ldi(vm: &mut VM, op: DecodedOp) -> Result<(), VMError> {
+    let pointer = vm.read_memory(vm.effective_address(vm.pc, op.operand)?)?;
+    let value = vm.read_memory(pointer)?;
+    vm.write_register_with_flags(op.dr, value);
+    Ok(())
+}
+
+fn sti(vm: &mut VM, op: DecodedOp) -> Result<(), VMError> {
+    let pointer = vm.read_memory(vm.effective_address(vm.pc, op.operand)?)?;
+    vm.write_memory(pointer, vm.read_register(op.dr))
+}
+
+fn jmp(vm: &mut VM, op: DecodedOp) -> Result<(), VMError> {
+    vm.pc = vm.read_register(op.sr);
+    Ok(())
+}
+
+fn lea(vm: &mut VM, op: DecodedOp) -> Result<(), VMError> {
+    let address = vm.effective_address(vm.pc, op.operand)?;
+    if vm.conformance.lea_sets_flags {
+        vm.write_register_with_flags(op.dr, address);
+    } else {
+        vm.write_register(op.dr, address);
+    }
+    Ok(())
+}
+
+fn trap(vm: &mut VM, op: DecodedOp) -> Result<(), VMError> {
+    vm.op_trap(op.raw)
+}
+
+fn rti(vm: &mut VM, op: DecodedOp) -> Result<(), VMError> {
+    vm.op_rti(op.raw)
+}
+
+fn reserved(vm: &mut VM, op: DecodedOp) -> Result<(), VMError> {
+    vm.op_reserved(op.raw)
+}
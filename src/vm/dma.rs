@@ -0,0 +1,98 @@
+//! Block-copy device, a small DMA controller copying memory in one operation
+//! instead of a loop of loads and stores:
+//!
+//! - `DMASRC` (xFE12): address of the first word copied
+//! - `DMADST` (xFE14): address the first word is copied to
+//! - `DMALEN` (xFE16): number of words copied
+//! - `DMACR` (xFE18): writing any value copies the block
+//! - `DMASR` (xFE1A): bit 15 is set when the last copy finished, which is
+//!   always since copies take no time, and bit 14 when it was refused
+//!   because a block leaves the RAM or wraps past xFFFF
+//!
+//! Overlapping blocks are copied as if through a temporary buffer, like
+//! `memmove`. Copies show up as writes to the observer and debugger but not
+//! as instructions.
+
+use super::VM;
+use crate::{errors::VMError, memory::MMIO_START};
+
+/// Source address memory mapped register
+pub const DMASRC: u16 = 0xFE12;
+/// Destination address memory mapped register
+pub const DMADST: u16 = 0xFE14;
+/// Length memory mapped register
+pub const DMALEN: u16 = 0xFE16;
+/// Control memory mapped register, writing it starts the copy
+pub const DMACR: u16 = 0xFE18;
+/// Status memory mapped register
+pub const DMASR: u16 = 0xFE1A;
+
+/// Set in `DMASR` once the last copy finished
+pub const DMA_DONE: u16 = 1 << 15;
+/// Set in `DMASR` when the last copy was refused
+pub const DMA_ERROR: u16 = 1 << 14;
+
+impl VM {
+    /// Attaches the block-copy device at `DMASRC`-`DMASR`, see `dma`,
+    /// failing if another device is mapped there
+    pub fn enable_dma(&mut self) -> Result<(), VMError> {
+        self.memory.map_device_ranges("dma", &[(DMASRC, DMASR)])?;
+        self.dma = Some(DMA_DONE);
+        Ok(())
+    }
+
+    /// Shows the status of the last copy in `DMASR`
+    pub(super) fn poll_dma(&mut self) -> Result<(), VMError> {
+        match self.dma {
+            Some(status) => self.memory.write(DMASR, status),
+            None => Ok(()),
+        }
+    }
+
+    /// Copies the block described by the device registers
+    pub(super) fn start_dma(&mut self) -> Result<(), VMError> {
+        let source = self.memory.peek(DMASRC);
+        let destination = self.memory.peek(DMADST);
+        let length = self.memory.peek(DMALEN);
+        if !self.in_ram(source, length) || !self.in_ram(destination, length) {
+            event!(
+                DEBUG,
+                "lc3_vm::devices",
+                source,
+                destination,
+                length,
+                "DMA copy refused"
+            );
+            self.dma = Some(DMA_DONE | DMA_ERROR);
+            return Ok(());
+        }
+        event!(
+            DEBUG,
+            "lc3_vm::devices",
+            source,
+            destination,
+            length,
+            "DMA copy"
+        );
+        // copy from the end when the destination overlaps the end of the
+        // source, so no word is overwritten before it is read
+        let backwards = destination > source;
+        for step in 0..length {
+            let offset = if backwards {
+                length.wrapping_sub(1).wrapping_sub(step)
+            } else {
+                step
+            };
+            let value = self.memory.peek(source.wrapping_add(offset));
+            self.write_memory(destination.wrapping_add(offset), value)?;
+        }
+        self.dma = Some(DMA_DONE);
+        Ok(())
+    }
+
+    /// Whether the `length` words at `start` are all RAM
+    fn in_ram(&self, start: u16, length: u16) -> bool {
+        let end = usize::from(start).saturating_add(usize::from(length));
+        end <= self.memory.size().min(usize::from(MMIO_START))
+    }
+}
@@ -0,0 +1,67 @@
+//! Bytes printed by the program turned into characters by the encoding of
+//! the console configuration.
+
+use alloc::format;
+
+use super::VM;
+use crate::{console::Encoding, errors::VMError};
+
+impl VM {
+    /// Prints the character in the low byte of `word`, whose high byte must
+    /// be zero
+    pub(super) fn print_word(&mut self, word: u16) -> Result<(), VMError> {
+        let byte = u8::try_from(word).map_err(|_| {
+            VMError::InvalidCharacter(format!("{word:#06x} is not a valid character"))
+        })?;
+        self.print_byte(byte)
+    }
+
+    pub(super) fn print_byte(&mut self, byte: u8) -> Result<(), VMError> {
+        if byte.is_ascii() && self.utf8.is_empty() {
+            return self.output(char::from(byte));
+        }
+        match self.console_config.encoding {
+            Encoding::Ascii => Err(VMError::InvalidCharacter(format!(
+                "{byte:#04x} is not ASCII, choose an encoding to print it"
+            ))),
+            Encoding::Latin1 => self.output(char::from(byte)),
+            Encoding::CodePage(table) => {
+                let index = usize::from(byte.wrapping_sub(0x80));
+                self.output(
+                    table
+                        .get(index)
+                        .copied()
+                        .unwrap_or(char::REPLACEMENT_CHARACTER),
+                )
+            }
+            Encoding::Utf8 => self.print_utf8(byte),
+        }
+    }
+
+    /// Adds `byte` to the UTF-8 sequence being printed, printing it once
+    /// complete
+    fn print_utf8(&mut self, byte: u8) -> Result<(), VMError> {
+        self.utf8.push(byte);
+        let character = match core::str::from_utf8(&self.utf8) {
+            Ok(text) => text.chars().next(),
+            // the start of a sequence, waiting for the rest
+            Err(error) if error.error_len().is_none() => return Ok(()),
+            Err(_) => {
+                let retried = self.utf8.len() > 1;
+                self.utf8.clear();
+                self.output(char::REPLACEMENT_CHARACTER)?;
+                // the byte may start the next sequence
+                return if retried {
+                    self.print_byte(byte)
+                } else {
+                    Ok(())
+                };
+            }
+        };
+        self.utf8.clear();
+        match character {
+            Some(character) => self.output(character),
+            None => Ok(()),
+        }
+    }
+}
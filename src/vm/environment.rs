@@ -0,0 +1,193 @@
+//! What programs learn about how they were started, e.g. to parameterize
+//! grading runs without editing images:
+//!
+//! - The GETENV trap reads the environment variables the embedder exposes.
+//!   Only the variables given to `VM::set_env_var` or `VM::expose_env_var`
+//!   are visible, and x84 stays an invalid trap until the first one is.
+//! - Command line arguments given to `VM::set_args` are written at
+//!   `ARGS_START` like the `argc` and `argv` of C, and GETARG finds them.
+//!   x85 stays an invalid trap without arguments.
+//!
+//! The argument area, at most the 256 words xFD00-xFDFF:
+//!
+//! - xFD00: the number of arguments, `argc`, the first being the image
+//! - xFD01: the address of the string of each argument, `argv`, followed by
+//!   a zero word
+//! - the strings, a byte per word, each followed by a zero word
+
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use super::VM;
+use crate::{errors::VMError, register::Register};
+
+/// Copies the value of the variable named by the string at R0 into the
+/// words at R1, a byte per word followed by a zero word, writing at most R2
+/// words. R0 receives the length of the value, or -1 if the variable is not
+/// exposed or the value and its terminator do not fit.
+pub const GETENV: u16 = 0x84;
+
+/// Puts the address of the string of argument R0 in R0, or -1 if there
+/// are not that many arguments. Argument 0 is the image.
+pub const GETARG: u16 = 0x85;
+
+/// First word of the argument area, holding the number of arguments
+pub const ARGS_START: u16 = 0xFD00;
+/// First word past the argument area
+const ARGS_END: u16 = 0xFE00;
+
+/// Longest variable name read
+const MAX_NAME_LENGTH: usize = 64;
+/// Returned in R0 when GETENV or GETARG fails
+const FAILURE: u16 = 0xFFFF;
+
+impl VM {
+    /// Lets the program read `value` as the variable `name` with GETENV
+    pub fn set_env_var(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.env_vars.insert(name.into(), value.into());
+    }
+
+    /// Lets the program read the host variable `name` with GETENV, returning
+    /// false if the host does not set it
+    #[cfg(feature = "std")]
+    pub fn expose_env_var(&mut self, name: &str) -> bool {
+        match std::env::var(name) {
+            Ok(value) => {
+                self.set_env_var(name, value);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// The variables the program can read
+    pub fn env_vars(&self) -> &BTreeMap<String, String> {
+        &self.env_vars
+    }
+
+    /// Writes `args` in the argument area for the program, replacing what
+    /// the area held, and enables GETARG. The first argument is the image by
+    /// convention.
+    pub fn set_args<S: ToString>(
+        &mut self,
+        args: impl IntoIterator<Item = S>,
+    ) -> Result<(), VMError> {
+        let args: Vec<String> = args.into_iter().map(|arg| arg.to_string()).collect();
+        let words = args_layout(&args)?;
+        if self.memory.size() < usize::from(ARGS_END) {
+            return Err(VMError::InvalidArgument(format!(
+                "Arguments need the memory up to {ARGS_END:#06x}, but it has {} words",
+                self.memory.size()
+            )));
+        }
+        self.args = args;
+        self.write_args(&words)
+    }
+
+    /// The arguments of the program, the first being the image
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Writes the argument area again after the memory was cleared
+    pub(super) fn restore_args(&mut self) {
+        if self.args.is_empty() {
+            return;
+        }
+        // `set_args` checked that they fit
+        if let Ok(words) = args_layout(&self.args) {
+            let _ = self.write_args(&words);
+        }
+    }
+
+    fn write_args(&mut self, words: &[u16]) -> Result<(), VMError> {
+        let mut address = ARGS_START;
+        for &word in words {
+            self.memory.write(address, word)?;
+            address = address.wrapping_add(1);
+        }
+        self.clear_decoded();
+        Ok(())
+    }
+
+    /// Runs GETENV or GETARG, or returns `None` if `vector` is neither or
+    /// nothing is exposed through it
+    pub(super) fn env_trap(&mut self, vector: u16) -> Option<Result<(), VMError>> {
+        let result = match vector {
+            GETENV if !self.env_vars.is_empty() => self.getenv(),
+            GETARG if !self.args.is_empty() => Ok(self.getarg()),
+            _ => return None,
+        };
+        Some(result.map(|value| {
+            self.write_register_with_flags(Register::R0, value.unwrap_or(FAILURE));
+        }))
+    }
+
+    fn getarg(&mut self) -> Option<u16> {
+        let index = self.read_register(Register::R0);
+        if usize::from(index) >= self.args.len() {
+            return None;
+        }
+        Some(
+            self.memory
+                .peek(ARGS_START.wrapping_add(1).wrapping_add(index)),
+        )
+    }
+
+    fn getenv(&mut self) -> Result<Option<u16>, VMError> {
+        let address = self.read_register(Register::R0);
+        let Some(name) = self.read_name(address, MAX_NAME_LENGTH)? else {
+            return Ok(None);
+        };
+        let Some(value) = self.env_vars.get(&name).cloned() else {
+            return Ok(None);
+        };
+        let capacity = usize::from(self.read_register(Register::R2));
+        if value.len() >= capacity {
+            return Ok(None);
+        }
+        let mut address = self.read_register(Register::R1);
+        for byte in value.bytes().chain([0]) {
+            self.write_memory(address, u16::from(byte))?;
+            address = address.wrapping_add(1);
+        }
+        Ok(u16::try_from(value.len()).ok())
+    }
+}
+
+/// The words of the argument area holding `args`
+fn args_layout(args: &[String]) -> Result<Vec<u16>, VMError> {
+    let too_long = || {
+        VMError::InvalidArgument(format!(
+            "The arguments do not fit in the {} words at {ARGS_START:#06x}",
+            ARGS_END.wrapping_sub(ARGS_START)
+        ))
+    };
+    let count = u16::try_from(args.len()).map_err(|_| too_long())?;
+    // argc, argv and its terminator
+    let mut string = ARGS_START
+        .checked_add(count)
+        .and_then(|end| end.checked_add(2))
+        .ok_or_else(too_long)?;
+    let mut pointers = Vec::from([count]);
+    let mut strings = Vec::new();
+    for arg in args {
+        pointers.push(string);
+        strings.extend(arg.bytes().map(u16::from).chain([0]));
+        let length = u16::try_from(arg.len()).map_err(|_| too_long())?;
+        string = string
+            .checked_add(length)
+            .and_then(|end| end.checked_add(1))
+            .ok_or_else(too_long)?;
+    }
+    if string > ARGS_END {
+        return Err(too_long());
+    }
+    pointers.push(0);
+    pointers.extend(strings);
+    Ok(pointers)
+}
@@ -0,0 +1,131 @@
+//! Execution as an iterator of events, for tools that would rather consume
+//! a stream than implement `Observer`.
+
+use alloc::{boxed::Box, collections::VecDeque, rc::Rc};
+use core::cell::RefCell;
+
+use super::{StopReason, VM};
+use crate::{errors::VMError, observer::Observer};
+
+/// Something that happened while the program ran, in the order it happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecEvent {
+    /// The instruction `raw` at `pc` finished, after the events it caused
+    InstructionRetired { pc: u16, raw: u16 },
+    /// Memory at `address` was set to `value`
+    MemoryWrite { address: u16, value: u16 },
+    /// A character was written to the console
+    Output(char),
+    /// The program executed HALT, always the last event
+    Halted,
+}
+
+/// Runs the program one instruction at a time while iterated, see
+/// `VM::events`. An observer attached to the VM keeps receiving its
+/// callbacks and is left in place when the iterator is dropped.
+pub struct Events<'a> {
+    vm: &'a mut VM,
+    recorder: Rc<RefCell<Recorder>>,
+    finished: bool,
+}
+
+struct Recorder {
+    queue: VecDeque<ExecEvent>,
+    inner: Option<Box<dyn Observer>>,
+}
+
+impl Observer for Recorder {
+    fn before_instruction(&mut self, vm: &VM, pc: u16, raw: u16) {
+        if let Some(inner) = &mut self.inner {
+            inner.before_instruction(vm, pc, raw);
+        }
+    }
+
+    fn after_instruction(&mut self, vm: &VM, pc: u16, raw: u16) {
+        self.queue
+            .push_back(ExecEvent::InstructionRetired { pc, raw });
+        if let Some(inner) = &mut self.inner {
+            inner.after_instruction(vm, pc, raw);
+        }
+    }
+
+    fn on_mem_read(&mut self, address: u16, value: u16) {
+        if let Some(inner) = &mut self.inner {
+            inner.on_mem_read(address, value);
+        }
+    }
+
+    fn on_mem_write(&mut self, address: u16, value: u16) {
+        self.queue
+            .push_back(ExecEvent::MemoryWrite { address, value });
+        if let Some(inner) = &mut self.inner {
+            inner.on_mem_write(address, value);
+        }
+    }
+
+    fn on_trap(&mut self, vector: u16) {
+        if let Some(inner) = &mut self.inner {
+            inner.on_trap(vector);
+        }
+    }
+
+    fn on_output(&mut self, character: char) {
+        self.queue.push_back(ExecEvent::Output(character));
+        if let Some(inner) = &mut self.inner {
+            inner.on_output(character);
+        }
+    }
+}
+
+impl VM {
+    /// Iterates over what the program does from the current PC, executing
+    /// instructions as events are requested. The iterator ends after
+    /// `Halted` or after yielding an error.
+    pub fn events(&mut self) -> Events<'_> {
+        let recorder = Rc::new(RefCell::new(Recorder {
+            queue: VecDeque::new(),
+            inner: self.observer.take(),
+        }));
+        self.observer = Some(Box::new(Rc::clone(&recorder)));
+        Events {
+            vm: self,
+            recorder,
+            finished: false,
+        }
+    }
+}
+
+impl Iterator for Events<'_> {
+    type Item = Result<ExecEvent, VMError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.recorder.borrow_mut().queue.pop_front() {
+                return Some(Ok(event));
+            }
+            if self.finished {
+                return None;
+            }
+            match self.vm.step() {
+                Ok(StopReason::Halted) => {
+                    self.finished = true;
+                    self.recorder
+                        .borrow_mut()
+                        .queue
+                        .push_back(ExecEvent::Halted);
+                }
+                Ok(_) => self.vm.tick_clock(),
+                Err(error) => {
+                    self.finished = true;
+                    return Some(Err(error));
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Events<'_> {
+    fn drop(&mut self) {
+        self.vm.observer = self.recorder.borrow_mut().inner.take();
+    }
+}
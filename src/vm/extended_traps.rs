@@ -0,0 +1,103 @@
+//! Convenience traps outside the LC-3 specification, only available after
+//! `VM::set_extended_traps(true)` so strict programs keep failing on them
+//! like on real hardware.
+
+use alloc::format;
+
+use super::VM;
+use crate::{clock::TimeSource, errors::VMError, register::Register};
+
+/// Prints R0 as a signed decimal number
+pub const PUTD: u16 = 0x26;
+/// Reads a line into the words at R0, without the newline and terminated by
+/// a zero word, writing at most R1 words. Typed characters are echoed and
+/// backspace erases; those that do not fit are ignored. Consoles without a
+/// terminal must already hold the whole line.
+pub const GETS: u16 = 0x27;
+
+/// Puts the milliseconds the VM has been running in R1:R0, R0 holding the
+/// low word, counted by the `TimeSource` of the VM
+pub const TIME: u16 = 0x28;
+
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7F;
+
+impl VM {
+    /// Enables or disables the PUTD (x26), GETS (x27) and TIME (x28) traps
+    pub fn set_extended_traps(&mut self, enabled: bool) {
+        self.extended_traps = enabled;
+    }
+
+    /// Runs the extended trap `vector`, or returns `None` if it is not one
+    /// or they are disabled
+    pub(super) fn extended_trap(&mut self, vector: u16) -> Option<Result<(), VMError>> {
+        if !self.extended_traps {
+            return None;
+        }
+        match vector {
+            PUTD => Some(self.putd()),
+            GETS => Some(self.gets()),
+            TIME => Some(self.time()),
+            _ => None,
+        }
+    }
+
+    fn putd(&mut self) -> Result<(), VMError> {
+        let value = i16::from_ne_bytes(self.read_register(Register::R0).to_ne_bytes());
+        self.write_str(&format!("{value}"))?;
+        self.console.flush()
+    }
+
+    fn time(&mut self) -> Result<(), VMError> {
+        let milliseconds = match self.time_source {
+            TimeSource::Host => self.metrics().host_time.as_millis(),
+            TimeSource::Virtual(rate) => u128::from(self.metrics().instructions)
+                .saturating_mul(1000)
+                .checked_div(u128::from(rate))
+                .unwrap_or_default(),
+            TimeSource::VirtualCycles(rate) => u128::from(self.metrics().cycles)
+                .saturating_mul(1000)
+                .checked_div(u128::from(rate))
+                .unwrap_or_default(),
+        };
+        let [low, high, third, fourth, ..] = milliseconds.to_le_bytes();
+        self.write_register(Register::R1, u16::from_le_bytes([third, fourth]));
+        self.write_register_with_flags(Register::R0, u16::from_le_bytes([low, high]));
+        Ok(())
+    }
+
+    fn gets(&mut self) -> Result<(), VMError> {
+        let start = self.read_register(Register::R0);
+        let size = self.read_register(Register::R1);
+        // the characters leave a word for the terminator
+        let capacity = size.saturating_sub(1);
+        let mut end = start;
+        loop {
+            self.console.flush()?;
+            let Some(key) = self.trap_key()? else {
+                return Ok(());
+            };
+            match key {
+                b'\n' | b'\r' => break,
+                BACKSPACE | DELETE => {
+                    if end != start {
+                        end = end.wrapping_sub(1);
+                        self.write_str("\x08 \x08")?;
+                    }
+                }
+                key => {
+                    if end.wrapping_sub(start) < capacity {
+                        self.write_memory(end, u16::from(key))?;
+                        self.print_byte(key)?;
+                        end = end.wrapping_add(1);
+                    }
+                }
+            }
+        }
+        if size > 0 {
+            self.write_memory(end, 0)?;
+        }
+        self.output('\n')?;
+        self.console.flush()
+    }
+}
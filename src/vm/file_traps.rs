@@ -0,0 +1,195 @@
+//! The optional file traps described in `vfs`. Failures a program can
+//! handle, like a missing file or a bad handle, return -1 in R0 instead of
+//! stopping the VM.
+
+use alloc::{boxed::Box, collections::BTreeMap, string::String, vec::Vec};
+
+use super::VM;
+use crate::{
+    errors::VMError,
+    register::Register,
+    vfs::{self, FileSystem, FCLOSE, FOPEN, FREAD, FWRITE, MAX_NAME_LENGTH},
+};
+
+/// Files a program may have open at once
+const MAX_OPEN_FILES: usize = 16;
+/// Returned in R0 when a file trap fails
+const FAILURE: u16 = 0xFFFF;
+
+pub(super) struct FileTraps {
+    fs: Box<dyn FileSystem>,
+    open: BTreeMap<u16, OpenFile>,
+}
+
+struct OpenFile {
+    name: String,
+    contents: Vec<u8>,
+    /// Next byte to read, files open for writing only grow at the end
+    position: usize,
+    writable: bool,
+}
+
+impl FileTraps {
+    pub(super) fn new(fs: Box<dyn FileSystem>) -> Self {
+        FileTraps {
+            fs,
+            open: BTreeMap::new(),
+        }
+    }
+
+    fn open(&mut self, name: &str, mode: u16) -> Option<u16> {
+        if !vfs::is_valid_name(name) || self.open.len() >= MAX_OPEN_FILES {
+            return None;
+        }
+        let existing = self.fs.load(name).ok()?;
+        let (contents, writable) = match mode {
+            0 => (existing?, false),
+            1 => (Vec::new(), true),
+            2 => (existing.unwrap_or_default(), true),
+            _ => return None,
+        };
+        let handle = (0..).find(|handle| !self.open.contains_key(handle))?;
+        self.open.insert(
+            handle,
+            OpenFile {
+                name: String::from(name),
+                position: 0,
+                contents,
+                writable,
+            },
+        );
+        Some(handle)
+    }
+
+    /// Forgets the open files without saving them
+    pub(super) fn discard_open(&mut self) {
+        self.open.clear();
+    }
+
+    fn close(&mut self, handle: u16) -> Option<u16> {
+        let file = self.open.remove(&handle)?;
+        if file.writable {
+            self.fs.store(&file.name, &file.contents).ok()?;
+        }
+        Some(0)
+    }
+}
+
+impl VM {
+    /// Enables the file traps FOPEN to FCLOSE (x80-x83) on `fs`
+    pub fn enable_file_traps(&mut self, fs: Box<dyn FileSystem>) {
+        self.files = Some(FileTraps::new(fs));
+    }
+
+    /// Runs the file trap `vector`, or returns `None` if it is not one or
+    /// the traps are disabled
+    pub(super) fn file_trap(&mut self, vector: u16) -> Option<Result<(), VMError>> {
+        self.files.as_ref()?;
+        let result = match vector {
+            FOPEN => self.file_open(),
+            FREAD => self.file_read(),
+            FWRITE => self.file_write(),
+            FCLOSE => {
+                let handle = self.read_register(Register::R0);
+                Ok(self.files.as_mut().and_then(|files| files.close(handle)))
+            }
+            _ => return None,
+        };
+        Some(result.map(|value| {
+            self.write_register_with_flags(Register::R0, value.unwrap_or(FAILURE));
+        }))
+    }
+
+    fn file_open(&mut self) -> Result<Option<u16>, VMError> {
+        let address = self.read_register(Register::R0);
+        let Some(name) = self.read_name(address, MAX_NAME_LENGTH)? else {
+            return Ok(None);
+        };
+        let mode = self.read_register(Register::R1);
+        Ok(self
+            .files
+            .as_mut()
+            .and_then(|files| files.open(&name, mode)))
+    }
+
+    /// Reads the name string at `address`, or `None` if it is longer than
+    /// `max_length` or not ASCII
+    pub(super) fn read_name(
+        &mut self,
+        mut address: u16,
+        max_length: usize,
+    ) -> Result<Option<String>, VMError> {
+        let mut name = String::new();
+        loop {
+            let word = self.read_memory(address)?;
+            if word == 0 {
+                return Ok(Some(name));
+            }
+            match u8::try_from(word) {
+                Ok(byte) if byte.is_ascii() && name.len() < max_length => {
+                    name.push(char::from(byte));
+                }
+                _ => return Ok(None),
+            }
+            address = address.wrapping_add(1);
+        }
+    }
+
+    fn file_read(&mut self) -> Result<Option<u16>, VMError> {
+        let handle = self.read_register(Register::R0);
+        let buffer = self.read_register(Register::R1);
+        let count = usize::from(self.read_register(Register::R2));
+        let Some(file) = self
+            .files
+            .as_mut()
+            .and_then(|files| files.open.get_mut(&handle))
+        else {
+            return Ok(None);
+        };
+        if file.writable {
+            return Ok(None);
+        }
+        let bytes: Vec<u8> = file
+            .contents
+            .iter()
+            .skip(file.position)
+            .take(count)
+            .copied()
+            .collect();
+        file.position = file.position.saturating_add(bytes.len());
+        let mut address = buffer;
+        for byte in &bytes {
+            self.write_memory(address, u16::from(*byte))?;
+            address = address.wrapping_add(1);
+        }
+        Ok(u16::try_from(bytes.len()).ok())
+    }
+
+    fn file_write(&mut self) -> Result<Option<u16>, VMError> {
+        let handle = self.read_register(Register::R0);
+        let writable = self
+            .files
+            .as_ref()
+            .and_then(|files| files.open.get(&handle))
+            .is_some_and(|file| file.writable);
+        if !writable {
+            return Ok(None);
+        }
+        let mut address = self.read_register(Register::R1);
+        let count = self.read_register(Register::R2);
+        let mut bytes = Vec::new();
+        for _ in 0..count {
+            let [low, _] = self.read_memory(address)?.to_le_bytes();
+            bytes.push(low);
+            address = address.wrapping_add(1);
+        }
+        if let Some(file) = self
+            .files
+            .as_mut()
+            .and_then(|files| files.open.get_mut(&handle))
+        {
+            file.contents.extend_from_slice(&bytes);
+        }
+        Ok(Some(count))
+    }
+}
@@ -0,0 +1,133 @@
+//! Branching a machine into copies that run on independently, for search
+//! tools, fuzzers and debuggers trying "what if" from a point of a
+//! program:
+//!
+//! ```
+//! use lc3_vm::{register::Register, vm::VM};
+//!
+//! let mut vm = VM::new();
+//! vm.load_asm_str(".ORIG x3000\nADD R0, R0, #1\nHALT\n.END").unwrap();
+//! let mut branch = vm.fork().unwrap();
+//! branch.set_register(Register::R0, 41);
+//! branch.run_headless().unwrap();
+//! assert_eq!(branch.register(Register::R0), 42);
+//! assert_eq!(vm.register(Register::R0), 0);
+//! ```
+//!
+//! The memory is not copied: the fork and the original share its pages of
+//! 1024 words, and the first write to a page by either copies that page
+//! alone. The first fork of a machine moves its memory into pages, after
+//! which forking takes under a microsecond instead of a copy of all
+//! 128 KiB, see the `fork` benchmarks of `benches/vm.rs`. The JIT and shared
+//! memory windows need the memory in one piece, so a machine using them
+//! gathers its pages again, taking the copy forking avoided.
+
+use alloc::{boxed::Box, collections::BTreeMap, format, vec::Vec};
+
+use super::{metrics::MetricsState, undo::Undo, VM};
+use crate::{console::NullConsole, errors::VMError};
+
+impl VM {
+    /// A copy of the machine that runs on from the same state: registers,
+    /// memory, devices, pending interrupts and keys, breakpoints, limits
+    /// and settings.
+    ///
+    /// The fork prints to and reads from a `NullConsole` until
+    /// `set_console` gives it another, and starts without an observer, a
+    /// metrics callback or the JIT, leaving the original's alone. It
+    /// decodes the instructions it runs again. Machines connected to the
+    /// host through a serial port, channels, file traps or trap and
+    /// opcode handlers cannot be forked, since those cannot be copied.
+    pub fn fork(&mut self) -> Result<VM, VMError> {
+        self.check_forkable()?;
+        let mut metrics = MetricsState::default();
+        metrics.counters = self.metrics();
+        let mut fork = VM {
+            memory: self.memory.fork(),
+            images: self.images.clone(),
+            registers: self.registers,
+            pc: self.pc,
+            entry: self.entry,
+            entry_fixed: self.entry_fixed,
+            psr: self.psr,
+            running: false,
+            halted: self.halted,
+            breakpoints: self.breakpoints.clone(),
+            trap_breaks: self.trap_breaks.clone(),
+            break_on_interrupt: self.break_on_interrupt,
+            device_breaks: self.device_breaks.clone(),
+            device_hit: None,
+            stack_checker: self.stack_checker,
+            loop_detector: self.loop_detector.clone(),
+            clock: self.clock.clone(),
+            fuel: None,
+            instruction_limit: self.instruction_limit,
+            output_limit: self.output_limit,
+            printed: self.printed,
+            #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+            watchdog: self.watchdog.clone(),
+            console: Box::new(NullConsole),
+            console_config: self.console_config.clone(),
+            pending_keys: self.pending_keys.clone(),
+            pending_scancodes: self.pending_scancodes.clone(),
+            utf8: self.utf8.clone(),
+            ansi: self.ansi.clone(),
+            break_requested: false,
+            trap_handlers: BTreeMap::new(),
+            reserved_opcode: None,
+            observer: None,
+            step_writes: None,
+            undo: Undo::default(),
+            interrupts: self.interrupts.clone(),
+            metrics,
+            extended_traps: self.extended_traps,
+            time_source: self.time_source,
+            trap_messages: self.trap_messages,
+            conformance: self.conformance,
+            files: None,
+            env_vars: self.env_vars.clone(),
+            args: self.args.clone(),
+            #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+            serial: None,
+            display: self.display.clone(),
+            dma: self.dma,
+            timer: self.timer,
+            rng: self.rng.clone(),
+            channels: Vec::new(),
+            windows: self.windows.clone(),
+            unmapped_access: self.unmapped_access,
+            micro: self.micro,
+            #[cfg(not(feature = "threaded"))]
+            blocks: super::block_cache::BlockCache::new(),
+            #[cfg(feature = "threaded")]
+            threaded: super::threaded::ThreadedCode::new(),
+            #[cfg(all(feature = "jit", not(feature = "threaded")))]
+            jit: None,
+        };
+        fork.set_cycle_model(self.cycle_model());
+        fork.set_undo(self.undo_enabled());
+        Ok(fork)
+    }
+
+    /// Fails if the machine has something connected to the host
+    fn check_forkable(&self) -> Result<(), VMError> {
+        #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+        let serial = self.serial.is_some();
+        #[cfg(not(all(feature = "std", not(target_arch = "wasm32"))))]
+        let serial = false;
+        let connected = if serial {
+            "a serial port"
+        } else if !self.channels.is_empty() {
+            "channels"
+        } else if self.files.is_some() {
+            "file traps"
+        } else if !self.trap_handlers.is_empty() || self.reserved_opcode.is_some() {
+            "host handlers"
+        } else {
+            return Ok(());
+        };
+        Err(VMError::InvalidArgument(format!(
+            "Cannot fork a machine connected to the host through {connected}"
+        )))
+    }
+}
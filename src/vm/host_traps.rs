@@ -0,0 +1,75 @@
+//! Handlers installed by the embedder for what the LC-3 leaves unused: trap
+//! vectors, consulted after the standard traps and before the extended and
+//! file traps, and the reserved opcode 1101 for experimental instructions.
+
+use alloc::{boxed::Box, format};
+
+use super::VM;
+use crate::errors::VMError;
+
+/// Handler run when a program executes its trap, with R7 already holding
+/// the return address
+pub type TrapHandler = Box<dyn FnMut(&mut VM) -> Result<(), VMError>>;
+
+/// Handler run for instructions with the reserved opcode, receiving the
+/// instruction word with the PC already past it
+pub type OpcodeHandler = Box<dyn FnMut(&mut VM, u16) -> Result<(), VMError>>;
+
+/// First vector after the standard traps, available for handlers up to xFF
+pub const FIRST_FREE_TRAP: u16 = 0x26;
+
+impl VM {
+    /// Runs `handler` whenever the program executes `TRAP vector`, replacing
+    /// any previous handler for it. Only the unused vectors x26-xFF can be
+    /// registered.
+    pub fn register_trap<F>(&mut self, vector: u16, handler: F) -> Result<(), VMError>
+    where
+        F: FnMut(&mut VM) -> Result<(), VMError> + 'static,
+    {
+        if !(FIRST_FREE_TRAP..=0xFF).contains(&vector) {
+            return Err(VMError::InvalidArgument(format!(
+                "Trap vector {vector:#04x} cannot be registered, use x26-xFF"
+            )));
+        }
+        self.trap_handlers.insert(vector, Box::new(handler));
+        Ok(())
+    }
+
+    /// Removes the handler of `vector`, returning false if there was none
+    pub fn unregister_trap(&mut self, vector: u16) -> bool {
+        self.trap_handlers.remove(&vector).is_some()
+    }
+
+    /// Runs the handler registered for `vector`, if any
+    pub(super) fn host_trap(&mut self, vector: u16) -> Option<Result<(), VMError>> {
+        let mut handler = self.trap_handlers.remove(&vector)?;
+        let result = handler(self);
+        // keep a handler the callback registered for its own vector
+        self.trap_handlers.entry(vector).or_insert(handler);
+        Some(result)
+    }
+
+    /// Runs `handler` for every instruction with the reserved opcode 1101,
+    /// e.g. to try out shifts or MUL, instead of failing with an invalid
+    /// opcode error. The handler decodes the operands from the instruction
+    /// and updates the registers, condition codes and PC itself.
+    pub fn set_reserved_opcode_handler<F>(&mut self, handler: F)
+    where
+        F: FnMut(&mut VM, u16) -> Result<(), VMError> + 'static,
+    {
+        self.reserved_opcode = Some(Box::new(handler));
+    }
+
+    /// Restores the default of failing on the reserved opcode
+    pub fn clear_reserved_opcode_handler(&mut self) {
+        self.reserved_opcode = None;
+    }
+
+    /// Runs the reserved opcode handler, if there is one
+    pub(super) fn custom_opcode(&mut self, raw: u16) -> Option<Result<(), VMError>> {
+        let mut handler = self.reserved_opcode.take()?;
+        let result = handler(self, raw);
+        self.reserved_opcode.get_or_insert(handler);
+        Some(result)
+    }
+}
@@ -0,0 +1,144 @@
+//! Attaching and detaching devices between runs, for instance to plug a
+//! serial console into a machine stopped in the debugger. The `set_serial`,
+//! `enable_display`, `enable_dma`, `enable_timer`, `enable_rng`,
+//! `add_channel` and `map_device` methods attach devices at any time, and
+//! `detach_device` takes them out of the device map again.
+//!
+//! Addresses from xFE00 up that no device answers behave as plain memory by
+//! default, as they always did. `set_unmapped_access` makes programs
+//! touching them read zeros, take a bus error exception or fail instead.
+
+use alloc::{format, vec::Vec};
+use core::{fmt, str::FromStr};
+
+use super::VM;
+use crate::{errors::VMError, memory::MMIO_START};
+
+/// Exception raised by accesses to device addresses no device answers, with
+/// `UnmappedAccess::BusError`. The LC-3 leaves this vector unused.
+pub const BUS_ERROR: u8 = 0x03;
+
+/// What accessing a device address no device answers does
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnmappedAccess {
+    /// Reads and writes the memory behind the address, like RAM
+    #[default]
+    Memory,
+    /// Reads zero and ignores writes
+    Zero,
+    /// Reads zero, ignores writes and raises the `BUS_ERROR` exception,
+    /// entered once the instruction completes
+    BusError,
+    /// Fails the instruction with a memory error
+    Fail,
+}
+
+impl FromStr for UnmappedAccess {
+    type Err = VMError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "memory" => Ok(UnmappedAccess::Memory),
+            "zero" => Ok(UnmappedAccess::Zero),
+            "bus-error" => Ok(UnmappedAccess::BusError),
+            "fail" => Ok(UnmappedAccess::Fail),
+            _ => Err(VMError::InvalidArgument(format!(
+                "Unknown unmapped access policy {name}, expected memory, zero, bus-error or fail"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for UnmappedAccess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            UnmappedAccess::Memory => "memory",
+            UnmappedAccess::Zero => "zero",
+            UnmappedAccess::BusError => "bus-error",
+            UnmappedAccess::Fail => "fail",
+        })
+    }
+}
+
+impl VM {
+    /// Chooses what accessing a device address no device answers does
+    pub fn set_unmapped_access(&mut self, policy: UnmappedAccess) {
+        self.unmapped_access = policy;
+    }
+
+    pub fn unmapped_access(&self) -> UnmappedAccess {
+        self.unmapped_access
+    }
+
+    /// Detaches the device `name`, one of `serial`, `display`, `dma`,
+    /// `timer`, `rng`, a channel, a window or a region given to
+    /// `map_device`, and clears its registers. The keyboard and the PSR
+    /// belong to every machine and cannot be detached.
+    pub fn detach_device(&mut self, name: &str) -> Result<(), VMError> {
+        if matches!(name, "keyboard" | "psr") {
+            return Err(VMError::InvalidArgument(format!(
+                "The {name} cannot be detached"
+            )));
+        }
+        let registers: Vec<_> = self
+            .memory
+            .mmio_map()
+            .regions()
+            .iter()
+            .filter(|region| region.name == name)
+            .map(|region| (region.start.max(MMIO_START), region.end))
+            .collect();
+        if !self.memory.unmap_device(name) {
+            return Err(VMError::InvalidArgument(format!(
+                "No device named `{name}` is attached"
+            )));
+        }
+        match name {
+            #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+            "serial" => self.serial = None,
+            "display" => self.display = None,
+            "dma" => self.dma = None,
+            "timer" => self.timer = None,
+            "rng" => self.rng = None,
+            _ => {
+                self.remove_channel(name);
+                self.remove_window(name);
+            }
+        }
+        for (start, end) in registers {
+            for address in start..=end {
+                self.memory.write(address, 0)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes memory for the host, which reaches the addresses no device
+    /// answers whatever the policy
+    pub(super) fn host_write(&mut self, address: u16, value: u16) -> Result<(), VMError> {
+        let policy = core::mem::take(&mut self.unmapped_access);
+        let result = self.write_memory(address, value);
+        self.unmapped_access = policy;
+        result
+    }
+
+    /// Applies the unmapped access policy to the device address `address`,
+    /// returning whether the access goes ahead
+    pub(super) fn device_answers(&mut self, address: u16) -> Result<bool, VMError> {
+        if self.unmapped_access == UnmappedAccess::Memory
+            || self.memory.mmio_map().region_at(address).is_some()
+        {
+            return Ok(true);
+        }
+        match self.unmapped_access {
+            UnmappedAccess::Memory | UnmappedAccess::Zero => {}
+            UnmappedAccess::BusError => self.raise_fault(BUS_ERROR),
+            UnmappedAccess::Fail => {
+                return Err(VMError::MemoryIndex(format!(
+                    "No device answers x{address:04X}"
+                )))
+            }
+        }
+        Ok(false)
+    }
+}
@@ -0,0 +1,51 @@
+//! Programs given as assembly text instead of object files
+
+use alloc::format;
+
+use super::VM;
+use crate::{
+    assembler::{assemble_at, Assembly},
+    errors::VMError,
+};
+
+impl VM {
+    /// Assembles `source` at the entry point, or at its own `.ORIG`, loads
+    /// it like an image and points the PC at its origin. Returns the
+    /// assembly for its symbols.
+    pub fn load_asm_str(&mut self, source: &str) -> Result<Assembly, VMError> {
+        let assembly = assemble_at(source, self.entry)?;
+        assembly.ensure_linked()?;
+        self.read_image_bytes(&assembly.image())?;
+        self.pc = assembly.origin;
+        Ok(assembly)
+    }
+
+    /// Assembles `source` at the PC, stores it there over whatever was in
+    /// memory and executes it until the PC leaves it or the program halts,
+    /// so `vm.exec_asm("ADD R0, R0, #1")` acts on the live machine. A
+    /// halted machine runs again. Stops with an error after the instruction
+    /// limit, if one is set.
+    pub fn exec_asm(&mut self, source: &str) -> Result<(), VMError> {
+        let assembly = assemble_at(source, self.pc)?;
+        assembly.ensure_linked()?;
+        self.memory_mut()
+            .write_words(assembly.origin, &assembly.words)?;
+        self.pc = assembly.origin;
+        self.halted = false;
+        let code = assembly.origin..assembly.end();
+        let mut executed: u64 = 0;
+        while code.contains(&self.pc) && !self.halted {
+            if let Some(limit) = self.instruction_limit {
+                if executed >= limit {
+                    return Err(VMError::InstructionLimit(format!(
+                        "Stopped after {limit} instructions at {:#06x}",
+                        self.pc
+                    )));
+                }
+            }
+            self.step()?;
+            executed = executed.saturating_add(1);
+        }
+        Ok(())
+    }
+}
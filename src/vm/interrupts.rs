@@ -0,0 +1,172 @@
+//! Interrupts and exceptions raised by the host, so tests and embedders can
+//! run the handlers of an operating system without a device generating the
+//! event. Both are served as in the LC-3: the PSR and the PC are pushed on
+//! the supervisor stack, the machine enters supervisor mode and jumps to
+//! the handler in the interrupt vector table at x0100, and RTI returns.
+//!
+//! A program entering from user mode switches from its stack to the
+//! supervisor stack, saving its R6 for the RTI that goes back to user mode.
+//! RTI returning to user mode without such an entry keeps R6, as before.
+
+use alloc::{collections::BTreeMap, format};
+
+use super::VM;
+use crate::{errors::VMError, register::Register};
+
+/// Start of the interrupt vector table, holding the handler of vector `v`
+/// at `INTERRUPT_VECTOR_TABLE + v`
+pub const INTERRUPT_VECTOR_TABLE: u16 = 0x0100;
+/// Supervisor stack pointer user mode programs switch to, the conventional
+/// top of the operating system's stack
+pub const SUPERVISOR_STACK: u16 = 0x3000;
+
+/// Interrupt state of the VM
+#[derive(Debug, Clone)]
+pub(super) struct Interrupts {
+    /// Priority of every raised interrupt not delivered yet, by vector
+    pending: BTreeMap<u8, u8>,
+    /// Handlers entered and not returned from yet
+    depth: u32,
+    saved_ssp: u16,
+    /// R6 of the user mode program an interrupt or exception entered from
+    saved_usp: Option<u16>,
+    /// Exception raised by the instruction executing, entered before the
+    /// next one
+    fault: Option<u8>,
+}
+
+impl Default for Interrupts {
+    fn default() -> Self {
+        Interrupts {
+            pending: BTreeMap::new(),
+            depth: 0,
+            saved_ssp: SUPERVISOR_STACK,
+            saved_usp: None,
+            fault: None,
+        }
+    }
+}
+
+impl VM {
+    /// Requests the interrupt with `vector` at `priority`, 0 to 7. It is
+    /// delivered before the next instruction executed at a lower priority,
+    /// so a priority of 0 is never delivered. Raising a pending vector again
+    /// changes its priority.
+    pub fn raise_interrupt(&mut self, vector: u8, priority: u8) -> Result<(), VMError> {
+        if priority > 7 {
+            return Err(VMError::InvalidArgument(format!(
+                "Interrupt priorities range from 0 to 7, got {priority}"
+            )));
+        }
+        self.interrupts.pending.insert(vector, priority);
+        Ok(())
+    }
+
+    /// Enters the handler of the exception with `vector` right away, keeping
+    /// the priority, as if the instruction at the PC had raised it
+    pub fn raise_exception(&mut self, vector: u8) -> Result<(), VMError> {
+        self.enter_handler(vector, None)
+    }
+
+    /// Interrupts raised and not delivered yet, as `(vector, priority)`
+    pub fn pending_interrupts(&self) -> impl Iterator<Item = (u8, u8)> + '_ {
+        self.interrupts
+            .pending
+            .iter()
+            .map(|(&vector, &priority)| (vector, priority))
+    }
+
+    /// Drops the interrupts raised and not delivered yet
+    pub fn clear_interrupts(&mut self) {
+        self.interrupts.pending.clear();
+    }
+
+    /// Whether a handler entered by an interrupt or exception has not
+    /// returned yet
+    pub fn in_handler(&self) -> bool {
+        self.interrupts.depth > 0
+    }
+
+    /// Raises the exception with `vector` for an instruction that cannot
+    /// stop halfway, entering its handler before the next instruction
+    pub(super) fn raise_fault(&mut self, vector: u8) {
+        self.interrupts.fault = Some(vector);
+    }
+
+    /// Exception the last instruction raised and not entered yet
+    pub(super) fn pending_fault(&self) -> Option<u8> {
+        self.interrupts.fault
+    }
+
+    /// Vector of the interrupt delivered before the next instruction, the
+    /// one with the highest priority above the PSR's and the lowest vector
+    /// among those
+    pub(super) fn deliverable_interrupt(&self) -> Option<u8> {
+        let level = self.psr.priority();
+        self.interrupts
+            .pending
+            .iter()
+            .filter(|&(_, &priority)| priority > level)
+            .max_by_key(|&(&vector, &priority)| (priority, core::cmp::Reverse(vector)))
+            .map(|(&vector, _)| vector)
+    }
+
+    /// Whether an interrupt may be due, for runners executing several
+    /// instructions between `take_interrupt` calls
+    #[inline]
+    pub(super) fn interrupt_raised(&self) -> bool {
+        !self.interrupts.pending.is_empty() || self.interrupts.fault.is_some()
+    }
+
+    /// Delivers the exception or interrupt due before the next instruction,
+    /// if any
+    #[inline]
+    pub(super) fn take_interrupt(&mut self) -> Result<(), VMError> {
+        if !self.interrupt_raised() {
+            return Ok(());
+        }
+        if let Some(vector) = self.interrupts.fault.take() {
+            return self.enter_handler(vector, None);
+        }
+        let Some(vector) = self.deliverable_interrupt() else {
+            return Ok(());
+        };
+        let priority = self.interrupts.pending.remove(&vector);
+        self.enter_handler(vector, priority)
+    }
+
+    /// Pushes the PSR and the PC on the supervisor stack and jumps to the
+    /// handler of `vector`, raising the priority for an interrupt
+    fn enter_handler(&mut self, vector: u8, priority: Option<u8>) -> Result<(), VMError> {
+        let psr = self.psr;
+        if psr.user_mode() {
+            self.interrupts.saved_usp = Some(self.read_register(Register::R6));
+            self.write_register(Register::R6, self.interrupts.saved_ssp);
+        }
+        let sp = self.read_register(Register::R6).wrapping_sub(2);
+        self.write_memory(sp.wrapping_add(1), psr.bits())?;
+        self.write_memory(sp, self.pc)?;
+        self.write_register(Register::R6, sp);
+        self.psr.set_user_mode(false);
+        if let Some(priority) = priority {
+            self.psr.set_priority(priority);
+        }
+        self.pc = self.read_memory(INTERRUPT_VECTOR_TABLE.wrapping_add(u16::from(vector)))?;
+        self.interrupts.depth = self.interrupts.depth.saturating_add(1);
+        let counters = &mut self.metrics.counters;
+        counters.interrupts = counters.interrupts.wrapping_add(1);
+        Ok(())
+    }
+
+    /// What RTI does besides popping the PC and PSR: leaves the handler and
+    /// goes back to the user stack if the handler was entered from it
+    pub(super) fn leave_handler(&mut self) {
+        self.interrupts.depth = self.interrupts.depth.saturating_sub(1);
+        if self.psr.user_mode() {
+            if let Some(usp) = self.interrupts.saved_usp.take() {
+                self.interrupts.saved_ssp = self.read_register(Register::R6);
+                self.write_register(Register::R6, usp);
+            }
+        }
+    }
+}
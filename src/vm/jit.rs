@@ -0,0 +1,587 @@
+//! Experimental JIT: hot basic blocks are compiled to native code with
+//! Cranelift. Compiled code keeps the interpreter semantics by bailing out
+//! ("deoptimizing") right before any instruction it cannot run natively:
+//! traps, device register accesses and out of bounds addresses. Stores that
+//! hit a cached block exit after the write so the block can be dropped.
+//! Blocks looping back to their start return every `LOOP_ITERATIONS`
+//! passes, and report the instructions and memory accesses they ran so the
+//! interpreter can account for them.
+
+use std::mem::{self, offset_of};
+
+use cranelift_codegen::{
+    ir::{condcodes::IntCC, types, AbiParam, Block, InstBuilder, MemFlagsData, Type, Value},
+    isa::TargetFrontendConfig,
+    settings::{self, Configurable},
+    Context,
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Module};
+
+use crate::{instructions::sign_extend, memory::MMIO_START};
+
+/// Executions of a block before it gets compiled
+pub(super) const HOT_THRESHOLD: u32 = 16;
+
+/// Passes through a block looping back to its start before compiled code
+/// returns, so the interpreter gets to stop the run and call back the host
+const LOOP_ITERATIONS: i64 = 1024;
+
+/// The block ran to its end, `pc` holds the next instruction
+pub(super) const EXIT_DONE: u32 = 0;
+/// The instruction at `pc` has to be executed by the interpreter
+pub(super) const EXIT_DEOPT: u32 = 1;
+/// A store wrote `written`, which holds cached code
+pub(super) const EXIT_CODE_WRITTEN: u32 = 2;
+
+/// Machine state shared with compiled code
+#[repr(C)]
+pub(super) struct JitState {
+    pub(super) registers: [u16; 8],
+    pub(super) pc: u16,
+    /// N, Z and P bits laid out as in the BR instruction
+    pub(super) cond: u16,
+    pub(super) written: u16,
+    /// Instructions completed, and the data reads and writes they made
+    pub(super) executed: u32,
+    pub(super) reads: u32,
+    pub(super) writes: u32,
+    pub(super) memory: *mut u16,
+    pub(super) coverage: *const u8,
+}
+
+pub(super) type CompiledBlock = unsafe extern "C" fn(*mut JitState) -> u32;
+
+pub(super) struct Jit {
+    module: JITModule,
+    context: Context,
+    builder_context: FunctionBuilderContext,
+}
+
+impl Jit {
+    /// Creates a JIT for the host, if Cranelift supports it
+    pub(super) fn new() -> Option<Jit> {
+        let mut flags = settings::builder();
+        flags.set("opt_level", "speed").ok()?;
+        let isa = cranelift_native::builder()
+            .ok()?
+            .finish(settings::Flags::new(flags))
+            .ok()?;
+        let module = JITModule::new(JITBuilder::with_isa(isa, default_libcall_names()));
+        Some(Jit {
+            context: module.make_context(),
+            module,
+            builder_context: FunctionBuilderContext::new(),
+        })
+    }
+
+    /// Compiles the block of `words` starting at `start`, returning `None`
+    /// when code generation fails
+    pub(super) fn compile(
+        &mut self,
+        start: u16,
+        words: &[u16],
+        lea_sets_flags: bool,
+    ) -> Option<CompiledBlock> {
+        self.module.clear_context(&mut self.context);
+        let pointer = self.module.target_config().pointer_type();
+        let signature = &mut self.context.func.signature;
+        signature.params.push(AbiParam::new(pointer));
+        signature.returns.push(AbiParam::new(types::I32));
+
+        let config = self.module.target_config();
+        let builder = FunctionBuilder::new(&mut self.context.func, &mut self.builder_context);
+        Translator::new(builder, pointer, start, lea_sets_flags).translate(words, config)?;
+
+        let id = self
+            .module
+            .declare_anonymous_function(&self.context.func.signature)
+            .ok()?;
+        self.module.define_function(id, &mut self.context).ok()?;
+        self.module.finalize_definitions().ok()?;
+        let code = self.module.get_finalized_function(id);
+        // SAFETY: the function was just defined with the `CompiledBlock`
+        // signature and stays alive as long as the module
+        Some(unsafe { mem::transmute::<*const u8, CompiledBlock>(code) })
+    }
+}
+
+/// Where execution continues after leaving compiled code
+#[derive(Clone, Copy)]
+enum Target {
+    Address(u16),
+    Dynamic(Value),
+}
+
+/// Instructions and the data accesses they made, in the order of the
+/// `JitState` counters
+#[derive(Clone, Copy, Default)]
+struct Counts([u32; 3]);
+
+impl Counts {
+    const INSTRUCTION: Counts = Counts([1, 0, 0]);
+    const READ: Counts = Counts([0, 1, 0]);
+    const WRITE: Counts = Counts([0, 0, 1]);
+
+    fn plus(self, other: Counts) -> Counts {
+        let [instructions, reads, writes] = self.0;
+        let [more_instructions, more_reads, more_writes] = other.0;
+        Counts([
+            instructions.saturating_add(more_instructions),
+            reads.saturating_add(more_reads),
+            writes.saturating_add(more_writes),
+        ])
+    }
+}
+
+/// Offsets of the `JitState` counters
+const COUNTERS: [usize; 3] = [
+    offset_of!(JitState, executed),
+    offset_of!(JitState, reads),
+    offset_of!(JitState, writes),
+];
+
+struct Translator<'a> {
+    builder: FunctionBuilder<'a>,
+    pointer: Type,
+    state: Value,
+    memory: Value,
+    coverage: Value,
+    registers: [Variable; 8],
+    cond: Variable,
+    /// Address of the first instruction and the IR block executing it, so
+    /// branches back to the start loop without leaving compiled code
+    start: u16,
+    body: Block,
+    /// Passes through the block so far
+    iterations: Variable,
+    /// What the passes through the block completed so far did
+    looped: [Variable; 3],
+    /// What the instructions before the current one did in this pass,
+    /// known when compiling, and what the current one did so far
+    completed: Counts,
+    current: Counts,
+    lea_sets_flags: bool,
+}
+
+impl<'a> Translator<'a> {
+    fn new(
+        mut builder: FunctionBuilder<'a>,
+        pointer: Type,
+        start: u16,
+        lea_sets_flags: bool,
+    ) -> Self {
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        let state = builder
+            .block_params(entry)
+            .first()
+            .copied()
+            .unwrap_or_else(|| {
+                // the signature always has the state parameter
+                builder.ins().iconst(pointer, 0)
+            });
+        let trusted = MemFlagsData::trusted();
+        let memory =
+            builder
+                .ins()
+                .load(pointer, trusted, state, field(offset_of!(JitState, memory)));
+        let coverage = builder.ins().load(
+            pointer,
+            trusted,
+            state,
+            field(offset_of!(JitState, coverage)),
+        );
+        let registers = [0_usize, 1, 2, 3, 4, 5, 6, 7].map(|index| {
+            let variable = builder.declare_var(types::I16);
+            let offset = offset_of!(JitState, registers).saturating_add(index.saturating_mul(2));
+            let value = builder
+                .ins()
+                .load(types::I16, trusted, state, field(offset));
+            builder.def_var(variable, value);
+            variable
+        });
+        let cond = builder.declare_var(types::I16);
+        let value = builder.ins().load(
+            types::I16,
+            trusted,
+            state,
+            field(offset_of!(JitState, cond)),
+        );
+        builder.def_var(cond, value);
+        let zero = builder.ins().iconst(types::I32, 0);
+        let iterations = builder.declare_var(types::I32);
+        builder.def_var(iterations, zero);
+        let looped = [(); 3].map(|()| {
+            let variable = builder.declare_var(types::I32);
+            builder.def_var(variable, zero);
+            variable
+        });
+        let body = builder.create_block();
+        builder.ins().jump(body, &[]);
+        builder.switch_to_block(body);
+        Translator {
+            builder,
+            pointer,
+            state,
+            memory,
+            coverage,
+            registers,
+            cond,
+            start,
+            body,
+            iterations,
+            looped,
+            completed: Counts::default(),
+            current: Counts::default(),
+            lea_sets_flags,
+        }
+    }
+
+    fn translate(mut self, words: &[u16], config: TargetFrontendConfig) -> Option<()> {
+        let mut pc = self.start;
+        let mut ended = false;
+        for &raw in words {
+            let next = pc.checked_add(1)?;
+            ended = self.instruction(pc, next, raw);
+            if ended {
+                break;
+            }
+            self.completed = self.completed.plus(self.current).plus(Counts::INSTRUCTION);
+            self.current = Counts::default();
+            pc = next;
+        }
+        if !ended {
+            self.leave(Target::Address(pc), EXIT_DONE, self.completed);
+        }
+        self.builder.seal_all_blocks();
+        self.builder.finalize(config);
+        Some(())
+    }
+
+    /// Emits `raw`, located at `pc`, returning whether it ended the block
+    fn instruction(&mut self, pc: u16, next: u16, raw: u16) -> bool {
+        let dr = usize::from((raw >> 9) & 0x7);
+        let sr1 = usize::from((raw >> 6) & 0x7);
+        match raw >> 12 {
+            0x0 => {
+                self.branch(pc, next, raw);
+                return true;
+            }
+            0x1 | 0x5 => {
+                let left = self.read(sr1);
+                let right = if (raw >> 5) & 1 == 1 {
+                    self.constant(sign_extend(raw, 5))
+                } else {
+                    self.read(usize::from(raw & 0x7))
+                };
+                let value = if raw >> 12 == 0x1 {
+                    self.builder.ins().iadd(left, right)
+                } else {
+                    self.builder.ins().band(left, right)
+                };
+                self.write_with_flags(dr, value);
+            }
+            0x2 => match next.checked_add_signed(sign_extend(raw, 9)) {
+                Some(address) => {
+                    let address = self.builder.ins().iconst(types::I16, i64::from(address));
+                    let value = self.load(pc, address);
+                    self.write_with_flags(dr, value);
+                }
+                None => return self.deopt(pc),
+            },
+            0x3 => match next.checked_add_signed(sign_extend(raw, 9)) {
+                Some(address) => {
+                    let address = self.builder.ins().iconst(types::I16, i64::from(address));
+                    let value = self.read(dr);
+                    self.store(pc, next, address, value);
+                }
+                None => return self.deopt(pc),
+            },
+            0x4 => {
+                let target = if (raw >> 11) & 1 == 1 {
+                    match next.checked_add_signed(sign_extend(raw, 11)) {
+                        Some(address) => Target::Address(address),
+                        None => return self.deopt(pc),
+                    }
+                } else {
+                    Target::Dynamic(self.read(sr1))
+                };
+                let return_address = self.builder.ins().iconst(types::I16, i64::from(next));
+                self.write(7, return_address);
+                self.exit(target, EXIT_DONE);
+                return true;
+            }
+            0x6 => {
+                let address = self.base_relative(pc, sr1, raw);
+                let value = self.load(pc, address);
+                self.write_with_flags(dr, value);
+            }
+            0x7 => {
+                let address = self.base_relative(pc, sr1, raw);
+                let value = self.read(dr);
+                self.store(pc, next, address, value);
+            }
+            0x9 => {
+                let value = self.read(sr1);
+                let value = self.builder.ins().bnot(value);
+                self.write_with_flags(dr, value);
+            }
+            0xA => match next.checked_add_signed(sign_extend(raw, 9)) {
+                Some(address) => {
+                    let address = self.builder.ins().iconst(types::I16, i64::from(address));
+                    let pointer = self.load(pc, address);
+                    let value = self.load(pc, pointer);
+                    self.write_with_flags(dr, value);
+                }
+                None => return self.deopt(pc),
+            },
+            0xB => match next.checked_add_signed(sign_extend(raw, 9)) {
+                Some(address) => {
+                    let address = self.builder.ins().iconst(types::I16, i64::from(address));
+                    let pointer = self.load(pc, address);
+                    let value = self.read(dr);
+                    self.store(pc, next, pointer, value);
+                }
+                None => return self.deopt(pc),
+            },
+            0xC => {
+                let target = self.read(sr1);
+                self.exit(Target::Dynamic(target), EXIT_DONE);
+                return true;
+            }
+            0xE => match next.checked_add_signed(sign_extend(raw, 9)) {
+                Some(address) => {
+                    let value = self.builder.ins().iconst(types::I16, i64::from(address));
+                    if self.lea_sets_flags {
+                        self.write_with_flags(dr, value);
+                    } else {
+                        self.write(dr, value);
+                    }
+                }
+                None => return self.deopt(pc),
+            },
+            // traps, RTI and the reserved opcode stay in the interpreter
+            _ => return self.deopt(pc),
+        }
+        false
+    }
+
+    fn branch(&mut self, pc: u16, next: u16, raw: u16) {
+        let mask = (raw >> 9) & 0x7;
+        let taken_target = match next.checked_add_signed(sign_extend(raw, 9)) {
+            Some(address) => (Target::Address(address), EXIT_DONE),
+            None => (Target::Address(pc), EXIT_DEOPT),
+        };
+        let cond = self.builder.use_var(self.cond);
+        let taken = self.builder.ins().band_imm_u(cond, i64::from(mask));
+        let taken_block = self.builder.create_block();
+        let fallthrough_block = self.builder.create_block();
+        self.builder
+            .ins()
+            .brif(taken, taken_block, &[], fallthrough_block, &[]);
+        self.builder.switch_to_block(taken_block);
+        match taken_target {
+            (Target::Address(address), EXIT_DONE) if address == self.start => {
+                self.loop_back();
+            }
+            (target, code) => self.exit(target, code),
+        }
+        self.builder.switch_to_block(fallthrough_block);
+        self.exit(Target::Address(next), EXIT_DONE);
+    }
+
+    /// Starts another pass through the block, unless it already made
+    /// `LOOP_ITERATIONS` and returns to the interpreter
+    fn loop_back(&mut self) {
+        let pass = self.completed.plus(Counts::INSTRUCTION);
+        for (variable, count) in self.looped.into_iter().zip(pass.0) {
+            let value = self.builder.use_var(variable);
+            let value = self.builder.ins().iadd_imm_u(value, i64::from(count));
+            self.builder.def_var(variable, value);
+        }
+        let iterations = self.builder.use_var(self.iterations);
+        let iterations = self.builder.ins().iadd_imm_u(iterations, 1);
+        self.builder.def_var(self.iterations, iterations);
+        let done = self.builder.ins().icmp_imm_u(
+            IntCC::UnsignedGreaterThanOrEqual,
+            iterations,
+            LOOP_ITERATIONS,
+        );
+        let exit_block = self.builder.create_block();
+        self.builder
+            .ins()
+            .brif(done, exit_block, &[], self.body, &[]);
+        self.builder.switch_to_block(exit_block);
+        self.leave(Target::Address(self.start), EXIT_DONE, Counts::default());
+    }
+
+    /// Computes `BaseR + offset6`, bailing out when it leaves memory
+    fn base_relative(&mut self, pc: u16, base: usize, raw: u16) -> Value {
+        let base = self.read(base);
+        let base = self.builder.ins().uextend(types::I32, base);
+        let address = self
+            .builder
+            .ins()
+            .iadd_imm_s(base, i64::from(sign_extend(raw, 6)));
+        let outside = self
+            .builder
+            .ins()
+            .icmp_imm_u(IntCC::UnsignedGreaterThan, address, 0xFFFF);
+        self.bail_out_if(outside, Target::Address(pc), EXIT_DEOPT);
+        self.builder.ins().ireduce(types::I16, address)
+    }
+
+    fn load(&mut self, pc: u16, address: Value) -> Value {
+        self.deopt_on_device(pc, address);
+        let pointer = self.word_pointer(address);
+        self.current = self.current.plus(Counts::READ);
+        self.builder
+            .ins()
+            .load(types::I16, MemFlagsData::trusted(), pointer, 0)
+    }
+
+    fn store(&mut self, pc: u16, next: u16, address: Value, value: Value) {
+        self.deopt_on_device(pc, address);
+        let pointer = self.word_pointer(address);
+        self.builder
+            .ins()
+            .store(MemFlagsData::trusted(), value, pointer, 0);
+        self.current = self.current.plus(Counts::WRITE);
+        // writing over cached code drops its block before going on
+        let index = self.builder.ins().uextend(self.pointer, address);
+        let covered = self.builder.ins().iadd(self.coverage, index);
+        let covered = self
+            .builder
+            .ins()
+            .load(types::I8, MemFlagsData::trusted(), covered, 0);
+        let written = self.builder.create_block();
+        let continue_block = self.builder.create_block();
+        self.builder
+            .ins()
+            .brif(covered, written, &[], continue_block, &[]);
+        self.builder.switch_to_block(written);
+        self.builder.ins().store(
+            MemFlagsData::trusted(),
+            address,
+            self.state,
+            field(offset_of!(JitState, written)),
+        );
+        self.exit(Target::Address(next), EXIT_CODE_WRITTEN);
+        self.builder.switch_to_block(continue_block);
+    }
+
+    fn deopt_on_device(&mut self, pc: u16, address: Value) {
+        let wide = self.builder.ins().uextend(types::I32, address);
+        let device = self.builder.ins().icmp_imm_u(
+            IntCC::UnsignedGreaterThanOrEqual,
+            wide,
+            i64::from(MMIO_START),
+        );
+        self.bail_out_if(device, Target::Address(pc), EXIT_DEOPT);
+    }
+
+    fn word_pointer(&mut self, address: Value) -> Value {
+        let index = self.builder.ins().uextend(self.pointer, address);
+        let offset = self.builder.ins().ishl_imm_u(index, 1);
+        self.builder.ins().iadd(self.memory, offset)
+    }
+
+    fn bail_out_if(&mut self, condition: Value, target: Target, code: u32) {
+        let exit_block = self.builder.create_block();
+        let continue_block = self.builder.create_block();
+        self.builder
+            .ins()
+            .brif(condition, exit_block, &[], continue_block, &[]);
+        self.builder.switch_to_block(exit_block);
+        self.exit(target, code);
+        self.builder.switch_to_block(continue_block);
+    }
+
+    fn deopt(&mut self, pc: u16) -> bool {
+        self.exit(Target::Address(pc), EXIT_DEOPT);
+        true
+    }
+
+    /// Leaves before the current instruction if `code` deoptimizes, or
+    /// after it
+    fn exit(&mut self, target: Target, code: u32) {
+        let counts = if code == EXIT_DEOPT {
+            self.completed
+        } else {
+            self.completed.plus(self.current).plus(Counts::INSTRUCTION)
+        };
+        self.leave(target, code, counts);
+    }
+
+    /// Writes the machine state and what the passes through the block and
+    /// `counts` did back, and returns `code`
+    fn leave(&mut self, target: Target, code: u32, counts: Counts) {
+        let trusted = MemFlagsData::trusted();
+        for ((variable, count), offset) in self.looped.into_iter().zip(counts.0).zip(COUNTERS) {
+            let value = self.builder.use_var(variable);
+            let value = self.builder.ins().iadd_imm_u(value, i64::from(count));
+            self.builder
+                .ins()
+                .store(trusted, value, self.state, field(offset));
+        }
+        for (index, variable) in self.registers.iter().enumerate() {
+            let value = self.builder.use_var(*variable);
+            let offset = offset_of!(JitState, registers).saturating_add(index.saturating_mul(2));
+            self.builder
+                .ins()
+                .store(trusted, value, self.state, field(offset));
+        }
+        let cond = self.builder.use_var(self.cond);
+        self.builder
+            .ins()
+            .store(trusted, cond, self.state, field(offset_of!(JitState, cond)));
+        let pc = match target {
+            Target::Address(address) => self.builder.ins().iconst(types::I16, i64::from(address)),
+            Target::Dynamic(value) => value,
+        };
+        self.builder
+            .ins()
+            .store(trusted, pc, self.state, field(offset_of!(JitState, pc)));
+        let code = self.builder.ins().iconst(types::I32, i64::from(code));
+        self.builder.ins().return_(&[code]);
+    }
+
+    fn constant(&mut self, value: i16) -> Value {
+        let bits = u16::from_ne_bytes(value.to_ne_bytes());
+        self.builder.ins().iconst(types::I16, i64::from(bits))
+    }
+
+    fn read(&mut self, register: usize) -> Value {
+        let variable = self.registers.get(register).copied().unwrap_or(self.cond);
+        self.builder.use_var(variable)
+    }
+
+    fn write(&mut self, register: usize, value: Value) {
+        if let Some(variable) = self.registers.get(register).copied() {
+            self.builder.def_var(variable, value);
+        }
+    }
+
+    fn write_with_flags(&mut self, register: usize, value: Value) {
+        self.write(register, value);
+        let zero = self.builder.ins().icmp_imm_u(IntCC::Equal, value, 0);
+        let negative = self
+            .builder
+            .ins()
+            .icmp_imm_s(IntCC::SignedLessThan, value, 0);
+        let n = self.builder.ins().iconst(types::I16, 4);
+        let z = self.builder.ins().iconst(types::I16, 2);
+        let p = self.builder.ins().iconst(types::I16, 1);
+        let sign = self.builder.ins().select(negative, n, p);
+        let cond = self.builder.ins().select(zero, z, sign);
+        self.builder.def_var(self.cond, cond);
+    }
+}
+
+/// Offset of a `JitState` field as a memory operand
+fn field(offset: usize) -> i32 {
+    i32::try_from(offset).unwrap_or(i32::MAX)
+}
@@ -0,0 +1,147 @@
+//! Keys on their way from the console to the program, translated by the
+//! key map and line ending of the console configuration, and the line
+//! endings the program prints.
+
+use alloc::{collections::VecDeque, vec, vec::Vec};
+
+use super::VM;
+use crate::{
+    console::{ConsoleConfig, KeyBinding, Newline},
+    errors::VMError,
+    scancode,
+};
+
+impl VM {
+    pub fn console_config(&self) -> &ConsoleConfig {
+        &self.console_config
+    }
+
+    /// Changes how keys and characters are translated, dropping the keys
+    /// already translated but not read by the program yet
+    pub fn set_console_config(&mut self, config: ConsoleConfig) {
+        self.console_config = config;
+        self.pending_keys.clear();
+        self.pending_scancodes.clear();
+        self.utf8.clear();
+    }
+
+    /// Next key for the program, waiting for one if `wait` is set. `None`
+    /// means no key is available, or that the break key was pressed.
+    pub(super) fn next_key(&mut self, wait: bool) -> Result<Option<u8>, VMError> {
+        loop {
+            if let Some(key) = self.pending_keys.pop_front() {
+                return Ok(Some(key));
+            }
+            let first = if wait {
+                match self.wait_for_key()? {
+                    Some(key) => key,
+                    None => return Ok(None),
+                }
+            } else {
+                match self.console.poll_key()? {
+                    Some(key) => key,
+                    None => return Ok(None),
+                }
+            };
+            let ConsoleConfig {
+                key_map, newline, ..
+            } = &self.console_config;
+            if key_map.is_empty() && (first != b'\n' || *newline == Newline::Lf) {
+                return Ok(Some(first));
+            }
+            let sequence = self.read_sequence(first)?;
+            let newline = self.console_config.newline;
+            match self.console_config.key_map.get(&sequence) {
+                Some(KeyBinding::Keys(keys)) => queue(&mut self.pending_keys, keys, newline),
+                Some(KeyBinding::Break) => {
+                    self.break_requested = true;
+                    self.running = false;
+                    return Ok(None);
+                }
+                None => queue(&mut self.pending_keys, &sequence, newline),
+            }
+        }
+    }
+
+    /// Next key event for KBDR in scancode mode, without waiting. `None`
+    /// means no key is available, or that the break key was pressed.
+    pub(super) fn next_scancode(&mut self) -> Result<Option<u16>, VMError> {
+        while self.pending_scancodes.is_empty() {
+            let Some(first) = self.console.poll_key()? else {
+                return Ok(None);
+            };
+            let sequence = self.read_sequence(first)?;
+            let events = match self.console_config.key_map.get(&sequence) {
+                Some(KeyBinding::Keys(keys)) => scancode::events(keys),
+                Some(KeyBinding::Break) => {
+                    self.break_requested = true;
+                    self.running = false;
+                    return Ok(None);
+                }
+                None => scancode::events(&sequence),
+            };
+            self.pending_scancodes.extend(events);
+        }
+        Ok(self.pending_scancodes.pop_front())
+    }
+
+    /// The key starting with `first`, reading the rest of its escape
+    /// sequence, which arrives together with its start
+    fn read_sequence(&mut self, first: u8) -> Result<Vec<u8>, VMError> {
+        let mut sequence = vec![first];
+        while self.console_config.key_map.extends(&sequence)
+            || (self.console_config.scancodes && scancode::extends(&sequence))
+        {
+            match self.console.poll_key()? {
+                Some(key) => sequence.push(key),
+                None => break,
+            }
+        }
+        Ok(sequence)
+    }
+
+    /// Blocks for a key, until the run times out if it has a deadline
+    fn wait_for_key(&mut self) -> Result<Option<u8>, VMError> {
+        #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+        if let Some(left) = self.time_left() {
+            let key = self.console.read_key_timeout(left)?;
+            if key.is_none() {
+                self.expire();
+            }
+            return Ok(key);
+        }
+        self.console.read_key().map(Some)
+    }
+
+    /// `character` as printed by the program, translated to the host's line
+    /// ending, or `None` if it is dropped
+    pub(super) fn host_character(&self, character: char) -> Option<char> {
+        match (self.console_config.newline, character) {
+            (Newline::Cr, '\r') => Some('\n'),
+            (Newline::CrLf, '\r') => None,
+            _ => Some(character),
+        }
+    }
+
+    /// Waits for a key for a trap. On the break key the PC goes back to the
+    /// trap, so it runs again once the program resumes, and `None` is
+    /// returned.
+    pub(super) fn trap_key(&mut self) -> Result<Option<u8>, VMError> {
+        let key = self.next_key(true)?;
+        if key.is_none() {
+            self.pc = self.pc.wrapping_sub(1);
+        }
+        Ok(key)
+    }
+}
+
+/// Queues `keys` for the program with the Enter key in its line ending
+fn queue(pending: &mut VecDeque<u8>, keys: &[u8], newline: Newline) {
+    for &key in keys {
+        match (key, newline) {
+            (b'\n', Newline::Cr) => pending.push_back(b'\r'),
+            (b'\n', Newline::CrLf) => pending.extend([b'\r', b'\n']),
+            _ => pending.push_back(key),
+        }
+    }
+}
@@ -0,0 +1,192 @@
+//! Counters of what a VM did, for hosts monitoring long-running programs.
+
+use alloc::boxed::Box;
+use core::time::Duration;
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+use std::time::Instant;
+
+use super::VM;
+use crate::cycles::CycleModel;
+
+/// Activity of a VM since it was created or `reset_metrics` was called
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// Instructions executed, whether run or stepped
+    pub instructions: u64,
+    /// Data reads by instructions and traps, including device registers.
+    /// Instruction fetches are not counted.
+    pub memory_reads: u64,
+    /// Writes to memory by instructions, traps and devices copying memory.
+    /// Device registers the VM updates as the program polls them are not
+    /// counted.
+    pub memory_writes: u64,
+    /// `TRAP` instructions executed
+    pub traps: u64,
+    /// Interrupts delivered to the program
+    pub interrupts: u64,
+    /// Cycles estimated by the cycle model, zero without one, see
+    /// `VM::set_cycle_model`
+    pub cycles: u64,
+    /// Wall-clock time spent in `run` and `run_with_fuel`. Always zero
+    /// without `std` and on the web, where there is no clock.
+    pub host_time: Duration,
+}
+
+/// Callback given the metrics every so many instructions, see
+/// `VM::set_metrics_callback`
+pub type MetricsCallback = Box<dyn FnMut(&Metrics)>;
+
+/// Metrics of a VM with the periodic callback and the running timer
+#[derive(Default)]
+pub(super) struct MetricsState {
+    pub(super) counters: Metrics,
+    callback: Option<(u64, MetricsCallback)>,
+    cycle_model: Option<CycleModel>,
+    #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+    run_started: Option<Instant>,
+}
+
+impl VM {
+    /// What the VM did so far, including the time of a run in progress
+    pub fn metrics(&self) -> Metrics {
+        let metrics = self.metrics.counters;
+        #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+        if let Some(started) = self.metrics.run_started {
+            return Metrics {
+                host_time: metrics.host_time.saturating_add(started.elapsed()),
+                ..metrics
+            };
+        }
+        metrics
+    }
+
+    /// Sets every counter back to zero
+    pub fn reset_metrics(&mut self) {
+        self.metrics.counters = Metrics::default();
+        self.sync_timer_cycles();
+        #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+        if self.metrics.run_started.is_some() {
+            self.metrics.run_started = Some(Instant::now());
+        }
+    }
+
+    /// Calls `callback` with the metrics after every `interval` executed
+    /// instructions, replacing any previous callback. An interval of 0
+    /// removes it.
+    pub fn set_metrics_callback<F>(&mut self, interval: u64, callback: F)
+    where
+        F: FnMut(&Metrics) + 'static,
+    {
+        self.metrics.callback = (interval > 0).then(|| {
+            let callback: MetricsCallback = Box::new(callback);
+            (interval, callback)
+        });
+    }
+
+    /// Counts an executed instruction for the metrics and the timer, calling
+    /// the metrics callback when its interval is reached
+    #[inline]
+    pub(super) fn retire(&mut self) {
+        self.advance_timer(1);
+        let counters = &mut self.metrics.counters;
+        counters.instructions = counters.instructions.wrapping_add(1);
+        let Some((interval, _)) = &self.metrics.callback else {
+            return;
+        };
+        if counters.instructions.checked_rem(*interval) != Some(0) {
+            return;
+        }
+        let metrics = self.metrics();
+        if let Some((_, callback)) = &mut self.metrics.callback {
+            callback(&metrics);
+        }
+    }
+
+    /// Counts what a run of compiled code did for the metrics and the
+    /// timer, calling the metrics callback if it passed the end of an
+    /// interval. Compiled code runs without a
+    /// cycle model, so it takes no cycles.
+    #[cfg(all(feature = "jit", not(feature = "threaded")))]
+    pub(super) fn retire_compiled(&mut self, instructions: u32, reads: u32, writes: u32) {
+        self.advance_timer(u64::from(instructions));
+        let counters = &mut self.metrics.counters;
+        let before = counters.instructions;
+        counters.instructions = before.wrapping_add(u64::from(instructions));
+        counters.memory_reads = counters.memory_reads.wrapping_add(u64::from(reads));
+        counters.memory_writes = counters.memory_writes.wrapping_add(u64::from(writes));
+        let Some((interval, _)) = &self.metrics.callback else {
+            return;
+        };
+        if before.checked_div(*interval) == counters.instructions.checked_div(*interval) {
+            return;
+        }
+        let metrics = self.metrics();
+        if let Some((_, callback)) = &mut self.metrics.callback {
+            callback(&metrics);
+        }
+    }
+
+    /// Estimates the cycles of every instruction executed from now on with
+    /// `model`, or stops with `None`. Execution falls back to one
+    /// instruction at a time while a model is set.
+    pub fn set_cycle_model(&mut self, model: Option<CycleModel>) {
+        self.metrics.cycle_model = model;
+    }
+
+    pub fn cycle_model(&self) -> Option<CycleModel> {
+        self.metrics.cycle_model
+    }
+
+    /// Adds the cost of the opcode of `raw`, whose memory accesses are
+    /// counted as they happen
+    #[inline]
+    pub(super) fn count_cycles(&mut self, raw: u16) {
+        if let Some(model) = &self.metrics.cycle_model {
+            let counters = &mut self.metrics.counters;
+            counters.cycles = counters
+                .cycles
+                .wrapping_add(u64::from(model.instruction(raw)));
+        }
+    }
+
+    /// Starts timing a run
+    pub(super) fn start_timer(&mut self) {
+        #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+        {
+            self.metrics.run_started = Some(Instant::now());
+        }
+    }
+
+    /// Adds the time since `start_timer` to the host time
+    pub(super) fn stop_timer(&mut self) {
+        #[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+        if let Some(started) = self.metrics.run_started.take() {
+            let counters = &mut self.metrics.counters;
+            counters.host_time = counters.host_time.saturating_add(started.elapsed());
+        }
+    }
+
+    #[inline]
+    pub(super) fn count_read(&mut self) {
+        let counters = &mut self.metrics.counters;
+        counters.memory_reads = counters.memory_reads.wrapping_add(1);
+        if let Some(model) = &self.metrics.cycle_model {
+            counters.cycles = counters.cycles.wrapping_add(u64::from(model.read));
+        }
+    }
+
+    #[inline]
+    pub(super) fn count_write(&mut self) {
+        let counters = &mut self.metrics.counters;
+        counters.memory_writes = counters.memory_writes.wrapping_add(1);
+        if let Some(model) = &self.metrics.cycle_model {
+            counters.cycles = counters.cycles.wrapping_add(u64::from(model.write));
+        }
+    }
+
+    #[inline]
+    pub(super) fn count_trap(&mut self) {
+        let counters = &mut self.metrics.counters;
+        counters.traps = counters.traps.wrapping_add(1);
+    }
+}
@@ -0,0 +1,259 @@
+//! Microsequenced execution for courses on the LC-3 microarchitecture:
+//! `micro_step` executes one state of the control unit of Patt and Patel's
+//! appendix C at a time, with the MAR, MDR, IR and BEN the ISA hides
+//! visible in `microstate`. Every instruction starts in state 18:
+//!
+//! ```text
+//! 18  MAR ← PC, PC ← PC + 1
+//! 33  MDR ← M[MAR]
+//! 35  IR ← MDR
+//! 32  BEN ← IR[11]·N + IR[10]·Z + IR[9]·P, then the state of the opcode
+//! ```
+//!
+//! The instruction then goes through the states of its opcode and returns
+//! to 18. Memory is always ready and interrupts are taken in state 18
+//! without going through the states that push the PSR and PC. The VM
+//! serves TRAP, RTI and the reserved opcode natively in their first state
+//! (15, 8 and 13) rather than through the operating system. Observers and
+//! checkers see whole instructions, as with `step`; only mix `micro_step`
+//! with `step` or `run` at instruction boundaries.
+
+use alloc::{format, string::String};
+
+use super::{dr, sr1, DISPATCH_TABLE, VM};
+use crate::{
+    errors::VMError,
+    instructions::{sign_extend, Opcode},
+};
+
+/// State every instruction starts in
+pub const FETCH_STATE: u8 = 18;
+
+/// Registers of the microarchitecture and the next control state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Microstate {
+    /// State `micro_step` executes next
+    pub state: u8,
+    pub mar: u16,
+    pub mdr: u16,
+    pub ir: u16,
+    /// Branch enable, computed in state 32
+    pub ben: bool,
+    /// Address of the instruction being executed
+    pub(super) pc: u16,
+}
+
+impl Default for Microstate {
+    fn default() -> Self {
+        Microstate {
+            state: FETCH_STATE,
+            mar: 0,
+            mdr: 0,
+            ir: 0,
+            ben: false,
+            pc: 0,
+        }
+    }
+}
+
+impl Microstate {
+    /// Whether the next state starts an instruction
+    pub fn at_instruction_boundary(&self) -> bool {
+        self.state == FETCH_STATE
+    }
+}
+
+/// A control state executed by `micro_step`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MicroStep {
+    pub state: u8,
+    /// The register transfers of the state, such as `MAR ← PC, PC ← PC + 1`
+    pub transfer: String,
+    /// The state after it, 18 once the instruction is complete
+    pub next: u8,
+}
+
+impl VM {
+    pub fn microstate(&self) -> Microstate {
+        self.micro
+    }
+
+    /// Executes the next state of the control unit
+    pub fn micro_step(&mut self) -> Result<MicroStep, VMError> {
+        if self.halted {
+            return Err(VMError::Debugger(String::from("The program halted")));
+        }
+        self.running = true;
+        self.undo.forget();
+        let state = self.micro.state;
+        let ir = self.micro.ir;
+        let (transfer, next) = match state {
+            FETCH_STATE => {
+                self.take_interrupt()?;
+                self.micro.pc = self.pc;
+                self.micro.mar = self.pc;
+                self.pc = self.next_pc(self.pc)?;
+                (String::from("MAR ← PC, PC ← PC + 1"), 33)
+            }
+            33 => {
+                self.micro.mdr = self.memory.read(self.micro.mar)?;
+                (String::from("MDR ← M[MAR]"), 35)
+            }
+            35 => {
+                self.micro.ir = self.micro.mdr;
+                (String::from("IR ← MDR"), 32)
+            }
+            32 => {
+                self.micro.ben = self.psr.branches(ir);
+                self.before_micro_instruction()?;
+                let opcode = u8::try_from(ir >> 12).unwrap_or_default();
+                (
+                    String::from("BEN ← IR[11]·N + IR[10]·Z + IR[9]·P, decode IR[15:12]"),
+                    opcode,
+                )
+            }
+            1 | 5 => {
+                let symbol = if state == 1 { "+" } else { "AND" };
+                let handler = if state == 1 { VM::op_add } else { VM::op_and };
+                handler(self, ir)?;
+                let operand = if ir & 0x20 == 0 {
+                    String::from("SR2")
+                } else {
+                    String::from("SEXT(IR[4:0])")
+                };
+                (format!("DR ← SR1 {symbol} {operand}, set CC"), FETCH_STATE)
+            }
+            9 => {
+                self.op_not(ir)?;
+                (String::from("DR ← NOT(SR), set CC"), FETCH_STATE)
+            }
+            14 => {
+                self.op_lea(ir)?;
+                let flags = if self.conformance.lea_sets_flags {
+                    ", set CC"
+                } else {
+                    ""
+                };
+                (format!("DR ← PC + off9{flags}"), FETCH_STATE)
+            }
+            2 | 3 | 10 | 11 => {
+                self.micro.mar = self.effective_address(self.pc, sign_extend(ir, 9))?;
+                let next = match state {
+                    2 => 25,
+                    3 => 23,
+                    10 => 24,
+                    _ => 29,
+                };
+                (String::from("MAR ← PC + off9"), next)
+            }
+            6 | 7 => {
+                let base = self.read_register(sr1(ir));
+                self.micro.mar = self.effective_address(base, sign_extend(ir, 6))?;
+                (
+                    String::from("MAR ← BaseR + off6"),
+                    if state == 6 { 25 } else { 23 },
+                )
+            }
+            24 | 29 => {
+                self.micro.mdr = self.read_memory(self.micro.mar)?;
+                (
+                    String::from("MDR ← M[MAR]"),
+                    if state == 24 { 26 } else { 31 },
+                )
+            }
+            26 | 31 => {
+                self.micro.mar = self.micro.mdr;
+                (String::from("MAR ← MDR"), if state == 26 { 25 } else { 23 })
+            }
+            25 => {
+                self.micro.mdr = self.read_memory(self.micro.mar)?;
+                (String::from("MDR ← M[MAR]"), 27)
+            }
+            27 => {
+                self.write_register_with_flags(dr(ir), self.micro.mdr);
+                (String::from("DR ← MDR, set CC"), FETCH_STATE)
+            }
+            23 => {
+                self.micro.mdr = self.read_register(dr(ir));
+                (String::from("MDR ← SR"), 16)
+            }
+            16 => {
+                self.write_memory(self.micro.mar, self.micro.mdr)?;
+                (String::from("M[MAR] ← MDR"), FETCH_STATE)
+            }
+            0 => {
+                let next = if self.micro.ben { 22 } else { FETCH_STATE };
+                (String::from("[BEN]"), next)
+            }
+            22 => {
+                self.pc = self.effective_address(self.pc, sign_extend(ir, 9))?;
+                (String::from("PC ← PC + off9"), FETCH_STATE)
+            }
+            4 => (
+                String::from("[IR[11]]"),
+                if ir & 0x800 == 0 { 20 } else { 21 },
+            ),
+            20 | 21 => {
+                self.op_jsr(ir)?;
+                let destination = if state == 20 { "BaseR" } else { "PC + off11" };
+                (format!("R7 ← PC, PC ← {destination}"), FETCH_STATE)
+            }
+            12 => {
+                self.op_jmp(ir)?;
+                (String::from("PC ← BaseR"), FETCH_STATE)
+            }
+            // TRAP, RTI and the reserved opcode
+            _ => {
+                let handler = DISPATCH_TABLE
+                    .get(usize::from(ir >> 12))
+                    .copied()
+                    .unwrap_or(VM::op_reserved);
+                handler(self, ir)?;
+                let transfer = match Opcode::from_instruction(ir) {
+                    Opcode::Trap => format!("R7 ← PC, the VM serves TRAP x{:02X}", ir & 0xFF),
+                    Opcode::Rti => String::from("PC ← M[R6], PSR ← M[R6 + 1], R6 ← R6 + 2"),
+                    _ => String::from("the VM handles the reserved opcode"),
+                };
+                (transfer, FETCH_STATE)
+            }
+        };
+        self.micro.state = next;
+        if next == FETCH_STATE && state != FETCH_STATE {
+            self.after_micro_instruction()?;
+        }
+        Ok(MicroStep {
+            state,
+            transfer,
+            next,
+        })
+    }
+
+    /// What `execute` does before an instruction, once it is decoded
+    fn before_micro_instruction(&mut self) -> Result<(), VMError> {
+        let (pc, raw) = (self.micro.pc, self.micro.ir);
+        if self.stack_checker.is_some() {
+            self.check_stack_before(pc, raw)?;
+        }
+        if self.observer.is_some() {
+            self.notify(|observer, vm| observer.before_instruction(vm, pc, raw));
+        }
+        Ok(())
+    }
+
+    /// What `execute` and `step` do after an instruction
+    fn after_micro_instruction(&mut self) -> Result<(), VMError> {
+        let (pc, raw) = (self.micro.pc, self.micro.ir);
+        if self.stack_checker.is_some() || self.loop_detector.is_some() {
+            self.check_after(pc, raw)?;
+        }
+        if self.observer.is_some() {
+            self.notify(|observer, vm| observer.after_instruction(vm, pc, raw));
+        }
+        self.count_cycles(raw);
+        self.retire();
+        if Opcode::from_instruction(raw) == Opcode::Trap {
+            self.console.flush()?;
+        }
+        Ok(())
+    }
+}
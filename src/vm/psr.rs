@@ -0,0 +1,129 @@
+//! The processor status register, the one place the privilege mode, the
+//! priority and the condition codes are kept. RTI restores it from the
+//! stack and programs read and write it at `PSR` like a device register.
+
+use core::fmt;
+
+/// Address of the memory-mapped processor status register
+pub const PSR: u16 = 0xFFFC;
+
+const USER_MODE: u16 = 1 << 15;
+const PRIORITY_SHIFT: u16 = 8;
+const PRIORITY_MASK: u16 = 0b111 << PRIORITY_SHIFT;
+const NEGATIVE: u16 = 0b100;
+const ZERO: u16 = 0b010;
+const POSITIVE: u16 = 0b001;
+const CONDITION_MASK: u16 = NEGATIVE | ZERO | POSITIVE;
+
+/// Bit 15 is set in user mode, bits [10:8] hold the priority and bits
+/// [2:0] the N, Z and P condition codes, exactly one of which is set
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Psr(u16);
+
+/// Supervisor mode at priority 0 with Z set, as the machine starts
+impl Default for Psr {
+    fn default() -> Self {
+        Psr(ZERO)
+    }
+}
+
+impl Psr {
+    /// Keeps the defined bits of `bits`, with the condition codes read as
+    /// RTI reads them: N or P when only that bit is set, Z otherwise
+    pub fn from_bits(bits: u16) -> Self {
+        let mut psr = Psr(bits & (USER_MODE | PRIORITY_MASK));
+        psr.set_condition_codes(bits);
+        psr
+    }
+
+    pub fn bits(self) -> u16 {
+        self.0
+    }
+
+    pub fn user_mode(self) -> bool {
+        self.0 & USER_MODE != 0
+    }
+
+    pub fn set_user_mode(&mut self, user: bool) {
+        self.0 = if user {
+            self.0 | USER_MODE
+        } else {
+            self.0 & !USER_MODE
+        };
+    }
+
+    /// Priority level of the running program, 0 to 7
+    pub fn priority(self) -> u8 {
+        u8::try_from((self.0 & PRIORITY_MASK) >> PRIORITY_SHIFT).unwrap_or_default()
+    }
+
+    /// Sets the priority level to the low three bits of `priority`
+    pub fn set_priority(&mut self, priority: u8) {
+        let field = (u16::from(priority) << PRIORITY_SHIFT) & PRIORITY_MASK;
+        self.0 = (self.0 & !PRIORITY_MASK) | field;
+    }
+
+    /// The N, Z and P bits, e.g. `0b100` after a negative result
+    pub fn condition_codes(self) -> u16 {
+        self.0 & CONDITION_MASK
+    }
+
+    /// Sets the condition codes to the low three bits of `codes`, read
+    /// like `from_bits` does
+    pub fn set_condition_codes(&mut self, codes: u16) {
+        let codes = match codes & CONDITION_MASK {
+            NEGATIVE => NEGATIVE,
+            POSITIVE => POSITIVE,
+            _ => ZERO,
+        };
+        self.0 = (self.0 & !CONDITION_MASK) | codes;
+    }
+
+    /// Sets the condition codes from the sign of `value`
+    pub fn set_condition(&mut self, value: u16) {
+        self.set_condition_codes(if value == 0 {
+            ZERO
+        } else if value >> 15 != 0 {
+            NEGATIVE
+        } else {
+            POSITIVE
+        });
+    }
+
+    pub fn negative(self) -> bool {
+        self.0 & NEGATIVE != 0
+    }
+
+    pub fn zero(self) -> bool {
+        self.0 & ZERO != 0
+    }
+
+    pub fn positive(self) -> bool {
+        self.0 & POSITIVE != 0
+    }
+
+    /// Whether the BR instruction `raw` branches, that is tests a set
+    /// condition code in its bits [11:9]
+    #[inline]
+    pub fn branches(self, raw: u16) -> bool {
+        (raw >> 9) & self.condition_codes() != 0
+    }
+
+    /// `N`, `Z` or `P`
+    pub fn condition_letter(self) -> char {
+        if self.negative() {
+            'N'
+        } else if self.positive() {
+            'P'
+        } else {
+            'Z'
+        }
+    }
+}
+
+/// `x8002`
+impl fmt::Display for Psr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "x{:04X}", self.0)
+    }
+}
@@ -0,0 +1,49 @@
+//! Random number generator device, so programs such as games get numbers
+//! that are reproducible from a seed:
+//!
+//! - `RNGDR` (xFE22): every read gives the next pseudo-random word.
+//!
+//! The numbers come from the SplitMix64 of `testgen`, so a seed gives the
+//! same sequence on every platform. A reset starts the sequence over.
+
+use super::VM;
+use crate::{errors::VMError, testgen::Rng};
+
+/// Random number data memory mapped register
+pub const RNGDR: u16 = 0xFE22;
+
+/// State of the generator
+#[derive(Debug, Clone)]
+pub(super) struct Random {
+    seed: u64,
+    numbers: Rng,
+}
+
+impl VM {
+    /// Attaches the random number generator at `RNGDR` with `seed`, see
+    /// `rng`, failing if another device is mapped there
+    pub fn enable_rng(&mut self, seed: u64) -> Result<(), VMError> {
+        self.memory.map_device_ranges("rng", &[(RNGDR, RNGDR)])?;
+        self.rng = Some(Random {
+            seed,
+            numbers: Rng::new(seed),
+        });
+        Ok(())
+    }
+
+    /// Starts the sequence over from the seed
+    pub(super) fn reseed_rng(&mut self) {
+        if let Some(random) = &mut self.rng {
+            random.numbers = Rng::new(random.seed);
+        }
+    }
+
+    /// Puts the next number in `RNGDR`
+    pub(super) fn poll_rng(&mut self) -> Result<(), VMError> {
+        let Some(random) = &mut self.rng else {
+            return Ok(());
+        };
+        let [low, high, ..] = random.numbers.next_u64().to_le_bytes();
+        self.memory.write(RNGDR, u16::from_le_bytes([low, high]))
+    }
+}
@@ -0,0 +1,297 @@
+//! Snapshots of the machine state that tests compare as a whole
+
+use alloc::{collections::BTreeMap, format, string::String, vec::Vec};
+use core::fmt;
+
+use super::{Psr, REGISTER_COUNT, VM};
+use crate::{errors::VMError, register::Register};
+
+/// Registers, PSR, PC, run state and the words of the memory ranges
+/// selected when the snapshot was taken
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VmState {
+    pub pc: u16,
+    pub registers: [u16; REGISTER_COUNT],
+    pub psr: Psr,
+    /// Only set for VMs running in the background, see `VM::spawn`
+    pub paused: bool,
+    pub halted: bool,
+    /// Captured words by address
+    pub memory: BTreeMap<u16, u16>,
+}
+
+impl VmState {
+    /// Adds `words` stored from `start` on to the captured memory
+    pub fn with_memory(mut self, start: u16, words: &[u16]) -> Self {
+        let mut address = start;
+        for &word in words {
+            self.memory.insert(address, word);
+            address = address.wrapping_add(1);
+        }
+        self
+    }
+
+    /// Describes every field where `self` differs from `expected`, one line
+    /// each, so failed assertions show what changed rather than two dumps
+    pub fn differences(&self, expected: &VmState) -> Vec<String> {
+        let mut differences = Vec::new();
+        let mut differ = |what: String, actual: String, expected: String| {
+            if actual != expected {
+                differences.push(format!("{what}: expected {expected}, got {actual}"));
+            }
+        };
+        differ(String::from("PC"), hex(self.pc), hex(expected.pc));
+        for (number, (actual, wanted)) in self.registers.iter().zip(expected.registers).enumerate()
+        {
+            differ(format!("R{number}"), hex(*actual), hex(wanted));
+        }
+        differ(
+            String::from("PSR"),
+            hex(self.psr.bits()),
+            hex(expected.psr.bits()),
+        );
+        differ(
+            String::from("paused"),
+            format!("{}", self.paused),
+            format!("{}", expected.paused),
+        );
+        differ(
+            String::from("halted"),
+            format!("{}", self.halted),
+            format!("{}", expected.halted),
+        );
+        let captured = |state: &VmState, address: &u16| {
+            state
+                .memory
+                .get(address)
+                .map_or_else(|| String::from("not captured"), |word| hex(*word))
+        };
+        let mut addresses: Vec<&u16> = self.memory.keys().chain(expected.memory.keys()).collect();
+        addresses.sort_unstable();
+        addresses.dedup();
+        for address in addresses {
+            differ(
+                hex(*address),
+                captured(self, address),
+                captured(expected, address),
+            );
+        }
+        differences
+    }
+
+    /// What changed from `self` to `other`: the PC, PSR, registers
+    /// and the memory words captured in both states that differ
+    pub fn diff(&self, other: &VmState) -> StateDiff {
+        let registers = (0..)
+            .map_while(Register::new)
+            .zip(self.registers.iter().zip(other.registers))
+            .filter(|(_, (before, after))| **before != *after)
+            .map(|(register, (&before, after))| RegisterChange {
+                register,
+                before,
+                after,
+            })
+            .collect();
+        let memory = self
+            .memory
+            .iter()
+            .filter_map(|(&address, &before)| {
+                let after = *other.memory.get(&address)?;
+                (before != after).then_some(MemoryChange {
+                    address,
+                    before,
+                    after,
+                })
+            })
+            .collect();
+        StateDiff {
+            pc: (self.pc != other.pc).then_some((self.pc, other.pc)),
+            psr: (self.psr != other.psr).then_some((self.psr, other.psr)),
+            registers,
+            memory,
+        }
+    }
+}
+
+/// A register holding another value in the later of two states
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterChange {
+    pub register: Register,
+    pub before: u16,
+    pub after: u16,
+}
+
+/// A memory word holding another value in the later of two states
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryChange {
+    pub address: u16,
+    pub before: u16,
+    pub after: u16,
+}
+
+/// Differences between two states, see `VmState::diff`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    /// PC before and after, if it moved
+    pub pc: Option<(u16, u16)>,
+    pub psr: Option<(Psr, Psr)>,
+    pub registers: Vec<RegisterChange>,
+    /// Changed words by increasing address
+    pub memory: Vec<MemoryChange>,
+}
+
+impl StateDiff {
+    pub fn is_empty(&self) -> bool {
+        self.pc.is_none()
+            && self.psr.is_none()
+            && self.registers.is_empty()
+            && self.memory.is_empty()
+    }
+}
+
+/// One line per change, such as `R1 x0000 -> x0003` or
+/// `x4000 x0000 -> x0005`
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut lines = Vec::new();
+        if let Some((before, after)) = self.pc {
+            lines.push(format!("PC {} -> {}", hex(before), hex(after)));
+        }
+        if let Some((before, after)) = self.psr {
+            lines.push(format!("PSR {before} -> {after}"));
+        }
+        for change in &self.registers {
+            lines.push(format!(
+                "{} {} -> {}",
+                change.register,
+                hex(change.before),
+                hex(change.after)
+            ));
+        }
+        for change in &self.memory {
+            lines.push(format!(
+                "{} {} -> {}",
+                hex(change.address),
+                hex(change.before),
+                hex(change.after)
+            ));
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+fn hex(word: u16) -> String {
+    format!("x{word:04X}")
+}
+
+impl VM {
+    /// Registers, PSR, PC and run state, without any memory
+    pub fn state(&self) -> VmState {
+        VmState {
+            pc: self.pc,
+            registers: self.registers,
+            psr: self.psr,
+            paused: false,
+            halted: self.halted,
+            memory: BTreeMap::new(),
+        }
+    }
+
+    /// `state` plus the memory ranges given as `(start, count)` pairs
+    pub fn snapshot(&self, ranges: &[(u16, usize)]) -> VmState {
+        let mut state = self.state();
+        for &(start, count) in ranges {
+            let words: Vec<u16> = self.memory().read_words(start, count).collect();
+            state = state.with_memory(start, &words);
+        }
+        state
+    }
+
+    /// Puts back the registers, PSR, PC and run state of `state` and
+    /// the memory words it captured. Devices and consoles keep their state,
+    /// and the words are not counted or observed as program writes.
+    pub fn restore(&mut self, state: &VmState) -> Result<(), VMError> {
+        for (&address, &word) in &state.memory {
+            if self.memory.peek(address) != word {
+                self.restore_word(address, word)?;
+            }
+        }
+        self.registers = state.registers;
+        self.psr = state.psr;
+        self.pc = state.pc;
+        self.halted = state.halted;
+        self.undo.forget();
+        Ok(())
+    }
+}
+
+/// Named states a machine can be rolled back to, so a region of a program
+/// can be executed again without restarting it
+#[derive(Debug, Clone, Default)]
+pub struct Checkpoints {
+    states: BTreeMap<String, VmState>,
+}
+
+impl Checkpoints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Saves the registers and all RAM of `vm` as `name`, replacing an
+    /// earlier checkpoint with that name
+    pub fn save(&mut self, name: &str, vm: &VM) {
+        let state = vm.snapshot(&[(0, vm.memory().size())]);
+        self.states.insert(String::from(name), state);
+    }
+
+    /// Rolls `vm` back to checkpoint `name`, which is kept
+    pub fn restore(&self, name: &str, vm: &mut VM) -> Result<(), VMError> {
+        let state = self
+            .get(name)
+            .ok_or_else(|| VMError::Debugger(format!("No checkpoint is named {name}")))?;
+        vm.restore(state)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&VmState> {
+        self.states.get(name)
+    }
+
+    /// Forgets checkpoint `name`, returning whether it existed
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.states.remove(name).is_some()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.states.keys().map(String::as_str)
+    }
+}
+
+/// Asserts that two `VmState`s are equal, listing the differing registers,
+/// flags and memory words when they are not
+///
+/// ```
+/// use lc3_vm::{assert_state_eq, vm::{VmState, VM}};
+///
+/// let vm = VM::new();
+/// let expected = VmState { pc: 0x3000, ..VmState::default() }.with_memory(0x3000, &[0]);
+/// assert_state_eq!(vm.snapshot(&[(0x3000, 1)]), expected);
+/// ```
+#[macro_export]
+macro_rules! assert_state_eq {
+    ($actual:expr, $expected:expr $(,)?) => {
+        $crate::assert_state_eq!($actual, $expected, "VM states differ")
+    };
+    ($actual:expr, $expected:expr, $($message:tt)+) => {
+        match (&$actual, &$expected) {
+            (actual, expected) => {
+                let differences = $crate::vm::VmState::differences(actual, expected);
+                assert!(
+                    differences.is_empty(),
+                    "{}:\n  {}",
+                    format_args!($($message)+),
+                    differences.join("\n  ")
+                );
+            }
+        }
+    };
+}
@@ -0,0 +1,66 @@
+//! Threaded-code backend: every memory word is decoded once into a handler
+//! pointer plus its pre-extracted operands, so the hot loop only performs an
+//! indirect call per instruction. Decoded words are dropped when written.
+
+use alloc::{vec, vec::Vec};
+
+use super::{
+    decoded::{decode, DecodedOp},
+    VM,
+};
+use crate::{errors::VMError, memory::MEMORY_SIZE};
+
+/// Decoded instructions indexed by address, filled lazily
+pub(super) struct ThreadedCode {
+    ops: Vec<Option<DecodedOp>>,
+}
+
+impl ThreadedCode {
+    pub(super) fn new() -> Self {
+        ThreadedCode { ops: Vec::new() }
+    }
+
+    /// Drops the decoded instruction at `address` after it was written
+    #[inline]
+    pub(super) fn invalidate(&mut self, address: u16) {
+        if let Some(op) = self.ops.get_mut(usize::from(address)) {
+            *op = None;
+        }
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.ops.clear();
+    }
+}
+
+impl VM {
+    pub(super) fn run_threaded(&mut self) -> Result<(), VMError> {
+        if self.threaded.ops.is_empty() {
+            self.threaded.ops = vec![None; MEMORY_SIZE];
+        }
+        while self.running {
+            self.take_interrupt()?;
+            let pc = self.pc;
+            let op = match self.threaded.ops.get(usize::from(pc)).copied().flatten() {
+                Some(op) => op,
+                None => self.decode_at(pc)?,
+            };
+            self.pc = self.next_pc(pc)?;
+            if self.inspects_instructions() {
+                self.execute(pc, op.raw)?;
+            } else {
+                op.execute(self)?;
+            }
+            self.tick();
+        }
+        Ok(())
+    }
+
+    fn decode_at(&mut self, pc: u16) -> Result<DecodedOp, VMError> {
+        let op = decode(self.memory.read(pc)?);
+        if let Some(slot) = self.threaded.ops.get_mut(usize::from(pc)) {
+            *slot = Some(op);
+        }
+        Ok(op)
+    }
+}
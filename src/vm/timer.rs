@@ -0,0 +1,108 @@
+//! Interval timer, telling programs that a number of instructions went by
+//! so games and schedulers can pace themselves without counting loops:
+//!
+//! - `TMSR` (xFE1C): bit 15 is set once the period elapsed since the timer
+//!   last fired. Reading it with bit 15 set fires the timer, starting the
+//!   next period.
+//! - `TMPR` (xFE1E): the period in instructions, or in cycles under a cycle
+//!   model. Writing it changes the period and starts it over; 0 stops the
+//!   timer.
+//!
+//! Time is counted in instructions executed, or in the cycles estimated by
+//! the cycle model set with `VM::set_cycle_model`, so runs are reproducible.
+//! The timer keeps its own count, which `reset_metrics` leaves alone.
+
+use super::VM;
+use crate::errors::VMError;
+
+/// Status memory mapped register
+pub const TMSR: u16 = 0xFE1C;
+/// Period memory mapped register
+pub const TMPR: u16 = 0xFE1E;
+
+/// State of the timer
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Timer {
+    period: u16,
+    /// Instructions or cycles since the current period started
+    elapsed: u64,
+    /// Cycles the metrics counted when the timer last advanced
+    cycles: u64,
+}
+
+impl VM {
+    /// Attaches the interval timer at `TMSR`-`TMPR` firing every `period`
+    /// instructions, see `timer`, failing if another device is mapped there
+    pub fn enable_timer(&mut self, period: u16) -> Result<(), VMError> {
+        self.memory.map_device_ranges("timer", &[(TMSR, TMPR)])?;
+        self.timer = Some(Timer {
+            period,
+            elapsed: 0,
+            cycles: self.metrics.counters.cycles,
+        });
+        self.memory.write(TMPR, period)
+    }
+
+    /// Starts the period over, keeping its length
+    pub(super) fn restart_timer(&mut self) {
+        let cycles = self.metrics.counters.cycles;
+        if let Some(timer) = &mut self.timer {
+            timer.elapsed = 0;
+            timer.cycles = cycles;
+        }
+    }
+
+    /// Counts `instructions` more executed towards the period, or the
+    /// cycles the metrics counted since under a cycle model
+    #[inline]
+    pub(super) fn advance_timer(&mut self, instructions: u64) {
+        let cycles = self.metrics.counters.cycles;
+        let modelled = self.cycle_model().is_some();
+        let Some(timer) = &mut self.timer else {
+            return;
+        };
+        let spent = if modelled {
+            cycles.wrapping_sub(timer.cycles)
+        } else {
+            instructions
+        };
+        timer.cycles = cycles;
+        timer.elapsed = timer.elapsed.saturating_add(spent);
+    }
+
+    /// Counts the cycles from the current count of the metrics, after it
+    /// was reset
+    pub(super) fn sync_timer_cycles(&mut self) {
+        let cycles = self.metrics.counters.cycles;
+        if let Some(timer) = &mut self.timer {
+            timer.cycles = cycles;
+        }
+    }
+
+    /// Shows in `TMSR` or `TMPR` whether the period elapsed, firing the
+    /// timer if it did, or the period
+    pub(super) fn poll_timer(&mut self, address: u16) -> Result<(), VMError> {
+        let Some(timer) = &mut self.timer else {
+            return Ok(());
+        };
+        match address {
+            TMSR => {
+                let fired = timer.period != 0 && timer.elapsed >= u64::from(timer.period);
+                if fired {
+                    timer.elapsed = 0;
+                }
+                self.memory.write(TMSR, if fired { 1 << 15 } else { 0 })
+            }
+            TMPR => self.memory.write(TMPR, timer.period),
+            _ => Ok(()),
+        }
+    }
+
+    /// Takes the period the program wrote to `TMPR`
+    pub(super) fn set_timer_period(&mut self, period: u16) {
+        self.restart_timer();
+        if let Some(timer) = &mut self.timer {
+            timer.period = period;
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Taking back the last instruction a debugger stepped, for the common
+//! "stepped one too far" case without recording the whole execution. While
+//! enabled, `step` keeps the registers, PSR and PC from before the
+//! instruction and the words it wrote, and `undo` puts them back. Only one
+//! instruction is kept, so undoing twice in a row fails.
+
+use alloc::{string::String, vec::Vec};
+
+use super::{MemoryChange, VmState, VM};
+use crate::errors::VMError;
+
+/// What the last stepped instruction changed
+#[derive(Debug, Clone)]
+struct LastStep {
+    before: VmState,
+    /// Words written, in order
+    writes: Vec<MemoryChange>,
+}
+
+/// Recording for `undo`
+#[derive(Debug, Clone, Default)]
+pub(super) struct Undo {
+    enabled: bool,
+    /// Words written by the instruction being stepped
+    writes: Option<Vec<MemoryChange>>,
+    last: Option<LastStep>,
+}
+
+impl Undo {
+    /// Whether an instruction is being stepped with undo enabled
+    #[inline]
+    pub(super) fn recording(&self) -> bool {
+        self.writes.is_some()
+    }
+
+    pub(super) fn record(&mut self, change: MemoryChange) {
+        if let Some(writes) = &mut self.writes {
+            writes.push(change);
+        }
+    }
+
+    /// Drops the last step, once something else changed the machine
+    pub(super) fn forget(&mut self) {
+        self.last = None;
+    }
+}
+
+impl VM {
+    /// Records what every `step` changes so `undo` can take the last one
+    /// back. Off by default; `run` and `micro_step` are never recorded.
+    pub fn set_undo(&mut self, enabled: bool) {
+        self.undo.enabled = enabled;
+        if !enabled {
+            self.undo.forget();
+        }
+    }
+
+    pub fn undo_enabled(&self) -> bool {
+        self.undo.enabled
+    }
+
+    /// Whether `undo` has an instruction to take back
+    pub fn can_undo(&self) -> bool {
+        self.undo.last.is_some()
+    }
+
+    /// Restores the registers, PSR, PC and every word written as they were
+    /// before the last stepped instruction, un-halting the machine if it was
+    /// HALT. Output printed, keys read and the metrics stay as they are.
+    pub fn undo(&mut self) -> Result<(), VMError> {
+        let last = self
+            .undo
+            .last
+            .take()
+            .ok_or_else(|| VMError::Debugger(String::from("No instruction to undo")))?;
+        for change in last.writes.iter().rev() {
+            self.restore_word(change.address, change.before)?;
+        }
+        self.restore(&last.before)
+    }
+
+    /// Puts back a word as it was, without the effects of a program
+    /// writing it: devices, metrics and observers never see it. Code
+    /// decoded from it is dropped.
+    pub(super) fn restore_word(&mut self, address: u16, value: u16) -> Result<(), VMError> {
+        #[cfg(not(feature = "threaded"))]
+        self.blocks.invalidate(address);
+        #[cfg(feature = "threaded")]
+        self.threaded.invalidate(address);
+        self.memory.write(address, value)
+    }
+
+    /// Starts recording the instruction `step` is about to execute, giving
+    /// the state to restore
+    pub(super) fn begin_undo_step(&mut self) -> Option<VmState> {
+        if !self.undo.enabled || self.halted {
+            return None;
+        }
+        self.undo.last = None;
+        self.undo.writes = Some(Vec::new());
+        Some(self.state())
+    }
+
+    /// Keeps what the instruction changed if it executed
+    pub(super) fn end_undo_step(&mut self, before: VmState, executed: bool) {
+        let writes = self.undo.writes.take().unwrap_or_default();
+        if executed {
+            self.undo.last = Some(LastStep { before, writes });
+        }
+    }
+}
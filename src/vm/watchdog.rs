@@ -0,0 +1,100 @@
+//! Host-time limit on `run`, for programs that wait for a key forever or
+//! spend their time on I/O, where an instruction limit does not help.
+
+use std::time::{Duration, Instant};
+
+use alloc::{format, string::String};
+
+use super::VM;
+use crate::errors::VMError;
+
+/// Instructions executed between two looks at the clock
+const CHECK_INTERVAL: u32 = 4096;
+
+#[derive(Debug, Clone)]
+pub(super) struct Watchdog {
+    timeout: Duration,
+    /// When the current run times out
+    deadline: Option<Instant>,
+    countdown: u32,
+    expired: bool,
+}
+
+impl VM {
+    /// Makes `run` fail once it took longer than `timeout` of host time,
+    /// waiting for keys included, or removes the limit with `None`
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) -> Result<(), VMError> {
+        if timeout == Some(Duration::ZERO) {
+            return Err(VMError::InvalidArgument(String::from(
+                "The timeout must be positive",
+            )));
+        }
+        self.watchdog = timeout.map(|timeout| Watchdog {
+            timeout,
+            deadline: None,
+            countdown: CHECK_INTERVAL,
+            expired: false,
+        });
+        Ok(())
+    }
+
+    /// Starts counting the time of a run
+    pub(super) fn arm_watchdog(&mut self) {
+        if let Some(watchdog) = &mut self.watchdog {
+            watchdog.deadline = Instant::now().checked_add(watchdog.timeout);
+            watchdog.countdown = CHECK_INTERVAL;
+            watchdog.expired = false;
+        }
+    }
+
+    /// Stops counting, returning the error of a run that timed out
+    pub(super) fn disarm_watchdog(&mut self) -> Option<VMError> {
+        let watchdog = self.watchdog.as_mut()?;
+        watchdog.deadline = None;
+        if !core::mem::take(&mut watchdog.expired) {
+            return None;
+        }
+        Some(VMError::Timeout(format!(
+            "Timed out after {:?} at PC=x{:04X}",
+            watchdog.timeout, self.pc
+        )))
+    }
+
+    /// Whether a run with a deadline is in progress
+    #[cfg(all(feature = "jit", not(feature = "threaded")))]
+    #[inline]
+    pub(super) fn watching(&self) -> bool {
+        self.watchdog
+            .as_ref()
+            .is_some_and(|watchdog| watchdog.deadline.is_some())
+    }
+
+    /// Stops the run once its time is up, looking at the clock only every
+    /// few thousand instructions
+    #[inline]
+    pub(super) fn watch(&mut self) {
+        let Some(watchdog) = &mut self.watchdog else {
+            return;
+        };
+        watchdog.countdown = watchdog.countdown.saturating_sub(1);
+        if watchdog.countdown == 0 {
+            watchdog.countdown = CHECK_INTERVAL;
+            if self.time_left() == Some(Duration::ZERO) {
+                self.expire();
+            }
+        }
+    }
+
+    /// Time left before the run times out, if it has a deadline
+    pub(super) fn time_left(&self) -> Option<Duration> {
+        let deadline = self.watchdog.as_ref()?.deadline?;
+        Some(deadline.saturating_duration_since(Instant::now()))
+    }
+
+    pub(super) fn expire(&mut self) {
+        if let Some(watchdog) = &mut self.watchdog {
+            watchdog.expired = true;
+        }
+        self.running = false;
+    }
+}
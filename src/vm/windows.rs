@@ -0,0 +1,166 @@
+//! Shared memory windows, regions of RAM the host reads and writes in place
+//! to exchange bulk data with the program, instead of a word at a time
+//! through traps or `poke`:
+//!
+//! ```
+//! use lc3_vm::vm::VM;
+//!
+//! let mut vm = VM::new();
+//! vm.reserve_window("samples", 0x5000, 256).unwrap();
+//! vm.window_mut("samples").unwrap().copy_from_slice(&[7; 256]);
+//! // ...run the program, then read what it wrote back
+//! let window = vm.window_mut("samples").unwrap();
+//! if let Some(written) = window.changed() {
+//!     println!("the program wrote x{:04X}-x{:04X}", written.start(), written.end());
+//! }
+//! ```
+//!
+//! A window is mapped like a device, so nothing else can be attached over
+//! it. Code the program runs from a window is decoded again after the host
+//! changed it. While windows exist, the JIT leaves compiled code alone so
+//! every write of the program is noticed.
+
+use alloc::{format, string::String};
+use core::ops::{Deref, DerefMut, RangeInclusive};
+
+use super::VM;
+use crate::errors::VMError;
+
+/// A window and the words the program wrote in it
+#[derive(Debug, Clone)]
+pub(super) struct Window {
+    name: String,
+    start: u16,
+    length: u16,
+    /// Lowest and highest address written since the host last opened it
+    changed: Option<(u16, u16)>,
+}
+
+impl Window {
+    fn end(&self) -> u16 {
+        self.start.wrapping_add(self.length).wrapping_sub(1)
+    }
+}
+
+/// The words of a window, borrowed from the VM, see `VM::window_mut`
+pub struct WindowMut<'a> {
+    vm: &'a mut VM,
+    start: u16,
+    length: u16,
+    changed: Option<RangeInclusive<u16>>,
+}
+
+impl WindowMut<'_> {
+    /// Address of the first word
+    pub fn start(&self) -> u16 {
+        self.start
+    }
+
+    /// Addresses from the lowest to the highest word written since the
+    /// window was last opened, if any
+    pub fn changed(&self) -> Option<RangeInclusive<u16>> {
+        self.changed.clone()
+    }
+}
+
+impl Deref for WindowMut<'_> {
+    type Target = [u16];
+
+    fn deref(&self) -> &[u16] {
+        self.vm
+            .memory
+            .words(self.start, self.length)
+            .unwrap_or_default()
+    }
+}
+
+impl DerefMut for WindowMut<'_> {
+    fn deref_mut(&mut self) -> &mut [u16] {
+        self.vm
+            .memory
+            .words_mut(self.start, self.length)
+            .unwrap_or_default()
+    }
+}
+
+/// Decodes again the code the host may have changed
+impl Drop for WindowMut<'_> {
+    fn drop(&mut self) {
+        for offset in 0..self.length {
+            let address = self.start.wrapping_add(offset);
+            #[cfg(not(feature = "threaded"))]
+            self.vm.blocks.invalidate(address);
+            #[cfg(feature = "threaded")]
+            self.vm.threaded.invalidate(address);
+        }
+    }
+}
+
+impl VM {
+    /// Reserves the `length` words of RAM from `start` as the window
+    /// `name`, failing unless they are RAM no device or other window uses.
+    /// A window with the same name is replaced.
+    pub fn reserve_window(&mut self, name: &str, start: u16, length: u16) -> Result<(), VMError> {
+        let end = usize::from(start).saturating_add(usize::from(length));
+        if length == 0 || end > self.memory.size() {
+            return Err(VMError::InvalidArgument(format!(
+                "Window `{name}` of {length} words at x{start:04X} is not in the RAM"
+            )));
+        }
+        let window = Window {
+            name: String::from(name),
+            start,
+            length,
+            changed: None,
+        };
+        self.memory
+            .map_device_ranges(name, &[(start, window.end())])?;
+        self.remove_window(name);
+        self.windows.push(window);
+        Ok(())
+    }
+
+    /// The words of the window `name`, to read or change in place. Opening
+    /// it forgets which words the program wrote before.
+    pub fn window_mut(&mut self, name: &str) -> Result<WindowMut<'_>, VMError> {
+        let window = self
+            .windows
+            .iter_mut()
+            .find(|window| window.name == name)
+            .ok_or_else(|| VMError::InvalidArgument(format!("No window is named `{name}`")))?;
+        let (start, length) = (window.start, window.length);
+        let changed = window.changed.take().map(|(low, high)| low..=high);
+        self.memory.unshare();
+        Ok(WindowMut {
+            vm: self,
+            start,
+            length,
+            changed,
+        })
+    }
+
+    /// Drops the window called `name`, if there is one
+    pub(super) fn remove_window(&mut self, name: &str) {
+        self.windows.retain(|window| window.name != name);
+    }
+
+    /// Whether the JIT has to leave writes to the interpreter
+    #[cfg(all(feature = "jit", not(feature = "threaded")))]
+    pub(super) fn has_windows(&self) -> bool {
+        !self.windows.is_empty()
+    }
+
+    /// Remembers that `address` changed if it is in a window
+    pub(super) fn note_window_write(&mut self, address: u16) {
+        let window = self
+            .windows
+            .iter_mut()
+            .find(|window| (window.start..=window.end()).contains(&address));
+        if let Some(window) = window {
+            window.changed = Some(match window.changed {
+                Some((low, high)) => (low.min(address), high.max(address)),
+                None => (address, address),
+            });
+        }
+    }
+}
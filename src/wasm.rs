@@ -0,0 +1,100 @@
+//! JavaScript bindings for running the VM in a browser. Build with
+//! `cargo rustc --lib --crate-type cdylib --target wasm32-unknown-unknown
+//! --features wasm` and generate the glue with `wasm-bindgen`.
+//!
+//! ```js
+//! const vm = new Lc3Vm();
+//! vm.loadImage(new Uint8Array(await (await fetch("game.obj")).arrayBuffer()));
+//! function frame() {
+//!     const running = vm.step(100000);
+//!     output.textContent += vm.takeOutput();
+//!     if (running) requestAnimationFrame(frame);
+//! }
+//! document.onkeydown = (event) => vm.keyPressed(event.key.charCodeAt(0));
+//! ```
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    console::SharedConsole,
+    errors::VMError,
+    register::Register,
+    vm::{StopReason, VM},
+};
+
+#[wasm_bindgen]
+pub struct Lc3Vm {
+    vm: VM,
+    console: SharedConsole,
+}
+
+impl Default for Lc3Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl Lc3Vm {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Lc3Vm {
+        let console = SharedConsole::new();
+        let mut vm = VM::new();
+        vm.set_console(Box::new(console.clone()));
+        Lc3Vm { vm, console }
+    }
+
+    /// Loads a big endian object file whose first word is the origin
+    #[wasm_bindgen(js_name = loadImage)]
+    pub fn load_image(&mut self, bytes: &[u8]) -> Result<(), JsError> {
+        self.vm.read_image_bytes(bytes).map_err(js_error)
+    }
+
+    /// Executes up to `count` instructions, stopping early when the program
+    /// waits for a key that has not been pressed. Returns false once the
+    /// program halted.
+    pub fn step(&mut self, count: u32) -> Result<bool, JsError> {
+        for _ in 0..count {
+            if self.vm.waits_for_key() && !self.console.has_input() {
+                break;
+            }
+            if self.vm.step().map_err(js_error)? == StopReason::Halted {
+                return Ok(false);
+            }
+        }
+        Ok(!self.vm.is_halted())
+    }
+
+    /// Queues a key for GETC, IN and the keyboard registers
+    #[wasm_bindgen(js_name = keyPressed)]
+    pub fn key_pressed(&mut self, key: u8) {
+        self.console.push_input([key]);
+    }
+
+    /// Returns the output written since the last call
+    #[wasm_bindgen(js_name = takeOutput)]
+    pub fn take_output(&mut self) -> String {
+        self.console.take_output()
+    }
+
+    /// Whether the program is blocked on GETC or IN without a pending key
+    #[wasm_bindgen(js_name = waitingForKey)]
+    pub fn waiting_for_key(&self) -> bool {
+        self.vm.waits_for_key() && !self.console.has_input()
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.vm.pc()
+    }
+
+    /// Value of register R`number`, or 0 if there is no such register
+    pub fn register(&self, number: u16) -> u16 {
+        Register::new(number)
+            .map(|register| self.vm.register(register))
+            .unwrap_or_default()
+    }
+}
+
+fn js_error(error: VMError) -> JsError {
+    JsError::new(&format!("{error:#}"))
+}
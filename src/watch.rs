@@ -0,0 +1,234 @@
+//! Watch expressions such as `R2`, `mem[x4000]` or `mem[R6+1]`, evaluated
+//! against a stopped machine, and watchpoints that notice when their value
+//! changes.
+//!
+//! An expression is a register, `PC`, a label, a number (`x4000`, `0x4000`,
+//! `#12` or `12`) or `mem[...]` around another expression, and terms can be
+//! added or subtracted with `+` and `-`. Arithmetic wraps like the LC-3.
+
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt;
+
+use crate::{errors::VMError, register::Register, symbols::SymbolTable, vm::VM};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expression {
+    Number(u16),
+    Register(Register),
+    Pc,
+    /// The word at the address given by the inner expression
+    Memory(Box<Expression>),
+    Sum(Box<Expression>, Box<Expression>),
+    Difference(Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+    /// Parses `text`, looking labels up in `symbols`
+    pub fn parse(text: &str, symbols: &SymbolTable) -> Result<Self, VMError> {
+        let mut parser = Parser {
+            text,
+            position: 0,
+            symbols,
+        };
+        let expression = parser.sum()?;
+        parser.skip_spaces();
+        match parser.rest().chars().next() {
+            None => Ok(expression),
+            Some(found) => Err(parser.error(&format!("unexpected {found}"))),
+        }
+    }
+
+    /// Value on `vm`. Memory is read without side effects on the device
+    /// registers.
+    pub fn evaluate(&self, vm: &VM) -> u16 {
+        match self {
+            Expression::Number(value) => *value,
+            Expression::Register(register) => vm.register(*register),
+            Expression::Pc => vm.pc(),
+            Expression::Memory(address) => vm.peek(address.evaluate(vm)),
+            Expression::Sum(left, right) => left.evaluate(vm).wrapping_add(right.evaluate(vm)),
+            Expression::Difference(left, right) => {
+                left.evaluate(vm).wrapping_sub(right.evaluate(vm))
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+    symbols: &'a SymbolTable,
+}
+
+impl Parser<'_> {
+    fn rest(&self) -> &str {
+        self.text.get(self.position..).unwrap_or_default()
+    }
+
+    fn skip_spaces(&mut self) {
+        let rest = self.rest();
+        let spaces = rest.len().saturating_sub(rest.trim_start().len());
+        self.position = self.position.saturating_add(spaces);
+    }
+
+    /// Consumes `symbol` if it comes next
+    fn eat(&mut self, symbol: char) -> bool {
+        self.skip_spaces();
+        if self.rest().starts_with(symbol) {
+            self.position = self.position.saturating_add(symbol.len_utf8());
+            true
+        } else {
+            false
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expression, VMError> {
+        let mut expression = self.term()?;
+        loop {
+            if self.eat('+') {
+                expression = Expression::Sum(Box::new(expression), Box::new(self.term()?));
+            } else if self.eat('-') {
+                expression = Expression::Difference(Box::new(expression), Box::new(self.term()?));
+            } else {
+                return Ok(expression);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<Expression, VMError> {
+        self.skip_spaces();
+        let word: String = self
+            .rest()
+            .chars()
+            .take_while(|character| {
+                character.is_ascii_alphanumeric() || matches!(character, '_' | '#')
+            })
+            .collect();
+        if word.is_empty() {
+            return Err(match self.rest().chars().next() {
+                Some(found) => self.error(&format!("unexpected {found}")),
+                None => self.error("expected a value"),
+            });
+        }
+        self.position = self.position.saturating_add(word.len());
+        if word.eq_ignore_ascii_case("mem") {
+            if !self.eat('[') {
+                return Err(self.error("expected [ after mem"));
+            }
+            let address = self.sum()?;
+            if !self.eat(']') {
+                return Err(self.error("expected ]"));
+            }
+            return Ok(Expression::Memory(Box::new(address)));
+        }
+        if word.eq_ignore_ascii_case("pc") {
+            return Ok(Expression::Pc);
+        }
+        if let Some(register) = register(&word) {
+            return Ok(Expression::Register(register));
+        }
+        if let Some(address) = self.symbols.address_of(&word) {
+            return Ok(Expression::Number(address));
+        }
+        number(&word)
+            .map(Expression::Number)
+            .ok_or_else(|| self.error(&format!("unknown register, label or number {word}")))
+    }
+
+    fn error(&self, message: &str) -> VMError {
+        VMError::Debugger(format!("{message} in watch expression {}", self.text))
+    }
+}
+
+fn register(word: &str) -> Option<Register> {
+    let number = word.strip_prefix(['R', 'r'])?.parse().ok()?;
+    Register::new(number)
+}
+
+fn number(word: &str) -> Option<u16> {
+    let hex = word
+        .strip_prefix("0x")
+        .or_else(|| word.strip_prefix(['x', 'X']));
+    match hex {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => word.strip_prefix('#').unwrap_or(word).parse().ok(),
+    }
+}
+
+/// A watched expression whose value changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub text: String,
+    pub old: u16,
+    pub new: u16,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} changed from x{:04X} to x{:04X}",
+            self.text, self.old, self.new
+        )
+    }
+}
+
+/// Expressions to stop on when their value changes, checked after every
+/// instruction by the debugger
+#[derive(Debug, Default, Clone)]
+pub struct Watchpoints {
+    /// Each expression with its text and last value
+    watches: Vec<(String, Expression, u16)>,
+}
+
+impl Watchpoints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Watches `text`, remembering its current value on `vm`
+    pub fn add(&mut self, text: &str, symbols: &SymbolTable, vm: &VM) -> Result<(), VMError> {
+        let expression = Expression::parse(text, symbols)?;
+        let value = expression.evaluate(vm);
+        self.watches.push((text.to_string(), expression, value));
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.watches.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.watches.is_empty()
+    }
+
+    /// The watched expressions with their last values
+    pub fn values(&self) -> impl Iterator<Item = (&str, u16)> {
+        self.watches
+            .iter()
+            .map(|(text, _, value)| (text.as_str(), *value))
+    }
+
+    /// Evaluates every expression again, returning the first whose value
+    /// differs from the last time
+    pub fn check(&mut self, vm: &VM) -> Option<Change> {
+        let mut first = None;
+        for (text, expression, value) in &mut self.watches {
+            let new = expression.evaluate(vm);
+            if new != *value && first.is_none() {
+                first = Some(Change {
+                    text: text.clone(),
+                    old: *value,
+                    new,
+                });
+            }
+            *value = new;
+        }
+        first
+    }
+}
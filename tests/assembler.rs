@@ -0,0 +1,341 @@
+//! The assembler against the images in `tests/golden` and `tests/programs`,
+//! which were assembled from the `.asm` next to them, and its error cases
+#![allow(clippy::unwrap_used)]
+
+use std::{fs, path::Path};
+
+use lc3_vm::{
+    assembler::{assemble, assemble_at, assemble_with_diagnostics},
+    errors::VMError,
+    symbols::SymbolTable,
+};
+
+#[test]
+fn sources_assemble_to_their_images() {
+    let tests = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let mut checked = 0;
+    for directory in ["golden", "programs"] {
+        for entry in fs::read_dir(tests.join(directory)).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|extension| extension != "asm") {
+                continue;
+            }
+            let source = fs::read_to_string(&path).unwrap();
+            let assembly = assemble(&source);
+            assert!(assembly.is_ok(), "{}: {assembly:?}", path.display());
+            let assembly = assembly.unwrap();
+            let image = fs::read(path.with_extension("obj")).unwrap();
+            assert!(assembly.image() == image, "{} differs", path.display());
+            checked += 1;
+        }
+    }
+    assert!(checked > 0);
+}
+
+#[test]
+fn labels_and_directives() {
+    let assembly = assemble(
+        ".ORIG x4000
+        start   LEA R0, msg     ; comment, with a comma
+                PUTS
+                BRnzp start
+        msg     .STRINGZ \"a;\\\"\\n\"
+        ptr     .FILL msg
+                .FILL #-1
+                .BLKW 2
+                .END
+                ADD R0, R0, #1",
+    )
+    .unwrap();
+    assert_eq!(assembly.origin, 0x4000);
+    assert_eq!(
+        assembly.words,
+        [0xE002, 0xF022, 0x0FFD, 0x61, 0x3B, 0x22, 0x0A, 0, 0x4003, 0xFFFF, 0, 0]
+    );
+    assert_eq!(assembly.symbols.address_of("MSG"), Some(0x4003));
+    assert_eq!(assembly.symbols.address_of("ptr"), Some(0x4008));
+    assert_eq!(assembly.end(), 0x400C);
+}
+
+#[test]
+fn snippets_take_the_given_origin() {
+    let assembly = assemble_at("add r1, r1, x-1\nret", 0x3100).unwrap();
+    assert_eq!(assembly.origin, 0x3100);
+    assert_eq!(assembly.words, [0x127F, 0xC1C0]);
+    assert_eq!(
+        assemble_at(".ORIG x5000\nHALT\n.END", 0x3100)
+            .unwrap()
+            .origin,
+        0x5000
+    );
+}
+
+#[test]
+fn constants_and_macros_expand_before_assembly() {
+    let with_macros = assemble(
+        ".ORIG x3000
+        .DEFINE SP R6
+        .DEFINE STACK xFE00
+        .MACRO PUSH reg
+                ADD SP, SP, #-1
+                STR reg, SP, #0
+        .ENDM
+        .MACRO POP reg
+                LDR reg, SP, #0
+                ADD SP, SP, #1
+        .ENDM
+        .macro call target
+                PUSH R7
+                JSR target
+                POP R7
+        .endm
+        main    LD SP, top
+        again   call sub
+                HALT
+        top     .FILL STACK
+        sub     RET
+                .END",
+    )
+    .unwrap();
+    let expanded = assemble(
+        ".ORIG x3000
+        main    LD R6, top
+                ADD R6, R6, #-1
+                STR R7, R6, #0
+                JSR sub
+                LDR R7, R6, #0
+                ADD R6, R6, #1
+                HALT
+        top     .FILL xFE00
+        sub     RET
+                .END",
+    )
+    .unwrap();
+    assert_eq!(with_macros.words, expanded.words);
+    assert_eq!(with_macros.symbols.address_of("again"), Some(0x3001));
+    assert_eq!(with_macros.symbols.address_of("SUB"), Some(0x3008));
+}
+
+#[test]
+fn errors_point_at_the_token() {
+    for (source, line, column, message) in [
+        ("ADD R0, R0, #1", 1, 1, "the program must start with .ORIG"),
+        (
+            ".ORIG x3000\nADD R0, R0, #16\n.END",
+            2,
+            13,
+            "16 does not fit in 5 signed bits",
+        ),
+        (
+            ".ORIG x3000\n\n  BRz nowhere\n.END",
+            3,
+            7,
+            "unknown label nowhere",
+        ),
+        (
+            ".ORIG x3000\nx .FILL 1\nx .FILL 2\n.END",
+            3,
+            1,
+            "label x is defined twice",
+        ),
+        (
+            ".ORIG x3000\nNOT R0\n.END",
+            2,
+            1,
+            "NOT takes 2 operands, found 1",
+        ),
+        (
+            ".ORIG x3000\nRET R7\n.END",
+            2,
+            5,
+            "RET takes 0 operands, found 1",
+        ),
+        (
+            ".ORIG x3000\nLDR R0, R8, #0\n.END",
+            2,
+            9,
+            "expected a register, found R8",
+        ),
+        (
+            ".ORIG x3000\n.STRINGZ \"open\n.END",
+            2,
+            10,
+            "unterminated string",
+        ),
+        (
+            ".ORIG x3000\nTRAP x100\n.END",
+            2,
+            6,
+            "trap vector x100 does not fit in 8 bits",
+        ),
+        (
+            ".ORIG xFFFF\n.BLKW 2\n.END",
+            2,
+            1,
+            "the program does not fit in memory",
+        ),
+        (".ORIG x3000\nFOO R0\n.END", 2, 1, "unknown operation FOO"),
+        (".ORIG x3000\nHALT", 1, 1, "the program has no .END"),
+        (".DEFINE ADD 1", 1, 9, "ADD cannot be redefined"),
+        (".DEFINE N 1\n.DEFINE n 2", 2, 9, "n is defined twice"),
+        (".DEFINE N", 1, 1, "expected .DEFINE NAME VALUE"),
+        (".MACRO M\nHALT", 1, 1, ".MACRO has no matching .ENDM"),
+        (".ENDM", 1, 1, ".ENDM without .MACRO"),
+        (
+            ".MACRO M\n.DEFINE N 1\n.ENDM",
+            2,
+            1,
+            ".DEFINE cannot be used inside a macro",
+        ),
+        (
+            ".MACRO INC r\nADD r, r, #1\n.ENDM\n.ORIG x3000\nINC R0, R1\n.END",
+            5,
+            1,
+            "INC takes 1 operand, found 2",
+        ),
+        (
+            ".MACRO INC r\nADD r, r, #16\n.ENDM\n.ORIG x3000\nHALT\n  INC R0\n.END",
+            6,
+            3,
+            "16 does not fit in 5 signed bits",
+        ),
+        (
+            ".MACRO INC r\nADD r, r, #1\n.ENDM\n.ORIG x3000\nINC R9\n.END",
+            5,
+            5,
+            "expected a register, found R9",
+        ),
+        (
+            ".MACRO LOOP\nLOOP\n.ENDM\n.ORIG x3000\nLOOP\n.END",
+            5,
+            1,
+            "macro LOOP is nested too deeply, does it use itself?",
+        ),
+    ] {
+        let diagnostics = assemble_with_diagnostics(source, None).unwrap_err();
+        assert!(
+            diagnostics.iter().any(|diagnostic| (
+                diagnostic.line,
+                diagnostic.column,
+                diagnostic.message.as_str()
+            ) == (line, column, message)),
+            "{source}: {diagnostics:?}"
+        );
+    }
+}
+
+#[test]
+fn every_error_is_reported_with_suggestions() {
+    let source = ".ORIG x3000
+loop    ADD R0, R0, #20
+        ADDD R0, R0, #1
+        BRp lop
+        LD R1, far
+        LDR R2, R9, #0
+        HALT
+        .BLKW 300
+far     .FILL 1
+        .END";
+    let diagnostics = assemble_with_diagnostics(source, None).unwrap_err();
+    let found: Vec<_> = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            (
+                diagnostic.line,
+                diagnostic.message.as_str(),
+                diagnostic.help.as_deref().unwrap_or_default(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (
+                2,
+                "20 does not fit in 5 signed bits",
+                "ADD takes immediates from #-16 to #15; \
+                 load other values with LD from a .FILL"
+            ),
+            (3, "unknown operation ADDD", "did you mean ADD?"),
+            (4, "unknown label lop", "did you mean loop?"),
+            (
+                5,
+                "302 does not fit in 9 signed bits",
+                "LD reaches #-256 to #255 words from the next instruction; move the \
+                 target closer, or load its address with LD from a .FILL and use a register"
+            ),
+            (6, "expected a register, found R9", "registers are R0 to R7"),
+        ]
+    );
+}
+
+#[test]
+fn errors_render_with_the_line_underlined() {
+    let source = ".ORIG x3000\n\tADD R0, R0, #16\n.END";
+    assert_eq!(
+        assemble(source).unwrap_err(),
+        VMError::Assembly(String::from(
+            "line 2, column 14: 16 does not fit in 5 signed bits\n\
+             2 | \tADD R0, R0, #16\n\
+             \x20 | \t            ^^^\n\
+             \x20 = help: ADD takes immediates from #-16 to #15; \
+             load other values with LD from a .FILL"
+        ))
+    );
+}
+
+#[test]
+fn listings_show_each_word_beside_its_line() {
+    let source = "; doubles R0
+        .ORIG x3000
+.MACRO DOUBLE reg
+        ADD reg, reg, reg
+.ENDM
+start   DOUBLE R0
+        DOUBLE R0
+text    .STRINGZ \"ok\"
+        .END";
+    let assembly = assemble(source).unwrap();
+    assert_eq!(
+        assembly.listing(source),
+        "                                (   1) ; doubles R0
+                                (   2)         .ORIG x3000
+                                (   3) .MACRO DOUBLE reg
+                                (   4)         ADD reg, reg, reg
+                                (   5) .ENDM
+  (3000) 1000  0001000000000000 (   6) start   DOUBLE R0
+  (3001) 1000  0001000000000000 (   7)         DOUBLE R0
+  (3002) 006F  0000000001101111 (   8) text    .STRINGZ \"ok\"
+  (3003) 006B  0000000001101011
+  (3004) 0000  0000000000000000
+                                (   9)         .END
+"
+    );
+}
+
+#[test]
+fn symbol_tables_are_written_like_lc3as() {
+    let assembly = assemble(".ORIG x3000\nloop BRnzp loop\nDone\nfin HALT\n.END").unwrap();
+    let text = assembly.symbols.to_sym();
+    assert_eq!(
+        text,
+        "// Symbol table
+// Scope level 0:
+//\tSymbol Name       Page Address
+//\t----------------  ------------
+//\tloop              3000
+//\tDone              3001
+//\tFIN               3001
+
+"
+    );
+    let parsed = SymbolTable::parse(&text);
+    assert_eq!(parsed.address_of("loop"), Some(0x3000));
+    assert_eq!(parsed.address_of("done"), Some(0x3001));
+}
+
+#[test]
+fn hex_images_have_a_word_per_line() {
+    let assembly = assemble(".ORIG x3000\nADD R0, R0, #1\nHALT\n.END").unwrap();
+    assert_eq!(assembly.hex(), "3000\n1021\nF025\n");
+}
@@ -0,0 +1,81 @@
+//! Programs hosted on a tokio executor through the async console
+#![cfg(feature = "tokio")]
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    async_console::AsyncConsole,
+    register::Register,
+    vm::{TrapMessages, VM},
+};
+use tokio::io::{self, AsyncReadExt, AsyncWriteExt};
+
+/// Prints a prompt, then echoes keys up to a newline in upper case
+const SHOUT: &str = ".ORIG x3000
+         LEA R0, PROMPT
+         PUTS
+LOOP     GETC
+         ADD R1, R0, #-10
+         BRz DONE
+         ADD R0, R0, R2
+         OUT
+         BRnzp LOOP
+DONE     HALT
+PROMPT   .STRINGZ \"> \"
+         .END";
+
+fn machine() -> VM {
+    let mut vm = VM::builder()
+        .trap_messages(TrapMessages::SILENT)
+        .build()
+        .unwrap();
+    vm.load_asm_str(SHOUT).unwrap();
+    // lower case to upper case
+    vm.set_register(Register::R2, 0xFFE0);
+    vm
+}
+
+#[tokio::test]
+async fn keys_are_read_and_output_written() {
+    let mut vm = machine();
+    let mut console = AsyncConsole::new(&b"shout\n"[..], Vec::new());
+    vm.run_async(&mut console).await.unwrap();
+    assert!(vm.is_halted());
+    let (_, output) = console.into_inner();
+    assert_eq!(String::from_utf8(output).unwrap(), "> SHOUT");
+}
+
+#[tokio::test]
+async fn keys_are_awaited_while_other_tasks_run() {
+    let (input, mut keyboard) = io::duplex(64);
+    let (mut screen, output) = io::duplex(64);
+    let user = async move {
+        let mut prompt = [0; 2];
+        screen.read_exact(&mut prompt).await.unwrap();
+        assert_eq!(&prompt, b"> ");
+        keyboard.write_all(b"ok").await.unwrap();
+        let mut echo = [0; 2];
+        screen.read_exact(&mut echo).await.unwrap();
+        assert_eq!(&echo, b"OK");
+        keyboard.write_all(b"\n").await.unwrap();
+    };
+    let mut vm = machine();
+    let mut console = AsyncConsole::new(input, output);
+    let (result, ()) = tokio::join!(vm.run_async(&mut console), user);
+    result.unwrap();
+    assert!(vm.is_halted());
+}
+
+#[tokio::test]
+async fn input_closing_while_a_key_is_awaited_fails() {
+    let mut vm = machine();
+    let mut console = AsyncConsole::new(&b"no"[..], Vec::new());
+    let error = vm.run_async(&mut console).await.unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("The input closed while the program waits for a key"),
+        "{error}"
+    );
+    let (_, output) = console.into_inner();
+    assert_eq!(String::from_utf8(output).unwrap(), "> NO");
+}
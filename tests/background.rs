@@ -0,0 +1,102 @@
+//! Programs running on their own thread, controlled through a handle
+#![allow(clippy::unwrap_used)]
+
+use std::{thread, time::Duration};
+
+use lc3_vm::{
+    console::NullConsole,
+    errors::VMError,
+    vm::{TrapMessages, VmHandle, VmState, VM},
+};
+
+/// Counts in R1 forever
+const SPIN: &str = ".ORIG x3000
+LOOP     ADD R1, R1, #1
+         BRnzp LOOP
+         .END";
+
+fn spawn(source: &'static str) -> VmHandle {
+    VM::spawn(move || {
+        let mut vm = VM::builder()
+            .console(Box::new(NullConsole))
+            .trap_messages(TrapMessages::SILENT)
+            .build()?;
+        vm.load_asm_str(source)?;
+        Ok(vm)
+    })
+}
+
+/// Queries `handle` until `done` holds for its state
+fn wait_for(handle: &VmHandle, done: impl Fn(&VmState) -> bool) -> VmState {
+    for _ in 0..1000 {
+        let state = handle.query().unwrap();
+        if done(&state) {
+            return state;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    handle.query().unwrap()
+}
+
+/// R1 of `state`
+fn counter(state: &VmState) -> u16 {
+    state.registers.get(1).copied().unwrap()
+}
+
+#[test]
+fn paused_programs_wait_until_resumed() {
+    let handle = spawn(SPIN);
+    let running = wait_for(&handle, |state| counter(state) != 0);
+    assert!(!running.paused);
+    assert_ne!(counter(&running), 0);
+
+    handle.pause().unwrap();
+    let paused = handle.query().unwrap();
+    assert!(paused.paused);
+    thread::sleep(Duration::from_millis(10));
+    assert_eq!(handle.query().unwrap(), paused);
+
+    handle.resume().unwrap();
+    let resumed = wait_for(&handle, |state| counter(state) != counter(&paused));
+    assert!(!resumed.paused);
+    assert_ne!(counter(&resumed), counter(&paused));
+
+    let stopped = handle.stop().unwrap();
+    assert!(!stopped.halted);
+}
+
+#[test]
+fn halted_programs_can_still_be_queried() {
+    let handle = spawn(
+        ".ORIG x3000
+         ADD R1, R1, #5
+         HALT
+         .END",
+    );
+    let halted = wait_for(&handle, |state| state.halted);
+    assert!(halted.halted);
+    assert_eq!(counter(&halted), 5);
+    assert_eq!(handle.stop().unwrap(), halted);
+}
+
+#[test]
+fn errors_end_the_thread() {
+    let handle = spawn(
+        ".ORIG x3000
+         .FILL xD000
+         .END",
+    );
+    // queries fail once the thread has exited
+    for _ in 0..1000 {
+        if handle.query().is_err() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+    let error = handle.stop().unwrap_err();
+    assert!(!matches!(error, VMError::Debugger(_)), "{error}");
+
+    let handle = VM::spawn(|| Err(VMError::InvalidArgument(String::from("no VM"))));
+    let error = handle.stop().unwrap_err();
+    assert!(matches!(&error, VMError::InvalidArgument(message) if message == "no VM"));
+}
@@ -0,0 +1,146 @@
+//! Programs run from a batch manifest
+#![allow(clippy::unwrap_used)]
+
+use std::{fs, path::PathBuf};
+
+use lc3_vm::{
+    assembler::assemble,
+    batch::{Manifest, Verdict},
+    errors::VMError,
+};
+
+/// A directory of its own for each test, holding the assembled `programs`
+fn directory(test: &str, programs: &[(&str, &str)]) -> PathBuf {
+    let directory = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(test);
+    fs::create_dir_all(&directory).unwrap();
+    for (name, source) in programs {
+        let image = assemble(source).unwrap().image();
+        fs::write(directory.join(name), image).unwrap();
+    }
+    directory
+}
+
+const ECHO: &str = ".ORIG x3000
+LOOP     GETC
+         ADD R1, R0, #-10
+         BRz DONE
+         OUT
+         BRnzp LOOP
+DONE     HALT
+         .END";
+
+const SPIN: &str = ".ORIG x3000
+LOOP     BRnzp LOOP
+         .END";
+
+#[test]
+fn each_program_gets_its_own_input_expectation_and_limit() {
+    let directory = directory("batch_verdicts", &[("echo.obj", ECHO), ("spin.obj", SPIN)]);
+    fs::write(directory.join("echo.out"), "abcHALT\n").unwrap();
+    let manifest = directory.join("manifest.toml");
+    fs::write(
+        &manifest,
+        r#"
+limit = 1000
+
+[[program]]
+image = "echo.obj"
+input_text = "abc\n"
+expected = "echo.out"
+
+[[program]]
+name = "wrong"
+image = "echo.obj"
+input_text = "abd\n"
+expected_text = "abcHALT\n"
+
+[[program]]
+image = "spin.obj"
+limit = 50
+"#,
+    )
+    .unwrap();
+    let manifest = Manifest::load(&manifest).unwrap();
+    assert_eq!(manifest.jobs.get(2).unwrap().limit, 50);
+    let report = manifest.run();
+    let verdicts: Vec<&Verdict> = report.outcomes.iter().map(|o| &o.verdict).collect();
+    assert!(matches!(
+        verdicts.as_slice(),
+        [
+            Verdict::Pass,
+            Verdict::Fail(_),
+            Verdict::Error(VMError::InstructionLimit(_))
+        ]
+    ));
+    assert_eq!(report.passed(), 1);
+    assert_eq!(report.failed(), 2);
+    assert_eq!(report.outcomes.get(2).unwrap().instructions, 50);
+
+    let table = report.to_string();
+    let lines: Vec<&str> = table.lines().collect();
+    assert_eq!(
+        lines.first().unwrap(),
+        &"PROGRAM  RESULT  INSTRUCTIONS  DETAIL"
+    );
+    assert!(lines.get(1).unwrap().starts_with("echo     pass  "));
+    assert!(
+        lines
+            .get(2)
+            .unwrap()
+            .ends_with(r#"line 1: expected "abcHALT\n", got "abdHALT\n""#),
+        "{table}"
+    );
+    assert!(lines.get(3).unwrap().starts_with("spin     error "));
+    assert_eq!(lines.last().unwrap(), &"1 passed, 2 failed of 3 programs");
+}
+
+#[test]
+fn manifest_mistakes_are_reported() {
+    let base = directory("batch_mistakes", &[]);
+    let error = |text: &str| Manifest::parse(text, &base).unwrap_err().to_string();
+    assert_eq!(
+        error("limit = 10"),
+        "Invalid manifest: The manifest lists no [[program]]"
+    );
+    assert_eq!(
+        error("[[program]]\nimage = \"a.obj\"\nexpect = \"a.out\""),
+        "Invalid manifest: program 1: `expect` is not a setting"
+    );
+    assert_eq!(
+        error("[[program]]\nname = \"a\""),
+        "Invalid manifest: program 1 has no `image`"
+    );
+    assert_eq!(
+        error("[[program]]\nimage = \"a.obj\"\nlimit = -1"),
+        "Invalid manifest: program 1: `limit` must be a positive number of instructions"
+    );
+}
+
+#[test]
+fn programs_read_their_env_table() {
+    let source = ".ORIG x3000
+         LEA R0, NAME
+         LEA R1, BUFFER
+         AND R2, R2, #0
+         ADD R2, R2, #8
+         TRAP x84
+         LEA R0, BUFFER
+         PUTS
+         HALT
+NAME     .STRINGZ \"CASE\"
+BUFFER   .BLKW 8
+         .END";
+    let directory = directory("batch_env", &[("env.obj", source)]);
+    let manifest = Manifest::parse(
+        r#"
+[[program]]
+image = "env.obj"
+env = { CASE = "b" }
+expected_text = "bHALT\n"
+"#,
+        &directory,
+    )
+    .unwrap();
+    assert_eq!(manifest.jobs.first().unwrap().env.get("CASE").unwrap(), "b");
+    assert_eq!(manifest.run().passed(), 1);
+}
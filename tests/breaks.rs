@@ -0,0 +1,148 @@
+//! Debugger runs stopping before traps and exceptions
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    console::SharedConsole,
+    register::Register,
+    vm::{DeviceAccess, StopReason, UnmappedAccess, BUS_ERROR, VM},
+};
+
+/// Prints a character, then halts
+const PUTC_HALT: &str = ".ORIG x3000
+         AND R0, R0, #0
+         OUT
+         HALT
+         .END";
+
+fn vm(source: &str) -> VM {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    vm.load_asm_str(source).unwrap();
+    vm
+}
+
+#[test]
+fn every_trap_stops_a_run_before_executing() {
+    let mut vm = vm(PUTC_HALT);
+    assert!(vm.add_trap_break(None));
+    assert!(!vm.add_trap_break(None));
+    assert_eq!(vm.resume().unwrap(), StopReason::Trap(0x21));
+    assert_eq!(vm.pc(), 0x3001);
+    assert_eq!(vm.resume().unwrap(), StopReason::Trap(0x25));
+    assert_eq!(vm.pc(), 0x3002);
+    assert_eq!(vm.resume().unwrap(), StopReason::Halted);
+}
+
+#[test]
+fn a_trap_vector_stops_only_its_traps() {
+    let mut vm = vm(PUTC_HALT);
+    vm.add_trap_break(Some(0x25));
+    assert_eq!(vm.resume().unwrap(), StopReason::Trap(0x25));
+    assert_eq!(vm.pc(), 0x3002);
+    assert!(vm.remove_trap_break(Some(0x25)));
+    assert_eq!(vm.trap_breaks().count(), 0);
+}
+
+#[test]
+fn exceptions_stop_a_run_when_enabled() {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .unmapped_access(UnmappedAccess::BusError)
+        .build()
+        .unwrap();
+    vm.load_asm_str(
+        ".ORIG x3000
+         LDI R1, DEVICE
+         HALT
+DEVICE   .FILL xFE20
+         .END",
+    )
+    .unwrap();
+    vm.set_break_on_interrupt(true);
+    assert_eq!(
+        vm.resume().unwrap(),
+        StopReason::Interrupt(u16::from(BUS_ERROR))
+    );
+    assert_eq!(vm.pc(), 0x3001);
+}
+
+#[test]
+fn failing_instructions_are_not_reported_as_exceptions() {
+    let mut vm = vm(".ORIG x3000
+         ADD R1, R1, #1
+         .FILL xD000
+         .END");
+    vm.set_break_on_interrupt(true);
+    assert!(vm.resume().is_err());
+    assert_eq!(vm.register(Register::R1), 1);
+}
+
+/// Polls the keyboard, then stores the key in a device register
+const POLL: &str = ".ORIG x3000
+LOOP     LDI R0, STATUS
+         BRzp LOOP
+         LDI R0, DATA
+         STI R0, SCRATCH
+         HALT
+STATUS   .FILL xFE00
+DATA     .FILL xFE02
+SCRATCH  .FILL xFE20
+         .END";
+
+fn polling(keys: &str) -> VM {
+    let console = SharedConsole::new();
+    console.push_input(keys.bytes());
+    let mut vm = VM::builder().console(Box::new(console)).build().unwrap();
+    vm.load_asm_str(POLL).unwrap();
+    vm
+}
+
+#[test]
+fn device_reads_stop_after_the_instruction() {
+    let mut vm = polling("a");
+    vm.add_device_break(0xFE02, DeviceAccess::Read).unwrap();
+    assert_eq!(
+        vm.resume().unwrap(),
+        StopReason::DeviceAccess {
+            address: 0xFE02,
+            write: false
+        }
+    );
+    assert_eq!(vm.pc(), 0x3003);
+    assert_eq!(vm.register(Register::R0), u16::from(b'a'));
+    assert_eq!(vm.resume().unwrap(), StopReason::Halted);
+}
+
+#[test]
+fn polling_the_keyboard_is_not_a_write() {
+    let mut vm = polling("a");
+    vm.add_device_break(0xFE00, DeviceAccess::Write).unwrap();
+    vm.add_device_break(0xFE20, DeviceAccess::Write).unwrap();
+    assert_eq!(
+        vm.resume().unwrap(),
+        StopReason::DeviceAccess {
+            address: 0xFE20,
+            write: true
+        }
+    );
+    assert_eq!(vm.pc(), 0x3004);
+}
+
+#[test]
+fn device_breaks_need_a_device_register() {
+    let mut vm = polling("");
+    assert!(vm.add_device_break(0x3000, DeviceAccess::Any).is_err());
+    vm.add_device_break(0xFE00, DeviceAccess::Any).unwrap();
+    assert_eq!(
+        vm.step().unwrap(),
+        StopReason::DeviceAccess {
+            address: 0xFE00,
+            write: false
+        }
+    );
+    assert!(vm.remove_device_break(0xFE00));
+    assert_eq!(vm.device_breaks().count(), 0);
+    assert_eq!(vm.step().unwrap(), StopReason::Step);
+}
@@ -0,0 +1,128 @@
+//! Machines configured with `VM::builder()`
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    assembler::assemble,
+    console::NullConsole,
+    errors::VMError,
+    memory::MMIO_START,
+    vm::{TrapMessages, VMBuilder, VM},
+};
+
+/// Spins forever
+const SPIN: &str = ".ORIG x3000
+LOOP     BRnzp LOOP
+         .END";
+
+fn builder() -> VMBuilder {
+    VM::builder()
+        .console(Box::new(NullConsole))
+        .trap_messages(TrapMessages::SILENT)
+}
+
+/// The message of an `InvalidArgument` from `build`
+fn invalid(builder: VMBuilder) -> String {
+    match builder.build() {
+        Err(VMError::InvalidArgument(message)) => message,
+        Err(error) => format!("unexpected error: {error}"),
+        Ok(_) => String::from("built"),
+    }
+}
+
+#[test]
+fn defaults_are_those_of_a_full_machine() {
+    let vm = builder().build().unwrap();
+    assert_eq!(vm.memory().size(), usize::from(MMIO_START));
+    assert_eq!(vm.pc(), 0x3000);
+}
+
+#[test]
+fn memory_sizes_are_validated() {
+    let expected = "Memory size must be between 1 and 0xfe00 words";
+    assert_eq!(invalid(builder().memory_size(0)), expected);
+    assert_eq!(
+        invalid(builder().memory_size(usize::from(MMIO_START) + 1)),
+        expected
+    );
+    assert_eq!(invalid(builder().memory_size(0x10000)), expected);
+    // the default entry x3000 is past the end of the RAM
+    assert_eq!(
+        invalid(builder().memory_size(0x2000)),
+        "Entry point 0x3000 is outside the 8192 words of memory"
+    );
+    assert_eq!(
+        invalid(builder().memory_size(0x2000).entry(0x2000)),
+        "Entry point 0x2000 is outside the 8192 words of memory"
+    );
+}
+
+#[test]
+fn small_memories_end_the_ram() {
+    let mut vm = builder().memory_size(0x2000).entry(0x1000).build().unwrap();
+    assert_eq!(vm.memory().size(), 0x2000);
+    vm.load_asm_str(
+        ".ORIG x1000
+         LD R0, PAST
+         STR R0, R0, #0
+         HALT
+PAST     .FILL x2000
+         .END",
+    )
+    .unwrap();
+    let error = vm.run().unwrap_err();
+    assert!(error.to_string().contains("0x2000"), "{error}");
+}
+
+#[test]
+fn entries_override_the_origin_of_images() {
+    let image = assemble(SPIN).unwrap().image();
+    let mut vm = builder().entry(0x3001).build().unwrap();
+    vm.read_image_bytes(&image).unwrap();
+    assert_eq!(vm.pc(), 0x3001);
+
+    let mut vm = builder().build().unwrap();
+    vm.read_image_bytes(&assemble(".ORIG x4000\nHALT\n.END").unwrap().image())
+        .unwrap();
+    assert_eq!(vm.pc(), 0x4000);
+}
+
+#[test]
+fn instruction_limits_stop_runs() {
+    assert_eq!(
+        invalid(builder().instruction_limit(0)),
+        "The instruction limit must be positive"
+    );
+    assert_eq!(
+        invalid(builder().output_limit(0)),
+        "The output limit must be positive"
+    );
+    let mut vm = builder().instruction_limit(100).build().unwrap();
+    vm.load_asm_str(SPIN).unwrap();
+    let error = vm.run().unwrap_err();
+    assert!(matches!(error, VMError::InstructionLimit(_)), "{error}");
+    assert_eq!(vm.metrics().instructions, 100);
+}
+
+#[cfg(feature = "jit")]
+#[test]
+fn small_memories_keep_the_jit_off() {
+    let mut vm = builder().memory_size(0x2000).entry(0x1000).build().unwrap();
+    vm.set_jit(true);
+    vm.load_asm_str(
+        ".ORIG x1000
+         LD R1, COUNT
+         LD R3, START
+LOOP     LDR R2, R3, #0
+         ADD R3, R3, #1
+         ADD R1, R1, #-1
+         BRp LOOP
+         HALT
+COUNT    .FILL #1000
+; reads past the end in a hot loop
+START    .FILL x1F00
+         .END",
+    )
+    .unwrap();
+    let error = vm.run().unwrap_err();
+    assert!(error.to_string().contains("0x2000"), "{error}");
+}
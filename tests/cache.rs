@@ -0,0 +1,78 @@
+//! Simulated caches of `--icache` and `--dcache`
+#![allow(clippy::unwrap_used)]
+
+use std::{cell::RefCell, rc::Rc};
+
+use lc3_vm::{
+    cache::{Cache, CacheConfig, CacheSimulator},
+    console::SharedConsole,
+    vm::VM,
+};
+
+#[test]
+fn geometries_must_be_powers_of_two_that_fit() {
+    let config: CacheConfig = "256:2:4".parse().unwrap();
+    assert_eq!(config.sets(), 32);
+    assert!("256:3:4".parse::<CacheConfig>().is_err());
+    assert!("4:2:4".parse::<CacheConfig>().is_err());
+    assert!("256:2".parse::<CacheConfig>().is_err());
+    assert!("0:1:1".parse::<CacheConfig>().is_err());
+}
+
+#[test]
+fn the_least_recently_used_line_is_evicted() {
+    // two sets of two 4-word lines: x0000, x0008 and x0010 share set 0
+    let mut cache = Cache::new(CacheConfig::new(16, 2, 4).unwrap());
+    assert!(!cache.access(0, 0x0000));
+    assert!(cache.access(0, 0x0003));
+    assert!(!cache.access(0, 0x0008));
+    assert!(cache.access(0, 0x0001));
+    // evicts x0008, used less recently than x0000
+    assert!(!cache.access(0, 0x0010));
+    assert!(cache.access(0, 0x0000));
+    assert!(!cache.access(0, 0x0008));
+    // set 1 is untouched
+    assert!(!cache.access(0, 0x0004));
+    assert_eq!(cache.totals().hits, 3);
+    assert_eq!(cache.totals().misses, 5);
+}
+
+#[test]
+fn misses_are_counted_by_the_instruction_causing_them() {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    vm.load_asm_str(
+        ".ORIG x3000
+         LEA R1, DATA
+         AND R2, R2, #0
+         ADD R2, R2, #8
+LOOP     LDR R0, R1, #0
+         ADD R1, R1, #1
+         ADD R2, R2, #-1
+         BRp LOOP
+         HALT
+DATA     .BLKW #8
+         .END",
+    )
+    .unwrap();
+    let caches = Rc::new(RefCell::new(CacheSimulator::new(
+        Some("64:1:4".parse().unwrap()),
+        Some("64:1:4".parse().unwrap()),
+    )));
+    vm.set_observer(Box::new(Rc::clone(&caches)));
+    vm.run().unwrap();
+    let caches = caches.borrow();
+    let data = caches.data_cache().unwrap();
+    // the eight words span two lines
+    let load = data.site(0x3003);
+    assert_eq!((load.hits, load.misses), (6, 2));
+    let instructions = caches.instruction_cache().unwrap();
+    assert_eq!(instructions.totals().accesses(), 3 + 4 * 8 + 1);
+    assert_eq!(instructions.totals().misses, 2);
+    let report = caches.report();
+    assert!(report.starts_with("--- instruction cache (64 words, 1-way, 4-word lines) ---\n"));
+    assert!(report.contains("--- data cache (64 words, 1-way, 4-word lines) ---\nAccesses:  8\n"));
+    assert!(report.contains("  x3003          6 hits          2 misses  25.0%\n"));
+}
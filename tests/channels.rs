@@ -0,0 +1,102 @@
+//! Console channels besides the console of the traps
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    console::SharedConsole,
+    errors::VMError,
+    register::Register,
+    vm::{TrapMessages, VM},
+};
+
+/// Prints "ok" on the console and "dbg\n" on the channel at xFE30
+const TWO_CHANNELS: &str = ".ORIG x3000
+         LEA R0, USER
+         PUTS
+         LEA R1, DEBUG
+LOOP     LDR R0, R1, #0
+         BRz DONE
+         STI R0, TDR
+         ADD R1, R1, #1
+         BRnzp LOOP
+DONE     HALT
+TDR      .FILL xFE36
+USER     .STRINGZ \"ok\"
+DEBUG    .STRINGZ \"dbg\\n\"
+         .END";
+
+fn machine(console: &SharedConsole, debug: &SharedConsole) -> VM {
+    let mut vm = VM::builder()
+        .console(Box::new(console.clone()))
+        .trap_messages(TrapMessages::SILENT)
+        .build()
+        .unwrap();
+    vm.add_channel("debug", 0xFE30, Box::new(debug.clone()))
+        .unwrap();
+    vm
+}
+
+#[test]
+fn output_goes_to_the_channel_written() {
+    let (console, debug) = (SharedConsole::new(), SharedConsole::new());
+    let mut vm = machine(&console, &debug);
+    vm.load_asm_str(TWO_CHANNELS).unwrap();
+    vm.run().unwrap();
+    assert_eq!(console.take_output(), "ok");
+    assert_eq!(debug.take_output(), "dbg\n");
+    assert_eq!(
+        vm.memory().mmio_map().region_at(0xFE36).unwrap().name,
+        "debug"
+    );
+}
+
+#[test]
+fn channels_receive_their_own_keys() {
+    let (console, debug) = (SharedConsole::new(), SharedConsole::new());
+    console.push_input(*b"k");
+    debug.push_input(*b"d");
+    let mut vm = machine(&console, &debug);
+    vm.load_asm_str(
+        ".ORIG x3000
+POLL     LDI R1, RSR
+         BRzp POLL
+         LDI R0, RDR
+         LDI R1, RSR
+         LDI R2, TSR
+         HALT
+RSR      .FILL xFE30
+RDR      .FILL xFE32
+TSR      .FILL xFE34
+         .END",
+    )
+    .unwrap();
+    vm.run().unwrap();
+    assert_eq!(vm.register(Register::R0), u16::from(b'd'));
+    assert_eq!(vm.register(Register::R1), 0);
+    assert_eq!(vm.register(Register::R2), 0x8000);
+    assert!(console.has_input());
+    // the status registers are updated by the channel, not the program
+    assert_eq!(vm.metrics().memory_writes, 0);
+}
+
+#[test]
+fn channels_need_free_device_registers() {
+    let mut vm = VM::new();
+    let error = vm
+        .add_channel("low", 0x3000, Box::new(SharedConsole::new()))
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Channel `low` at x3000 is not in the device registers"
+    );
+    assert!(vm
+        .add_channel("end", 0xFFFC, Box::new(SharedConsole::new()))
+        .is_err());
+    assert!(matches!(
+        vm.add_channel("keys", 0xFE00, Box::new(SharedConsole::new())),
+        Err(VMError::DeviceConflict(_))
+    ));
+    vm.add_channel("debug", 0xFE30, Box::new(SharedConsole::new()))
+        .unwrap();
+    vm.detach_device("debug").unwrap();
+    assert!(!vm.memory().mmio_map().contains_device("debug"));
+}
@@ -0,0 +1,60 @@
+//! Runs paced to an instruction or cycle rate
+#![allow(clippy::unwrap_used)]
+
+use std::time::{Duration, Instant};
+
+use lc3_vm::{
+    clock::Speed,
+    console::NullConsole,
+    cycles::CycleModel,
+    vm::{TrapMessages, VM},
+};
+
+/// Runs 2 instructions per pass of a loop of 200 passes
+const COUNTDOWN: &str = ".ORIG x3000
+         LD R0, PASSES
+LOOP     ADD R0, R0, #-1
+         BRp LOOP
+         HALT
+PASSES   .FILL #200
+         .END";
+
+/// How long running the countdown at `speed` took, and the cycles it took
+fn run(speed: Speed, model: Option<CycleModel>) -> (Duration, u64) {
+    let mut vm = VM::builder()
+        .console(Box::new(NullConsole))
+        .trap_messages(TrapMessages::SILENT)
+        .speed(speed)
+        .build()
+        .unwrap();
+    vm.set_cycle_model(model);
+    vm.load_asm_str(COUNTDOWN).unwrap();
+    let start = Instant::now();
+    vm.run().unwrap();
+    (start.elapsed(), vm.metrics().cycles)
+}
+
+#[test]
+fn instruction_rates_throttle() {
+    // 400 instructions at 2000 per second take at least 200 ms
+    let (elapsed, _) = run(Speed::InstructionsPerSecond(2000), None);
+    assert!(elapsed >= Duration::from_millis(150), "{elapsed:?}");
+}
+
+#[test]
+fn cycle_rates_throttle_the_estimated_cycles() {
+    let (elapsed, cycles) = run(Speed::CyclesPerSecond(10_000), Some(CycleModel::LC3));
+    assert!(cycles > 2000, "{cycles}");
+    let expected = Duration::from_secs(cycles) / 10_000;
+    assert!(
+        elapsed >= expected * 3 / 4,
+        "{elapsed:?} for {cycles} cycles"
+    );
+}
+
+#[test]
+fn cycle_rates_count_instructions_without_a_cycle_model() {
+    let (elapsed, cycles) = run(Speed::CyclesPerSecond(2000), None);
+    assert_eq!(cycles, 0);
+    assert!(elapsed >= Duration::from_millis(150), "{elapsed:?}");
+}
@@ -0,0 +1,450 @@
+//! Translation between programs and the console: the key map, echo, line
+//! endings and encodings
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    ansi::{Command, Erase},
+    console::Console,
+};
+use lc3_vm::{
+    console::{ConsoleConfig, Encoding, KeyBinding, KeyMap, Newline, SharedConsole, CP437},
+    errors::VMError,
+    vm::{StopReason, TrapMessages, VM},
+};
+use std::{cell::RefCell, rc::Rc};
+
+/// Reads three keys with GETC into x3100-x3102
+const READ_KEYS: &str = ".ORIG x3000
+         LD R1, KEYS
+         GETC
+         STR R0, R1, #0
+         GETC
+         STR R0, R1, #1
+         GETC
+         STR R0, R1, #2
+         HALT
+KEYS     .FILL x3100
+         .END";
+
+fn machine(source: &str, config: ConsoleConfig, input: &[u8]) -> VM {
+    let console = SharedConsole::new();
+    console.push_input(input.iter().copied());
+    let mut vm = VM::builder()
+        .console(Box::new(console))
+        .console_config(config)
+        .build()
+        .unwrap();
+    vm.load_asm_str(source).unwrap();
+    vm
+}
+
+fn key_map(bindings: &[&str]) -> ConsoleConfig {
+    let mut key_map = KeyMap::new();
+    for binding in bindings {
+        key_map.parse_binding(binding).unwrap();
+    }
+    ConsoleConfig {
+        key_map,
+        ..ConsoleConfig::default()
+    }
+}
+
+fn keys_read(vm: &VM) -> [u16; 3] {
+    [vm.peek(0x3100), vm.peek(0x3101), vm.peek(0x3102)]
+}
+
+#[test]
+fn escape_sequences_become_single_codes() {
+    let config = key_map(&["up=x80", "left=a"]);
+    let mut vm = machine(READ_KEYS, config, b"\x1b[Aq\x1b[D");
+    vm.run().unwrap();
+    assert_eq!(keys_read(&vm), [0x80, 0x71, 0x61]);
+}
+
+#[test]
+fn unbound_sequences_are_delivered_unchanged() {
+    let config = key_map(&["up=x80"]);
+    let mut vm = machine(READ_KEYS, config, b"\x1b[Z");
+    vm.run().unwrap();
+    assert_eq!(keys_read(&vm), [0x1B, 0x5B, 0x5A]);
+}
+
+#[test]
+fn the_break_key_stops_at_the_trap_waiting_for_it() {
+    let config = key_map(&["f10=break", "x="]);
+    let mut vm = machine(READ_KEYS, config, b"a\x1b[21~xbc");
+    assert_eq!(vm.step().unwrap(), StopReason::Step);
+    assert_eq!(vm.step().unwrap(), StopReason::Step);
+    assert_eq!(vm.step().unwrap(), StopReason::Step);
+    assert_eq!(vm.step().unwrap(), StopReason::Break);
+    assert_eq!(vm.pc(), 0x3003);
+    // the ignored x is skipped when GETC runs again
+    vm.run().unwrap();
+    assert!(vm.is_halted());
+    assert_eq!(keys_read(&vm), [0x61, 0x62, 0x63]);
+}
+
+#[test]
+fn the_break_key_stops_a_run_without_halting() {
+    let config = key_map(&["f10=break"]);
+    let mut vm = machine(READ_KEYS, config, b"a\x1b[21~");
+    vm.run().unwrap();
+    assert!(!vm.is_halted());
+    assert_eq!(vm.pc(), 0x3003);
+}
+
+#[test]
+fn bindings_are_parsed_from_key_and_value() {
+    let mut key_map = KeyMap::new();
+    key_map.parse_binding("==x3D").unwrap();
+    key_map.parse_binding("F1=help").unwrap();
+    assert_eq!(key_map.get(b"="), Some(&KeyBinding::Keys(vec![0x3D])));
+    assert_eq!(
+        key_map.get(b"\x1bOP"),
+        Some(&KeyBinding::Keys(b"help".to_vec()))
+    );
+    assert!(key_map.extends(b"\x1bO"));
+    assert!(matches!(
+        key_map.parse_binding("up"),
+        Err(VMError::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        key_map.parse_binding("pageup=x80"),
+        Err(VMError::InvalidArgument(_))
+    ));
+}
+
+const PROMPT: &str = ".ORIG x3000
+         IN
+         GETC
+         HALT
+         .END";
+
+fn output(config: ConsoleConfig) -> String {
+    let console = SharedConsole::new();
+    console.push_input(*b"ab");
+    let mut vm = VM::builder()
+        .console(Box::new(console.clone()))
+        .console_config(config)
+        .build()
+        .unwrap();
+    vm.load_asm_str(PROMPT).unwrap();
+    vm.run().unwrap();
+    console.take_output()
+}
+
+#[test]
+fn in_echoes_its_key_and_a_newline_but_getc_does_not() {
+    assert_eq!(
+        output(ConsoleConfig::default()),
+        "Enter a character: a\nHALT\n"
+    );
+}
+
+#[test]
+fn echo_is_chosen_for_each_trap() {
+    let config = ConsoleConfig {
+        getc_echo: true,
+        in_echo: false,
+        ..ConsoleConfig::default()
+    };
+    assert_eq!(output(config), "Enter a character: bHALT\n");
+}
+
+/// Reads three keys, then prints `A`, CR, `B`, CR LF
+const LINES: &str = ".ORIG x3000
+         LD R1, KEYS
+         GETC
+         STR R0, R1, #0
+         GETC
+         STR R0, R1, #1
+         GETC
+         STR R0, R1, #2
+         LEA R0, TEXT
+         PUTS
+         HALT
+KEYS     .FILL x3100
+TEXT     .FILL x41
+         .FILL x0D
+         .FILL x42
+         .FILL x0D
+         .FILL x0A
+         .FILL 0
+         .END";
+
+/// The keys read and the output of `LINES` given `input`
+fn translated(newline: Newline, input: &[u8]) -> ([u16; 3], String) {
+    let console = SharedConsole::new();
+    console.push_input(input.iter().copied());
+    let config = ConsoleConfig {
+        newline,
+        ..ConsoleConfig::default()
+    };
+    let mut vm = VM::builder()
+        .console(Box::new(console.clone()))
+        .console_config(config)
+        .build()
+        .unwrap();
+    vm.load_asm_str(LINES).unwrap();
+    vm.run().unwrap();
+    (keys_read(&vm), console.take_output())
+}
+
+#[test]
+fn line_endings_are_left_alone_by_default() {
+    assert_eq!(
+        translated(Newline::Lf, b"a\nb"),
+        ([0x61, 0x0A, 0x62], String::from("A\rB\r\nHALT\n"))
+    );
+}
+
+#[test]
+fn carriage_returns_stand_for_newlines() {
+    assert_eq!(
+        translated(Newline::Cr, b"a\nb"),
+        ([0x61, 0x0D, 0x62], String::from("A\nB\n\nHALT\n"))
+    );
+}
+
+#[test]
+fn enter_becomes_cr_lf() {
+    assert_eq!(
+        translated(Newline::CrLf, b"\nb"),
+        ([0x0D, 0x0A, 0x62], String::from("AB\nHALT\n"))
+    );
+}
+
+/// Prints xC3 xA9 (`é` in UTF-8), xC3 alone, then `!` and xDB, with OUT so
+/// the bytes are printed one by one
+const BYTES: &str = ".ORIG x3000
+         LEA R1, TEXT
+LOOP     LDR R0, R1, #0
+         BRz DONE
+         OUT
+         ADD R1, R1, #1
+         BRnzp LOOP
+DONE     HALT
+TEXT     .FILL xC3
+         .FILL xA9
+         .FILL xC3
+         .FILL x21
+         .FILL xDB
+         .FILL 0
+         .END";
+
+fn printed(encoding: Encoding) -> Result<String, VMError> {
+    let console = SharedConsole::new();
+    let config = ConsoleConfig {
+        encoding,
+        ..ConsoleConfig::default()
+    };
+    let mut vm = VM::builder()
+        .console(Box::new(console.clone()))
+        .console_config(config)
+        .build()
+        .unwrap();
+    vm.load_asm_str(BYTES).unwrap();
+    vm.run()?;
+    Ok(console.take_output())
+}
+
+#[test]
+fn only_ascii_is_printed_by_default() {
+    assert!(matches!(
+        printed(Encoding::default()),
+        Err(VMError::InvalidCharacter(_))
+    ));
+}
+
+#[test]
+fn bytes_are_decoded_by_the_chosen_encoding() {
+    assert_eq!(printed(Encoding::Latin1).unwrap(), "Ã©Ã!ÛHALT\n");
+    assert_eq!(printed(Encoding::CodePage(&CP437)).unwrap(), "├⌐├!█HALT\n");
+    // the lone xC3 and xDB are invalid, xDB because it is never completed
+    assert_eq!(printed(Encoding::Utf8).unwrap(), "é\u{FFFD}!HALT\n");
+}
+
+#[test]
+fn resets_drop_characters_printed_in_part() {
+    let console = SharedConsole::new();
+    let config = ConsoleConfig {
+        encoding: Encoding::Utf8,
+        ..ConsoleConfig::default()
+    };
+    let mut vm = VM::builder()
+        .console(Box::new(console.clone()))
+        .console_config(config)
+        .trap_messages(TrapMessages::SILENT)
+        .build()
+        .unwrap();
+    // the first byte of a two byte sequence
+    vm.load_asm_str(&printing("\u{C3}")).unwrap();
+    vm.step().unwrap();
+    vm.step().unwrap();
+    vm.reset(false);
+    vm.load_asm_str(&printing("A")).unwrap();
+    vm.run().unwrap();
+    assert_eq!(console.take_output(), "A");
+}
+
+/// A program printing `text` with PUTS, its characters as `.FILL`s
+fn printing(text: &str) -> String {
+    let words: String = text
+        .chars()
+        .map(|character| format!("         .FILL x{:02X}\n", u32::from(character)))
+        .collect();
+    format!(".ORIG x3000\n         LEA R0, TEXT\n         PUTS\n         HALT\nTEXT\n{words}         .FILL 0\n         .END")
+}
+
+/// Console recording text and commands, in order
+#[derive(Clone, Default)]
+struct CommandConsole(Rc<RefCell<Vec<Result<char, Command>>>>);
+
+impl Console for CommandConsole {
+    fn read_key(&mut self) -> Result<u8, VMError> {
+        Ok(0)
+    }
+
+    fn poll_key(&mut self) -> Result<Option<u8>, VMError> {
+        Ok(None)
+    }
+
+    fn command(&mut self, command: &Command) -> Result<(), VMError> {
+        self.0.borrow_mut().push(Err(command.clone()));
+        Ok(())
+    }
+
+    fn write_char(&mut self, character: char) -> Result<(), VMError> {
+        self.0.borrow_mut().push(Ok(character));
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), VMError> {
+        Ok(())
+    }
+}
+
+fn commands(text: &str, ansi: bool) -> Vec<Result<char, Command>> {
+    let console = CommandConsole::default();
+    let config = ConsoleConfig {
+        ansi,
+        ..ConsoleConfig::default()
+    };
+    let mut vm = VM::builder()
+        .console(Box::new(console.clone()))
+        .console_config(config)
+        .build()
+        .unwrap();
+    vm.load_asm_str(&printing(text)).unwrap();
+    vm.run().unwrap();
+    let printed = console.0.borrow().clone();
+    printed
+}
+
+#[test]
+fn escape_sequences_become_commands() {
+    assert_eq!(
+        commands("\x1b[2J\x1b[5;10HX\x1b[1;31m\x1b[A\x1b[?25l\x1b[K", true),
+        [
+            Err(Command::EraseDisplay(Erase::All)),
+            Err(Command::CursorPosition { row: 5, column: 10 }),
+            Ok('X'),
+            Err(Command::Style(vec![1, 31])),
+            Err(Command::CursorUp(1)),
+            Err(Command::ShowCursor(false)),
+            Err(Command::EraseLine(Erase::ToEnd)),
+            Ok('H'),
+            Ok('A'),
+            Ok('L'),
+            Ok('T'),
+            Ok('\n'),
+        ]
+    );
+}
+
+#[test]
+fn unknown_sequences_are_kept_verbatim() {
+    let printed = commands("\x1b[5;1;2H\x1b7", true);
+    assert_eq!(
+        printed.first(),
+        Some(&Err(Command::Other(String::from("[5;1;2H"))))
+    );
+    assert_eq!(
+        printed.get(1),
+        Some(&Err(Command::Other(String::from("7"))))
+    );
+}
+
+#[test]
+fn escape_sequences_are_text_without_ansi() {
+    assert_eq!(
+        commands("\x1b[H", false).get(..3),
+        Some(&[Ok('\x1b'), Ok('['), Ok('H')][..])
+    );
+}
+
+#[test]
+fn consoles_write_commands_as_escape_sequences_by_default() {
+    let console = SharedConsole::new();
+    let config = ConsoleConfig {
+        ansi: true,
+        ..ConsoleConfig::default()
+    };
+    let mut vm = VM::builder()
+        .console(Box::new(console.clone()))
+        .console_config(config)
+        .build()
+        .unwrap();
+    vm.load_asm_str(&printing("\x1b[H\x1b[m!")).unwrap();
+    vm.run().unwrap();
+    assert_eq!(console.take_output(), "\x1b[1;1H\x1b[0m!HALT\n");
+}
+
+/// Reads four events from KBDR, polling KBSR, into x3100-x3103
+const EVENTS: &str = ".ORIG x3000
+         LD R1, BUFFER
+         AND R2, R2, #0
+         ADD R2, R2, #4
+POLL     LDI R0, STATUS
+         BRzp POLL
+         LDI R0, DATA
+         STR R0, R1, #0
+         ADD R1, R1, #1
+         ADD R2, R2, #-1
+         BRp POLL
+         HALT
+BUFFER   .FILL x3100
+STATUS   .FILL xFE00
+DATA     .FILL xFE02
+         .END";
+
+fn scancodes(input: &[u8]) -> [u16; 4] {
+    let config = ConsoleConfig {
+        scancodes: true,
+        ..ConsoleConfig::default()
+    };
+    let mut vm = machine(EVENTS, config, input);
+    vm.set_instruction_limit(Some(10_000)).unwrap();
+    vm.run().unwrap();
+    [
+        vm.peek(0x3100),
+        vm.peek(0x3101),
+        vm.peek(0x3102),
+        vm.peek(0x3103),
+    ]
+}
+
+#[test]
+fn keys_are_pressed_and_released() {
+    assert_eq!(scancodes(b"ab"), [0x1E, 0x9E, 0x30, 0xB0]);
+}
+
+#[test]
+fn shifted_characters_hold_shift() {
+    assert_eq!(scancodes(b"A"), [0x2A, 0x1E, 0x9E, 0xAA]);
+}
+
+#[test]
+fn arrows_are_extended_keys() {
+    assert_eq!(scancodes(b"\x1b[A\x1bOP"), [0x148, 0x1C8, 0x3B, 0xBB]);
+}
@@ -0,0 +1,113 @@
+//! Cycle estimates of `--cycles` and the rates counting them
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    clock::TimeSource,
+    console::SharedConsole,
+    cycles::CycleModel,
+    register::Register,
+    vm::{TrapMode, VM},
+};
+
+/// Loads a word, increments it and stores it back
+const INCREMENT: &str = ".ORIG x3000
+         LD R0, VALUE
+         ADD R0, R0, #1
+         ST R0, VALUE
+         HALT
+VALUE    .FILL #5
+         .END";
+
+fn vm(model: CycleModel) -> VM {
+    VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .cycle_model(model)
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn models_are_parsed_from_settings() {
+    let model: CycleModel = "lc3, read=2\n# slow stores\nst=10,write=3".parse().unwrap();
+    assert_eq!(model.opcodes[0x2], 7);
+    assert_eq!(model.opcodes[0x3], 10);
+    assert_eq!((model.read, model.write), (2, 3));
+    assert_eq!("".parse::<CycleModel>().unwrap(), CycleModel::UNIFORM);
+    assert!("fast".parse::<CycleModel>().is_err());
+    assert!("mul=3".parse::<CycleModel>().is_err());
+    assert!("add=-1".parse::<CycleModel>().is_err());
+}
+
+#[test]
+fn opcodes_and_memory_accesses_add_their_costs() {
+    let mut vm = vm("ld=3,st=4,read=2,write=5".parse().unwrap());
+    vm.load_asm_str(INCREMENT).unwrap();
+    vm.step().unwrap();
+    assert_eq!(vm.metrics().cycles, 5);
+    vm.step().unwrap();
+    assert_eq!(vm.metrics().cycles, 6);
+    vm.step().unwrap();
+    assert_eq!(vm.metrics().cycles, 15);
+}
+
+#[test]
+fn the_uniform_model_counts_instructions() {
+    let mut vm = vm(CycleModel::UNIFORM);
+    vm.load_asm_str(INCREMENT).unwrap();
+    vm.run().unwrap();
+    assert_eq!(vm.metrics().cycles, 4);
+    assert_eq!(vm.metrics().instructions, 4);
+}
+
+#[test]
+fn microstepping_estimates_the_same_cycles() {
+    let mut stepped = vm(CycleModel::LC3);
+    stepped.load_asm_str(INCREMENT).unwrap();
+    let mut microstepped = vm(CycleModel::LC3);
+    microstepped.load_asm_str(INCREMENT).unwrap();
+    for _ in 0..3 {
+        stepped.step().unwrap();
+        while {
+            microstepped.micro_step().unwrap();
+            !microstepped.microstate().at_instruction_boundary()
+        } {}
+    }
+    assert_eq!(stepped.metrics().cycles, 19);
+    assert_eq!(microstepped.metrics().cycles, 19);
+}
+
+#[test]
+fn no_cycles_are_counted_without_a_model() {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    vm.load_asm_str(INCREMENT).unwrap();
+    vm.run().unwrap();
+    assert_eq!(vm.metrics().cycles, 0);
+}
+
+#[test]
+fn virtual_time_can_count_cycles() {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .trap_mode(TrapMode::Extended)
+        .cycle_model(CycleModel::LC3)
+        .time_source(TimeSource::VirtualCycles(1000))
+        .build()
+        .unwrap();
+    // LD, then 40 ADD and BR of 5 cycles each before TIME
+    vm.load_asm_str(
+        ".ORIG x3000
+         LD R2, COUNT
+LOOP     ADD R2, R2, #-1
+         BRp LOOP
+         TRAP x28
+         HALT
+COUNT    .FILL #40
+         .END",
+    )
+    .unwrap();
+    vm.run().unwrap();
+    assert_eq!(vm.register(Register::R0), 407);
+}
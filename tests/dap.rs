@@ -0,0 +1,169 @@
+//! The debug adapter driven through a session like an editor's
+#![allow(clippy::unwrap_used)]
+
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, PipeReader, PipeWriter, Read, Write},
+    path::PathBuf,
+    thread::{self, JoinHandle},
+};
+
+use lc3_vm::{assembler::assemble, dap, errors::VMError};
+use serde_json::{json, Value};
+
+/// Prints "AB"
+const PROGRAM: &str = ".ORIG x3000
+         LD R0, LETTER
+         OUT
+         ADD R0, R0, #1
+         OUT
+         HALT
+LETTER   .FILL x41
+         .END";
+
+/// An editor talking to an adapter on its own thread
+struct Client {
+    requests: PipeWriter,
+    messages: BufReader<PipeReader>,
+    server: JoinHandle<Result<(), VMError>>,
+    sequence: u64,
+}
+
+impl Client {
+    fn start() -> Self {
+        let (input, requests) = io::pipe().unwrap();
+        let (messages, output) = io::pipe().unwrap();
+        let server = thread::spawn(move || dap::serve_streams(input, output));
+        Client {
+            requests,
+            messages: BufReader::new(messages),
+            server,
+            sequence: 0,
+        }
+    }
+
+    fn send(&mut self, command: &str, arguments: Value) {
+        self.sequence = self.sequence.saturating_add(1);
+        let body = json!({
+            "seq": self.sequence,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        })
+        .to_string();
+        write!(
+            self.requests,
+            "Content-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+    }
+
+    fn receive(&mut self) -> Value {
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            self.messages.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            length = line
+                .strip_prefix("Content-Length: ")
+                .unwrap()
+                .parse()
+                .unwrap();
+        }
+        let mut body = vec![0; length];
+        self.messages.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    /// The response or event name of the next messages, up to `last`
+    fn receive_until(&mut self, last: &str) -> Vec<Value> {
+        let mut messages = Vec::new();
+        loop {
+            let message = self.receive();
+            let done = name(&message) == last;
+            messages.push(message);
+            if done {
+                return messages;
+            }
+        }
+    }
+}
+
+/// The command of a response or the event of an event
+fn name(message: &Value) -> &str {
+    message
+        .get("command")
+        .or_else(|| message.get("event"))
+        .and_then(Value::as_str)
+        .unwrap()
+}
+
+/// The value at `path` in the body of `message`
+fn body<'a>(message: &'a Value, path: &str) -> &'a Value {
+    message.pointer(&format!("/body/{path}")).unwrap()
+}
+
+/// The program assembled next to its source
+fn program() -> PathBuf {
+    let directory = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("dap");
+    fs::create_dir_all(&directory).unwrap();
+    let image = directory.join("letters.obj");
+    fs::write(&image, assemble(PROGRAM).unwrap().image()).unwrap();
+    fs::write(directory.join("letters.asm"), PROGRAM).unwrap();
+    image
+}
+
+#[test]
+fn breakpoints_stop_a_launched_program() {
+    let mut client = Client::start();
+    client.send("initialize", json!({ "adapterID": "lc3" }));
+    client.send("launch", json!({ "program": program() }));
+    let messages = client.receive_until("initialized");
+    let names: Vec<&str> = messages.iter().map(name).collect();
+    assert_eq!(names, ["initialize", "launch", "initialized"]);
+    assert!(messages
+        .iter()
+        .all(|message| message.get("success") != Some(&json!(false))));
+
+    // the second OUT, on line 5
+    client.send(
+        "setBreakpoints",
+        json!({ "source": { "path": "letters.asm" }, "breakpoints": [{ "line": 5 }] }),
+    );
+    let response = client.receive();
+    assert_eq!(
+        body(&response, "breakpoints"),
+        &json!([{ "verified": true, "line": 5 }])
+    );
+
+    client.send("configurationDone", Value::Null);
+    let messages = client.receive_until("stopped");
+    let stopped = messages.last().unwrap();
+    assert_eq!(body(stopped, "reason"), "breakpoint");
+    let output: String = messages
+        .iter()
+        .filter(|message| name(message) == "output")
+        .filter_map(|message| body(message, "output").as_str())
+        .collect();
+    assert_eq!(output, "A");
+
+    client.send("stackTrace", json!({ "threadId": 1 }));
+    let trace = client.receive();
+    assert_eq!(body(&trace, "stackFrames/0/line"), 5);
+
+    client.send("continue", json!({ "threadId": 1 }));
+    let messages = client.receive_until("terminated");
+    let names: Vec<&str> = messages.iter().map(name).collect();
+    assert_eq!(names, ["continue", "output", "exited", "terminated"]);
+    let output = body(messages.get(1).unwrap(), "output").as_str().unwrap();
+    // followed by the halt message
+    assert!(output.starts_with('B'), "{output}");
+
+    client.send("disconnect", Value::Null);
+    assert_eq!(name(&client.receive()), "disconnect");
+    client.server.join().unwrap().unwrap();
+}
@@ -0,0 +1,80 @@
+//! Datapath diagrams of `--datapath`
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    datapath::{phases, render, Element, Phase},
+    instructions::Instruction,
+    vm::{Conformance, Profile},
+};
+
+fn phases_of(raw: u16, conformance: &Conformance) -> Vec<Phase> {
+    phases(&Instruction::decode(raw), raw, conformance)
+        .iter()
+        .map(|activity| activity.phase)
+        .collect()
+}
+
+#[test]
+fn instructions_skip_the_phases_they_do_not_need() {
+    let conformance = Conformance::default();
+    // ADD R0, R1, #2
+    assert_eq!(
+        phases_of(0x1062, &conformance),
+        [
+            Phase::Fetch,
+            Phase::Decode,
+            Phase::FetchOperands,
+            Phase::Execute,
+            Phase::StoreResult
+        ]
+    );
+    // LDR R2, R6, #1
+    assert_eq!(
+        phases_of(0x6581, &conformance),
+        [
+            Phase::Fetch,
+            Phase::Decode,
+            Phase::EvaluateAddress,
+            Phase::FetchOperands,
+            Phase::StoreResult
+        ]
+    );
+    // BRz #3
+    assert_eq!(
+        phases_of(0x0403, &conformance),
+        [Phase::Fetch, Phase::Decode, Phase::Execute]
+    );
+}
+
+#[test]
+fn lea_only_uses_the_condition_codes_when_it_sets_them() {
+    let uses_cc = |conformance: &Conformance| {
+        // LEA R0, #4
+        let activities = phases(&Instruction::decode(0xE004), 0xE004, conformance);
+        activities
+            .iter()
+            .any(|activity| activity.elements.contains(&Element::Cc))
+    };
+    assert!(uses_cc(&Conformance::default()));
+    assert!(!uses_cc(&Profile::StrictSpec.conformance()));
+}
+
+#[test]
+fn diagrams_show_the_active_elements_in_columns() {
+    let activities = phases(
+        &Instruction::decode(0x1062),
+        0x1062,
+        &Conformance::default(),
+    );
+    assert_eq!(
+        render(0x3001, 0x1062, &activities),
+        "\
+x3001  ADD R0, R1, #2
+  fetch            PC  IR  MAR MDR MEM ·   ·    ·   ·    MAR ← PC, PC ← PC + 1, MDR ← M[MAR], IR ← MDR
+  decode           ·   IR  ·   ·   ·   ·   ·    ·   ·    opcode 0001 selects ADD
+  fetch operands   ·   ·   ·   ·   ·   ·   ·    REG ·    R1
+  execute          ·   ·   ·   ·   ·   ALU ·    ·   ·    R1 + #2
+  store result     ·   ·   ·   ·   ·   ·   ·    REG CC   R0 ← R1 + #2, CC ← NZP(R0)
+"
+    );
+}
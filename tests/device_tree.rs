@@ -0,0 +1,91 @@
+//! Machines set up from a device tree
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    console::SharedConsole,
+    device_tree::{DeviceSpec, DeviceTree},
+    vm::{UnmappedAccess, TMPR, VM},
+};
+
+const MACHINE: &str = r#"
+unmapped = "zero"
+
+[[device]]
+type = "display"
+columns = 40
+rows = 12
+
+[[device]]
+type = "timer"
+period = 3
+
+[[device]]
+type = "rng"
+seed = 42
+"#;
+
+fn machine() -> VM {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    DeviceTree::parse(MACHINE).unwrap().apply(&mut vm).unwrap();
+    vm
+}
+
+#[test]
+fn the_tree_lists_the_devices_in_order() {
+    let tree = DeviceTree::parse(MACHINE).unwrap();
+    assert_eq!(tree.unmapped, UnmappedAccess::Zero);
+    assert_eq!(
+        tree.devices,
+        [
+            DeviceSpec::Display {
+                columns: 40,
+                rows: 12
+            },
+            DeviceSpec::Timer { period: 3 },
+            DeviceSpec::Rng { seed: 42 },
+        ]
+    );
+    let vm = machine();
+    assert_eq!(
+        vm.memory().mmio_map().to_string(),
+        "xF000-xF1DF  display\n\
+         xFE00-xFE02  keyboard\n\
+         xFE10        display\n\
+         xFE1C-xFE1E  timer\n\
+         xFE22        rng\n\
+         xFFFC        psr\n"
+    );
+    assert_eq!(vm.unmapped_access(), UnmappedAccess::Zero);
+    assert_eq!(vm.peek(TMPR), 3);
+}
+
+#[test]
+fn mistakes_in_the_tree_are_reported() {
+    for (text, message) in [
+        (
+            "[[device]]\ntype = \"lamp\"",
+            "Invalid device tree: device 1: unknown device type lamp",
+        ),
+        (
+            "[[device]]\ntype = \"timer\"",
+            "Invalid device tree: device 1 has no `period`",
+        ),
+        (
+            "[[device]]\ntype = \"rng\"\nperiod = 3",
+            "Invalid device tree: device 1: `period` is not a setting of rng",
+        ),
+        (
+            "unmapped = \"ignore\"",
+            "Invalid device tree: `unmapped` must be memory, zero, bus-error or fail, not ignore",
+        ),
+        ("speed = 3", "Invalid device tree: Unknown setting `speed`"),
+    ] {
+        assert_eq!(DeviceTree::parse(text).unwrap_err().to_string(), message);
+    }
+    let mut vm = VM::new();
+    let display = "[[device]]\ntype = \"display\"\ncolumns = 100\nrows = 100";
+    assert!(DeviceTree::parse(display).unwrap().apply(&mut vm).is_err());
+}
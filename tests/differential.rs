@@ -0,0 +1,309 @@
+//! Differential tests: every program runs in lockstep in the VM and in the
+//! plain interpreter of `tests/reference`. Registers, condition codes, PC,
+//! memory and output are compared every `LC3_DIFF_INTERVAL` instructions
+//! (1000 by default); on a mismatch the run is replayed one instruction at
+//! a time to report the first instruction after which the two disagree,
+//! with its disassembly.
+//!
+//! Any other image can be checked with
+//! `LC3_DIFF_IMAGE=prog.obj [LC3_DIFF_INPUT=keys] cargo test --test differential`.
+//!
+//! lc3sim is not used as the reference: its traps run the service routines
+//! of its operating system image, so R7, the instruction counts and the
+//! system area of memory differ from this VM by design.
+#![allow(clippy::unwrap_used)]
+
+mod reference;
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use lc3_vm::{console::SharedConsole, disassembler::disassemble, errors::VMError, vm::VM};
+use proptest::prelude::*;
+use reference::Reference;
+
+const DEFAULT_INTERVAL: u64 = 1_000;
+/// Stops programs that never halt instead of hanging the test
+const INSTRUCTION_LIMIT: u64 = 10_000_000;
+/// Memory differences listed in a report before the rest are counted
+const SHOWN_ADDRESSES: usize = 4;
+
+/// The VM and the reference loaded with the same image and keys
+struct Machines {
+    vm: VM,
+    console: SharedConsole,
+    output: String,
+    reference: Reference,
+}
+
+impl Machines {
+    fn new(image: &[u8], input: &[u8]) -> Self {
+        let console = SharedConsole::new();
+        console.push_input(input.iter().copied());
+        let mut vm = VM::builder()
+            .console(Box::new(console.clone()))
+            .build()
+            .unwrap();
+        vm.read_image_bytes(image).unwrap();
+        Machines {
+            vm,
+            console,
+            output: String::new(),
+            reference: Reference::new(image, input).unwrap(),
+        }
+    }
+
+    /// Describes how the two machines differ after both ran, or failed with
+    /// the given results. Once both failed only the failure is compared.
+    fn differences(
+        &mut self,
+        vm_result: Result<(), VMError>,
+        reference_result: Result<(), String>,
+    ) -> Vec<String> {
+        self.output.push_str(&self.console.take_output());
+        match (vm_result, reference_result) {
+            (Ok(()), Ok(())) => {}
+            (Err(_), Err(_)) => return Vec::new(),
+            (Err(error), Ok(())) => return vec![format!("VM failed with {error:?}")],
+            (Ok(()), Err(error)) => return vec![format!("reference failed with {error}")],
+        }
+        let reference = &self.reference;
+        let mut differences = Vec::new();
+        let mut differ = |what: String, got: String, want: String| {
+            if got != want {
+                differences.push(format!("{what} is {got}, reference has {want}"));
+            }
+        };
+        differ(
+            String::from("PC"),
+            format!("x{:04X}", self.vm.pc()),
+            format!("x{:04X}", reference.pc),
+        );
+        for (number, (got, want)) in self
+            .vm
+            .registers()
+            .into_iter()
+            .zip(reference.registers)
+            .enumerate()
+        {
+            differ(
+                format!("R{number}"),
+                format!("x{got:04X}"),
+                format!("x{want:04X}"),
+            );
+        }
+        differ(
+            String::from("condition"),
+            format!("{:03b}", self.vm.psr().condition_codes()),
+            format!("{:03b}", reference.condition),
+        );
+        differ(
+            String::from("halted"),
+            self.vm.is_halted().to_string(),
+            reference.halted.to_string(),
+        );
+        let memory: Vec<(usize, u16, u16)> = self
+            .vm
+            .memory()
+            .read_words(0, reference.memory.len())
+            .zip(reference.memory.iter().copied())
+            .enumerate()
+            .filter(|(_, (got, want))| got != want)
+            .map(|(address, (got, want))| (address, got, want))
+            .collect();
+        for (address, got, want) in memory.iter().take(SHOWN_ADDRESSES) {
+            differ(
+                format!("x{address:04X}"),
+                format!("x{got:04X}"),
+                format!("x{want:04X}"),
+            );
+        }
+        if memory.len() > SHOWN_ADDRESSES {
+            differences.push(format!(
+                "{} more words of memory differ",
+                memory.len().saturating_sub(SHOWN_ADDRESSES)
+            ));
+        }
+        if self.output != reference.output {
+            let common = self
+                .output
+                .chars()
+                .zip(reference.output.chars())
+                .take_while(|(got, want)| got == want)
+                .count();
+            let rest = |output: &str| output.chars().skip(common).take(20).collect::<String>();
+            differences.push(format!(
+                "output continues with {:?} after {common} characters, reference with {:?}",
+                rest(&self.output),
+                rest(&reference.output)
+            ));
+        }
+        differences
+    }
+
+    fn finished(&self) -> bool {
+        self.vm.is_halted() || self.reference.halted
+    }
+}
+
+/// Runs `image` in both machines for at most `limit` instructions,
+/// comparing them every `interval` instructions, and returns how many
+/// instructions were compared or a report of the first divergence
+fn compare(image: &[u8], input: &[u8], interval: u64, limit: u64) -> Result<u64, String> {
+    let mut machines = Machines::new(image, input);
+    let mut executed: u64 = 0;
+    while executed < limit {
+        let vm_result = machines.vm.run_with_fuel(interval).map(|_| ());
+        let failed = vm_result.is_err();
+        let reference_result = machines.reference.run(interval);
+        let differences = machines.differences(vm_result, reference_result);
+        if !differences.is_empty() {
+            return Err(pinpoint(image, input, executed, interval, &differences));
+        }
+        if failed || machines.finished() {
+            return Ok(executed);
+        }
+        executed = executed.saturating_add(interval);
+    }
+    Ok(executed)
+}
+
+/// Replays the first `start` instructions, then steps both machines one
+/// instruction at a time through the next `interval` to find the first one
+/// after which they differ
+fn pinpoint(
+    image: &[u8],
+    input: &[u8],
+    start: u64,
+    interval: u64,
+    chunk_differences: &[String],
+) -> String {
+    let mut machines = Machines::new(image, input);
+    for _ in 0..start {
+        machines.vm.step().unwrap();
+        machines.reference.step().unwrap();
+    }
+    for number in start.saturating_add(1)..=start.saturating_add(interval) {
+        let pc = machines.reference.pc;
+        let raw = machines.reference.peek(pc);
+        let vm_result = machines.vm.step().map(|_| ());
+        let failed = vm_result.is_err();
+        let reference_result = machines.reference.step();
+        let differences = machines.differences(vm_result, reference_result);
+        if !differences.is_empty() {
+            return format!(
+                "diverged after instruction {number}, x{raw:04X} at x{pc:04X} ({}):\n  {}",
+                disassemble(pc, raw),
+                differences.join("\n  ")
+            );
+        }
+        if failed || machines.finished() {
+            break;
+        }
+    }
+    format!(
+        "diverged within instructions {}..={} only when run in chunks of {interval}:\n  {}",
+        start.saturating_add(1),
+        start.saturating_add(interval),
+        chunk_differences.join("\n  ")
+    )
+}
+
+fn interval() -> u64 {
+    env::var("LC3_DIFF_INTERVAL")
+        .ok()
+        .and_then(|interval| interval.parse().ok())
+        .filter(|&interval| interval > 0)
+        .unwrap_or(DEFAULT_INTERVAL)
+}
+
+fn tests_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+fn assert_agree(name: &str, image: &[u8], input: &[u8]) {
+    let result = compare(image, input, interval(), INSTRUCTION_LIMIT);
+    assert!(
+        result.is_ok(),
+        "{name}: {}",
+        result.err().unwrap_or_default()
+    );
+}
+
+#[test]
+fn golden_programs_match_the_reference() {
+    let mut images: Vec<PathBuf> = fs::read_dir(tests_dir().join("golden"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "obj"))
+        .collect();
+    images.sort();
+    for image in images {
+        let input = fs::read(image.with_extension("in")).unwrap_or_default();
+        assert_agree(
+            &image.display().to_string(),
+            &fs::read(&image).unwrap(),
+            &input,
+        );
+    }
+}
+
+#[test]
+fn games_match_the_reference() {
+    for (program, keys) in [
+        ("2048.obj", "adwsq"),
+        ("2048.obj", "ds".repeat(200).as_str()),
+        ("rogue.obj", "wddsdsdddsd"),
+        ("rogue.obj", "ww"),
+    ] {
+        let image = fs::read(tests_dir().join("programs").join(program)).unwrap();
+        assert_agree(program, &image, keys.as_bytes());
+    }
+}
+
+/// Compares the image named by `LC3_DIFF_IMAGE`, if any
+#[test]
+fn image_from_environment_matches_the_reference() {
+    let Some(path) = env::var_os("LC3_DIFF_IMAGE") else {
+        return;
+    };
+    let input = env::var("LC3_DIFF_INPUT").unwrap_or_default();
+    assert_agree(
+        &path.to_string_lossy(),
+        &fs::read(&path).unwrap(),
+        input.as_bytes(),
+    );
+}
+
+#[test]
+fn divergence_reports_the_instruction() {
+    // ADD R1, R1, #1 then HALT, with the reference computing a different sum
+    let image = [0x30, 0x00, 0x12, 0x61, 0xF0, 0x25];
+    let mut machines = Machines::new(&image, b"");
+    machines.reference.registers[1] = 5;
+    machines.vm.step().unwrap();
+    machines.reference.step().unwrap();
+    let differences = machines.differences(Ok(()), Ok(()));
+    assert_eq!(differences, ["R1 is x0001, reference has x0006"]);
+}
+
+proptest! {
+    // Every comparison scans the whole memory, so keep the runs short
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn arbitrary_programs_match_the_reference(
+        words in proptest::collection::vec(any::<u16>(), 1..64),
+        input in proptest::collection::vec(any::<u8>(), 0..4),
+        interval in 8_u64..64,
+    ) {
+        let image: Vec<u8> = std::iter::once(0x3000)
+            .chain(words)
+            .flat_map(u16::to_be_bytes)
+            .collect();
+        let result = compare(&image, &input, interval, 500);
+        prop_assert!(result.is_ok(), "{}", result.err().unwrap_or_default());
+    }
+}
@@ -0,0 +1,96 @@
+//! Whole programs disassembled back to source
+#![allow(clippy::unwrap_used)]
+
+use std::{fs, path::Path};
+
+use lc3_vm::{
+    assembler::assemble,
+    disassembler::{disassemble_program, find_code},
+    symbols::SymbolTable,
+};
+
+fn split(image: &[u8]) -> (u16, Vec<u16>) {
+    let mut words = image
+        .chunks_exact(2)
+        .map(|pair| u16::from_be_bytes(pair.try_into().unwrap()));
+    (words.next().unwrap(), words.collect())
+}
+
+#[test]
+fn images_disassemble_to_source_that_assembles_back() {
+    let tests = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let mut checked = 0;
+    for directory in ["golden", "programs"] {
+        for entry in fs::read_dir(tests.join(directory)).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().and_then(|e| e.to_str()) != Some("obj") {
+                continue;
+            }
+            let image = fs::read(&path).unwrap();
+            let (origin, words) = split(&image);
+            let source = disassemble_program(origin, &words, &SymbolTable::default());
+            let assembly = assemble(&source).unwrap();
+            assert_eq!(assembly.image(), image, "{}", path.display());
+            checked += 1;
+        }
+    }
+    assert!(checked > 2);
+}
+
+#[test]
+fn unreachable_words_are_data() {
+    let source = "\
+        .ORIG x3000
+        LEA R0, TEXT
+        PUTS
+        JSR SHOW
+        HALT
+COUNT   .FILL #3
+TEXT    .STRINGZ \"Hi\\n\"
+BUFFER  .BLKW 2
+SHOW    LD R1, COUNT
+        RET
+        .END";
+    let assembly = assemble(source).unwrap();
+    let code = find_code(assembly.origin, &assembly.words, &SymbolTable::default());
+    assert_eq!(
+        code,
+        [true, true, true, true, false, false, false, false, false, false, false, true, true]
+    );
+    assert_eq!(
+        disassemble_program(assembly.origin, &assembly.words, &assembly.symbols),
+        "        .ORIG x3000
+        LEA R0, TEXT
+        PUTS
+        JSR SHOW
+        HALT
+COUNT   .FILL x0003
+TEXT    .STRINGZ \"Hi\\n\"
+BUFFER  .BLKW #2
+SHOW    LD R1, COUNT
+        RET
+        .END
+"
+    );
+}
+
+#[test]
+fn symbols_are_followed_unless_used_as_data() {
+    // HANDLER is only reached through a table, DATA looks like an ADD
+    let source = "\
+        .ORIG x3000
+        LDI R0, TABLE
+        JSRR R0
+        LD R1, DATA
+        HALT
+TABLE   .FILL HANDLER
+DATA    .FILL x1021
+HANDLER ADD R2, R2, #1
+        RET
+        .END";
+    let assembly = assemble(source).unwrap();
+    let without = find_code(assembly.origin, &assembly.words, &SymbolTable::default());
+    assert_eq!(without.get(6), Some(&false));
+    let with = find_code(assembly.origin, &assembly.words, &assembly.symbols);
+    assert_eq!(with, [true, true, true, true, false, false, true, true]);
+}
@@ -0,0 +1,99 @@
+//! The memory mapped character display
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    console::SharedConsole,
+    display::{Display, COLUMNS, DISPLAY_START},
+    vm::{TrapMessages, VM},
+};
+
+/// STI R0 into DFR, then HALT
+const PRESENT: [u16; 4] = [0x3000, 0xB001, 0xF025, 0xFE10];
+
+fn image() -> Vec<u8> {
+    PRESENT.iter().flat_map(|word| word.to_be_bytes()).collect()
+}
+
+fn display_vm(console: &SharedConsole) -> VM {
+    VM::builder()
+        .console(Box::new(console.clone()))
+        .trap_messages(TrapMessages::SILENT)
+        .display()
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn writing_dfr_draws_the_grid() {
+    let console = SharedConsole::new();
+    let mut vm = display_vm(&console);
+    vm.read_image_bytes(&image()).unwrap();
+    vm.poke(DISPLAY_START, u16::from(b'H')).unwrap();
+    vm.poke(DISPLAY_START + 1, u16::from(b'i')).unwrap();
+    vm.poke(DISPLAY_START + 81, u16::from(b'#')).unwrap();
+    vm.run().unwrap();
+    assert_eq!(
+        console.take_output(),
+        "\x1b[2J\x1b[1;1HHi\x1b[2;2H#\x1b[25;1H"
+    );
+}
+
+#[test]
+fn later_frames_redraw_only_changed_cells() {
+    let console = SharedConsole::new();
+    let mut vm = display_vm(&console);
+    vm.read_image_bytes(&image()).unwrap();
+    vm.poke(DISPLAY_START, u16::from(b'A')).unwrap();
+    vm.run().unwrap();
+    console.take_output();
+
+    vm.poke(DISPLAY_START, u16::from(b'B')).unwrap();
+    vm.reset(true);
+    vm.run().unwrap();
+    assert_eq!(console.take_output(), "\x1b[1;1HB\x1b[25;1H");
+
+    vm.reset(true);
+    vm.run().unwrap();
+    assert_eq!(console.take_output(), "");
+}
+
+#[test]
+fn the_cursor_is_moved_after_the_last_column() {
+    let mut display = Display::new();
+    let mut frame = vec![0; 1920];
+    *frame.get_mut(COLUMNS - 1).unwrap() = u16::from(b'x');
+    *frame.get_mut(COLUMNS).unwrap() = u16::from(b'y');
+    // control characters show as blanks
+    *frame.get_mut(COLUMNS + 1).unwrap() = 0x07;
+    assert_eq!(
+        display.render(&frame),
+        "\x1b[2J\x1b[1;80Hx\x1b[2;1Hy\x1b[25;1H"
+    );
+}
+
+#[test]
+fn programs_without_the_display_keep_dfr_as_memory() {
+    let console = SharedConsole::new();
+    let mut vm = VM::builder()
+        .console(Box::new(console.clone()))
+        .trap_messages(TrapMessages::SILENT)
+        .build()
+        .unwrap();
+    vm.read_image_bytes(&image()).unwrap();
+    vm.poke(DISPLAY_START, u16::from(b'A')).unwrap();
+    vm.run().unwrap();
+    assert_eq!(console.take_output(), "");
+}
+
+#[test]
+fn smaller_grids_wrap_at_their_width() {
+    let mut display = Display::with_size(4, 2).unwrap();
+    assert_eq!(display.end(), DISPLAY_START + 7);
+    let frame = [0, 0, 0, u16::from(b'x'), u16::from(b'y'), 0, 0, 0];
+    assert_eq!(
+        display.render(&frame),
+        "\x1b[2J\x1b[1;4Hx\x1b[2;1Hy\x1b[3;1H"
+    );
+    assert!(Display::with_size(80, 50).is_err());
+    assert!(Display::with_size(0, 24).is_err());
+}
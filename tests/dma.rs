@@ -0,0 +1,92 @@
+//! The block-copy device
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    console::SharedConsole,
+    register::Register,
+    vm::{DMA_DONE, DMA_ERROR, VM},
+};
+
+/// Copies LEN words from SRC to DST, then reads DMASR into R1
+fn copy(source: u16, destination: u16, length: u16) -> String {
+    format!(
+        ".ORIG x3000
+         LD R0, SRC
+         STI R0, PSRC
+         LD R0, DST
+         STI R0, PDST
+         LD R0, LEN
+         STI R0, PLEN
+         STI R0, PCR
+         LDI R1, PSR
+         HALT
+SRC      .FILL x{source:04X}
+DST      .FILL x{destination:04X}
+LEN      .FILL #{length}
+PSRC     .FILL xFE12
+PDST     .FILL xFE14
+PLEN     .FILL xFE16
+PCR      .FILL xFE18
+PSR      .FILL xFE1A
+         .END"
+    )
+}
+
+fn dma_vm(program: &str) -> VM {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .dma()
+        .build()
+        .unwrap();
+    vm.load_asm_str(program).unwrap();
+    for (offset, value) in (0..8).zip(1..) {
+        vm.poke(0x4000 | offset, value).unwrap();
+    }
+    vm
+}
+
+fn words(vm: &VM, start: u16, length: u16) -> Vec<u16> {
+    (0..length)
+        .map(|offset| vm.peek(start.wrapping_add(offset)))
+        .collect()
+}
+
+#[test]
+fn copies_a_block() {
+    let mut vm = dma_vm(&copy(0x4000, 0x5000, 8));
+    vm.run().unwrap();
+    assert_eq!(words(&vm, 0x5000, 9), [1, 2, 3, 4, 5, 6, 7, 8, 0]);
+    assert_eq!(vm.register(Register::R1), DMA_DONE);
+}
+
+#[test]
+fn overlapping_blocks_are_copied_like_memmove() {
+    let mut vm = dma_vm(&copy(0x4000, 0x4002, 6));
+    vm.run().unwrap();
+    assert_eq!(words(&vm, 0x4000, 8), [1, 2, 1, 2, 3, 4, 5, 6]);
+
+    let mut vm = dma_vm(&copy(0x4002, 0x4000, 6));
+    vm.run().unwrap();
+    assert_eq!(words(&vm, 0x4000, 8), [3, 4, 5, 6, 7, 8, 7, 8]);
+}
+
+#[test]
+fn blocks_reaching_the_device_registers_are_refused() {
+    let mut vm = dma_vm(&copy(0x4000, 0xFDFC, 8));
+    vm.run().unwrap();
+    assert_eq!(vm.register(Register::R1), DMA_DONE | DMA_ERROR);
+    assert_eq!(words(&vm, 0xFDFC, 4), [0; 4]);
+}
+
+#[test]
+fn without_the_device_the_registers_are_plain_memory() {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    vm.load_asm_str(&copy(0x4000, 0x5000, 8)).unwrap();
+    vm.poke(0x4000, 1).unwrap();
+    vm.run().unwrap();
+    assert_eq!(vm.peek(0x5000), 0);
+    assert_eq!(vm.register(Register::R1), 0);
+}
@@ -0,0 +1,40 @@
+//! `VMError` as a standard error with its I/O causes
+#![allow(clippy::unwrap_used)]
+
+use std::{error::Error, io::ErrorKind};
+
+use lc3_vm::{errors::VMError, vm::VM};
+
+#[test]
+fn io_errors_are_kept_as_sources() {
+    let mut vm = VM::new();
+    let error = vm.read_image("missing/program.obj").unwrap_err();
+    assert!(
+        matches!(&error, VMError::OpenFile(cause) if cause.kind() == Some(ErrorKind::NotFound)),
+        "{error:?}"
+    );
+    assert_eq!(
+        error.to_string(),
+        "Error opening file: Could not open missing/program.obj"
+    );
+    let source = error.source().unwrap();
+    assert!(format!("{error:#}").ends_with(&format!(": {source}")));
+}
+
+#[test]
+fn errors_convert_into_boxed_errors() {
+    fn load() -> Result<(), Box<dyn Error + Send + Sync>> {
+        VM::new().read_image("missing/program.obj")?;
+        Ok(())
+    }
+    let error = load().unwrap_err();
+    assert!(error.downcast_ref::<VMError>().is_some());
+    assert!(error.source().is_some());
+    assert_eq!(
+        VMError::InvalidOpcode(String::from("x3000")).to_string(),
+        "Invalid opcode: x3000"
+    );
+    assert!(VMError::InvalidOpcode(String::from("x3000"))
+        .source()
+        .is_none());
+}
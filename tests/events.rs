@@ -0,0 +1,116 @@
+//! Execution consumed as an iterator of events
+#![allow(clippy::unwrap_used)]
+
+use std::{cell::RefCell, rc::Rc};
+
+use lc3_vm::{
+    console::NullConsole,
+    observer::Observer,
+    vm::{ExecEvent, TrapMessages, VM},
+};
+
+/// Prints a character and saves it
+const SAVE: &str = ".ORIG x3000
+         LD R0, CHAR
+         OUT
+         ST R0, SAVED
+         HALT
+CHAR     .FILL x41
+SAVED    .BLKW 1
+         .END";
+
+fn machine(source: &str) -> VM {
+    let mut vm = VM::builder()
+        .console(Box::new(NullConsole))
+        .trap_messages(TrapMessages::SILENT)
+        .build()
+        .unwrap();
+    vm.load_asm_str(source).unwrap();
+    vm
+}
+
+#[test]
+fn events_follow_the_program() {
+    let mut vm = machine(SAVE);
+    let events: Vec<ExecEvent> = vm.events().map(Result::unwrap).collect();
+    assert_eq!(
+        events,
+        [
+            ExecEvent::InstructionRetired {
+                pc: 0x3000,
+                raw: 0x2003
+            },
+            // what an instruction causes comes before it retires
+            ExecEvent::Output('A'),
+            ExecEvent::InstructionRetired {
+                pc: 0x3001,
+                raw: 0xF021
+            },
+            ExecEvent::MemoryWrite {
+                address: 0x3005,
+                value: 0x41
+            },
+            ExecEvent::InstructionRetired {
+                pc: 0x3002,
+                raw: 0x3002
+            },
+            ExecEvent::InstructionRetired {
+                pc: 0x3003,
+                raw: 0xF025
+            },
+            ExecEvent::Halted,
+        ]
+    );
+    assert!(vm.is_halted());
+}
+
+#[test]
+fn instructions_run_as_events_are_requested() {
+    let mut vm = machine(SAVE);
+    let mut events = vm.events();
+    assert!(events.next().unwrap().is_ok());
+    drop(events);
+    assert_eq!(vm.pc(), 0x3001);
+    assert_eq!(vm.metrics().instructions, 1);
+}
+
+#[test]
+fn errors_end_the_events() {
+    let mut vm = machine(
+        ".ORIG x3000
+         ADD R0, R0, #1
+         .FILL xD000
+         .END",
+    );
+    let mut events = vm.events();
+    assert_eq!(
+        events.next().unwrap().unwrap(),
+        ExecEvent::InstructionRetired {
+            pc: 0x3000,
+            raw: 0x1021
+        }
+    );
+    assert!(events.next().unwrap().is_err());
+    assert!(events.next().is_none());
+}
+
+#[derive(Default)]
+struct Retired(u32);
+
+impl Observer for Retired {
+    fn after_instruction(&mut self, _vm: &VM, _pc: u16, _raw: u16) {
+        self.0 = self.0.saturating_add(1);
+    }
+}
+
+#[test]
+fn observers_keep_their_callbacks() {
+    let mut vm = machine(SAVE);
+    let retired = Rc::new(RefCell::new(Retired::default()));
+    vm.set_observer(Box::new(Rc::clone(&retired)));
+    assert_eq!(vm.events().take(3).count(), 3);
+    assert_eq!(retired.borrow().0, 2);
+    // and stay attached after the iterator
+    vm.run().unwrap();
+    assert_eq!(retired.borrow().0, 4);
+}
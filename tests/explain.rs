@@ -0,0 +1,78 @@
+//! Explanations of `--explain`
+#![allow(clippy::unwrap_used)]
+
+use std::{cell::RefCell, rc::Rc};
+
+use lc3_vm::{console::SharedConsole, explain::Explainer, vm::VM};
+
+/// The explanations of the instructions of `source`
+fn explain(source: &str) -> Vec<String> {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    vm.load_asm_str(source).unwrap();
+    let lines = Rc::new(RefCell::new(Vec::new()));
+    let sink = Rc::clone(&lines);
+    vm.set_observer(Box::new(Explainer::new(move |explanation| {
+        sink.borrow_mut().push(String::from(explanation));
+    })));
+    vm.run().unwrap();
+    lines.take()
+}
+
+#[test]
+fn explanations_show_the_values_involved() {
+    let lines = explain(
+        ".ORIG x3000
+         ADD R1, R1, #1
+         ADD R0, R1, #2
+         LEA R6, DATA
+         LDR R2, R6, #1
+         NOT R3, R2
+         STR R3, R6, #0
+         HALT
+DATA     .FILL #0
+         .FILL x41
+         .END",
+    );
+    assert_eq!(
+        lines,
+        [
+            "ADD: R1 ← R1 + #1 = x0000 + x0001 = x0001; condition codes set to P",
+            "ADD: R0 ← R1 + #2 = x0001 + x0002 = x0003; condition codes set to P",
+            "LEA: R6 ← x3007; condition codes set to P",
+            "LDR: R2 ← mem[R6 + #1] = mem[x3008] = x0041; condition codes set to P",
+            "NOT: R3 ← NOT R2 = NOT x0041 = xFFBE; condition codes set to N",
+            "STR: mem[R6 + #0] = mem[x3007] ← R3 = xFFBE",
+            "TRAP x25 (HALT): stops the machine",
+        ]
+    );
+}
+
+#[test]
+fn branches_say_whether_they_are_taken() {
+    let lines = explain(
+        ".ORIG x3000
+         ADD R0, R0, #1
+         BRz SKIP
+         BRp SKIP
+SKIP     BRnzp DONE
+DONE     JSR SUB
+         HALT
+SUB      RET
+         .END",
+    );
+    assert_eq!(
+        lines,
+        [
+            "ADD: R0 ← R0 + #1 = x0000 + x0001 = x0001; condition codes set to P",
+            "BRz: the condition codes are P, so the branch to x3003 is not taken",
+            "BRp: the condition codes are P, so the branch to x3003 is taken",
+            "BRnzp: always branches, to x3004",
+            "JSR: R7 ← x3005, the return address, then PC ← x3006",
+            "RET: PC ← R7 = x3005, returning from the subroutine",
+            "TRAP x25 (HALT): stops the machine",
+        ]
+    );
+}
@@ -0,0 +1,132 @@
+//! The convenience traps enabled with `--extended-traps`
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    console::SharedConsole,
+    errors::VMError,
+    register::Register,
+    vm::{TrapMessages, TrapMode, VM},
+};
+
+/// The buffer GETS reads into
+const BUFFER: u16 = 0x4000;
+
+fn machine(source: &str) -> (VM, SharedConsole) {
+    let console = SharedConsole::new();
+    let mut vm = VM::builder()
+        .console(Box::new(console.clone()))
+        .trap_messages(TrapMessages::SILENT)
+        .trap_mode(TrapMode::Extended)
+        .build()
+        .unwrap();
+    vm.load_asm_str(source).unwrap();
+    (vm, console)
+}
+
+/// Prints the R2 numbers at x4000 with PUTD, separated by spaces
+fn print_numbers(numbers: &[u16]) -> String {
+    let (mut vm, console) = machine(
+        ".ORIG x3000
+         LD R1, NUMBERS
+LOOP     LDR R0, R1, #0
+         TRAP x26
+         LD R0, SPACE
+         OUT
+         ADD R1, R1, #1
+         ADD R2, R2, #-1
+         BRp LOOP
+         HALT
+NUMBERS  .FILL x4000
+SPACE    .FILL x20
+         .END",
+    );
+    for (address, &number) in (0x4000..).zip(numbers) {
+        vm.poke(address, number).unwrap();
+    }
+    vm.set_register(Register::R2, u16::try_from(numbers.len()).unwrap());
+    vm.run().unwrap();
+    console.take_output()
+}
+
+/// Reads a line of at most `size` words at x4000 from `keys`
+fn read_line(keys: &[u8], size: u16) -> (Result<(), VMError>, VM, String) {
+    let (mut vm, console) = machine(
+        ".ORIG x3000
+         LD R0, LINE
+         TRAP x27
+         HALT
+LINE     .FILL x4000
+         .END",
+    );
+    vm.set_register(Register::R1, size);
+    console.push_input(keys.iter().copied());
+    let result = vm.run();
+    (result, vm, console.take_output())
+}
+
+/// The zero terminated string at x4000
+fn line(vm: &VM) -> String {
+    (BUFFER..)
+        .map(|address| vm.peek(address))
+        .take_while(|&word| word != 0)
+        .map(|word| char::from(u8::try_from(word).unwrap()))
+        .collect()
+}
+
+#[test]
+fn putd_prints_signed_numbers() {
+    assert_eq!(
+        print_numbers(&[0, 7, 0xFFFF, 0x7FFF, 0x8000]),
+        "0 7 -1 32767 -32768 "
+    );
+}
+
+#[test]
+fn gets_reads_a_line_without_the_newline() {
+    let (result, vm, output) = read_line(b"hello\nrest", 10);
+    result.unwrap();
+    assert_eq!(line(&vm), "hello");
+    assert_eq!(output, "hello\n");
+}
+
+#[test]
+fn gets_ignores_characters_past_the_buffer() {
+    let (result, vm, output) = read_line(b"hello\n", 4);
+    result.unwrap();
+    assert_eq!(line(&vm), "hel");
+    assert_eq!(output, "hel\n");
+    // the terminator takes the last word
+    assert_eq!(vm.peek(0x4003), 0);
+
+    // without room for the terminator nothing is written
+    let (mut vm, console) = machine(
+        ".ORIG x3000
+         LD R0, LINE
+         TRAP x27
+         HALT
+LINE     .FILL x4000
+         .END",
+    );
+    vm.poke(BUFFER, 0x1234).unwrap();
+    console.push_input(*b"ab\n");
+    vm.run().unwrap();
+    assert_eq!(vm.peek(BUFFER), 0x1234);
+    assert_eq!(console.take_output(), "\n");
+}
+
+#[test]
+fn gets_erases_with_backspace() {
+    let (result, vm, output) = read_line(b"\x08abx\x08c\x7F\x7Fd\n", 10);
+    result.unwrap();
+    assert_eq!(line(&vm), "ad");
+    assert_eq!(output, "abx\x08 \x08c\x08 \x08\x08 \x08d\n");
+}
+
+#[test]
+fn gets_fails_when_the_input_ends_before_the_newline() {
+    let (result, vm, output) = read_line(b"abc", 10);
+    let error = result.unwrap_err();
+    assert!(matches!(error, VMError::StandardIO(_)), "{error}");
+    assert_eq!(output, "abc");
+    assert!(!vm.is_halted());
+}
@@ -0,0 +1,138 @@
+//! The C API called as C programs call it
+#![cfg(feature = "ffi")]
+#![allow(clippy::unwrap_used)]
+
+use std::{
+    collections::VecDeque,
+    ffi::{c_void, CStr},
+    ptr,
+};
+
+use lc3_vm::{assembler::assemble, ffi::*};
+
+/// Echoes a key, then halts
+const ECHO: &str = ".ORIG x3000
+         GETC
+         OUT
+         HALT
+         .END";
+
+/// What the callbacks of a test read and write
+#[derive(Default)]
+struct Io {
+    keys: VecDeque<u8>,
+    output: Vec<u8>,
+}
+
+extern "C" fn read_key(user_data: *mut c_void, _block: bool) -> i32 {
+    // SAFETY: the tests pass an `Io` they keep alive while the VM runs
+    let io = unsafe { &mut *user_data.cast::<Io>() };
+    io.keys.pop_front().map_or(-1, i32::from)
+}
+
+extern "C" fn write_char(user_data: *mut c_void, character: u8) {
+    // SAFETY: as for `read_key`
+    let io = unsafe { &mut *user_data.cast::<Io>() };
+    io.output.push(character);
+}
+
+/// A VM running `source` with its I/O going through `io`
+fn machine(source: &str, io: &mut Io) -> *mut Lc3Vm {
+    let image = assemble(source).unwrap().image();
+    let vm = lc3_vm_new();
+    // SAFETY: `vm` is fresh and `image` is readable for its length
+    unsafe {
+        lc3_vm_set_io(
+            vm,
+            ptr::from_mut(io).cast(),
+            Some(read_key),
+            Some(write_char),
+        );
+        assert_eq!(lc3_vm_load(vm, image.as_ptr(), image.len()), Lc3Status::Ok);
+    }
+    vm
+}
+
+/// The last error of `vm` as a string
+///
+/// # Safety
+///
+/// `vm` must come from `lc3_vm_new`.
+unsafe fn last_error(vm: *const Lc3Vm) -> Option<String> {
+    let message = lc3_vm_last_error(vm);
+    (!message.is_null()).then(|| CStr::from_ptr(message).to_string_lossy().into_owned())
+}
+
+#[test]
+fn programs_run_through_the_callbacks() {
+    let mut io = Io::default();
+    io.keys.push_back(b'x');
+    let vm = machine(ECHO, &mut io);
+    // SAFETY: `vm` comes from `lc3_vm_new` and `io` outlives it
+    unsafe {
+        assert_eq!(lc3_vm_step(vm, 1), Lc3Status::Ok);
+        assert_eq!(lc3_vm_pc(vm), 0x3001);
+        assert_eq!(lc3_vm_read_reg(vm, 0), u16::from(b'x'));
+        assert_eq!(lc3_vm_step(vm, 100), Lc3Status::Halted);
+        assert_eq!(last_error(vm), None);
+        lc3_vm_free(vm);
+    }
+    assert!(io.output.starts_with(b"x"));
+}
+
+#[test]
+fn memory_and_registers_are_read_and_written() {
+    let mut io = Io::default();
+    let vm = machine(ECHO, &mut io);
+    // SAFETY: `vm` comes from `lc3_vm_new`
+    unsafe {
+        // GETC is TRAP x20
+        assert_eq!(lc3_vm_read_mem(vm, 0x3000), 0xF020);
+        assert_eq!(lc3_vm_write_mem(vm, 0x4000, 0x1234), Lc3Status::Ok);
+        assert_eq!(lc3_vm_read_mem(vm, 0x4000), 0x1234);
+        // there is no R8
+        assert_eq!(lc3_vm_read_reg(vm, 8), 0);
+        lc3_vm_free(vm);
+    }
+}
+
+#[test]
+fn failures_are_described_by_the_last_error() {
+    let mut io = Io::default();
+    let vm = machine(ECHO, &mut io);
+    // SAFETY: `vm` comes from `lc3_vm_new`, and the byte is readable
+    unsafe {
+        assert_eq!(last_error(vm), None);
+        // GETC without a key to read
+        assert_eq!(lc3_vm_step(vm, 10), Lc3Status::Error);
+        assert!(last_error(vm).unwrap().contains("No input is available"));
+
+        assert_eq!(lc3_vm_load(vm, ptr::null(), 0), Lc3Status::Error);
+        assert!(last_error(vm).unwrap().contains("The image is NULL"));
+        let odd = [0x30];
+        assert_eq!(lc3_vm_load(vm, odd.as_ptr(), odd.len()), Lc3Status::Error);
+        assert_ne!(last_error(vm).unwrap(), "");
+        lc3_vm_free(vm);
+    }
+}
+
+#[test]
+fn null_handles_are_refused() {
+    let image = [0x30, 0x00, 0xF0, 0x25];
+    let vm: *mut Lc3Vm = ptr::null_mut();
+    // SAFETY: every function accepts a NULL handle
+    unsafe {
+        assert_eq!(
+            lc3_vm_load(vm, image.as_ptr(), image.len()),
+            Lc3Status::Error
+        );
+        assert_eq!(lc3_vm_step(vm, 1), Lc3Status::Error);
+        assert_eq!(lc3_vm_write_mem(vm, 0x3000, 1), Lc3Status::Error);
+        assert_eq!(lc3_vm_read_mem(vm, 0x3000), 0);
+        assert_eq!(lc3_vm_read_reg(vm, 0), 0);
+        assert_eq!(lc3_vm_pc(vm), 0);
+        assert!(lc3_vm_last_error(vm).is_null());
+        lc3_vm_set_io(vm, ptr::null_mut(), None, None);
+        lc3_vm_free(vm);
+    }
+}
@@ -0,0 +1,140 @@
+//! The file traps FOPEN to FCLOSE on the file systems of `vfs`
+#![allow(clippy::unwrap_used)]
+
+use std::{fs, path::PathBuf};
+
+use lc3_vm::{
+    console::SharedConsole,
+    register::Register,
+    vfs::{DirectoryFileSystem, FileSystem, MemoryFileSystem, FCLOSE, FOPEN, FREAD, FWRITE},
+    vm::VM,
+};
+
+/// Where file names and data are put for the traps
+const NAME: u16 = 0x4000;
+const BUFFER: u16 = 0x4100;
+/// What the traps return in R0 when they fail
+const FAILURE: u16 = 0xFFFF;
+
+fn vm(fs: impl FileSystem + 'static) -> VM {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    vm.enable_file_traps(Box::new(fs));
+    vm
+}
+
+/// Executes `TRAP vector` with R0 to R2 set to `arguments`, returning R0
+fn call(vm: &mut VM, vector: u16, [r0, r1, r2]: [u16; 3]) -> u16 {
+    vm.poke(0x3000, 0xF000 | vector).unwrap();
+    vm.set_pc(0x3000);
+    vm.set_register(Register::R0, r0);
+    vm.set_register(Register::R1, r1);
+    vm.set_register(Register::R2, r2);
+    vm.step().unwrap();
+    vm.register(Register::R0)
+}
+
+/// Opens `name` with `mode`, returning the handle or `FAILURE`
+fn open(vm: &mut VM, name: &str, mode: u16) -> u16 {
+    let words: Vec<u16> = name.bytes().map(u16::from).chain([0]).collect();
+    vm.memory_mut().write_words(NAME, &words).unwrap();
+    call(vm, FOPEN, [NAME, mode, 0])
+}
+
+/// Writes the bytes of `text` to `handle`, returning the count written
+fn write(vm: &mut VM, handle: u16, text: &str) -> u16 {
+    let words: Vec<u16> = text.bytes().map(u16::from).collect();
+    vm.memory_mut().write_words(BUFFER, &words).unwrap();
+    let count = u16::try_from(words.len()).unwrap();
+    call(vm, FWRITE, [handle, BUFFER, count])
+}
+
+/// Reads up to `count` bytes of `handle`
+fn read(vm: &mut VM, handle: u16, count: u16) -> Option<String> {
+    let read = call(vm, FREAD, [handle, BUFFER, count]);
+    (read != FAILURE).then(|| {
+        vm.memory()
+            .read_words(BUFFER, usize::from(read))
+            .map(|word| char::from(u8::try_from(word).unwrap()))
+            .collect()
+    })
+}
+
+#[test]
+fn file_traps_are_disabled_by_default() {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    vm.poke(0x3000, 0xF000 | FOPEN).unwrap();
+    assert!(vm.step().is_err());
+}
+
+#[test]
+fn files_are_written_and_read_back() {
+    let fs = MemoryFileSystem::new();
+    let mut vm = vm(fs.clone());
+    let handle = open(&mut vm, "notes.txt", 1);
+    assert_eq!(handle, 0);
+    assert_eq!(write(&mut vm, handle, "hello"), 5);
+    // saved on closing
+    assert_eq!(fs.get("notes.txt"), None);
+    assert_eq!(call(&mut vm, FCLOSE, [handle, 0, 0]), 0);
+    assert_eq!(fs.get("notes.txt").unwrap(), b"hello");
+
+    let handle = open(&mut vm, "notes.txt", 2);
+    assert_eq!(write(&mut vm, handle, "!"), 1);
+    call(&mut vm, FCLOSE, [handle, 0, 0]);
+
+    let handle = open(&mut vm, "notes.txt", 0);
+    assert_eq!(read(&mut vm, handle, 4).unwrap(), "hell");
+    assert_eq!(read(&mut vm, handle, 4).unwrap(), "o!");
+    assert_eq!(read(&mut vm, handle, 4).unwrap(), "");
+    assert!(vm.psr().zero());
+    assert_eq!(call(&mut vm, FCLOSE, [handle, 0, 0]), 0);
+}
+
+#[test]
+fn failures_return_minus_one() {
+    let fs = MemoryFileSystem::new();
+    fs.insert("data", b"abc");
+    let mut vm = vm(fs);
+    assert_eq!(open(&mut vm, "missing", 0), FAILURE);
+    assert!(vm.psr().negative());
+    assert_eq!(open(&mut vm, "data", 3), FAILURE);
+    // handles only do what they were opened for
+    let reading = open(&mut vm, "data", 0);
+    assert_eq!(write(&mut vm, reading, "x"), FAILURE);
+    let writing = open(&mut vm, "other", 1);
+    assert_eq!(read(&mut vm, writing, 1), None);
+    assert_eq!(call(&mut vm, FCLOSE, [7, 0, 0]), FAILURE);
+    assert_eq!(read(&mut vm, 7, 1), None);
+    assert_eq!(call(&mut vm, FCLOSE, [reading, 0, 0]), 0);
+    assert_eq!(call(&mut vm, FCLOSE, [reading, 0, 0]), FAILURE);
+}
+
+#[test]
+fn names_cannot_leave_the_directory() {
+    let base = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("file_traps");
+    let root = base.join("root");
+    fs::create_dir_all(&root).unwrap();
+    fs::write(base.join("secret"), "outside").unwrap();
+    let mut directory = DirectoryFileSystem::new(root.to_str().unwrap()).unwrap();
+    assert!(directory.load("../secret").is_err());
+
+    let mut vm = vm(directory);
+    let secret = base.join("secret");
+    let absolute = secret.to_str().unwrap();
+    for name in ["../secret", absolute, "sub/secret", ".hidden", ""] {
+        assert_eq!(open(&mut vm, name, 0), FAILURE, "{name}");
+        assert_eq!(open(&mut vm, name, 1), FAILURE, "{name}");
+    }
+    assert_eq!(fs::read_to_string(&secret).unwrap(), "outside");
+
+    let handle = open(&mut vm, "inside.txt", 1);
+    write(&mut vm, handle, "ok");
+    call(&mut vm, FCLOSE, [handle, 0, 0]);
+    assert_eq!(fs::read_to_string(root.join("inside.txt")).unwrap(), "ok");
+}
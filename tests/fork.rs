@@ -0,0 +1,124 @@
+//! Forked machines running on independently from a shared state
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    console::{NullConsole, SharedConsole},
+    register::Register,
+    vm::{TrapMessages, VM},
+};
+
+/// Adds R0 to the word at x4000, then prints it as a character
+const ACCUMULATE: &str = ".ORIG x3000
+         LDI R1, TOTAL
+         ADD R1, R1, R0
+         STI R1, TOTAL
+         ADD R0, R1, #0
+         OUT
+         HALT
+TOTAL    .FILL x4000
+         .END";
+
+fn machine() -> VM {
+    let mut vm = VM::builder()
+        .console(Box::new(NullConsole))
+        .trap_messages(TrapMessages::SILENT)
+        .build()
+        .unwrap();
+    vm.load_asm_str(ACCUMULATE).unwrap();
+    vm.poke(0x4000, 0x40).unwrap();
+    vm
+}
+
+#[test]
+fn forks_branch_from_the_same_state() {
+    let mut vm = machine();
+    vm.set_register(Register::R0, 1);
+    let mut branches: Vec<VM> = (1..=3)
+        .map(|step| {
+            let mut branch = vm.fork().unwrap();
+            branch.set_register(Register::R0, step);
+            branch
+        })
+        .collect();
+    let mut outputs = Vec::new();
+    for branch in &mut branches {
+        let console = SharedConsole::new();
+        branch.set_console(Box::new(console.clone()));
+        branch.run().unwrap();
+        assert!(branch.is_halted());
+        outputs.push(console.take_output());
+    }
+    assert_eq!(outputs, ["A", "B", "C"]);
+    let totals: Vec<u16> = branches.iter().map(|branch| branch.peek(0x4000)).collect();
+    assert_eq!(totals, [0x41, 0x42, 0x43]);
+    // the original is where it was forked
+    assert_eq!(vm.pc(), 0x3000);
+    assert_eq!(vm.peek(0x4000), 0x40);
+    vm.run().unwrap();
+    assert_eq!(vm.peek(0x4000), 0x41);
+    assert_eq!(branches.last().unwrap().peek(0x4000), 0x43);
+}
+
+#[test]
+fn forks_keep_the_machine_state() {
+    let mut vm = machine();
+    vm.add_breakpoint(0x3002);
+    vm.step().unwrap();
+    vm.raise_interrupt(0x80, 4).unwrap();
+    let mut fork = vm.fork().unwrap();
+    assert_eq!(fork.state(), vm.state());
+    assert_eq!(fork.state_hash(), vm.state_hash());
+    assert_eq!(fork.metrics().instructions, 1);
+    assert_eq!(fork.breakpoints().collect::<Vec<_>>(), [0x3002]);
+    assert_eq!(fork.pending_interrupts().collect::<Vec<_>>(), [(0x80, 4)]);
+    fork.poke(0x4000, 0).unwrap();
+    assert_ne!(fork.state_hash(), vm.state_hash());
+}
+
+#[test]
+fn forks_of_forks_share_nothing_they_write() {
+    let mut vm = machine();
+    let mut child = vm.fork().unwrap();
+    let mut grandchild = child.fork().unwrap();
+    child.poke(0x4000, 1).unwrap();
+    grandchild.poke(0x4001, 2).unwrap();
+    vm.poke(0x4002, 3).unwrap();
+    let words = |vm: &VM| [vm.peek(0x4000), vm.peek(0x4001), vm.peek(0x4002)];
+    assert_eq!(words(&vm), [0x40, 0, 3]);
+    assert_eq!(words(&child), [1, 0, 0]);
+    assert_eq!(words(&grandchild), [0x40, 2, 0]);
+    grandchild.reload().unwrap();
+    assert_eq!(words(&grandchild), [0, 0, 0]);
+    assert_eq!(words(&child), [1, 0, 0]);
+    grandchild.poke(0x4000, 0x30).unwrap();
+    grandchild.run().unwrap();
+    assert_eq!(grandchild.peek(0x4000), 0x30);
+}
+
+#[test]
+fn windows_work_on_forked_memory() {
+    let mut vm = machine();
+    vm.reserve_window("data", 0x43F0, 0x20).unwrap();
+    let mut fork = vm.fork().unwrap();
+    fork.window_mut("data").unwrap().fill(7);
+    assert_eq!(fork.peek(0x4400), 7);
+    assert_eq!(vm.peek(0x4400), 0);
+    assert!(vm.window_mut("data").unwrap().iter().all(|&word| word == 0));
+}
+
+#[test]
+fn machines_connected_to_the_host_are_not_forked() {
+    let mut vm = machine();
+    vm.add_channel("debug", 0xFE30, Box::new(NullConsole))
+        .unwrap();
+    assert_eq!(
+        vm.fork().err().unwrap().to_string(),
+        "Cannot fork a machine connected to the host through channels"
+    );
+    vm.detach_device("debug").unwrap();
+    vm.register_trap(0x90, |_| Ok(())).unwrap();
+    assert_eq!(
+        vm.fork().err().unwrap().to_string(),
+        "Cannot fork a machine connected to the host through host handlers"
+    );
+}
@@ -0,0 +1,174 @@
+//! The gdb stub driven packet by packet over TCP
+#![allow(clippy::unwrap_used)]
+
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    thread,
+};
+
+use lc3_vm::{
+    console::NullConsole,
+    gdb,
+    register::Register,
+    vm::{TrapMessages, VM},
+};
+
+/// Adds 1 to R0 twice
+const COUNT: &str = ".ORIG x3000
+         ADD R0, R0, #1
+         ADD R0, R0, #1
+         HALT
+         .END";
+
+fn machine(source: &str) -> VM {
+    let mut vm = VM::builder()
+        .console(Box::new(NullConsole))
+        .trap_messages(TrapMessages::SILENT)
+        .build()
+        .unwrap();
+    vm.load_asm_str(source).unwrap();
+    vm
+}
+
+/// `data` framed as a packet
+fn packet(data: &[u8]) -> Vec<u8> {
+    let checksum = data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    let mut framed = vec![b'$'];
+    framed.extend_from_slice(data);
+    framed.extend_from_slice(format!("#{checksum:02x}").as_bytes());
+    framed
+}
+
+/// Sends `packets` to a session serving `vm` and returns everything the
+/// stub sent back, acknowledgements included
+fn session(vm: &mut VM, packets: Vec<Vec<u8>>) -> String {
+    let listener = gdb::listen("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let client = thread::spawn(move || {
+        let mut stream = TcpStream::connect(address).unwrap();
+        for packet in packets {
+            stream.write_all(&packet).unwrap();
+        }
+        let mut received = String::new();
+        stream.read_to_string(&mut received).unwrap();
+        received
+    });
+    let (stream, _) = gdb::accept(&listener).unwrap();
+    gdb::serve_connection(vm, stream).unwrap();
+    client.join().unwrap()
+}
+
+/// The data of the packets in what the stub sent
+fn replies(received: &str) -> Vec<&str> {
+    received
+        .split('$')
+        .skip(1)
+        .filter_map(|packet| packet.split_once('#'))
+        .map(|(data, _)| data)
+        .collect()
+}
+
+#[test]
+fn registers_and_memory_are_read_and_written() {
+    let mut vm = machine(COUNT);
+    vm.set_register(Register::R1, 0x1234);
+    let received = session(
+        &mut vm,
+        vec![
+            packet(b"p1"),
+            packet(b"p8"),
+            packet(b"P2=cdab"),
+            packet(b"m6000,4"),
+            packet(b"M8000,2:3412"),
+            packet(b"m8000,2"),
+            packet(b"D"),
+        ],
+    );
+    assert_eq!(
+        replies(&received),
+        ["3412", "00600000", "OK", "21102110", "OK", "3412", "OK"]
+    );
+    assert!(received.starts_with('+'));
+    assert_eq!(vm.register(Register::R2), 0xABCD);
+    // x3000 holds ADD R0, R0, #1
+    assert_eq!(vm.peek(0x3000), 0x1021);
+    assert_eq!(vm.peek(0x4000), 0x1234);
+}
+
+#[test]
+fn binary_writes_restore_escaped_bytes() {
+    let mut vm = machine(COUNT);
+    // '#', '$', '}' and '*' are sent escaped
+    let mut write = b"X8000,6:".to_vec();
+    write.extend_from_slice(&[b'}', b'#' ^ 0x20, b'}', b'$' ^ 0x20, b'}', b'}' ^ 0x20]);
+    write.extend_from_slice(&[b'}', b'*' ^ 0x20, b'a', b'b']);
+    let received = session(
+        &mut vm,
+        vec![
+            // gdb probes for X with an empty write
+            packet(b"X8000,0:"),
+            packet(&write),
+            packet(b"X8000,4:ab"),
+            packet(b"D"),
+        ],
+    );
+    assert_eq!(replies(&received), ["OK", "OK", "E01", "OK"]);
+    assert_eq!(vm.peek(0x4000), u16::from_le_bytes([b'#', b'$']));
+    assert_eq!(vm.peek(0x4001), u16::from_le_bytes([b'}', b'*']));
+    assert_eq!(vm.peek(0x4002), u16::from_le_bytes([b'a', b'b']));
+}
+
+#[test]
+fn breakpoints_stop_continuing() {
+    let mut vm = machine(COUNT);
+    let received = session(
+        &mut vm,
+        vec![
+            packet(b"Z0,6002,2"),
+            packet(b"c"),
+            packet(b"p0"),
+            packet(b"z0,6002,2"),
+            packet(b"s"),
+            packet(b"c"),
+            packet(b"k"),
+        ],
+    );
+    assert_eq!(
+        replies(&received),
+        ["OK", "S05", "0100", "OK", "S05", "W00"]
+    );
+    assert!(vm.is_halted());
+    assert_eq!(vm.register(Register::R0), 2);
+}
+
+#[test]
+fn errors_are_shown_in_the_gdb_console() {
+    let mut vm = machine(
+        ".ORIG x3000
+         .FILL xD000
+         .END",
+    );
+    let received = session(&mut vm, vec![packet(b"c"), packet(b"D")]);
+    let replies = replies(&received);
+    assert_eq!(replies.len(), 3);
+    let message = replies.first().unwrap().strip_prefix('O').unwrap();
+    let message: Vec<u8> = message
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+        .collect();
+    assert!(String::from_utf8(message).unwrap().ends_with('\n'));
+    assert_eq!(replies.get(1..), Some(&["S04", "OK"][..]));
+}
+
+#[test]
+fn corrupt_packets_are_refused() {
+    let mut vm = machine(COUNT);
+    let received = session(
+        &mut vm,
+        vec![b"$p0#00".to_vec(), packet(b"p0"), packet(b"D")],
+    );
+    assert!(received.starts_with("-+"), "{received}");
+    assert_eq!(replies(&received), ["0000", "OK"]);
+}
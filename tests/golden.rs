@@ -0,0 +1,100 @@
+//! Runs every program in `tests/golden` and compares its console output with
+//! the expected output next to it. For `NAME.obj` the keys in `NAME.in`, if
+//! present, are fed to the keyboard and the output must equal `NAME.out`.
+//! `NAME.asm` is the source each image was assembled from.
+//!
+//! After an intended change of the output, regenerate the expected files
+//! with `UPDATE_GOLDEN=1 cargo test --test golden` and review the diff.
+#![allow(clippy::unwrap_used)]
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use lc3_vm::{console::SharedConsole, vm::VM};
+
+/// Stops programs that never halt instead of hanging the test
+const INSTRUCTION_LIMIT: u64 = 10_000_000;
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+}
+
+/// Runs `image` with `input` as keyboard input and returns its output, or
+/// the error that stopped it
+fn run(image: &Path, input: &[u8]) -> Result<String, String> {
+    let console = SharedConsole::new();
+    console.push_input(input.iter().copied());
+    let mut vm = VM::builder()
+        .console(Box::new(console.clone()))
+        .instruction_limit(INSTRUCTION_LIMIT)
+        .build()
+        .map_err(|e| format!("{e:?}"))?;
+    vm.read_image(image.to_str().unwrap())
+        .map_err(|e| format!("{e:?}"))?;
+    vm.run().map_err(|e| format!("{e:?}"))?;
+    Ok(console.take_output())
+}
+
+/// Describes the first line where `actual` differs from `expected`
+fn first_difference(expected: &str, actual: &str) -> String {
+    let mut expected_lines = expected.split_inclusive('\n');
+    let mut actual_lines = actual.split_inclusive('\n');
+    for line in 1.. {
+        match (expected_lines.next(), actual_lines.next()) {
+            (Some(want), Some(got)) if want == got => continue,
+            (None, None) => break,
+            (want, got) => {
+                return format!("line {line}: expected {want:?}, got {got:?}");
+            }
+        }
+    }
+    String::from("outputs are equal")
+}
+
+#[test]
+fn golden_outputs() {
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+    let mut images: Vec<PathBuf> = fs::read_dir(golden_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "obj"))
+        .collect();
+    images.sort();
+    assert!(!images.is_empty(), "no programs in {:?}", golden_dir());
+
+    let mut failures = Vec::new();
+    for image in &images {
+        let name = image.file_stem().unwrap().to_string_lossy();
+        let input = fs::read(image.with_extension("in")).unwrap_or_default();
+        let expected_path = image.with_extension("out");
+        let actual = match run(image, &input) {
+            Ok(output) => output,
+            Err(error) => {
+                failures.push(format!("{name}: {error}"));
+                continue;
+            }
+        };
+        if update {
+            fs::write(&expected_path, &actual).unwrap();
+            continue;
+        }
+        match fs::read_to_string(&expected_path) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => {
+                failures.push(format!("{name}: {}", first_difference(&expected, &actual)))
+            }
+            Err(error) => failures.push(format!("{name}: cannot read {expected_path:?}: {error}")),
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} golden programs failed:\n{}",
+        failures.len(),
+        images.len(),
+        failures.join("\n")
+    );
+}
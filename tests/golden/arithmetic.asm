@@ -0,0 +1,67 @@
+; Multiplies 6 by 7 with repeated ADD, subtracts 100 - 58 with NOT and
+; masks x1234 with AND, printing each result in decimal
+        .ORIG x3000
+        AND R1, R1, #0
+        AND R2, R2, #0
+        ADD R2, R2, #7
+MUL     ADD R1, R1, #6
+        ADD R2, R2, #-1
+        BRp MUL
+        ADD R0, R1, #0
+        JSR PRINT
+        LD R1, HUNDRED
+        LD R2, FIFTY8
+        NOT R2, R2
+        ADD R2, R2, #1
+        ADD R0, R1, R2
+        JSR PRINT
+        LD R1, PATTERN
+        LD R2, MASK
+        AND R0, R1, R2
+        JSR PRINT
+        HALT
+HUNDRED .FILL #100
+FIFTY8  .FILL #58
+PATTERN .FILL x1234
+MASK    .FILL x00FF
+; Prints R0 (0-9999) in decimal followed by a newline
+PRINT   ST R7, SAVE7
+        LEA R3, POWERS
+        AND R5, R5, #0
+NEXT    LDR R4, R3, #0
+        BRz LAST
+        AND R6, R6, #0
+DIGIT   ADD R0, R0, R4
+        BRn DONE
+        ADD R6, R6, #1
+        BR DIGIT
+DONE    NOT R4, R4
+        ADD R4, R4, #1
+        ADD R0, R0, R4
+        ADD R5, R5, R6
+        BRz SKIP
+        ST R0, SAVE0
+        LD R0, ASCII
+        ADD R0, R0, R6
+        OUT
+        LD R0, SAVE0
+SKIP    ADD R3, R3, #1
+        BR NEXT
+LAST    ST R0, SAVE0
+        LD R4, ASCII
+        ADD R0, R0, R4
+        OUT
+        AND R0, R0, #0
+        ADD R0, R0, #10
+        OUT
+        LD R0, SAVE0
+        LD R7, SAVE7
+        RET
+POWERS  .FILL #-1000
+        .FILL #-100
+        .FILL #-10
+        .FILL #0
+ASCII   .FILL x30
+SAVE0   .FILL #0
+SAVE7   .FILL #0
+        .END
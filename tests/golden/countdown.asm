@@ -0,0 +1,15 @@
+; Prints the digits 9 down to 0 with OUT, looping with BRzp
+        .ORIG x3000
+        LD R1, NINE
+        LD R2, ZERO
+LOOP    ADD R0, R1, R2
+        OUT
+        ADD R1, R1, #-1
+        BRzp LOOP
+        AND R0, R0, #0
+        ADD R0, R0, #10
+        OUT
+        HALT
+NINE    .FILL #9
+ZERO    .FILL x30
+        .END
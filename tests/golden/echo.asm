@@ -0,0 +1,23 @@
+; Reads a line with GETC and prints it back in upper case with OUT
+        .ORIG x3000
+        LD R2, NEWLINE
+        NOT R2, R2
+        ADD R2, R2, #1
+        LD R3, LOWER
+        NOT R3, R3
+        ADD R3, R3, #1
+        LD R4, UPPER
+LOOP    GETC
+        ADD R1, R0, R2
+        BRz END
+        ADD R1, R0, R3
+        BRn PRINT
+        ADD R0, R0, R4
+PRINT   OUT
+        BR LOOP
+END     OUT
+        HALT
+NEWLINE .FILL x0A
+LOWER   .FILL x61
+UPPER   .FILL #-32
+        .END
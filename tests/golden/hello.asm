@@ -0,0 +1,7 @@
+; Prints a string with PUTS
+        .ORIG x3000
+        LEA R0, MSG
+        PUTS
+        HALT
+MSG     .STRINGZ "Hello, World!\n"
+        .END
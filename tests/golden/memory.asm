@@ -0,0 +1,35 @@
+; Fills an array through STR, sums it back with LDR, stores the sum with STI
+; and prints the value read back with LDI as a character
+        .ORIG x3000
+        LEA R1, ARRAY
+        AND R2, R2, #0
+        ADD R2, R2, #5
+        AND R3, R3, #0
+FILL    ADD R3, R3, #3
+        STR R3, R1, #0
+        ADD R1, R1, #1
+        ADD R2, R2, #-1
+        BRp FILL
+        LEA R1, ARRAY
+        AND R2, R2, #0
+        ADD R2, R2, #5
+        AND R4, R4, #0
+SUM     LDR R3, R1, #0
+        ADD R4, R4, R3
+        ADD R1, R1, #1
+        ADD R2, R2, #-1
+        BRp SUM
+        LD R5, BASE
+        ADD R4, R4, R5
+        STI R4, PTR
+        LDI R0, PTR
+        OUT
+        AND R0, R0, #0
+        ADD R0, R0, #10
+        OUT
+        HALT
+BASE    .FILL x20       ; 3+6+9+12+15 = 45, plus x20 is 'M'
+PTR     .FILL RESULT
+ARRAY   .BLKW 5
+RESULT  .FILL #0
+        .END
@@ -0,0 +1,15 @@
+; Reads a character with IN, which prompts and echoes, then prints it twice
+        .ORIG x3000
+        IN
+        ADD R1, R0, #0
+        LEA R0, SAID
+        PUTS
+        ADD R0, R1, #0
+        OUT
+        OUT
+        AND R0, R0, #0
+        ADD R0, R0, #10
+        OUT
+        HALT
+SAID    .STRINGZ "\nTwice: "
+        .END
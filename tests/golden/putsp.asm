@@ -0,0 +1,17 @@
+; Prints packed strings of even and odd length with PUTSP
+        .ORIG x3000
+        LEA R0, EVEN
+        PUTSP
+        LEA R0, ODD
+        PUTSP
+        HALT
+EVEN    .FILL x6150     ; "Pa"
+        .FILL x6B63     ; "ck"
+        .FILL x6465     ; "ed"
+        .FILL x0A21     ; "!\n"
+        .FILL x0000
+ODD     .FILL x644F     ; "Od"
+        .FILL x2164     ; "d!"
+        .FILL x000A     ; "\n", the high byte ends the string
+        .FILL x0000
+        .END
@@ -0,0 +1,55 @@
+; Computes 5! with a recursive subroutine using a stack in R6 and calls the
+; printer through JSRR
+        .ORIG x3000
+        LD R6, STACK
+        AND R0, R0, #0
+        ADD R0, R0, #5
+        JSR FACT
+        LEA R2, PRINTC
+        ADD R0, R1, #0
+        JSRR R2
+        HALT
+STACK   .FILL xFE00
+; R1 = R0!, using R7 and R0 saved on the stack
+FACT    ADD R6, R6, #-1
+        STR R7, R6, #0
+        ADD R6, R6, #-1
+        STR R0, R6, #0
+        ADD R1, R0, #-1
+        BRp RECURSE
+        AND R1, R1, #0
+        ADD R1, R1, #1
+        BR RETURN
+RECURSE ADD R0, R0, #-1
+        JSR FACT
+        LDR R0, R6, #0
+        AND R2, R2, #0
+        ADD R3, R1, #0
+MUL     ADD R2, R2, R3
+        ADD R0, R0, #-1
+        BRp MUL
+        ADD R1, R2, #0
+RETURN  LDR R0, R6, #0
+        ADD R6, R6, #1
+        LDR R7, R6, #0
+        ADD R6, R6, #1
+        RET
+; Prints one '*' per ten in R0, then a newline. OUT overwrites R7, so the
+; return address is saved first
+PRINTC  ST R7, SAVE7
+        ADD R1, R0, #0
+        LD R2, MINUS10
+        LD R0, STAR
+TENS    ADD R1, R1, R2
+        BRn FINISH
+        OUT
+        BR TENS
+FINISH  AND R0, R0, #0
+        ADD R0, R0, #10
+        OUT
+        LD R7, SAVE7
+        RET
+MINUS10 .FILL #-10
+STAR    .FILL x2A
+SAVE7   .FILL #0
+        .END
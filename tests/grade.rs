@@ -0,0 +1,119 @@
+//! Grading a submission against a suite
+#![allow(clippy::unwrap_used)]
+
+use std::{fs, path::PathBuf};
+
+use serde_json::Value;
+
+use lc3_vm::{
+    assembler::assemble,
+    batch::Verdict,
+    errors::VMError,
+    grade::{Format, Suite},
+};
+
+const ECHO: &str = ".ORIG x3000
+LOOP     GETC
+         ADD R1, R0, #-10
+         BRz DONE
+         OUT
+         BRnzp LOOP
+DONE     HALT
+         .END";
+
+const SUITE: &str = r#"
+limit = 1000
+timeout = 5
+
+[[case]]
+input_text = "abc\n"
+expected_text = "abcHALT\n"
+
+[[case]]
+name = "<wrong>"
+input_text = "abd\n"
+expected_text = "abcHALT\n"
+
+[[case]]
+name = "no newline"
+input_text = "ab"
+limit = 20
+"#;
+
+/// The suite, with the echo program as the submission
+fn suite(test: &str) -> Suite {
+    let directory = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(test);
+    fs::create_dir_all(&directory).unwrap();
+    let image = directory.join("echo.obj");
+    fs::write(&image, assemble(ECHO).unwrap().image()).unwrap();
+    let path = directory.join("echo-suite.toml");
+    fs::write(&path, SUITE).unwrap();
+    Suite::load(&path, &[image]).unwrap()
+}
+
+#[test]
+fn cases_run_the_submission() {
+    let suite = suite("grade_cases");
+    assert_eq!(suite.name, "echo-suite");
+    let report = suite.run();
+    assert_eq!(report.outcomes.first().unwrap().name, "case 1");
+    let verdicts: Vec<&Verdict> = report.outcomes.iter().map(|o| &o.verdict).collect();
+    assert!(matches!(
+        verdicts.as_slice(),
+        [Verdict::Pass, Verdict::Fail(_), Verdict::Error(_)]
+    ));
+    assert_eq!(report.outcomes.get(1).unwrap().output, "abdHALT\n");
+}
+
+#[test]
+fn junit_results_count_failures_and_errors() {
+    let suite = suite("grade_junit");
+    let xml = Format::Junit.render(&suite.name, &suite.run());
+    assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n"));
+    assert!(xml.contains(r#"<testsuite name="echo-suite" tests="3" failures="1" errors="1""#));
+    assert!(xml.contains(r#"<testcase name="&lt;wrong&gt;" classname="echo-suite""#));
+    assert!(xml.contains(
+        r#"<failure message="line 1: expected &quot;abcHALT\n&quot;, got &quot;abdHALT\n&quot;"/>"#
+    ));
+    assert!(xml.contains(r#"<error message="I/O error: No input is available"/>"#));
+    assert!(xml.contains("<system-out>abcHALT\n</system-out>"));
+}
+
+#[test]
+fn json_results_describe_each_case() {
+    let suite = suite("grade_json");
+    let json = Format::Json.render(&suite.name, &suite.run());
+    let results: Value = serde_json::from_str(&json).unwrap();
+    let field = |value: &Value, name: &str| value.get(name).unwrap().clone();
+    assert_eq!(field(&results, "suite"), "echo-suite");
+    assert_eq!(field(&results, "passed"), 1);
+    assert_eq!(field(&results, "failed"), 2);
+    let cases = field(&results, "cases");
+    let case = |index: usize| cases.get(index).unwrap();
+    assert_eq!(field(case(0), "result"), "pass");
+    assert_eq!(field(case(0), "message"), Value::Null);
+    assert_eq!(field(case(1), "result"), "fail");
+    assert_eq!(field(case(2), "result"), "error");
+    assert_eq!(field(case(2), "instructions"), 10);
+    assert_eq!(field(case(2), "output"), "ab");
+}
+
+#[test]
+fn suites_need_a_submission_and_cases() {
+    let base = PathBuf::from(".");
+    let error = Suite::parse(SUITE, &base, &[]).unwrap_err();
+    assert!(matches!(error, VMError::InvalidArgument(_)));
+    let image = [PathBuf::from("echo.obj")];
+    let error = Suite::parse("limit = 5", &base, &image).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Invalid manifest: The manifest lists no [[case]]"
+    );
+    let error = Suite::parse("[[case]]\ntimeout = 0", &base, &image).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Invalid manifest: case 1: `timeout` must be a positive number of seconds"
+    );
+    assert_eq!("json".parse::<Format>().unwrap(), Format::Json);
+    assert!("xml".parse::<Format>().is_err());
+}
@@ -0,0 +1,141 @@
+//! Traps and instructions handled by the embedder
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    console::SharedConsole,
+    errors::VMError,
+    register::Register,
+    vm::{TrapMode, FIRST_FREE_TRAP, VM},
+};
+
+/// Adds R1 and R2 into R0 with the reserved opcode, then halts
+const RESERVED: &str = ".ORIG x3000
+         .FILL xD042
+         HALT
+         .END";
+
+/// Prints R0 with PUTD, the extended trap x26, then halts
+const PUTD: &str = ".ORIG x3000
+         TRAP x26
+         HALT
+         .END";
+
+fn vm(source: &str) -> VM {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    vm.load_asm_str(source).unwrap();
+    vm
+}
+
+fn extended(console: &SharedConsole) -> VM {
+    let mut vm = VM::builder()
+        .console(Box::new(console.clone()))
+        .trap_mode(TrapMode::Extended)
+        .build()
+        .unwrap();
+    vm.load_asm_str(PUTD).unwrap();
+    vm.set_register(Register::R0, 42);
+    vm
+}
+
+/// Puts R1 + R2 in the register of bits [11:9]
+fn add(vm: &mut VM, raw: u16) -> Result<(), VMError> {
+    let sum = vm
+        .register(Register::R1)
+        .wrapping_add(vm.register(Register::R2));
+    vm.set_register(Register::from_bits(raw >> 9), sum);
+    Ok(())
+}
+
+#[test]
+fn reserved_opcodes_go_to_the_handler() {
+    let mut vm = vm(RESERVED);
+    vm.set_register(Register::R1, 2);
+    vm.set_register(Register::R2, 3);
+    vm.set_reserved_opcode_handler(|vm, raw| {
+        assert_eq!(raw, 0xD042);
+        // the PC is past the instruction
+        assert_eq!(vm.pc(), 0x3001);
+        add(vm, raw)
+    });
+    vm.run().unwrap();
+    assert_eq!(vm.register(Register::R0), 5);
+    assert!(vm.is_halted());
+}
+
+#[test]
+fn reserved_opcodes_fail_without_a_handler() {
+    let mut vm = vm(RESERVED);
+    let error = vm.run().unwrap_err();
+    assert!(matches!(error, VMError::InvalidOpcode(_)), "{error}");
+
+    let mut vm = self::vm(RESERVED);
+    vm.set_reserved_opcode_handler(add);
+    vm.clear_reserved_opcode_handler();
+    let error = vm.run().unwrap_err();
+    assert!(matches!(error, VMError::InvalidOpcode(_)), "{error}");
+    assert_eq!(vm.register(Register::R0), 0);
+}
+
+#[test]
+fn handler_errors_stop_the_program() {
+    let mut vm = vm(RESERVED);
+    vm.set_reserved_opcode_handler(|_, _| {
+        Err(VMError::InvalidArgument(String::from(
+            "no such instruction",
+        )))
+    });
+    let error = vm.run().unwrap_err();
+    assert!(
+        matches!(&error, VMError::InvalidArgument(message) if message == "no such instruction")
+    );
+    assert!(!vm.is_halted());
+}
+
+#[test]
+fn only_unused_trap_vectors_can_be_registered() {
+    let mut vm = vm(PUTD);
+    for vector in [0x20, 0x25, FIRST_FREE_TRAP - 1, 0x100, 0xFFFF] {
+        let error = vm.register_trap(vector, |_| Ok(())).unwrap_err();
+        assert!(matches!(error, VMError::InvalidArgument(_)), "{error}");
+    }
+    vm.register_trap(FIRST_FREE_TRAP, |_| Ok(())).unwrap();
+    vm.register_trap(0xFF, |_| Ok(())).unwrap();
+    assert!(!vm.unregister_trap(0x25));
+}
+
+#[test]
+fn registered_traps_replace_the_extended_traps() {
+    let console = SharedConsole::new();
+    let mut vm = extended(&console);
+    vm.register_trap(0x26, |vm| {
+        // R7 already holds the return address
+        assert_eq!(vm.register(Register::R7), 0x3001);
+        vm.set_register(Register::R1, vm.register(Register::R0));
+        Ok(())
+    })
+    .unwrap();
+    vm.run().unwrap();
+    assert_eq!(vm.register(Register::R1), 42);
+    assert!(!console.take_output().contains("42"));
+}
+
+#[test]
+fn unregistered_traps_fall_back() {
+    let console = SharedConsole::new();
+    let mut vm = extended(&console);
+    vm.register_trap(0x26, |_| Ok(())).unwrap();
+    assert!(vm.unregister_trap(0x26));
+    assert!(!vm.unregister_trap(0x26));
+    vm.run().unwrap();
+    assert!(console.take_output().starts_with("42"));
+
+    // to the error of an unknown trap without the extended traps
+    let mut vm = self::vm(PUTD);
+    vm.register_trap(0x26, |_| Ok(())).unwrap();
+    vm.unregister_trap(0x26);
+    assert!(vm.run().is_err());
+    assert!(!vm.is_halted());
+}
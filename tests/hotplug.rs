@@ -0,0 +1,123 @@
+//! Devices attached and detached between runs, and the addresses they leave
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    console::SharedConsole,
+    errors::VMError,
+    mmio::DeviceRegion,
+    register::Register,
+    vm::{UnmappedAccess, BUS_ERROR, DMASR, DMA_DONE, INTERRUPT_VECTOR_TABLE, VM},
+};
+
+/// Reads xFE20 into R1, writes 9 to it and halts; the handler at x3006
+/// sets R2 to 5
+const UNMAPPED: &str = ".ORIG x3000
+         LD R6, STACK
+         LDI R1, DEVICE
+         AND R0, R0, #0
+         ADD R0, R0, #9
+         STI R0, DEVICE
+         HALT
+HANDLER  ADD R2, R2, #5
+         RTI
+STACK    .FILL x4000
+DEVICE   .FILL xFE20
+         .END";
+const HANDLER: u16 = 0x3006;
+const REGISTER: u16 = 0xFE20;
+
+fn vm(policy: UnmappedAccess) -> VM {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .unmapped_access(policy)
+        .build()
+        .unwrap();
+    vm.load_asm_str(UNMAPPED).unwrap();
+    vm.poke(INTERRUPT_VECTOR_TABLE + u16::from(BUS_ERROR), HANDLER)
+        .unwrap();
+    vm.poke(REGISTER, 7).unwrap();
+    vm
+}
+
+#[test]
+fn unmapped_addresses_are_memory_by_default() {
+    let mut vm = vm(UnmappedAccess::default());
+    vm.run().unwrap();
+    assert_eq!(vm.register(Register::R1), 7);
+    assert_eq!(vm.peek(REGISTER), 9);
+}
+
+#[test]
+fn unmapped_addresses_can_read_zero() {
+    let mut vm = vm(UnmappedAccess::Zero);
+    vm.run().unwrap();
+    assert_eq!(vm.register(Register::R1), 0);
+    assert_eq!(vm.peek(REGISTER), 7);
+    assert_eq!(vm.register(Register::R2), 0);
+}
+
+#[test]
+fn unmapped_addresses_can_raise_a_bus_error() {
+    let mut vm = vm(UnmappedAccess::BusError);
+    vm.step().unwrap();
+    vm.step().unwrap();
+    assert_eq!(vm.register(Register::R1), 0);
+    assert_eq!(vm.pc(), 0x3002);
+    vm.step().unwrap();
+    assert_eq!(vm.pc(), HANDLER + 1);
+    assert_eq!(vm.register(Register::R2), 5);
+    vm.run().unwrap();
+    assert_eq!(vm.register(Register::R2), 10);
+    assert_eq!(vm.peek(REGISTER), 7);
+    assert_eq!(vm.metrics().interrupts, 2);
+}
+
+#[test]
+fn unmapped_addresses_can_fail() {
+    let mut vm = vm(UnmappedAccess::Fail);
+    let error = vm.run().unwrap_err();
+    assert_eq!(
+        error,
+        VMError::MemoryIndex(String::from("No device answers xFE20"))
+    );
+}
+
+#[test]
+fn mapped_addresses_are_not_affected() {
+    let mut vm = vm(UnmappedAccess::Fail);
+    vm.map_device(DeviceRegion::new("host", REGISTER, REGISTER).unwrap())
+        .unwrap();
+    vm.run().unwrap();
+    assert_eq!(vm.register(Register::R1), 7);
+    assert_eq!(vm.peek(REGISTER), 9);
+}
+
+#[test]
+fn detached_devices_leave_their_addresses() {
+    let mut vm = vm(UnmappedAccess::Zero);
+    vm.enable_dma().unwrap();
+    vm.poke(DMASR, DMA_DONE).unwrap();
+    vm.detach_device("dma").unwrap();
+    assert!(!vm.memory().mmio_map().contains_device("dma"));
+    assert_eq!(vm.peek(DMASR), 0);
+    vm.enable_dma().unwrap();
+    assert!(vm.memory().mmio_map().contains_device("dma"));
+    assert!(vm.detach_device("keyboard").is_err());
+    assert!(vm.detach_device("timer").is_err());
+}
+
+#[test]
+fn policies_parse_from_their_names() {
+    for policy in [
+        UnmappedAccess::Memory,
+        UnmappedAccess::Zero,
+        UnmappedAccess::BusError,
+        UnmappedAccess::Fail,
+    ] {
+        assert_eq!(
+            policy.to_string().parse::<UnmappedAccess>().unwrap(),
+            policy
+        );
+    }
+    assert!("ignore".parse::<UnmappedAccess>().is_err());
+}
@@ -0,0 +1,82 @@
+//! Programs given to the VM as assembly text
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{console::SharedConsole, errors::VMError, register::Register, vm::VM};
+
+fn vm(console: &SharedConsole) -> VM {
+    VM::builder()
+        .console(Box::new(console.clone()))
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn load_asm_str_runs_like_an_image() {
+    let console = SharedConsole::new();
+    let mut vm = vm(&console);
+    let assembly = vm
+        .load_asm_str(
+            "       LEA R0, GREETING
+                    PUTS
+                    HALT
+            GREETING .STRINGZ \"Hi\"",
+        )
+        .unwrap();
+    assert_eq!(assembly.symbols.address_of("greeting"), Some(0x3003));
+    vm.run().unwrap();
+    assert_eq!(console.take_output(), "HiHALT\n");
+}
+
+#[test]
+fn load_asm_str_uses_the_entry_point_or_orig() {
+    let console = SharedConsole::new();
+    let mut vm = VM::builder()
+        .console(Box::new(console.clone()))
+        .entry(0x4000)
+        .build()
+        .unwrap();
+    vm.load_asm_str("HALT").unwrap();
+    assert_eq!((vm.pc(), vm.peek(0x4000)), (0x4000, 0xF025));
+    vm.load_asm_str(".ORIG x5000\nHALT\n.END").unwrap();
+    assert_eq!(vm.pc(), 0x5000);
+}
+
+#[test]
+fn exec_asm_acts_on_the_live_machine() {
+    let console = SharedConsole::new();
+    let mut vm = vm(&console);
+    vm.exec_asm("ADD R0, R0, #1").unwrap();
+    vm.exec_asm("ADD R0, R0, #1\nADD R1, R0, #-3").unwrap();
+    assert_eq!(vm.register(Register::R0), 2);
+    assert_eq!(vm.register(Register::R1), 0xFFFF);
+    assert!(vm.psr().negative());
+    assert_eq!(vm.pc(), 0x3003);
+
+    // a loop runs to completion before the PC leaves the snippet
+    vm.exec_asm("AND R2, R2, #0\nLOOP ADD R2, R2, #2\nADD R0, R0, #-1\nBRp LOOP")
+        .unwrap();
+    assert_eq!(vm.register(Register::R2), 4);
+
+    vm.exec_asm("HALT").unwrap();
+    assert!(vm.is_halted());
+    vm.exec_asm("LD R3, #-2").unwrap();
+    assert_eq!(vm.register(Register::R3), 0xF025);
+    assert!(!vm.is_halted());
+}
+
+#[test]
+fn exec_asm_reports_assembly_errors_and_limits() {
+    let console = SharedConsole::new();
+    let mut vm = vm(&console);
+    assert!(matches!(
+        vm.exec_asm("ADD R0, R0, #99"),
+        Err(VMError::Assembly(report))
+            if report.starts_with("line 1, column 13: 99 does not fit in 5 signed bits\n")
+    ));
+    assert_eq!(vm.pc(), 0x3000);
+    vm.set_instruction_limit(Some(100)).unwrap();
+    assert!(matches!(
+        vm.exec_asm("LOOP BRnzp LOOP"),
+        Err(VMError::InstructionLimit(_))
+    ));
+}
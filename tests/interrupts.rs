@@ -0,0 +1,127 @@
+//! Interrupts and exceptions raised by the host
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    console::SharedConsole,
+    register::Register,
+    vm::{Psr, StopReason, INTERRUPT_VECTOR_TABLE, SUPERVISOR_STACK, VM},
+};
+
+/// Counts in R1 while a handler adds 5 to R2
+const COUNTER: &str = ".ORIG x3000
+         LD R6, STACK
+         ADD R1, R1, #1
+         ADD R1, R1, #1
+         HALT
+HANDLER  ADD R2, R2, #5
+         RTI
+STACK    .FILL x4000
+         .END";
+const HANDLER: u16 = 0x3004;
+const VECTOR: u8 = 0x80;
+/// The illegal opcode exception of an LC-3 operating system
+const EXCEPTION: u8 = 0x01;
+
+fn vm() -> VM {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    vm.load_asm_str(COUNTER).unwrap();
+    for vector in [VECTOR, EXCEPTION] {
+        vm.poke(INTERRUPT_VECTOR_TABLE + u16::from(vector), HANDLER)
+            .unwrap();
+    }
+    vm
+}
+
+#[test]
+fn interrupts_enter_the_handler_and_return() {
+    let mut vm = vm();
+    vm.step().unwrap();
+    vm.raise_interrupt(VECTOR, 4).unwrap();
+    vm.step().unwrap();
+    assert_eq!(vm.pc(), HANDLER + 1);
+    assert_eq!(vm.register(Register::R6), 0x3FFE);
+    assert_eq!(vm.peek(0x3FFE), 0x3001);
+    assert_eq!(vm.peek(0x3FFF), 0x0001);
+    assert_eq!(vm.psr().priority(), 4);
+    assert!(vm.in_handler());
+    vm.run().unwrap();
+    assert_eq!(vm.register(Register::R1), 2);
+    assert_eq!(vm.register(Register::R2), 5);
+    assert_eq!(vm.register(Register::R6), 0x4000);
+    assert_eq!(vm.psr().priority(), 0);
+    assert!(!vm.in_handler());
+    assert_eq!(vm.metrics().interrupts, 1);
+}
+
+#[test]
+fn interrupts_wait_for_a_lower_priority() {
+    let mut vm = vm();
+    let mut psr = Psr::default();
+    psr.set_priority(5);
+    vm.set_psr(psr);
+    vm.raise_interrupt(VECTOR, 3).unwrap();
+    vm.run().unwrap();
+    assert_eq!(vm.register(Register::R2), 0);
+    assert_eq!(vm.pending_interrupts().collect::<Vec<_>>(), [(VECTOR, 3)]);
+    vm.clear_interrupts();
+    assert_eq!(vm.pending_interrupts().count(), 0);
+    assert!(vm.raise_interrupt(VECTOR, 8).is_err());
+}
+
+#[test]
+fn exceptions_are_entered_right_away_at_the_same_priority() {
+    let mut vm = vm();
+    vm.step().unwrap();
+    vm.raise_exception(EXCEPTION).unwrap();
+    assert_eq!(vm.pc(), HANDLER);
+    assert_eq!(vm.psr().priority(), 0);
+    vm.run().unwrap();
+    assert_eq!(vm.register(Register::R2), 5);
+    assert_eq!(vm.metrics().interrupts, 1);
+}
+
+#[test]
+fn user_programs_switch_to_the_supervisor_stack() {
+    let mut vm = vm();
+    vm.step().unwrap();
+    let mut psr = vm.psr();
+    psr.set_user_mode(true);
+    vm.set_psr(psr);
+    vm.raise_interrupt(VECTOR, 1).unwrap();
+    vm.step().unwrap();
+    assert!(!vm.psr().user_mode());
+    assert_eq!(vm.register(Register::R6), SUPERVISOR_STACK - 2);
+    vm.step().unwrap();
+    assert!(vm.psr().user_mode());
+    assert_eq!(vm.register(Register::R6), 0x4000);
+    assert_eq!(vm.pc(), 0x3001);
+}
+
+#[test]
+fn debuggers_can_stop_before_an_interrupt() {
+    let mut vm = vm();
+    vm.set_break_on_interrupt(true);
+    vm.step().unwrap();
+    vm.raise_interrupt(VECTOR, 2).unwrap();
+    assert_eq!(vm.pending_stop(), Some(StopReason::Interrupt(0x80)));
+    assert_eq!(vm.resume().unwrap(), StopReason::Halted);
+    assert_eq!(vm.register(Register::R2), 5);
+}
+
+#[test]
+fn debuggers_stop_before_an_interrupt_at_a_trap() {
+    let mut vm = vm();
+    vm.set_break_on_interrupt(true);
+    for _ in 0..3 {
+        vm.step().unwrap();
+    }
+    // the PC is at HALT
+    vm.raise_interrupt(VECTOR, 2).unwrap();
+    assert_eq!(vm.pending_stop(), Some(StopReason::Interrupt(0x80)));
+    // a break on the trap comes first
+    vm.add_trap_break(Some(0x25));
+    assert_eq!(vm.pending_stop(), Some(StopReason::Trap(0x25)));
+}
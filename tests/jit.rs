@@ -0,0 +1,200 @@
+//! Runs the same programs with and without the JIT and compares the final
+//! machine state and metrics.
+#![cfg(feature = "jit")]
+#![allow(clippy::unwrap_used)]
+
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+    time::Duration,
+};
+
+use lc3_vm::{
+    console::Console,
+    errors::VMError,
+    instructions::{Instruction, Operand},
+    test_utils::{Program, R0, R1, R2, R3, R4},
+    vm::{Metrics, VM},
+};
+
+/// Console that never has input and records every written character
+struct RecordingConsole {
+    output: Rc<RefCell<String>>,
+}
+
+impl Console for RecordingConsole {
+    fn read_key(&mut self) -> Result<u8, VMError> {
+        Ok(0)
+    }
+
+    fn poll_key(&mut self) -> Result<Option<u8>, VMError> {
+        Ok(None)
+    }
+
+    fn write_char(&mut self, character: char) -> Result<(), VMError> {
+        self.output.borrow_mut().push(character);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), VMError> {
+        Ok(())
+    }
+}
+
+/// Runs `program` and returns the state hash, the output and the metrics
+/// but for the time taken
+fn run(program: &Program, jit: bool) -> (u64, String, Metrics) {
+    let output = Rc::new(RefCell::new(String::new()));
+    let mut vm = VM::new();
+    vm.set_jit(jit);
+    vm.set_console(Box::new(RecordingConsole {
+        output: Rc::clone(&output),
+    }));
+    program.load_into(&mut vm).unwrap();
+    vm.run().unwrap();
+    let output = output.borrow().clone();
+    let metrics = Metrics {
+        host_time: Duration::ZERO,
+        ..vm.metrics()
+    };
+    (vm.state_hash(), output, metrics)
+}
+
+fn assert_same_state(program: &Program) {
+    assert_eq!(run(program, true), run(program, false));
+}
+
+#[test]
+fn arithmetic_loop() {
+    assert_same_state(
+        &Program::at(0x3000)
+            .and(R0, R0, 0)
+            .ld(R1, 7) // COUNT
+            .add(R0, R0, R1) // LOOP
+            .not(R2, R0)
+            .and(R3, R2, 7)
+            .add(R0, R0, R3)
+            .add(R1, R1, -1)
+            .brp(-6) // LOOP
+            .trap_halt()
+            .fill(500), // COUNT
+    );
+}
+
+#[test]
+fn memory_accesses() {
+    assert_same_state(
+        &Program::at(0x3000)
+            .lea(R1, 15) // ARRAY
+            .ld(R2, 13) // COUNT
+            .and(R0, R0, 0)
+            .str(R2, R1, 0) // LOOP
+            .ldr(R3, R1, 0)
+            .add(R0, R0, R3)
+            .st(R0, 7) // TOTAL
+            .sti(R2, 5) // PTR
+            .ldi(R4, 4) // PTR
+            .add(R1, R1, 1)
+            .add(R2, R2, -1)
+            .brp(-9) // LOOP
+            .trap_halt()
+            .fill(0x4000) // PTR
+            .fill(0) // TOTAL
+            .fill(200), // COUNT
+    );
+}
+
+#[test]
+fn subroutine_calls() {
+    assert_same_state(
+        &Program::at(0x3000)
+            .ld(R1, 7) // COUNT
+            .and(R0, R0, 0)
+            .jsr(3) // LOOP, calls SUB
+            .add(R1, R1, -1)
+            .brp(-3) // LOOP
+            .trap_halt()
+            .add(R0, R0, 3) // SUB
+            .ret()
+            .fill(300), // COUNT
+    );
+}
+
+#[test]
+fn traps_inside_hot_loop() {
+    let (_, output, _) = run(
+        &Program::at(0x3000)
+            .ld(R1, 5) // COUNT
+            .ld(R0, 5) // CHAR
+            .trap_out() // LOOP
+            .add(R1, R1, -1)
+            .brp(-3) // LOOP
+            .trap_halt()
+            .fill(50) // COUNT
+            .fill(0x0061), // CHAR 'a'
+        true,
+    );
+    assert_eq!(output, format!("{}HALT\n", "a".repeat(50)));
+}
+
+#[test]
+fn compiled_store_patches_cached_code() {
+    let new_op = Instruction::Add {
+        dr: R0,
+        sr1: R0,
+        operand: Operand::Immediate(2),
+    };
+    assert_same_state(
+        &Program::at(0x3000)
+            .ld(R1, 10) // COUNT
+            .ld(R2, 10) // NEWOP
+            .lea(R4, 10) // PAD
+            .and(R0, R0, 0)
+            .jsr(27) // LOOP, calls TARGET
+            .str(R2, R4, 0)
+            .add(R4, R4, 1)
+            .add(R1, R1, -1)
+            .brp(-5) // LOOP
+            .jsr(22) // TARGET
+            .trap_halt()
+            .fill(20) // COUNT
+            .fill(new_op.encode()) // NEWOP
+            .blkw(19) // PAD
+            .add(R0, R0, 1) // TARGET
+            .ret(),
+    );
+}
+
+/// Counts 30000 down in a two instruction loop
+fn countdown() -> Program {
+    Program::at(0x3000)
+        .ld(R1, 3) // COUNT
+        .add(R1, R1, -1) // LOOP
+        .brp(-2) // LOOP
+        .trap_halt()
+        .fill(30000) // COUNT
+}
+
+#[test]
+fn compiled_loops_are_counted() {
+    let (_, _, metrics) = run(&countdown(), true);
+    assert_eq!(metrics.instructions, 60002);
+    assert_eq!(metrics.memory_reads, 1);
+    assert_same_state(&countdown());
+}
+
+#[test]
+fn compiled_loops_return_to_call_back_the_host() {
+    let callbacks = |jit: bool| {
+        let calls = Rc::new(Cell::new(0));
+        let mut vm = VM::new();
+        vm.set_jit(jit);
+        let counter = Rc::clone(&calls);
+        vm.set_metrics_callback(5000, move |_| counter.set(counter.get() + 1));
+        countdown().load_into(&mut vm).unwrap();
+        vm.run_headless().unwrap();
+        calls.get()
+    };
+    assert_eq!(callbacks(true), 12);
+    assert_eq!(callbacks(false), 12);
+}
@@ -0,0 +1,328 @@
+//! The lc3sim compatible front end driven command by command
+#![allow(clippy::unwrap_used)]
+
+use std::{fs, path::Path};
+
+use lc3_vm::{
+    assembler::assemble, console::SharedConsole, lc3sim::Simulator, register::Register, vm::VM,
+};
+
+const CALLS: &str = "\
+        .ORIG x3000
+        JSR OUTER
+        HALT
+OUTER   ADD R6, R7, #0
+        JSR INNER
+        ADD R7, R6, #0
+        RET
+INNER   ADD R1, R1, #1
+        RET
+        .END";
+
+fn simulator_with(console: &SharedConsole) -> Simulator {
+    Simulator::new(
+        VM::builder()
+            .console(Box::new(console.clone()))
+            .build()
+            .unwrap(),
+    )
+}
+
+fn golden(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(name);
+    path.to_str().unwrap().to_owned()
+}
+
+/// Writes `name.obj` and `name.sym` for `source` and returns the path
+/// without extension
+fn write_program(name: &str, source: &str) -> String {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let assembly = assemble(source).unwrap();
+    fs::write(path.with_extension("obj"), assembly.image()).unwrap();
+    fs::write(path.with_extension("sym"), assembly.symbols.to_sym()).unwrap();
+    path.to_str().unwrap().to_owned()
+}
+
+#[test]
+fn breakpoints_stop_and_print_the_registers() {
+    let console = SharedConsole::new();
+    let mut simulator = simulator_with(&console);
+    let source = golden("countdown.asm");
+    assert_eq!(
+        simulator.eval(&format!("file {source}")).unwrap(),
+        format!("Loaded \"{source}\" and set PC to x3000")
+    );
+    assert_eq!(
+        simulator.eval("break set LOOP").unwrap(),
+        "Set breakpoint at x3002."
+    );
+    assert_eq!(
+        simulator.eval("c").unwrap(),
+        "PC=x3002 IR=x2409 PSR=x0001 POSITIVE\n\
+         R0=x0000 R1=x0009 R2=x0030 R3=x0000 R4=x0000 R5=x0000 R6=x0000 R7=x0000 \n\
+         LOOP              x3002 x1042 ADD R0, R1, R2"
+    );
+    assert_eq!(
+        simulator.eval("translate NINE").unwrap(),
+        "Address x300A has value x0009."
+    );
+    simulator.eval("break clear all").unwrap();
+    assert_eq!(
+        simulator.eval("break list").unwrap(),
+        "No breakpoints are set."
+    );
+    let halted = simulator.eval("continue").unwrap();
+    assert!(halted.starts_with("PC=x300A"));
+    assert!(simulator.vm().is_halted());
+    assert_eq!(
+        console.take_output(),
+        "9876543210\n\n\n--- halting the LC-3 ---\n\n"
+    );
+}
+
+#[test]
+fn next_and_finish_run_whole_subroutines() {
+    let console = SharedConsole::new();
+    let mut simulator = simulator_with(&console);
+    let path = write_program("calls", CALLS);
+    simulator.eval(&format!("file {path}")).unwrap();
+    // JSR OUTER runs to the HALT after it
+    assert!(simulator.eval("n").unwrap().starts_with("PC=x3001"));
+    simulator.eval("reset").unwrap();
+    simulator.eval("step").unwrap();
+    simulator.eval("step").unwrap();
+    // inside OUTER, INNER is called and returned from on the way out
+    let finished = simulator.eval("finish").unwrap();
+    assert!(finished.starts_with("PC=x3001 IR=xC1C0"));
+    assert_eq!(simulator.vm().register(Register::R1), 1);
+}
+
+#[test]
+fn object_files_load_with_their_symbols() {
+    let console = SharedConsole::new();
+    let mut simulator = simulator_with(&console);
+    let path = write_program("symbols", CALLS);
+    simulator.eval(&format!("f {path}")).unwrap();
+    assert_eq!(
+        simulator.eval("list INNER INNER").unwrap(),
+        "INNER             x3006 x1261 ADD R1, R1, #1"
+    );
+    assert_eq!(
+        simulator.eval("dump x3000 x3002").unwrap(),
+        "x3000: x4801 xF025 x1DE0"
+    );
+    assert_eq!(
+        simulator.eval("memory x4000 #-1").unwrap(),
+        "Wrote xFFFF to address x4000."
+    );
+    assert_eq!(
+        simulator.eval("register R3 x41").unwrap(),
+        "Set R3 to x0041."
+    );
+    assert_eq!(
+        simulator.eval("frobnicate").unwrap(),
+        "Unknown command.  Type 'help' for a list."
+    );
+}
+
+#[test]
+fn scripts_run_until_quit() {
+    let console = SharedConsole::new();
+    let mut simulator = simulator_with(&console);
+    let path = write_program("scripted", CALLS);
+    let script = Path::new(env!("CARGO_TARGET_TMPDIR")).join("script.lcs");
+    fs::write(
+        &script,
+        format!("file {path}\nbreak set INNER\ncontinue\nquit\nstep\n"),
+    )
+    .unwrap();
+    let output = simulator
+        .eval(&format!("execute {}", script.display()))
+        .unwrap();
+    assert!(output.ends_with("INNER             x3006 x1261 ADD R1, R1, #1"));
+    assert!(simulator.has_quit());
+    assert_eq!(simulator.vm().pc(), 0x3006);
+    assert!(simulator.eval("q").is_none());
+}
+
+#[test]
+fn diffs_show_what_changed_since_a_checkpoint() {
+    let console = SharedConsole::new();
+    let mut simulator = simulator_with(&console);
+    let path = write_program("checkpoints", CALLS);
+    simulator.eval(&format!("file {path}")).unwrap();
+    assert_eq!(
+        simulator.eval("checkpoint save start").unwrap(),
+        "Saved checkpoint start at x3000."
+    );
+    assert_eq!(
+        simulator.eval("diff start").unwrap(),
+        "Nothing changed since checkpoint start."
+    );
+    simulator.eval("step").unwrap();
+    simulator.eval("step").unwrap();
+    simulator.eval("memory x4000 #5").unwrap();
+    assert_eq!(
+        simulator.eval("di start").unwrap(),
+        "PC x3000 -> x3003\n\
+         PSR x0002 -> x0001\n\
+         R6 x0000 -> x3001\n\
+         R7 x0000 -> x3001\n\
+         x4000 x0000 -> x0005"
+    );
+    assert_eq!(
+        simulator.eval("diff later").unwrap(),
+        "No checkpoint is named later."
+    );
+}
+
+#[test]
+fn checkpoints_are_restored_to_run_a_region_again() {
+    let console = SharedConsole::new();
+    let mut simulator = simulator_with(&console);
+    let path = write_program("rollback", CALLS);
+    simulator.eval(&format!("file {path}")).unwrap();
+    simulator.eval("step").unwrap();
+    simulator.eval("checkpoint save outer").unwrap();
+    simulator.eval("memory x4000 #5").unwrap();
+    simulator.eval("finish").unwrap();
+    assert_eq!(simulator.vm().register(Register::R1), 1);
+
+    let restored = simulator.eval("checkpoint restore outer").unwrap();
+    assert!(restored.starts_with("Restored checkpoint outer.\nPC=x3002"));
+    assert_eq!(simulator.vm().register(Register::R1), 0);
+    assert_eq!(simulator.vm().peek(0x4000), 0);
+    simulator.eval("finish").unwrap();
+    assert_eq!(simulator.vm().register(Register::R1), 1);
+
+    assert_eq!(simulator.eval("checkpoint list").unwrap(), "outer");
+    assert_eq!(
+        simulator.eval("checkpoint restore inner").unwrap(),
+        "No checkpoint is named inner."
+    );
+    assert_eq!(
+        simulator.eval("ch delete outer").unwrap(),
+        "Deleted checkpoint outer."
+    );
+    assert_eq!(
+        simulator.eval("checkpoint list").unwrap(),
+        "No checkpoints are saved."
+    );
+}
+
+#[test]
+fn microstep_shows_the_control_states() {
+    let console = SharedConsole::new();
+    let mut simulator = simulator_with(&console);
+    let base = write_program("microstep", CALLS);
+    simulator.eval(&format!("file {base}.obj")).unwrap();
+    assert_eq!(
+        simulator.eval("microstep").unwrap(),
+        "State 18: MAR ← PC, PC ← PC + 1\nMAR=x3000 MDR=x0000 IR=x0000 BEN=0 next state 33"
+    );
+    simulator.eval("microstep").unwrap();
+    simulator.eval("microstep").unwrap();
+    simulator.eval("mi").unwrap();
+    assert_eq!(
+        simulator.eval("microstep").unwrap().lines().next().unwrap(),
+        "State 4: [IR[11]]"
+    );
+    let last = simulator.eval("microstep").unwrap();
+    assert!(last.starts_with("State 21: R7 ← PC, PC ← PC + off11\n"));
+    assert!(last.contains("R7=x3001"));
+}
+
+#[test]
+fn trap_breaks_stop_before_the_trap() {
+    let console = SharedConsole::new();
+    let mut simulator = simulator_with(&console);
+    let path = write_program("trap_breaks", CALLS);
+    simulator.eval(&format!("file {path}")).unwrap();
+    assert_eq!(
+        simulator.eval("break trap x25").unwrap(),
+        "Will stop before TRAP x25."
+    );
+    let stopped = simulator.eval("continue").unwrap();
+    assert!(stopped.starts_with("Stopped before TRAP x25.\nPC=x3001"));
+    assert_eq!(
+        simulator.eval("break trap clear").unwrap(),
+        "Cleared all trap breaks."
+    );
+}
+
+#[test]
+fn device_breaks_stop_after_the_access() {
+    let console = SharedConsole::new();
+    let mut simulator = simulator_with(&console);
+    let path = write_program(
+        "device_breaks",
+        ".ORIG x3000
+         LD R0, VALUE
+         STI R0, DEVICE
+         HALT
+VALUE    .FILL #7
+DEVICE   .FILL xFE20
+         .END",
+    );
+    simulator.eval(&format!("file {path}")).unwrap();
+    assert_eq!(
+        simulator.eval("break device x3000").unwrap(),
+        "x3000 is not a device register."
+    );
+    assert_eq!(
+        simulator.eval("break device xFE20 write").unwrap(),
+        "Will stop after writing xFE20."
+    );
+    let stopped = simulator.eval("continue").unwrap();
+    assert!(stopped.starts_with("Stopped after writing xFE20.\nPC=x3002"));
+    assert_eq!(
+        simulator.eval("break device clear").unwrap(),
+        "Cleared all device breaks."
+    );
+}
+
+#[test]
+fn undo_takes_back_the_last_instruction() {
+    let console = SharedConsole::new();
+    let mut simulator = simulator_with(&console);
+    let path = write_program("undo", CALLS);
+    simulator.eval(&format!("file {path}")).unwrap();
+    assert_eq!(simulator.eval("undo").unwrap(), "No instruction to undo.");
+    simulator.eval("step").unwrap();
+    assert!(simulator.eval("undo").unwrap().starts_with("PC=x3000"));
+    assert_eq!(simulator.vm().register(Register::R7), 0);
+}
+
+#[test]
+fn devices_lists_the_device_map() {
+    let console = SharedConsole::new();
+    let mut simulator = simulator_with(&console);
+    assert_eq!(
+        simulator.eval("devices").unwrap(),
+        "xFE00-xFE02  keyboard\nxFFFC        psr"
+    );
+}
+
+#[test]
+fn devices_attach_and_detach_while_stopped() {
+    let console = SharedConsole::new();
+    let mut simulator = simulator_with(&console);
+    assert_eq!(simulator.eval("attach dma").unwrap(), "Attached dma.");
+    assert!(simulator
+        .eval("devices")
+        .unwrap()
+        .contains("xFE12-xFE1A  dma"));
+    assert_eq!(simulator.eval("detach dma").unwrap(), "Detached dma.");
+    assert!(!simulator.eval("devices").unwrap().contains("dma"));
+    assert_eq!(
+        simulator.eval("detach dma").unwrap(),
+        "No device named `dma` is attached."
+    );
+    assert_eq!(
+        simulator.eval("detach keyboard").unwrap(),
+        "The keyboard cannot be detached."
+    );
+}
@@ -0,0 +1,142 @@
+//! Programs assembled separately and linked through `.GLOBAL` and
+//! `.EXTERNAL` labels
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    assembler::{assemble, assemble_with_diagnostics, Field, Relocation},
+    console::SharedConsole,
+    errors::VMError,
+    linker::link,
+    vm::VM,
+};
+
+const MAIN: &str = "
+        .ORIG x3000
+        .EXTERNAL print, MESSAGE
+        LEA R0, TEXT
+        JSR PRINT
+        LD R0, POINTER
+        JSR PRINT
+        HALT
+TEXT    .STRINGZ \"main\\n\"
+POINTER .FILL MESSAGE
+        .END";
+
+const LIBRARY: &str = "
+        .ORIG x3020
+        .GLOBAL PRINT, message
+PRINT   ST R7, SAVE
+        PUTS
+        LD R7, SAVE
+        RET
+SAVE    .BLKW 1
+MESSAGE .STRINGZ \"library\\n\"
+        .END";
+
+#[test]
+fn external_labels_are_left_for_the_linker() {
+    let main = assemble(MAIN).unwrap();
+    assert_eq!(
+        main.relocations,
+        [
+            Relocation {
+                address: 0x3001,
+                symbol: String::from("print"),
+                field: Field::PcOffset(11),
+            },
+            Relocation {
+                address: 0x3003,
+                symbol: String::from("print"),
+                field: Field::PcOffset(11),
+            },
+            Relocation {
+                address: 0x300B,
+                symbol: String::from("MESSAGE"),
+                field: Field::Word,
+            },
+        ]
+    );
+    assert!(matches!(main.ensure_linked(), Err(VMError::Link(_))));
+    assert_eq!(assemble(LIBRARY).unwrap().globals, ["message", "PRINT"]);
+}
+
+#[test]
+fn linked_programs_run_as_one() {
+    let linked = link(&[assemble(LIBRARY).unwrap(), assemble(MAIN).unwrap()]).unwrap();
+    assert_eq!(linked.origin, 0x3000);
+    assert_eq!(linked.words.len(), 0x2E);
+    assert!(linked.relocations.is_empty());
+    assert_eq!(linked.symbols.address_of("message"), Some(0x3025));
+    // JSR PRINT from x3001 reaches x3020
+    assert_eq!(linked.words.get(1), Some(&0x481E));
+
+    let console = SharedConsole::new();
+    let mut vm = VM::builder()
+        .console(Box::new(console.clone()))
+        .build()
+        .unwrap();
+    vm.read_image_bytes(&linked.image()).unwrap();
+    vm.run().unwrap();
+    assert_eq!(console.take_output(), "main\nlibrary\nHALT\n");
+}
+
+#[test]
+fn linking_errors() {
+    let main = assemble(MAIN).unwrap();
+    let library = assemble(LIBRARY).unwrap();
+    let far = assemble(&LIBRARY.replace("x3020", "x3800")).unwrap();
+    let overlapping = assemble(&LIBRARY.replace("x3020", "x3008")).unwrap();
+    for (programs, message) in [
+        (
+            vec![main.clone()],
+            "print is used at x3001 but no program declares it .GLOBAL",
+        ),
+        (
+            vec![main.clone(), library.clone(), library],
+            "message is declared .GLOBAL twice, at x3025 and x3025",
+        ),
+        (
+            vec![main.clone(), overlapping],
+            "the programs at x3000 and x3008 overlap",
+        ),
+        (
+            vec![main, far],
+            "print at x3800 is too far from x3001 for a 11-bit offset",
+        ),
+        (vec![], "nothing to link"),
+    ] {
+        assert_eq!(
+            link(&programs).unwrap_err(),
+            VMError::Link(String::from(message))
+        );
+    }
+}
+
+#[test]
+fn linkage_declarations_are_checked() {
+    for (source, message) in [
+        (
+            ".ORIG x3000\n.EXTERNAL X\nX HALT\n.END",
+            "label X is declared .EXTERNAL",
+        ),
+        (
+            ".ORIG x3000\n.GLOBAL MISSING\nHALT\n.END",
+            "MISSING is declared .GLOBAL but not defined",
+        ),
+        (".ORIG x3000\n.GLOBAL\n.END", ".GLOBAL takes labels"),
+    ] {
+        let diagnostics = assemble_with_diagnostics(source, None).unwrap_err();
+        assert_eq!(
+            diagnostics
+                .first()
+                .map(|diagnostic| diagnostic.message.as_str()),
+            Some(message),
+            "{source}"
+        );
+    }
+    let mut vm = VM::new();
+    assert!(matches!(
+        vm.load_asm_str(".EXTERNAL X\nJSR X"),
+        Err(VMError::Link(_))
+    ));
+}
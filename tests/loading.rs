@@ -0,0 +1,145 @@
+//! Where programs start after their images are loaded
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{console::SharedConsole, register::Register, vm::VM};
+
+/// An image at `origin` adding 1 to R0 and halting
+fn program(origin: u16) -> Vec<u8> {
+    [origin, 0x1021, 0xF025]
+        .iter()
+        .flat_map(|word| word.to_be_bytes())
+        .collect()
+}
+
+fn quiet_vm() -> VM {
+    VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn programs_start_at_the_origin_of_the_first_image() {
+    let mut vm = quiet_vm();
+    vm.read_image_bytes(&program(0x4000)).unwrap();
+    vm.read_image_bytes(&program(0x5000)).unwrap();
+    assert_eq!(vm.pc(), 0x4000);
+    vm.run().unwrap();
+    assert_eq!(vm.register(Register::R0), 1);
+
+    vm.reload().unwrap();
+    assert_eq!(vm.pc(), 0x4000);
+}
+
+#[test]
+fn resets_forget_the_images_loaded() {
+    let mut vm = quiet_vm();
+    vm.read_image_bytes(&program(0x4000)).unwrap();
+    vm.reset(false);
+    vm.read_image_bytes(&program(0x5000)).unwrap();
+    vm.reload().unwrap();
+    assert_eq!(vm.peek(0x4000), 0);
+    assert_eq!(vm.peek(0x5000), 0x1021);
+    assert_eq!(vm.pc(), 0x5000);
+
+    // unless the memory is kept
+    vm.reset(true);
+    vm.reload().unwrap();
+    assert_eq!(vm.peek(0x5000), 0x1021);
+}
+
+#[test]
+fn an_explicit_entry_overrides_the_origin() {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .entry(0x5000)
+        .build()
+        .unwrap();
+    vm.read_image_bytes(&program(0x4000)).unwrap();
+    vm.read_image_bytes(&program(0x5000)).unwrap();
+    assert_eq!(vm.pc(), 0x5000);
+
+    let mut vm = quiet_vm();
+    vm.read_image_bytes(&program(0x4000)).unwrap();
+    vm.set_entry(0x4001);
+    vm.reset(true);
+    assert_eq!(vm.entry(), 0x4001);
+    assert_eq!(vm.pc(), 0x4001);
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn gzip_compressed_images_load_transparently() {
+    use std::{fs, io::Write, path::Path};
+
+    use flate2::{write::GzEncoder, Compression};
+    use lc3_vm::errors::VMError;
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&program(0x4000)).unwrap();
+    let compressed = encoder.finish().unwrap();
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("compressed.obj.gz");
+    fs::write(&path, &compressed).unwrap();
+
+    let mut vm = quiet_vm();
+    vm.read_image(path.to_str().unwrap()).unwrap();
+    vm.run().unwrap();
+    assert_eq!(vm.register(Register::R0), 1);
+
+    let truncated = compressed.get(..compressed.len() / 2).unwrap();
+    fs::write(&path, truncated).unwrap();
+    assert!(matches!(
+        quiet_vm().read_image(path.to_str().unwrap()),
+        Err(VMError::ReadFile(_))
+    ));
+}
+
+/// Serves `body` once over HTTP on a local port, returning its URL
+#[cfg(feature = "http")]
+fn serve_once(body: Vec<u8>) -> String {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 2 {
+            line.clear();
+        }
+        let mut stream = reader.into_inner();
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            body.len()
+        )
+        .unwrap();
+        stream.write_all(&body).unwrap();
+    });
+    format!("http://{address}/program.obj")
+}
+
+#[cfg(feature = "http")]
+#[test]
+fn images_load_from_urls() {
+    let url = serve_once(program(0x4000));
+    let mut vm = quiet_vm();
+    vm.read_image(&url).unwrap();
+    assert_eq!(vm.pc(), 0x4000);
+    vm.run().unwrap();
+    assert_eq!(vm.register(Register::R0), 1);
+}
+
+#[cfg(not(feature = "http"))]
+#[test]
+fn urls_need_the_http_feature() {
+    let error = quiet_vm()
+        .read_image("https://example.com/program.obj")
+        .unwrap_err();
+    assert!(error.to_string().contains("`http` feature"), "{error}");
+}
@@ -0,0 +1,84 @@
+//! Programs stuck in loops that make no progress
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    console::SharedConsole,
+    errors::VMError,
+    loop_detector::LoopDetector,
+    vm::{Psr, VM},
+};
+
+/// Counts R1 down from 100, then spins on a branch to itself
+const STUCK: &str = ".ORIG x3000
+         LD R1, COUNT
+LOOP     ADD R1, R1, #-1
+         BRp LOOP
+SPIN     BRnzp SPIN
+COUNT    .FILL #100
+         .END";
+
+/// Counts R1 down from 100 and halts
+const COUNTDOWN: &str = ".ORIG x3000
+         LD R1, COUNT
+LOOP     ADD R1, R1, #-1
+         BRp LOOP
+         HALT
+COUNT    .FILL #100
+         .END";
+
+fn run(source: &str, threshold: u32) -> Result<VM, VMError> {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .loop_detector(LoopDetector::new(threshold))
+        .build()
+        .unwrap();
+    vm.load_asm_str(source).unwrap();
+    vm.run().map(|()| vm)
+}
+
+#[test]
+fn repeated_states_stop_the_program() {
+    let error = run(STUCK, 10).err().unwrap();
+    assert!(matches!(error, VMError::InfiniteLoop(_)));
+    assert_eq!(
+        error.to_string(),
+        "Infinite loop: program appears stuck at x3003"
+    );
+}
+
+#[test]
+fn loops_changing_registers_are_not_stuck() {
+    let vm = run(COUNTDOWN, 1).unwrap();
+    assert!(vm.is_halted());
+}
+
+#[test]
+fn the_threshold_counts_repetitions_in_a_row() {
+    let psr = Psr::default();
+    let mut detector = LoopDetector::new(3);
+    // forward jumps are not loops
+    for _ in 0..10 {
+        detector.observe(0x3000, 0x3005, [0; 8], psr).unwrap();
+    }
+    detector.observe(0x3001, 0x3000, [0; 8], psr).unwrap();
+    detector.observe(0x3001, 0x3000, [0; 8], psr).unwrap();
+    detector.observe(0x3001, 0x3000, [0; 8], psr).unwrap();
+    // a side effect starts over
+    detector.reset();
+    detector.observe(0x3001, 0x3000, [0; 8], psr).unwrap();
+    detector.observe(0x3001, 0x3000, [0; 8], psr).unwrap();
+    detector.observe(0x3001, 0x3000, [0; 8], psr).unwrap();
+    assert!(detector.observe(0x3001, 0x3000, [0; 8], psr).is_err());
+}
+
+#[test]
+fn a_zero_threshold_stops_at_the_first_repetition() {
+    for threshold in [0, 1] {
+        let mut detector = LoopDetector::new(threshold);
+        let psr = Psr::default();
+        detector.observe(0x3001, 0x3000, [0; 8], psr).unwrap();
+        assert!(detector.observe(0x3001, 0x3000, [0; 8], psr).is_err());
+    }
+    assert!(run(STUCK, 0).is_err());
+    assert!(run(COUNTDOWN, 0).is_ok());
+}
@@ -0,0 +1,83 @@
+//! Counters kept by the VM while programs run
+#![allow(clippy::unwrap_used)]
+
+use std::{cell::RefCell, path::Path, rc::Rc, time::Duration};
+
+use lc3_vm::{
+    console::SharedConsole,
+    memory::{KBDR, KBSR},
+    vm::{Metrics, VM},
+};
+
+fn memory_program() -> VM {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/memory.obj");
+    vm.read_image(path.to_str().unwrap()).unwrap();
+    vm
+}
+
+#[test]
+fn running_and_stepping_count_the_same() {
+    let mut run = memory_program();
+    run.run().unwrap();
+    let mut stepped = memory_program();
+    stepped.resume().unwrap();
+
+    let metrics = run.metrics();
+    assert!(metrics.instructions > 0);
+    assert!(metrics.memory_writes > 0);
+    assert!(metrics.traps > 0);
+    assert_eq!(metrics.interrupts, 0);
+    assert!(metrics.host_time > Duration::ZERO);
+    // stepping is not timed
+    assert_eq!(
+        stepped.metrics(),
+        Metrics {
+            host_time: Duration::ZERO,
+            ..metrics
+        }
+    );
+
+    run.reset_metrics();
+    assert_eq!(run.metrics(), Metrics::default());
+}
+
+#[test]
+fn callbacks_see_the_metrics_periodically() {
+    let mut vm = memory_program();
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let record = Rc::clone(&seen);
+    vm.set_metrics_callback(10, move |metrics| {
+        record.borrow_mut().push(metrics.instructions);
+    });
+    vm.run().unwrap();
+    let total = vm.metrics().instructions;
+    let expected: Vec<u64> = (1..=total / 10).map(|n| n * 10).collect();
+    assert_eq!(*seen.borrow(), expected);
+
+    vm.set_metrics_callback(0, |_| {});
+    vm.reload().unwrap();
+    vm.run().unwrap();
+    assert_eq!(seen.borrow().len(), expected.len());
+}
+
+#[test]
+fn polled_device_registers_are_not_program_writes() {
+    let console = SharedConsole::new();
+    console.push_input(*b"k");
+    let mut vm = VM::builder().console(Box::new(console)).build().unwrap();
+    vm.load_asm_str(".ORIG x3000\nLDI R0, KB\nHALT\nKB .FILL xFE00\n.END")
+        .unwrap();
+    vm.set_undo(true);
+    let (_, diff) = vm.step_diff().unwrap();
+    assert_eq!(vm.peek(KBSR), 1 << 15);
+    assert_eq!(vm.peek(KBDR), u16::from(b'k'));
+    assert!(diff.memory.is_empty());
+    let metrics = vm.metrics();
+    assert_eq!((metrics.memory_reads, metrics.memory_writes), (2, 0));
+    vm.undo().unwrap();
+    assert_eq!(vm.peek(KBSR), 1 << 15);
+}
@@ -0,0 +1,94 @@
+//! Microsequenced execution with `VM::micro_step`
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    console::SharedConsole,
+    vm::{VmState, FETCH_STATE, VM},
+};
+
+const PROGRAM: &str = "\
+        .ORIG x3000
+        LD R1, COUNT
+        LEA R2, DATA
+LOOP    LDR R3, R2, #0
+        ADD R3, R3, R3
+        STR R3, R2, #0
+        ADD R2, R2, #1
+        ADD R1, R1, #-1
+        BRp LOOP
+        LDI R4, POINTER
+        STI R4, POINTER
+        JSR DONE
+        HALT
+DONE    NOT R5, R4
+        AND R5, R5, #7
+        RET
+COUNT   .FILL #3
+POINTER .FILL DATA
+DATA    .FILL #1
+        .FILL #2
+        .FILL #3
+        .END";
+
+fn vm() -> VM {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    vm.load_asm_str(PROGRAM).unwrap();
+    vm
+}
+
+fn state(vm: &VM) -> VmState {
+    vm.snapshot(&[(0x3000, 0x20)])
+}
+
+#[test]
+fn micro_steps_end_where_instructions_do() {
+    let mut whole = vm();
+    let mut micro = vm();
+    while !whole.is_halted() {
+        whole.step().unwrap();
+        loop {
+            micro.micro_step().unwrap();
+            if micro.microstate().at_instruction_boundary() {
+                break;
+            }
+        }
+        assert_eq!(state(&micro), state(&whole));
+    }
+    assert_eq!(micro.metrics().instructions, whole.metrics().instructions);
+}
+
+#[test]
+fn loads_go_through_the_mar_and_mdr() {
+    let mut vm = vm();
+    let states: Vec<(u8, String)> = (0..6)
+        .map(|_| {
+            let step = vm.micro_step().unwrap();
+            (step.state, step.transfer)
+        })
+        .collect();
+    assert_eq!(
+        states,
+        [
+            (18, String::from("MAR ← PC, PC ← PC + 1")),
+            (33, String::from("MDR ← M[MAR]")),
+            (35, String::from("IR ← MDR")),
+            (
+                32,
+                String::from("BEN ← IR[11]·N + IR[10]·Z + IR[9]·P, decode IR[15:12]")
+            ),
+            (2, String::from("MAR ← PC + off9")),
+            (25, String::from("MDR ← M[MAR]")),
+        ]
+    );
+    let micro = vm.microstate();
+    assert_eq!(micro.ir, 0x220E);
+    assert_eq!(micro.mar, 0x300F);
+    assert_eq!(micro.mdr, 3);
+    assert_eq!(micro.state, 27);
+    vm.micro_step().unwrap();
+    assert_eq!(vm.microstate().state, FETCH_STATE);
+    assert_eq!(vm.registers()[1], 3);
+}
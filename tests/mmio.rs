@@ -0,0 +1,69 @@
+//! The device layout and the conflicts it refuses
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    console::SharedConsole,
+    errors::VMError,
+    mmio::DeviceRegion,
+    vm::{DMASR, VM},
+};
+
+fn machine() -> VM {
+    VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .display()
+        .dma()
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn the_map_lists_the_attached_devices() {
+    let vm = VM::new();
+    let names: Vec<&str> = vm
+        .memory()
+        .mmio_map()
+        .regions()
+        .iter()
+        .map(|region| region.name.as_str())
+        .collect();
+    assert_eq!(names, ["keyboard", "psr"]);
+
+    let vm = machine();
+    assert_eq!(
+        vm.memory().mmio_map().to_string(),
+        "xF000-xF77F  display\n\
+         xFE00-xFE02  keyboard\n\
+         xFE10        display\n\
+         xFE12-xFE1A  dma\n\
+         xFFFC        psr\n"
+    );
+    let map = vm.memory().mmio_map();
+    assert_eq!(map.region_at(DMASR).unwrap().name, "dma");
+    assert!(map.region_at(0xFE04).is_none());
+}
+
+#[test]
+fn overlapping_devices_are_refused() {
+    let mut vm = VM::new();
+    vm.map_device(DeviceRegion::new("timer", 0xFE18, 0xFE19).unwrap())
+        .unwrap();
+    assert!(matches!(vm.enable_dma(), Err(VMError::DeviceConflict(_))));
+    assert!(!vm.memory().mmio_map().contains_device("dma"));
+    let error = vm
+        .map_device(DeviceRegion::new("rng", 0xFE01, 0xFE01).unwrap())
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Device conflict: `rng` at xFE01 overlaps `keyboard` at xFE00-xFE02"
+    );
+    assert!(DeviceRegion::new("backwards", 0xFE20, 0xFE10).is_err());
+}
+
+#[test]
+fn enabling_a_device_again_keeps_one_mapping() {
+    let mut vm = machine();
+    vm.enable_dma().unwrap();
+    vm.enable_display().unwrap();
+    assert_eq!(vm.memory().mmio_map().regions().len(), 5);
+}
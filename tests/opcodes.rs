@@ -0,0 +1,145 @@
+//! The core instructions, one at a time
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    console::NullConsole,
+    register::Register,
+    vm::{TrapMessages, VM},
+};
+
+/// A machine that ran `body` placed at x3000 and followed by HALT
+fn run(body: &str) -> VM {
+    let mut vm = VM::builder()
+        .console(Box::new(NullConsole))
+        .trap_messages(TrapMessages::SILENT)
+        .build()
+        .unwrap();
+    vm.load_asm_str(&format!(".ORIG x3000\n{body}\nHALT\n.END"))
+        .unwrap();
+    vm.run().unwrap();
+    assert!(vm.is_halted());
+    vm
+}
+
+#[test]
+fn add_takes_registers_and_immediates() {
+    let vm = run("ADD R1, R1, #7
+                  ADD R2, R1, #-3
+                  ADD R3, R1, R2");
+    assert_eq!(vm.register(Register::R1), 7);
+    assert_eq!(vm.register(Register::R2), 4);
+    assert_eq!(vm.register(Register::R3), 11);
+    assert!(vm.psr().positive());
+}
+
+#[test]
+fn add_wraps_around() {
+    let vm = run("LD R1, MAX
+                  ADD R1, R1, #1
+                  BRnzp DONE
+         MAX     .FILL x7FFF
+         DONE    ADD R2, R2, #0");
+    assert_eq!(vm.register(Register::R1), 0x8000);
+}
+
+#[test]
+fn and_masks_and_clears() {
+    let vm = run("LD R1, BITS
+                  AND R2, R1, #15
+                  AND R3, R1, R1
+                  AND R4, R1, #0
+                  BRnzp DONE
+         BITS    .FILL xF0F5
+         DONE    AND R4, R4, R4");
+    assert_eq!(vm.register(Register::R2), 0x0005);
+    assert_eq!(vm.register(Register::R3), 0xF0F5);
+    assert_eq!(vm.register(Register::R4), 0);
+    assert!(vm.psr().zero());
+}
+
+#[test]
+fn not_complements() {
+    let vm = run("NOT R1, R1");
+    assert_eq!(vm.register(Register::R1), 0xFFFF);
+    assert!(vm.psr().negative());
+}
+
+#[test]
+fn br_follows_the_condition_codes() {
+    let vm = run("ADD R1, R1, #-1
+                  BRzp SKIPPED
+                  ADD R2, R2, #1
+                  BRp TAKEN
+         SKIPPED ADD R3, R3, #1
+         TAKEN   ADD R4, R4, #1");
+    assert_eq!(vm.register(Register::R2), 1);
+    assert_eq!(vm.register(Register::R3), 0);
+    assert_eq!(vm.register(Register::R4), 1);
+}
+
+#[test]
+fn jsr_and_ret_save_and_restore_the_pc() {
+    let vm = run("JSR SUB
+                  ADD R2, R7, #0
+                  BRnzp DONE
+         SUB     ADD R1, R1, #5
+                  RET
+         DONE    ADD R1, R1, #1");
+    assert_eq!(vm.register(Register::R1), 6);
+    assert_eq!(vm.register(Register::R2), 0x3001);
+}
+
+#[test]
+fn jsrr_and_jmp_jump_through_registers() {
+    let vm = run("LEA R5, SUB
+                  JSRR R5
+                  LEA R5, DONE
+                  JMP R5
+                  ADD R1, R1, #10
+         SUB     ADD R1, R1, #1
+                  RET
+         DONE    ADD R2, R7, #0");
+    assert_eq!(vm.register(Register::R1), 1);
+    assert_eq!(vm.register(Register::R2), 0x3002);
+}
+
+#[test]
+fn loads_read_memory_directly_and_indirectly() {
+    let vm = run("LD R1, VALUE
+                  LDI R2, POINTER
+                  LEA R3, VALUE
+                  LDR R4, R3, #1
+                  BRnzp DONE
+         VALUE   .FILL x1234
+         POINTER .FILL x3007
+                  .FILL xBEEF
+         DONE    ADD R0, R0, #0");
+    assert_eq!(vm.register(Register::R1), 0x1234);
+    assert_eq!(vm.register(Register::R2), 0xBEEF);
+    assert_eq!(vm.register(Register::R3), 0x3005);
+    assert_eq!(vm.register(Register::R4), 0x3007);
+}
+
+#[test]
+fn loads_set_the_condition_codes() {
+    let vm = run("LD R1, NEGATIVE
+                  HALT
+         NEGATIVE .FILL x8000");
+    assert!(vm.psr().negative());
+}
+
+#[test]
+fn stores_write_memory_directly_and_indirectly() {
+    let vm = run("ADD R1, R1, #9
+                  ST R1, FIRST
+                  STI R1, POINTER
+                  LD R2, POINTER
+                  STR R1, R2, #1
+                  BRnzp DONE
+         FIRST   .FILL 0
+         POINTER .FILL x4000
+         DONE    ADD R0, R0, #0");
+    assert_eq!(vm.peek(0x3006), 9);
+    assert_eq!(vm.peek(0x4000), 9);
+    assert_eq!(vm.peek(0x4001), 9);
+}
@@ -0,0 +1,94 @@
+//! RAM kept in a memory file between machines
+#![cfg(feature = "persistent")]
+#![allow(clippy::unwrap_used)]
+
+use std::{fs, path::PathBuf};
+
+use lc3_vm::{
+    console::SharedConsole,
+    errors::VMError,
+    memory::KBSR,
+    vm::{TrapMessages, VM},
+};
+
+/// Counts its runs in x4000
+const COUNTER: &str = ".ORIG x3000
+         LDI R0, COUNT
+         ADD R0, R0, #1
+         STI R0, COUNT
+         HALT
+COUNT    .FILL x4000
+         .END";
+
+/// A memory file of its own for `test`, not created yet
+fn memory_file(test: &str) -> PathBuf {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{test}.mem"));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+fn machine(path: &PathBuf) -> VM {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .trap_messages(TrapMessages::SILENT)
+        .persistent_memory(path)
+        .build()
+        .unwrap();
+    vm.load_asm_str(COUNTER).unwrap();
+    vm
+}
+
+#[test]
+fn the_memory_outlives_the_machine() {
+    let path = memory_file("outlives");
+    for runs in 1..=3 {
+        let mut vm = machine(&path);
+        vm.run().unwrap();
+        assert_eq!(vm.peek(0x4000), runs);
+        vm.sync_memory().unwrap();
+    }
+    assert_eq!(fs::metadata(&path).unwrap().len(), 0x20000);
+}
+
+#[test]
+fn a_new_file_starts_with_the_memory() {
+    let path = memory_file("new");
+    let mut vm = VM::new();
+    vm.poke(0x5000, 0x1234).unwrap();
+    vm.poke(KBSR, 0x8000).unwrap();
+    vm.persist_memory(&path).unwrap();
+    assert_eq!(vm.peek(0x5000), 0x1234);
+    // device registers are not kept
+    assert_eq!(vm.peek(KBSR), 0);
+    drop(vm);
+
+    let mut vm = VM::new();
+    vm.poke(0x5000, 0x5678).unwrap();
+    vm.persist_memory(&path).unwrap();
+    assert_eq!(vm.peek(0x5000), 0x1234);
+}
+
+#[test]
+fn a_file_is_used_by_one_machine_at_a_time() {
+    let path = memory_file("locked");
+    let first = machine(&path);
+    let error = VM::new().persist_memory(&path).unwrap_err();
+    assert!(
+        matches!(&error, VMError::OpenFile(io) if io.message().contains("another machine")),
+        "{error}"
+    );
+    drop(first);
+    VM::new().persist_memory(&path).unwrap();
+}
+
+#[test]
+fn other_files_are_refused() {
+    let path = memory_file("other");
+    fs::write(&path, b"not memory").unwrap();
+    let error = VM::new().persist_memory(&path).unwrap_err();
+    assert!(
+        error.to_string().contains("is not a memory file"),
+        "{error}"
+    );
+    assert_eq!(fs::read(&path).unwrap(), b"not memory");
+}
@@ -0,0 +1,129 @@
+//! Conformance profiles resolving what the specification leaves open
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    console::SharedConsole,
+    errors::VMError,
+    instructions::AddressArithmetic,
+    register::Register,
+    vm::{Conformance, Profile, VM},
+};
+
+fn run(source: &str, conformance: impl Into<Conformance>) -> (VM, Result<(), VMError>) {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .conformance(conformance)
+        .build()
+        .unwrap();
+    vm.load_asm_str(source).unwrap();
+    let result = vm.run();
+    (vm, result)
+}
+
+#[test]
+fn only_the_strict_profile_keeps_lea_from_setting_the_flags() {
+    let source = ".ORIG x3000
+         AND R0, R0, #0
+         LEA R1, DATA
+         HALT
+DATA     .FILL 0
+         .END";
+    let (vm, result) = run(source, Conformance::default());
+    result.unwrap();
+    assert!(vm.psr().positive());
+    let (vm, result) = run(source, Profile::StrictSpec);
+    result.unwrap();
+    assert!(vm.psr().zero());
+    assert_eq!(vm.register(Register::R1), 0x3003);
+}
+
+#[test]
+fn profiles_wrap_addresses_past_the_end_of_memory() {
+    let source = ".ORIG x3000
+         LD R1, TOP
+         LDR R0, R1, #2
+         HALT
+TOP      .FILL xFFFF
+         .END";
+    let (_, result) = run(source, Conformance::default());
+    assert!(matches!(result, Err(VMError::AddressOverflow(_))));
+    let (_, result) = run(source, Profile::StrictSpec);
+    result.unwrap();
+}
+
+const RTI: &str = ".ORIG x3000
+         LEA R6, STACK
+         RTI
+         HALT
+DONE     ADD R2, R2, #1
+         HALT
+STACK    .FILL DONE
+         .FILL x8004
+         .END";
+
+#[test]
+fn rti_pops_the_pc_and_psr_only_where_programs_run_in_supervisor_mode() {
+    for conformance in [Conformance::default(), Profile::StrictSpec.into()] {
+        let (_, result) = run(RTI, conformance);
+        assert!(matches!(result, Err(VMError::InvalidOpcode(_))));
+    }
+    let (vm, result) = run(RTI, Profile::Lc3sim);
+    result.unwrap();
+    assert_eq!(vm.register(Register::R2), 1);
+    assert_eq!(vm.register(Register::R6), 0x3007);
+    assert!(vm.psr().user_mode());
+}
+
+#[test]
+fn the_permissive_profile_skips_the_reserved_opcode() {
+    let source = ".ORIG x3000
+         .FILL xD000
+         ADD R0, R0, #1
+         HALT
+         .END";
+    let (_, result) = run(source, Profile::Lc3sim);
+    assert!(matches!(result, Err(VMError::InvalidOpcode(_))));
+    let (vm, result) = run(source, Profile::Permissive);
+    result.unwrap();
+    assert_eq!(vm.register(Register::R0), 1);
+}
+
+#[test]
+fn profiles_parse_from_their_names() {
+    for profile in [Profile::StrictSpec, Profile::Lc3sim, Profile::Permissive] {
+        assert_eq!(profile.to_string().parse::<Profile>().unwrap(), profile);
+    }
+    assert_eq!(
+        "lc3sim-compatible".parse::<Profile>().unwrap(),
+        Profile::Lc3sim
+    );
+    assert!(matches!(
+        "lenient".parse::<Profile>(),
+        Err(VMError::InvalidArgument(_))
+    ));
+}
+
+/// Jumps to xFFFF after writing a HALT at x0000 for the PC to wrap to
+const PAST_THE_END: &str = ".ORIG x3000
+         LD R0, HALT_WORD
+         STI R0, ZERO
+         LD R1, TOP
+         JMP R1
+HALT_WORD .FILL xF025
+ZERO     .FILL x0000
+TOP      .FILL xFFFF
+         .END";
+
+#[test]
+fn the_pc_wraps_past_xffff_only_with_wrapping_addresses() {
+    let checked = Conformance::default();
+    let (_, result) = run(PAST_THE_END, checked);
+    assert!(matches!(result, Err(VMError::AddressOverflow(_))));
+    let wrapping = Conformance {
+        addresses: AddressArithmetic::Wrapping,
+        ..checked
+    };
+    let (vm, result) = run(PAST_THE_END, wrapping);
+    result.unwrap();
+    assert!(vm.is_halted());
+}
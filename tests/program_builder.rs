@@ -0,0 +1,95 @@
+//! Programs written with `test_utils::Program` instead of encoded words
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    assert_state_eq,
+    console::SharedConsole,
+    errors::VMError,
+    test_utils::{Program, R0, R1, R2, R7},
+    vm::{VmState, VM},
+};
+
+fn vm_with(console: &SharedConsole) -> VM {
+    VM::builder()
+        .console(Box::new(console.clone()))
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn loop_sums_with_a_branch() {
+    // R0 = 5 + 4 + 3 + 2 + 1
+    let console = SharedConsole::new();
+    let mut vm = vm_with(&console);
+    Program::at(0x3000)
+        .and(R0, R0, 0)
+        .and(R1, R1, 0)
+        .add(R1, R1, 5)
+        .add(R0, R0, R1)
+        .add(R1, R1, -1)
+        .brp(-3)
+        .trap_halt()
+        .load_into(&mut vm)
+        .unwrap();
+    vm.run().unwrap();
+    assert_state_eq!(
+        vm.state(),
+        VmState {
+            pc: 0x3007,
+            registers: [15, 0, 0, 0, 0, 0, 0, 0x3007],
+            halted: true,
+            ..VmState::default()
+        }
+    );
+}
+
+#[test]
+fn data_directives_and_subroutines() {
+    let console = SharedConsole::new();
+    let mut vm = vm_with(&console);
+    Program::at(0x4000)
+        .lea(R0, 3)
+        .trap_puts()
+        .jsr(8)
+        .trap_halt()
+        .stringz("Hi!\n")
+        .blkw(2)
+        .ld(R2, 1)
+        .ret()
+        .fill(0x1234)
+        .load_into(&mut vm)
+        .unwrap();
+    assert_eq!(vm.pc(), 0x4000);
+    vm.run().unwrap();
+    assert_eq!(console.take_output(), "Hi!\nHALT\n");
+    assert_eq!(vm.register(R2), 0x1234);
+    assert_eq!(vm.register(R7), 0x4004);
+}
+
+#[test]
+fn here_tracks_the_next_address() {
+    let program = Program::at(0x3000).add(R0, R0, 1).stringz("ab");
+    assert_eq!(program.here(), 0x3004);
+    assert_eq!(
+        program.image().unwrap(),
+        [0x30, 0x00, 0x10, 0x21, 0x00, 0x61, 0x00, 0x62, 0x00, 0x00]
+    );
+}
+
+#[test]
+fn operands_that_do_not_fit_are_rejected() {
+    for program in [
+        Program::at(0x3000).add(R0, R0, 16),
+        Program::at(0x3000).ldr(R0, R1, -33),
+        Program::at(0x3000).brnzp(256),
+        Program::at(0x3000).jsr(-1025),
+        Program::at(0x3000).trap(0x100),
+    ] {
+        assert!(matches!(program.words(), Err(VMError::InvalidArgument(_))));
+    }
+    let mut vm = VM::new();
+    assert!(Program::at(0x3000)
+        .add(R0, R0, -17)
+        .load_into(&mut vm)
+        .is_err());
+}
@@ -0,0 +1,114 @@
+//! Plays whole programs from `tests/programs` with scripted keys through
+//! `SharedConsole` and checks both what they print and the machine state
+//! they leave behind. The `.asm` next to each image is its source; the
+//! addresses below come from assembling it.
+#![allow(clippy::unwrap_used)]
+
+use std::path::Path;
+
+use lc3_vm::{console::SharedConsole, errors::VMError, vm::VM};
+
+/// Score of `2048.asm`
+const SCORE_2048: u16 = 0x3046;
+/// First of the 16 cells of `2048.asm`, row by row
+const BOARD_2048: u16 = 0x30D6;
+/// Map index of the player in `rogue.asm`
+const PLAYER_ROGUE: u16 = 0x3046;
+/// Gold picked up in `rogue.asm`
+const PURSE_ROGUE: u16 = 0x3047;
+/// First of the 8x5 map cells of `rogue.asm`
+const MAP_ROGUE: u16 = 0x30AB;
+
+/// Runs `image` with `keys` as keyboard input, returning the VM, what the
+/// run returned and the output
+fn play(image: &str, keys: &str) -> (VM, Result<(), VMError>, String) {
+    let console = SharedConsole::new();
+    console.push_input(keys.bytes());
+    let mut vm = VM::builder()
+        .console(Box::new(console.clone()))
+        .instruction_limit(10_000_000)
+        .build()
+        .unwrap();
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join(image);
+    vm.read_image(path.to_str().unwrap()).unwrap();
+    let result = vm.run();
+    (vm, result, console.take_output())
+}
+
+fn board(vm: &VM) -> Vec<u16> {
+    vm.memory().read_words(BOARD_2048, 16).collect()
+}
+
+fn map(vm: &VM) -> String {
+    vm.memory()
+        .read_words(MAP_ROGUE, 40)
+        .map(|word| char::from(u8::try_from(word).unwrap()))
+        .collect()
+}
+
+#[test]
+fn hello_prints_and_halts() {
+    let (vm, result, output) = play("golden/hello.obj", "");
+    assert_eq!(result, Ok(()));
+    assert_eq!(output, "Hello, World!\nHALT\n");
+    assert!(vm.is_halted());
+    assert_eq!(vm.pc(), 0x3003);
+}
+
+#[test]
+fn game_2048_slides_and_merges() {
+    let (vm, result, output) = play("programs/2048.obj", "adwsq");
+    assert_eq!(result, Ok(()));
+    assert!(output.starts_with("2 . . .\n. . 2 .\n. . . .\n. . . .\n\n"));
+    assert!(output.ends_with("2 . . .\n. . . .\n. . . 4\n2 2 . 2\n\nScore: 4\nHALT\n"));
+    assert_eq!(board(&vm), [2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 4, 2, 2, 0, 2]);
+    assert_eq!(vm.memory().read(SCORE_2048), 4);
+}
+
+#[test]
+fn game_2048_ignores_unknown_keys() {
+    let (_, _, with_noise) = play("programs/2048.obj", "a?x dq");
+    let (_, _, without) = play("programs/2048.obj", "adq");
+    assert_eq!(with_noise, without);
+}
+
+#[test]
+fn game_2048_ends_when_the_board_is_full() {
+    let keys = "ds".repeat(200);
+    let (vm, result, output) = play("programs/2048.obj", &keys);
+    assert_eq!(result, Ok(()));
+    assert!(output.ends_with("Game over\nScore: 2004\nHALT\n"));
+    assert!(board(&vm).iter().all(|&tile| tile != 0));
+    assert_eq!(vm.memory().read(SCORE_2048), 2004);
+}
+
+#[test]
+fn rogue_collects_gold_and_escapes() {
+    let (vm, result, output) = play("programs/rogue.obj", "wddsdsdddsd");
+    assert_eq!(result, Ok(()));
+    assert!(output.ends_with(
+        "########\n#......#\n#..##..#\n#.$..#@#\n########\n\nYou escape with 2 gold.\nHALT\n"
+    ));
+    assert_eq!(output.matches("You bump into a wall.").count(), 3);
+    assert_eq!(vm.memory().read(PURSE_ROGUE), 2);
+    assert_eq!(vm.memory().read(PLAYER_ROGUE), 30);
+    assert_eq!(map(&vm), "#########......##..##..##.$..#>#########");
+}
+
+#[test]
+fn rogue_walls_block_the_player() {
+    let (vm, result, output) = play("programs/rogue.obj", "dddq");
+    assert_eq!(result, Ok(()));
+    assert_eq!(output.matches("You bump into a wall.").count(), 3);
+    assert!(output.ends_with("You leave with 0 gold.\nHALT\n"));
+    assert_eq!(vm.memory().read(PLAYER_ROGUE), 18);
+}
+
+#[test]
+fn running_out_of_keys_stops_the_game() {
+    let (vm, result, _) = play("programs/rogue.obj", "ww");
+    assert!(matches!(result, Err(VMError::StandardIO(_))));
+    assert!(!vm.is_halted());
+}
@@ -0,0 +1,222 @@
+; A small 2048 on a 4x4 board. w, a, s and d slide the tiles up, left, down
+; and right, merging equal neighbours once per move, then a 2 appears in the
+; first empty cell. q quits. The game is over once a move leaves no empty
+; cell. The board is printed after every move and the score at the end.
+        .ORIG x3000
+        JSR SHOW
+MAIN    GETC
+        LEA R1, KEYS
+        LEA R2, MOVES
+FIND    LDR R3, R1, #0
+        BRz MAIN            ; unknown key
+        NOT R3, R3
+        ADD R3, R3, #1
+        ADD R3, R3, R0
+        BRz FOUND
+        ADD R1, R1, #1
+        ADD R2, R2, #3
+        BR FIND
+FOUND   LDR R3, R2, #0
+        BRn QUIT            ; q has a negative first line
+        JSR MOVE
+        JSR SPAWN
+        ADD R0, R0, #0
+        BRz OVER
+        JSR SHOW
+        BR MAIN
+OVER    JSR SHOW
+        LEA R0, LOST
+        PUTS
+QUIT    LEA R0, SCOREIS
+        PUTS
+        LD R0, SCORE
+        JSR PRINTN
+        AND R0, R0, #0
+        ADD R0, R0, #10
+        OUT
+        HALT
+
+KEYS    .FILL x61           ; a
+        .FILL x64           ; d
+        .FILL x77           ; w
+        .FILL x73           ; s
+        .FILL x71           ; q
+        .FILL #0
+; Per key: offset of the first cell of the first line, offset between the
+; first cells of consecutive lines, and offset between cells of a line
+MOVES   .FILL #0
+        .FILL #4
+        .FILL #1
+        .FILL #3
+        .FILL #4
+        .FILL #-1
+        .FILL #0
+        .FILL #1
+        .FILL #4
+        .FILL #12
+        .FILL #1
+        .FILL #-4
+        .FILL #-1
+LOST    .STRINGZ "Game over\n"
+SCOREIS .STRINGZ "Score: "
+SCORE   .FILL #0
+BOARDP  .FILL BOARD
+
+; Slides the four lines described by the entry at R2
+MOVE    ST R7, SAVEM
+        LDR R1, R2, #0
+        LD R3, BOARDP
+        ADD R1, R1, R3
+        LDR R3, R2, #1
+        ST R3, STEP
+        LDR R3, R2, #2
+        ST R3, STRIDE
+        AND R3, R3, #0
+        ADD R3, R3, #4
+        ST R3, LINES
+NEXTLN  ST R1, FIRST
+        LD R2, STRIDE
+        JSR SLIDE
+        LD R1, FIRST
+        LD R3, STEP
+        ADD R1, R1, R3
+        LD R3, LINES
+        ADD R3, R3, #-1
+        ST R3, LINES
+        BRp NEXTLN
+        LD R7, SAVEM
+        RET
+SAVEM   .FILL #0
+STEP    .FILL #0
+STRIDE  .FILL #0
+LINES   .FILL #0
+FIRST   .FILL #0
+
+; Slides the line of four cells starting at R1, R2 apart, towards R1.
+; R3 is the next cell to fill and R5 the tile before it that may still merge.
+SLIDE   ADD R3, R1, #0
+        AND R5, R5, #0
+        AND R4, R4, #0
+        ADD R4, R4, #4
+CELL    LDR R0, R1, #0
+        BRz SKIPC
+        AND R6, R6, #0
+        STR R6, R1, #0
+        NOT R6, R5
+        ADD R6, R6, #1
+        ADD R6, R6, R0
+        BRnp PLACE
+        NOT R6, R2
+        ADD R6, R6, #1
+        ADD R6, R3, R6
+        ADD R0, R0, R0
+        STR R0, R6, #0
+        LD R6, SCORE
+        ADD R6, R6, R0
+        ST R6, SCORE
+        AND R5, R5, #0
+        BR SKIPC
+PLACE   STR R0, R3, #0
+        ADD R5, R0, #0
+        ADD R3, R3, R2
+SKIPC   ADD R1, R1, R2
+        ADD R4, R4, #-1
+        BRp CELL
+        RET
+
+; Puts a 2 in the first empty cell, returning 0 in R0 if there was none
+SPAWN   LD R1, BOARDP
+        AND R2, R2, #0
+        ADD R2, R2, #15
+        ADD R2, R2, #1
+FREE    LDR R0, R1, #0
+        BRz EMPTY
+        ADD R1, R1, #1
+        ADD R2, R2, #-1
+        BRp FREE
+        AND R0, R0, #0
+        RET
+EMPTY   AND R0, R0, #0
+        ADD R0, R0, #2
+        STR R0, R1, #0
+        RET
+
+; Prints the board, one row per line and '.' for empty cells
+SHOW    ST R7, SAVES
+        LD R1, BOARDP
+        AND R2, R2, #0
+        ADD R2, R2, #15
+        ADD R2, R2, #1
+SHOWC   LDR R0, R1, #0
+        BRnp NUMBER
+        LD R0, DOT
+        OUT
+        BR SEP
+NUMBER  ST R1, SAVE1
+        ST R2, SAVE2
+        JSR PRINTN
+        LD R1, SAVE1
+        LD R2, SAVE2
+SEP     ADD R1, R1, #1
+        ADD R2, R2, #-1
+        BRz ENDROW
+        AND R3, R2, #3
+        BRz ENDROW
+        LD R0, SPACE
+        OUT
+        BR SHOWC
+ENDROW  AND R0, R0, #0
+        ADD R0, R0, #10
+        OUT
+        ADD R2, R2, #0
+        BRp SHOWC
+        OUT
+        LD R7, SAVES
+        RET
+SAVES   .FILL #0
+SAVE1   .FILL #0
+SAVE2   .FILL #0
+DOT     .FILL x2E
+SPACE   .FILL x20
+
+; Prints R0 (1-9999) in decimal
+PRINTN  ST R7, SAVEP
+        LEA R3, POWERS
+        AND R5, R5, #0
+PNEXT   LDR R4, R3, #0
+        BRz PLAST
+        AND R6, R6, #0
+PDIGIT  ADD R0, R0, R4
+        BRn PDONE
+        ADD R6, R6, #1
+        BR PDIGIT
+PDONE   NOT R4, R4
+        ADD R4, R4, #1
+        ADD R0, R0, R4
+        ADD R5, R5, R6
+        BRz PSKIP
+        ST R0, SAVE0
+        LD R0, ASCII
+        ADD R0, R0, R6
+        OUT
+        LD R0, SAVE0
+PSKIP   ADD R3, R3, #1
+        BR PNEXT
+PLAST   LD R4, ASCII
+        ADD R0, R0, R4
+        OUT
+        LD R7, SAVEP
+        RET
+POWERS  .FILL #-1000
+        .FILL #-100
+        .FILL #-10
+        .FILL #0
+ASCII   .FILL x30
+SAVE0   .FILL #0
+SAVEP   .FILL #0
+
+BOARD   .FILL #2
+        .BLKW 5
+        .FILL #2
+        .BLKW 9
+        .END
@@ -0,0 +1,122 @@
+; A tiny dungeon crawl. w, a, s and d move the @ through the map, walls (#)
+; block it, gold ($) is picked up and the stairs (>) end the game. q quits.
+; The map is printed after every key.
+        .ORIG x3000
+        JSR SHOW
+MAIN    GETC
+        LEA R1, KEYS
+        LEA R2, STEPS
+FIND    LDR R3, R1, #0
+        BRz MAIN            ; unknown key
+        NOT R3, R3
+        ADD R3, R3, #1
+        ADD R3, R3, R0
+        BRz FOUND
+        ADD R1, R1, #1
+        ADD R2, R2, #1
+        BR FIND
+FOUND   LDR R2, R2, #0
+        BRz QUIT            ; q does not move
+        LD R1, PLAYER
+        ADD R1, R1, R2
+        LD R3, MAPP
+        ADD R3, R3, R1
+        LDR R4, R3, #0
+        LD R5, WALL
+        ADD R5, R5, R4
+        BRz BLOCKED
+        ST R1, PLAYER
+        LD R5, GOLD
+        ADD R5, R5, R4
+        BRnp STAIRS
+        LD R5, FLOOR
+        STR R5, R3, #0
+        LD R5, PURSE
+        ADD R5, R5, #1
+        ST R5, PURSE
+        BR MOVED
+STAIRS  LD R5, EXIT
+        ADD R5, R5, R4
+        BRz ESCAPE
+MOVED   JSR SHOW
+        BR MAIN
+BLOCKED LEA R0, BUMP
+        PUTS
+        BR MAIN
+ESCAPE  JSR SHOW
+        LEA R0, WON
+        PUTS
+        BR DONE
+QUIT    LEA R0, LEFT
+        PUTS
+DONE    LD R0, PURSE
+        LD R1, ASCII
+        ADD R0, R0, R1
+        OUT
+        LEA R0, GOLDIS
+        PUTS
+        HALT
+
+KEYS    .FILL x77           ; w
+        .FILL x61           ; a
+        .FILL x73           ; s
+        .FILL x64           ; d
+        .FILL x71           ; q
+        .FILL #0
+STEPS   .FILL #-8
+        .FILL #-1
+        .FILL #8
+        .FILL #1
+        .FILL #0
+WALL    .FILL #-35          ; '#'
+GOLD    .FILL #-36          ; '$'
+EXIT    .FILL #-62          ; '>'
+FLOOR   .FILL x2E           ; '.'
+ASCII   .FILL x30
+PLAYER  .FILL #18
+PURSE   .FILL #0
+MAPP    .FILL MAP
+BUMP    .STRINGZ "You bump into a wall.\n"
+WON     .STRINGZ "You escape with "
+LEFT    .STRINGZ "You leave with "
+GOLDIS  .STRINGZ " gold.\n"
+
+; Prints the map, eight cells per row, with the @ over the player's cell and
+; an empty line after it
+SHOW    LD R1, MAPP
+        AND R2, R2, #0      ; cell index
+        AND R3, R3, #0
+        ADD R3, R3, #8      ; cells left in the row
+        LD R4, PLAYER
+        NOT R4, R4
+        ADD R4, R4, #1
+SHOWC   LDR R0, R1, #0
+        BRz SHOWN
+        ADD R5, R2, R4
+        BRnp CELL
+        LD R0, HERO
+CELL    ST R7, SAVE7
+        OUT
+        LD R7, SAVE7
+        ADD R1, R1, #1
+        ADD R2, R2, #1
+        ADD R3, R3, #-1
+        BRp SHOWC
+        AND R0, R0, #0
+        ADD R0, R0, #10
+        ST R7, SAVE7
+        OUT
+        LD R7, SAVE7
+        AND R3, R3, #0
+        ADD R3, R3, #8
+        BR SHOWC
+SHOWN   ADD R0, R0, #10 ; R0 is 0 at the end of the map
+        ST R7, SAVE7
+        OUT
+        LD R7, SAVE7
+        RET
+HERO    .FILL x40
+SAVE7   .FILL #0
+
+MAP     .STRINGZ "#########..$...##..##$.##.$..#>#########"
+        .END
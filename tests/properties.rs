@@ -0,0 +1,163 @@
+//! Property tests for instruction decoding and the ALU instructions, run on
+//! random instructions and register values.
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    console::NullConsole,
+    instructions::{Instruction, JsrTarget, Operand},
+    register::Register,
+    vm::VM,
+};
+use proptest::prelude::*;
+
+fn register() -> impl Strategy<Value = Register> {
+    (0_u16..8).prop_map(Register::from_bits)
+}
+
+/// Signed values that fit in a field of `bits` bits
+fn offset(bits: u32) -> impl Strategy<Value = i16> {
+    let limit = 1_i16 << bits.saturating_sub(1);
+    limit.wrapping_neg()..limit
+}
+
+fn operand() -> impl Strategy<Value = Operand> {
+    prop_oneof![
+        register().prop_map(Operand::Register),
+        offset(5).prop_map(Operand::Immediate),
+    ]
+}
+
+/// Any instruction whose offsets fit in their fields
+fn instruction() -> impl Strategy<Value = Instruction> {
+    prop_oneof![
+        (any::<bool>(), any::<bool>(), any::<bool>(), offset(9))
+            .prop_map(|(n, z, p, pc_offset)| Instruction::Br { n, z, p, pc_offset }),
+        (register(), register(), operand()).prop_map(|(dr, sr1, operand)| Instruction::Add {
+            dr,
+            sr1,
+            operand
+        }),
+        (register(), offset(9)).prop_map(|(dr, pc_offset)| Instruction::Ld { dr, pc_offset }),
+        (register(), offset(9)).prop_map(|(sr, pc_offset)| Instruction::St { sr, pc_offset }),
+        offset(11).prop_map(|offset| Instruction::Jsr {
+            target: JsrTarget::Offset(offset)
+        }),
+        register().prop_map(|base| Instruction::Jsr {
+            target: JsrTarget::Register(base)
+        }),
+        (register(), register(), operand()).prop_map(|(dr, sr1, operand)| Instruction::And {
+            dr,
+            sr1,
+            operand
+        }),
+        (register(), register(), offset(6)).prop_map(|(dr, base, offset)| Instruction::Ldr {
+            dr,
+            base,
+            offset
+        }),
+        (register(), register(), offset(6)).prop_map(|(sr, base, offset)| Instruction::Str {
+            sr,
+            base,
+            offset
+        }),
+        Just(Instruction::Rti),
+        (register(), register()).prop_map(|(dr, sr)| Instruction::Not { dr, sr }),
+        (register(), offset(9)).prop_map(|(dr, pc_offset)| Instruction::Ldi { dr, pc_offset }),
+        (register(), offset(9)).prop_map(|(sr, pc_offset)| Instruction::Sti { sr, pc_offset }),
+        register().prop_map(|base| Instruction::Jmp { base }),
+        Just(Instruction::Res),
+        (register(), offset(9)).prop_map(|(dr, pc_offset)| Instruction::Lea { dr, pc_offset }),
+        (0_u16..=0xFF).prop_map(|trap_vector| Instruction::Trap { trap_vector }),
+    ]
+}
+
+/// ADD, AND or NOT with its destination register
+fn alu_instruction() -> impl Strategy<Value = (Register, Instruction)> {
+    prop_oneof![
+        (register(), register(), operand())
+            .prop_map(|(dr, sr1, operand)| (dr, Instruction::Add { dr, sr1, operand })),
+        (register(), register(), operand())
+            .prop_map(|(dr, sr1, operand)| (dr, Instruction::And { dr, sr1, operand })),
+        (register(), register()).prop_map(|(dr, sr)| (dr, Instruction::Not { dr, sr })),
+    ]
+}
+
+/// Executes `instruction` at x3000 with R0-R7 set to `registers`
+fn execute(instruction: Instruction, registers: [u16; 8]) -> VM {
+    let mut vm = VM::builder()
+        .console(Box::new(NullConsole))
+        .build()
+        .unwrap();
+    for (number, value) in (0..).zip(registers) {
+        vm.set_register(Register::from_bits(number), value);
+    }
+    vm.poke(0x3000, instruction.encode()).unwrap();
+    vm.step().unwrap();
+    vm
+}
+
+/// The N, Z and P bits after a result of `value`
+fn expected_condition(value: u16) -> u16 {
+    match value {
+        0 => 0b010,
+        0x8000.. => 0b100,
+        _ => 0b001,
+    }
+}
+
+proptest! {
+    #[test]
+    fn decode_inverts_encode(instruction in instruction()) {
+        prop_assert_eq!(Instruction::decode(instruction.encode()), instruction);
+    }
+
+    #[test]
+    fn encode_keeps_every_decoded_field(word in any::<u16>()) {
+        let decoded = Instruction::decode(word);
+        prop_assert_eq!(Instruction::decode(decoded.encode()), decoded);
+    }
+
+    #[test]
+    fn immediate_operand_matches_register_operand(
+        registers in any::<[u16; 8]>(),
+        dr in register(),
+        sr1 in register(),
+        immediate in offset(5),
+        and in any::<bool>(),
+    ) {
+        // R7 holds the immediate for the register form, so avoid it as input
+        prop_assume!(sr1 != Register::R7);
+        let build = |operand| if and {
+            Instruction::And { dr, sr1, operand }
+        } else {
+            Instruction::Add { dr, sr1, operand }
+        };
+        let with_immediate = execute(build(Operand::Immediate(immediate)), registers);
+        let mut with_r7 = registers;
+        with_r7[7] = u16::from_ne_bytes(immediate.to_ne_bytes());
+        let with_register = execute(build(Operand::Register(Register::R7)), with_r7);
+        prop_assert_eq!(with_immediate.register(dr), with_register.register(dr));
+        prop_assert_eq!(with_immediate.psr(), with_register.psr());
+    }
+
+    #[test]
+    fn alu_results_set_the_condition_from_the_result(
+        registers in any::<[u16; 8]>(),
+        (dr, instruction) in alu_instruction(),
+    ) {
+        let vm = execute(instruction, registers);
+        prop_assert_eq!(vm.psr().condition_codes(), expected_condition(vm.register(dr)));
+        prop_assert_eq!(vm.pc(), 0x3001);
+    }
+
+    #[test]
+    fn not_twice_restores_the_value(value in any::<u16>(), sr in register(), dr in register()) {
+        let mut registers = [0; 8];
+        if let Some(register) = registers.get_mut(sr.index()) {
+            *register = value;
+        }
+        let once = execute(Instruction::Not { dr, sr }, registers);
+        let twice = execute(Instruction::Not { dr, sr: dr }, once.registers());
+        prop_assert_eq!(twice.register(dr), value);
+    }
+}
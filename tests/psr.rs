@@ -0,0 +1,65 @@
+//! The processor status register and its memory-mapped view
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    console::SharedConsole,
+    register::Register,
+    vm::{Psr, PSR, VM},
+};
+
+#[test]
+fn the_fields_are_kept_in_one_word() {
+    let mut psr = Psr::default();
+    assert_eq!(psr.bits(), 0x0002);
+    assert!(!psr.user_mode());
+    psr.set_user_mode(true);
+    psr.set_priority(5);
+    psr.set_condition(0x8000);
+    assert_eq!(psr.bits(), 0x8504);
+    assert_eq!(psr.priority(), 5);
+    assert!(psr.negative() && !psr.zero() && !psr.positive());
+    assert_eq!(psr.condition_letter(), 'N');
+    psr.set_priority(9);
+    assert_eq!(psr.priority(), 1);
+}
+
+#[test]
+fn undefined_bits_and_condition_codes_are_dropped() {
+    assert_eq!(Psr::from_bits(0xFFFF).bits(), 0x8702);
+    assert_eq!(Psr::from_bits(0x0000).condition_codes(), 0b010);
+    assert_eq!(Psr::from_bits(0x0001).condition_codes(), 0b001);
+}
+
+#[test]
+fn branches_test_the_condition_codes() {
+    let positive = Psr::from_bits(0b001);
+    // BRp, BRzp and BRnz
+    assert!(positive.branches(0x0205));
+    assert!(positive.branches(0x0605));
+    assert!(!positive.branches(0x0C05));
+}
+
+#[test]
+fn programs_read_and_write_the_psr_in_memory() {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    vm.load_asm_str(
+        ".ORIG x3000
+         ADD R0, R0, #-1
+         LDI R1, PSRADDR
+         LD R2, LOWER
+         STI R2, PSRADDR
+         HALT
+PSRADDR  .FILL xFFFC
+LOWER    .FILL x0301
+         .END",
+    )
+    .unwrap();
+    vm.run().unwrap();
+    assert_eq!(vm.register(Register::R1), 0x0004);
+    assert_eq!(vm.psr().priority(), 3);
+    assert!(vm.psr().positive());
+    assert_eq!(PSR, 0xFFFC);
+}
@@ -0,0 +1,303 @@
+//! A deliberately plain LC-3 interpreter used as the reference of the
+//! differential tests. It shares no code with the crate: instructions are
+//! decoded by hand, one at a time, without block caches or compiled code.
+//! Where the specification leaves room it makes the same choices as the VM:
+//! addresses computed past either end of memory and the PC running past
+//! xFFFF fail, RTI and the reserved opcode fail, LEA sets the condition
+//! codes, only ASCII is printed, IN prints the VM's prompt and HALT prints
+//! `HALT`. xFFFC reads and writes the PSR, whose condition codes are kept
+//! as RTI reads them.
+
+use std::collections::VecDeque;
+
+const KBSR: u16 = 0xFE00;
+const KBDR: u16 = 0xFE02;
+const PSR: u16 = 0xFFFC;
+/// The privilege mode and priority bits of the PSR
+const STATUS_MASK: u16 = 0x8700;
+
+pub struct Reference {
+    pub registers: [u16; 8],
+    pub pc: u16,
+    /// The N, Z and P bits
+    pub condition: u16,
+    /// The other bits of the PSR, the privilege mode and the priority
+    pub status: u16,
+    pub memory: Vec<u16>,
+    pub output: String,
+    pub halted: bool,
+    input: VecDeque<u8>,
+}
+
+impl Reference {
+    /// Loads the big endian `image` with `input` as the pending keys
+    pub fn new(image: &[u8], input: &[u8]) -> Result<Self, String> {
+        let mut words = image.chunks_exact(2).map(|pair| match pair {
+            [high, low] => u16::from_be_bytes([*high, *low]),
+            _ => 0,
+        });
+        let origin = words.next().ok_or("image has no origin")?;
+        let mut memory = vec![0; 0x10000];
+        for (address, word) in (usize::from(origin)..).zip(words) {
+            *memory
+                .get_mut(address)
+                .ok_or("image does not fit in memory")? = word;
+        }
+        Ok(Reference {
+            registers: [0; 8],
+            pc: 0x3000,
+            condition: 0b010,
+            status: 0,
+            memory,
+            output: String::new(),
+            halted: false,
+            input: input.iter().copied().collect(),
+        })
+    }
+
+    /// Executes up to `count` instructions, stopping early on HALT
+    pub fn run(&mut self, count: u64) -> Result<(), String> {
+        for _ in 0..count {
+            if self.halted {
+                break;
+            }
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    pub fn step(&mut self) -> Result<(), String> {
+        if self.halted {
+            return Ok(());
+        }
+        let ir = self.peek(self.pc);
+        self.pc = self
+            .pc
+            .checked_add(1)
+            .ok_or_else(|| String::from("the PC runs past xFFFF"))?;
+        let dr = usize::from((ir >> 9) & 7);
+        let sr1 = usize::from((ir >> 6) & 7);
+        match ir >> 12 {
+            0x0 => {
+                if (ir >> 9) & self.condition != 0 {
+                    self.pc = add(self.pc, ir, 9)?;
+                }
+            }
+            0x1 => {
+                let value = self.reg(sr1).wrapping_add(self.operand(ir));
+                self.set(dr, value);
+            }
+            0x2 => {
+                let value = self.load(add(self.pc, ir, 9)?);
+                self.set(dr, value);
+            }
+            0x3 => {
+                let address = add(self.pc, ir, 9)?;
+                self.store(address, self.reg(dr));
+            }
+            0x4 => {
+                let link = self.pc;
+                self.pc = if ir & 0x0800 != 0 {
+                    add(self.pc, ir, 11)?
+                } else {
+                    self.reg(sr1)
+                };
+                self.put(7, link);
+            }
+            0x5 => {
+                let value = self.reg(sr1) & self.operand(ir);
+                self.set(dr, value);
+            }
+            0x6 => {
+                let value = self.load(add(self.reg(sr1), ir, 6)?);
+                self.set(dr, value);
+            }
+            0x7 => {
+                let address = add(self.reg(sr1), ir, 6)?;
+                self.store(address, self.reg(dr));
+            }
+            0x9 => {
+                let value = !self.reg(sr1);
+                self.set(dr, value);
+            }
+            0xA => {
+                let pointer = self.load(add(self.pc, ir, 9)?);
+                let value = self.load(pointer);
+                self.set(dr, value);
+            }
+            0xB => {
+                let pointer = self.load(add(self.pc, ir, 9)?);
+                self.store(pointer, self.reg(dr));
+            }
+            0xC => self.pc = self.reg(sr1),
+            0xE => {
+                let address = add(self.pc, ir, 9)?;
+                self.set(dr, address);
+            }
+            0xF => {
+                self.put(7, self.pc);
+                self.trap(ir & 0xFF)?;
+            }
+            _ => return Err(format!("illegal opcode in x{ir:04X}")),
+        }
+        Ok(())
+    }
+
+    fn trap(&mut self, vector: u16) -> Result<(), String> {
+        match vector {
+            0x20 => {
+                let key = self.key()?;
+                self.set(0, u16::from(key));
+            }
+            0x21 => {
+                let character = character(self.reg(0))?;
+                self.output.push(character);
+            }
+            0x22 => self.print_string(|word, output| {
+                output.push(character(word)?);
+                Ok(())
+            })?,
+            0x23 => {
+                self.output.push_str("Enter a character: ");
+                let key = self.key()?;
+                self.output.push(character(u16::from(key))?);
+                self.output.push('\n');
+                self.set(0, u16::from(key));
+            }
+            0x24 => self.print_string(|word, output| {
+                let [high, low] = word.to_be_bytes();
+                output.push(character(u16::from(low))?);
+                if high != 0 {
+                    output.push(character(u16::from(high))?);
+                }
+                Ok(())
+            })?,
+            0x25 => {
+                self.output.push_str("HALT\n");
+                self.halted = true;
+            }
+            _ => return Err(format!("unknown trap x{vector:02X}")),
+        }
+        Ok(())
+    }
+
+    /// Prints the zero terminated string at R0, one word at a time
+    fn print_string(
+        &mut self,
+        print: impl Fn(u16, &mut String) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let mut address = self.reg(0);
+        for _ in 0..=u16::MAX {
+            let word = self.load(address);
+            if word == 0 {
+                return Ok(());
+            }
+            print(word, &mut self.output)?;
+            address = address.wrapping_add(1);
+        }
+        Err(String::from("unterminated string"))
+    }
+
+    fn key(&mut self) -> Result<u8, String> {
+        self.input
+            .pop_front()
+            .ok_or_else(|| String::from("no input"))
+    }
+
+    fn operand(&self, ir: u16) -> u16 {
+        if ir & 0x20 != 0 {
+            sext(ir, 5)
+        } else {
+            self.reg(usize::from(ir & 7))
+        }
+    }
+
+    fn reg(&self, number: usize) -> u16 {
+        self.registers.get(number).copied().unwrap_or_default()
+    }
+
+    fn put(&mut self, number: usize, value: u16) {
+        if let Some(register) = self.registers.get_mut(number) {
+            *register = value;
+        }
+    }
+
+    /// Writes a register and sets the condition codes from the value
+    fn set(&mut self, number: usize, value: u16) {
+        self.put(number, value);
+        self.condition = match value {
+            0 => 0b010,
+            0x8000.. => 0b100,
+            _ => 0b001,
+        };
+    }
+
+    pub fn peek(&self, address: u16) -> u16 {
+        self.memory
+            .get(usize::from(address))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Reads memory; reading KBSR takes the next key into KBDR and
+    /// reading xFFFC gives the PSR
+    fn load(&mut self, address: u16) -> u16 {
+        if address == PSR {
+            return self.status | self.condition;
+        }
+        if address == KBSR {
+            match self.input.pop_front() {
+                Some(key) => {
+                    self.store(KBSR, 0x8000);
+                    self.store(KBDR, u16::from(key));
+                }
+                None => self.store(KBSR, 0),
+            }
+        }
+        self.peek(address)
+    }
+
+    /// Writes memory; writing xFFFC also sets the PSR, with the condition
+    /// codes N or P when only that bit is set and Z otherwise
+    fn store(&mut self, address: u16, value: u16) {
+        if address == PSR {
+            self.status = value & STATUS_MASK;
+            self.condition = match value & 0b111 {
+                0b100 => 0b100,
+                0b001 => 0b001,
+                _ => 0b010,
+            };
+        }
+        if let Some(cell) = self.memory.get_mut(usize::from(address)) {
+            *cell = value;
+        }
+    }
+}
+
+/// The low `bits` bits of `ir` sign extended to 16 bits
+fn sext(ir: u16, bits: u32) -> u16 {
+    let sign: u16 = 1 << bits.saturating_sub(1);
+    let field = ir & ((sign << 1).wrapping_sub(1));
+    if field & sign != 0 {
+        field | !((sign << 1).wrapping_sub(1))
+    } else {
+        field
+    }
+}
+
+/// `base` plus the sign extended low `bits` bits of `ir`, which must stay
+/// within x0000-xFFFF
+fn add(base: u16, ir: u16, bits: u32) -> Result<u16, String> {
+    let offset = i32::from(i16::from_ne_bytes(sext(ir, bits).to_ne_bytes()));
+    u16::try_from(i32::from(base).saturating_add(offset))
+        .map_err(|_| format!("x{base:04X} + {offset} leaves memory"))
+}
+
+/// The ASCII character `word`, the only ones printed by default
+fn character(word: u16) -> Result<char, String> {
+    u8::try_from(word)
+        .ok()
+        .filter(u8::is_ascii)
+        .map(char::from)
+        .ok_or_else(|| format!("x{word:04X} is not an ASCII character"))
+}
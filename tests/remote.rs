@@ -0,0 +1,110 @@
+//! The debugger driven over its line protocol
+#![cfg(unix)]
+#![allow(clippy::unwrap_used)]
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    os::unix::net::UnixStream,
+    path::Path,
+    thread,
+    time::Duration,
+};
+
+use lc3_vm::{
+    remote::{frame, serve, Session},
+    vm::VM,
+};
+
+fn countdown() -> String {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join("countdown.asm")
+        .to_str()
+        .unwrap()
+        .to_owned()
+}
+
+fn prompt() -> String {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join("prompt.asm")
+        .to_str()
+        .unwrap()
+        .to_owned()
+}
+
+#[test]
+fn answers_carry_the_program_output() {
+    let mut session = Session::new(VM::new());
+    session.handle(&format!("file {}", countdown())).unwrap();
+    session.handle("break set x3004").unwrap();
+    let answer = session.handle("continue").unwrap();
+    assert!(answer.starts_with("9\nPC=x3004"), "{answer}");
+    let answer = session.handle("c").unwrap();
+    assert!(answer.starts_with("8\nPC=x3004"), "{answer}");
+    assert!(session.handle("quit").is_none());
+}
+
+#[test]
+fn programs_wait_for_typed_input() {
+    let mut session = Session::new(VM::new());
+    session.handle(&format!("file {}", prompt())).unwrap();
+    let answer = session.handle("continue").unwrap();
+    assert!(answer.starts_with("The program is waiting for input.\nPC=x3000"));
+    assert_eq!(session.handle("input q").unwrap(), "Queued 1 key(s).");
+    let answer = session.handle("continue").unwrap();
+    assert!(
+        answer.starts_with("\nInput a character> q\n\nTwice: qq\n\n\n--- halting the LC-3 ---\n"),
+        "{answer}"
+    );
+    assert!(session.simulator().vm().is_halted());
+}
+
+#[test]
+fn answers_are_framed_with_a_final_dot() {
+    assert_eq!(frame(""), ".\n");
+    assert_eq!(frame("one\n.two\n"), "one\n..two\n.\n");
+}
+
+#[test]
+fn clients_connect_over_the_socket() {
+    let socket = Path::new(env!("CARGO_TARGET_TMPDIR")).join("remote.sock");
+    let path = socket.to_str().unwrap().to_owned();
+    let program = countdown();
+    let client = thread::spawn(move || {
+        let stream = loop {
+            match UnixStream::connect(&socket) {
+                Ok(stream) => break stream,
+                Err(_) => thread::sleep(Duration::from_millis(10)),
+            }
+        };
+        let mut writer = stream.try_clone().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut answer = |command: &str| {
+            writeln!(writer, "{command}").unwrap();
+            let mut lines = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == ".\n" {
+                    return lines;
+                }
+                lines.push(line.trim_end().to_owned());
+            }
+        };
+        assert_eq!(
+            answer(&format!("file {program}")),
+            [format!("Loaded \"{program}\" and set PC to x3000")]
+        );
+        assert_eq!(
+            answer("translate x300A"),
+            ["Address x300A has value x0009."]
+        );
+        assert_eq!(answer("quit"), Vec::<String>::new());
+    });
+    serve(VM::new(), &path).unwrap();
+    client.join().unwrap();
+    assert!(!Path::new(&path).exists());
+}
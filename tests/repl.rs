@@ -0,0 +1,88 @@
+//! The assembly playground driven line by line
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{console::SharedConsole, register::Register, repl::Repl, vm::VM};
+
+fn repl_with(console: &SharedConsole) -> Repl {
+    Repl::new(
+        VM::builder()
+            .console(Box::new(console.clone()))
+            .build()
+            .unwrap(),
+    )
+}
+
+#[test]
+fn lines_run_against_the_live_machine() {
+    let console = SharedConsole::new();
+    let mut repl = repl_with(&console);
+    assert_eq!(repl.prompt(), "x3000> ");
+    repl.eval("ADD R0, R0, #5").unwrap();
+    let output = repl.eval("ADD R1, R0, #-7").unwrap();
+    assert_eq!(
+        output,
+        "R0=x0005 R1=xFFFE R2=x0000 R3=x0000 R4=x0000 R5=x0000 R6=x0000 R7=x0000\n\
+         PC=x3002 CC=N"
+    );
+    assert_eq!(repl.prompt(), "x3002> ");
+    repl.eval("LD R2, #-3").unwrap();
+    assert_eq!(repl.vm().register(Register::R2), 0x1025);
+}
+
+#[test]
+fn directives_store_data_without_running_it() {
+    let console = SharedConsole::new();
+    let mut repl = repl_with(&console);
+    repl.eval(":pc x3100").unwrap();
+    repl.eval("TEXT .STRINGZ \"hi\"").unwrap();
+    assert_eq!(repl.prompt(), "x3103> ");
+    repl.eval("LEA R0, #-4").unwrap();
+    let output = repl.eval("PUTS").unwrap();
+    assert!(output.ends_with("PC=x3105 CC=P"), "{output}");
+    assert_eq!(console.take_output(), "hi");
+    assert_eq!(
+        repl.eval(":mem x3100 2").unwrap(),
+        "x3100  x0068  NOP\nx3101  x0069  NOP"
+    );
+}
+
+#[test]
+fn halting_is_shown_and_the_next_line_runs_again() {
+    let console = SharedConsole::new();
+    let mut repl = repl_with(&console);
+    assert!(repl.eval("HALT").unwrap().ends_with("halted"));
+    assert_eq!(console.take_output(), "HALT\n");
+    assert!(repl.eval("ADD R3, R3, #1").unwrap().ends_with("CC=P"));
+    assert_eq!(repl.vm().register(Register::R3), 1);
+}
+
+#[test]
+fn errors_and_commands() {
+    let console = SharedConsole::new();
+    let mut repl = repl_with(&console);
+    assert_eq!(
+        repl.eval("ADD R0, R0, #16").unwrap(),
+        "                   ^^^ 16 does not fit in 5 signed bits\n       \
+         help: ADD takes immediates from #-16 to #15; load other values with LD from a .FILL"
+    );
+    assert_eq!(
+        repl.eval("  BRz nowher").unwrap(),
+        "             ^^^^^^ unknown label nowher"
+    );
+    assert_eq!(repl.prompt(), "x3000> ");
+    assert_eq!(
+        repl.eval(":jump").unwrap(),
+        "error: unknown command :jump, see :help"
+    );
+    assert_eq!(
+        repl.eval(":mem nowhere").unwrap(),
+        "error: expected :mem ADDR [COUNT]"
+    );
+    assert!(repl.eval(":help").unwrap().contains(":reset"));
+    assert_eq!(repl.eval("   ").unwrap(), "");
+    repl.eval("NOT R4, R4").unwrap();
+    repl.eval(":reset").unwrap();
+    assert_eq!(repl.vm().register(Register::R4), 0);
+    assert_eq!(repl.prompt(), "x3000> ");
+    assert_eq!(repl.eval(":quit"), None);
+}
@@ -0,0 +1,45 @@
+//! The random number generator device
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    console::SharedConsole,
+    register::Register,
+    vm::{RNGDR, VM},
+};
+
+/// Reads `RNGDR` into R0 and R1, then halts
+const DRAW: &str = ".ORIG x3000
+         LDI R0, NUMBER
+         LDI R1, NUMBER
+         HALT
+NUMBER   .FILL xFE22
+         .END";
+
+fn draw(seed: u64) -> VM {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    vm.enable_rng(seed).unwrap();
+    vm.load_asm_str(DRAW).unwrap();
+    vm.run().unwrap();
+    vm
+}
+
+#[test]
+fn every_read_gives_the_next_number() {
+    let vm = draw(42);
+    assert_ne!(vm.register(Register::R0), vm.register(Register::R1));
+    assert_eq!(vm.peek(RNGDR), vm.register(Register::R1));
+}
+
+#[test]
+fn the_generator_repeats_its_sequence_after_a_reset() {
+    let mut vm = draw(42);
+    let first = vm.register(Register::R0);
+    vm.reset(true);
+    vm.run().unwrap();
+    assert_eq!(vm.register(Register::R0), first);
+    assert_eq!(draw(42).register(Register::R0), first);
+    assert_ne!(draw(7).register(Register::R0), first);
+}
@@ -0,0 +1,141 @@
+//! Inputs the fuzz targets in `fuzz/` must survive: malformed images and
+//! arbitrary instruction streams end with an error or the instruction
+//! limit, never a panic or a hang.
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    console::{ConsoleConfig, Encoding, NullConsole, SharedConsole},
+    errors::VMError,
+    memory::{append_checksum, Image},
+    vfs::MemoryFileSystem,
+    vm::{TrapMode, VM},
+};
+use proptest::prelude::*;
+
+fn sandbox() -> VM {
+    VM::builder()
+        .console(Box::new(NullConsole))
+        .trap_mode(TrapMode::Extended)
+        .file_system(Box::new(MemoryFileSystem::new()))
+        .instruction_limit(1_000)
+        .build()
+        .unwrap()
+}
+
+fn image(origin: u16, words: &[u16]) -> Vec<u8> {
+    std::iter::once(origin)
+        .chain(words.iter().copied())
+        .flat_map(u16::to_be_bytes)
+        .collect()
+}
+
+#[test]
+fn malformed_images_fail_to_load() {
+    let mut vm = sandbox();
+    assert!(matches!(
+        vm.read_image_bytes(&[]),
+        Err(VMError::InvalidImage(_))
+    ));
+    assert!(matches!(
+        vm.read_image_bytes(&[0x30]),
+        Err(VMError::InvalidImage(_))
+    ));
+    assert!(matches!(
+        vm.read_image_bytes(&image(0xFFFF, &[1, 2])),
+        Err(VMError::InvalidImage(_))
+    ));
+}
+
+fn image_error(bytes: &[u8]) -> String {
+    match Image::parse(bytes) {
+        Ok(_) => String::from("parsed"),
+        Err(error) => error.to_string(),
+    }
+}
+
+#[test]
+fn image_errors_say_what_is_wrong() {
+    assert_eq!(
+        image_error(&[0x30, 0x00, 0x12]),
+        "Invalid image: Image has an odd number of bytes (3), its last word is truncated"
+    );
+    assert_eq!(
+        image_error(&image(0x3000, &[])),
+        "Invalid image: Image loaded at 0x3000 has no words after its origin"
+    );
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .memory_size(0x4000)
+        .build()
+        .unwrap();
+    assert_eq!(
+        vm.read_image_bytes(&image(0x3FFF, &[1, 2]))
+            .unwrap_err()
+            .to_string(),
+        "Invalid image: Image of 2 words loaded at 0x3fff runs past the 16384 words of RAM"
+    );
+    assert_eq!(vm.memory().read(0x3FFF), 0);
+}
+
+#[test]
+fn checksum_footers_are_verified_and_stripped() {
+    let mut bytes = image(0x3000, &[0x1021, 0xF025]);
+    append_checksum(&mut bytes);
+    let parsed = Image::parse(&bytes).unwrap();
+    assert_eq!(parsed.origin, 0x3000);
+    assert_eq!(parsed.words, [0x1021, 0xF025]);
+
+    let mut corrupted = bytes.clone();
+    if let Some(byte) = corrupted.get_mut(3) {
+        *byte ^= 1;
+    }
+    assert!(image_error(&corrupted).starts_with("Invalid image: Checksum"));
+}
+
+#[test]
+fn spinning_programs_stop_at_the_instruction_limit() {
+    let mut vm = sandbox();
+    vm.read_image_bytes(&image(0x3000, &[0x0FFF])).unwrap();
+    assert!(matches!(vm.run(), Err(VMError::InstructionLimit(_))));
+}
+
+#[test]
+fn strings_without_terminator_fail() {
+    // PUTSP at x3000 prints from x0000, and every word of memory is non-zero,
+    // KBSR included since a key is pending, whose x80 needs an encoding
+    let mut words = vec![0x4141; 0x10000];
+    if let Some(word) = words.get_mut(0x3000) {
+        *word = 0xF024;
+    }
+    let console = SharedConsole::new();
+    console.push_input(*b"x");
+    let mut vm = VM::builder()
+        .console(Box::new(console))
+        .console_config(ConsoleConfig {
+            encoding: Encoding::Latin1,
+            ..ConsoleConfig::default()
+        })
+        .entry(0x3000)
+        .instruction_limit(10)
+        .build()
+        .unwrap();
+    vm.read_image_bytes(&image(0x0000, &words)).unwrap();
+    assert!(matches!(vm.run(), Err(VMError::MemoryIndex(_))));
+}
+
+proptest! {
+    #[test]
+    fn arbitrary_programs_terminate(words in proptest::collection::vec(any::<u16>(), 1..64)) {
+        let mut vm = sandbox();
+        vm.read_image_bytes(&image(0x3000, &words)).unwrap();
+        let _ = vm.run();
+    }
+
+    #[test]
+    fn arbitrary_images_load_or_fail(bytes in proptest::collection::vec(any::<u8>(), 0..256)) {
+        let mut vm = sandbox();
+        if vm.read_image_bytes(&bytes).is_ok() {
+            let _ = vm.run_with_fuel(1_000);
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! Sandboxed runs of untrusted images
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    assembler::assemble,
+    sandbox::{FaultKind, Sandbox, Termination},
+};
+
+fn image(source: &str) -> Vec<u8> {
+    assemble(source).unwrap().image()
+}
+
+fn fault(sandbox: &Sandbox, source: &str, input: &[u8]) -> FaultKind {
+    match sandbox.run(&[&image(source)], input).termination {
+        Termination::Fault(fault) => fault.kind,
+        Termination::Halted => FaultKind::Other,
+    }
+}
+
+const ECHO: &str = ".ORIG x3000
+LOOP     GETC
+         OUT
+         ADD R1, R0, #-10
+         BRnp LOOP
+         HALT
+         .END";
+
+#[test]
+fn halting_programs_report_their_output_and_state() {
+    let execution = Sandbox::new().run(&[&image(ECHO)], b"hi\n");
+    assert!(execution.halted());
+    assert_eq!(execution.output, "hi\nHALT\n");
+    assert_eq!(execution.registers.first(), Some(&u16::from(b'\n')));
+    assert_eq!(execution.instructions, 13);
+}
+
+#[test]
+fn faults_are_results() {
+    let sandbox = Sandbox::new();
+    assert_eq!(fault(&sandbox, ECHO, b"hi"), FaultKind::InputExhausted);
+    let spin = ".ORIG x3000
+LOOP     BRnzp LOOP
+         .END";
+    let limited = Sandbox {
+        instruction_limit: 100,
+        ..Sandbox::new()
+    };
+    assert_eq!(fault(&limited, spin, b""), FaultKind::InstructionLimit);
+    let reserved = ".ORIG x3000
+         .FILL xD000
+         .END";
+    assert_eq!(
+        fault(&sandbox, reserved, b""),
+        FaultKind::IllegalInstruction
+    );
+    let execution = sandbox.run(&[&[0x30]], b"");
+    assert!(matches!(
+        execution.termination,
+        Termination::Fault(fault) if fault.kind == FaultKind::InvalidImage
+    ));
+}
+
+#[test]
+fn output_is_limited() {
+    let chatter = ".ORIG x3000
+LOOP     LEA R0, TEXT
+         PUTS
+         BRnzp LOOP
+TEXT     .STRINGZ \"spam\"
+         .END";
+    let sandbox = Sandbox {
+        output_limit: 10,
+        ..Sandbox::new()
+    };
+    let execution = sandbox.run(&[&image(chatter)], b"");
+    assert!(matches!(
+        &execution.termination,
+        Termination::Fault(fault) if fault.kind == FaultKind::OutputLimit
+    ));
+    assert_eq!(execution.output, "spamspamsp");
+}
+
+#[test]
+fn host_files_are_out_of_reach() {
+    // FOPEN, which only exists where the embedder enables file traps
+    let open = ".ORIG x3000
+         LEA R0, NAME
+         TRAP x80
+         HALT
+NAME     .STRINGZ \"/etc/passwd\"
+         .END";
+    assert_eq!(
+        fault(&Sandbox::new(), open, b""),
+        FaultKind::IllegalInstruction
+    );
+}
+
+#[test]
+fn executions_serialize_to_json() {
+    let execution = Sandbox::new().run(&[&image(ECHO)], b"");
+    let json: serde_json::Value = serde_json::from_str(&execution.to_json()).unwrap();
+    assert_eq!(json.get("halted").unwrap(), false);
+    let fault = json.get("fault").unwrap();
+    assert_eq!(fault.get("kind").unwrap(), "input_exhausted");
+    assert_eq!(json.get("pc").unwrap(), 0x3001);
+}
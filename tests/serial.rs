@@ -0,0 +1,77 @@
+//! The serial port registers driven by programs
+#![allow(clippy::unwrap_used)]
+
+use std::{
+    io::{self, Read, Write},
+    thread,
+    time::Duration,
+};
+
+use lc3_vm::{
+    console::NullConsole,
+    serial::{SerialPort, SRSR, STSR},
+    vm::{TrapMessages, VM},
+};
+
+/// Sends the word at x4000, waits for a byte and stores it at x4001
+const ROUND_TRIP: &str = ".ORIG x3000
+WAITTX   LDI R1, STSR
+         BRzp WAITTX
+         LDI R0, OUTBOX
+         STI R0, STDR
+WAITRX   LDI R1, SRSR
+         BRzp WAITRX
+         LDI R0, SRDR
+         STI R0, INBOX
+         HALT
+STSR     .FILL xFE0C
+STDR     .FILL xFE0E
+SRSR     .FILL xFE08
+SRDR     .FILL xFE0A
+OUTBOX   .FILL x4000
+INBOX    .FILL x4001
+         .END";
+
+fn machine(port: SerialPort) -> VM {
+    let mut vm = VM::builder()
+        .console(Box::new(NullConsole))
+        .trap_messages(TrapMessages::SILENT)
+        .build()
+        .unwrap();
+    vm.set_serial(port).unwrap();
+    vm.load_asm_str(ROUND_TRIP).unwrap();
+    vm
+}
+
+#[test]
+fn sent_bytes_come_back_through_a_loopback() {
+    let (reader, writer) = io::pipe().unwrap();
+    let mut vm = machine(SerialPort::new(reader, writer));
+    vm.poke(0x4000, 0x5A).unwrap();
+    vm.run().unwrap();
+    assert!(vm.is_halted());
+    assert_eq!(vm.peek(0x4001), 0x5A);
+    // the byte was taken, and sending is always possible
+    assert_eq!(vm.peek(SRSR) & 0x8000, 0);
+    assert_eq!(vm.peek(STSR) & 0x8000, 0x8000);
+}
+
+#[test]
+fn bytes_go_to_and_come_from_the_peer() {
+    let (reader, mut to_vm) = io::pipe().unwrap();
+    let (mut from_vm, writer) = io::pipe().unwrap();
+    let peer = thread::spawn(move || {
+        let mut sent = [0];
+        from_vm.read_exact(&mut sent).unwrap();
+        let [sent] = sent;
+        // answer later than the program starts waiting
+        thread::sleep(Duration::from_millis(10));
+        to_vm.write_all(&[sent.wrapping_add(1)]).unwrap();
+        sent
+    });
+    let mut vm = machine(SerialPort::new(reader, writer));
+    vm.poke(0x4000, u16::from(b'a')).unwrap();
+    vm.run().unwrap();
+    assert_eq!(peer.join().unwrap(), b'a');
+    assert_eq!(vm.peek(0x4001), u16::from(b'b'));
+}
@@ -0,0 +1,52 @@
+//! Source lines mapped to the words assembled from them
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{assembler::assemble, source_map::SourceMap, symbols::SymbolTable};
+
+const SOURCE: &str = "\
+; counts down from three
+        .ORIG x3000
+        .MACRO DEC reg
+        ADD reg, reg, #-1
+        ADD reg, reg, #0
+        .ENDM
+        AND R0, R0, #0
+        ADD R0, R0, #3
+LOOP
+        DEC R0
+        BRp LOOP
+        HALT
+TEXT    .STRINGZ \"ok\"
+        .END";
+
+#[test]
+fn lines_map_to_their_words_and_back() {
+    let assembly = assemble(SOURCE).unwrap();
+    let map = SourceMap::new(&assembly);
+    assert_eq!(map.line_at(0x3000), Some(7));
+    // both words of the macro use belong to its line
+    assert_eq!(map.line_at(0x3002), Some(10));
+    assert_eq!(map.line_at(0x3003), Some(10));
+    assert_eq!(map.line_at(0x3007), Some(13));
+    assert_eq!(map.line_at(0x3009), None);
+    // comments, directives and label-only lines move to the next instruction
+    assert_eq!(map.address_of_line(1), Some((7, 0x3000)));
+    assert_eq!(map.address_of_line(9), Some((10, 0x3002)));
+    assert_eq!(map.address_of_line(12), Some((12, 0x3005)));
+    assert_eq!(map.address_of_line(14), None);
+}
+
+#[test]
+fn addresses_inside_the_program_are_label_relative() {
+    let assembly = assemble(SOURCE).unwrap();
+    let map = SourceMap::new(&assembly);
+    let symbols = &assembly.symbols;
+    assert_eq!(map.describe(symbols, 0x3002), "x3002 (LOOP)");
+    assert_eq!(map.describe(symbols, 0x3004), "x3004 (LOOP+2)");
+    assert_eq!(map.describe(symbols, 0x3008), "x3008 (TEXT+2)");
+    assert_eq!(map.describe(symbols, 0x4000), "x4000");
+    assert_eq!(
+        SourceMap::default().describe(&SymbolTable::default(), 0x3000),
+        "x3000"
+    );
+}
@@ -0,0 +1,128 @@
+//! The stack discipline checker on its own and on running programs
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    console::NullConsole,
+    errors::VMError,
+    instructions::{AddressArithmetic, Instruction},
+    register::Register,
+    stack::StackChecker,
+    vm::{TrapMessages, VM},
+};
+
+/// STR R0, R6, #0
+const PUSH: u16 = 0x7180;
+
+fn checker() -> StackChecker {
+    StackChecker::new(0x4000, 0x4010).unwrap()
+}
+
+fn store(offset: i16) -> Instruction {
+    Instruction::Str {
+        sr: Register::R0,
+        base: Register::R6,
+        offset,
+    }
+}
+
+fn violation(result: Result<(), VMError>) -> String {
+    let error = result.unwrap_err();
+    assert!(matches!(error, VMError::StackViolation(_)), "{error}");
+    error.to_string().replace("Stack violation: ", "")
+}
+
+#[test]
+fn the_region_must_not_be_empty() {
+    assert!(StackChecker::new(0x4000, 0x4000).is_err());
+    assert!(StackChecker::new(0x4010, 0x4000).is_err());
+    assert_eq!(checker().register(), Register::R6);
+    assert_eq!(
+        checker().with_register(Register::R5).register(),
+        Register::R5
+    );
+}
+
+#[test]
+fn the_checker_idles_until_the_pointer_enters_the_stack() {
+    let mut checker = checker();
+    checker.check_pointer(0x3000, PUSH, 0).unwrap();
+    checker.check_pointer(0x3000, PUSH, 0x5000).unwrap();
+    let wild =
+        checker.check_instruction(0x3000, PUSH, &store(0), 0x5000, AddressArithmetic::Checked);
+    wild.unwrap();
+    checker.check_pointer(0x3001, PUSH, 0x4010).unwrap();
+    checker.check_pointer(0x3002, PUSH, 0x4000).unwrap();
+}
+
+#[test]
+fn pointers_leaving_the_stack_are_violations() {
+    let mut checker = checker();
+    checker.check_pointer(0x3000, PUSH, 0x4010).unwrap();
+    assert_eq!(
+        violation(checker.check_pointer(0x3004, 0x1DBF, 0x3FFF)),
+        "stack overflow, R6 = 0x3fff at 0x3004 (instruction 0x1dbf)"
+    );
+    assert_eq!(
+        violation(checker.check_pointer(0x3005, 0x1DA1, 0x4011)),
+        "stack underflow, R6 = 0x4011 at 0x3005 (instruction 0x1da1)"
+    );
+    checker.reset();
+    checker.check_pointer(0x3000, PUSH, 0x3FFF).unwrap();
+}
+
+#[test]
+fn stores_through_the_pointer_stay_in_the_stack() {
+    let mut checker = checker();
+    checker.check_pointer(0x3000, PUSH, 0x4008).unwrap();
+    let checked = |instruction: &Instruction| {
+        checker.check_instruction(
+            0x3001,
+            PUSH,
+            instruction,
+            0x4008,
+            AddressArithmetic::Checked,
+        )
+    };
+    checked(&store(-8)).unwrap();
+    checked(&store(7)).unwrap();
+    assert_eq!(
+        violation(checked(&store(8))),
+        "write to 0x4010 is outside of the stack at 0x3001 (instruction 0x7180)"
+    );
+    assert!(violation(checked(&store(-9))).contains("0x3fff"));
+    // other base registers and instructions are not checked
+    let other = Instruction::Str {
+        sr: Register::R0,
+        base: Register::R5,
+        offset: 20,
+    };
+    checked(&other).unwrap();
+    checked(&Instruction::decode(0x1021)).unwrap();
+}
+
+#[test]
+fn programs_overflowing_the_stack_are_stopped() {
+    let mut vm = VM::builder()
+        .console(Box::new(NullConsole))
+        .trap_messages(TrapMessages::SILENT)
+        .stack_checker(StackChecker::new(0x4000, 0x4002).unwrap())
+        .build()
+        .unwrap();
+    vm.load_asm_str(
+        ".ORIG x3000
+         LD R6, BASE
+PUSH     ADD R6, R6, #-1
+         STR R0, R6, #0
+         BRnzp PUSH
+BASE     .FILL x4002
+         .END",
+    )
+    .unwrap();
+    let error = vm.run().unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Stack violation: stack overflow, R6 = 0x3fff at 0x3001 (instruction 0x1dbf)"
+    );
+    assert_eq!(vm.peek(0x4001), 0);
+    assert_eq!(vm.peek(0x3FFF), 0);
+}
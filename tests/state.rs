@@ -0,0 +1,206 @@
+//! Whole-machine assertions with `VmState` and `assert_state_eq!`
+#![allow(clippy::unwrap_used)]
+
+use std::path::Path;
+
+use lc3_vm::{
+    assert_state_eq,
+    console::SharedConsole,
+    errors::VMError,
+    register::Register,
+    vm::{Checkpoints, MemoryChange, Psr, VmState, VM},
+};
+
+/// `ARRAY` and `RESULT` of `golden/memory.asm`
+const ARRAY: u16 = 0x301D;
+
+fn run_memory_program() -> VM {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/memory.obj");
+    vm.read_image(path.to_str().unwrap()).unwrap();
+    vm.run().unwrap();
+    vm
+}
+
+#[test]
+fn memory_program_leaves_the_expected_state() {
+    let vm = run_memory_program();
+    let expected = VmState {
+        pc: 0x301B,
+        registers: [10, 0x3022, 0, 15, 0x4D, 0x20, 0, 0x301B],
+        psr: Psr::from_bits(0b001),
+        halted: true,
+        ..VmState::default()
+    }
+    .with_memory(ARRAY, &[3, 6, 9, 12, 15, 0x4D]);
+    assert_state_eq!(vm.snapshot(&[(ARRAY, 6)]), expected);
+}
+
+#[test]
+fn differences_name_each_changed_field() {
+    let vm = run_memory_program();
+    let mut expected = vm.snapshot(&[(ARRAY, 2)]);
+    assert!(vm.snapshot(&[(ARRAY, 2)]).differences(&expected).is_empty());
+
+    expected.registers[1] = 0x3000;
+    expected.psr = Psr::from_bits(0b100);
+    expected.memory.insert(ARRAY, 4);
+    expected.memory.insert(ARRAY + 2, 9);
+    assert_eq!(
+        vm.snapshot(&[(ARRAY, 2)]).differences(&expected),
+        [
+            "R1: expected x3000, got x3022",
+            "PSR: expected x0004, got x0001",
+            "x301D: expected x0004, got x0003",
+            "x301F: expected x0009, got not captured",
+        ]
+    );
+}
+
+#[test]
+#[should_panic(expected = "after HALT:\n  PC: expected x3000, got x301B")]
+fn failed_assertions_list_the_differences() {
+    let vm = run_memory_program();
+    assert_state_eq!(
+        vm.state(),
+        VmState {
+            pc: 0x3000,
+            ..vm.state()
+        },
+        "after HALT"
+    );
+}
+
+#[test]
+fn diffs_list_what_changed_between_states() {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/memory.obj");
+    vm.read_image(path.to_str().unwrap()).unwrap();
+    let before = vm.snapshot(&[(ARRAY, 6)]);
+    assert!(before.diff(&before).is_empty());
+    vm.run().unwrap();
+    let diff = before.diff(&vm.snapshot(&[(ARRAY, 3)]));
+    assert_eq!(diff.pc, Some((0x3000, 0x301B)));
+    assert_eq!(
+        diff.registers
+            .iter()
+            .map(|change| change.register)
+            .collect::<Vec<_>>(),
+        [
+            Register::R0,
+            Register::R1,
+            Register::R3,
+            Register::R4,
+            Register::R5,
+            Register::R7
+        ]
+    );
+    // only the words captured in both states are compared
+    assert_eq!(
+        diff.memory,
+        [
+            MemoryChange {
+                address: ARRAY,
+                before: 0,
+                after: 3
+            },
+            MemoryChange {
+                address: ARRAY + 1,
+                before: 0,
+                after: 6
+            },
+            MemoryChange {
+                address: ARRAY + 2,
+                before: 0,
+                after: 9
+            },
+        ]
+    );
+    assert!(diff
+        .to_string()
+        .starts_with("PC x3000 -> x301B\nPSR x0002 -> x0001\nR0 x0000 -> x000A\n"));
+    assert!(diff.to_string().ends_with("\nx301F x0000 -> x0009"));
+}
+
+#[test]
+fn steps_report_what_they_changed() {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    vm.load_asm_str(
+        ".ORIG x3000
+         ADD R1, R1, #5
+         ST R1, VALUE
+         ST R2, VALUE
+         BRnzp #0
+VALUE    .FILL #0
+         .END",
+    )
+    .unwrap();
+    let (_, diff) = vm.step_diff().unwrap();
+    assert_eq!(diff.pc, Some((0x3000, 0x3001)));
+    assert_eq!(
+        diff.psr,
+        Some((Psr::from_bits(0b010), Psr::from_bits(0b001)))
+    );
+    assert_eq!(diff.registers.len(), 1);
+    assert!(diff.memory.is_empty());
+    let (_, diff) = vm.step_diff().unwrap();
+    assert_eq!(diff.psr, None);
+    assert!(diff.registers.is_empty());
+    assert_eq!(
+        diff.memory,
+        [MemoryChange {
+            address: 0x3004,
+            before: 0,
+            after: 5
+        }]
+    );
+    // a write of the value a word already holds changes nothing
+    vm.set_register(Register::R2, 5);
+    let (_, diff) = vm.step_diff().unwrap();
+    assert!(diff.memory.is_empty());
+    let (_, diff) = vm.step_diff().unwrap();
+    assert_eq!(diff.pc, Some((0x3003, 0x3004)));
+}
+
+#[test]
+fn checkpoints_roll_the_machine_back() {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/memory.obj");
+    vm.read_image(path.to_str().unwrap()).unwrap();
+    let mut checkpoints = Checkpoints::new();
+    checkpoints.save("start", &vm);
+    let start = vm.snapshot(&[(ARRAY, 6)]);
+    vm.run().unwrap();
+    let end = vm.snapshot(&[(ARRAY, 6)]);
+
+    checkpoints.restore("start", &mut vm).unwrap();
+    assert_state_eq!(vm.snapshot(&[(ARRAY, 6)]), start);
+    // the same region runs again to the same end
+    vm.run().unwrap();
+    assert_state_eq!(vm.snapshot(&[(ARRAY, 6)]), end);
+
+    // restoring is not the program writing
+    let metrics = vm.metrics();
+    checkpoints.restore("start", &mut vm).unwrap();
+    assert_eq!(vm.metrics().memory_writes, metrics.memory_writes);
+
+    assert_eq!(checkpoints.names().collect::<Vec<_>>(), ["start"]);
+    assert!(matches!(
+        checkpoints.restore("missing", &mut vm),
+        Err(VMError::Debugger(_))
+    ));
+    assert!(checkpoints.remove("start"));
+    assert!(checkpoints.get("start").is_none());
+}
@@ -0,0 +1,152 @@
+//! The end-of-run statistics of `--stats`
+#![allow(clippy::unwrap_used)]
+
+use std::{cell::RefCell, rc::Rc};
+
+use lc3_vm::{console::SharedConsole, instructions::Opcode, stats::Statistics, vm::VM};
+
+#[test]
+fn runs_are_broken_down_by_opcode_trap_and_call_depth() {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    vm.load_asm_str(
+        ".ORIG x3000
+         JSR OUTER
+         JSR INNER
+         HALT
+OUTER    ADD R6, R7, #0
+         JSR INNER
+         ADD R7, R6, #0
+         RET
+INNER    ADD R1, R1, #1
+         RET
+         .END",
+    )
+    .unwrap();
+    let statistics = Rc::new(RefCell::new(Statistics::new()));
+    vm.set_observer(Box::new(Rc::clone(&statistics)));
+    vm.run().unwrap();
+
+    let statistics = statistics.borrow();
+    assert_eq!(statistics.peak_call_depth(), 2);
+    assert_eq!(
+        statistics.opcodes().collect::<Vec<_>>(),
+        [
+            (Opcode::Add, 4),
+            (Opcode::Jsr, 3),
+            (Opcode::Jmp, 3),
+            (Opcode::Trap, 1)
+        ]
+    );
+    assert_eq!(statistics.traps().collect::<Vec<_>>(), [(0x25, 1)]);
+
+    let report = statistics.report(&vm.metrics());
+    assert!(report.starts_with("--- statistics ---\nInstructions:    11\n"));
+    assert!(report.contains("Peak call depth: 2\n"));
+    // 4 of 11 is 36.36%
+    assert!(report.contains("\n  ADD            4  36.4%\n"), "{report}");
+    assert!(report.ends_with("Traps:\n  x25 HALT           1\n"));
+}
+
+#[test]
+fn branches_are_ranked_by_how_unpredictable_they_are() {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    // an outer loop of 4 runs an inner loop of 3, the inner test at x3003
+    // alternates with the parity of R0 at x3006, and x3008 loops back
+    vm.load_asm_str(
+        ".ORIG x3000
+         ADD R1, R1, #4
+OUTER    AND R2, R2, #0
+         ADD R2, R2, #3
+INNER    ADD R2, R2, #-1
+         BRp INNER
+         AND R3, R0, #1
+         BRz EVEN
+         ADD R0, R0, #0
+EVEN     ADD R0, R0, #1
+         ADD R1, R1, #-1
+         BRp OUTER
+         BRnzp DONE
+DONE     HALT
+         .END",
+    )
+    .unwrap();
+    let statistics = Rc::new(RefCell::new(Statistics::new()));
+    vm.set_observer(Box::new(Rc::clone(&statistics)));
+    vm.run().unwrap();
+
+    let statistics = statistics.borrow();
+    let branches: Vec<_> = statistics
+        .branches()
+        .map(|(address, counts)| (address, counts.taken, counts.not_taken))
+        .collect();
+    // BRnzp is not a conditional branch
+    assert_eq!(branches, [(0x3004, 8, 4), (0x3006, 2, 2), (0x300A, 3, 1)]);
+    let report = statistics.report(&vm.metrics());
+    assert!(
+        report.ends_with(
+            "Least predictable branches:
+  x3006 BRz x3008                 2 taken          2 not taken  50.0%
+  x3004 BRp x3003                 8 taken          4 not taken  66.7%
+  x300A BRp x3001                 3 taken          1 not taken  75.0%
+"
+        ),
+        "{report}"
+    );
+}
+
+#[test]
+fn the_instruction_mix_counts_memory_traffic_by_class_and_trap() {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    vm.load_asm_str(
+        ".ORIG x3000
+         LD R1, VALUE
+         LDI R2, POINTER
+         ADD R1, R1, R2
+         ST R1, VALUE
+         LEA R0, TEXT
+         PUTS
+         HALT
+VALUE    .FILL #1
+POINTER  .FILL VALUE
+TEXT     .STRINGZ \"ok\"
+         .END",
+    )
+    .unwrap();
+    let statistics = Rc::new(RefCell::new(Statistics::new()));
+    vm.set_observer(Box::new(Rc::clone(&statistics)));
+    vm.run().unwrap();
+
+    let statistics = statistics.borrow();
+    assert_eq!(statistics.loads_per_store(), Some((3, 1)));
+    assert_eq!(
+        statistics.mix_csv(),
+        "group,name,count,share,reads,writes
+class,operate,1,0.1428,0,0
+class,load,3,0.4285,3,0
+class,store,1,0.1428,0,1
+class,trap,2,0.2857,3,0
+trap,PUTS,1,0.1428,3,0
+trap,HALT,1,0.1428,0,0
+"
+    );
+    let json = statistics.mix_json();
+    assert!(json.starts_with("{\n  \"instructions\": 7,\n  \"loads_per_store\": 3.0000,\n"));
+    assert!(json.contains(
+        "\"classes\": [\n    {\"name\": \"operate\", \"count\": 1, \"share\": 0.1428, \"reads\": 0, \"writes\": 0},\n"
+    ));
+    let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let traps = parsed
+        .get("traps")
+        .and_then(|traps| traps.as_array())
+        .unwrap();
+    assert_eq!(traps.len(), 2);
+}
@@ -0,0 +1,79 @@
+//! Reproducible test vectors
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    testgen::{seed_from, Generator, Precondition, Rng},
+    vm::VM,
+};
+
+fn vector(seed: u64) -> (Vec<i16>, lc3_vm::testgen::TestVector) {
+    let mut generator = Generator::new(seed);
+    let numbers = generator.array(0x4000, 20, -5..=5);
+    generator.input_number(1..=9);
+    generator.string(0x5000, 4, b"ab");
+    (numbers, generator.finish())
+}
+
+#[test]
+fn the_same_seed_gives_the_same_vector() {
+    assert_eq!(vector(7), vector(7));
+    assert_ne!(vector(7), vector(8));
+    assert_eq!(seed_from("alice"), seed_from("alice"));
+    assert_ne!(seed_from("alice"), seed_from("bob"));
+}
+
+#[test]
+fn generated_values_respect_their_ranges() {
+    let (numbers, vector) = vector(seed_from("alice"));
+    assert!(numbers.iter().all(|number| (-5..=5).contains(number)));
+    let [digit, b'\n'] = vector.input.as_slice() else {
+        unreachable!("{:?}", vector.input);
+    };
+    assert!((b'1'..=b'9').contains(digit));
+    let string = vector.memory.get(1).unwrap();
+    assert_eq!(string.address, 0x5000);
+    assert_eq!(string.words.len(), 5);
+    assert_eq!(string.words.last(), Some(&0));
+    assert!(string
+        .words
+        .iter()
+        .take(4)
+        .all(|&word| word == 0x61 || word == 0x62));
+
+    let mut rng = Rng::new(1);
+    // the whole range is reachable without overflowing
+    let full: Vec<i16> = (0..1000).map(|_| rng.range(i16::MIN..=i16::MAX)).collect();
+    assert!(full.iter().any(|&number| number < -16000));
+    assert!(full.iter().any(|&number| number > 16000));
+    assert_eq!(rng.range(3..=3), 3);
+    assert_eq!(rng.choose::<u8>(&[]), None);
+}
+
+#[test]
+fn permutations_hold_every_number_once() {
+    let mut generator = Generator::new(3);
+    let mut numbers = generator.permutation(0x4000, 50);
+    numbers.sort_unstable();
+    assert_eq!(numbers, (1..=50).collect::<Vec<u16>>());
+}
+
+#[test]
+fn vectors_are_applied_to_memory() {
+    let mut generator = Generator::new(5);
+    let numbers = generator.array(0x4000, 3, -1..=-1);
+    generator.words(0xFFFF, vec![7, 8]);
+    let vector = generator.finish();
+    assert_eq!(
+        vector.memory.get(1),
+        Some(&Precondition {
+            address: 0xFFFF,
+            words: vec![7, 8]
+        })
+    );
+    let mut vm = VM::new();
+    vector.apply(&mut vm).unwrap();
+    assert_eq!(numbers, [-1, -1, -1]);
+    assert_eq!(vm.peek(0x4002), 0xFFFF);
+    assert_eq!(vm.peek(0xFFFF), 7);
+    assert_eq!(vm.peek(0x0000), 8);
+}
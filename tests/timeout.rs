@@ -0,0 +1,89 @@
+//! Wall-clock timeouts of `run`
+#![allow(clippy::unwrap_used)]
+
+use std::{thread, time::Duration};
+
+use lc3_vm::{
+    console::{Console, SharedConsole},
+    errors::VMError,
+    vm::VM,
+};
+
+/// Console whose user never presses a key
+struct IdleConsole;
+
+impl Console for IdleConsole {
+    fn read_key(&mut self) -> Result<u8, VMError> {
+        Err(VMError::InvalidArgument(String::from(
+            "read_key must not block under a timeout",
+        )))
+    }
+
+    fn read_key_timeout(&mut self, timeout: Duration) -> Result<Option<u8>, VMError> {
+        thread::sleep(timeout);
+        Ok(None)
+    }
+
+    fn poll_key(&mut self) -> Result<Option<u8>, VMError> {
+        Ok(None)
+    }
+
+    fn write_char(&mut self, _character: char) -> Result<(), VMError> {
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), VMError> {
+        Ok(())
+    }
+}
+
+fn image(words: &[u16]) -> Vec<u8> {
+    [0x3000]
+        .iter()
+        .chain(words)
+        .flat_map(|word| word.to_be_bytes())
+        .collect()
+}
+
+fn run(console: Box<dyn Console>, words: &[u16]) -> (VM, Result<(), VMError>) {
+    let mut vm = VM::builder()
+        .console(console)
+        .timeout(Duration::from_millis(50))
+        .build()
+        .unwrap();
+    vm.read_image_bytes(&image(words)).unwrap();
+    let result = vm.run();
+    (vm, result)
+}
+
+#[test]
+fn endless_loops_time_out() {
+    // BRnzp #-1
+    let (_, result) = run(Box::new(SharedConsole::new()), &[0x0FFF]);
+    let error = result.unwrap_err();
+    assert!(matches!(error, VMError::Timeout(_)));
+    assert_eq!(error.to_string(), "Timed out after 50ms at PC=x3000");
+}
+
+#[test]
+fn programs_waiting_for_a_key_time_out() {
+    // NOT R0, R0; GETC
+    let (vm, result) = run(Box::new(IdleConsole), &[0x903F, 0xF020]);
+    assert!(matches!(result, Err(VMError::Timeout(_))));
+    // the GETC runs again if the program is resumed
+    assert_eq!(vm.pc(), 0x3001);
+}
+
+#[test]
+fn programs_finishing_in_time_succeed() {
+    // HALT
+    let (_, result) = run(Box::new(SharedConsole::new()), &[0xF025]);
+    assert!(result.is_ok());
+}
+
+#[test]
+fn timeouts_must_be_positive() {
+    let mut vm = VM::new();
+    assert!(vm.set_timeout(Some(Duration::ZERO)).is_err());
+    assert!(vm.set_timeout(None).is_ok());
+}
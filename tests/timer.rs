@@ -0,0 +1,112 @@
+//! The interval timer device
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    console::SharedConsole,
+    cycles::CycleModel,
+    register::Register,
+    vm::{TMPR, TMSR, VM},
+};
+
+fn machine(period: u16) -> VM {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    vm.enable_timer(period).unwrap();
+    vm
+}
+
+/// Reads `TMSR` into R0 `count` times, then halts
+fn polls(count: usize) -> String {
+    let mut source = String::from(".ORIG x3000\n");
+    for _ in 0..count {
+        source.push_str("LDI R0, STATUS\n");
+    }
+    source.push_str("HALT\nSTATUS .FILL xFE1C\n.END");
+    source
+}
+
+#[test]
+fn the_timer_fires_once_per_period() {
+    let mut vm = machine(3);
+    // three instructions executed before the fourth read
+    vm.load_asm_str(&polls(4)).unwrap();
+    let mut fired = Vec::new();
+    for _ in 0..4 {
+        vm.step().unwrap();
+        fired.push(vm.register(Register::R0) != 0);
+    }
+    assert_eq!(fired, [false, false, false, true]);
+    assert_eq!(vm.peek(TMPR), 3);
+    assert_eq!(vm.peek(TMSR), 1 << 15);
+}
+
+#[test]
+fn programs_change_the_period() {
+    let mut vm = machine(3);
+    vm.load_asm_str(
+        ".ORIG x3000
+         AND R0, R0, #0
+         STI R0, PERIOD
+         LDI R1, PERIOD
+         LDI R2, STATUS
+         HALT
+PERIOD   .FILL xFE1E
+STATUS   .FILL xFE1C
+         .END",
+    )
+    .unwrap();
+    vm.run().unwrap();
+    assert_eq!(vm.register(Register::R1), 0);
+    assert_eq!(vm.register(Register::R2), 0);
+}
+
+#[test]
+fn the_timer_is_detached_like_other_devices() {
+    let mut vm = machine(1);
+    assert!(vm
+        .memory()
+        .mmio_map()
+        .to_string()
+        .contains("xFE1C-xFE1E  timer"));
+    vm.detach_device("timer").unwrap();
+    assert!(!vm.memory().mmio_map().to_string().contains("timer"));
+    vm.load_asm_str(&polls(3)).unwrap();
+    vm.run().unwrap();
+    assert_eq!(vm.register(Register::R0), 0);
+}
+
+/// Polls `TMSR` into R0 until the timer fires, counting the polls in R1
+const WAIT: &str = ".ORIG x3000
+         AND R1, R1, #0
+POLL     ADD R1, R1, #1
+         LDI R0, STATUS
+         BRzp POLL
+         HALT
+STATUS   .FILL xFE1C
+         .END";
+
+#[test]
+fn resetting_the_metrics_leaves_the_timer_alone() {
+    let mut vm = machine(3);
+    vm.load_asm_str(&polls(2)).unwrap();
+    vm.step().unwrap();
+    vm.reset_metrics();
+    vm.step().unwrap();
+    assert_eq!(vm.register(Register::R0), 0);
+}
+
+#[test]
+fn the_period_counts_cycles_under_a_cycle_model() {
+    let polls = |model: Option<CycleModel>| {
+        let mut vm = machine(40);
+        vm.set_cycle_model(model);
+        vm.load_asm_str(WAIT).unwrap();
+        vm.run().unwrap();
+        vm.register(Register::R1)
+    };
+    // three instructions per poll, or 5 + 9 + 5 cycles
+    assert_eq!(polls(None), 14);
+    assert_eq!(polls(Some(CycleModel::LC3)), 3);
+}
@@ -0,0 +1,212 @@
+//! Instruction traces of `--trace` and `--trace-binary`
+#![allow(clippy::unwrap_used)]
+
+use std::{cell::RefCell, rc::Rc};
+
+use lc3_vm::{
+    console::SharedConsole,
+    errors::VMError,
+    trace::{self, Step, Tracer},
+    vm::VM,
+};
+
+/// The lines traced running `source`, each step rendered by `render`
+fn trace(source: &str, render: fn(&Step) -> String) -> Vec<String> {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    vm.load_asm_str(source).unwrap();
+    let lines = Rc::new(RefCell::new(Vec::new()));
+    let sink = Rc::clone(&lines);
+    vm.set_observer(Box::new(Tracer::new(move |step| {
+        sink.borrow_mut().push(render(step));
+    })));
+    vm.run().unwrap();
+    lines.take()
+}
+
+#[test]
+fn lines_show_only_what_each_instruction_changed() {
+    let lines = trace(
+        ".ORIG x3000
+         ADD R0, R0, #3
+         ADD R0, R0, #1
+         ST R0, VALUE
+         BRnzp NEXT
+NEXT     HALT
+VALUE    .FILL #0
+         .END",
+        Step::to_string,
+    );
+    assert_eq!(
+        lines.first().unwrap(),
+        "x3000 ADD R0, R0, #3 ; R0: x0000→x0003, COND=P"
+    );
+    assert_eq!(
+        lines.get(1).unwrap(),
+        "x3001 ADD R0, R0, #1 ; R0: x0003→x0004"
+    );
+    assert_eq!(
+        lines.get(2).unwrap(),
+        "x3002 ST R0, x3005 ; mem[x3005]=x0004"
+    );
+    assert_eq!(lines.get(3).unwrap(), "x3003 BRnzp x3004");
+    assert!(lines.get(4).unwrap().starts_with("x3004 HALT"));
+    assert_eq!(lines.len(), 5);
+}
+
+/// `text` without its ANSI escape sequences
+fn strip_colors(text: &str) -> String {
+    let mut plain = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|&c| c == 'm');
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+#[test]
+fn colored_lines_align_the_changes_and_highlight_new_values() {
+    let lines = trace(
+        ".ORIG x3000
+         ADD R0, R0, #3
+         LEA R1, DATA
+         HALT
+DATA     .FILL #0
+         .END",
+        Step::colored,
+    );
+    let add = lines.first().unwrap();
+    assert!(add.contains("\x1b[1;32mx0003\x1b[0m"), "{add:?}");
+    assert_eq!(
+        strip_colors(add),
+        "x3000 ADD   R0, R0, #3   ; R0: x0000→x0003, COND=P"
+    );
+    assert_eq!(
+        strip_colors(lines.get(1).unwrap()),
+        "x3001 LEA   R1, x3003    ; R1: x0000→x3003"
+    );
+}
+
+const COUNTER: &str = ".ORIG x3000
+         AND R0, R0, #0
+LOOP     ADD R0, R0, #1
+         ST R0, COUNT
+         ADD R1, R0, #-3
+         BRn LOOP
+         HALT
+COUNT    .FILL #0
+         .END";
+
+fn record(source: &str) -> Vec<u8> {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    vm.load_asm_str(source).unwrap();
+    let recording = Rc::new(RefCell::new(trace::HEADER.to_vec()));
+    let sink = Rc::clone(&recording);
+    vm.set_observer(Box::new(Tracer::new(move |step| {
+        step.encode(&mut sink.borrow_mut());
+    })));
+    vm.run().unwrap();
+    recording.take()
+}
+
+fn loaded(source: &str) -> VM {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    vm.load_asm_str(source).unwrap();
+    vm
+}
+
+#[test]
+fn binary_records_decode_to_the_traced_steps() {
+    let recording = record(COUNTER);
+    let steps: Vec<Step> = trace::records(&recording)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    let lines: Vec<String> = steps.iter().map(ToString::to_string).collect();
+    assert_eq!(lines, trace(COUNTER, Step::to_string));
+    assert_eq!(
+        lines.get(2).unwrap(),
+        "x3002 ST R0, x3006 ; mem[x3006]=x0001"
+    );
+}
+
+#[test]
+fn replaying_the_same_program_verifies_every_step() {
+    let recording = record(COUNTER);
+    let steps = trace::records(&recording).unwrap().count();
+    let verified = trace::verify(&mut loaded(COUNTER), &recording).unwrap();
+    assert_eq!(verified, u64::try_from(steps).unwrap());
+}
+
+#[test]
+fn replay_reports_the_first_divergent_step() {
+    let recording = record(COUNTER);
+    let changed = COUNTER.replace("ADD R1, R0, #-3", "ADD R1, R0, #-2");
+    let error = trace::verify(&mut loaded(&changed), &recording).unwrap_err();
+    assert_eq!(
+        error,
+        VMError::TraceMismatch(String::from(
+            "Step 4 was recorded as `x3003 ADD R1, R0, #-3 ; R1: x0000→xFFFE, COND=N` \
+             but executed as `x3003 ADD R1, R0, #-2 ; R1: x0000→xFFFF, COND=N`"
+        ))
+    );
+}
+
+#[test]
+fn malformed_recordings_are_rejected() {
+    assert!(matches!(
+        trace::records(b"LC3X\x01"),
+        Err(VMError::InvalidTrace(_))
+    ));
+    let mut recording = record(COUNTER);
+    recording.pop();
+    assert!(matches!(
+        trace::records(&recording).unwrap().last(),
+        Some(Err(VMError::InvalidTrace(_)))
+    ));
+}
+
+fn lines(text: &str) -> Vec<String> {
+    text.lines().map(String::from).collect()
+}
+
+#[test]
+fn binary_and_text_traces_read_as_the_same_lines() {
+    let text = trace(COUNTER, Step::to_string).join("\n");
+    assert_eq!(
+        trace::read_lines(text.as_bytes()).unwrap(),
+        trace::read_lines(&record(COUNTER)).unwrap()
+    );
+    assert!(trace::first_divergence(&lines(&text), &lines(&text), 3).is_none());
+}
+
+#[test]
+fn the_first_divergence_comes_with_context() {
+    let left = lines("a\nb\nc\nd\ne\nf");
+    let right = lines("a\nb\nc\nD\ne");
+    let divergence = trace::first_divergence(&left, &right, 1).unwrap();
+    assert_eq!(divergence.step, 4);
+    assert_eq!(
+        divergence.to_string(),
+        "First difference at step 4:\n  c\n- d\n- e\n+ D\n+ e\n"
+    );
+
+    let shorter = lines("a\nb");
+    let divergence = trace::first_divergence(&left, &shorter, 2).unwrap();
+    assert_eq!(
+        divergence.to_string(),
+        "First difference at step 3:\n  a\n  b\n- c\n- d\n- e\n+ (end of trace)\n"
+    );
+}
@@ -0,0 +1,81 @@
+//! Events reported to a `tracing` subscriber while a program runs
+#![cfg(feature = "tracing")]
+#![allow(clippy::unwrap_used)]
+
+use std::{
+    fmt,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use lc3_vm::{console::SharedConsole, vm::VM};
+use tracing::{
+    field::{Field, Visit},
+    span, Event, Metadata, Subscriber,
+};
+
+/// Target and message of every event
+#[derive(Clone, Default)]
+struct Recorder(Arc<Mutex<Vec<(String, String)>>>);
+
+struct Message(String);
+
+impl Visit for Message {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{value:?}");
+        }
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+        span::Id::from_u64(1)
+    }
+
+    fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+    fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut message = Message(String::new());
+        event.record(&mut message);
+        let target = event.metadata().target().to_owned();
+        self.0.lock().unwrap().push((target, message.0));
+    }
+
+    fn enter(&self, _: &span::Id) {}
+
+    fn exit(&self, _: &span::Id) {}
+}
+
+#[test]
+fn runs_report_instructions_memory_and_traps() {
+    let recorder = Recorder::default();
+    tracing::subscriber::set_global_default(recorder.clone()).unwrap();
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/memory.obj");
+    vm.read_image(path.to_str().unwrap()).unwrap();
+    vm.run().unwrap();
+
+    let events = recorder.0.lock().unwrap();
+    let count = |target: &str, message: &str| {
+        events
+            .iter()
+            .filter(|(t, m)| t == target && m == message)
+            .count()
+    };
+    assert_eq!(count("lc3_vm::memory", "loading image"), 1);
+    assert!(count("lc3_vm::vm", "execute") > 10);
+    assert!(count("lc3_vm::memory", "read") > 0);
+    assert!(count("lc3_vm::memory", "write") > 0);
+    assert!(count("lc3_vm::traps", "trap") > 0);
+    assert_eq!(count("lc3_vm::vm", "halted"), 1);
+}
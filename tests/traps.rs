@@ -0,0 +1,269 @@
+//! The output traps with and without the strict semantics of the
+//! specification
+#![allow(clippy::unwrap_used)]
+
+use std::{cell::RefCell, rc::Rc};
+
+use lc3_vm::{
+    clock::TimeSource,
+    console::{Console, SharedConsole},
+    errors::VMError,
+    register::Register,
+    vm::{TrapMode, ARGS_START, VM},
+};
+
+/// Prints `ABC` packed two characters per word, the odd length ended by
+/// the zero high byte of the last word, which `AB` follows
+const ODD_PUTSP: &str = ".ORIG x3000
+         LEA R0, TEXT
+         PUTSP
+         HALT
+TEXT     .FILL x4241
+         .FILL x0043
+         .FILL x4241
+         .FILL 0
+         .END";
+
+/// Prints x0141, whose high byte is not part of a character
+const WIDE_OUT: &str = ".ORIG x3000
+         LD R0, WIDE
+         OUT
+         LEA R0, TEXT
+         PUTS
+         HALT
+WIDE     .FILL x0141
+TEXT     .FILL x0142
+         .FILL 0
+         .END";
+
+fn output(source: &str, strict: bool) -> Result<String, VMError> {
+    let console = SharedConsole::new();
+    let mut vm = VM::builder()
+        .console(Box::new(console.clone()))
+        .strict_traps(strict)
+        .build()
+        .unwrap();
+    vm.load_asm_str(source).unwrap();
+    vm.run()?;
+    Ok(console.take_output())
+}
+
+#[test]
+fn putsp_runs_until_a_zero_word_by_default() {
+    assert_eq!(output(ODD_PUTSP, false).unwrap(), "ABCABHALT\n");
+}
+
+#[test]
+fn strict_putsp_stops_at_a_zero_high_byte() {
+    assert_eq!(output(ODD_PUTSP, true).unwrap(), "ABCHALT\n");
+}
+
+#[test]
+fn strict_out_and_puts_ignore_the_high_byte() {
+    assert!(matches!(
+        output(WIDE_OUT, false),
+        Err(VMError::InvalidCharacter(_))
+    ));
+    assert_eq!(output(WIDE_OUT, true).unwrap(), "ABHALT\n");
+}
+
+/// Console recording the characters written, with a `|` for every flush
+#[derive(Clone, Default)]
+struct FlushingConsole(Rc<RefCell<String>>);
+
+impl Console for FlushingConsole {
+    fn read_key(&mut self) -> Result<u8, VMError> {
+        Ok(0)
+    }
+
+    fn poll_key(&mut self) -> Result<Option<u8>, VMError> {
+        Ok(None)
+    }
+
+    fn write_char(&mut self, character: char) -> Result<(), VMError> {
+        self.0.borrow_mut().push(character);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), VMError> {
+        self.0.borrow_mut().push('|');
+        Ok(())
+    }
+}
+
+#[test]
+fn output_traps_flush_what_they_print() {
+    let console = FlushingConsole::default();
+    let mut vm = VM::builder()
+        .console(Box::new(console.clone()))
+        .build()
+        .unwrap();
+    vm.load_asm_str(
+        ".ORIG x3000
+         LD R0, CHAR
+         OUT
+         LEA R0, TEXT
+         PUTS
+         HALT
+CHAR     .FILL x41
+TEXT     .STRINGZ \"BC\"
+         .END",
+    )
+    .unwrap();
+    vm.run().unwrap();
+    assert!(
+        console.0.borrow().starts_with("A|BC|"),
+        "{}",
+        console.0.borrow()
+    );
+}
+
+/// Prints the variable SEED read with GETENV into a buffer of 4 words and
+/// keeps the length R0 received at x3100
+const GETENV: &str = ".ORIG x3000
+         LEA R0, NAME
+         LEA R1, BUFFER
+         AND R2, R2, #0
+         ADD R2, R2, #4
+         TRAP x84
+         STI R0, LENGTH
+         LEA R0, BUFFER
+         PUTS
+         HALT
+LENGTH   .FILL x3100
+NAME     .STRINGZ \"SEED\"
+BUFFER   .BLKW 4
+         .END";
+
+/// The output and length of `GETENV` with `variables` exposed
+fn getenv(variables: &[(&str, &str)]) -> Result<(String, u16), VMError> {
+    let console = SharedConsole::new();
+    let mut vm = VM::builder()
+        .console(Box::new(console.clone()))
+        .build()
+        .unwrap();
+    for (name, value) in variables {
+        vm.set_env_var(*name, *value);
+    }
+    vm.load_asm_str(GETENV).unwrap();
+    vm.run()?;
+    Ok((console.take_output(), vm.peek(0x3100)))
+}
+
+#[test]
+fn getenv_copies_exposed_variables() {
+    assert_eq!(
+        getenv(&[("SEED", "42")]).unwrap(),
+        (String::from("42HALT\n"), 2)
+    );
+}
+
+#[test]
+fn getenv_fails_on_hidden_or_long_variables() {
+    assert_eq!(getenv(&[("OTHER", "1")]).unwrap().1, 0xFFFF);
+    // the terminator no longer fits in the 4 words
+    assert_eq!(getenv(&[("SEED", "1234")]).unwrap().1, 0xFFFF);
+}
+
+#[test]
+fn getenv_is_an_invalid_trap_without_variables() {
+    assert!(matches!(getenv(&[]), Err(VMError::InvalidTrapCode(_))));
+}
+
+/// Prints the argument R0 found with GETARG, or `!` if there is none
+const GETARG: &str = ".ORIG x3000
+         AND R0, R0, #0
+         ADD R0, R0, #2
+         TRAP x85
+         ADD R1, R0, #1
+         BRz NONE
+         PUTS
+         HALT
+NONE     LD R0, BANG
+         OUT
+         HALT
+BANG     .FILL x21
+         .END";
+
+#[test]
+fn arguments_are_laid_out_like_argv() {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    vm.set_args(["prog.obj", "ab", ""]).unwrap();
+    let area: Vec<u16> = vm.memory().read_words(ARGS_START, 14).collect();
+    assert_eq!(
+        area,
+        [3, 0xFD05, 0xFD0E, 0xFD11, 0, 112, 114, 111, 103, 46, 111, 98, 106, 0]
+    );
+    assert_eq!(vm.peek(0xFD11), 0);
+    // written again when the memory is cleared
+    vm.reset(false);
+    assert_eq!(vm.peek(ARGS_START), 3);
+}
+
+#[test]
+fn getarg_finds_the_string_of_an_argument() {
+    let console = SharedConsole::new();
+    let mut vm = VM::builder()
+        .console(Box::new(console.clone()))
+        .build()
+        .unwrap();
+    vm.load_asm_str(GETARG).unwrap();
+    vm.set_args(["prog.obj", "one", "two"]).unwrap();
+    vm.run().unwrap();
+    assert_eq!(console.take_output(), "twoHALT\n");
+
+    vm.set_args(["prog.obj"]).unwrap();
+    vm.reset(true);
+    vm.run().unwrap();
+    assert_eq!(console.take_output(), "!HALT\n");
+}
+
+#[test]
+fn arguments_must_fit_their_area() {
+    let mut vm = VM::new();
+    let long = "x".repeat(300);
+    assert!(matches!(
+        vm.set_args(["prog.obj", long.as_str()]),
+        Err(VMError::InvalidArgument(_))
+    ));
+    assert!(vm.args().is_empty());
+}
+
+/// Runs 81 instructions, then reads the time
+const TIME: &str = ".ORIG x3000
+         LD R2, COUNT
+LOOP     ADD R2, R2, #-1
+         BRp LOOP
+         TRAP x28
+         HALT
+COUNT    .FILL #40
+         .END";
+
+fn time(source: TimeSource) -> (u16, u16) {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .trap_mode(TrapMode::Extended)
+        .time_source(source)
+        .build()
+        .unwrap();
+    vm.load_asm_str(TIME).unwrap();
+    vm.run().unwrap();
+    (vm.register(Register::R1), vm.register(Register::R0))
+}
+
+#[test]
+fn virtual_time_counts_instructions() {
+    assert_eq!(time(TimeSource::Virtual(1000)), (0, 81));
+    // 81000 ms
+    assert_eq!(time(TimeSource::Virtual(1)), (1, 0x3C68));
+}
+
+#[test]
+fn host_time_is_the_time_spent_running() {
+    let (high, low) = time(TimeSource::Host);
+    assert_eq!(high, 0);
+    assert!(low < 10_000);
+}
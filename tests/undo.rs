@@ -0,0 +1,111 @@
+//! Taking back the last stepped instruction
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    console::SharedConsole,
+    register::Register,
+    vm::{Psr, StopReason, VM},
+};
+
+/// Stores a negative number twice, then halts
+const STORE: &str = ".ORIG x3000
+         ADD R1, R1, #-2
+         ST R1, SLOT
+         STI R1, POINTER
+         HALT
+SLOT     .FILL #5
+POINTER  .FILL SLOT
+         .END";
+
+fn vm() -> VM {
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    vm.load_asm_str(STORE).unwrap();
+    vm.set_undo(true);
+    vm
+}
+
+#[test]
+fn undo_restores_registers_and_flags() {
+    let mut vm = vm();
+    vm.step().unwrap();
+    assert_eq!(vm.register(Register::R1), 0xFFFE);
+    vm.undo().unwrap();
+    assert_eq!(vm.register(Register::R1), 0);
+    assert_eq!(vm.pc(), 0x3000);
+    assert_eq!(vm.psr(), Psr::default());
+    assert!(!vm.can_undo());
+    assert!(vm.undo().is_err());
+}
+
+#[test]
+fn undo_restores_the_words_written() {
+    let mut vm = vm();
+    vm.step().unwrap();
+    vm.step().unwrap();
+    vm.undo().unwrap();
+    assert_eq!(vm.peek(0x3004), 5);
+    assert_eq!(vm.pc(), 0x3001);
+    // only the last instruction is kept
+    assert!(vm.undo().is_err());
+    assert_eq!(vm.register(Register::R1), 0xFFFE);
+}
+
+#[test]
+fn undoing_halt_resumes_the_machine() {
+    let mut vm = vm();
+    for _ in 0..4 {
+        vm.step().unwrap();
+    }
+    assert!(vm.is_halted());
+    assert_eq!(vm.step().unwrap(), StopReason::Halted);
+    vm.undo().unwrap();
+    assert!(!vm.is_halted());
+    assert_eq!(vm.pc(), 0x3003);
+    assert_eq!(vm.step().unwrap(), StopReason::Halted);
+}
+
+#[test]
+fn nothing_is_recorded_unless_enabled() {
+    let mut vm = vm();
+    vm.set_undo(false);
+    vm.step().unwrap();
+    assert!(!vm.can_undo());
+    vm.set_undo(true);
+    vm.step().unwrap();
+    vm.run().unwrap();
+    assert!(!vm.can_undo());
+}
+
+#[test]
+fn undo_leaves_devices_and_metrics_alone() {
+    let console = SharedConsole::new();
+    let mut vm = VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .build()
+        .unwrap();
+    vm.add_channel("debug", 0xFE30, Box::new(console.clone()))
+        .unwrap();
+    vm.load_asm_str(
+        ".ORIG x3000
+         LD R0, CHAR
+         STI R0, TDR
+         HALT
+CHAR     .FILL x41
+TDR      .FILL xFE36
+         .END",
+    )
+    .unwrap();
+    vm.set_undo(true);
+    vm.step().unwrap();
+    vm.step().unwrap();
+    assert_eq!(console.take_output(), "A");
+    let writes = vm.metrics().memory_writes;
+    vm.undo().unwrap();
+    // the byte is not sent again
+    assert_eq!(console.take_output(), "");
+    assert_eq!(vm.metrics().memory_writes, writes);
+    assert_eq!(vm.pc(), 0x3001);
+}
@@ -0,0 +1,79 @@
+//! Watch expressions and the watchpoints built on them
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    errors::VMError,
+    register::Register,
+    symbols::SymbolTable,
+    vm::VM,
+    watch::{Change, Expression, Watchpoints},
+};
+
+fn machine() -> (VM, SymbolTable) {
+    let mut vm = VM::new();
+    let assembly = vm
+        .load_asm_str(
+            ".ORIG x3000
+             ADD R2, R2, #3
+             STR R2, R6, #1
+             HALT
+    COUNT    .FILL x0007
+             .END",
+        )
+        .unwrap();
+    vm.set_register(Register::R6, 0x4000);
+    (vm, assembly.symbols)
+}
+
+#[test]
+fn expressions_read_registers_and_memory() {
+    let (mut vm, symbols) = machine();
+    vm.poke(0x4001, 0x1234).unwrap();
+    let value = |text: &str| Expression::parse(text, &symbols).unwrap().evaluate(&vm);
+    assert_eq!(value("R6"), 0x4000);
+    assert_eq!(value("pc"), 0x3000);
+    assert_eq!(value("mem[x4001]"), 0x1234);
+    assert_eq!(value("MEM[ R6 + 1 ]"), 0x1234);
+    assert_eq!(value("mem[R6+#2-1]"), 0x1234);
+    assert_eq!(value("mem[COUNT]"), 7);
+    assert_eq!(value("COUNT + 0x10"), 0x3013);
+    assert_eq!(value("mem[mem[COUNT] - 7]"), 0);
+    assert_eq!(value("R0 - 1"), 0xFFFF);
+}
+
+#[test]
+fn malformed_expressions_are_rejected() {
+    let (_, symbols) = machine();
+    for text in [
+        "", "mem[R6", "mem R6", "R8", "NOWHERE", "R1 +", "R1 R2", "mem[]",
+    ] {
+        assert!(
+            matches!(Expression::parse(text, &symbols), Err(VMError::Debugger(_))),
+            "{text}"
+        );
+    }
+}
+
+#[test]
+fn watchpoints_report_the_first_change() {
+    let (mut vm, symbols) = machine();
+    let mut watchpoints = Watchpoints::new();
+    watchpoints.add("mem[R6+1]", &symbols, &vm).unwrap();
+    watchpoints.add("R2", &symbols, &vm).unwrap();
+    vm.step().unwrap();
+    let change = watchpoints.check(&vm).unwrap();
+    assert_eq!(
+        change,
+        Change {
+            text: String::from("R2"),
+            old: 0,
+            new: 3
+        }
+    );
+    assert_eq!(change.to_string(), "R2 changed from x0000 to x0003");
+    assert!(watchpoints.check(&vm).is_none());
+    vm.step().unwrap();
+    assert_eq!(watchpoints.check(&vm).unwrap().text, "mem[R6+1]");
+    let values: Vec<(&str, u16)> = watchpoints.values().collect();
+    assert_eq!(values, [("mem[R6+1]", 3), ("R2", 3)]);
+}
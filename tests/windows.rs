@@ -0,0 +1,88 @@
+//! Shared memory windows the host exchanges data through
+#![allow(clippy::unwrap_used)]
+
+use lc3_vm::{
+    console::SharedConsole,
+    errors::VMError,
+    register::Register,
+    vm::{TrapMessages, VM},
+};
+
+/// Doubles the four words at x5000 in place
+const DOUBLE: &str = ".ORIG x3000
+         LD R1, DATA
+         AND R2, R2, #0
+         ADD R2, R2, #4
+LOOP     LDR R0, R1, #0
+         ADD R0, R0, R0
+         STR R0, R1, #0
+         ADD R1, R1, #1
+         ADD R2, R2, #-1
+         BRp LOOP
+         HALT
+DATA     .FILL x5000
+         .END";
+
+fn vm() -> VM {
+    VM::builder()
+        .console(Box::new(SharedConsole::new()))
+        .trap_messages(TrapMessages::SILENT)
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn hosts_and_programs_share_the_words() {
+    let mut vm = vm();
+    vm.load_asm_str(DOUBLE).unwrap();
+    vm.reserve_window("data", 0x5000, 6).unwrap();
+    vm.window_mut("data")
+        .unwrap()
+        .copy_from_slice(&[1, 2, 3, 4, 5, 6]);
+    assert_eq!(vm.peek(0x5003), 4);
+    vm.run().unwrap();
+    let window = vm.window_mut("data").unwrap();
+    assert_eq!(window.start(), 0x5000);
+    assert_eq!(*window, [2, 4, 6, 8, 5, 6]);
+    assert_eq!(window.changed(), Some(0x5000..=0x5003));
+    drop(window);
+    assert_eq!(vm.window_mut("data").unwrap().changed(), None);
+}
+
+#[test]
+fn code_changed_in_a_window_runs_as_changed() {
+    let mut vm = vm();
+    vm.reserve_window("code", 0x3000, 2).unwrap();
+    // ADD R0, R0, #1 then HALT
+    vm.window_mut("code")
+        .unwrap()
+        .copy_from_slice(&[0x1021, 0xF025]);
+    vm.run().unwrap();
+    assert_eq!(vm.register(Register::R0), 1);
+    // ADD R0, R0, #5
+    *vm.window_mut("code").unwrap().first_mut().unwrap() = 0x1025;
+    vm.reset(true);
+    vm.run().unwrap();
+    assert_eq!(vm.register(Register::R0), 5);
+}
+
+#[test]
+fn windows_take_free_ram() {
+    let mut vm = vm();
+    vm.enable_display().unwrap();
+    assert_eq!(
+        vm.reserve_window("io", 0xFD00, 0x200)
+            .unwrap_err()
+            .to_string(),
+        "Window `io` of 512 words at xFD00 is not in the RAM"
+    );
+    assert!(vm.reserve_window("empty", 0x4000, 0).is_err());
+    assert!(matches!(
+        vm.reserve_window("grid", 0xF100, 16),
+        Err(VMError::DeviceConflict(_))
+    ));
+    assert!(vm.window_mut("grid").is_err());
+    vm.reserve_window("data", 0x4000, 16).unwrap();
+    vm.detach_device("data").unwrap();
+    assert!(vm.window_mut("data").is_err());
+}